@@ -0,0 +1,30 @@
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use mocker_core::Buffer;
+
+/// Request carrying a large JSON payload, as stores receive on bulk imports
+fn large_request() -> Bytes {
+  let items = (0..2000)
+    .map(|i| format!(r#"{{"id": {}, "name": "user {}"}}"#, i, i))
+    .collect::<Vec<_>>();
+  let body = format!("[{}]", items.join(","));
+  Bytes::from(format!(
+    "POST /users HTTP/1.1\r\nHost: localhost\r\nUser-Agent: bench\r\nAccept: */*\r\n\
+     Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+    body.len(),
+    body
+  ))
+}
+
+fn parse(c: &mut Criterion) {
+  let raw = large_request();
+  c.bench_function("parse", |b| {
+    b.iter(|| Buffer::parse(black_box(raw.clone())).unwrap())
+  });
+  c.bench_function("read_from", |b| {
+    b.iter(|| Buffer::read_from(black_box(&raw[..])).unwrap())
+  });
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
@@ -0,0 +1,550 @@
+use std::{
+  collections::HashMap,
+  sync::{mpsc::Receiver, Arc},
+};
+
+use log::debug;
+use serde::Serialize;
+
+use crate::{
+  docs_page, namespace::Namespaces, openapi, parse_duration, parse_offset, AuditLog, AuthPreset,
+  Clock, Column, Error, ErrorKind, Journal, JournalQuery, Mailbox, Method, Metrics, Request,
+  Response, Route, RouteScenario, RouteTags, Router, SheetFormat, Status, Value,
+};
+
+/// Path prefix under which the admin API is mounted
+pub const ADMIN_PREFIX: &str = "/__mocker";
+
+/// What a route serves, as listed by the `OPTIONS /` discovery endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct Capability {
+  pub endpoint: String,
+  pub methods: Vec<Method>,
+  pub kind: &'static str,
+  /// Names of the `{param}` segments of the endpoint
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub params: Vec<String>,
+  /// Scenario state the route is served in
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scenario: Option<RouteScenario>,
+  /// Header keying isolated sessions
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub session_header: Option<String>,
+  /// Authentication scheme enforced
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub auth: Option<AuthPreset>,
+  /// Hosts the route answers for
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub host: Option<String>,
+  /// Schema of the response body
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub schema: Option<Value>,
+}
+
+impl From<&Route> for Capability {
+  fn from(route: &Route) -> Self {
+    let options = route.options();
+    Self {
+      endpoint: route.endpoint().clone(),
+      methods: route.methods().clone(),
+      kind: route.kind_str(),
+      params: route
+        .endpoint()
+        .split('/')
+        .filter_map(|s| s.strip_prefix('{').and_then(|s| s.strip_suffix('}')))
+        .map(|s| s.to_string())
+        .collect(),
+      scenario: options.scenario.clone(),
+      session_header: options.session_header.clone(),
+      auth: options.auth.clone(),
+      host: options.host.clone(),
+      schema: options.schema.clone(),
+    }
+  }
+}
+
+/// Introspection and control endpoints, served alongside the configured routes.
+#[derive(Default, Clone)]
+pub struct Admin {
+  router: Arc<Router>,
+  journal: Arc<Journal>,
+  metrics: Arc<Metrics>,
+  mailbox: Arc<Mailbox>,
+  audit: Arc<AuditLog>,
+  #[cfg(feature = "oidc")]
+  oidc: Option<Arc<crate::Oidc>>,
+}
+
+impl Admin {
+  const DOCS_TITLE: &'static str = "Mocker";
+
+  pub fn new(router: Arc<Router>) -> Self {
+    Self {
+      router,
+      journal: Default::default(),
+      metrics: Default::default(),
+      mailbox: Default::default(),
+      audit: Default::default(),
+      #[cfg(feature = "oidc")]
+      oidc: None,
+    }
+  }
+
+  pub fn with_journal(mut self, journal: Arc<Journal>) -> Self {
+    self.journal = journal;
+    self
+  }
+
+  pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+    self.metrics = metrics;
+    self
+  }
+
+  pub fn with_mailbox(mut self, mailbox: Arc<Mailbox>) -> Self {
+    self.mailbox = mailbox;
+    self
+  }
+
+  /// Admin operations which changed the mock's state
+  pub fn audit(&self) -> &Arc<AuditLog> {
+    &self.audit
+  }
+
+  #[cfg(feature = "oidc")]
+  pub fn with_oidc(mut self, oidc: crate::Oidc) -> Self {
+    self.oidc = Some(Arc::new(oidc));
+    self
+  }
+
+  pub fn handles(req: &Request) -> bool {
+    match req.path() {
+      Some(path) => path == ADMIN_PREFIX || path.starts_with(&format!("{}/", ADMIN_PREFIX)),
+      None => false,
+    }
+  }
+
+  /// Capabilities of the mock, answering `OPTIONS /` unless a route serves it
+  /// or it is a CORS preflight request, and the OIDC discovery document
+  pub fn discover(&self, req: &Request) -> crate::Result<Option<Response>> {
+    #[cfg(feature = "oidc")]
+    if let Some(oidc) = &self.oidc {
+      if req.method() == Some(Method::Get)
+        && req.path() == Some(crate::OIDC_DISCOVERY_PATH)
+        && self.router.route(req)?.is_none()
+      {
+        return oidc.discovery(req).map(Some);
+      }
+    }
+    let discovery = req.method() == Some(Method::Options)
+      && req.path() == Some("/")
+      && req.header("Access-Control-Request-Method").is_none();
+    if !discovery || self.router.route(req)?.is_some() {
+      return Ok(None);
+    }
+    let capabilities = self
+      .router
+      .routes()?
+      .iter()
+      .map(Capability::from)
+      .collect::<Vec<_>>();
+    let mut allow: Vec<String> = vec![];
+    for method in capabilities.iter().flat_map(|c| &c.methods) {
+      if !allow.contains(&method.to_string()) {
+        allow.push(method.to_string());
+      }
+    }
+    let res = Response::api_for(req, Status::OK, &capabilities)?;
+    Ok(Some(res.with_header("Allow", allow.join(", "))))
+  }
+
+  /// Server-sent events requested by `req`, if it targets a streaming endpoint
+  pub fn events(&self, req: &Request) -> crate::Result<Option<Receiver<String>>> {
+    let path = req
+      .path()
+      .unwrap_or_default()
+      .trim_start_matches(ADMIN_PREFIX);
+    Ok(match (req.method().unwrap_or(Method::Get), path) {
+      (Method::Get, "/requests/stream") => Some(self.journal.subscribe()?),
+      (Method::Get, "/events") => Some(self.router.store_events().subscribe()?),
+      _ => None,
+    })
+  }
+
+  /// Whether `req` changes the mock's state, rather than reading it or
+  /// using a utility endpoint
+  fn mutates(req: &Request) -> bool {
+    let path = req
+      .path()
+      .unwrap_or(ADMIN_PREFIX)
+      .trim_start_matches(ADMIN_PREFIX);
+    !matches!(
+      req.method().unwrap_or(Method::Get),
+      Method::Get | Method::Head | Method::Options
+    ) && !["/status/", "/oauth/", "/oidc/"]
+      .iter()
+      .any(|prefix| path.starts_with(prefix))
+  }
+
+  /// Serve `req`, recording it in the audit log if it changes the mock's state
+  pub fn handle(&self, req: &Request) -> crate::Result<Response> {
+    let res = self.serve(req);
+    if Self::mutates(req) {
+      self.audit.record(req, res.as_ref().ok())?;
+    }
+    res
+  }
+
+  fn serve(&self, req: &Request) -> crate::Result<Response> {
+    let path = req
+      .path()
+      .unwrap_or(ADMIN_PREFIX)
+      .trim_start_matches(ADMIN_PREFIX);
+    let method = req.method().unwrap_or(Method::Get);
+    debug!("Admin request: {} {}", method, path);
+    #[cfg(feature = "oidc")]
+    if let Some(oidc) = &self.oidc {
+      if let Some(res) = oidc.handle(req, path)? {
+        return Ok(res);
+      }
+    }
+    match (method, path) {
+      (Method::Get, "/invocations") => {
+        Response::api_for(req, Status::OK, &self.router.invocations().all()?)
+      }
+      (Method::Delete, "/invocations") => {
+        self.router.invocations().reset()?;
+        Ok(Response::default().with_status(Status::NoContent))
+      }
+      (Method::Delete, "/cache") => {
+        self.router.cache().clear()?;
+        Ok(Response::default().with_status(Status::NoContent))
+      }
+      (Method::Delete, "/rate-limits") => {
+        self.router.rate_limits().reset()?;
+        Ok(Response::default().with_status(Status::NoContent))
+      }
+      (Method::Get, "/verify") => match self.router.verify() {
+        Ok(()) => Response::api_for(req, Status::OK, &"all expectations met"),
+        Err(e) => Ok(e.into()),
+      },
+      (Method::Get, "/requests") => {
+        let query = JournalQuery::from_request(req)?;
+        Response::api_for(req, Status::OK, &self.journal.query(&query)?)
+      }
+      (Method::Delete, "/requests") => {
+        self.journal.clear()?;
+        Ok(Response::default().with_status(Status::NoContent))
+      }
+      (Method::Get, "/audit") => {
+        let after = req
+          .query_param("after")
+          .and_then(|(_, after)| after)
+          .map(|after| after.parse::<u64>())
+          .transpose()?;
+        Response::api_for(req, Status::OK, &self.audit.entries(after)?)
+      }
+      (Method::Get, "/stats") => Response::api_for(req, Status::OK, &self.metrics.report()?),
+      (Method::Delete, "/stats") => {
+        self.metrics.reset()?;
+        Ok(Response::default().with_status(Status::NoContent))
+      }
+      (Method::Get, "/scenarios") => {
+        Response::api_for(req, Status::OK, &self.router.scenarios().states()?)
+      }
+      (Method::Delete, "/scenarios") => {
+        self.router.scenarios().reset()?;
+        self.router.variables().reset()?;
+        Ok(Response::default().with_status(Status::NoContent))
+      }
+      (Method::Put, path) if path.starts_with("/scenarios/") => {
+        let name = path.trim_start_matches("/scenarios/");
+        let body = req.parse_body::<HashMap<String, String>>()?;
+        let state = body.get("state").ok_or_else(|| {
+          Error::new(
+            ErrorKind::Api(Status::BadRequest),
+            Some("missing `state` field".to_string()),
+            None,
+          )
+        })?;
+        self.router.scenarios().set(name, state)?;
+        Response::api_for(req, Status::OK, &self.router.scenarios().state(name)?)
+      }
+      (Method::Get, "/tags") => self.tags(req),
+      (Method::Put, "/tags") => {
+        self
+          .router
+          .tags()
+          .select(req.parse_body::<Option<Vec<String>>>()?)?;
+        self.tags(req)
+      }
+      (Method::Put, path) if path.starts_with("/tags/") => {
+        self
+          .router
+          .tags()
+          .enable(path.trim_start_matches("/tags/"))?;
+        self.tags(req)
+      }
+      (Method::Delete, path) if path.starts_with("/tags/") => {
+        let known = RouteTags::known(&self.router.routes()?);
+        self
+          .router
+          .tags()
+          .disable(path.trim_start_matches("/tags/"), &known)?;
+        self.tags(req)
+      }
+      (Method::Get, "/variables") => {
+        Response::api_for(req, Status::OK, &self.router.variables().all()?)
+      }
+      (Method::Delete, "/variables") => {
+        self.router.variables().reset()?;
+        Ok(Response::default().with_status(Status::NoContent))
+      }
+      (Method::Get, "/emails") => {
+        let to = req.query_param("to").and_then(|(_, to)| to);
+        Response::api_for(req, Status::OK, &self.mailbox.emails(to.as_deref())?)
+      }
+      (Method::Delete, "/emails") => {
+        self.mailbox.clear()?;
+        Ok(Response::default().with_status(Status::NoContent))
+      }
+      (Method::Get, path) if path.starts_with("/emails/") => {
+        let id = path.trim_start_matches("/emails/");
+        let email = id
+          .parse::<u64>()
+          .ok()
+          .map(|id| self.mailbox.get(id))
+          .transpose()?
+          .flatten()
+          .ok_or_else(|| {
+            Error::new(
+              ErrorKind::Api(Status::NotFound),
+              Some(format!("no email with id '{}'", id)),
+              None,
+            )
+          })?;
+        Response::api_for(req, Status::OK, &email)
+      }
+      (Method::Post, crate::TOKEN_PATH) => self.router.tokens().token_endpoint(req),
+      (Method::Post, crate::INTROSPECT_PATH) => self.router.tokens().introspection_endpoint(req),
+      (Method::Get, "/clock") => {
+        Response::api_for(req, Status::OK, &Clock::of(self.router.clock()))
+      }
+      (Method::Post, "/clock") => {
+        let body = req.parse_body::<HashMap<String, String>>()?;
+        let offset = body.get("offset").ok_or_else(|| {
+          Error::new(
+            ErrorKind::Api(Status::BadRequest),
+            Some("missing `offset` field".to_string()),
+            None,
+          )
+        })?;
+        self.router.clock().set_offset(parse_offset(offset)?);
+        Response::api_for(req, Status::OK, &Clock::of(self.router.clock()))
+      }
+      (Method::Delete, "/clock") => {
+        self.router.clock().set_offset(0);
+        Ok(Response::default().with_status(Status::NoContent))
+      }
+      #[cfg(feature = "json")]
+      (Method::Get, "/stores/export") => {
+        let param = |key: &str| req.query_param(key).and_then(|(_, v)| v);
+        let format = param("format")
+          .unwrap_or_else(|| "csv".to_string())
+          .parse::<SheetFormat>()?;
+        let columns = param("columns")
+          .map(|c| {
+            c.split(',')
+              .map(|c| c.parse::<Column>())
+              .collect::<crate::Result<Vec<_>>>()
+          })
+          .transpose()?
+          .unwrap_or_default();
+        let endpoint = param("endpoint").ok_or_else(|| {
+          Error::new(
+            ErrorKind::Api(Status::BadRequest),
+            Some("missing `endpoint` query parameter".to_string()),
+            None,
+          )
+        })?;
+        let mut store = crate::Store::for_endpoint(&self.router.routes()?, endpoint)?;
+        store.load()?;
+        Ok(
+          Response::default()
+            .with_status(Status::OK)
+            .with_header("Content-Type", format.content_type())
+            .with_body_bytes(store.sheet(columns).to_bytes(format)?),
+        )
+      }
+      (Method::Post, "/namespaces") => {
+        let namespaces = self.router.namespaces().ok_or_else(|| {
+          Error::new(
+            ErrorKind::Api(Status::NotFound),
+            Some("namespaces are disabled, set `namespaces` to true".to_string()),
+            None,
+          )
+        })?;
+        let id = namespaces.create()?;
+        let prefix = Namespaces::prefix(&id);
+        Response::api_for(
+          req,
+          Status::Created,
+          &HashMap::from([("id", id), ("prefix", prefix)]),
+        )
+      }
+      (Method::Delete, path) if path.starts_with("/namespaces/") => {
+        let id = path.trim_start_matches("/namespaces/");
+        match self.router.namespaces() {
+          Some(namespaces) if namespaces.remove(id)? => {
+            self.router.end_session(id)?;
+            Ok(Response::default().with_status(Status::NoContent))
+          }
+          _ => Ok(Response::default().with_status(Status::NotFound)),
+        }
+      }
+      (Method::Delete, path) if path.starts_with("/sessions/") => {
+        self
+          .router
+          .end_session(path.trim_start_matches("/sessions/"))?;
+        Ok(Response::default().with_status(Status::NoContent))
+      }
+      (Method::Get, "/routes") => Response::api_for(req, Status::OK, &self.router.routes()?),
+      (Method::Put, "/routes") => {
+        self.router.replace(req.parse_body::<Vec<Route>>()?)?;
+        Response::api_for(req, Status::OK, &self.router.routes()?)
+      }
+      (Method::Post, "/routes") => {
+        self.router.add(req.parse_body::<Route>()?)?;
+        Response::api_for(req, Status::Created, &self.router.routes()?)
+      }
+      (Method::Get | Method::Put | Method::Delete, path)
+        if path.starts_with("/routes/") && path.ends_with("/enabled") =>
+      {
+        let index = path
+          .trim_start_matches("/routes/")
+          .trim_end_matches("/enabled");
+        let enabled = match req.method() {
+          Some(Method::Put) => Some(true),
+          Some(Method::Delete) => Some(false),
+          _ => None,
+        };
+        self.switch_route(req, index, enabled)
+      }
+      (Method::Delete, path) if path.starts_with("/routes/") => {
+        let mut routes = self.router.routes()?;
+        match path.trim_start_matches("/routes/").parse::<usize>() {
+          Ok(index) if index < routes.len() => {
+            routes.remove(index);
+            self.router.replace(routes)?;
+            Ok(Response::default().with_status(Status::NoContent))
+          }
+          _ => Ok(Response::default().with_status(Status::NotFound)),
+        }
+      }
+      #[cfg(feature = "dashboard")]
+      (Method::Get, crate::DASHBOARD_PATH) => Ok(crate::dashboard()),
+      (Method::Get, "/openapi.json") => {
+        let server = req.header("Host").map(|host| format!("http://{}", host));
+        Response::api_for(
+          req,
+          Status::OK,
+          &openapi(Self::DOCS_TITLE, server, &self.router.routes()?),
+        )
+      }
+      (Method::Get, "/docs") => Ok(
+        Response::default()
+          .with_status(Status::OK)
+          .with_header("Content-Type", "text/html; charset=utf-8")
+          .with_body(docs_page(
+            Self::DOCS_TITLE,
+            format!("{}/openapi.json", ADMIN_PREFIX),
+            &self.router.routes()?,
+          )),
+      ),
+      (_, path) if path.starts_with("/status/") => {
+        Self::simulate_status(req, path.trim_start_matches("/status/"))
+      }
+      _ => Ok(Response::default().with_status(Status::NotFound)),
+    }
+  }
+
+  /// Whether the route at `index` serves requests, after switching it on or
+  /// off as `enabled` demands
+  fn switch_route(
+    &self,
+    req: &Request,
+    index: &str,
+    enabled: Option<bool>,
+  ) -> crate::Result<Response> {
+    let routes = self.router.routes()?;
+    let keys = self.router.keys()?;
+    let (route, key) = match index
+      .parse::<usize>()
+      .ok()
+      .and_then(|i| routes.get(i).zip(keys.get(i)))
+    {
+      Some(found) => found,
+      None => return Ok(Response::default().with_status(Status::NotFound)),
+    };
+    let switches = self.router.switches();
+    if let Some(enabled) = enabled {
+      switches.set(key, enabled)?;
+    }
+    Response::api_for(
+      req,
+      Status::OK,
+      &HashMap::from([
+        ("route", Value::from(key.as_str())),
+        ("enabled", Value::from(switches.enabled(key, route)?)),
+      ]),
+    )
+  }
+
+  /// Tags used by the routes and those active, `null` when all are
+  fn tags(&self, req: &Request) -> crate::Result<Response> {
+    let known = RouteTags::known(&self.router.routes()?);
+    let active = self.router.tags().active()?;
+    Response::api_for(
+      req,
+      Status::OK,
+      &HashMap::from([
+        (
+          "tags",
+          Value::from(known.into_iter().map(Value::from).collect::<Vec<_>>()),
+        ),
+        (
+          "active",
+          active.map_or(Value::Null, |a| {
+            Value::from(a.into_iter().map(Value::from).collect::<Vec<_>>())
+          }),
+        ),
+      ]),
+    )
+  }
+
+  /// Answer with status `code`, after `?delay=` and echoing the request body
+  /// along with its type, for testing how clients handle arbitrary statuses
+  fn simulate_status(req: &Request, code: &str) -> crate::Result<Response> {
+    let code = code
+      .parse::<u16>()
+      .ok()
+      .filter(|code| (100..600).contains(code))
+      .ok_or_else(|| {
+        Error::new(
+          ErrorKind::Api(Status::BadRequest),
+          Some(format!("not a http status: {}", code)),
+          None,
+        )
+      })?;
+    if let Some((_, Some(delay))) = req.query_param("delay") {
+      std::thread::sleep(parse_duration(delay)?);
+    }
+    let mut res = Response::default().with_status_code(code);
+    if !req.body().is_empty() {
+      res = res.with_body_bytes(req.body().clone());
+      if let Some(content_type) = req.header("Content-Type") {
+        res.set_header("Content-Type", content_type);
+      }
+    }
+    Ok(res)
+  }
+}
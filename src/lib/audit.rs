@@ -0,0 +1,84 @@
+use std::{
+  sync::Mutex,
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Method, Request, Response};
+
+/// A change made to the mock's state through the admin API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+  /// Position in the log, starting at 1
+  pub seq: u64,
+  /// Milliseconds since the unix epoch, on the system clock so clock changes
+  /// do not blur the timeline
+  pub at: u128,
+  pub method: Method,
+  pub path: String,
+  pub body: String,
+  /// Status answered, absent when the operation failed before responding
+  pub status: Option<u16>,
+}
+
+/// Append-only record of the admin operations which changed the mock's
+/// state, to tell what happened mid-run when investigating flaky tests.
+#[derive(Debug, Default)]
+pub struct AuditLog(Mutex<Vec<AuditEntry>>);
+
+impl AuditLog {
+  pub fn record(&self, req: &Request, res: Option<&Response>) -> crate::Result<()> {
+    let mut g = self.0.lock()?;
+    let entry = AuditEntry {
+      seq: g.len() as u64 + 1,
+      at: SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default(),
+      method: req.method().unwrap_or(Method::Get),
+      path: req.path().unwrap_or("/").to_string(),
+      body: String::from_utf8_lossy(req.body()).to_string(),
+      status: res.map(|res| res.status()),
+    };
+    g.push(entry);
+    Ok(())
+  }
+
+  /// Entries recorded after the one numbered `after`, every one when `None`
+  pub fn entries(&self, after: Option<u64>) -> crate::Result<Vec<AuditEntry>> {
+    let g = self.0.lock()?;
+    let skip = after.unwrap_or_default().min(g.len() as u64) as usize;
+    Ok(g[skip..].to_vec())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Request, Response};
+
+  use super::AuditLog;
+
+  #[test]
+  fn record() {
+    let log = AuditLog::default();
+    log
+      .record(
+        &Request::new(Method::Post, "/__mocker/clock").with_body(r#"{"offset": "1d"}"#),
+        Some(&Response::default().with_status_code(200)),
+      )
+      .unwrap();
+    log
+      .record(&Request::new(Method::Delete, "/__mocker/stats"), None)
+      .unwrap();
+    let entries = log.entries(None).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].seq, 1);
+    assert_eq!(entries[0].body, r#"{"offset": "1d"}"#);
+    assert_eq!(entries[0].status, Some(200));
+    assert_eq!(entries[1].path, "/__mocker/stats");
+    assert_eq!(entries[1].status, None);
+    assert_eq!(log.entries(Some(1)).unwrap()[0].seq, 2);
+    assert!(log.entries(Some(5)).unwrap().is_empty());
+  }
+}
@@ -0,0 +1,462 @@
+use std::{
+  collections::HashMap,
+  fmt::Display,
+  hash::{BuildHasher, Hasher},
+  str::FromStr,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+  },
+  time::Duration,
+};
+
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+
+use crate::{now_millis, Error, ErrorKind, Request, Response, Status, VirtualClock};
+
+/// Admin API path issuing OAuth2 access tokens
+pub const TOKEN_PATH: &str = "/oauth/token";
+/// Admin API path introspecting OAuth2 access tokens, as in RFC 7662
+pub const INTROSPECT_PATH: &str = "/oauth/introspect";
+
+/// Standard authentication scheme enforced on a route, written as a string in
+/// the `auth` route option:
+///
+/// - `api-key:X-Api-Key=secret`: the header must hold the key
+/// - `basic:user:password`: HTTP basic credentials
+/// - `bearer:token`: a fixed bearer token
+/// - `oauth2-client-credentials[:scope]`: a bearer token issued by the admin
+///   API token endpoint, granted `scope` when given
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum AuthPreset {
+  ApiKey { header: String, key: String },
+  Basic { user: String, password: String },
+  Bearer { token: String },
+  OAuth2ClientCredentials { scope: Option<String> },
+}
+
+impl AuthPreset {
+  const REALM: &'static str = "mocker";
+
+  /// Response refusing `req`, or `None` when it carries valid credentials
+  pub fn check(&self, req: &Request, tokens: &Tokens) -> crate::Result<Option<Response>> {
+    let bearer = req
+      .header("Authorization")
+      .and_then(|h| h.strip_prefix("Bearer "))
+      .map(|t| t.trim());
+    let denied = match self {
+      Self::ApiKey { header, key } => match req.header(header) {
+        Some(given) if given == key => None,
+        Some(_) => Some((Status::Unauthorized, None, format!("invalid {}", header))),
+        None => Some((Status::Unauthorized, None, format!("missing {}", header))),
+      },
+      Self::Basic { user, password } => match basic_credentials(req) {
+        Some((u, p)) if u == *user && p == *password => None,
+        given => Some((
+          Status::Unauthorized,
+          Some(format!("Basic realm=\"{}\"", Self::REALM)),
+          match given {
+            Some(_) => "invalid credentials".to_string(),
+            None => "missing credentials".to_string(),
+          },
+        )),
+      },
+      Self::Bearer { token } => match bearer {
+        Some(given) if given == token => None,
+        given => Some(Self::bearer_denied(given.is_some())),
+      },
+      Self::OAuth2ClientCredentials { scope } => {
+        match bearer.map(|t| tokens.introspect(t)).transpose()?.flatten() {
+          Some(issued) if scope.as_ref().is_none_or(|s| issued.has_scope(s)) => None,
+          Some(_) => Some((
+            Status::Forbidden,
+            Some(format!(
+              "Bearer realm=\"{}\", error=\"insufficient_scope\"",
+              Self::REALM
+            )),
+            format!("scope '{}' required", scope.as_deref().unwrap_or_default()),
+          )),
+          None => Some(Self::bearer_denied(bearer.is_some())),
+        }
+      }
+    };
+    Ok(denied.map(|(status, challenge, message)| {
+      let res = Response::default().with_status(status).with_body(message);
+      match challenge {
+        Some(challenge) => res.with_header("WWW-Authenticate", challenge),
+        None => res,
+      }
+    }))
+  }
+
+  /// Who `req` authenticates as, assuming it passed [`Self::check`]: the
+  /// basic auth user or the OAuth client, the scheme otherwise
+  pub fn principal(&self, req: &Request, tokens: &Tokens) -> crate::Result<Option<String>> {
+    Ok(match self {
+      Self::ApiKey { .. } => Some("api-key".to_string()),
+      Self::Basic { .. } => basic_credentials(req).map(|(user, _)| user),
+      Self::Bearer { .. } => Some("bearer".to_string()),
+      Self::OAuth2ClientCredentials { .. } => req
+        .header("Authorization")
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|t| tokens.introspect(t.trim()))
+        .transpose()?
+        .flatten()
+        .map(|issued| issued.client_id),
+    })
+  }
+
+  fn bearer_denied(given: bool) -> (Status, Option<String>, String) {
+    match given {
+      true => (
+        Status::Unauthorized,
+        Some(format!(
+          "Bearer realm=\"{}\", error=\"invalid_token\"",
+          Self::REALM
+        )),
+        "invalid token".to_string(),
+      ),
+      false => (
+        Status::Unauthorized,
+        Some(format!("Bearer realm=\"{}\"", Self::REALM)),
+        "missing token".to_string(),
+      ),
+    }
+  }
+}
+
+impl FromStr for AuthPreset {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = |why: &str| {
+      Error::new(
+        ErrorKind::Parse,
+        Some(format!("invalid auth preset '{}': {}", s, why)),
+        None,
+      )
+    };
+    let (scheme, args) = match s.split_once(':') {
+      Some((scheme, args)) => (scheme, Some(args)),
+      None => (s, None),
+    };
+    match (scheme, args) {
+      ("api-key", Some(args)) => match args.split_once('=') {
+        Some((header, key)) if !header.is_empty() => Ok(Self::ApiKey {
+          header: header.to_string(),
+          key: key.to_string(),
+        }),
+        _ => Err(invalid("expected api-key:Header=key")),
+      },
+      ("basic", Some(args)) => match args.split_once(':') {
+        Some((user, password)) => Ok(Self::Basic {
+          user: user.to_string(),
+          password: password.to_string(),
+        }),
+        None => Err(invalid("expected basic:user:password")),
+      },
+      ("bearer", Some(token)) if !token.is_empty() => Ok(Self::Bearer {
+        token: token.to_string(),
+      }),
+      ("oauth2-client-credentials", scope) => Ok(Self::OAuth2ClientCredentials {
+        scope: scope.filter(|s| !s.is_empty()).map(|s| s.to_string()),
+      }),
+      _ => Err(invalid(
+        "expected api-key, basic, bearer or oauth2-client-credentials",
+      )),
+    }
+  }
+}
+
+impl TryFrom<String> for AuthPreset {
+  type Error = Error;
+
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    value.parse()
+  }
+}
+
+impl Display for AuthPreset {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::ApiKey { header, key } => write!(f, "api-key:{}={}", header, key),
+      Self::Basic { user, password } => write!(f, "basic:{}:{}", user, password),
+      Self::Bearer { token } => write!(f, "bearer:{}", token),
+      Self::OAuth2ClientCredentials { scope: None } => write!(f, "oauth2-client-credentials"),
+      Self::OAuth2ClientCredentials { scope: Some(scope) } => {
+        write!(f, "oauth2-client-credentials:{}", scope)
+      }
+    }
+  }
+}
+
+impl From<AuthPreset> for String {
+  fn from(value: AuthPreset) -> Self {
+    value.to_string()
+  }
+}
+
+/// User and password of the `Authorization: Basic` header of `req`
+pub fn basic_credentials(req: &Request) -> Option<(String, String)> {
+  let encoded = req.header("Authorization")?.strip_prefix("Basic ")?;
+  let decoded = base64::engine::general_purpose::STANDARD
+    .decode(encoded.trim())
+    .ok()?;
+  let decoded = String::from_utf8(decoded).ok()?;
+  let (user, password) = decoded.split_once(':')?;
+  Some((user.to_string(), password.to_string()))
+}
+
+/// Unguessable enough token, 32 hex digits
+pub fn random_token() -> String {
+  static COUNTER: AtomicU64 = AtomicU64::new(0);
+  let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+  hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+  hasher.write_u128(now_millis(0));
+  let high = hasher.finish();
+  hasher.write_u64(high);
+  format!("{:016x}{:016x}", high, hasher.finish())
+}
+
+/// Percent-encode `s` for a query string or form body
+pub fn form_encode(s: &str) -> String {
+  s.bytes()
+    .map(|b| match b {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+        (b as char).to_string()
+      }
+      b => format!("%{:02X}", b),
+    })
+    .collect()
+}
+
+/// Decode the `%XX` escapes of `s`, and `+` as a space when `form` is set
+fn decode(s: &str, form: bool) -> String {
+  let bytes = s.as_bytes();
+  let mut out = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'+' if form => out.push(b' '),
+      b'%' if i + 2 < bytes.len() => {
+        let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or_default();
+        match u8::from_str_radix(hex, 16) {
+          Ok(b) => {
+            out.push(b);
+            i += 2;
+          }
+          Err(_) => out.push(b'%'),
+        }
+      }
+      b => out.push(b),
+    }
+    i += 1;
+  }
+  String::from_utf8_lossy(&out).to_string()
+}
+
+/// Decode the `%XX` escapes of a URL path
+pub fn percent_decode(s: &str) -> String {
+  decode(s, false)
+}
+
+/// Decode the `%XX` escapes of a query parameter, `+` standing for a space
+pub fn query_decode(s: &str) -> String {
+  decode(s, true)
+}
+
+/// Fields of an `application/x-www-form-urlencoded` body
+pub fn form_params(body: &[u8]) -> HashMap<String, String> {
+  String::from_utf8_lossy(body)
+    .split('&')
+    .filter(|p| !p.is_empty())
+    .map(|p| match p.split_once('=') {
+      Some((k, v)) => (decode(k, true), decode(v, true)),
+      None => (decode(p, true), String::new()),
+    })
+    .collect()
+}
+
+/// Access token handed out by the token endpoint
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct IssuedToken {
+  pub client_id: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scope: Option<String>,
+  /// Milliseconds since the unix epoch, on the mock clock
+  pub expires_at: u128,
+}
+
+impl IssuedToken {
+  pub fn has_scope(&self, scope: &str) -> bool {
+    self
+      .scope
+      .as_deref()
+      .is_some_and(|s| s.split_whitespace().any(|s| s == scope))
+  }
+}
+
+/// Answer of the token endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenGrant {
+  pub access_token: String,
+  pub token_type: &'static str,
+  /// Seconds
+  pub expires_in: u64,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scope: Option<String>,
+}
+
+/// Answer of the introspection endpoint
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Introspection {
+  pub active: bool,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub client_id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub scope: Option<String>,
+  /// Expiry, in seconds since the unix epoch
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub exp: Option<u64>,
+}
+
+/// OAuth2 tokens issued by the mock, any client being granted one.
+#[derive(Debug, Default)]
+pub struct Tokens {
+  issued: Mutex<HashMap<String, IssuedToken>>,
+  clock: VirtualClock,
+}
+
+impl Tokens {
+  /// How long issued tokens stay valid
+  pub const TTL: Duration = Duration::from_secs(3600);
+
+  /// Expire tokens by `clock` instead of the system clock
+  pub fn with_clock(mut self, clock: VirtualClock) -> Self {
+    self.clock = clock;
+    self
+  }
+
+  /// Clock tokens are expired by
+  pub fn clock(&self) -> &VirtualClock {
+    &self.clock
+  }
+
+  pub fn issue<C: AsRef<str>>(
+    &self,
+    client_id: C,
+    scope: Option<String>,
+  ) -> crate::Result<TokenGrant> {
+    let token = random_token();
+    self.issued.lock()?.insert(
+      token.clone(),
+      IssuedToken {
+        client_id: client_id.as_ref().to_string(),
+        scope: scope.clone(),
+        expires_at: self.clock.now_millis() + Self::TTL.as_millis(),
+      },
+    );
+    Ok(TokenGrant {
+      access_token: token,
+      token_type: "Bearer",
+      expires_in: Self::TTL.as_secs(),
+      scope,
+    })
+  }
+
+  /// The unexpired token `token`, if it was issued
+  pub fn introspect<T: AsRef<str>>(&self, token: T) -> crate::Result<Option<IssuedToken>> {
+    let now = self.clock.now_millis();
+    let mut issued = self.issued.lock()?;
+    issued.retain(|_, t| now < t.expires_at);
+    Ok(issued.get(token.as_ref()).cloned())
+  }
+
+  /// Answer a client credentials grant, sent as a form with the client
+  /// credentials in the body or in a basic `Authorization` header
+  pub fn token_endpoint(&self, req: &Request) -> crate::Result<Response> {
+    let form = form_params(req.body());
+    if form.get("grant_type").map(|g| g.as_str()) != Some("client_credentials") {
+      return Response::api_for(
+        req,
+        Status::BadRequest,
+        &HashMap::from([("error", "unsupported_grant_type")]),
+      );
+    }
+    let client_id = match (basic_credentials(req), form.get("client_id")) {
+      (Some((client_id, _)), _) => client_id,
+      (None, Some(client_id)) => client_id.clone(),
+      (None, None) => {
+        return Response::api_for(
+          req,
+          Status::Unauthorized,
+          &HashMap::from([("error", "invalid_client")]),
+        )
+      }
+    };
+    let scope = form.get("scope").filter(|s| !s.is_empty()).cloned();
+    Response::api_for(req, Status::OK, &self.issue(client_id, scope)?)
+  }
+
+  pub fn introspection_endpoint(&self, req: &Request) -> crate::Result<Response> {
+    let form = form_params(req.body());
+    let introspection = match form.get("token").map(|t| self.introspect(t)).transpose()? {
+      Some(Some(token)) => Introspection {
+        active: true,
+        client_id: Some(token.client_id),
+        scope: token.scope,
+        exp: Some((token.expires_at / 1000) as u64),
+      },
+      _ => Introspection::default(),
+    };
+    Response::api_for(req, Status::OK, &introspection)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Request};
+
+  use super::{form_params, AuthPreset, Tokens};
+
+  #[test]
+  fn presets() {
+    let tokens = Tokens::default();
+    let status = |preset: &str, req: &Request| {
+      preset
+        .parse::<AuthPreset>()
+        .unwrap()
+        .check(req, &tokens)
+        .unwrap()
+        .map(|res| res.status())
+    };
+    let req = Request::new(Method::Get, "/");
+    assert_eq!(status("api-key:X-Api-Key=secret", &req), Some(401));
+    let keyed = req.clone().with_header("X-Api-Key", "secret");
+    assert_eq!(status("api-key:X-Api-Key=secret", &keyed), None);
+    let basic = req
+      .clone()
+      .with_header("Authorization", "Basic dXNlcjpwYXNz");
+    assert_eq!(status("basic:user:pass", &basic), None);
+    assert_eq!(status("basic:user:other", &basic), Some(401));
+
+    let grant = tokens.issue("app", Some("read write".to_string())).unwrap();
+    let bearer = req.with_header("Authorization", format!("Bearer {}", grant.access_token));
+    assert_eq!(status("oauth2-client-credentials:read", &bearer), None);
+    assert_eq!(
+      status("oauth2-client-credentials:admin", &bearer),
+      Some(403)
+    );
+    assert_eq!(status("bearer:nope", &bearer), Some(401));
+    assert!("digest".parse::<AuthPreset>().is_err());
+  }
+
+  #[test]
+  fn forms() {
+    let form = form_params(b"grant_type=client_credentials&scope=read+write&client_id=a%2Fb");
+    assert_eq!(form["scope"], "read write");
+    assert_eq!(form["client_id"], "a/b");
+  }
+}
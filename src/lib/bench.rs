@@ -0,0 +1,173 @@
+use std::{
+  collections::BTreeMap,
+  io::Write,
+  sync::Arc,
+  thread,
+  time::{Duration, Instant},
+};
+
+use crate::{Client, Error, ErrorKind, Request, Table};
+
+/// Outcome of a load test.
+#[derive(Debug, Default, Clone)]
+pub struct BenchReport {
+  /// Number of responses received, per status code
+  pub statuses: BTreeMap<u16, usize>,
+  /// Requests which did not get any response
+  pub errors: usize,
+  /// Latency of every answered request, sorted
+  pub latencies: Vec<Duration>,
+  pub elapsed: Duration,
+}
+
+impl BenchReport {
+  pub fn requests(&self) -> usize {
+    self.latencies.len() + self.errors
+  }
+
+  /// Answered requests per second
+  pub fn throughput(&self) -> f64 {
+    match self.elapsed.as_secs_f64() {
+      secs if secs > 0.0 => self.latencies.len() as f64 / secs,
+      _ => 0.0,
+    }
+  }
+
+  /// Latency under which `p` percent of the requests were answered
+  pub fn percentile(&self, p: f64) -> Duration {
+    if self.latencies.is_empty() {
+      return Duration::ZERO;
+    }
+    let rank = (p / 100.0 * self.latencies.len() as f64).ceil() as usize;
+    self.latencies[rank.clamp(1, self.latencies.len()) - 1]
+  }
+
+  fn merge(&mut self, other: BenchReport) {
+    for (status, count) in other.statuses {
+      *self.statuses.entry(status).or_default() += count;
+    }
+    self.errors += other.errors;
+    self.latencies.extend(other.latencies);
+  }
+
+  pub fn write<W: Write>(&self, mut w: W) -> crate::Result<()> {
+    let ms = |d: Duration| format!("{:.2}ms", d.as_secs_f64() * 1000.0);
+    let mut table = Table::new().with_line_prefix("  ").with_rows([
+      ["requests".to_string(), self.requests().to_string()],
+      ["errors".to_string(), self.errors.to_string()],
+      [
+        "throughput".to_string(),
+        format!("{:.1} req/s", self.throughput()),
+      ],
+      ["p50".to_string(), ms(self.percentile(50.0))],
+      ["p90".to_string(), ms(self.percentile(90.0))],
+      ["p99".to_string(), ms(self.percentile(99.0))],
+      ["max".to_string(), ms(self.percentile(100.0))],
+    ]);
+    for (status, count) in &self.statuses {
+      table.push([format!("status {}", status), count.to_string()]);
+    }
+    table.aligned().write(&mut w)?;
+    Ok(())
+  }
+}
+
+/// Sends the same request from several workers for a given duration.
+#[derive(Clone)]
+pub struct Bench {
+  client: Client,
+  request: Request,
+  concurrency: usize,
+  duration: Duration,
+}
+
+impl Bench {
+  pub fn new(client: Client, request: Request) -> Self {
+    Self {
+      client,
+      request,
+      concurrency: 1,
+      duration: Duration::from_secs(10),
+    }
+  }
+
+  pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+    self.concurrency = concurrency;
+    self
+  }
+
+  pub fn with_duration(mut self, duration: Duration) -> Self {
+    self.duration = duration;
+    self
+  }
+
+  pub fn run(self) -> crate::Result<BenchReport> {
+    if self.concurrency == 0 {
+      return Err(Error::new(
+        ErrorKind::Parse,
+        Some("concurrency must be at least 1".to_string()),
+        None,
+      ));
+    }
+    let started = Instant::now();
+    let deadline = started + self.duration;
+    let bench = Arc::new(self);
+    let workers = (0..bench.concurrency)
+      .map(|_| {
+        let bench = bench.clone();
+        thread::spawn(move || bench.worker(deadline))
+      })
+      .collect::<Vec<_>>();
+    let mut report = BenchReport::default();
+    for worker in workers {
+      let partial = worker.join().map_err(|_| {
+        Error::new(
+          ErrorKind::Sync,
+          Some("bench worker panicked".to_string()),
+          None,
+        )
+      })?;
+      report.merge(partial);
+    }
+    report.elapsed = started.elapsed();
+    report.latencies.sort();
+    Ok(report)
+  }
+
+  fn worker(&self, deadline: Instant) -> BenchReport {
+    let mut report = BenchReport::default();
+    while Instant::now() < deadline {
+      let sent = Instant::now();
+      match self.client.send(&self.request) {
+        Ok(res) => {
+          report.latencies.push(sent.elapsed());
+          *report.statuses.entry(res.status()).or_default() += 1;
+        }
+        Err(_) => report.errors += 1,
+      }
+    }
+    report
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::BenchReport;
+
+  #[test]
+  fn percentiles() {
+    let report = BenchReport {
+      latencies: (1..=100).map(Duration::from_millis).collect(),
+      elapsed: Duration::from_secs(2),
+      ..Default::default()
+    };
+    assert_eq!(report.percentile(50.0), Duration::from_millis(50));
+    assert_eq!(report.percentile(99.0), Duration::from_millis(99));
+    assert_eq!(report.percentile(100.0), Duration::from_millis(100));
+    assert_eq!(report.percentile(0.0), Duration::from_millis(1));
+    assert_eq!(report.throughput(), 50.0);
+    assert_eq!(BenchReport::default().percentile(50.0), Duration::ZERO);
+  }
+}
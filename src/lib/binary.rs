@@ -0,0 +1,289 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{random_token, Error, ErrorKind};
+
+/// Generated binary body of fixture routes, for clients handling uploads and
+/// downloads, written as a string in the `binary` route option:
+///
+/// - `random(size)`: `size` random bytes
+/// - `png(width, height)`: a gray PNG image
+/// - `jpeg(width, height)`: a gray baseline JPEG image
+/// - `pdf(pages)`: a PDF document of blank pages, 1 by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum BinaryPayload {
+  Random { size: usize },
+  Png { width: u16, height: u16 },
+  Jpeg { width: u16, height: u16 },
+  Pdf { pages: usize },
+}
+
+impl BinaryPayload {
+  pub fn generate(&self) -> Vec<u8> {
+    match *self {
+      Self::Random { size } => random_bytes(size),
+      Self::Png { width, height } => placeholder_png(width.into(), height.into()),
+      Self::Jpeg { width, height } => placeholder_jpeg(width, height),
+      Self::Pdf { pages } => placeholder_pdf(pages),
+    }
+  }
+
+  pub fn content_type(&self) -> &'static str {
+    match self {
+      Self::Random { .. } => "application/octet-stream",
+      Self::Png { .. } => "image/png",
+      Self::Jpeg { .. } => "image/jpeg",
+      Self::Pdf { .. } => "application/pdf",
+    }
+  }
+}
+
+/// `size` random bytes
+pub fn random_bytes(size: usize) -> Vec<u8> {
+  let mut bytes = Vec::with_capacity(size + 16);
+  while bytes.len() < size {
+    let token = u128::from_str_radix(&random_token(), 16).unwrap_or_default();
+    bytes.extend(token.to_le_bytes());
+  }
+  bytes.truncate(size);
+  bytes
+}
+
+fn crc32(data: &[u8]) -> u32 {
+  !data.iter().fold(!0u32, |crc, byte| {
+    (0..8).fold(crc ^ *byte as u32, |crc, _| match crc & 1 {
+      1 => (crc >> 1) ^ 0xedb8_8320,
+      _ => crc >> 1,
+    })
+  })
+}
+
+fn adler32(data: &[u8]) -> u32 {
+  let (a, b) = data.iter().fold((1u32, 0u32), |(a, b), byte| {
+    let a = (a + *byte as u32) % 65521;
+    (a, (b + a) % 65521)
+  });
+  (b << 16) | a
+}
+
+/// Gray 8-bit grayscale PNG of `width` x `height` pixels, its pixels deflated
+/// in stored blocks
+pub fn placeholder_png(width: u32, height: u32) -> Vec<u8> {
+  let (width, height) = (width.max(1), height.max(1));
+  let row = std::iter::once(0)
+    .chain(std::iter::repeat_n(0xc0, width as usize))
+    .collect::<Vec<_>>();
+  let raw = row.repeat(height as usize);
+  let mut zlib = vec![0x78, 0x01];
+  let mut blocks = raw.chunks(0xffff).peekable();
+  while let Some(block) = blocks.next() {
+    let len = block.len() as u16;
+    zlib.push(u8::from(blocks.peek().is_none()));
+    zlib.extend(len.to_le_bytes());
+    zlib.extend((!len).to_le_bytes());
+    zlib.extend(block);
+  }
+  zlib.extend(adler32(&raw).to_be_bytes());
+
+  let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+  let mut chunk = |kind: &[u8], data: &[u8]| {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+  };
+  let mut header = vec![];
+  header.extend(width.to_be_bytes());
+  header.extend(height.to_be_bytes());
+  header.extend([8, 0, 0, 0, 0]);
+  chunk(b"IHDR", &header);
+  chunk(b"IDAT", &zlib);
+  chunk(b"IEND", &[]);
+  png
+}
+
+/// Gray baseline grayscale JPEG of `width` x `height` pixels, every block
+/// being coded as a null DC difference followed by end-of-block
+pub fn placeholder_jpeg(width: u16, height: u16) -> Vec<u8> {
+  let (width, height) = (width.max(1), height.max(1));
+  let mut jpeg = vec![0xff, 0xd8];
+  let mut segment = |marker: u8, data: &[u8]| {
+    jpeg.extend([0xff, marker]);
+    jpeg.extend((data.len() as u16 + 2).to_be_bytes());
+    jpeg.extend(data);
+  };
+  // quantization table 0, all ones
+  segment(0xdb, &[[0].as_slice(), &[1; 64]].concat());
+  let mut frame = vec![8];
+  frame.extend(height.to_be_bytes());
+  frame.extend(width.to_be_bytes());
+  frame.extend([1, 1, 0x11, 0]);
+  segment(0xc0, &frame);
+  // DC and AC tables 0, each with a single 1-bit code: DC category 0, EOB
+  let table = |class: u8| {
+    let mut counts = [0u8; 16];
+    counts[0] = 1;
+    [[class].as_slice(), &counts, &[0]].concat()
+  };
+  segment(0xc4, &table(0x00));
+  segment(0xc4, &table(0x10));
+  segment(0xda, &[1, 1, 0x00, 0, 63, 0]);
+  let blocks = (width as usize).div_ceil(8) * (height as usize).div_ceil(8);
+  // 2 zero bits per block, the last byte padded with ones
+  let bits = blocks * 2;
+  jpeg.extend(std::iter::repeat_n(0u8, bits / 8));
+  if !bits.is_multiple_of(8) {
+    jpeg.push(0xff >> (bits % 8));
+  }
+  jpeg.extend([0xff, 0xd9]);
+  jpeg
+}
+
+/// PDF document of `pages` blank A4 pages, at least one
+pub fn placeholder_pdf(pages: usize) -> Vec<u8> {
+  let pages = pages.max(1);
+  let kids = (0..pages)
+    .map(|i| format!("{} 0 R", i + 3))
+    .collect::<Vec<_>>()
+    .join(" ");
+  let mut objects = vec![
+    "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+    format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids, pages),
+  ];
+  objects.extend(
+    (0..pages).map(|_| "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] >>".to_string()),
+  );
+  let mut pdf = b"%PDF-1.4\n".to_vec();
+  let mut offsets = vec![];
+  for (i, object) in objects.iter().enumerate() {
+    offsets.push(pdf.len());
+    pdf.extend(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).into_bytes());
+  }
+  let xref = pdf.len();
+  pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).into_bytes());
+  for offset in offsets {
+    pdf.extend(format!("{:010} 00000 n \n", offset).into_bytes());
+  }
+  pdf.extend(
+    format!(
+      "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+      objects.len() + 1,
+      xref
+    )
+    .into_bytes(),
+  );
+  pdf
+}
+
+impl FromStr for BinaryPayload {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = |why: &str| {
+      Error::new(
+        ErrorKind::Parse,
+        Some(format!("invalid binary payload '{}': {}", s, why)),
+        None,
+      )
+    };
+    let (name, args) = match s.trim().split_once('(') {
+      Some((name, args)) => match args.strip_suffix(')') {
+        Some(args) => (name.trim(), args),
+        None => return Err(invalid("missing ')'")),
+      },
+      None => (s.trim(), ""),
+    };
+    let args = args
+      .split([',', 'x'])
+      .map(str::trim)
+      .filter(|arg| !arg.is_empty())
+      .map(|arg| arg.parse::<usize>())
+      .collect::<Result<Vec<_>, _>>()
+      .map_err(|e| invalid(&e.to_string()))?;
+    let dimension = |n: usize| u16::try_from(n).map_err(|_| invalid("dimensions go up to 65535"));
+    match (name, &args[..]) {
+      ("random", &[size]) => Ok(Self::Random { size }),
+      ("png", &[width, height]) => Ok(Self::Png {
+        width: dimension(width)?,
+        height: dimension(height)?,
+      }),
+      ("jpeg", &[width, height]) => Ok(Self::Jpeg {
+        width: dimension(width)?,
+        height: dimension(height)?,
+      }),
+      ("pdf", []) => Ok(Self::Pdf { pages: 1 }),
+      ("pdf", &[pages]) => Ok(Self::Pdf { pages }),
+      _ => Err(invalid(
+        "expected random(size), png(width, height), jpeg(width, height) or pdf(pages)",
+      )),
+    }
+  }
+}
+
+impl TryFrom<String> for BinaryPayload {
+  type Error = Error;
+
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    value.parse()
+  }
+}
+
+impl Display for BinaryPayload {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Random { size } => write!(f, "random({})", size),
+      Self::Png { width, height } => write!(f, "png({}, {})", width, height),
+      Self::Jpeg { width, height } => write!(f, "jpeg({}, {})", width, height),
+      Self::Pdf { pages } => write!(f, "pdf({})", pages),
+    }
+  }
+}
+
+impl From<BinaryPayload> for String {
+  fn from(value: BinaryPayload) -> Self {
+    value.to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::sniff_content_type;
+
+  use super::{adler32, crc32, random_bytes, BinaryPayload};
+
+  #[test]
+  fn payloads() {
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    assert_eq!(random_bytes(100).len(), 100);
+    assert_ne!(random_bytes(16), random_bytes(16));
+
+    for spec in ["png(64x32)", "jpeg(640, 480)", "pdf(3)", "random(10)"] {
+      let payload = spec.parse::<BinaryPayload>().unwrap();
+      assert_eq!(
+        payload.to_string().parse::<BinaryPayload>().unwrap(),
+        payload
+      );
+      let body = payload.generate();
+      if !matches!(payload, BinaryPayload::Random { .. }) {
+        assert_eq!(
+          sniff_content_type(&body),
+          payload.content_type(),
+          "{}",
+          spec
+        );
+      }
+    }
+    let png = "png(3, 2)".parse::<BinaryPayload>().unwrap().generate();
+    assert_eq!(&png[16..24], &[0, 0, 0, 3, 0, 0, 0, 2]);
+    let pdf = String::from_utf8("pdf(2)".parse::<BinaryPayload>().unwrap().generate()).unwrap();
+    assert!(pdf.contains("/Count 2"));
+    assert!(pdf.ends_with("%%EOF\n"));
+    assert!("gif(1, 1)".parse::<BinaryPayload>().is_err());
+    assert!("png(1)".parse::<BinaryPayload>().is_err());
+  }
+}
@@ -0,0 +1,88 @@
+use std::sync::{Arc, Mutex};
+
+use lazy_static::lazy_static;
+
+use crate::{MediaType, Value};
+
+/// Turns a raw request body into a [`Value`], e.g. from MessagePack or CBOR.
+pub type BodyParser = fn(&[u8]) -> crate::Result<Value>;
+
+/// Custom body parsers by media type, consulted before the built-in formats
+/// by [`crate::Request::parse_body`], matchers and templates.
+pub struct BodyParsers(Vec<(MediaType, BodyParser)>);
+
+impl BodyParsers {
+  /// Parse bodies falling within `media_type` with `parser`. `media_type`
+  /// may be a range such as `application/*`; a plain type also covers
+  /// structured suffixes, `application/cbor` handling
+  /// `application/vnd.api+cbor`. Later registrations take precedence.
+  pub fn register<M: AsRef<str>>(media_type: M, parser: BodyParser) -> crate::Result<()> {
+    let media_type = media_type.as_ref().parse::<MediaType>()?;
+    let mut g = body_parsers.lock()?;
+    g.0.retain(|(m, _)| *m != media_type);
+    g.0.push((media_type, parser));
+    Ok(())
+  }
+
+  /// Parser registered for bodies of type `content_type`, if any
+  pub fn find(content_type: &MediaType) -> Option<BodyParser> {
+    let g = body_parsers.lock().ok()?;
+    g.0
+      .iter()
+      .rev()
+      .find(|(range, _)| {
+        content_type.matches(range)
+          || (range.kind == content_type.kind
+            && range.suffix.is_none()
+            && content_type.suffix.as_ref() == Some(&range.subtype))
+      })
+      .map(|(_, parser)| *parser)
+  }
+
+  pub fn unregister<M: AsRef<str>>(media_type: M) -> crate::Result<()> {
+    let media_type = media_type.as_ref().parse::<MediaType>()?;
+    body_parsers.lock()?.0.retain(|(m, _)| *m != media_type);
+    Ok(())
+  }
+}
+
+lazy_static! {
+  static ref body_parsers: Arc<Mutex<BodyParsers>> = Arc::new(Mutex::new(BodyParsers(Vec::new())));
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+  use crate::{JsonPathMatcher, Matcher, Method, Request, Value, WithPredicate};
+
+  use super::BodyParsers;
+
+  /// `key=value` pairs separated by `;`, standing for a binary format
+  fn pairs(body: &[u8]) -> crate::Result<Value> {
+    Ok(Value::from(
+      String::from_utf8_lossy(body)
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (k.to_string(), Value::from(v)))
+        .collect::<std::collections::HashMap<_, _>>(),
+    ))
+  }
+
+  #[test]
+  fn custom_format() {
+    BodyParsers::register("application/x-pairs", pairs).unwrap();
+    let req = Request::new(Method::Post, "/")
+      .with_header("Content-Type", "application/vnd.test+x-pairs")
+      .with_body("name=ada;lang=en");
+    let body: serde_json::Value = req.parse_body().unwrap();
+    assert_eq!(body, serde_json::json!({"name": "ada", "lang": "en"}));
+    assert_eq!(req.to_value().to_json()["body"]["name"], "ada");
+    assert!(
+      JsonPathMatcher::new("$.lang")
+        .with_equals("en")
+        .matches(&req)
+        .matched
+    );
+    BodyParsers::unregister("application/x-pairs").unwrap();
+    assert!(req.parse_body::<serde_json::Value>().is_err());
+  }
+}
@@ -0,0 +1,248 @@
+use std::{
+  collections::BTreeMap,
+  fs::{self, File, OpenOptions},
+  io::{BufRead, BufReader, Read, Write},
+  path::{Path, PathBuf},
+  sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{civil_date, now_millis, Error, ErrorKind, Method, Request, Response};
+
+/// Where and how recorded traffic is written, read from the `capture`
+/// section of the workspace.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CaptureConfig {
+  pub dir: PathBuf,
+  /// Gzip the capture files, requires the `gzip` feature
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub compress: bool,
+}
+
+/// One recorded request and the response it got.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Capture {
+  /// Endpoint of the route which served the request, if any
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub route: Option<String>,
+  pub method: Method,
+  pub target: String,
+  pub request_headers: Vec<(String, String)>,
+  pub request_body: String,
+  pub status: u16,
+  pub response_headers: Vec<(String, String)>,
+  pub response_body: String,
+  /// Milliseconds since the unix epoch
+  pub at: u128,
+}
+
+impl Capture {
+  pub fn new(route: Option<&str>, req: &Request, res: &Response) -> Self {
+    let (method, target) = match req.start_line().as_request() {
+      Some(start) => (start.method, start.target.clone()),
+      None => (Method::Get, String::from("/")),
+    };
+    Self {
+      route: route.map(|r| r.to_string()),
+      method,
+      target,
+      request_headers: req.owned_headers(),
+      request_body: String::from_utf8_lossy(req.body()).to_string(),
+      status: res.status(),
+      response_headers: res.owned_headers(),
+      response_body: String::from_utf8_lossy(res.body()).to_string(),
+      at: now_millis(0),
+    }
+  }
+}
+
+/// Summary of a capture file, as kept in the store index
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CaptureFile {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub route: Option<String>,
+  /// `YYYY-MM-DD`, in UTC
+  pub day: String,
+  pub count: usize,
+  pub first: u128,
+  pub last: u128,
+}
+
+/// Recorded traffic split into one JSON lines file per route and day, along
+/// with an `index.json` summarizing them, so long recording sessions stay
+/// manageable. Files are appended to, gzipped ones one member at a time.
+#[derive(Debug)]
+pub struct CaptureStore {
+  config: CaptureConfig,
+  index: Mutex<BTreeMap<String, CaptureFile>>,
+}
+
+impl CaptureStore {
+  pub const INDEX_NAME: &'static str = "index.json";
+  const UNMATCHED: &'static str = "_unmatched";
+
+  pub fn open(config: CaptureConfig) -> crate::Result<Self> {
+    if config.compress && !cfg!(feature = "gzip") {
+      return Err(Error::new(
+        ErrorKind::Parse,
+        Some("compressed captures require the `gzip` feature".to_string()),
+        None,
+      ));
+    }
+    fs::create_dir_all(&config.dir)?;
+    let index_path = config.dir.join(Self::INDEX_NAME);
+    let index = match index_path.exists() {
+      true => serde_json::from_slice(&fs::read(&index_path)?)?,
+      false => BTreeMap::new(),
+    };
+    Ok(Self {
+      config,
+      index: Mutex::new(index),
+    })
+  }
+
+  /// Files of the store, by path relative to its directory
+  pub fn index(&self) -> crate::Result<BTreeMap<String, CaptureFile>> {
+    Ok(self.index.lock()?.clone())
+  }
+
+  fn file_name(&self, capture: &Capture) -> String {
+    let slug = match capture.route.as_deref() {
+      Some(route) => route
+        .trim_matches('/')
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() || c == '-' {
+          true => c,
+          false => '_',
+        })
+        .collect::<String>(),
+      None => Self::UNMATCHED.to_string(),
+    };
+    let slug = match slug.is_empty() {
+      true => "_root".to_string(),
+      false => slug,
+    };
+    let ext = match self.config.compress {
+      true => "jsonl.gz",
+      false => "jsonl",
+    };
+    format!("{}/{}.{}", slug, day(capture.at), ext)
+  }
+
+  pub fn record(&self, capture: &Capture) -> crate::Result<()> {
+    let name = self.file_name(capture);
+    let path = self.config.dir.join(&name);
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_vec(capture)?;
+    line.push(b'\n');
+    let mut index = self.index.lock()?;
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    Self::write(file, &line, self.config.compress)?;
+    let entry = index.entry(name).or_insert_with(|| CaptureFile {
+      route: capture.route.clone(),
+      day: day(capture.at),
+      count: 0,
+      first: capture.at,
+      last: capture.at,
+    });
+    entry.count += 1;
+    entry.last = capture.at;
+    fs::write(
+      self.config.dir.join(Self::INDEX_NAME),
+      serde_json::to_vec_pretty(&*index)?,
+    )?;
+    Ok(())
+  }
+
+  fn write(mut file: File, data: &[u8], compress: bool) -> crate::Result<()> {
+    #[cfg(feature = "gzip")]
+    if compress {
+      let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+      encoder.write_all(data)?;
+      encoder.finish()?;
+      return Ok(());
+    }
+    let _ = compress;
+    file.write_all(data)?;
+    Ok(())
+  }
+
+  fn reader(path: &Path) -> crate::Result<Box<dyn Read>> {
+    let file = File::open(path)?;
+    #[cfg(feature = "gzip")]
+    if path.extension().is_some_and(|ext| ext == "gz") {
+      return Ok(Box::new(flate2::read::MultiGzDecoder::new(file)));
+    }
+    Ok(Box::new(file))
+  }
+
+  /// Every capture of the store, file by file in index order
+  pub fn load(&self) -> crate::Result<Vec<Capture>> {
+    let mut ret = vec![];
+    for name in self.index.lock()?.keys() {
+      ret.extend(Self::load_file(self.config.dir.join(name))?);
+    }
+    Ok(ret)
+  }
+
+  /// Captures of a single, possibly gzipped, JSON lines file
+  pub fn load_file<P: AsRef<Path>>(path: P) -> crate::Result<Vec<Capture>> {
+    let mut ret = vec![];
+    let reader = BufReader::new(Self::reader(path.as_ref())?);
+    for line in reader.lines() {
+      let line = line?;
+      if !line.trim().is_empty() {
+        ret.push(serde_json::from_str(&line)?);
+      }
+    }
+    Ok(ret)
+  }
+}
+
+/// UTC day of a unix timestamp in milliseconds, as `YYYY-MM-DD`
+fn day(millis: u128) -> String {
+  let (y, m, d) = civil_date(millis);
+  format!("{:04}-{:02}-{:02}", y, m, d)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Request, Response};
+
+  use super::{day, Capture, CaptureConfig, CaptureStore};
+
+  #[test]
+  fn days() {
+    assert_eq!(day(0), "1970-01-01");
+    assert_eq!(day(951_782_400_000), "2000-02-29");
+    assert_eq!(day(1_792_108_799_999), "2026-10-15");
+  }
+
+  #[test]
+  fn record() {
+    let dir = std::env::temp_dir().join(format!("mocker-capture-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let config = CaptureConfig {
+      dir: dir.clone(),
+      compress: cfg!(feature = "gzip"),
+    };
+    let store = CaptureStore::open(config.clone()).unwrap();
+    let req = Request::new(Method::Get, "/users/42");
+    let res = Response::default().with_status_code(200).with_body("{}");
+    for route in [Some("/users/{id}"), Some("/users/{id}"), None] {
+      store.record(&Capture::new(route, &req, &res)).unwrap();
+    }
+    let index = CaptureStore::open(config).unwrap().index().unwrap();
+    assert_eq!(index.len(), 2);
+    let (name, file) = index.iter().find(|(_, f)| f.route.is_some()).unwrap();
+    assert!(name.starts_with("users__id_/"));
+    assert_eq!(file.count, 2);
+    let captures = store.load().unwrap();
+    assert_eq!(captures.len(), 3);
+    assert_eq!(captures[0].target, "/users/42");
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,121 @@
+use std::{
+  io::Write,
+  net::{Shutdown, TcpStream},
+  time::Duration,
+};
+
+use crate::{Error, ErrorKind, Request, Response};
+
+/// Minimal blocking HTTP client, opening one connection per request.
+#[derive(Debug, Clone)]
+pub struct Client {
+  addr: String,
+  base_path: String,
+  timeout: Option<Duration>,
+}
+
+impl Client {
+  pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+  /// Create a client talking to `addr` (`host:port`)
+  pub fn new<A: AsRef<str>>(addr: A) -> Self {
+    Self {
+      addr: addr.as_ref().to_string(),
+      base_path: String::new(),
+      timeout: Some(Self::DEFAULT_TIMEOUT),
+    }
+  }
+
+  /// Create a client from an url such as `http://localhost:8080/api`, whose path
+  /// is prepended to every request target.
+  pub fn from_url<U: AsRef<str>>(url: U) -> crate::Result<Self> {
+    let url = url.as_ref();
+    let rest = match url.split_once("://") {
+      Some(("http", rest)) => rest,
+      None => url,
+      Some((scheme, _)) => {
+        return Err(Error::new(
+          ErrorKind::Parse,
+          Some(format!(
+            "unsupported scheme '{}' in '{}', only plain http is supported",
+            scheme, url
+          )),
+          None,
+        ))
+      }
+    };
+    let (host, path) = match rest.find('/') {
+      Some(i) => rest.split_at(i),
+      None => (rest, ""),
+    };
+    if host.is_empty() {
+      return Err(Error::new(
+        ErrorKind::Parse,
+        Some(format!("missing host in '{}'", url)),
+        None,
+      ));
+    }
+    let addr = match host.contains(':') {
+      true => host.to_string(),
+      false => format!("{}:80", host),
+    };
+    Ok(Self::new(addr).with_base_path(path.trim_end_matches('/')))
+  }
+
+  pub fn with_base_path<P: AsRef<str>>(mut self, path: P) -> Self {
+    self.base_path = path.as_ref().to_string();
+    self
+  }
+
+  pub fn with_timeout<T: Into<Option<Duration>>>(mut self, timeout: T) -> Self {
+    self.timeout = timeout.into();
+    self
+  }
+
+  pub fn addr(&self) -> &String {
+    &self.addr
+  }
+
+  pub fn send(&self, req: &Request) -> crate::Result<Response> {
+    let mut stream = TcpStream::connect(&self.addr)?;
+    stream.set_read_timeout(self.timeout)?;
+    stream.set_write_timeout(self.timeout)?;
+    let mut req = req.clone();
+    if !self.base_path.is_empty() {
+      if let Some(start) = req.start_line_mut().as_request_mut() {
+        start.target = format!("{}{}", self.base_path, start.target);
+      }
+    }
+    if req.header("Host").is_none() {
+      req.set_header("Host", &self.addr);
+    }
+    // sent in one go, the server parses whatever its first read returns
+    let mut buf = vec![];
+    req.write_to(&mut buf)?;
+    if req.body().is_empty() {
+      writeln!(buf)?;
+    }
+    stream.write_all(&buf)?;
+    stream.flush()?;
+    stream.shutdown(Shutdown::Write)?;
+    Response::from_reader(stream)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Client;
+
+  #[test]
+  fn from_url() {
+    let c = Client::from_url("http://localhost:8080/api/").unwrap();
+    assert_eq!(c.addr(), "localhost:8080");
+    assert_eq!(c.base_path, "/api");
+    assert_eq!(
+      Client::from_url("example.com").unwrap().addr(),
+      "example.com:80"
+    );
+    assert!(Client::from_url("https://example.com").is_err());
+    assert!(Client::from_url("http:///users").is_err());
+  }
+}
@@ -0,0 +1,89 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{render, TemplateContext, Value, Variables, VirtualClock};
+
+type Item = HashMap<String, Value>;
+
+/// Fields store routes add to their entities when serving them, so fixtures
+/// only hold what cannot be derived, e.g.
+/// `"fullName": "firstName + ' ' + lastName"` or `"age": "age birthDate"`.
+///
+/// Each definition is either a template, when it holds `{{...}}`, rendered
+/// to a string, or a single template expression keeping the type of its
+/// result. Entity fields are in scope by name.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ComputedFields(pub BTreeMap<String, String>);
+
+impl ComputedFields {
+  pub fn with_field<N: AsRef<str>, D: AsRef<str>>(mut self, name: N, definition: D) -> Self {
+    self
+      .0
+      .insert(name.as_ref().to_string(), definition.as_ref().to_string());
+    self
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// `item` along with its computed fields, as of now on `clock`
+  pub fn apply(&self, item: &Item, clock: &VirtualClock) -> crate::Result<Item> {
+    if self.is_empty() {
+      return Ok(item.clone());
+    }
+    let variables = Variables::default();
+    let ctx = item.iter().fold(
+      TemplateContext::new(&variables).with_clock(clock),
+      |ctx, (k, v)| ctx.with_data(k, v.clone()),
+    );
+    let mut ret = item.clone();
+    for (name, definition) in &self.0 {
+      let value = match definition.contains("{{") {
+        true => Value::from(render(definition, &ctx)?),
+        false => ctx.eval(definition)?,
+      };
+      ret.insert(name.clone(), value);
+    }
+    Ok(ret)
+  }
+
+  /// Every item of `items` along with its computed fields
+  pub fn apply_all(&self, items: &[Item], clock: &VirtualClock) -> crate::Result<Vec<Item>> {
+    items.iter().map(|item| self.apply(item, clock)).collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use crate::{civil_date, now_millis, Value, VirtualClock};
+
+  use super::ComputedFields;
+
+  #[test]
+  fn apply() {
+    let (year, _, _) = civil_date(now_millis(0));
+    let user = HashMap::from([
+      ("id".to_string(), Value::from(1)),
+      ("firstName".to_string(), Value::from("Ada")),
+      ("lastName".to_string(), Value::from("Lovelace")),
+      (
+        "birthDate".to_string(),
+        Value::from(format!("{}-01-01", year - 30)),
+      ),
+    ]);
+    let fields = ComputedFields::default()
+      .with_field("fullName", "firstName + ' ' + lastName")
+      .with_field("age", "age birthDate")
+      .with_field("greeting", "Hello {{firstName}}!");
+    let user = fields.apply(&user, &VirtualClock::default()).unwrap();
+    assert_eq!(user["fullName"], Value::from("Ada Lovelace"));
+    assert_eq!(user["age"], Value::Integer(30));
+    assert_eq!(user["greeting"], Value::from("Hello Ada!"));
+    assert_eq!(user.len(), 7);
+  }
+}
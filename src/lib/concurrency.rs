@@ -0,0 +1,51 @@
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+};
+
+use crate::Route;
+
+/// Requests each route is serving right now, keyed by [`Route::id`].
+#[derive(Debug, Default)]
+pub struct Concurrency(Mutex<HashMap<String, usize>>);
+
+impl Concurrency {
+  /// Seconds clients are told to wait when a route is saturated
+  pub const RETRY_AFTER: u64 = 1;
+
+  /// Count one more request in flight on `route`, unless `limit` are already
+  /// being served. The request is done when the permit is dropped.
+  pub fn acquire(self: &Arc<Self>, route: &Route, limit: usize) -> crate::Result<Option<Permit>> {
+    let mut g = self.0.lock()?;
+    let in_flight = g.entry(route.id()).or_default();
+    if *in_flight >= limit {
+      return Ok(None);
+    }
+    *in_flight += 1;
+    Ok(Some(Permit {
+      concurrency: self.clone(),
+      route: route.id(),
+    }))
+  }
+
+  /// Requests `route` is currently serving
+  pub fn in_flight(&self, route: &Route) -> crate::Result<usize> {
+    Ok(self.0.lock()?.get(&route.id()).copied().unwrap_or_default())
+  }
+}
+
+/// A request being served by a route with limited concurrency.
+pub struct Permit {
+  concurrency: Arc<Concurrency>,
+  route: String,
+}
+
+impl Drop for Permit {
+  fn drop(&mut self) {
+    if let Ok(mut g) = self.concurrency.0.lock() {
+      if let Some(in_flight) = g.get_mut(&self.route) {
+        *in_flight = in_flight.saturating_sub(1);
+      }
+    }
+  }
+}
@@ -1,46 +1,306 @@
 use std::{
-  fs::File,
+  collections::{BTreeMap, HashMap},
   net::{IpAddr, Ipv4Addr},
   path::{Path, PathBuf},
-  str::FromStr,
-  sync::{Arc, Mutex},
 };
 
-use crate::{config_formats, find_fmt, Error, ErrorKind, Method, Middleware};
+#[cfg(feature = "json")]
+use crate::substitute_vars;
+use crate::{
+  config_formats, endpoint_regex, find_fmt, headers::HeaderRules, render_value, tenancy::Tenancy,
+  transform::TransformRules, AuthPreset, BinaryPayload, CachePolicy, ComputedFields, Disorder,
+  Error, ErrorKind, Experiment, Fault, Hypermedia, IdStrategy, Journal, MaskRules, Method,
+  MiddlewareSpec, Pagination, RateLimit, Request, RequestMatcher, ResponseCheck, RouteScenario,
+  ScenarioConfig, Times, UpstreamConfig, Value,
+};
 use serde::{Deserialize, Serialize};
-use strum::IntoEnumIterator;
 
-pub const CONFIG_NAME: &'static str = "mocker.json";
+pub const CONFIG_NAME: &str = "mocker.json";
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(tag = "type")]
 pub enum RouteKind {
   /// A file-backed json store
   #[cfg(feature = "json")]
-  Store { path: PathBuf, identifier: String },
+  Store {
+    path: PathBuf,
+    identifier: String,
+    /// Relation scoping the store to the items of a parent resource, for
+    /// nested routes such as `/posts/:postId/comments`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent: Option<ParentRelation>,
+  },
   /// A javascript handler
   #[cfg(feature = "js")]
   Script { script: PathBuf, func: String },
+  /// An S3-compatible object storage, buckets being the directories of `root_dir`
+  #[cfg(feature = "s3")]
+  S3 { root_dir: PathBuf },
+  /// A static response, with an inline body or one read from a file
+  Fixture {
+    #[serde(default = "RouteKind::default_status")]
+    status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file: Option<PathBuf>,
+    /// Render `{{...}}` expressions found in the body
+    #[serde(default)]
+    template: bool,
+  },
+  /// A local command fed with the json request on stdin, its stdout is the response body
+  Exec {
+    command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<String>,
+    #[serde(default = "RouteKind::default_status")]
+    status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>,
+    /// Maximum run time before the command is killed, e.g. `5s`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cwd: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+    /// Do not inherit the server's environment
+    #[serde(default)]
+    clear_env: bool,
+  },
+  /// A reflection of the incoming request: method, path, headers, query and
+  /// parsed body, for inspecting what clients actually send
+  Echo {
+    #[serde(default = "RouteKind::default_status")]
+    status: u16,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    headers: BTreeMap<String, String>,
+  },
 }
 impl RouteKind {
+  pub(crate) fn default_status() -> u16 {
+    200
+  }
+
   pub fn name(&self) -> &'static str {
     match self {
+      RouteKind::Fixture { .. } => "fixture",
+      RouteKind::Exec { .. } => "exec",
+      RouteKind::Echo { .. } => "echo",
       #[cfg(feature = "json")]
       RouteKind::Store { .. } => "store",
       #[cfg(feature = "js")]
       RouteKind::Script { .. } => "script",
+      #[cfg(feature = "s3")]
+      RouteKind::S3 { .. } => "s3",
     }
   }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Route(Vec<Method>, String, RouteKind);
+/// Foreign key tying the items of a nested store route to their parent: only
+/// the items whose `field` equals the `param` path parameter are served, and
+/// created items get it set.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ParentRelation {
+  /// Foreign-key field of the items, e.g. `postId`
+  pub field: String,
+  /// Path parameter holding the parent identifier, `field` by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub param: Option<String>,
+}
+
+impl ParentRelation {
+  /// Path parameter holding the parent identifier
+  pub fn param(&self) -> &str {
+    self.param.as_deref().unwrap_or(&self.field)
+  }
+}
+
+/// Optional per-route settings, appended after the route kind
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RouteOptions {
+  /// How many times this route is expected to be called
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expect: Option<Times>,
+  /// Scenario state this route depends on and moves to
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub scenario: Option<RouteScenario>,
+  /// Header edits applied to this route's requests and responses
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub headers: Option<HeaderRules>,
+  /// Memoization of responses, for expensive templates and scripts
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub cache: Option<CachePolicy>,
+  /// Response variants served by weight, for A/B testing clients
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub experiment: Option<Experiment>,
+  /// Connection fault simulated instead of a proper response
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub fault: Option<Fault>,
+  /// Duplicated or late delivery of this route's responses
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub disorder: Option<Disorder>,
+  /// How long store routes remember `Idempotency-Key`s, 24 hours by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub idempotency_retention: Option<String>,
+  /// Read store items from disk as they are requested instead of loading
+  /// the whole file, which must be newline-delimited JSON. Cannot be
+  /// combined with `session_header` nor workspace tenancy.
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub lazy: bool,
+  /// Header keying isolated sessions, each writing to its own copy of the store
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub session_header: Option<String>,
+  /// Fields of response bodies disguised before being sent, by JSONPath
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub mask: Option<MaskRules>,
+  /// JSON schema the response body must conform to
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub schema: Option<Value>,
+  /// Envelope with links store routes wrap their entities in
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub hypermedia: Option<Hypermedia>,
+  /// Fields store routes derive from their entities when serving them
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub computed: Option<ComputedFields>,
+  /// How store routes identify items created without an identifier, see
+  /// [`IdStrategy`]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub ids: Option<IdStrategy>,
+  /// How store routes list their items when no identifier is requested
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub pagination: Option<Pagination>,
+  /// Requests allowed per window, announced through rate-limit headers
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub rate_limit: Option<RateLimit>,
+  /// Requests served at once, others being answered `503 Service Unavailable`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub max_concurrent: Option<usize>,
+  /// Generated binary body of fixture responses, in place of their `body`
+  /// or `file`, see [`BinaryPayload`]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub binary: Option<BinaryPayload>,
+  /// Content-Type of fixture responses not setting one in their headers,
+  /// otherwise guessed from the file extension or sniffed from the body
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub content_type: Option<String>,
+  /// Largest acceptable response body, in bytes
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub max_size: Option<usize>,
+  /// Authentication scheme requests must satisfy, see [`AuthPreset`]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub auth: Option<AuthPreset>,
+  /// Checks requests must pass for this route to serve them
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub when: Option<RequestMatcher>,
+  /// Glob pattern the `Host` header must match, e.g. `api.service.test`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub host: Option<String>,
+  /// Whether this route serves requests, `true` by default. Routes can be
+  /// switched on and off at runtime with the admin API.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub enabled: Option<bool>,
+  /// Labels selecting the deployments this route is part of, see `tags`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub tags: Vec<String>,
+  /// Request sent to this route by `mocker validate --execute`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub sample: Option<SampleRequest>,
+}
+
+/// Request exercising a route when validating the workspace.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SampleRequest {
+  /// Path and query, the route endpoint by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub target: Option<String>,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub headers: BTreeMap<String, String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub body: Option<Value>,
+}
+
+impl SampleRequest {
+  pub fn to_request(&self, method: Method, route: &Route) -> Request {
+    let mut req = Request::new(method, self.target.as_ref().unwrap_or(route.endpoint()))
+      .with_headers(self.headers.iter());
+    match &self.body {
+      Some(Value::String(body)) => req.with_body(body),
+      Some(body) => {
+        if req.header("Content-Type").is_none() {
+          req.set_header("Content-Type", "application/json");
+        }
+        req.with_body(render_value(body))
+      }
+      None => req,
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Route(
+  Vec<Method>,
+  String,
+  RouteKind,
+  #[serde(default)] RouteOptions,
+);
 
 impl Route {
+  pub fn new<M: IntoIterator<Item = Method>, E: AsRef<str>>(
+    methods: M,
+    endpoint: E,
+    kind: RouteKind,
+  ) -> Self {
+    Self(
+      methods.into_iter().collect(),
+      endpoint.as_ref().to_string(),
+      kind,
+      RouteOptions::default(),
+    )
+  }
+
+  pub fn with_options(mut self, options: RouteOptions) -> Self {
+    self.3 = options;
+    self
+  }
+
+  pub fn with_expect(mut self, times: Times) -> Self {
+    self.3.expect = Some(times);
+    self
+  }
+
+  pub fn options(&self) -> &RouteOptions {
+    &self.3
+  }
+
+  pub fn options_mut(&mut self) -> &mut RouteOptions {
+    &mut self.3
+  }
+
+  /// Unique, human-readable identifier of this route, e.g. `GET,POST /users`
+  pub fn id(&self) -> String {
+    format!(
+      "{} {}",
+      self
+        .methods()
+        .iter()
+        .map(|m| m.repr())
+        .collect::<Vec<_>>()
+        .join(","),
+      self.endpoint()
+    )
+  }
+
   pub fn kind(&self) -> &RouteKind {
     &self.2
   }
 
+  pub fn kind_mut(&mut self) -> &mut RouteKind {
+    &mut self.2
+  }
+
   pub fn methods(&self) -> &Vec<Method> {
     &self.0
   }
@@ -56,9 +316,65 @@ impl Route {
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct UserConfig {
+  /// Config format version, see [`crate::CONFIG_VERSION`]
+  pub version: Option<u32>,
   pub host: Option<IpAddr>,
   pub port: Option<u16>,
-  pub middlewares: Option<Vec<String>>,
+  /// Middlewares enabled, by name or along with the requests they apply to
+  pub middlewares: Option<Vec<MiddlewareSpec>>,
+  /// Maximum number of requests kept in the journal
+  pub journal_limit: Option<usize>,
+  pub scenarios: Option<HashMap<String, ScenarioConfig>>,
+  /// Header edits applied to every request and response
+  pub headers: Option<HeaderRules>,
+  /// Body edits applied to every request and response
+  pub transform: Option<TransformRules>,
+  /// How requests are scoped to a tenant, each with its own store files
+  pub tenancy: Option<Tenancy>,
+  /// HMAC signature incoming requests must carry
+  #[cfg(feature = "signature")]
+  pub signature: Option<crate::signature::Signature>,
+  /// Data profiles clients select, to be served different stores and error rates
+  pub personas: Option<crate::persona::Personas>,
+  /// Whether each client gets its responses in the order of its requests
+  pub ordering: Option<crate::ResponseOrdering>,
+  /// Strict HTTP compliance of the responses served
+  pub compliance: Option<crate::compliance::Compliance>,
+  /// Backend incoming requests are mirrored to
+  #[cfg(any(feature = "server", feature = "reqwest"))]
+  pub shadow: Option<crate::shadow::Shadow>,
+  /// Whether test runs can allocate a prefix to mount every endpoint under,
+  /// with data of their own
+  pub namespaces: Option<bool>,
+  /// Tags of the routes served, every route being when unset
+  pub tags: Option<Vec<String>>,
+  /// Timeouts, proxy, TLS and retries of outgoing calls
+  pub upstream: Option<UpstreamConfig>,
+  /// Whether served responses are checked against their route schema
+  pub check_responses: Option<ResponseCheck>,
+  /// Directory recorded traffic is written to
+  #[cfg(feature = "json")]
+  pub capture: Option<crate::CaptureConfig>,
+  /// Identity provider mock
+  #[cfg(feature = "oidc")]
+  pub oidc: Option<crate::OidcConfig>,
+  /// SMTP listener capturing sent emails
+  pub smtp: Option<crate::SmtpConfig>,
+  /// Non-HTTP listeners stubbing custom protocols
+  pub listeners: Option<Vec<crate::RawListener>>,
+  /// Virtual hostnames `mocker hosts install` resolves to the server
+  pub hosts: Option<Vec<String>>,
+  /// Forward proxy answering selected hosts with the routes
+  pub proxy: Option<crate::ProxyConfig>,
+  /// Bodies of the error responses mocker generates, by status
+  pub error_pages: Option<crate::ErrorPages>,
+  /// Commands and scripts run before the server accepts connections
+  pub startup: Option<Vec<crate::StartupHook>>,
+  /// Servers script handlers may call with `fetch`
+  #[cfg(feature = "js")]
+  pub fetch: Option<crate::FetchPolicy>,
+  /// Values shared by the routes, templates (`{{vars.name}}`) and scripts
+  pub variables: Option<BTreeMap<String, Value>>,
   pub routes: Vec<Route>,
 }
 
@@ -66,13 +382,39 @@ impl UserConfig {
   pub fn realize(&self) -> Config {
     let dflt = Config::default();
     Config {
-      host: self.host.unwrap_or_else(|| dflt.host),
-      port: self.port.unwrap_or_else(|| dflt.port),
-      middlewares: self
-        .middlewares
-        .as_ref()
-        .map(|mws| mws.clone())
-        .unwrap_or_default(),
+      version: self.version.unwrap_or(dflt.version),
+      host: self.host.unwrap_or(dflt.host),
+      port: self.port.unwrap_or(dflt.port),
+      middlewares: self.middlewares.clone().unwrap_or_default(),
+      journal_limit: self.journal_limit.unwrap_or(dflt.journal_limit),
+      scenarios: self.scenarios.clone().unwrap_or_default(),
+      headers: self.headers.clone().unwrap_or_default(),
+      transform: self.transform.clone().unwrap_or_default(),
+      tenancy: self.tenancy.clone(),
+      #[cfg(feature = "signature")]
+      signature: self.signature.clone(),
+      personas: self.personas.clone(),
+      ordering: self.ordering.clone(),
+      compliance: self.compliance.clone(),
+      #[cfg(any(feature = "server", feature = "reqwest"))]
+      shadow: self.shadow.clone(),
+      namespaces: self.namespaces.unwrap_or_default(),
+      tags: self.tags.clone(),
+      upstream: self.upstream.clone().unwrap_or_default(),
+      check_responses: self.check_responses.unwrap_or_default(),
+      #[cfg(feature = "json")]
+      capture: self.capture.clone(),
+      #[cfg(feature = "oidc")]
+      oidc: self.oidc.clone(),
+      smtp: self.smtp.clone(),
+      listeners: self.listeners.clone().unwrap_or_default(),
+      hosts: self.hosts.clone().unwrap_or_default(),
+      proxy: self.proxy.clone(),
+      error_pages: self.error_pages.clone().unwrap_or_default(),
+      startup: self.startup.clone().unwrap_or_default(),
+      #[cfg(feature = "js")]
+      fetch: self.fetch.clone(),
+      variables: self.variables.clone().unwrap_or_default(),
       routes: self.routes.clone(),
     }
   }
@@ -80,18 +422,101 @@ impl UserConfig {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+  #[serde(default)]
+  pub version: u32,
   pub host: IpAddr,
   pub port: u16,
-  pub middlewares: Vec<String>,
+  pub middlewares: Vec<MiddlewareSpec>,
+  pub journal_limit: usize,
+  pub scenarios: HashMap<String, ScenarioConfig>,
+  #[serde(default, skip_serializing_if = "HeaderRules::is_empty")]
+  pub headers: HeaderRules,
+  #[serde(default, skip_serializing_if = "TransformRules::is_empty")]
+  pub transform: TransformRules,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tenancy: Option<Tenancy>,
+  #[cfg(feature = "signature")]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub signature: Option<crate::signature::Signature>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub personas: Option<crate::persona::Personas>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub ordering: Option<crate::ResponseOrdering>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub compliance: Option<crate::compliance::Compliance>,
+  #[cfg(any(feature = "server", feature = "reqwest"))]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub shadow: Option<crate::shadow::Shadow>,
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub namespaces: bool,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tags: Option<Vec<String>>,
+  #[serde(default, skip_serializing_if = "UpstreamConfig::is_default")]
+  pub upstream: UpstreamConfig,
+  #[serde(default, skip_serializing_if = "ResponseCheck::is_off")]
+  pub check_responses: ResponseCheck,
+  #[cfg(feature = "json")]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub capture: Option<crate::CaptureConfig>,
+  #[cfg(feature = "oidc")]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub oidc: Option<crate::OidcConfig>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub smtp: Option<crate::SmtpConfig>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub listeners: Vec<crate::RawListener>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub hosts: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub proxy: Option<crate::ProxyConfig>,
+  #[serde(default, skip_serializing_if = "crate::ErrorPages::is_empty")]
+  pub error_pages: crate::ErrorPages,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub startup: Vec<crate::StartupHook>,
+  #[cfg(feature = "js")]
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub fetch: Option<crate::FetchPolicy>,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub variables: BTreeMap<String, Value>,
   pub routes: Vec<Route>,
 }
 
 impl Default for Config {
   fn default() -> Self {
     Self {
+      version: crate::CONFIG_VERSION,
       host: IpAddr::V4("127.0.0.1".parse::<Ipv4Addr>().expect("invalid loopback")),
       port: 8080,
       middlewares: vec![],
+      journal_limit: Journal::DEFAULT_LIMIT,
+      scenarios: Default::default(),
+      headers: Default::default(),
+      transform: Default::default(),
+      tenancy: None,
+      #[cfg(feature = "signature")]
+      signature: None,
+      personas: None,
+      ordering: None,
+      compliance: None,
+      #[cfg(any(feature = "server", feature = "reqwest"))]
+      shadow: None,
+      namespaces: false,
+      tags: None,
+      upstream: Default::default(),
+      check_responses: Default::default(),
+      #[cfg(feature = "json")]
+      capture: None,
+      #[cfg(feature = "oidc")]
+      oidc: None,
+      smtp: None,
+      listeners: vec![],
+      hosts: vec![],
+      proxy: None,
+      error_pages: Default::default(),
+      startup: vec![],
+      #[cfg(feature = "js")]
+      fetch: None,
+      variables: BTreeMap::new(),
       routes: Default::default(),
     }
   }
@@ -128,7 +553,58 @@ impl Config {
         ))
       }
     };
-    (fmt.deserialize)(&path)
+    let config = (fmt.deserialize)(&path)?;
+    config.validate()?;
+    Ok(config)
+  }
+
+  /// Mistakes preventing routes from being served as declared, as the id
+  /// of the route at fault and a description
+  pub fn route_problems(&self) -> Vec<(String, String)> {
+    let mut problems = vec![];
+    for route in &self.routes {
+      if let Some(Err(e)) = endpoint_regex(route.endpoint()) {
+        problems.push((route.id(), e.to_string()));
+      }
+      let options = route.options();
+      if options.lazy && options.session_header.is_some() {
+        problems.push((
+          route.id(),
+          "lazy stores cannot keep sessions apart, remove `session_header`".to_string(),
+        ));
+      }
+      if options.lazy && self.tenancy.is_some() {
+        problems.push((
+          route.id(),
+          "lazy stores cannot keep tenants apart, remove `lazy`".to_string(),
+        ));
+      }
+    }
+    problems
+  }
+
+  /// Fail on invalid scenario transitions or on the first of the
+  /// [`Config::route_problems`]
+  pub fn validate(&self) -> crate::Result<()> {
+    let mut scenarios = self.scenarios.iter().collect::<Vec<_>>();
+    scenarios.sort_by_key(|(name, _)| *name);
+    for (name, scenario) in scenarios {
+      scenario.validate().map_err(|e| {
+        Error::new(
+          ErrorKind::Parse,
+          Some(format!("scenario '{}': {}", name, e)),
+          None,
+        )
+      })?;
+    }
+    match self.route_problems().into_iter().next() {
+      Some((route, problem)) => Err(Error::new(
+        ErrorKind::Parse,
+        Some(format!("{}: {}", route, problem)),
+        None,
+      )),
+      None => Ok(()),
+    }
   }
 
   pub fn save<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
@@ -145,4 +621,66 @@ impl Config {
     };
     (fmt.serialize)(path.as_ref(), self)
   }
+
+  /// Set the workspace variable at the dotted `path`, e.g. `db.host`
+  pub fn set_variable<P: AsRef<str>>(&mut self, path: P, value: Value) {
+    fn set(target: &mut Value, path: &[&str], value: Value) {
+      match path.split_first() {
+        None => *target = value,
+        Some((key, rest)) => {
+          if !matches!(target, Value::Map(_)) {
+            *target = Value::Map(HashMap::new());
+          }
+          if let Value::Map(map) = target {
+            set(map.entry(key.to_string()).or_default(), rest, value);
+          }
+        }
+      }
+    }
+    let path = path.as_ref().split('.').collect::<Vec<_>>();
+    let target = self.variables.entry(path[0].to_string()).or_default();
+    set(target, &path[1..], value);
+  }
+
+  /// Routes with the `{{vars.name}}` expressions of their definition
+  /// replaced by the workspace variables. Template bodies are left to be
+  /// rendered per request, where personas may override the variables.
+  #[cfg(feature = "json")]
+  pub fn resolved_routes(&self) -> crate::Result<Vec<Route>> {
+    let vars = Value::from(self.variables.clone());
+    let escape = |s: String| {
+      let quoted = serde_json::Value::String(s).to_string();
+      quoted[1..quoted.len() - 1].to_string()
+    };
+    self
+      .routes
+      .iter()
+      .map(|route| {
+        let mut route = route.clone();
+        let template_body = match &mut route.2 {
+          RouteKind::Fixture {
+            body,
+            template: true,
+            ..
+          } => body.take(),
+          _ => None,
+        };
+        let json = substitute_vars(&serde_json::to_string(&route)?, &vars, escape);
+        let mut route: Route = serde_json::from_str(&json)?;
+        if let RouteKind::Fixture { body, .. } = &mut route.2 {
+          if template_body.is_some() {
+            *body = template_body;
+          }
+        }
+        Ok(route)
+      })
+      .collect()
+  }
+
+  /// Routes as defined, workspace variables being substituted with the
+  /// `json` feature only
+  #[cfg(not(feature = "json"))]
+  pub fn resolved_routes(&self) -> crate::Result<Vec<Route>> {
+    Ok(self.routes.clone())
+  }
 }
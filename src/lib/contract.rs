@@ -0,0 +1,269 @@
+use std::io::Write;
+#[cfg(feature = "json")]
+use std::{
+  collections::{hash_map::Entry, HashMap},
+  fs,
+  path::{Path, PathBuf},
+};
+
+use crate::{schema_diff, Method, Request, Response, Router, SchemaChange, UpstreamClient, Value};
+#[cfg(feature = "json")]
+use crate::{Route, RouteKind};
+
+/// `routes` with their stores copied to `dir`, so the writes replayed by a
+/// contract check leave the workspace alone. Routes sharing a store share
+/// its copy.
+#[cfg(feature = "json")]
+pub fn scratch_routes(routes: &[Route], dir: &Path) -> crate::Result<Vec<Route>> {
+  fs::create_dir_all(dir)?;
+  let mut copies: HashMap<PathBuf, PathBuf> = HashMap::new();
+  let mut ret = routes.to_vec();
+  for route in &mut ret {
+    if let RouteKind::Store { path, .. } = route.kind_mut() {
+      let n = copies.len();
+      let copy = match copies.entry(path.clone()) {
+        Entry::Occupied(e) => e.get().clone(),
+        Entry::Vacant(e) => {
+          let name = path.file_name().unwrap_or_default().to_string_lossy();
+          let copy = dir.join(format!("{}-{}", n, name));
+          if path.exists() {
+            fs::copy(&*path, &copy)?;
+          }
+          e.insert(copy).clone()
+        }
+      };
+      *path = copy;
+    }
+  }
+  Ok(ret)
+}
+
+/// Outcome of replaying one stubbed request against the real backend.
+#[derive(Debug, Clone)]
+pub struct ContractCheck {
+  pub method: Method,
+  pub endpoint: String,
+  /// Every difference found, empty when the mock matches reality
+  pub drifts: Vec<String>,
+}
+
+impl ContractCheck {
+  pub fn is_ok(&self) -> bool {
+    self.drifts.is_empty()
+  }
+}
+
+/// Compares the configured stubs with a real backend: status codes, body
+/// schemas and, optionally, the values of selected fields. Every method is
+/// replayed, so the router should serve [`scratch_routes`].
+pub struct Contract<'a> {
+  router: &'a Router,
+  upstream: Box<dyn UpstreamClient>,
+  fields: Vec<String>,
+}
+
+impl<'a> Contract<'a> {
+  pub fn new(router: &'a Router, upstream: Box<dyn UpstreamClient>) -> Self {
+    Self {
+      router,
+      upstream,
+      fields: vec![],
+    }
+  }
+
+  /// Dotted paths (e.g. `data.0.id`) whose values must be equal on both sides
+  pub fn with_fields<F: AsRef<str>, I: IntoIterator<Item = F>>(mut self, fields: I) -> Self {
+    self.fields = fields.into_iter().map(|f| f.as_ref().to_string()).collect();
+    self
+  }
+
+  pub fn check(&self) -> crate::Result<Vec<ContractCheck>> {
+    let mut ret = vec![];
+    for route in self.router.routes()? {
+      for method in route.methods() {
+        let req = Request::new(*method, route.endpoint());
+        ret.push(ContractCheck {
+          method: *method,
+          endpoint: route.endpoint().clone(),
+          drifts: self.compare(&req),
+        });
+      }
+    }
+    Ok(ret)
+  }
+
+  fn compare(&self, req: &Request) -> Vec<String> {
+    let mock = self
+      .router
+      .dispatch(req, Response::default())
+      .unwrap_or_else(|e| e.into());
+    let real = match self.upstream.send(req) {
+      Ok(res) => res,
+      Err(e) => return vec![format!("upstream request failed: {}", e)],
+    };
+    let mut drifts = vec![];
+    if mock.status() != real.status() {
+      drifts.push(format!(
+        "status is {} in the mock but {} upstream",
+        mock.status(),
+        real.status()
+      ));
+    }
+    let (mock, real) = (body_value(&mock), body_value(&real));
+    schema_drifts("body", &mock, &real, &mut drifts);
+    for field in &self.fields {
+      let (m, r) = (mock.get_path(field), real.get_path(field));
+      let same = match (m, r) {
+        (Some(m), Some(r)) => m.loose_eq(r),
+        (None, None) => true,
+        _ => false,
+      };
+      if !same {
+        drifts.push(format!(
+          "`{}` is {} in the mock but {} upstream",
+          field,
+          m.map(|v| v.to_string()).unwrap_or("missing".to_string()),
+          r.map(|v| v.to_string()).unwrap_or("missing".to_string()),
+        ));
+      }
+    }
+    drifts
+  }
+
+  pub fn write_report<W: Write>(checks: &[ContractCheck], mut w: W) -> crate::Result<()> {
+    for check in checks {
+      match check.is_ok() {
+        true => writeln!(w, "  ✔ {} {}", check.method, check.endpoint)?,
+        false => {
+          writeln!(w, "  ✘ {} {}", check.method, check.endpoint)?;
+          for drift in &check.drifts {
+            writeln!(w, "      {}", drift)?;
+          }
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+fn body_value(res: &Response) -> Value {
+  #[cfg(feature = "json")]
+  if res
+    .header("Content-Type")
+    .is_some_and(|ct| ct.contains("json"))
+  {
+    if let Ok(v) = serde_json::from_slice::<Value>(res.body()) {
+      return v;
+    }
+  }
+  match res.body().is_empty() {
+    true => Value::Null,
+    false => Value::from(String::from_utf8_lossy(res.body()).to_string()),
+  }
+}
+
+/// Collect structural differences between the schemas inferred from `mock`
+/// and `real`. Array items are merged, `null` matches anything.
+pub fn schema_drifts(path: &str, mock: &Value, real: &Value, drifts: &mut Vec<String>) {
+  let changes = schema_diff(path, &mock.infer_schema(), &real.infer_schema());
+  drifts.extend(changes.into_iter().map(|change| match change {
+    SchemaChange::Added(path) => format!("`{}` is missing in the mock", path),
+    SchemaChange::Removed(path) => format!("`{}` is missing upstream", path),
+    SchemaChange::TypeChanged { path, from, to } => {
+      format!("`{}` is {} in the mock but {} upstream", path, from, to)
+    }
+  }));
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use crate::Value;
+
+  use super::schema_drifts;
+
+  fn user(id: Value, extra: Option<(&str, Value)>) -> Value {
+    let mut m = HashMap::from([("id".to_string(), id), ("name".to_string(), "Joe".into())]);
+    if let Some((k, v)) = extra {
+      m.insert(k.to_string(), v);
+    }
+    Value::from(vec![Value::from(m)])
+  }
+
+  #[test]
+  fn schema() {
+    let mut drifts = vec![];
+    schema_drifts(
+      "body",
+      &user(1.into(), None),
+      &user(2.into(), None),
+      &mut drifts,
+    );
+    assert!(drifts.is_empty());
+
+    schema_drifts(
+      "body",
+      &user(1.into(), None),
+      &user("1".into(), Some(("email", "a@b.c".into()))),
+      &mut drifts,
+    );
+    assert_eq!(
+      drifts,
+      vec![
+        "`body.0.email` is missing in the mock",
+        "`body.0.id` is number in the mock but string upstream",
+      ]
+    );
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn scratch() {
+    use crate::{Method, Request, Response, Route, RouteKind, Router};
+
+    let root = std::env::temp_dir().join(format!("mocker-scratch-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+    let path = root.join("users.json");
+    std::fs::write(&path, r#"[{"id": 1}]"#).unwrap();
+    let route = |methods: Vec<Method>, endpoint: &str| {
+      Route::new(
+        methods,
+        endpoint,
+        RouteKind::Store {
+          path: path.clone(),
+          identifier: "id".to_string(),
+          parent: None,
+        },
+      )
+    };
+    let routes = super::scratch_routes(
+      &[
+        route(vec![Method::Get, Method::Post], "/users"),
+        route(vec![Method::Get], "/people"),
+      ],
+      &root.join("scratch"),
+    )
+    .unwrap();
+    let router = Router::default().with_routes(routes);
+    let created = router
+      .dispatch(
+        &Request::new(Method::Post, "/users")
+          .with_header("Content-Type", "application/json")
+          .with_body(r#"{"id": 2}"#),
+        Response::default(),
+      )
+      .unwrap();
+    assert_eq!(created.status(), 201);
+    let people = router
+      .dispatch(
+        &Request::new(Method::Get, "/people?id=2"),
+        Response::default(),
+      )
+      .unwrap();
+    assert_eq!(people.status(), 200);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), r#"[{"id": 1}]"#);
+    std::fs::remove_dir_all(&root).unwrap();
+  }
+}
@@ -0,0 +1,14 @@
+use crate::{Response, Status};
+
+/// Path of the dashboard, relative to the admin prefix
+pub const DASHBOARD_PATH: &str = "/ui";
+
+const INDEX: &str = include_str!("index.html");
+
+/// Single page web UI built on top of the admin API
+pub fn dashboard() -> Response {
+  Response::default()
+    .with_status(Status::OK)
+    .with_header("Content-Type", "text/html; charset=utf-8")
+    .with_body(INDEX)
+}
@@ -0,0 +1,344 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  fmt::Display,
+  io::Write,
+};
+
+use crate::{Config, Route, RouteOptions, Workspace};
+
+/// A single difference between two workspaces.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+  Added(String),
+  Removed(String),
+  /// Subject, with a description of what changed
+  Changed(String, String),
+}
+
+impl Display for Change {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Change::Added(what) => write!(f, "\x1b[32m+ {}\x1b[0m", what),
+      Change::Removed(what) => write!(f, "\x1b[31m- {}\x1b[0m", what),
+      Change::Changed(what, how) => write!(f, "\x1b[33m~ {}\x1b[0m: {}", what, how),
+    }
+  }
+}
+
+/// Differences in config, routes and store contents between two workspaces.
+#[derive(Debug, Default, Clone)]
+pub struct WorkspaceDiff {
+  pub config: Vec<Change>,
+  pub routes: Vec<Change>,
+  /// Store item changes, by store route
+  pub stores: BTreeMap<String, Vec<Change>>,
+}
+
+impl WorkspaceDiff {
+  pub fn new(a: &Workspace, b: &Workspace) -> Self {
+    Self {
+      config: diff_config(&a.config, &b.config),
+      routes: diff_routes(&a.config.routes, &b.config.routes),
+      stores: diff_stores(a, b),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.config.is_empty() && self.routes.is_empty() && self.stores.is_empty()
+  }
+
+  pub fn write<W: Write>(&self, mut w: W) -> crate::Result<()> {
+    let mut section = |title: &str, changes: &Vec<Change>| -> crate::Result<()> {
+      if changes.is_empty() {
+        return Ok(());
+      }
+      writeln!(w, "\x1b[1;4m{}\x1b[0m\n", title)?;
+      for change in changes {
+        writeln!(w, "  {}", change)?;
+      }
+      writeln!(w)?;
+      Ok(())
+    };
+    section("Config", &self.config)?;
+    section("Routes", &self.routes)?;
+    for (endpoint, changes) in &self.stores {
+      section(&format!("Store {}", endpoint), changes)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(feature = "json")]
+fn diff_stores(a: &Workspace, b: &Workspace) -> BTreeMap<String, Vec<Change>> {
+  use crate::{RouteKind, Value};
+  use std::collections::HashMap;
+
+  let items = |w: &Workspace, endpoint: &String| {
+    let mut ret = BTreeMap::new();
+    for route in w.config.routes.iter().filter(|r| r.endpoint() == endpoint) {
+      if let RouteKind::Store {
+        path, identifier, ..
+      } = route.kind()
+      {
+        let mut store = crate::Store::json(w.dir().join(path), identifier);
+        if store.load().is_ok() {
+          for item in store.items() {
+            let id = item.get(identifier).cloned().unwrap_or_default();
+            ret.insert(id.to_string(), item.clone());
+          }
+        }
+      }
+    }
+    ret
+  };
+  let mut ret = BTreeMap::new();
+  let endpoints = a
+    .config
+    .routes
+    .iter()
+    .chain(b.config.routes.iter())
+    .filter(|r| matches!(r.kind(), RouteKind::Store { .. }))
+    .map(|r| r.endpoint())
+    .collect::<BTreeSet<_>>();
+  for endpoint in endpoints {
+    let changes = diff_maps(
+      &items(a, endpoint),
+      &items(b, endpoint),
+      |id| format!("item {}", id),
+      |x: &HashMap<String, Value>, y: &HashMap<String, Value>| {
+        let fields = x
+          .keys()
+          .chain(y.keys())
+          .filter(|k| x.get(*k) != y.get(*k))
+          .collect::<BTreeSet<_>>();
+        match fields.is_empty() {
+          true => None,
+          false => Some(format!(
+            "fields {} differ",
+            fields
+              .into_iter()
+              .map(|f| format!("`{}`", f))
+              .collect::<Vec<_>>()
+              .join(", ")
+          )),
+        }
+      },
+    );
+    if !changes.is_empty() {
+      ret.insert(endpoint.clone(), changes);
+    }
+  }
+  ret
+}
+
+#[cfg(not(feature = "json"))]
+fn diff_stores(_a: &Workspace, _b: &Workspace) -> BTreeMap<String, Vec<Change>> {
+  BTreeMap::new()
+}
+
+/// Every difference between two JSON trees, objects being compared key by
+/// key and anything else as a whole, labelled by their dotted path
+#[cfg(feature = "json")]
+fn diff_json(path: &str, a: &serde_json::Value, b: &serde_json::Value, changes: &mut Vec<Change>) {
+  use serde_json::Value;
+
+  let label = |key: &str| match path.is_empty() {
+    true => key.to_string(),
+    false => format!("{}.{}", path, key),
+  };
+  match (a, b) {
+    (Value::Object(x), Value::Object(y)) => {
+      for key in x.keys().chain(y.keys()).collect::<BTreeSet<_>>() {
+        match (x.get(key), y.get(key)) {
+          (Some(x), Some(y)) => diff_json(&label(key), x, y, changes),
+          (Some(_), None) => changes.push(Change::Removed(label(key))),
+          (None, Some(_)) => changes.push(Change::Added(label(key))),
+          (None, None) => {}
+        }
+      }
+    }
+    (x, y) if x != y => changes.push(Change::Changed(path.to_string(), format!("{} → {}", x, y))),
+    _ => {}
+  }
+}
+
+/// Differences between two serializable values, by dotted path
+#[cfg(feature = "json")]
+fn diff_serialized<T: serde::Serialize>(a: &T, b: &T) -> Vec<Change> {
+  let tree = |v: &T| serde_json::to_value(v).unwrap_or_default();
+  let mut ret = vec![];
+  diff_json("", &tree(a), &tree(b), &mut ret);
+  ret
+}
+
+#[cfg(feature = "json")]
+fn diff_config(a: &Config, b: &Config) -> Vec<Change> {
+  // routes are compared on their own
+  let without_routes = |c: &Config| Config {
+    routes: vec![],
+    ..c.clone()
+  };
+  diff_serialized(&without_routes(a), &without_routes(b))
+}
+
+#[cfg(not(feature = "json"))]
+fn diff_config(a: &Config, b: &Config) -> Vec<Change> {
+  let without_routes = |c: &Config| {
+    format!(
+      "{:?}",
+      Config {
+        routes: vec![],
+        ..c.clone()
+      }
+    )
+  };
+  match without_routes(a) == without_routes(b) {
+    true => vec![],
+    false => vec![Change::Changed(
+      "config".to_string(),
+      "settings changed".to_string(),
+    )],
+  }
+}
+
+/// How the options of a route changed, one description per option
+#[cfg(feature = "json")]
+fn diff_options(a: &RouteOptions, b: &RouteOptions) -> Vec<String> {
+  diff_serialized(a, b)
+    .into_iter()
+    .map(|change| match change {
+      Change::Added(what) => format!("option {} added", what),
+      Change::Removed(what) => format!("option {} removed", what),
+      Change::Changed(what, how) => format!("option {} {}", what, how),
+    })
+    .collect()
+}
+
+#[cfg(not(feature = "json"))]
+fn diff_options(a: &RouteOptions, b: &RouteOptions) -> Vec<String> {
+  match a == b {
+    true => vec![],
+    false => vec!["options changed".to_string()],
+  }
+}
+
+fn diff_routes(a: &[Route], b: &[Route]) -> Vec<Change> {
+  let by_id = |routes: &[Route]| {
+    routes
+      .iter()
+      .map(|r| (r.id(), r.clone()))
+      .collect::<BTreeMap<_, _>>()
+  };
+  diff_maps(
+    &by_id(a),
+    &by_id(b),
+    |id| id.to_string(),
+    |x: &Route, y: &Route| {
+      let mut what = vec![];
+      if x.kind_str() != y.kind_str() {
+        what.push(format!("kind {} → {}", x.kind_str(), y.kind_str()));
+      } else if x.kind() != y.kind() {
+        what.push(format!("{} settings changed", x.kind_str()));
+      }
+      what.extend(diff_options(x.options(), y.options()));
+      (!what.is_empty()).then(|| what.join(", "))
+    },
+  )
+}
+
+/// Compare two keyed collections, `changed` describing how two values differ
+fn diff_maps<K: Ord + Display, V, L: Fn(&K) -> String, C: Fn(&V, &V) -> Option<String>>(
+  a: &BTreeMap<K, V>,
+  b: &BTreeMap<K, V>,
+  label: L,
+  changed: C,
+) -> Vec<Change> {
+  let keys = a.keys().chain(b.keys()).collect::<BTreeSet<_>>();
+  keys
+    .into_iter()
+    .filter_map(|k| match (a.get(k), b.get(k)) {
+      (Some(x), Some(y)) => changed(x, y).map(|how| Change::Changed(label(k), how)),
+      (Some(_), None) => Some(Change::Removed(label(k))),
+      (None, Some(_)) => Some(Change::Added(label(k))),
+      (None, None) => None,
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{compliance::Compliance, Config, Method, Route, RouteKind, RouteOptions, Workspace};
+
+  use super::{Change, WorkspaceDiff};
+
+  fn fixture(status: u16) -> RouteKind {
+    RouteKind::Fixture {
+      status,
+      headers: Default::default(),
+      body: None,
+      file: None,
+      template: false,
+    }
+  }
+
+  fn workspace(port: u16, routes: Vec<Route>) -> Workspace {
+    Workspace {
+      path: "mocker.json".into(),
+      config: Config {
+        port,
+        routes,
+        ..Default::default()
+      },
+    }
+  }
+
+  #[test]
+  fn routes_and_config() {
+    let a = workspace(
+      8080,
+      vec![
+        Route::new(vec![Method::Get], "/health", fixture(200)),
+        Route::new(vec![Method::Get], "/old", fixture(200)),
+      ],
+    );
+    let mut b = workspace(
+      9090,
+      vec![
+        Route::new(vec![Method::Get], "/health", fixture(503)).with_options(RouteOptions {
+          idempotency_retention: Some("1h".to_string()),
+          ..Default::default()
+        }),
+        Route::new(vec![Method::Post], "/new", fixture(201)),
+      ],
+    );
+    b.config.compliance = Some(Compliance {
+      server: Some("edge".to_string()),
+    });
+    let diff = WorkspaceDiff::new(&a, &b);
+    assert_eq!(
+      diff.config,
+      vec![
+        Change::Added("compliance".into()),
+        Change::Changed("port".into(), "8080 → 9090".into()),
+      ]
+    );
+    let mut c = workspace(9090, b.config.routes.clone());
+    c.config.compliance = Some(Compliance::default());
+    assert_eq!(
+      WorkspaceDiff::new(&c, &b).config,
+      vec![Change::Added("compliance.server".into())]
+    );
+    assert_eq!(
+      diff.routes,
+      vec![
+        Change::Changed(
+          "GET /health".into(),
+          "fixture settings changed, option idempotency_retention added".into()
+        ),
+        Change::Removed("GET /old".into()),
+        Change::Added("POST /new".into()),
+      ]
+    );
+    assert!(WorkspaceDiff::new(&a, &a).is_empty());
+  }
+}
@@ -0,0 +1,1324 @@
+use std::{sync::Arc, time::Instant};
+
+use log::debug;
+
+use crate::{
+  Admin, Config, ErrorPages, Explanation, Journal, JournalEntry, Mailbox, Metrics, Middleware,
+  Request, RequestId, Response, RouteOptions, Router, WorkspaceVars,
+};
+
+/// Request handling without any transport: middlewares, admin API, routing
+/// and journaling. Embed it to serve mocks from another server or a lambda.
+#[derive(Default, Clone)]
+pub struct Engine {
+  router: Arc<Router>,
+  admin: Arc<Admin>,
+  journal: Arc<Journal>,
+  metrics: Arc<Metrics>,
+  mailbox: Arc<Mailbox>,
+  middlewares: Vec<Arc<dyn Middleware>>,
+  error_pages: Arc<ErrorPages>,
+  vars: WorkspaceVars,
+  #[cfg(feature = "json")]
+  capture: Option<Arc<crate::CaptureStore>>,
+}
+
+impl Engine {
+  /// Engine serving the routes and scenarios of `config`, without
+  /// middlewares. Fails when workspace variables cannot be substituted.
+  pub fn new(config: &Config) -> crate::Result<Self> {
+    let router = Router::default()
+      .with_scenarios(config.scenarios.clone())
+      .with_response_check(config.check_responses)
+      .with_namespaces(config.namespaces)
+      .with_tags(config.tags.clone());
+    #[cfg(feature = "js")]
+    let router = router.with_fetch(config.fetch.clone().map(|policy| match policy.origin {
+      Some(_) => policy,
+      None => policy.with_origin(format!("http://{}:{}", config.host, config.port)),
+    }));
+    let router = Arc::new(router.with_routes(config.resolved_routes()?));
+    let journal = Arc::new(Journal::new(config.journal_limit));
+    let metrics = Arc::new(Metrics::default());
+    let mailbox = Arc::new(Mailbox::default());
+    Ok(Self {
+      admin: Arc::new(
+        Admin::new(router.clone())
+          .with_journal(journal.clone())
+          .with_metrics(metrics.clone())
+          .with_mailbox(mailbox.clone()),
+      ),
+      router,
+      journal,
+      metrics,
+      mailbox,
+      middlewares: Vec::new(),
+      error_pages: Arc::new(config.error_pages.clone()),
+      vars: WorkspaceVars(Arc::new(config.variables.clone())),
+      #[cfg(feature = "json")]
+      capture: None,
+    })
+  }
+
+  /// Engine set up as `mocker serve` would, middlewares included
+  pub fn from_config(config: &Config) -> crate::Result<Self> {
+    Self::new(config)?.with_config_features(config)
+  }
+
+  /// Add the middlewares, traffic capture and identity provider `config`
+  /// enables on top of its routes
+  pub fn with_config_features(self, config: &Config) -> crate::Result<Self> {
+    #[allow(unused_mut)]
+    let mut engine = self.with_config_middlewares(config)?;
+    #[cfg(feature = "json")]
+    if let Some(capture) = &config.capture {
+      engine = engine.with_capture(crate::CaptureStore::open(capture.clone())?);
+    }
+    #[cfg(feature = "oidc")]
+    if let Some(oidc) = &config.oidc {
+      let oidc = crate::Oidc::new(oidc.clone(), engine.router.tokens().clone())?;
+      engine.admin = Arc::new((*engine.admin).clone().with_oidc(oidc));
+    }
+    Ok(engine)
+  }
+
+  pub fn router(&self) -> &Arc<Router> {
+    &self.router
+  }
+
+  pub fn admin(&self) -> &Arc<Admin> {
+    &self.admin
+  }
+
+  pub fn journal(&self) -> &Arc<Journal> {
+    &self.journal
+  }
+
+  pub fn metrics(&self) -> &Arc<Metrics> {
+    &self.metrics
+  }
+
+  /// Emails captured by the SMTP listener
+  pub fn mailbox(&self) -> &Arc<Mailbox> {
+    &self.mailbox
+  }
+
+  /// Serve `pages` in place of the error responses mocker generates
+  pub fn with_error_pages(mut self, pages: ErrorPages) -> Self {
+    self.error_pages = Arc::new(pages);
+    self
+  }
+
+  /// Record every routed exchange to `capture`
+  #[cfg(feature = "json")]
+  pub fn with_capture(mut self, capture: crate::CaptureStore) -> Self {
+    self.capture = Some(Arc::new(capture));
+    self
+  }
+
+  pub fn with_middleware<M: Middleware + 'static>(mut self, m: M) -> Self {
+    self.middlewares.push(Arc::new(m));
+    self
+  }
+
+  /// Add the middlewares `config` enables, unless already present
+  pub fn with_config_middlewares(mut self, config: &Config) -> crate::Result<Self> {
+    #[cfg(any(feature = "server", feature = "reqwest"))]
+    if let Some(shadow) = config.shadow.clone() {
+      let upstream = &config.upstream;
+      self = self.with_middleware(crate::shadow::ShadowMiddleware::new(shadow, upstream)?);
+    }
+    if let Some(namespaces) = self.router.namespaces().cloned() {
+      self = self.with_middleware(crate::namespace::NamespaceMiddleware::new(namespaces));
+    }
+    if !config.headers.is_empty() {
+      let rules = config.headers.clone();
+      self = self.with_middleware(crate::headers::HeadersMiddleware::new(rules));
+    }
+    if !config.transform.is_empty() {
+      let rules = config.transform.clone();
+      self = self.with_middleware(crate::transform::TransformMiddleware::new(rules));
+    }
+    if let Some(tenancy) = config.tenancy.clone() {
+      self = self.with_middleware(crate::tenancy::TenancyMiddleware::new(tenancy));
+    }
+    if let Some(personas) = config.personas.clone() {
+      self = self.with_middleware(crate::persona::PersonaMiddleware::new(personas));
+    }
+    #[cfg(feature = "signature")]
+    if let Some(signature) = config.signature.clone() {
+      self = self.with_middleware(crate::signature::SignatureMiddleware::new(signature));
+    }
+    #[cfg(feature = "cors")]
+    crate::Middlewares::register(String::from(crate::cors::CORS_MW_NAME), || {
+      Ok(Arc::new(crate::cors::CorsMiddleware::new()))
+    });
+    for spec in &config.middlewares {
+      let found = self
+        .middlewares
+        .iter()
+        .any(|mw| mw.name().eq_ignore_ascii_case(spec.name()));
+      if !found {
+        self.middlewares.push(spec.create()?)
+      }
+    }
+    if let Some(compliance) = config.compliance.clone() {
+      let clock = self.router.clock().clone();
+      self = self.with_middleware(
+        crate::compliance::ComplianceMiddleware::new(compliance).with_clock(clock),
+      );
+    }
+    Ok(self)
+  }
+
+  /// Let every middleware adjust the incoming request
+  pub fn prepare(&self, req: &mut Request) -> crate::Result<()> {
+    if req.extensions().get::<RequestId>().is_none() {
+      let id = RequestId::of(req);
+      req.extensions_mut().insert(id);
+    }
+    if req.extensions().get::<WorkspaceVars>().is_none() {
+      req.extensions_mut().insert(self.vars.clone());
+    }
+    for middleware in &self.middlewares {
+      debug!("Preparing request with middleware: {}", middleware.name());
+      middleware.prepare(req)?;
+    }
+    Ok(())
+  }
+
+  /// Answer a prepared request, along with the options of the route which
+  /// served it (default ones for the admin API or unmatched requests).
+  pub fn respond(&self, req: &Request) -> crate::Result<(Response, RouteOptions)> {
+    let started = Instant::now();
+    let mut res = Response::default();
+    for middleware in &self.middlewares {
+      debug!("Executing middleware: {}", middleware.name());
+      res = middleware.execute(req, res)?;
+    }
+    let mut options = None;
+    let discovered = self.admin.discover(req)?;
+    res = match (Admin::handles(req), discovered) {
+      (true, _) => self.admin.handle(req)?,
+      (false, Some(res)) => res,
+      (false, None) => {
+        let route = self.router.route(req)?;
+        let (variables, clock) = (self.router.variables(), self.router.clock());
+        let res = match (self.router.dispatch(req, res), &route) {
+          (Ok(res), None) => self.error_pages.apply(req, res, variables, clock),
+          (Err(e), _) if !self.error_pages.is_empty() => {
+            self.error_pages.apply(req, e.into(), variables, clock)
+          }
+          (res, _) => res,
+        };
+        self
+          .journal
+          .record(JournalEntry::new(req, res.as_ref().ok()))?;
+        self.metrics.record(
+          route
+            .as_ref()
+            .map_or(Metrics::UNMATCHED.to_string(), |r| r.id()),
+          started.elapsed(),
+          res.as_ref().ok().map(|res| res.status()),
+        )?;
+        #[cfg(feature = "json")]
+        if let (Some(capture), Ok(res)) = (&self.capture, &res) {
+          let endpoint = route.as_ref().map(|r| r.endpoint().as_str());
+          if let Err(e) = capture.record(&crate::Capture::new(endpoint, req, res)) {
+            log::warn!("Failed to capture exchange: {}", e);
+          }
+        }
+        options = route.map(|r| r.options().clone());
+        res?
+      }
+    };
+    for middleware in &self.middlewares {
+      res = middleware.finish(req, res)?;
+    }
+    Ok((res, options.unwrap_or_default()))
+  }
+
+  /// Run `req` through the whole pipeline, errors becoming error responses
+  pub fn handle(&self, mut req: Request) -> Response {
+    match self.prepare(&mut req).and_then(|_| self.respond(&req)) {
+      Ok((res, _options)) => res,
+      Err(e) => self
+        .error_pages
+        .apply(&req, e.into(), self.router.variables(), self.router.clock())
+        .unwrap_or_else(|e| e.into()),
+    }
+  }
+
+  /// Route `req` without serving it, after letting middlewares prepare it
+  pub fn explain(&self, mut req: Request) -> crate::Result<Explanation> {
+    self.prepare(&mut req)?;
+    let names = self.middlewares.iter().map(|m| m.name().clone()).collect();
+    Explanation::new(&self.router, &req, names)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+  };
+
+  use crate::{
+    AuthPreset, Config, HeaderMatcher, Method, Middleware, Request, RequestMatcher, Response,
+    ResponseCheck, Route, RouteKind, RouteOptions, RouteScenario, Value, WithPredicate,
+    SCENARIO_STARTED,
+  };
+
+  use super::Engine;
+
+  #[test]
+  fn handle() {
+    let config = Config {
+      routes: vec![Route::new(
+        vec![Method::Get],
+        "/health",
+        RouteKind::Fixture {
+          status: 200,
+          headers: Default::default(),
+          body: Some(Value::from("ok")),
+          file: None,
+          template: false,
+        },
+      )],
+      ..Default::default()
+    };
+    let engine = Engine::from_config(&config).unwrap();
+    let res = engine.handle(Request::new(Method::Get, "/health"));
+    assert_eq!(res.status(), 200);
+    assert_eq!(&res.body()[..], b"ok");
+    assert_eq!(
+      engine.handle(Request::new(Method::Get, "/nope")).status(),
+      404
+    );
+    assert_eq!(engine.journal().len().unwrap(), 2);
+  }
+
+  #[test]
+  fn sniffed_content_type() {
+    let fixture = |endpoint: &str, body: &str| {
+      Route::new(
+        vec![Method::Get],
+        endpoint,
+        RouteKind::Fixture {
+          status: 200,
+          headers: Default::default(),
+          body: Some(Value::from(body)),
+          file: None,
+          template: false,
+        },
+      )
+    };
+    let mut forced = fixture("/feed", "<rss/>");
+    forced.options_mut().content_type = Some("application/rss+xml".to_string());
+    let mut avatar = fixture("/avatar", "{{png 1 1}}");
+    avatar.options_mut().binary = Some("jpeg(64x64)".parse().unwrap());
+    let engine = Engine::new(&Config {
+      routes: vec![
+        fixture("/page", "<html><body>hi</body></html>"),
+        forced,
+        avatar,
+      ],
+      ..Default::default()
+    })
+    .unwrap();
+    let content_type = |path| {
+      let res = engine.handle(Request::new(Method::Get, path));
+      res.header("Content-Type").map(str::to_string)
+    };
+    assert_eq!(
+      content_type("/page").as_deref(),
+      Some("text/html; charset=utf-8")
+    );
+    assert_eq!(
+      content_type("/feed").as_deref(),
+      Some("application/rss+xml")
+    );
+    assert_eq!(content_type("/avatar").as_deref(), Some("image/jpeg"));
+    let avatar = engine.handle(Request::new(Method::Get, "/avatar"));
+    assert!(avatar.body().starts_with(b"\xff\xd8"));
+  }
+
+  #[test]
+  fn merge_patch() {
+    let path = std::env::temp_dir().join(format!("mocker-patch-{}.json", std::process::id()));
+    std::fs::write(
+      &path,
+      r#"[{"id": 1, "name": "ada", "address": {"city": "London", "zip": "N1"}}]"#,
+    )
+    .unwrap();
+    let engine = Engine::new(&Config {
+      routes: vec![Route::new(
+        vec![Method::Get, Method::Patch],
+        "/users",
+        RouteKind::Store {
+          path: path.clone(),
+          identifier: "id".to_string(),
+          parent: None,
+        },
+      )],
+      ..Default::default()
+    })
+    .unwrap();
+    let res = engine.handle(
+      Request::new(Method::Patch, "/users?id=1")
+        .with_header("Content-Type", "application/merge-patch+json")
+        .with_body(r#"{"name": null, "address": {"zip": "E1", "street": "Baker"}}"#),
+    );
+    assert_eq!(res.status(), 200);
+    let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(
+      body,
+      serde_json::json!({"id": 1, "address": {"city": "London", "zip": "E1", "street": "Baker"}})
+    );
+    let stored = std::fs::read_to_string(&path).unwrap();
+    assert!(!stored.contains("ada") && stored.contains("Baker"));
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn method_not_allowed() {
+    let fixture = |methods, endpoint: &str| {
+      Route::new(
+        methods,
+        endpoint,
+        RouteKind::Fixture {
+          status: 200,
+          headers: Default::default(),
+          body: None,
+          file: None,
+          template: false,
+        },
+      )
+    };
+    let engine = Engine::new(&Config {
+      routes: vec![
+        fixture(vec![Method::Get, Method::Delete], "/users/{id}"),
+        fixture(vec![Method::Post], "/users/me"),
+      ],
+      ..Default::default()
+    })
+    .unwrap();
+    let res = engine.handle(Request::new(Method::Put, "/users/me"));
+    assert_eq!(res.status(), 405);
+    assert_eq!(res.header("Allow"), Some("POST, GET, DELETE"));
+    assert_eq!(
+      engine
+        .handle(Request::new(Method::Delete, "/users/me"))
+        .status(),
+      200
+    );
+    assert_eq!(
+      engine
+        .handle(Request::new(Method::Put, "/orders/1"))
+        .status(),
+      404
+    );
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn discover() {
+    let config = Config {
+      routes: vec![Route::new(
+        vec![Method::Get, Method::Delete],
+        "/users/{id}",
+        RouteKind::Fixture {
+          status: 204,
+          headers: Default::default(),
+          body: None,
+          file: None,
+          template: false,
+        },
+      )],
+      ..Default::default()
+    };
+    let engine = Engine::from_config(&config).unwrap();
+    let res = engine.handle(Request::new(Method::Options, "/"));
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.header("Allow"), Some("GET, DELETE"));
+    let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body[0]["endpoint"], "/users/{id}");
+    assert_eq!(body[0]["params"], serde_json::json!(["id"]));
+  }
+
+  #[test]
+  fn when() {
+    let fixture = |body: &str| RouteKind::Fixture {
+      status: 200,
+      headers: Default::default(),
+      body: Some(Value::from(body)),
+      file: None,
+      template: false,
+    };
+    let config = Config {
+      routes: vec![
+        Route::new(vec![Method::Get], "/users", fixture("beta")).with_options(
+          crate::RouteOptions {
+            when: Some(
+              RequestMatcher::new()
+                .with_header(HeaderMatcher::new("X-Variant").with_equals("beta")),
+            ),
+            ..Default::default()
+          },
+        ),
+        Route::new(vec![Method::Get, Method::Head], "/users", fixture("stable")),
+      ],
+      ..Default::default()
+    };
+    let engine = Engine::from_config(&config).unwrap();
+    let body = |req: Request| engine.handle(req).body().clone();
+    assert_eq!(&body(Request::new(Method::Get, "/users"))[..], b"stable");
+    assert_eq!(
+      &body(Request::new(Method::Get, "/users").with_header("X-Variant", "beta"))[..],
+      b"beta"
+    );
+    let explanation = engine.explain(Request::new(Method::Get, "/users")).unwrap();
+    assert_eq!(explanation.selected, Some(1));
+    assert_eq!(
+      explanation.routes[0].matchers[2].detail,
+      "header X-Variant is missing"
+    );
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn max_concurrent() {
+    let route = Route::new(
+      vec![Method::Get],
+      "/reports",
+      RouteKind::Fixture {
+        status: 200,
+        headers: Default::default(),
+        body: None,
+        file: None,
+        template: false,
+      },
+    )
+    .with_options(crate::RouteOptions {
+      max_concurrent: Some(1),
+      ..Default::default()
+    });
+    let engine = Engine::from_config(&Config {
+      routes: vec![route.clone()],
+      ..Default::default()
+    })
+    .unwrap();
+    let concurrency = engine.router().concurrency();
+    let busy = concurrency.acquire(&route, 1).unwrap();
+    assert!(busy.is_some());
+    let res = engine.handle(Request::new(Method::Get, "/reports"));
+    assert_eq!(res.status(), 503);
+    assert_eq!(res.header("Retry-After"), Some("1"));
+    drop(busy);
+    assert_eq!(concurrency.in_flight(&route).unwrap(), 0);
+    let res = engine.handle(Request::new(Method::Get, "/reports"));
+    assert_eq!(res.status(), 200);
+    assert_eq!(concurrency.in_flight(&route).unwrap(), 0);
+  }
+
+  #[test]
+  fn check_responses() {
+    let route = Route::new(
+      vec![Method::Get],
+      "/users",
+      RouteKind::Fixture {
+        status: 200,
+        headers: Default::default(),
+        body: Some(Value::from("ok")),
+        file: None,
+        template: false,
+      },
+    )
+    .with_options(crate::RouteOptions {
+      schema: Some(serde_json::from_str(r#"{"type": "array"}"#).unwrap()),
+      ..Default::default()
+    });
+    let status = |check_responses| {
+      let config = Config {
+        routes: vec![route.clone()],
+        check_responses,
+        ..Default::default()
+      };
+      let engine = Engine::from_config(&config).unwrap();
+      engine.handle(Request::new(Method::Get, "/users")).status()
+    };
+    assert_eq!(status(ResponseCheck::Off), 200);
+    assert_eq!(status(ResponseCheck::Warn), 200);
+    assert_eq!(status(ResponseCheck::Strict), 500);
+  }
+
+  #[test]
+  fn thread_safe() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Engine>();
+    assert_send_sync::<crate::Router>();
+    assert_send_sync::<crate::Error>();
+    assert_send_sync::<Request>();
+    assert_send_sync::<Response>();
+  }
+
+  struct Slow(String);
+
+  impl Middleware for Slow {
+    fn name(&self) -> &String {
+      &self.0
+    }
+
+    fn supported_methods(&self) -> Vec<Method> {
+      vec![Method::Get]
+    }
+
+    fn execute(&self, _request: &Request, response: Response) -> crate::Result<Response> {
+      std::thread::sleep(Duration::from_millis(100));
+      Ok(response)
+    }
+  }
+
+  #[test]
+  fn concurrent_middlewares() {
+    let engine = Engine::default().with_middleware(Slow("slow".to_string()));
+    let started = Instant::now();
+    let threads = (0..4)
+      .map(|_| {
+        let engine = engine.clone();
+        std::thread::spawn(move || engine.handle(Request::new(Method::Get, "/")))
+      })
+      .collect::<Vec<_>>();
+    for thread in threads {
+      thread.join().unwrap();
+    }
+    assert!(started.elapsed() < Duration::from_millis(350));
+  }
+
+  #[test]
+  fn status_route() {
+    let engine = Engine::new(&Config::default()).unwrap();
+    let started = Instant::now();
+    let res = engine.handle(
+      Request::new(Method::Post, "/__mocker/status/418?delay=20ms")
+        .with_header("Content-Type", "text/plain")
+        .with_body("teapot"),
+    );
+    assert!(started.elapsed() >= Duration::from_millis(20));
+    assert_eq!(res.status(), 418);
+    assert_eq!(&res.body()[..], b"teapot");
+    assert_eq!(res.header("Content-Type").unwrap(), "text/plain");
+    let status = |target: &str| engine.handle(Request::new(Method::Get, target)).status();
+    assert_eq!(status("/__mocker/status/503"), 503);
+    assert_eq!(status("/__mocker/status/42"), 400);
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn echo() {
+    let engine = Engine::new(&Config {
+      routes: vec![Route::new(
+        vec![Method::Post],
+        "/debug",
+        RouteKind::Echo {
+          status: 200,
+          headers: Default::default(),
+        },
+      )],
+      ..Default::default()
+    })
+    .unwrap();
+    let res = engine.handle(
+      Request::new(Method::Post, "/debug?page=2")
+        .with_header("Content-Type", "application/json")
+        .with_header("X-Trace", "abc")
+        .with_body(r#"{"name": "ada"}"#),
+    );
+    let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body["method"], "POST");
+    assert_eq!(body["path"], "/debug");
+    assert_eq!(body["query"]["page"], "2");
+    assert_eq!(body["headers"]["x-trace"], "abc");
+    assert_eq!(body["body"]["name"], "ada");
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn tags() {
+    let route = |endpoint: &str, tag: &str| {
+      Route::new(
+        vec![Method::Get],
+        endpoint,
+        RouteKind::Echo {
+          status: 200,
+          headers: Default::default(),
+        },
+      )
+      .with_options(RouteOptions {
+        tags: vec![tag.to_string()],
+        ..Default::default()
+      })
+    };
+    let engine = Engine::new(&Config {
+      tags: Some(vec!["payments".to_string()]),
+      routes: vec![route("/pay", "payments"), route("/v2", "v2")],
+      ..Default::default()
+    })
+    .unwrap();
+    let status =
+      |method: Method, target: &str| engine.handle(Request::new(method, target)).status();
+    assert_eq!(status(Method::Get, "/pay"), 200);
+    assert_eq!(status(Method::Get, "/v2"), 404);
+    assert_eq!(status(Method::Put, "/__mocker/tags/v2"), 200);
+    assert_eq!(status(Method::Get, "/v2"), 200);
+    let res = engine.handle(Request::new(Method::Delete, "/__mocker/tags/payments"));
+    let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body["active"], serde_json::json!(["v2"]));
+    assert_eq!(body["tags"], serde_json::json!(["payments", "v2"]));
+    assert_eq!(status(Method::Get, "/pay"), 404);
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn switches() {
+    let route = |methods: Vec<Method>, enabled: Option<bool>| {
+      Route::new(
+        methods,
+        "/payments",
+        RouteKind::Echo {
+          status: 200,
+          headers: Default::default(),
+        },
+      )
+      .with_options(RouteOptions {
+        enabled,
+        ..Default::default()
+      })
+    };
+    let engine = Engine::new(&Config {
+      routes: vec![
+        route(vec![Method::Get], None),
+        route(vec![Method::Post], Some(false)),
+      ],
+      ..Default::default()
+    })
+    .unwrap();
+    let status =
+      |method: Method, target: &str| engine.handle(Request::new(method, target)).status();
+    assert_eq!(status(Method::Get, "/payments"), 200);
+    assert_eq!(status(Method::Post, "/payments"), 405);
+    assert!(engine
+      .router()
+      .switched_off(Method::Post, "/payments")
+      .unwrap());
+    let res = engine.handle(Request::new(Method::Delete, "/__mocker/routes/0/enabled"));
+    let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body["route"], "GET /payments");
+    assert_eq!(body["enabled"], false);
+    assert_eq!(status(Method::Get, "/payments"), 404);
+    assert_eq!(status(Method::Put, "/__mocker/routes/1/enabled"), 200);
+    assert_eq!(status(Method::Post, "/payments"), 200);
+    assert_eq!(status(Method::Put, "/__mocker/routes/0/enabled"), 200);
+    assert_eq!(status(Method::Get, "/payments"), 200);
+    assert_eq!(status(Method::Get, "/__mocker/routes/2/enabled"), 404);
+  }
+
+  #[test]
+  fn scenario_variants_survive_replace() {
+    let variant = |status: u16, state: &str, next: Option<&str>| {
+      Route::new(
+        vec![Method::Get],
+        "/job",
+        RouteKind::Echo {
+          status,
+          headers: Default::default(),
+        },
+      )
+      .with_options(RouteOptions {
+        scenario: Some(RouteScenario {
+          name: "job".to_string(),
+          state: Some(state.to_string()),
+          next: next.map(str::to_string),
+        }),
+        ..Default::default()
+      })
+    };
+    let engine = Engine::new(&Config {
+      routes: vec![
+        variant(202, SCENARIO_STARTED, Some("done")),
+        variant(200, "done", None),
+      ],
+      ..Default::default()
+    })
+    .unwrap();
+    let router = engine.router();
+    router.replace(router.routes().unwrap()).unwrap();
+    assert_eq!(router.routes().unwrap().len(), 2);
+    assert_eq!(router.keys().unwrap(), vec!["GET /job", "GET /job #2"]);
+    let status = || engine.handle(Request::new(Method::Get, "/job")).status();
+    assert_eq!(status(), 202);
+    assert_eq!(status(), 200);
+    assert_eq!(router.invocations().count("GET /job").unwrap(), 1);
+    assert_eq!(router.invocations().count("GET /job #2").unwrap(), 1);
+  }
+
+  #[test]
+  fn context() {
+    let engine = Engine::new(&Config {
+      routes: vec![Route::new(
+        vec![Method::Get],
+        "/me",
+        RouteKind::Fixture {
+          status: 200,
+          headers: Default::default(),
+          body: Some(Value::from(
+            "{{context.id}} {{context.principal}} {{context.route}}",
+          )),
+          file: None,
+          template: true,
+        },
+      )
+      .with_options(RouteOptions {
+        auth: Some(AuthPreset::Basic {
+          user: "ada".to_string(),
+          password: "secret".to_string(),
+        }),
+        ..Default::default()
+      })],
+      ..Default::default()
+    })
+    .unwrap();
+    let res = engine.handle(
+      Request::new(Method::Get, "/me")
+        .with_header("X-Request-Id", "r-1")
+        .with_header("Authorization", "Basic YWRhOnNlY3JldA=="),
+    );
+    assert_eq!(&res.body()[..], b"r-1 ada GET /me");
+  }
+
+  #[test]
+  fn route_context() {
+    let engine = Engine::new(&Config {
+      routes: vec![Route::new(
+        vec![Method::Get],
+        "/users/{id}",
+        RouteKind::Fixture {
+          status: 200,
+          headers: Default::default(),
+          body: Some(Value::from(
+            "{{route.pattern}} {{route.kind}} {{route.tags.0}} {{route.params.id}} {{route.query.page}}",
+          )),
+          file: None,
+          template: true,
+        },
+      )
+      .with_options(RouteOptions {
+        tags: vec!["v2".to_string()],
+        ..Default::default()
+      })],
+      ..Default::default()
+    })
+    .unwrap();
+    let res = engine.handle(Request::new(Method::Get, "/users/42?page=2"));
+    assert_eq!(&res.body()[..], b"/users/{id} fixture v2 42 2");
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn path_params() {
+    let path = std::env::temp_dir().join(format!("mocker-params-{}.json", std::process::id()));
+    std::fs::write(&path, r#"[{"id": "7", "name": "ada"}]"#).unwrap();
+    let engine = Engine::new(&Config {
+      routes: vec![Route::new(
+        vec![Method::Get, Method::Delete],
+        "/users/:id",
+        RouteKind::Store {
+          path: path.clone(),
+          identifier: "id".to_string(),
+          parent: None,
+        },
+      )],
+      ..Default::default()
+    })
+    .unwrap();
+    let res = engine.handle(Request::new(Method::Get, "/users/7"));
+    assert_eq!(res.status(), 200);
+    let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body["name"], "ada");
+    assert_eq!(
+      engine
+        .handle(Request::new(Method::Get, "/users/8"))
+        .status(),
+      404
+    );
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn lazy_hypermedia() {
+    let path = std::env::temp_dir().join(format!("mocker-lazy-{}.ndjson", std::process::id()));
+    std::fs::write(
+      &path,
+      concat!(
+        "{\"id\": 1, \"name\": \"bob\"}\n",
+        "{\"id\": 2, \"name\": \"ada\", \"bio\": \"Ada Lovelace\"}\n",
+        "{\"id\": 3, \"name\": \"cid\"}\n",
+      ),
+    )
+    .unwrap();
+    let mut route = Route::new(
+      vec![Method::Get],
+      "/users",
+      RouteKind::Store {
+        path: path.clone(),
+        identifier: "id".to_string(),
+        parent: None,
+      },
+    );
+    route.options_mut().lazy = true;
+    route.options_mut().hypermedia = Some(crate::Hypermedia::new(crate::HypermediaFormat::JsonApi));
+    let engine = Engine::new(&Config {
+      routes: vec![route],
+      ..Default::default()
+    })
+    .unwrap();
+    let names = |target: &str| {
+      let res = engine.handle(Request::new(Method::Get, target));
+      let body: Vec<serde_json::Value> = serde_json::from_slice(res.body()).unwrap();
+      body
+        .iter()
+        .map(|u| u["attributes"]["name"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+    };
+    assert_eq!(names("/users?_sort=name"), "ada,bob,cid");
+    assert_eq!(names("/users?_sort=name&_order=desc"), "cid,bob,ada");
+    // the envelope is not searched, only the entities
+    assert_eq!(names("/users?q=users"), "");
+    assert_eq!(names("/users?q=B&_sort=name"), "bob");
+    assert_eq!(names("/users?q=ada%20l"), "ada");
+    assert_eq!(names("/users?q=ada+l"), "ada");
+    std::fs::remove_file(&path).unwrap();
+    let _ = std::fs::remove_file(crate::LazyStore::index_path_for(&path));
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn idempotency() {
+    let path = std::env::temp_dir().join(format!("mocker-idempotency-{}.json", std::process::id()));
+    std::fs::write(&path, "[]").unwrap();
+    let mut route = Route::new(
+      vec![Method::Get, Method::Post],
+      "/users",
+      RouteKind::Store {
+        path: path.clone(),
+        identifier: "id".to_string(),
+        parent: None,
+      },
+    );
+    route.options_mut().session_header = Some("X-Session".to_string());
+    route.options_mut().idempotency_retention = Some("100ms".to_string());
+    let engine = Engine::new(&Config {
+      routes: vec![route],
+      ..Default::default()
+    })
+    .unwrap();
+    let post = |key: &str, session: &str, body: &str| {
+      let res = engine.handle(
+        Request::new(Method::Post, "/users")
+          .with_header("Content-Type", "application/json")
+          .with_header("Idempotency-Key", key)
+          .with_header("X-Session", session)
+          .with_body(body),
+      );
+      (
+        res.status(),
+        String::from_utf8_lossy(res.body()).to_string(),
+      )
+    };
+    let count = |session: &str| {
+      let res =
+        engine.handle(Request::new(Method::Get, "/users").with_header("X-Session", session));
+      serde_json::from_slice::<Vec<serde_json::Value>>(res.body())
+        .unwrap()
+        .len()
+    };
+    let created = post("k1", "a", r#"{"id": 1}"#);
+    assert_eq!(created.0, 201);
+    assert_eq!(post("k1", "a", r#"{"id": 1}"#), created);
+    assert_eq!(count("a"), 1);
+    assert_eq!(post("k1", "a", r#"{"id": 2}"#).0, 422);
+    // keys are scoped to their session
+    assert_eq!(post("k1", "b", r#"{"id": 2}"#).0, 201);
+    assert_eq!(count("b"), 1);
+    // and forgotten once retained long enough
+    std::thread::sleep(std::time::Duration::from_millis(150));
+    assert_eq!(post("k1", "a", r#"{"id": 2}"#).0, 201);
+    assert_eq!(count("a"), 2);
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn nested() {
+    let path = std::env::temp_dir().join(format!("mocker-nested-{}.json", std::process::id()));
+    std::fs::write(
+      &path,
+      r#"[{"id": 1, "postId": 1}, {"id": 2, "postId": 2}, {"id": 3, "postId": 1}]"#,
+    )
+    .unwrap();
+    let comments = |endpoint: &str| {
+      Route::new(
+        vec![Method::Get, Method::Post, Method::Delete],
+        endpoint,
+        RouteKind::Store {
+          path: path.clone(),
+          identifier: "id".to_string(),
+          parent: Some(crate::ParentRelation {
+            field: "postId".to_string(),
+            param: None,
+          }),
+        },
+      )
+    };
+    let engine = Engine::new(&Config {
+      routes: vec![
+        comments("/posts/:postId/comments"),
+        comments("/posts/:postId/comments/:id"),
+      ],
+      ..Default::default()
+    })
+    .unwrap();
+    let ids = |target: &str| {
+      let res = engine.handle(Request::new(Method::Get, target));
+      let body: Vec<serde_json::Value> = serde_json::from_slice(res.body()).unwrap();
+      body
+        .iter()
+        .map(|c| c["id"].as_u64().unwrap())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(ids("/posts/1/comments"), vec![1, 3]);
+    assert_eq!(ids("/posts/2/comments"), vec![2]);
+    let status = |method, target: &str| engine.handle(Request::new(method, target)).status();
+    assert_eq!(status(Method::Get, "/posts/1/comments/3"), 200);
+    assert_eq!(status(Method::Get, "/posts/2/comments/3"), 404);
+    assert_eq!(status(Method::Delete, "/posts/2/comments/1"), 404);
+    let created = engine.handle(
+      Request::new(Method::Post, "/posts/2/comments")
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"id": 4, "postId": 1}"#),
+    );
+    assert_eq!(created.status(), 201);
+    assert_eq!(ids("/posts/2/comments"), vec![2, 4]);
+    let stored: Vec<serde_json::Value> =
+      serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    let created = stored.iter().find(|c| c["id"] == 4).unwrap();
+    assert_eq!(created["postId"], serde_json::json!(2));
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn variables() {
+    let mut config = Config {
+      routes: vec![Route::new(
+        vec![Method::Get],
+        "/api/{{vars.api.version}}/hello",
+        RouteKind::Fixture {
+          status: 200,
+          headers: [("X-Api".to_string(), "{{ vars.api.version }}".to_string())].into(),
+          body: Some(Value::from(
+            "{{vars.greeting}} {{vars.api.version}} {{vars.api.name}}",
+          )),
+          file: None,
+          template: true,
+        },
+      )],
+      personas: Some(crate::persona::Personas {
+        header: "X-Persona".to_string(),
+        cookie: None,
+        tokens: Default::default(),
+        profiles: [(
+          "pirate".to_string(),
+          crate::persona::Persona {
+            variables: [
+              ("greeting".to_string(), Value::from("ahoy")),
+              (
+                "api".to_string(),
+                Value::from(HashMap::from([("name".to_string(), Value::from("jolly"))])),
+              ),
+            ]
+            .into(),
+            ..Default::default()
+          },
+        )]
+        .into(),
+      }),
+      ..Default::default()
+    };
+    config.set_variable("greeting", Value::from("hello"));
+    config.set_variable("api.version", Value::from("v1"));
+    config.set_variable("api.version", Value::from("v2"));
+    config.set_variable("api.name", Value::from("core"));
+    let engine = Engine::from_config(&config).unwrap();
+    let res = engine.handle(Request::new(Method::Get, "/api/v2/hello"));
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.header("X-Api").unwrap(), "v2");
+    assert_eq!(&res.body()[..], b"hello v2 core");
+    let res =
+      engine.handle(Request::new(Method::Get, "/api/v2/hello").with_header("X-Persona", "pirate"));
+    // nested persona variables are merged into the workspace ones
+    assert_eq!(&res.body()[..], b"ahoy v2 jolly");
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn clocks() {
+    let config = Config {
+      routes: vec![Route::new(
+        vec![Method::Get],
+        "/now",
+        RouteKind::Fixture {
+          status: 200,
+          headers: Default::default(),
+          body: Some(Value::from("{{now}}")),
+          file: None,
+          template: true,
+        },
+      )],
+      ..Default::default()
+    };
+    let shifted = Engine::new(&config).unwrap();
+    let other = Engine::new(&config).unwrap();
+    let res = shifted.handle(
+      Request::new(Method::Post, "/__mocker/clock")
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"offset": "+1d"}"#),
+    );
+    assert_eq!(res.status(), 200);
+    let now = |engine: &Engine| {
+      let res = engine.handle(Request::new(Method::Get, "/now"));
+      std::str::from_utf8(res.body())
+        .unwrap()
+        .parse::<u128>()
+        .unwrap()
+    };
+    let day = 86_400_000;
+    assert!(now(&shifted) >= crate::now_millis(0) + day - 60_000);
+    assert!(now(&other) < crate::now_millis(0) + 60_000);
+    let res = other.handle(Request::new(Method::Get, "/__mocker/clock"));
+    let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body["offset"], 0);
+  }
+
+  #[test]
+  fn personas() {
+    let dir = std::env::temp_dir();
+    let path = dir.join(format!("mocker-persona-{}.json", std::process::id()));
+    let empty = dir.join(format!("mocker-persona-empty-{}.json", std::process::id()));
+    std::fs::write(&path, r#"[{"id": 1}]"#).unwrap();
+    std::fs::write(&empty, "[]").unwrap();
+    let personas = crate::persona::Personas {
+      header: "X-Persona".to_string(),
+      cookie: None,
+      tokens: Default::default(),
+      profiles: [(
+        "new".to_string(),
+        crate::persona::Persona {
+          stores: [("/carts".to_string(), empty.clone())].into(),
+          ..Default::default()
+        },
+      )]
+      .into(),
+    };
+    let engine = Engine::from_config(&Config {
+      routes: vec![Route::new(
+        vec![Method::Get, Method::Post],
+        "/carts",
+        RouteKind::Store {
+          path: path.clone(),
+          identifier: "id".to_string(),
+          parent: None,
+        },
+      )],
+      personas: Some(personas),
+      ..Default::default()
+    })
+    .unwrap();
+    let get = |id: u32, persona: Option<&str>| {
+      let req = Request::new(Method::Get, format!("/carts?id={}", id));
+      let req = match persona {
+        Some(persona) => req.with_header("X-Persona", persona),
+        None => req,
+      };
+      engine.handle(req).status()
+    };
+    assert_eq!(get(1, None), 200);
+    assert_eq!(get(1, Some("new")), 404);
+    let created = engine.handle(
+      Request::new(Method::Post, "/carts")
+        .with_header("X-Persona", "new")
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"id": 2}"#),
+    );
+    assert_eq!(created.status(), 201);
+    assert_eq!(get(2, Some("new")), 200);
+    assert_eq!(get(2, None), 404);
+    assert_eq!(std::fs::read_to_string(&empty).unwrap(), "[]");
+    assert_eq!(get(1, Some("ghost")), 400);
+    std::fs::remove_file(&path).unwrap();
+    std::fs::remove_file(&empty).unwrap();
+  }
+
+  #[test]
+  fn sessions() {
+    let path = std::env::temp_dir().join(format!("mocker-sessions-{}.json", std::process::id()));
+    let stored = r#"[{"id": 1, "name": "ada"}]"#;
+    std::fs::write(&path, stored).unwrap();
+    let mut route = Route::new(
+      vec![Method::Get, Method::Post, Method::Delete],
+      "/users",
+      RouteKind::Store {
+        path: path.clone(),
+        identifier: "id".to_string(),
+        parent: None,
+      },
+    );
+    route.options_mut().session_header = Some("X-Session".to_string());
+    let engine = Engine::new(&Config {
+      routes: vec![route],
+      ..Default::default()
+    })
+    .unwrap();
+    let ids = |session: Option<&str>| {
+      let mut req = Request::new(Method::Get, "/users");
+      if let Some(session) = session {
+        req = req.with_header("X-Session", session);
+      }
+      let res = engine.handle(req);
+      serde_json::from_slice::<Vec<serde_json::Value>>(res.body())
+        .unwrap()
+        .iter()
+        .map(|u| u["id"].as_u64().unwrap())
+        .collect::<Vec<_>>()
+    };
+    let created = engine.handle(
+      Request::new(Method::Post, "/users")
+        .with_header("X-Session", "s1")
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"id": 2, "name": "grace"}"#),
+    );
+    assert_eq!(created.status(), 201);
+    let deleted =
+      engine.handle(Request::new(Method::Delete, "/users?id=1").with_header("X-Session", "s1"));
+    assert!(deleted.status() < 300);
+    assert_eq!(ids(Some("s1")), vec![2]);
+    // neither another session nor the backing file see the writes of s1
+    assert_eq!(ids(Some("s2")), vec![1]);
+    assert_eq!(ids(None), vec![1]);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), stored);
+    let ended = engine.handle(Request::new(Method::Delete, "/__mocker/sessions/s1"));
+    assert_eq!(ended.status(), 204);
+    assert_eq!(ids(Some("s1")), vec![1]);
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn persona_sessions() {
+    let dir = std::env::temp_dir();
+    let path = dir.join(format!(
+      "mocker-persona-sessions-{}.json",
+      std::process::id()
+    ));
+    std::fs::write(&path, "[]").unwrap();
+    let personas = crate::persona::Personas {
+      header: "X-Persona".to_string(),
+      cookie: None,
+      tokens: Default::default(),
+      profiles: [("new".to_string(), crate::persona::Persona::default())].into(),
+    };
+    let mut route = Route::new(
+      vec![Method::Get, Method::Post],
+      "/carts",
+      RouteKind::Store {
+        path: path.clone(),
+        identifier: "id".to_string(),
+        parent: None,
+      },
+    );
+    route.options_mut().session_header = Some("X-Session".to_string());
+    let engine = Engine::from_config(&Config {
+      routes: vec![route],
+      personas: Some(personas),
+      ..Default::default()
+    })
+    .unwrap();
+    let post = |session: &str, body: &str| {
+      engine
+        .handle(
+          Request::new(Method::Post, "/carts")
+            .with_header("X-Persona", "new")
+            .with_header("X-Session", session)
+            .with_header("Idempotency-Key", "k1")
+            .with_header("Content-Type", "application/json")
+            .with_body(body),
+        )
+        .status()
+    };
+    let count = |session: &str| {
+      let res = engine.handle(
+        Request::new(Method::Get, "/carts")
+          .with_header("X-Persona", "new")
+          .with_header("X-Session", session),
+      );
+      serde_json::from_slice::<Vec<serde_json::Value>>(res.body())
+        .unwrap()
+        .len()
+    };
+    assert_eq!(post("s1", r#"{"id": 1}"#), 201);
+    assert_eq!(post("s1", r#"{"id": 2}"#), 422);
+    assert_eq!(post("s2", r#"{"id": 2}"#), 201);
+    assert_eq!((count("s1"), count("s2")), (1, 1));
+    let ended = engine.handle(Request::new(Method::Delete, "/__mocker/sessions/s1"));
+    assert_eq!(ended.status(), 204);
+    // the persona's copy and idempotency keys went with the session
+    assert_eq!((count("s1"), count("s2")), (0, 1));
+    assert_eq!(post("s1", r#"{"id": 2}"#), 201);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]");
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn ids() {
+    let path = std::env::temp_dir().join(format!("mocker-ids-{}.json", std::process::id()));
+    std::fs::write(&path, r#"[{"id": 41}]"#).unwrap();
+    let mut route = Route::new(
+      vec![Method::Get, Method::Post],
+      "/orders",
+      RouteKind::Store {
+        path: path.clone(),
+        identifier: "id".to_string(),
+        parent: None,
+      },
+    );
+    route.options_mut().ids = Some("sequence(1, 1)".parse().unwrap());
+    let engine = Engine::new(&Config {
+      routes: vec![route],
+      ..Default::default()
+    })
+    .unwrap();
+    let created = engine.handle(
+      Request::new(Method::Post, "/orders")
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"item": "book"}"#),
+    );
+    assert_eq!(created.status(), 201);
+    let res = engine.handle(Request::new(Method::Get, "/orders?id=42"));
+    assert_eq!(res.status(), 200);
+    let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(body["item"], "book");
+    std::fs::remove_file(&path).unwrap();
+  }
+}
@@ -22,17 +22,14 @@ pub enum ErrorKind {
 pub struct Error {
   kind: ErrorKind,
   message: Option<String>,
-  cause: Option<Arc<dyn std::error::Error>>,
+  cause: Option<Arc<dyn std::error::Error + Send + Sync>>,
 }
 
-unsafe impl Send for Error {}
-unsafe impl Sync for Error {}
-
 impl Error {
   pub fn new(
     kind: ErrorKind,
     msg: Option<String>,
-    cause: Option<Arc<dyn std::error::Error>>,
+    cause: Option<Arc<dyn std::error::Error + Send + Sync>>,
   ) -> Self {
     Self {
       kind,
@@ -49,7 +46,7 @@ impl Error {
     self.message.as_ref()
   }
 
-  pub fn cause(&self) -> Option<&Arc<dyn std::error::Error>> {
+  pub fn cause(&self) -> Option<&Arc<dyn std::error::Error + Send + Sync>> {
     self.cause.as_ref()
   }
 
@@ -118,6 +115,13 @@ impl From<serde_yml::Error> for Error {
   }
 }
 
+#[cfg(feature = "reqwest")]
+impl From<reqwest::Error> for Error {
+  fn from(value: reqwest::Error) -> Self {
+    Error::new(ErrorKind::IO, Some(value.to_string()), None)
+  }
+}
+
 impl From<Box<dyn std::error::Error>> for Error {
   fn from(value: Box<dyn std::error::Error>) -> Self {
     Error::new(ErrorKind::Unknown, Some(value.to_string()), None)
@@ -0,0 +1,208 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  content_type_for, read_file, render, Response, Status, TemplateContext, Value, Variables,
+  VirtualClock,
+};
+
+/// Body served in place of one mocker generates for an error status.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorPage {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub body: Option<Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub file: Option<PathBuf>,
+  /// Whether the body is rendered with `status`, `reason`, `message` and
+  /// `request` in scope
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub template: bool,
+  /// Overrides the type guessed from the file extension or body
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub content_type: Option<String>,
+}
+
+impl ErrorPage {
+  pub fn with_body<B: Into<Value>>(mut self, body: B) -> Self {
+    self.body = Some(body.into());
+    self
+  }
+
+  pub fn with_file<P: Into<PathBuf>>(mut self, file: P) -> Self {
+    self.file = Some(file.into());
+    self
+  }
+
+  pub fn with_template(mut self, template: bool) -> Self {
+    self.template = template;
+    self
+  }
+
+  pub fn with_content_type<T: AsRef<str>>(mut self, content_type: T) -> Self {
+    self.content_type = Some(content_type.as_ref().to_string());
+    self
+  }
+
+  /// `res` with this page as its body, `message` describing the error
+  pub fn render(
+    &self,
+    req: &crate::Request,
+    res: Response,
+    message: &str,
+    variables: &Variables,
+    clock: &VirtualClock,
+  ) -> crate::Result<Response> {
+    let (text, content_type) = match (&self.file, &self.body) {
+      (Some(file), _) => (
+        std::str::from_utf8(&read_file(file)?)?.to_string(),
+        Some(content_type_for(file).to_string()),
+      ),
+      (None, Some(Value::String(body))) => (body.clone(), None),
+      (None, Some(body)) => {
+        let api = Response::api(Status::OK, body)?;
+        (
+          std::str::from_utf8(api.body())?.to_string(),
+          api.header("Content-Type").map(str::to_string),
+        )
+      }
+      (None, None) => return Ok(res),
+    };
+    let text = match self.template {
+      true => {
+        let reason = Status::try_from(res.status()).map_or("", |s| s.text());
+        let ctx = TemplateContext::new(variables)
+          .with_clock(clock)
+          .with_request(req)
+          .with_data("status", u64::from(res.status()))
+          .with_data("reason", reason)
+          .with_data("message", message);
+        render(text, &ctx)?
+      }
+      false => text,
+    };
+    let mut res = res.with_body(text);
+    if let Some(content_type) = self.content_type.clone().or(content_type) {
+      res.set_header("Content-Type", content_type);
+    }
+    Ok(res)
+  }
+}
+
+/// Pages replacing the error responses mocker generates itself, for
+/// unmatched requests and failing routes, so they match the envelope of the
+/// mocked backend, by status or class, e.g. `{"404": ..., "5xx": ...}`.
+/// Error responses routes are configured to serve are left as they are.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ErrorPages(pub BTreeMap<String, ErrorPage>);
+
+impl ErrorPages {
+  pub fn with_page<S: AsRef<str>>(mut self, status: S, page: ErrorPage) -> Self {
+    self.0.insert(status.as_ref().to_string(), page);
+    self
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// Page for `status`, an exact match winning over its class
+  pub fn find(&self, status: u16) -> Option<&ErrorPage> {
+    self
+      .0
+      .get(&status.to_string())
+      .or_else(|| self.0.get(&format!("{}xx", status / 100)))
+  }
+
+  /// `res` with the page configured for its status, if an error one
+  pub fn apply(
+    &self,
+    req: &crate::Request,
+    res: Response,
+    variables: &Variables,
+    clock: &VirtualClock,
+  ) -> crate::Result<Response> {
+    match self.find(res.status()) {
+      Some(page) if res.status() >= 400 => {
+        let message = match res.body().is_empty() {
+          true => Status::try_from(res.status()).map_or(String::new(), |s| s.text().to_string()),
+          false => String::from_utf8_lossy(res.body()).to_string(),
+        };
+        page.render(req, res, &message, variables, clock)
+      }
+      _ => Ok(res),
+    }
+  }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+  use crate::{Config, Engine, ErrorPage, ErrorPages, Method, Request, Route, RouteKind, Value};
+
+  #[test]
+  fn pages() {
+    let page = ErrorPage::default()
+      .with_body(r#"{"error": {"code": {{status}}, "message": "{{message}}"}}"#)
+      .with_template(true)
+      .with_content_type("application/json");
+    let engine = Engine::new(&Config {
+      routes: vec![
+        Route::new(
+          vec![Method::Get],
+          "/users",
+          RouteKind::Fixture {
+            status: 404,
+            headers: Default::default(),
+            body: Some(Value::from("gone")),
+            file: None,
+            template: false,
+          },
+        ),
+        Route::new(
+          vec![Method::Get],
+          "/broken",
+          RouteKind::Fixture {
+            status: 200,
+            headers: Default::default(),
+            body: Some(Value::from("{{nope")),
+            file: None,
+            template: true,
+          },
+        ),
+      ],
+      error_pages: ErrorPages::default()
+        .with_page("404", page.clone())
+        .with_page("5xx", page)
+        .with_page(
+          "405",
+          ErrorPage::default().with_body("<h1>Not allowed</h1>"),
+        ),
+      ..Default::default()
+    })
+    .unwrap();
+    let json = |res: &crate::Response| -> serde_json::Value {
+      assert_eq!(res.header("Content-Type").unwrap(), "application/json");
+      serde_json::from_slice(res.body()).unwrap()
+    };
+
+    let res = engine.handle(Request::new(Method::Get, "/nope"));
+    assert_eq!(res.status(), 404);
+    assert_eq!(
+      json(&res),
+      serde_json::json!({"error": {"code": 404, "message": "Not Found"}})
+    );
+
+    let res = engine.handle(Request::new(Method::Delete, "/users"));
+    assert_eq!(res.status(), 405);
+    assert_eq!(res.header("Allow").unwrap(), "GET");
+    assert_eq!(res.body().as_ref(), b"<h1>Not allowed</h1>");
+
+    let res = engine.handle(Request::new(Method::Get, "/broken"));
+    assert_eq!(res.status(), 500);
+    assert_eq!(json(&res)["error"]["code"], 500);
+
+    let res = engine.handle(Request::new(Method::Get, "/users"));
+    assert_eq!((res.status(), res.body().as_ref()), (404, b"gone".as_ref()));
+  }
+}
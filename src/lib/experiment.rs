@@ -0,0 +1,217 @@
+use std::{
+  collections::{hash_map::RandomState, BTreeMap},
+  hash::{BuildHasher, Hasher},
+  path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{fnv1a, now_millis, Request, Response, Status, Value};
+
+/// Request attribute users are bucketed by, so each keeps getting the same
+/// variant.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StickyKey {
+  Header(String),
+  Cookie(String),
+}
+
+impl StickyKey {
+  /// Value `req` has for this key, if any
+  pub fn value<'a>(&self, req: &'a Request) -> Option<&'a str> {
+    match self {
+      StickyKey::Header(name) => req.header(name),
+      StickyKey::Cookie(name) => req.cookie(name),
+    }
+  }
+}
+
+/// One of the responses an experiment serves, overriding the route's own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Variant {
+  /// Sent back in [`Experiment::VARIANT_HEADER`]
+  pub name: String,
+  /// Share of the traffic, relative to the other variants
+  #[serde(default = "Variant::default_weight")]
+  pub weight: u32,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub status: Option<u16>,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub headers: BTreeMap<String, String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub body: Option<Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub file: Option<PathBuf>,
+}
+
+impl Variant {
+  fn default_weight() -> u32 {
+    1
+  }
+
+  pub fn new<N: AsRef<str>>(name: N) -> Self {
+    Self {
+      name: name.as_ref().to_string(),
+      weight: Self::default_weight(),
+      status: None,
+      headers: BTreeMap::new(),
+      body: None,
+      file: None,
+    }
+  }
+
+  pub fn with_weight(mut self, weight: u32) -> Self {
+    self.weight = weight;
+    self
+  }
+
+  pub fn with_status(mut self, status: u16) -> Self {
+    self.status = Some(status);
+    self
+  }
+
+  pub fn with_header<K: AsRef<str>, V: AsRef<str>>(mut self, k: K, v: V) -> Self {
+    self
+      .headers
+      .insert(k.as_ref().to_string(), v.as_ref().to_string());
+    self
+  }
+
+  pub fn with_body<B: Into<Value>>(mut self, body: B) -> Self {
+    self.body = Some(body.into());
+    self
+  }
+
+  /// `res` with what this variant overrides
+  pub fn apply(&self, mut res: Response) -> crate::Result<Response> {
+    let status = self.status.unwrap_or(res.status());
+    match (&self.file, &self.body) {
+      (Some(file), _) => res = Response::file(file)?,
+      (None, Some(Value::String(body))) => res = res.with_body(body),
+      (None, Some(body)) => {
+        let api = Response::api(Status::OK, body)?;
+        if let Some(content_type) = api.header("Content-Type") {
+          res.set_header("Content-Type", content_type);
+        }
+        res = res.with_body_bytes(api.body().clone());
+      }
+      (None, None) => {}
+    }
+    let mut res = res.with_status_code(status);
+    for (k, v) in &self.headers {
+      res.set_header(k, v);
+    }
+    res.set_header(Experiment::VARIANT_HEADER, &self.name);
+    Ok(res)
+  }
+}
+
+/// A/B test run by a route, serving each request one of several variants
+/// picked by weight, at random or by hashing a sticky key.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Experiment {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub sticky: Option<StickyKey>,
+  pub variants: Vec<Variant>,
+}
+
+impl Experiment {
+  /// Response header naming the variant served
+  pub const VARIANT_HEADER: &'static str = "X-Mocker-Variant";
+
+  pub fn new(variants: Vec<Variant>) -> Self {
+    Self {
+      sticky: None,
+      variants,
+    }
+  }
+
+  pub fn with_sticky(mut self, key: StickyKey) -> Self {
+    self.sticky = Some(key);
+    self
+  }
+
+  /// Variant `req` falls into, always the same for a given sticky key value
+  pub fn pick(&self, req: &Request) -> Option<&Variant> {
+    let total = self
+      .variants
+      .iter()
+      .map(|v| u64::from(v.weight))
+      .sum::<u64>();
+    if total == 0 {
+      return None;
+    }
+    let roll = match self.sticky.as_ref().and_then(|key| key.value(req)) {
+      Some(value) => fnv1a(value.as_bytes()),
+      None => {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(now_millis(0));
+        hasher.finish()
+      }
+    };
+    let mut point = roll % total;
+    self.variants.iter().find(|v| {
+      let hit = point < u64::from(v.weight);
+      point = point.saturating_sub(u64::from(v.weight));
+      hit
+    })
+  }
+
+  /// `res` as overridden by the variant `req` falls into
+  pub fn apply(&self, req: &Request, res: Response) -> crate::Result<Response> {
+    match self.pick(req) {
+      Some(variant) => variant.apply(res),
+      None => Ok(res),
+    }
+  }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+  use std::collections::HashMap;
+
+  use crate::{Method, Request, Response, Value};
+
+  use super::{Experiment, StickyKey, Variant};
+
+  #[test]
+  fn variants() {
+    let experiment = Experiment::new(vec![
+      Variant::new("control").with_weight(3),
+      Variant::new("beta")
+        .with_body(Value::try_from_json(serde_json::json!({"beta": true})).unwrap())
+        .with_header("X-Flag", "on"),
+      Variant::new("never").with_weight(0),
+    ])
+    .with_sticky(StickyKey::Cookie("uid".to_string()));
+    let serve = |uid: usize| {
+      let req = Request::new(Method::Get, "/home")
+        .with_header("Cookie", format!("theme=dark; uid=user-{}", uid));
+      experiment
+        .apply(&req, Response::default().with_status_code(200))
+        .unwrap()
+    };
+    let mut served = HashMap::new();
+    for uid in 0..200 {
+      let res = serve(uid);
+      let name = res.header(Experiment::VARIANT_HEADER).unwrap().to_string();
+      assert_eq!(
+        serve(uid).header(Experiment::VARIANT_HEADER),
+        Some(name.as_str())
+      );
+      if name == "beta" {
+        let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(body, serde_json::json!({"beta": true}));
+        assert_eq!(res.header("X-Flag").unwrap(), "on");
+      }
+      *served.entry(name).or_insert(0) += 1;
+    }
+    assert!(served["control"] > served["beta"]);
+    assert!(!served.contains_key("never"));
+
+    let anonymous = Request::new(Method::Get, "/home");
+    assert!(experiment.pick(&anonymous).is_some());
+    assert!(Experiment::default().pick(&anonymous).is_none());
+  }
+}
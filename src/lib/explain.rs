@@ -0,0 +1,140 @@
+use serde::Serialize;
+
+use crate::{
+  endpoint_matches, host_matches, request_host, Admin, Matcher, Method, Request, Route, Router,
+};
+
+/// Outcome of one of the checks deciding whether a route serves a request
+#[derive(Debug, Clone, Serialize)]
+pub struct MatcherOutcome {
+  pub matcher: String,
+  pub passed: bool,
+  pub detail: String,
+}
+
+/// How a route fared against the explained request
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteExplanation {
+  pub index: usize,
+  pub route: String,
+  pub kind: &'static str,
+  pub matched: bool,
+  pub matchers: Vec<MatcherOutcome>,
+}
+
+/// Dry-run of the routing of a request: the route which would serve it, why
+/// the others would not, and the middlewares it would go through.
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+  pub method: Method,
+  /// Request target once middlewares prepared it
+  pub target: String,
+  /// Whether the admin API, rather than a route, would answer
+  pub admin: bool,
+  pub middlewares: Vec<String>,
+  /// Index of the route which would serve the request
+  pub selected: Option<usize>,
+  pub routes: Vec<RouteExplanation>,
+}
+
+impl Explanation {
+  pub fn new(router: &Router, req: &Request, middlewares: Vec<String>) -> crate::Result<Self> {
+    let method = req.method().unwrap_or(Method::Get);
+    let path = req.path().unwrap_or("/");
+    let mut routes = vec![];
+    for (index, route) in router.routes()?.iter().enumerate() {
+      let matchers = Self::matchers(router, route, req, method, path)?;
+      routes.push(RouteExplanation {
+        index,
+        route: route.id(),
+        kind: route.kind_str(),
+        matched: matchers.iter().all(|m| m.passed),
+        matchers,
+      });
+    }
+    let admin = Admin::handles(req);
+    Ok(Self {
+      method,
+      target: req
+        .start_line()
+        .as_request()
+        .map(|s| s.target.clone())
+        .unwrap_or_default(),
+      admin,
+      middlewares,
+      selected: match admin {
+        true => None,
+        false => routes.iter().find(|r| r.matched).map(|r| r.index),
+      },
+      routes,
+    })
+  }
+
+  fn matchers(
+    router: &Router,
+    route: &Route,
+    req: &Request,
+    method: Method,
+    path: &str,
+  ) -> crate::Result<Vec<MatcherOutcome>> {
+    let outcome = |matcher: &str, passed: bool, detail: String| MatcherOutcome {
+      matcher: matcher.to_string(),
+      passed,
+      detail,
+    };
+    let mut ret = vec![
+      outcome(
+        "endpoint",
+        endpoint_matches(route.endpoint(), path),
+        format!("'{}' against '{}'", path, route.endpoint()),
+      ),
+      outcome(
+        "method",
+        route.methods().contains(&method),
+        format!(
+          "{} among {}",
+          method,
+          route
+            .methods()
+            .iter()
+            .map(|m| m.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+        ),
+      ),
+    ];
+    if let Some(scenario) = &route.options().scenario {
+      let state = router.scenarios().state(&scenario.name)?.state;
+      ret.push(outcome(
+        "scenario",
+        router.scenarios().accepts(scenario)?,
+        match &scenario.state {
+          Some(required) => format!(
+            "'{}' is in state '{}', '{}' required",
+            scenario.name, state, required
+          ),
+          None => format!("'{}' is in state '{}', any accepted", scenario.name, state),
+        },
+      ));
+    }
+    if let Some(host) = &route.options().host {
+      ret.push(outcome(
+        "host",
+        host_matches(host, req),
+        match request_host(req) {
+          Some(actual) => format!("'{}' against '{}'", actual, host),
+          None => format!("no Host header, '{}' required", host),
+        },
+      ));
+    }
+    if let Some(when) = &route.options().when {
+      let result = when.matches(req);
+      ret.push(outcome(
+        "when",
+        result.matched,
+        result.explanations.join("; "),
+      ));
+    }
+    Ok(ret)
+  }
+}
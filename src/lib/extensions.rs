@@ -0,0 +1,246 @@
+use std::{
+  any::{Any, TypeId},
+  collections::{BTreeMap, HashMap},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+  },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{persona::ActivePersona, Request, Route, Value};
+
+/// Values attached to a request while it is served, one per type, so
+/// middlewares can hand data to handlers and templates without smuggling it
+/// through headers.
+#[derive(Clone, Default)]
+pub struct Extensions(HashMap<TypeId, Arc<dyn Any + Send + Sync>>);
+
+impl Extensions {
+  /// Attach `value`, replacing any previous value of the same type
+  pub fn insert<T: Any + Send + Sync>(&mut self, value: T) {
+    self.0.insert(TypeId::of::<T>(), Arc::new(value));
+  }
+
+  pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+    self
+      .0
+      .get(&TypeId::of::<T>())
+      .and_then(|v| v.downcast_ref::<T>())
+  }
+
+  pub fn remove<T: Any + Send + Sync>(&mut self) -> bool {
+    self.0.remove(&TypeId::of::<T>()).is_some()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// The well-known entries, as exposed to templates: `id`, `tenant`,
+  /// `principal` and `route`
+  pub fn to_value(&self) -> Value {
+    let mut map = HashMap::new();
+    if let Some(RequestId(id)) = self.get() {
+      map.insert("id".to_string(), Value::from(id.as_str()));
+    }
+    if let Some(Tenant(tenant)) = self.get() {
+      map.insert("tenant".to_string(), Value::from(tenant.as_str()));
+    }
+    if let Some(Principal(principal)) = self.get() {
+      map.insert("principal".to_string(), Value::from(principal.as_str()));
+    }
+    if let Some(MatchedRoute(route)) = self.get() {
+      map.insert("route".to_string(), Value::from(route.id()));
+    }
+    Value::from(map)
+  }
+}
+
+impl std::fmt::Debug for Extensions {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Extensions")
+      .field("len", &self.0.len())
+      .finish()
+  }
+}
+
+/// Identifier of a request, from its `X-Request-Id` header or generated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+  pub const HEADER: &'static str = "X-Request-Id";
+
+  /// Identifier `req` carries, or a new one
+  pub fn of(req: &Request) -> Self {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    match req.header(Self::HEADER) {
+      Some(id) => Self(id.to_string()),
+      None => Self(format!("req-{}", NEXT.fetch_add(1, Ordering::Relaxed))),
+    }
+  }
+}
+
+/// Tenant the request was resolved to, see [`crate::tenancy::Tenancy`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tenant(pub String);
+
+/// Who the request authenticated as, once its route's `auth` check passed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal(pub String);
+
+/// Details of the TLS connection a request came over, when the forward
+/// proxy terminated it
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TlsInfo {
+  /// Server name the client asked for (SNI)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub sni: Option<String>,
+  /// Protocol version, e.g. `TLSv1_3`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub protocol: Option<String>,
+  /// Cipher suite, e.g. `TLS13_AES_256_GCM_SHA384`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub cipher: Option<String>,
+  /// Application protocol negotiated with ALPN, e.g. `http/1.1`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub alpn: Option<String>,
+  /// Subject of the certificate the client presented, e.g. `CN=svc, O=acme`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub client_subject: Option<String>,
+}
+
+impl TlsInfo {
+  /// As exposed to templates and scripts, unknown details being `null`
+  pub fn to_value(&self) -> Value {
+    Value::from(HashMap::from([
+      ("sni".to_string(), Value::from(self.sni.clone())),
+      ("protocol".to_string(), Value::from(self.protocol.clone())),
+      ("cipher".to_string(), Value::from(self.cipher.clone())),
+      ("alpn".to_string(), Value::from(self.alpn.clone())),
+      (
+        "client_subject".to_string(),
+        Value::from(self.client_subject.clone()),
+      ),
+    ]))
+  }
+}
+
+/// Workspace variables, as configured and overridden from the command line
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct WorkspaceVars(pub Arc<BTreeMap<String, Value>>);
+
+impl WorkspaceVars {
+  /// Variables `req` sees, its persona overriding the workspace ones
+  pub fn of(req: &Request) -> Value {
+    let mut vars = req
+      .extensions()
+      .get::<Self>()
+      .map(|vars| (*vars.0).clone())
+      .unwrap_or_default();
+    if let Some(active) = req.extensions().get::<ActivePersona>() {
+      for (name, value) in active.persona.variables.clone() {
+        match vars.get_mut(&name) {
+          Some(var) => Self::overlay(var, value),
+          None => {
+            vars.insert(name, value);
+          }
+        }
+      }
+    }
+    Value::from(vars)
+  }
+
+  /// Override `target` with `value`, nested maps being merged field by field
+  fn overlay(target: &mut Value, value: Value) {
+    match (target, value) {
+      (Value::Map(target), Value::Map(fields)) => {
+        for (key, value) in fields {
+          match target.get_mut(&key) {
+            Some(field) => Self::overlay(field, value),
+            None => {
+              target.insert(key, value);
+            }
+          }
+        }
+      }
+      (target, value) => *target = value,
+    }
+  }
+}
+
+/// Route serving the request
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchedRoute(pub Route);
+
+/// Path segments captured by the parameters of the route serving the request
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PathParams(pub HashMap<String, String>);
+
+impl MatchedRoute {
+  /// Structured view of the route serving `req`, as exposed to templates and
+  /// scripts: `id`, `pattern`, `methods`, `kind`, `tags`, the path `params`
+  /// it extracted, and the request `query` and `body`
+  pub fn to_value(&self, req: &Request) -> Value {
+    let route = &self.0;
+    let params = req
+      .path_params()
+      .into_iter()
+      .map(|(k, v)| (k, Value::from(v)))
+      .collect::<HashMap<_, _>>();
+    let list =
+      |items: Vec<String>| Value::from(items.into_iter().map(Value::from).collect::<Vec<_>>());
+    let mut map = match req.to_value() {
+      Value::Map(map) => map,
+      _ => HashMap::new(),
+    };
+    map.retain(|k, _| k == "query" || k == "body");
+    map.extend([
+      ("id".to_string(), Value::from(route.id())),
+      (
+        "pattern".to_string(),
+        Value::from(route.endpoint().as_str()),
+      ),
+      (
+        "methods".to_string(),
+        list(route.methods().iter().map(|m| m.to_string()).collect()),
+      ),
+      ("kind".to_string(), Value::from(route.kind_str())),
+      ("tags".to_string(), list(route.options().tags.clone())),
+      ("params".to_string(), Value::from(params)),
+    ]);
+    Value::from(map)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Request};
+
+  use super::{Extensions, RequestId, Tenant};
+
+  #[test]
+  fn typed() {
+    let mut ext = Extensions::default();
+    assert!(ext.is_empty());
+    ext.insert(Tenant("acme".to_string()));
+    ext.insert(Tenant("globex".to_string()));
+    ext.insert(42u32);
+    assert_eq!(ext.get::<Tenant>(), Some(&Tenant("globex".to_string())));
+    assert_eq!(ext.get::<u32>(), Some(&42));
+    assert_eq!(ext.get::<RequestId>(), None);
+    assert_eq!(
+      ext.to_value().get_path("tenant").cloned(),
+      Some("globex".into())
+    );
+    assert!(ext.remove::<u32>());
+    assert!(!ext.remove::<u32>());
+
+    let req = Request::new(Method::Get, "/").with_header(RequestId::HEADER, "abc");
+    assert_eq!(RequestId::of(&req), RequestId("abc".to_string()));
+    let generated = RequestId::of(&Request::new(Method::Get, "/"));
+    assert_ne!(generated, RequestId::of(&Request::new(Method::Get, "/")));
+  }
+}
@@ -0,0 +1,204 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "server")]
+use std::{
+  io::Write,
+  net::{Shutdown, TcpStream},
+  time::Duration,
+};
+
+#[cfg(feature = "server")]
+use log::warn;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "server")]
+use socket2::SockRef;
+
+use crate::now_millis;
+#[cfg(feature = "server")]
+use crate::parse_duration;
+
+static SEED: AtomicU64 = AtomicU64::new(0);
+
+/// Cheap xorshift generator, good enough to pick which responses misbehave
+pub(crate) fn random() -> u64 {
+  let mut state = SEED.load(Ordering::Relaxed);
+  if state == 0 {
+    state = now_millis(0) as u64 | 1;
+  }
+  state ^= state << 13;
+  state ^= state >> 7;
+  state ^= state << 17;
+  SEED.store(state, Ordering::Relaxed);
+  state
+}
+
+/// Whether an event of probability `p` (0 to 1) happens
+pub(crate) fn chance(p: f64) -> bool {
+  p > 0.0 && (random() as f64 / u64::MAX as f64) < p
+}
+
+/// Connection-level misbehaviour a route can simulate, instead of answering normally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fault {
+  /// Abort the connection with a TCP reset
+  Reset,
+  /// Close the connection without sending anything
+  EmptyResponse,
+  /// Send the status line and headers, then garbage instead of the body
+  Malformed,
+  /// Send the first half of the response, then close the writing side
+  HalfClose,
+}
+
+#[cfg(feature = "server")]
+impl Fault {
+  const GARBAGE_LEN: usize = 64;
+
+  fn garbage() -> Vec<u8> {
+    (0..Self::GARBAGE_LEN).map(|_| random() as u8).collect()
+  }
+
+  /// Misbehave on `stream`, given the response which should have been written
+  pub fn inject(&self, mut stream: &TcpStream, response: &[u8]) -> crate::Result<()> {
+    match self {
+      Fault::Reset => {
+        // a zero linger timeout makes closing the socket send RST instead of FIN
+        SockRef::from(stream).set_linger(Some(Duration::ZERO))?;
+        return Ok(());
+      }
+      Fault::EmptyResponse => {}
+      Fault::Malformed => {
+        let head = response
+          .windows(4)
+          .position(|w| w == b"\r\n\r\n")
+          .map(|i| &response[..i + 4])
+          .unwrap_or(response);
+        stream.write_all(head)?;
+        stream.write_all(&Self::garbage())?;
+      }
+      Fault::HalfClose => {
+        stream.write_all(&response[..response.len() / 2])?;
+        stream.flush()?;
+        stream.shutdown(Shutdown::Write)?;
+        return Ok(());
+      }
+    }
+    stream.flush()?;
+    stream.shutdown(Shutdown::Both)?;
+    Ok(())
+  }
+}
+
+/// Delivery misbehaviour, randomly affecting a share of a route's responses.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Disorder {
+  /// Share of responses (0 to 1) sent twice
+  #[serde(default)]
+  pub duplicate: f64,
+  /// Share of responses (0 to 1) held back before being sent
+  #[serde(default)]
+  pub late: f64,
+  /// How long late responses are held back, longer than the client's timeout
+  #[serde(default = "Disorder::default_delay")]
+  pub delay: String,
+}
+
+impl Default for Disorder {
+  fn default() -> Self {
+    Self {
+      duplicate: 0.0,
+      late: 0.0,
+      delay: Self::default_delay(),
+    }
+  }
+}
+
+impl Disorder {
+  fn default_delay() -> String {
+    "30s".to_string()
+  }
+
+  /// Send `response` on `stream`, possibly late and possibly twice
+  #[cfg(feature = "server")]
+  pub fn deliver(&self, mut stream: &TcpStream, response: &[u8]) -> crate::Result<()> {
+    if chance(self.late) {
+      let delay = parse_duration(&self.delay)?;
+      warn!("Holding response back for {:?}", delay);
+      std::thread::sleep(delay);
+    }
+    stream.write_all(response)?;
+    if chance(self.duplicate) {
+      warn!("Sending response twice");
+      stream.write_all(response)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Disorder, Fault};
+
+  #[cfg(feature = "server")]
+  #[test]
+  fn chances() {
+    use super::chance;
+
+    assert!(!(0..100).any(|_| chance(0.0)));
+    assert!((0..100).all(|_| chance(1.0)));
+  }
+
+  #[cfg(feature = "server")]
+  fn inject(fault: Fault, response: &[u8]) -> (std::net::TcpStream, std::net::TcpStream) {
+    use std::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    fault.inject(&server, response).unwrap();
+    (client, server)
+  }
+
+  #[cfg(feature = "server")]
+  #[test]
+  fn faults() {
+    use std::io::{ErrorKind, Read, Write};
+
+    let response = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+    let head = &response[..response.len() - 5];
+
+    let (mut client, server) = inject(Fault::Reset, response);
+    drop(server);
+    let err = client.read_to_end(&mut vec![]).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionReset);
+
+    let (mut client, _server) = inject(Fault::EmptyResponse, response);
+    let mut buf = vec![];
+    assert_eq!(client.read_to_end(&mut buf).unwrap(), 0);
+
+    let (mut client, _server) = inject(Fault::Malformed, response);
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).unwrap();
+    assert!(buf.starts_with(head));
+    assert_eq!(buf.len(), head.len() + Fault::GARBAGE_LEN);
+
+    let (mut client, mut server) = inject(Fault::HalfClose, response);
+    let mut buf = vec![];
+    client.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, &response[..response.len() / 2]);
+    // the reading side stays open
+    client.write_all(b"ping").unwrap();
+    let mut ping = [0; 4];
+    server.read_exact(&mut ping).unwrap();
+    assert_eq!(&ping, b"ping");
+  }
+
+  #[test]
+  fn options() {
+    let fault: Fault = serde_json::from_str(r#""half-close""#).unwrap();
+    assert_eq!(fault, Fault::HalfClose);
+    let disorder: Disorder = serde_json::from_str(r#"{"duplicate": 0.1}"#).unwrap();
+    assert_eq!(disorder.late, 0.0);
+    assert_eq!(disorder.delay, "30s");
+  }
+}
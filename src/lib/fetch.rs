@@ -0,0 +1,262 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{glob_match, Error, ErrorKind, MediaType, Method, Request, Response, Status, Value};
+#[cfg(any(feature = "server", feature = "reqwest"))]
+use crate::{parse_duration, UpstreamConfig};
+
+/// Which servers script handlers may call with `fetch(url, options)`, so
+/// mocks can aggregate other mocked routes without reaching out anywhere.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct FetchPolicy {
+  /// Host patterns allowed besides the mock itself, e.g. `localhost:*`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub allow: Vec<String>,
+  /// Maximum time waited for a response, e.g. `2s`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub timeout: Option<String>,
+  /// Url relative urls resolve against, the mock itself by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub origin: Option<String>,
+}
+
+impl FetchPolicy {
+  pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+  pub fn with_allow<H: AsRef<str>>(mut self, host: H) -> Self {
+    self.allow.push(host.as_ref().to_string());
+    self
+  }
+
+  pub fn with_origin<O: AsRef<str>>(mut self, origin: O) -> Self {
+    self.origin = Some(origin.as_ref().trim_end_matches('/').to_string());
+    self
+  }
+
+  /// Host (with port, if any) of `url`, and the rest of it
+  fn split(url: &str) -> (&str, &str) {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()))
+  }
+
+  /// Whether scripts may call `host`, the origin always being allowed
+  pub fn allows(&self, host: &str) -> bool {
+    let host = host.to_ascii_lowercase();
+    let bare = host.split(':').next().unwrap_or_default();
+    let origin = self.origin.as_deref().map(|o| Self::split(o).0);
+    origin.is_some_and(|o| o.eq_ignore_ascii_case(&host))
+      || self.allow.iter().any(|pattern| {
+        let pattern = pattern.to_ascii_lowercase();
+        glob_match(&pattern, &host) || glob_match(&pattern, bare)
+      })
+  }
+
+  /// Send the request described by `options` (`method`, `headers`, `body`)
+  /// to `url`, relative urls going to the origin
+  pub fn fetch(&self, url: &str, options: &serde_json::Value) -> crate::Result<FetchResponse> {
+    let url = match (url.starts_with('/'), &self.origin) {
+      (true, Some(origin)) => format!("{}{}", origin, url),
+      (true, None) => {
+        return Err(Error::new(
+          ErrorKind::Parse,
+          Some(format!(
+            "cannot fetch relative url '{}' without an origin",
+            url
+          )),
+          None,
+        ))
+      }
+      (false, _) => url.to_string(),
+    };
+    let (host, target) = Self::split(&url);
+    if !self.allows(host) {
+      return Err(Error::new(
+        ErrorKind::Api(Status::Forbidden),
+        Some(format!("fetching from '{}' is not allowed", host)),
+        None,
+      ));
+    }
+    let method = match options.get("method").and_then(|m| m.as_str()) {
+      Some(method) => method.to_ascii_uppercase().parse::<Method>()?,
+      None => Method::Get,
+    };
+    let target = match target.starts_with('/') {
+      true => target.to_string(),
+      false => format!("/{}", target),
+    };
+    let req = request_from_options(method, target, options);
+    Ok(FetchResponse::from(&self.send(&url, &req)?))
+  }
+
+  /// Send `req` to the server of `url`, within the policy timeout
+  #[cfg(any(feature = "server", feature = "reqwest"))]
+  fn send(&self, url: &str, req: &Request) -> crate::Result<Response> {
+    let timeout = match &self.timeout {
+      Some(timeout) => parse_duration(timeout)?,
+      None => Self::DEFAULT_TIMEOUT,
+    };
+    let upstream = UpstreamConfig {
+      timeout: Some(format!("{}ms", timeout.as_millis())),
+      ..Default::default()
+    };
+    upstream
+      .client(&url[..url.len() - Self::split(url).1.len()])?
+      .send(req)
+  }
+
+  #[cfg(not(any(feature = "server", feature = "reqwest")))]
+  fn send(&self, url: &str, _req: &Request) -> crate::Result<Response> {
+    Err(Error::new(
+      ErrorKind::Unknown,
+      Some(format!(
+        "cannot fetch '{}': an http client needs the `server` or `reqwest` feature",
+        url
+      )),
+      None,
+    ))
+  }
+}
+
+/// Request to `target` with the `headers` and `body` of `options`, JSON
+/// bodies other than strings being serialized
+pub(crate) fn request_from_options<T: AsRef<str>>(
+  method: Method,
+  target: T,
+  options: &serde_json::Value,
+) -> Request {
+  let mut req = Request::new(method, target);
+  if let Some(headers) = options.get("headers").and_then(|h| h.as_object()) {
+    for (key, value) in headers {
+      match value {
+        serde_json::Value::String(value) => req.set_header(key, value),
+        value => req.set_header(key, value.to_string()),
+      }
+    }
+  }
+  match options.get("body") {
+    None | Some(serde_json::Value::Null) => req,
+    Some(serde_json::Value::String(body)) => req.with_body(body),
+    Some(body) => {
+      if req.header("Content-Type").is_none() {
+        req.set_header("Content-Type", "application/json");
+      }
+      req.with_body(body.to_string())
+    }
+  }
+}
+
+/// Response to a script's `fetch`, its body parsed when JSON.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchResponse {
+  pub status: u16,
+  /// Lowercased header names
+  pub headers: BTreeMap<String, String>,
+  pub body: Value,
+}
+
+impl FetchResponse {
+  /// `{status, headers, body}`
+  pub fn to_value(&self) -> Value {
+    let headers = self
+      .headers
+      .iter()
+      .map(|(k, v)| (k.clone(), Value::from(v.as_str())))
+      .collect::<BTreeMap<_, _>>();
+    Value::from(BTreeMap::from([
+      ("status".to_string(), Value::from(self.status as u64)),
+      ("headers".to_string(), Value::from(headers)),
+      ("body".to_string(), self.body.clone()),
+    ]))
+  }
+}
+
+impl From<&Response> for FetchResponse {
+  fn from(res: &Response) -> Self {
+    let json = res
+      .header("Content-Type")
+      .and_then(|ct| ct.parse::<MediaType>().ok())
+      .is_some_and(|ct| ct.is_json());
+    let text = || Value::from(String::from_utf8_lossy(res.body()).to_string());
+    let body = match (res.body().is_empty(), json) {
+      (true, _) => Value::Null,
+      (false, true) => serde_json::from_slice::<Value>(res.body()).unwrap_or_else(|_| text()),
+      (false, false) => text(),
+    };
+    Self {
+      status: res.status(),
+      headers: res
+        .headers()
+        .iter()
+        .map(|(k, v)| (k.to_ascii_lowercase(), v.to_string()))
+        .collect(),
+      body,
+    }
+  }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+  use std::{net::TcpListener, thread, time::Duration};
+
+  use crate::{Config, Method, Route, RouteKind, Script, Server, Value};
+
+  use super::FetchPolicy;
+
+  #[test]
+  fn fetch() {
+    let port = TcpListener::bind("127.0.0.1:0")
+      .unwrap()
+      .local_addr()
+      .unwrap()
+      .port();
+    let config = Config {
+      port,
+      routes: vec![Route::new(
+        vec![Method::Get],
+        "/users",
+        RouteKind::Fixture {
+          status: 200,
+          headers: [("Content-Type".to_string(), "application/json".to_string())].into(),
+          body: Some(Value::from(r#"[{"id": 1}]"#)),
+          file: None,
+          template: false,
+        },
+      )],
+      ..Default::default()
+    };
+    let server = Server::new(config).unwrap();
+    thread::spawn(move || server.listen());
+    let policy = FetchPolicy::default()
+      .with_origin(format!("http://127.0.0.1:{}", port))
+      .with_allow("*.internal");
+    assert!(policy.allows("api.internal:8080"));
+    assert!(!policy.allows("example.com"));
+    assert!(policy
+      .fetch("http://example.com/", &Default::default())
+      .is_err());
+    let res = loop {
+      match policy.fetch("/users", &Default::default()) {
+        Ok(res) => break res,
+        Err(_) => thread::sleep(Duration::from_millis(20)),
+      }
+    };
+    assert_eq!(res.status, 200);
+    assert_eq!(res.body.get_path("0.id"), Some(&Value::from(1u64)));
+
+    let path = std::env::temp_dir().join(format!("mocker-fetch-{}.js", std::process::id()));
+    std::fs::write(
+      &path,
+      "function handle() { const res = fetch('/users'); return { ok: res.ok, id: res.json()[0].id }; }",
+    )
+    .unwrap();
+    let ret = Script::new(&path, "handle")
+      .with_fetch(Some(policy))
+      .call(serde_json::Value::Null)
+      .unwrap();
+    assert_eq!(ret, serde_json::json!({"ok": true, "id": 1}));
+    let denied = Script::new(&path, "handle").call(serde_json::Value::Null);
+    assert!(denied.is_err());
+    std::fs::remove_file(&path).unwrap();
+  }
+}
@@ -1,23 +1,28 @@
 use std::{
+  fs::File,
+  io::{Read, Write},
   path::{Path, PathBuf},
-  rc::Rc,
+  sync::Arc,
 };
 
-use crate::{Config, Error, ErrorKind, UserConfig};
+use crate::{upgrade, Config, UserConfig, Value};
+
+pub type Serializer<T> = Arc<dyn Fn(&Path, &T) -> crate::Result<()> + Send + Sync>;
+pub type Deserializer<T> = Arc<dyn Fn(&Path) -> crate::Result<T> + Send + Sync>;
 
 #[derive(Clone)]
 pub struct Format<T> {
   pub exts: Vec<String>,
-  pub serialize: Rc<dyn Fn(&Path, &T) -> crate::Result<()>>,
-  pub deserialize: Rc<dyn Fn(&Path) -> crate::Result<T>>,
+  pub serialize: Serializer<T>,
+  pub deserialize: Deserializer<T>,
 }
 
 impl<T> Format<T> {
   pub fn new<
     X: AsRef<str>,
     Xi: IntoIterator<Item = X>,
-    S: Fn(&Path, &T) -> crate::Result<()> + 'static,
-    D: Fn(&Path) -> crate::Result<T> + 'static,
+    S: Fn(&Path, &T) -> crate::Result<()> + Send + Sync + 'static,
+    D: Fn(&Path) -> crate::Result<T> + Send + Sync + 'static,
   >(
     exts: Xi,
     serialize: S,
@@ -28,8 +33,8 @@ impl<T> Format<T> {
         .into_iter()
         .map(|ext| ext.as_ref().to_string())
         .collect::<Vec<_>>(),
-      serialize: Rc::new(serialize),
-      deserialize: Rc::new(deserialize),
+      serialize: Arc::new(serialize),
+      deserialize: Arc::new(deserialize),
     }
   }
 }
@@ -45,8 +50,12 @@ pub fn config_formats() -> Vec<Format<Config>> {
         Ok(())
       },
       |path| {
-        let json = std::fs::read(path)?;
-        let cfg: UserConfig = serde_json::from_slice(&json)?;
+        let json: serde_json::Value = serde_json::from_slice(&std::fs::read(path)?)?;
+        let json = match upgrade(Value::try_from_json(json.clone())?, path)? {
+          Some(doc) => doc.to_json(),
+          None => json,
+        };
+        let cfg: UserConfig = serde_json::from_value(json)?;
         Ok(cfg.realize())
       },
     ),
@@ -59,8 +68,12 @@ pub fn config_formats() -> Vec<Format<Config>> {
         Ok(())
       },
       |path| {
-        let toml = std::fs::read_to_string(path)?;
-        let cfg: UserConfig = toml::from_str(&toml)?;
+        let toml: toml::Value = toml::from_str(&std::fs::read_to_string(path)?)?;
+        let toml = match upgrade(Value::try_from_toml(toml.clone())?, path)? {
+          Some(doc) => doc.to_toml()?,
+          None => toml,
+        };
+        let cfg: UserConfig = toml.try_into()?;
         Ok(cfg.realize())
       },
     ),
@@ -73,19 +86,53 @@ pub fn config_formats() -> Vec<Format<Config>> {
         Ok(())
       },
       |path| {
-        let toml = std::fs::read_to_string(path)?;
-        let cfg: UserConfig = serde_yml::from_str(&toml)?;
+        let yaml: serde_yml::Value = serde_yml::from_str(&std::fs::read_to_string(path)?)?;
+        let yaml = match upgrade(Value::try_from_yaml(yaml.clone())?, path)? {
+          Some(doc) => doc.to_yaml(),
+          None => yaml,
+        };
+        let cfg: UserConfig = serde_yml::from_value(yaml)?;
         Ok(cfg.realize())
       },
     ),
   ]
 }
 
+/// Config files as plain documents, for rewriting them as they are
+pub fn document_formats() -> Vec<Format<Value>> {
+  vec![
+    #[cfg(feature = "json")]
+    Format::new(
+      vec!["json"],
+      |path, value: &Value| {
+        std::fs::write(path, serde_json::to_vec_pretty(&value.to_json())?)?;
+        Ok(())
+      },
+      |path| Value::try_from_json(serde_json::from_slice(&std::fs::read(path)?)?),
+    ),
+    #[cfg(feature = "toml")]
+    Format::new(
+      vec!["toml"],
+      |path, value: &Value| {
+        std::fs::write(path, toml::to_string_pretty(&value.to_toml()?)?)?;
+        Ok(())
+      },
+      |path| Value::try_from_toml(toml::from_str(&std::fs::read_to_string(path)?)?),
+    ),
+    #[cfg(feature = "yaml")]
+    Format::new(
+      vec!["yaml", "yml"],
+      |path, value: &Value| {
+        std::fs::write(path, serde_yml::to_string(&value.to_yaml())?)?;
+        Ok(())
+      },
+      |path| Value::try_from_yaml(serde_yml::from_str(&std::fs::read_to_string(path)?)?),
+    ),
+  ]
+}
+
 pub fn find_fmt<P: AsRef<Path>>(path: P) -> Option<(Format<Config>, PathBuf)> {
-  let pext = match path.as_ref().extension().and_then(|ext| ext.to_str()) {
-    Some(ext) => ext,
-    None => return None,
-  };
+  let pext = path.as_ref().extension().and_then(|ext| ext.to_str())?;
   let formats = config_formats();
   for fmt in &formats {
     for ext in &fmt.exts {
@@ -96,3 +143,86 @@ pub fn find_fmt<P: AsRef<Path>>(path: P) -> Option<(Format<Config>, PathBuf)> {
   }
   None
 }
+
+/// Whether `path` holds gzip-compressed data, judging by its `.gz` extension
+pub fn is_gzipped<P: AsRef<Path>>(path: P) -> bool {
+  path
+    .as_ref()
+    .extension()
+    .is_some_and(|ext| ext.eq_ignore_ascii_case("gz"))
+}
+
+#[cfg(not(feature = "gzip"))]
+fn gzip_unsupported(path: &Path) -> crate::Error {
+  crate::Error::new(
+    crate::ErrorKind::Parse,
+    Some(format!(
+      "{} is compressed, which requires the `gzip` feature",
+      path.display()
+    )),
+    None,
+  )
+}
+
+/// Open `path` for reading, decompressing `.gz` files on the fly
+pub fn open_file<P: AsRef<Path>>(path: P) -> crate::Result<Box<dyn Read>> {
+  let path = path.as_ref();
+  let file = File::open(path)?;
+  if is_gzipped(path) {
+    #[cfg(feature = "gzip")]
+    return Ok(Box::new(flate2::read::MultiGzDecoder::new(file)));
+    #[cfg(not(feature = "gzip"))]
+    return Err(gzip_unsupported(path));
+  }
+  Ok(Box::new(file))
+}
+
+/// Content of `path`, decompressed if it is a `.gz` file
+pub fn read_file<P: AsRef<Path>>(path: P) -> crate::Result<Vec<u8>> {
+  let mut ret = vec![];
+  open_file(path)?.read_to_end(&mut ret)?;
+  Ok(ret)
+}
+
+/// Replace the content of `path` with what `f` writes, compressed if it is a
+/// `.gz` file
+pub fn write_file<P: AsRef<Path>, F: FnOnce(&mut dyn Write) -> crate::Result<()>>(
+  path: P,
+  f: F,
+) -> crate::Result<()> {
+  let path = path.as_ref();
+  if is_gzipped(path) {
+    #[cfg(feature = "gzip")]
+    {
+      let mut encoder =
+        flate2::write::GzEncoder::new(File::create(path)?, flate2::Compression::default());
+      f(&mut encoder)?;
+      encoder.finish()?;
+      return Ok(());
+    }
+    #[cfg(not(feature = "gzip"))]
+    return Err(gzip_unsupported(path));
+  }
+  let mut file = File::create(path)?;
+  f(&mut file)?;
+  file.flush()?;
+  Ok(())
+}
+
+#[cfg(all(test, feature = "gzip"))]
+mod tests {
+  use super::{read_file, write_file};
+
+  #[test]
+  fn gzipped() {
+    let dir = std::env::temp_dir().join(format!("mocker-gz-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (plain, gzipped) = (dir.join("users.json"), dir.join("users.json.gz"));
+    for path in [&plain, &gzipped] {
+      write_file(path, |w| Ok(w.write_all(br#"[{"id": 1}]"#)?)).unwrap();
+      assert_eq!(read_file(path).unwrap(), br#"[{"id": 1}]"#);
+    }
+    assert_eq!(std::fs::read(&gzipped).unwrap()[..2], [0x1f, 0x8b]);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
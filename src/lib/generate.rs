@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{fault::random, render, Error, ErrorKind, TemplateContext, Value, Variables};
+
+type Item = HashMap<String, Value>;
+
+fn invalid<S: AsRef<str>>(message: S) -> Error {
+  Error::new(
+    ErrorKind::Parse,
+    Some(format!("invalid dataset: {}", message.as_ref())),
+    None,
+  )
+}
+
+/// Reference from the items of a store to those of another, e.g. the
+/// `authorId` of posts being the `id` of a user
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Relation {
+  /// Store referenced, a key of the dataset
+  pub store: String,
+  /// Field of the referenced items copied, `id` by default
+  #[serde(default = "Relation::default_field")]
+  pub field: String,
+  /// Least number of items referencing each referenced item
+  #[serde(default)]
+  pub min: usize,
+  /// Most number of items referencing each referenced item, unbounded when
+  /// missing
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub max: Option<usize>,
+}
+
+impl Relation {
+  fn default_field() -> String {
+    "id".to_string()
+  }
+
+  /// Referenced value of each of `count` items, `targets` being the values
+  /// of the referenced items, spread at random within the cardinality bounds
+  fn assign(&self, count: usize, targets: &[Value]) -> crate::Result<Vec<Value>> {
+    let max = self.max.unwrap_or(usize::MAX);
+    let n = targets.len();
+    if (n == 0 && count > 0) || n * self.min > count || n.saturating_mul(max) < count {
+      return Err(invalid(format!(
+        "{} items cannot reference the {} items of '{}' between {} and {} times each",
+        count,
+        n,
+        self.store,
+        self.min,
+        self.max.map_or("any".to_string(), |max| max.to_string())
+      )));
+    }
+    let mut uses = vec![self.min; n];
+    let mut picks = (0..n)
+      .flat_map(|target| std::iter::repeat_n(target, self.min))
+      .collect::<Vec<_>>();
+    while picks.len() < count {
+      let open = (0..n).filter(|t| uses[*t] < max).collect::<Vec<_>>();
+      let target = open[random() as usize % open.len()];
+      uses[target] += 1;
+      picks.push(target);
+    }
+    for i in (1..picks.len()).rev() {
+      picks.swap(i, random() as usize % (i + 1));
+    }
+    Ok(picks.into_iter().map(|t| targets[t].clone()).collect())
+  }
+}
+
+/// Fake items of a store: `count` items whose `fields` are templates,
+/// rendered with `index` and the fields generated so far as `item` in
+/// scope, a lone `{{expression}}` keeping the type of its value
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct StoreSpec {
+  pub count: usize,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub fields: BTreeMap<String, Value>,
+  /// Fields referencing the items of other stores
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub relations: BTreeMap<String, Relation>,
+}
+
+/// Fake data spanning several stores, keyed by the endpoint of their store
+/// route, whose relations hold: every reference points to an existing item.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(transparent)]
+pub struct Dataset(pub BTreeMap<String, StoreSpec>);
+
+impl Dataset {
+  pub fn with_store<S: AsRef<str>>(mut self, name: S, spec: StoreSpec) -> Self {
+    self.0.insert(name.as_ref().to_string(), spec);
+    self
+  }
+
+  /// Stores in generation order, referenced stores first
+  fn order(&self) -> crate::Result<Vec<&String>> {
+    fn visit<'a>(
+      dataset: &'a Dataset,
+      name: &'a String,
+      path: &mut Vec<&'a String>,
+      order: &mut Vec<&'a String>,
+    ) -> crate::Result<()> {
+      if order.contains(&name) {
+        return Ok(());
+      }
+      if path.contains(&name) {
+        return Err(invalid(format!("'{}' references itself", name)));
+      }
+      let spec = dataset
+        .0
+        .get(name)
+        .ok_or_else(|| invalid(format!("unknown store '{}'", name)))?;
+      path.push(name);
+      for relation in spec.relations.values() {
+        visit(dataset, &relation.store, path, order)?;
+      }
+      path.pop();
+      order.push(name);
+      Ok(())
+    }
+    let mut order = vec![];
+    for name in self.0.keys() {
+      visit(self, name, &mut vec![], &mut order)?;
+    }
+    Ok(order)
+  }
+
+  /// Items of every store
+  pub fn generate(&self) -> crate::Result<BTreeMap<String, Vec<Item>>> {
+    let variables = Variables::default();
+    let mut stores = BTreeMap::<String, Vec<Item>>::new();
+    for name in self.order()? {
+      let spec = &self.0[name];
+      let mut references = vec![];
+      for (field, relation) in &spec.relations {
+        let targets = stores[&relation.store]
+          .iter()
+          .map(|item| item.get(&relation.field).cloned().unwrap_or_default())
+          .collect::<Vec<_>>();
+        references.push((field, relation.assign(spec.count, &targets)?));
+      }
+      let mut items = Vec::with_capacity(spec.count);
+      for index in 0..spec.count {
+        let mut item = references
+          .iter()
+          .map(|(field, values)| (field.to_string(), values[index].clone()))
+          .collect::<Item>();
+        for (field, template) in &spec.fields {
+          let ctx = TemplateContext::new(&variables)
+            .with_scope(name)
+            .with_data("index", index as u64)
+            .with_data("item", item.clone());
+          item.insert(field.clone(), generate_value(template, &ctx)?);
+        }
+        items.push(item);
+      }
+      stores.insert(name.clone(), items);
+    }
+    Ok(stores)
+  }
+}
+
+/// Value of a field `template`, strings being rendered at any depth
+fn generate_value(template: &Value, ctx: &TemplateContext) -> crate::Result<Value> {
+  Ok(match template {
+    Value::String(source) => {
+      let expr = source
+        .trim()
+        .strip_prefix("{{")
+        .and_then(|s| s.strip_suffix("}}"))
+        .filter(|expr| !expr.contains("{{") && !expr.trim_start().starts_with('>'));
+      match expr {
+        Some(expr) => ctx.eval(expr)?,
+        None => Value::from(render(source, ctx)?),
+      }
+    }
+    Value::Array(values) => Value::Array(
+      values
+        .iter()
+        .map(|v| generate_value(v, ctx))
+        .collect::<crate::Result<_>>()?,
+    ),
+    Value::Map(fields) => Value::Map(
+      fields
+        .iter()
+        .map(|(k, v)| Ok((k.clone(), generate_value(v, ctx)?)))
+        .collect::<crate::Result<_>>()?,
+    ),
+    v => v.clone(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::{BTreeMap, HashMap};
+
+  use crate::Value;
+
+  use super::{Dataset, Relation, StoreSpec};
+
+  #[test]
+  fn relations() {
+    let fields = |entries: &[(&str, &str)]| {
+      entries
+        .iter()
+        .map(|(k, v)| (k.to_string(), Value::from(*v)))
+        .collect::<BTreeMap<_, _>>()
+    };
+    let author = |min, max| Relation {
+      store: "/users".to_string(),
+      field: "id".to_string(),
+      min,
+      max,
+    };
+    let dataset = Dataset::default()
+      .with_store(
+        "/posts",
+        StoreSpec {
+          count: 9,
+          fields: fields(&[("id", "{{sequence 'id'}}"), ("title", "Post {{index}}")]),
+          relations: BTreeMap::from([("authorId".to_string(), author(1, Some(3)))]),
+        },
+      )
+      .with_store(
+        "/users",
+        StoreSpec {
+          count: 3,
+          fields: fields(&[("id", "{{sequence 'id' 100}}")]),
+          ..Default::default()
+        },
+      );
+    let stores = dataset.generate().unwrap();
+    let ids = stores["/users"]
+      .iter()
+      .map(|u| u["id"].clone())
+      .collect::<Vec<_>>();
+    assert_eq!(ids, [100, 101, 102].map(Value::from));
+    let mut counts = HashMap::new();
+    for post in &stores["/posts"] {
+      assert!(ids.contains(&post["authorId"]));
+      *counts.entry(post["authorId"].to_string()).or_insert(0) += 1;
+    }
+    assert!(counts.values().all(|n| *n == 3), "{:?}", counts);
+    assert_eq!(stores["/posts"][8]["title"], Value::from("Post 8"));
+
+    let mut impossible = dataset.clone();
+    impossible.0.get_mut("/posts").unwrap().relations =
+      BTreeMap::from([("authorId".to_string(), author(4, None))]);
+    assert!(impossible.generate().is_err());
+    let mut cycle = dataset.clone();
+    cycle.0.get_mut("/users").unwrap().relations = BTreeMap::from([(
+      "pinned".to_string(),
+      Relation {
+        store: "/posts".to_string(),
+        ..author(0, None)
+      },
+    )]);
+    assert!(cycle.generate().is_err());
+  }
+}
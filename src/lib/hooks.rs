@@ -0,0 +1,186 @@
+use std::{
+  collections::BTreeMap,
+  io::Read,
+  path::PathBuf,
+  process::{Command, Stdio},
+  thread,
+  time::{Duration, Instant},
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::{parse_duration, Error, ErrorKind};
+
+/// Work done once the routes and their stores are loaded but before the
+/// server accepts connections, such as generating time-relative fixture
+/// data. A failing hook aborts startup.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum StartupHook {
+  /// A local command, which must exit successfully
+  Command {
+    command: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    args: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    env: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cwd: Option<PathBuf>,
+    /// Maximum run time before the command is killed, e.g. `30s`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timeout: Option<String>,
+    /// File the command's stdout is written to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<PathBuf>,
+  },
+  /// A javascript function, called without arguments
+  #[cfg(feature = "js")]
+  Script {
+    script: PathBuf,
+    func: String,
+    /// File the function's result is written to, as JSON
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<PathBuf>,
+  },
+}
+
+impl StartupHook {
+  pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(60);
+
+  /// Human-readable description, for logs and errors
+  pub fn describe(&self) -> String {
+    match self {
+      Self::Command { command, args, .. } => std::iter::once(command.clone())
+        .chain(args.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(" "),
+      #[cfg(feature = "js")]
+      Self::Script { script, func, .. } => format!("{}#{}", script.display(), func),
+    }
+  }
+
+  pub fn run(&self) -> crate::Result<()> {
+    let failed = |msg: String| {
+      Error::new(
+        ErrorKind::IO,
+        Some(format!(
+          "startup hook `{}` failed: {}",
+          self.describe(),
+          msg
+        )),
+        None,
+      )
+    };
+    let (output, content) = match self {
+      Self::Command {
+        command,
+        args,
+        env,
+        cwd,
+        timeout,
+        output,
+      } => {
+        let timeout = match timeout {
+          Some(t) => parse_duration(t)?,
+          None => Self::DEFAULT_TIMEOUT,
+        };
+        let mut cmd = Command::new(command);
+        cmd
+          .args(args)
+          .envs(env)
+          .stdin(Stdio::null())
+          .stdout(Stdio::piped())
+          .stderr(Stdio::piped());
+        if let Some(cwd) = cwd {
+          cmd.current_dir(cwd);
+        }
+        let mut child = cmd.spawn().map_err(|e| failed(e.to_string()))?;
+        let pipe = |pipe: Option<Box<dyn Read + Send>>| {
+          pipe.map(|mut pipe| {
+            thread::spawn(move || {
+              let mut buf = vec![];
+              let _ = pipe.read_to_end(&mut buf);
+              buf
+            })
+          })
+        };
+        let stdout = pipe(child.stdout.take().map(|p| Box::new(p) as _));
+        let stderr = pipe(child.stderr.take().map(|p| Box::new(p) as _));
+        let started = Instant::now();
+        let exit = loop {
+          if let Some(exit) = child.try_wait()? {
+            break exit;
+          }
+          if started.elapsed() >= timeout {
+            child.kill()?;
+            child.wait()?;
+            return Err(failed(format!("timed out after {:?}", timeout)));
+          }
+          thread::sleep(Duration::from_millis(10));
+        };
+        let join = |h: Option<thread::JoinHandle<Vec<u8>>>| {
+          h.and_then(|h| h.join().ok()).unwrap_or_default()
+        };
+        let (stdout, stderr) = (join(stdout), join(stderr));
+        if !exit.success() {
+          return Err(failed(format!(
+            "{}: {}",
+            exit,
+            String::from_utf8_lossy(&stderr).trim()
+          )));
+        }
+        (output, stdout)
+      }
+      #[cfg(feature = "js")]
+      Self::Script {
+        script,
+        func,
+        output,
+      } => {
+        let ret = crate::Script::new(script, func)
+          .call(serde_json::Value::Null)
+          .map_err(|e| failed(e.to_string()))?;
+        (output, serde_json::to_vec_pretty(&ret)?)
+      }
+    };
+    if let Some(output) = output {
+      std::fs::write(output, content)
+        .map_err(|e| failed(format!("cannot write {}: {}", output.display(), e)))?;
+    }
+    Ok(())
+  }
+}
+
+/// Run every hook in order, stopping at the first failure
+pub fn run_startup_hooks(hooks: &[StartupHook]) -> crate::Result<()> {
+  for hook in hooks {
+    info!("🪝 Running startup hook `{}`", hook.describe());
+    hook.run()?;
+  }
+  Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use super::{run_startup_hooks, StartupHook};
+
+  #[test]
+  fn run() {
+    let path = std::env::temp_dir().join(format!("mocker-hook-{}.json", std::process::id()));
+    let hook = |script: &str, output| StartupHook::Command {
+      command: "sh".to_string(),
+      args: vec!["-c".to_string(), script.to_string()],
+      env: [("SEED".to_string(), "42".to_string())].into(),
+      cwd: None,
+      timeout: Some("5s".to_string()),
+      output,
+    };
+    run_startup_hooks(&[hook(r#"echo "[$SEED]""#, Some(path.clone()))]).unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "[42]\n");
+    std::fs::remove_file(&path).unwrap();
+
+    let err = run_startup_hooks(&[hook("echo nope >&2; exit 3", None)]).unwrap_err();
+    assert!(err.to_string().contains("nope"), "{}", err);
+  }
+}
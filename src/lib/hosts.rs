@@ -0,0 +1,247 @@
+use std::{
+  fs,
+  io::{ErrorKind as IoErrorKind, Write},
+  net::IpAddr,
+  path::{Path, PathBuf},
+  process::{Command, Stdio},
+};
+
+use log::info;
+
+use crate::{glob_match, Config, Error, ErrorKind, Request};
+
+/// Host a request was sent to, from its `Host` header, without the port
+pub fn request_host(req: &Request) -> Option<String> {
+  let host = req.header("Host")?.trim();
+  let host = match host.strip_prefix('[') {
+    // IPv6 literal, e.g. `[::1]:8080`
+    Some(v6) => v6.split(']').next().unwrap_or_default(),
+    None => host.split(':').next().unwrap_or_default(),
+  };
+  Some(host.to_ascii_lowercase())
+}
+
+/// Whether `req` was sent to a host matching the glob `pattern`, e.g.
+/// `api.service.test` or `*.service.test`
+pub fn host_matches<P: AsRef<str>>(pattern: P, req: &Request) -> bool {
+  request_host(req).is_some_and(|host| glob_match(pattern.as_ref().to_ascii_lowercase(), host))
+}
+
+/// Whether `name` is a valid hostname as RFC 1123 defines it: dot separated
+/// labels of letters, digits and inner hyphens
+pub fn is_hostname<S: AsRef<str>>(name: S) -> bool {
+  let name = name.as_ref();
+  name.len() <= 253
+    && name.split('.').all(|label| {
+      (1..=63).contains(&label.len())
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+        && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+/// Section of a hosts file managed by `mocker hosts`, so virtual hostnames
+/// resolve to the mock server without changing the application.
+#[derive(Debug, Clone)]
+pub struct HostsFile {
+  path: PathBuf,
+}
+
+impl HostsFile {
+  const BEGIN: &'static str = "# BEGIN mocker";
+  const END: &'static str = "# END mocker";
+
+  pub fn new<P: AsRef<Path>>(path: P) -> Self {
+    Self {
+      path: path.as_ref().to_path_buf(),
+    }
+  }
+
+  /// The hosts file of the system
+  pub fn system() -> Self {
+    match cfg!(windows) {
+      true => Self::new(r"C:\Windows\System32\drivers\etc\hosts"),
+      false => Self::new("/etc/hosts"),
+    }
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Hostnames a workspace serves: the configured ones and those routes are
+  /// restricted to, wildcard patterns aside
+  pub fn hostnames(config: &Config) -> Vec<String> {
+    let mut ret: Vec<String> = vec![];
+    let routes = config
+      .routes
+      .iter()
+      .filter_map(|r| r.options().host.as_ref());
+    for host in config.hosts.iter().chain(routes) {
+      let host = host.to_ascii_lowercase();
+      if !host.contains(['*', '?']) && !ret.contains(&host) {
+        ret.push(host);
+      }
+    }
+    ret
+  }
+
+  /// `content` without the managed section
+  pub fn without_entries(content: &str) -> String {
+    let mut ret = String::new();
+    let mut managed = false;
+    for line in content.lines() {
+      match line.trim() {
+        l if l == Self::BEGIN => managed = true,
+        l if l == Self::END && managed => managed = false,
+        _ if managed => {}
+        _ => {
+          ret.push_str(line);
+          ret.push('\n');
+        }
+      }
+    }
+    ret
+  }
+
+  /// `content` with a managed section mapping `names` to `addr`, replacing
+  /// any previous one. Fails on anything but an IP address and hostnames,
+  /// which could smuggle more entries in.
+  pub fn with_entries<S: AsRef<str>>(
+    content: &str,
+    names: &[S],
+    addr: &str,
+  ) -> crate::Result<String> {
+    if addr.parse::<IpAddr>().is_err() {
+      return Err(Error::new(
+        ErrorKind::Parse,
+        Some(format!("invalid IP address '{}'", addr)),
+        None,
+      ));
+    }
+    if let Some(name) = names.iter().find(|name| !is_hostname(name)) {
+      return Err(Error::new(
+        ErrorKind::Parse,
+        Some(format!("invalid hostname '{}'", name.as_ref())),
+        None,
+      ));
+    }
+    let mut ret = Self::without_entries(content);
+    if names.is_empty() {
+      return Ok(ret);
+    }
+    if !ret.is_empty() && !ret.ends_with("\n\n") {
+      ret.push('\n');
+    }
+    ret.push_str(Self::BEGIN);
+    ret.push('\n');
+    for name in names {
+      ret.push_str(&format!("{}\t{}\n", addr, name.as_ref()));
+    }
+    ret.push_str(Self::END);
+    ret.push('\n');
+    Ok(ret)
+  }
+
+  /// Map `names` to `addr`, returning whether the file changed
+  pub fn install<S: AsRef<str>>(&self, names: &[S], addr: &str) -> crate::Result<bool> {
+    let content = fs::read_to_string(&self.path)?;
+    self.write(&content, Self::with_entries(&content, names, addr)?)
+  }
+
+  /// Drop the managed section, returning whether the file changed
+  pub fn remove(&self) -> crate::Result<bool> {
+    let content = fs::read_to_string(&self.path)?;
+    self.write(&content, Self::without_entries(&content))
+  }
+
+  /// Replace the file content, through `sudo` when it is not writable
+  fn write(&self, previous: &str, content: String) -> crate::Result<bool> {
+    if previous == content {
+      return Ok(false);
+    }
+    match fs::write(&self.path, &content) {
+      Err(e) if e.kind() == IoErrorKind::PermissionDenied && cfg!(unix) => {
+        info!(
+          "🔐 {} is not writable, asking sudo for permission",
+          self.path.display()
+        );
+        let mut child = Command::new("sudo")
+          .arg("tee")
+          .arg(&self.path)
+          .stdin(Stdio::piped())
+          .stdout(Stdio::null())
+          .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+          stdin.write_all(content.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if !status.success() {
+          return Err(Error::new(
+            ErrorKind::IO,
+            Some(format!(
+              "failed to write {}: sudo exited with {}",
+              self.path.display(),
+              status
+            )),
+            None,
+          ));
+        }
+        Ok(true)
+      }
+      ret => ret.map(|_| true).map_err(Into::into),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Request};
+
+  use super::{host_matches, is_hostname, HostsFile};
+
+  #[test]
+  fn entries() {
+    let original = "127.0.0.1\tlocalhost\n::1\tlocalhost\n";
+    let installed = HostsFile::with_entries(
+      original,
+      &["api.service.test", "auth.service.test"],
+      "127.0.0.1",
+    )
+    .unwrap();
+    assert_eq!(
+      installed,
+      "127.0.0.1\tlocalhost\n::1\tlocalhost\n\n# BEGIN mocker\n127.0.0.1\tapi.service.test\n127.0.0.1\tauth.service.test\n# END mocker\n"
+    );
+    let reinstalled =
+      HostsFile::with_entries(&installed, &["api.service.test"], "127.0.0.1").unwrap();
+    assert_eq!(reinstalled.matches("service.test").count(), 1);
+    assert_eq!(
+      HostsFile::without_entries(&installed).trim_end(),
+      original.trim_end()
+    );
+    assert!(HostsFile::with_entries(original, &["api.test\n0.0.0.0\tbank.test"], "::1").is_err());
+    assert!(HostsFile::with_entries(original, &["api.test"], "127.0.0.1 evil.test").is_err());
+    assert!(is_hostname("api-2.service.test"));
+    for name in [
+      "",
+      "-api.test",
+      "api-.test",
+      "api..test",
+      "api_test",
+      "a b",
+      &"a".repeat(64),
+    ] {
+      assert!(!is_hostname(name), "{}", name);
+    }
+
+    let req = Request::new(Method::Get, "/").with_header("Host", "API.service.test:8080");
+    assert!(host_matches("api.service.test", &req));
+    assert!(host_matches("*.service.test", &req));
+    assert!(!host_matches("auth.service.test", &req));
+    assert!(!host_matches(
+      "api.service.test",
+      &Request::new(Method::Get, "/")
+    ));
+  }
+}
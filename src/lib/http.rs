@@ -1,10 +1,10 @@
 use std::{
-  collections::VecDeque,
-  io::Write,
-  ops::{Deref, DerefMut},
+  io::{IoSlice, Read, Write},
+  ops::Deref,
   str::FromStr,
 };
 
+use bytes::{Bytes, BytesMut};
 use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 
@@ -280,9 +280,10 @@ impl Display for Method {
   }
 }
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, EnumIter)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, EnumIter, Default)]
 pub enum Version {
   V1_0,
+  #[default]
   V1_1,
   V2,
 }
@@ -320,12 +321,6 @@ impl Display for Version {
   }
 }
 
-impl Default for Version {
-  fn default() -> Self {
-    Self::V1_1
-  }
-}
-
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct RequestStart {
   pub method: Method,
@@ -348,16 +343,16 @@ pub enum StartLine {
 
 impl StartLine {
   pub fn request<M: Into<Method>, T: AsRef<str>, V: Into<Version>>(m: M, t: T, v: V) -> Self {
-    return Self::Request(RequestStart {
+    Self::Request(RequestStart {
       method: m.into(),
       target: t.as_ref().to_string(),
       version: v.into(),
-    });
+    })
   }
 
   pub fn response<V: Into<Version>, R: Into<Option<String>>>(v: V, s: u16, r: R) -> Self {
     let reason: Option<String> = r.into();
-    return Self::Response(ResponseStart {
+    Self::Response(ResponseStart {
       version: v.into(),
       status: s,
       reason: reason.or_else(|| {
@@ -366,7 +361,7 @@ impl StartLine {
         }
         None
       }),
-    });
+    })
   }
 
   pub fn as_request(&self) -> Option<&RequestStart> {
@@ -425,7 +420,7 @@ impl FromStr for StartLine {
       // is request line
       Ok(StartLine::request(
         parts[0].parse::<Method>()?,
-        parts[1].to_string(),
+        parts[1],
         parts
           .get(2)
           .ok_or_else(|| {
@@ -465,16 +460,75 @@ impl Display for StartLine {
   }
 }
 
+/// Header name or value, sharing the memory of the message it was parsed
+/// from instead of being copied out of it
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HeaderText(Bytes);
+
+impl HeaderText {
+  pub fn as_str(&self) -> &str {
+    // only ever built from text
+    std::str::from_utf8(&self.0).unwrap_or_default()
+  }
+}
+
+impl Deref for HeaderText {
+  type Target = str;
+
+  fn deref(&self) -> &Self::Target {
+    self.as_str()
+  }
+}
+
+impl AsRef<str> for HeaderText {
+  fn as_ref(&self) -> &str {
+    self.as_str()
+  }
+}
+
+impl From<&str> for HeaderText {
+  fn from(value: &str) -> Self {
+    Self(Bytes::copy_from_slice(value.as_bytes()))
+  }
+}
+
+impl From<String> for HeaderText {
+  fn from(value: String) -> Self {
+    Self(Bytes::from(value))
+  }
+}
+
+impl PartialEq<str> for HeaderText {
+  fn eq(&self, other: &str) -> bool {
+    self.as_str() == other
+  }
+}
+
+impl PartialEq<&str> for HeaderText {
+  fn eq(&self, other: &&str) -> bool {
+    self.as_str() == *other
+  }
+}
+
+impl Display for HeaderText {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl std::fmt::Debug for HeaderText {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    std::fmt::Debug::fmt(self.as_str(), f)
+  }
+}
+
 #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Buffer {
   start_line: StartLine,
-  headers: Vec<(String, String)>,
-  body: Vec<u8>,
+  headers: Vec<(HeaderText, HeaderText)>,
+  body: Bytes,
 }
 
-unsafe impl Send for Buffer {}
-unsafe impl Sync for Buffer {}
-
 impl Default for Buffer {
   fn default() -> Self {
     Self {
@@ -485,6 +539,8 @@ impl Default for Buffer {
   }
 }
 impl Buffer {
+  const READ_SIZE: usize = 8192;
+
   pub fn with_start_line(mut self, v: StartLine) -> Self {
     self.start_line = v;
     self
@@ -496,7 +552,7 @@ impl Buffer {
   ) -> Self {
     self.headers = v
       .into_iter()
-      .map(|(k, v)| (k.as_ref().to_string(), v.as_ref().to_string()))
+      .map(|(k, v)| (HeaderText::from(k.as_ref()), HeaderText::from(v.as_ref())))
       .collect::<Vec<_>>();
     self
   }
@@ -504,7 +560,7 @@ impl Buffer {
   pub fn with_header<K: AsRef<str>, V: AsRef<str>>(mut self, k: K, v: V) -> Self {
     self
       .headers
-      .push((k.as_ref().to_string(), v.as_ref().to_string()));
+      .push((HeaderText::from(k.as_ref()), HeaderText::from(v.as_ref())));
     self
   }
 
@@ -514,9 +570,16 @@ impl Buffer {
     self
   }
 
+  pub fn with_body_bytes<B: Into<Bytes>>(mut self, v: B) -> Self {
+    self.body = v.into();
+    self.set_header("Content-Length", self.body.len().to_string());
+    self
+  }
+
   pub fn append_body<B: AsRef<str>>(&mut self, v: B) {
-    let data = v.as_ref().bytes().collect::<Vec<_>>();
-    self.body.extend_from_slice(&data);
+    let mut body = BytesMut::from(&self.body[..]);
+    body.extend_from_slice(v.as_ref().as_bytes());
+    self.body = body.freeze();
     self.set_header("Content-Length", self.body.len().to_string());
   }
 
@@ -526,13 +589,26 @@ impl Buffer {
       .iter_mut()
       .find(|(hk, _hv)| hk.eq_ignore_ascii_case(k.as_ref()))
     {
-      Some((_hk, hv)) => *hv = v.as_ref().to_string(),
+      Some((_hk, hv)) => *hv = HeaderText::from(v.as_ref()),
       None => self
         .headers
-        .push((k.as_ref().to_string(), v.as_ref().to_string())),
+        .push((HeaderText::from(k.as_ref()), HeaderText::from(v.as_ref()))),
     }
   }
 
+  /// Remove every `k` header, returning the first value found
+  pub fn remove_header<K: AsRef<str>>(&mut self, k: K) -> Option<String> {
+    let mut ret = None;
+    self.headers.retain(|(hk, hv)| {
+      if hk.eq_ignore_ascii_case(k.as_ref()) {
+        ret.get_or_insert_with(|| hv.to_string());
+        return false;
+      }
+      true
+    });
+    ret
+  }
+
   pub fn start_line(&self) -> &StartLine {
     &self.start_line
   }
@@ -541,31 +617,173 @@ impl Buffer {
     &mut self.start_line
   }
 
-  pub fn header<K: AsRef<str>>(&self, uk: K) -> Option<&String> {
+  pub fn header<K: AsRef<str>>(&self, uk: K) -> Option<&str> {
     self.headers.iter().find_map(|(k, v)| {
       if k.eq_ignore_ascii_case(uk.as_ref()) {
-        return Some(v);
+        return Some(v.as_str());
       }
       None
     })
   }
 
-  pub fn headers(&self) -> &Vec<(String, String)> {
+  pub fn headers(&self) -> &Vec<(HeaderText, HeaderText)> {
     &self.headers
   }
 
-  pub fn body(&self) -> &Vec<u8> {
+  /// Copy of the headers, for records outliving the message
+  pub fn owned_headers(&self) -> Vec<(String, String)> {
+    self
+      .headers
+      .iter()
+      .map(|(k, v)| (k.to_string(), v.to_string()))
+      .collect()
+  }
+
+  pub fn body(&self) -> &Bytes {
     &self.body
   }
 
-  pub fn write_to<W: Write>(&self, mut w: W) -> crate::Result<()> {
-    writeln!(w, "{}", self.start_line)?;
+  /// Offsets of the blank line ending the head of `data` and of the body
+  fn head_end(data: &[u8]) -> Option<(usize, usize)> {
+    data
+      .iter()
+      .enumerate()
+      .find_map(|(i, c)| match (c, &data[i + 1..]) {
+        (b'\n', [b'\n', ..]) => Some((i, i + 2)),
+        (b'\n', [b'\r', b'\n', ..]) => Some((i, i + 3)),
+        _ => None,
+      })
+  }
+
+  fn content_length(head: &[u8]) -> Option<usize> {
+    std::str::from_utf8(head).ok()?.lines().find_map(|line| {
+      let (k, v) = line.split_once(':')?;
+      match k.trim().eq_ignore_ascii_case("Content-Length") {
+        true => v.trim().parse().ok(),
+        false => None,
+      }
+    })
+  }
+
+  /// Parse a whole message, the body sharing the memory of `data`
+  pub fn parse(data: Bytes) -> crate::Result<Self> {
+    let (head_len, body_start) = Self::head_end(&data).unwrap_or((data.len(), data.len()));
+    let head = std::str::from_utf8(&data[..head_len])?;
+    let mut lines = head.lines();
+    let start_line = lines
+      .next()
+      .filter(|line| !line.is_empty())
+      .ok_or_else(|| {
+        Error::new(
+          ErrorKind::Parse,
+          Some(format!(
+            "invalid http buffer, missing start line:\n{}",
+            head
+          )),
+          None,
+        )
+      })?
+      .parse()?;
+    let mut headers = vec![];
+    for line in lines.filter(|line| !line.is_empty()) {
+      let (k, v) = line.split_once(':').ok_or_else(|| {
+        Error::new(
+          ErrorKind::Parse,
+          Some(format!("invalid header '{}'", line)),
+          None,
+        )
+      })?;
+      headers.push((
+        HeaderText(data.slice_ref(k.as_bytes())),
+        HeaderText(data.slice_ref(v.trim().as_bytes())),
+      ));
+    }
+    let mut buf = Self {
+      start_line,
+      headers,
+      body: data.slice(body_start..),
+    };
+    // the announced length is fixed up, but not added to messages without body
+    if !buf.body.is_empty() || buf.header("Content-Length").is_some() {
+      buf.set_header("Content-Length", buf.body.len().to_string());
+    }
+    Ok(buf)
+  }
+
+  /// Read a message from `r`, until its announced body is complete or, when
+  /// it announces none, until a read comes back short.
+  pub fn read_from<R: Read>(mut r: R) -> crate::Result<Self> {
+    let mut data = BytesMut::new();
+    loop {
+      let len = data.len();
+      data.resize(len + Self::READ_SIZE, 0);
+      let nread = r.read(&mut data[len..])?;
+      data.truncate(len + nread);
+      if nread == 0 {
+        break;
+      }
+      let expected = Self::head_end(&data).map(|(head_len, body_start)| {
+        Self::content_length(&data[..head_len]).map(|n| body_start + n)
+      });
+      match expected {
+        Some(Some(total)) if data.len() >= total => break,
+        Some(Some(_)) => continue,
+        _ if nread < Self::READ_SIZE => break,
+        _ => continue,
+      }
+    }
+    Self::parse(data.freeze())
+  }
+
+  /// Start line and headers, followed by the blank line when a body comes next
+  pub fn head(&self) -> Vec<u8> {
+    let mut head = format!("{}\n", self.start_line);
     for (key, value) in self.headers() {
-      writeln!(w, "{}: {}", key, value)?;
+      head.push_str(key);
+      head.push_str(": ");
+      head.push_str(value);
+      head.push('\n');
     }
     if !self.body.is_empty() {
-      writeln!(w)?;
-      w.write(&self.body())?;
+      head.push('\n');
+    }
+    head.into_bytes()
+  }
+
+  /// Head as RFC 9112 requires it: CRLF line endings, and the blank line
+  /// ending it even without a body
+  pub fn strict_head(&self) -> Vec<u8> {
+    let mut head = format!("{}\r\n", self.start_line);
+    for (key, value) in self.headers() {
+      head.push_str(key);
+      head.push_str(": ");
+      head.push_str(value);
+      head.push_str("\r\n");
+    }
+    head.push_str("\r\n");
+    head.into_bytes()
+  }
+
+  /// Write the head and the body together, in as few calls as `w` allows
+  pub fn write_to<W: Write>(&self, w: W) -> crate::Result<()> {
+    self.write_with_head(w, self.head())
+  }
+
+  /// Same as [`Self::write_to`], with the [`Self::strict_head`]
+  pub fn write_strict_to<W: Write>(&self, w: W) -> crate::Result<()> {
+    self.write_with_head(w, self.strict_head())
+  }
+
+  fn write_with_head<W: Write>(&self, mut w: W, head: Vec<u8>) -> crate::Result<()> {
+    let mut bufs = [IoSlice::new(&head), IoSlice::new(&self.body)];
+    let mut bufs = &mut bufs[..];
+    while bufs.iter().any(|b| !b.is_empty()) {
+      match w.write_vectored(bufs) {
+        Ok(0) => return Err(std::io::Error::from(std::io::ErrorKind::WriteZero).into()),
+        Ok(n) => IoSlice::advance_slices(&mut bufs, n),
+        Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+        Err(e) => return Err(e.into()),
+      }
     }
     Ok(())
   }
@@ -584,78 +802,72 @@ impl FromStr for Buffer {
   type Err = crate::Error;
 
   fn from_str(s: &str) -> Result<Self, Self::Err> {
-    let mut lines = s.lines().collect::<VecDeque<_>>();
-    let start_line = lines.remove(0).ok_or_else(|| {
-      Error::new(
-        ErrorKind::Parse,
-        Some(format!("invalid http buffer, missing start line:\n{}", s)),
-        None,
-      )
-    })?;
-    let start_line = start_line.parse()?;
-    let mut body_mode = false;
-    let mut headers = vec![];
-    let mut body = vec![];
-    for line in lines {
-      if line.is_empty() {
-        body_mode = true;
-      } else {
-        if body_mode {
-          body.push(line);
-        } else {
-          headers.push(line);
-        }
-      }
-    }
-    let headers = headers
-      .iter()
-      .map(|header| {
-        header.split_once(':').ok_or_else(|| {
-          Error::new(
-            ErrorKind::Parse,
-            Some(format!("invalid header '{}'", header)),
-            None,
-          )
-        })
-      })
-      .collect::<Vec<_>>();
-    for kv in &headers {
-      if kv.is_err() {
-        return Err(kv.as_ref().err().unwrap().clone());
-      }
-    }
-    let headers = headers
-      .iter()
-      .filter_map(|h| {
-        if h.is_ok() {
-          let kv = h.as_ref().ok().unwrap();
-          return Some((kv.0, kv.1.trim()));
-        }
-        None
-      })
-      .collect::<Vec<_>>();
-    let body = body.join("\n");
-    Ok(
-      Self::default()
-        .with_start_line(start_line)
-        .with_headers(headers)
-        .with_body(body),
-    )
+    Self::parse(Bytes::copy_from_slice(s.as_bytes()))
   }
 }
 
 #[cfg(test)]
 mod tests {
+  use bytes::Bytes;
+
   use crate::Method;
 
   use super::{Buffer, StartLine, Version};
 
+  #[test]
+  fn parse() {
+    let data = Bytes::from_static(b"POST /upload HTTP/1.1\r\nHost: x\r\n\r\n\x00\xff\n\nend");
+    let buf = Buffer::parse(data.clone()).unwrap();
+    assert_eq!(buf.header("host"), Some("x"));
+    assert_eq!(buf.headers()[0].1.as_ptr(), data[29..].as_ptr());
+    assert_eq!(&buf.body()[..], b"\x00\xff\n\nend");
+    assert_eq!(buf.body().as_ptr(), data[data.len() - 7..].as_ptr());
+
+    // the body arrives in several reads, shorter than the read size
+    let raw = b"POST / HTTP/1.1\nContent-Length: 6\n\nabcdef";
+    let reader = std::io::Read::chain(&raw[..40], &raw[40..]);
+    let buf = Buffer::read_from(reader).unwrap();
+    assert_eq!(&buf.body()[..], b"abcdef");
+
+    let buf = Buffer::parse(Bytes::from_static(b"GET / HTTP/1.1\r\nHost: x\r\n\r\n")).unwrap();
+    assert!(buf.header("Content-Length").is_none());
+    let raw = Bytes::from_static(b"POST / HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+    let buf = Buffer::parse(raw).unwrap();
+    assert_eq!(buf.header("Content-Length"), Some("0"));
+  }
+
+  /// Accepts a few bytes per call, like a congested socket
+  struct Trickle(Vec<u8>);
+
+  impl std::io::Write for Trickle {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      let n = buf.len().min(3);
+      self.0.extend_from_slice(&buf[..n]);
+      Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn write_to() {
+    let buf = Buffer::default()
+      .with_start_line(StartLine::request(Method::Post, "/", Version::V1_1))
+      .with_body("payload");
+    let mut out = Trickle(vec![]);
+    buf.write_to(&mut out).unwrap();
+    assert_eq!(out.0, buf.to_string().into_bytes());
+    assert!(out.0.ends_with(b"\n\npayload"));
+  }
+
   #[test]
   fn response() {
     let buf = Buffer::default()
       .with_start_line(StartLine::response(
         Version::V1_0,
-        200 as u16,
+        200_u16,
         Some("OK".to_string()),
       ))
       .with_headers([("Content-Type", "application/json")])
@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{render_value, Route, Value};
+
+type Item = HashMap<String, Value>;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum HypermediaFormat {
+  /// Entity fields along with `_links: {rel: {href}}`
+  #[default]
+  Hal,
+  /// Resource objects with `type`, `id`, `attributes` and `links`
+  JsonApi,
+}
+
+/// Envelope store routes wrap their entities in, so hypermedia clients can
+/// follow links from one to the other.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hypermedia {
+  #[serde(default)]
+  pub format: HypermediaFormat,
+  /// Link templates by relation, `{field}` standing for a field of the
+  /// entity, e.g. `"orders": "/orders?user_id={id}"`. `self` defaults to the
+  /// entity on the route.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub links: BTreeMap<String, String>,
+  /// JSON:API resource type, the last segment of the endpoint by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub resource_type: Option<String>,
+}
+
+impl Hypermedia {
+  pub fn new(format: HypermediaFormat) -> Self {
+    Self {
+      format,
+      ..Default::default()
+    }
+  }
+
+  pub fn with_link<R: AsRef<str>, T: AsRef<str>>(mut self, rel: R, template: T) -> Self {
+    self
+      .links
+      .insert(rel.as_ref().to_string(), template.as_ref().to_string());
+    self
+  }
+
+  /// `template` with every `{field}` replaced by the value `item` has for it
+  pub fn expand<T: AsRef<str>>(template: T, item: &Item) -> String {
+    let mut ret = String::new();
+    let mut rest = template.as_ref();
+    while let Some(start) = rest.find('{') {
+      let end = match rest[start..].find('}') {
+        Some(end) => start + end,
+        None => break,
+      };
+      ret.push_str(&rest[..start]);
+      let field = &rest[start + 1..end];
+      match item.get(field) {
+        Some(value) => ret.push_str(&render_value(value)),
+        None => ret.push_str(&rest[start..=end]),
+      }
+      rest = &rest[end + 1..];
+    }
+    ret.push_str(rest);
+    ret
+  }
+
+  /// Links of `item`, served by `route` under `identifier`
+  fn links(&self, route: &Route, identifier: &str, item: &Item) -> BTreeMap<String, String> {
+    let mut ret = BTreeMap::new();
+    if let Some(id) = item.get(identifier) {
+      let endpoint = route.endpoint().trim_end_matches('*');
+      let href = format!("{}?{}={}", endpoint, identifier, render_value(id));
+      ret.insert("self".to_string(), href);
+    }
+    for (rel, template) in &self.links {
+      ret.insert(rel.clone(), Self::expand(template, item));
+    }
+    ret
+  }
+
+  fn resource_type(&self, route: &Route) -> String {
+    match &self.resource_type {
+      Some(t) => t.clone(),
+      None => route
+        .endpoint()
+        .rsplit('/')
+        .find(|s| !s.is_empty() && !s.starts_with('{') && *s != "*")
+        .unwrap_or("items")
+        .to_string(),
+    }
+  }
+
+  /// `item` as a HAL resource or a JSON:API resource object
+  pub fn wrap(&self, route: &Route, identifier: &str, item: &Item) -> Item {
+    let links = self.links(route, identifier, item);
+    match self.format {
+      HypermediaFormat::Hal => {
+        let links = links
+          .into_iter()
+          .map(|(rel, href)| {
+            let href = HashMap::from([("href".to_string(), Value::from(href))]);
+            (rel, Value::from(href))
+          })
+          .collect::<HashMap<_, _>>();
+        let mut ret = item.clone();
+        ret.insert("_links".to_string(), Value::from(links));
+        ret
+      }
+      HypermediaFormat::JsonApi => {
+        let attributes = item
+          .iter()
+          .filter(|(k, _)| *k != identifier)
+          .map(|(k, v)| (k.clone(), v.clone()))
+          .collect::<HashMap<_, _>>();
+        let links = links
+          .into_iter()
+          .map(|(rel, href)| (rel, Value::from(href)))
+          .collect::<HashMap<_, _>>();
+        let id = item.get(identifier).map(render_value).unwrap_or_default();
+        HashMap::from([
+          ("type".to_string(), Value::from(self.resource_type(route))),
+          ("id".to_string(), Value::from(id)),
+          ("attributes".to_string(), Value::from(attributes)),
+          ("links".to_string(), Value::from(links)),
+        ])
+      }
+    }
+  }
+
+  /// Response body for a single `item`, JSON:API nesting it under `data`
+  pub fn document(&self, route: &Route, identifier: &str, item: &Item) -> Item {
+    let resource = self.wrap(route, identifier, item);
+    match self.format {
+      HypermediaFormat::Hal => resource,
+      HypermediaFormat::JsonApi => HashMap::from([("data".to_string(), Value::from(resource))]),
+    }
+  }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+  use std::{collections::HashMap, path::PathBuf};
+
+  use crate::{Method, Route, RouteKind, Value};
+
+  use super::{Hypermedia, HypermediaFormat};
+
+  #[test]
+  fn envelopes() {
+    let route = Route::new(
+      vec![Method::Get],
+      "/users",
+      RouteKind::Store {
+        path: PathBuf::from("users.json"),
+        identifier: "id".to_string(),
+        parent: None,
+      },
+    );
+    let user = HashMap::from([
+      ("id".to_string(), Value::from(7)),
+      ("name".to_string(), Value::from("ada")),
+    ]);
+    let json = |item: HashMap<String, Value>| Value::from(item).to_json();
+
+    let hal = Hypermedia::new(HypermediaFormat::Hal).with_link("orders", "/orders?user_id={id}");
+    assert_eq!(
+      json(hal.document(&route, "id", &user)),
+      serde_json::json!({
+        "id": 7,
+        "name": "ada",
+        "_links": {
+          "self": {"href": "/users?id=7"},
+          "orders": {"href": "/orders?user_id=7"}
+        }
+      })
+    );
+
+    let json_api = Hypermedia::new(HypermediaFormat::JsonApi);
+    assert_eq!(
+      json(json_api.document(&route, "id", &user)),
+      serde_json::json!({
+        "data": {
+          "type": "users",
+          "id": "7",
+          "attributes": {"name": "ada"},
+          "links": {"self": "/users?id=7"}
+        }
+      })
+    );
+    assert_eq!(Hypermedia::expand("/x/{missing}", &user), "/x/{missing}");
+  }
+}
@@ -0,0 +1,190 @@
+use std::{
+  fmt::Display,
+  str::FromStr,
+  sync::atomic::{AtomicU64, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{now_millis, random_token, Error, ErrorKind, Value};
+
+/// Crockford's base 32 alphabet, as used by ULIDs
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+
+/// Milliseconds since 2010-11-04, the epoch of Twitter snowflakes
+const SNOWFLAKE_EPOCH: u128 = 1_288_834_974_657;
+
+/// How store routes generate the identifier of items created without one,
+/// written as a string in the `ids` route option:
+///
+/// - `uuid_v4`: a random UUID
+/// - `ulid`: a lexicographically sortable ULID
+/// - `sequence(start, step)`: the largest numeric identifier plus `step`, or
+///   `start` in an empty store; `sequence` alone counts from 1
+/// - `snowflake`: a 64-bit number ordered by creation time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum IdStrategy {
+  UuidV4,
+  Ulid,
+  Sequence { start: i128, step: i128 },
+  Snowflake,
+}
+
+impl IdStrategy {
+  /// Identifier of a new item, given those already `taken`
+  pub fn next<'a, I: IntoIterator<Item = &'a Value>>(&self, taken: I) -> Value {
+    match self {
+      Self::UuidV4 => Value::from(uuid_v4()),
+      Self::Ulid => Value::from(ulid()),
+      Self::Sequence { start, step } => {
+        let last = taken
+          .into_iter()
+          .filter_map(|id| match id {
+            Value::Integer(v) => Some(*v),
+            Value::Unsigned(v) => i128::try_from(*v).ok(),
+            Value::String(v) => v.parse().ok(),
+            _ => None,
+          })
+          .reduce(|a, b| match *step < 0 {
+            true => a.min(b),
+            false => a.max(b),
+          });
+        Value::Integer(last.map_or(*start, |last| last + step))
+      }
+      Self::Snowflake => Value::from(snowflake() as i128),
+    }
+  }
+}
+
+/// Random version 4 UUID, e.g. `7c9e6679-7425-40de-944b-e07fc1f90ae7`
+pub fn uuid_v4() -> String {
+  let bits = u128::from_str_radix(&random_token(), 16).unwrap_or_default();
+  let bits = (bits & !(0xf << 76) & !(0x3 << 62)) | (0x4 << 76) | (0x2 << 62);
+  let hex = format!("{:032x}", bits);
+  format!(
+    "{}-{}-{}-{}-{}",
+    &hex[..8],
+    &hex[8..12],
+    &hex[12..16],
+    &hex[16..20],
+    &hex[20..]
+  )
+}
+
+/// ULID of the current time: 26 characters, sorting by creation time
+pub fn ulid() -> String {
+  let random = u128::from_str_radix(&random_token(), 16).unwrap_or_default();
+  let bits = ((now_millis(0) & 0xffff_ffff_ffff) << 80) | (random & ((1 << 80) - 1));
+  (0..26)
+    .rev()
+    .map(|i| CROCKFORD[((bits >> (i * 5)) & 0x1f) as usize] as char)
+    .collect()
+}
+
+/// Snowflake of the current time: milliseconds since the snowflake epoch
+/// followed by a 22-bit sequence number
+pub fn snowflake() -> u64 {
+  static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+  let millis = now_millis(0).saturating_sub(SNOWFLAKE_EPOCH) as u64;
+  (millis << 22) | (SEQUENCE.fetch_add(1, Ordering::Relaxed) & 0x3f_ffff)
+}
+
+impl FromStr for IdStrategy {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = |why: &str| {
+      Error::new(
+        ErrorKind::Parse,
+        Some(format!("invalid id strategy '{}': {}", s, why)),
+        None,
+      )
+    };
+    let (name, args) = match s.trim().split_once('(') {
+      Some((name, args)) => match args.strip_suffix(')') {
+        Some(args) => (name.trim(), Some(args)),
+        None => return Err(invalid("missing ')'")),
+      },
+      None => (s.trim(), None),
+    };
+    match (name, args) {
+      ("uuid_v4", None) => Ok(Self::UuidV4),
+      ("ulid", None) => Ok(Self::Ulid),
+      ("snowflake", None) => Ok(Self::Snowflake),
+      ("sequence", None) => Ok(Self::Sequence { start: 1, step: 1 }),
+      ("sequence", Some(args)) => {
+        let args = args
+          .split(',')
+          .map(|arg| arg.trim().parse::<i128>())
+          .collect::<Result<Vec<_>, _>>()
+          .map_err(|e| invalid(&e.to_string()))?;
+        match args[..] {
+          [start] => Ok(Self::Sequence { start, step: 1 }),
+          [start, step] if step != 0 => Ok(Self::Sequence { start, step }),
+          _ => Err(invalid("expected sequence(start, step), step not being 0")),
+        }
+      }
+      _ => Err(invalid(
+        "expected uuid_v4, ulid, sequence(start, step) or snowflake",
+      )),
+    }
+  }
+}
+
+impl TryFrom<String> for IdStrategy {
+  type Error = Error;
+
+  fn try_from(value: String) -> Result<Self, Self::Error> {
+    value.parse()
+  }
+}
+
+impl Display for IdStrategy {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::UuidV4 => write!(f, "uuid_v4"),
+      Self::Ulid => write!(f, "ulid"),
+      Self::Sequence { start, step } => write!(f, "sequence({}, {})", start, step),
+      Self::Snowflake => write!(f, "snowflake"),
+    }
+  }
+}
+
+impl From<IdStrategy> for String {
+  fn from(value: IdStrategy) -> Self {
+    value.to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::Value;
+
+  use super::{snowflake, ulid, uuid_v4, IdStrategy};
+
+  #[test]
+  fn strategies() {
+    let uuid = uuid_v4();
+    assert_eq!(uuid.len(), 36);
+    assert_eq!(&uuid[14..15], "4");
+    assert!("89ab".contains(&uuid[19..20]), "{}", uuid);
+    assert_ne!(uuid, uuid_v4());
+    let id = ulid();
+    assert_eq!(id.len(), 26);
+    assert!(id[..10] <= ulid()[..10]);
+    assert!(snowflake() < snowflake());
+
+    let taken = [Value::from(3u64), Value::from("12"), Value::from("abc")];
+    let seq = "sequence(100, 10)".parse::<IdStrategy>().unwrap();
+    assert_eq!(seq.next(&taken), Value::Integer(22));
+    assert_eq!(seq.next(&[]), Value::Integer(100));
+    assert_eq!(
+      "sequence".parse::<IdStrategy>().unwrap(),
+      IdStrategy::Sequence { start: 1, step: 1 }
+    );
+    assert_eq!(seq.to_string().parse::<IdStrategy>().unwrap(), seq);
+    assert!("sequence(1, 0)".parse::<IdStrategy>().is_err());
+    assert!("uuid_v7".parse::<IdStrategy>().is_err());
+  }
+}
@@ -0,0 +1,168 @@
+use http::{HeaderMap, HeaderName, HeaderValue, StatusCode};
+
+use crate::{Buffer, Error, ErrorKind, Method, Request, Response, Status};
+
+fn interop_error<E: std::fmt::Display>(e: E) -> Error {
+  Error::new(ErrorKind::Parse, Some(e.to_string()), None)
+}
+
+impl From<Method> for http::Method {
+  fn from(value: Method) -> Self {
+    match value {
+      Method::Post => http::Method::POST,
+      Method::Get => http::Method::GET,
+      Method::Put => http::Method::PUT,
+      Method::Patch => http::Method::PATCH,
+      Method::Delete => http::Method::DELETE,
+      Method::Head => http::Method::HEAD,
+      Method::Options => http::Method::OPTIONS,
+    }
+  }
+}
+
+impl TryFrom<&http::Method> for Method {
+  type Error = Error;
+
+  fn try_from(value: &http::Method) -> crate::Result<Self> {
+    value.as_str().parse()
+  }
+}
+
+impl TryFrom<http::Method> for Method {
+  type Error = Error;
+
+  fn try_from(value: http::Method) -> crate::Result<Self> {
+    Method::try_from(&value)
+  }
+}
+
+impl From<Status> for StatusCode {
+  fn from(value: Status) -> Self {
+    StatusCode::from_u16(value.code()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+  }
+}
+
+impl TryFrom<StatusCode> for Status {
+  type Error = Error;
+
+  fn try_from(value: StatusCode) -> crate::Result<Self> {
+    Status::try_from(value.as_u16())
+  }
+}
+
+impl TryFrom<&Buffer> for HeaderMap {
+  type Error = Error;
+
+  fn try_from(value: &Buffer) -> crate::Result<Self> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in value.headers() {
+      headers.append(
+        HeaderName::try_from(name.as_str()).map_err(interop_error)?,
+        HeaderValue::try_from(value.as_str()).map_err(interop_error)?,
+      );
+    }
+    Ok(headers)
+  }
+}
+
+impl TryFrom<http::Request<Vec<u8>>> for Request {
+  type Error = Error;
+
+  fn try_from(value: http::Request<Vec<u8>>) -> crate::Result<Self> {
+    let (parts, body) = value.into_parts();
+    let target = parts
+      .uri
+      .path_and_query()
+      .map(|pq| pq.as_str())
+      .unwrap_or("/");
+    let mut req = Request::new(Method::try_from(&parts.method)?, target);
+    for (name, value) in &parts.headers {
+      req = req.with_header(name.as_str(), String::from_utf8_lossy(value.as_bytes()));
+    }
+    Ok(req.with_body_bytes(body))
+  }
+}
+
+impl TryFrom<Request> for http::Request<Vec<u8>> {
+  type Error = Error;
+
+  fn try_from(value: Request) -> crate::Result<Self> {
+    let method = value
+      .method()
+      .ok_or_else(|| interop_error("not a request"))?;
+    let target = value
+      .start_line()
+      .as_request()
+      .map(|s| s.target.clone())
+      .unwrap_or_default();
+    let mut req = http::Request::builder()
+      .method(http::Method::from(method))
+      .uri(target)
+      .body(value.body().to_vec())
+      .map_err(interop_error)?;
+    *req.headers_mut() = HeaderMap::try_from(&*value)?;
+    Ok(req)
+  }
+}
+
+impl TryFrom<http::Response<Vec<u8>>> for Response {
+  type Error = Error;
+
+  fn try_from(value: http::Response<Vec<u8>>) -> crate::Result<Self> {
+    let (parts, body) = value.into_parts();
+    let mut res = Response::default().with_status_code(parts.status.as_u16());
+    if let Some(reason) = parts.status.canonical_reason() {
+      res = res.with_reason(reason);
+    }
+    for (name, value) in &parts.headers {
+      res = res.with_header(name.as_str(), String::from_utf8_lossy(value.as_bytes()));
+    }
+    Ok(res.with_body_bytes(body))
+  }
+}
+
+impl TryFrom<Response> for http::Response<Vec<u8>> {
+  type Error = Error;
+
+  fn try_from(value: Response) -> crate::Result<Self> {
+    let mut res = http::Response::builder()
+      .status(StatusCode::from_u16(value.status()).map_err(interop_error)?)
+      .body(value.body().to_vec())
+      .map_err(interop_error)?;
+    *res.headers_mut() = HeaderMap::try_from(&*value)?;
+    Ok(res)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Request, Response, Status};
+
+  #[test]
+  fn round_trip() {
+    let req = Request::new(Method::Patch, "/users?id=1")
+      .with_header("Content-Type", "application/json")
+      .with_body("{}");
+    let converted = http::Request::<Vec<u8>>::try_from(req.clone()).unwrap();
+    assert_eq!(converted.method(), http::Method::PATCH);
+    assert_eq!(converted.uri(), "/users?id=1");
+    assert_eq!(converted.headers()["content-type"], "application/json");
+    let back = Request::try_from(converted).unwrap();
+    assert_eq!(back.path(), Some("/users"));
+    assert_eq!(back.body(), req.body());
+
+    let res = http::Response::builder()
+      .status(404)
+      .header("X-Id", "1")
+      .body(b"missing".to_vec())
+      .unwrap();
+    let res = Response::try_from(res).unwrap();
+    assert_eq!(res.status(), 404);
+    assert_eq!(res.header("x-id"), Some("1"));
+    assert_eq!(
+      http::StatusCode::from(Status::NotFound),
+      http::StatusCode::NOT_FOUND
+    );
+    assert!(Method::try_from(http::Method::CONNECT).is_err());
+  }
+}
@@ -0,0 +1,190 @@
+use std::{collections::HashMap, fmt::Display, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{now_millis, Error, ErrorKind, Method, Request, Route, Status};
+
+/// How many times a route is expected to be called.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Times {
+  Exactly(usize),
+  AtLeast(usize),
+  AtMost(usize),
+  Never,
+}
+
+impl Times {
+  pub fn matches(&self, count: usize) -> bool {
+    match self {
+      Self::Exactly(n) => count == *n,
+      Self::AtLeast(n) => count >= *n,
+      Self::AtMost(n) => count <= *n,
+      Self::Never => count == 0,
+    }
+  }
+}
+
+impl Display for Times {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Exactly(n) => write!(f, "exactly {} time(s)", n),
+      Self::AtLeast(n) => write!(f, "at least {} time(s)", n),
+      Self::AtMost(n) => write!(f, "at most {} time(s)", n),
+      Self::Never => write!(f, "never"),
+    }
+  }
+}
+
+/// A single call to a route, as seen by the router.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invocation {
+  pub method: Method,
+  pub target: String,
+  /// Milliseconds since the unix epoch
+  pub at: u128,
+}
+
+impl Invocation {
+  pub fn from_request(req: &Request) -> Self {
+    let (method, target) = match req.start_line().as_request() {
+      Some(start) => (start.method, start.target.clone()),
+      None => (Method::Get, String::from("/")),
+    };
+    Self {
+      method,
+      target,
+      at: now_millis(0),
+    }
+  }
+}
+
+impl Display for Invocation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} {} (at {})", self.method, self.target, self.at)
+  }
+}
+
+/// Per-route invocation registry, keyed by [`Router::keys`](crate::Router::keys).
+#[derive(Debug, Default)]
+pub struct Invocations(Mutex<HashMap<String, Vec<Invocation>>>);
+
+impl Invocations {
+  pub fn record(&self, key: &str, req: &Request) -> crate::Result<()> {
+    let mut g = self.0.lock()?;
+    g.entry(key.to_string())
+      .or_default()
+      .push(Invocation::from_request(req));
+    Ok(())
+  }
+
+  pub fn count(&self, key: &str) -> crate::Result<usize> {
+    let g = self.0.lock()?;
+    Ok(g.get(key).map(|calls| calls.len()).unwrap_or_default())
+  }
+
+  pub fn of(&self, key: &str) -> crate::Result<Vec<Invocation>> {
+    let g = self.0.lock()?;
+    Ok(g.get(key).cloned().unwrap_or_default())
+  }
+
+  pub fn all(&self) -> crate::Result<HashMap<String, Vec<Invocation>>> {
+    Ok(self.0.lock()?.clone())
+  }
+
+  pub fn reset(&self) -> crate::Result<()> {
+    self.0.lock()?.clear();
+    Ok(())
+  }
+
+  /// Check every route expectation, reporting all unmet ones at once.
+  /// Routes come along with their key.
+  pub fn verify<'a, I: IntoIterator<Item = (&'a String, &'a Route)>>(
+    &self,
+    routes: I,
+  ) -> crate::Result<()> {
+    let mut failures = vec![];
+    for (key, route) in routes.into_iter() {
+      let times = match route.options().expect {
+        Some(times) => times,
+        None => continue,
+      };
+      let calls = self.of(key)?;
+      if times.matches(calls.len()) {
+        continue;
+      }
+      let mut failure = format!(
+        "route `{}` expected to be called {} but was called {} time(s)",
+        key,
+        times,
+        calls.len()
+      );
+      for call in &calls {
+        failure.push_str(&format!("\n  - {}", call));
+      }
+      failures.push(failure);
+    }
+    if failures.is_empty() {
+      return Ok(());
+    }
+    Err(Error::new(
+      ErrorKind::Api(Status::ExpectationFailed),
+      Some(format!(
+        "{} unmet expectation(s):\n{}",
+        failures.len(),
+        failures.join("\n")
+      )),
+      None,
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+
+  use crate::{Method, Request, Route, RouteKind};
+
+  use super::{Invocations, Times};
+
+  #[test]
+  fn times() {
+    assert!(Times::Exactly(2).matches(2));
+    assert!(!Times::Exactly(2).matches(3));
+    assert!(Times::AtLeast(2).matches(3));
+    assert!(!Times::AtLeast(2).matches(1));
+    assert!(Times::AtMost(2).matches(0));
+    assert!(!Times::AtMost(2).matches(3));
+    assert!(Times::Never.matches(0));
+    assert!(!Times::Never.matches(1));
+  }
+
+  #[test]
+  fn verify() {
+    let route = Route::new(
+      [Method::Get],
+      "/users",
+      RouteKind::Store {
+        path: PathBuf::from("users.json"),
+        identifier: "id".to_string(),
+        parent: None,
+      },
+    )
+    .with_expect(Times::Exactly(2));
+    let invocations = Invocations::default();
+    let key = route.id();
+    invocations
+      .record(&key, &Request::new(Method::Get, "/users?id=1"))
+      .unwrap();
+    let err = invocations.verify([(&key, &route)]).unwrap_err();
+    let msg = err.message().unwrap();
+    assert!(msg.contains("expected to be called exactly 2 time(s) but was called 1 time(s)"));
+    assert!(msg.contains("GET /users?id=1"));
+    invocations
+      .record(&key, &Request::new(Method::Get, "/users?id=2"))
+      .unwrap();
+    assert!(invocations.verify([(&key, &route)]).is_ok());
+    invocations.reset().unwrap();
+    assert_eq!(invocations.count(&key).unwrap(), 0);
+  }
+}
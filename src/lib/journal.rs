@@ -0,0 +1,255 @@
+use std::{
+  collections::VecDeque,
+  sync::{
+    mpsc::{channel, Receiver, Sender},
+    Mutex,
+  },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{glob_match, now_millis, Error, ErrorKind, Method, Request, Response, Status, TlsInfo};
+
+/// A request received by the server, along with the status it was answered with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+  pub method: Method,
+  pub path: String,
+  pub target: String,
+  pub headers: Vec<(String, String)>,
+  pub body: String,
+  pub status: Option<u16>,
+  /// Milliseconds since the unix epoch
+  pub at: u128,
+  /// Connection details, for requests that came over TLS
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub tls: Option<TlsInfo>,
+}
+
+impl JournalEntry {
+  pub fn new(req: &Request, res: Option<&Response>) -> Self {
+    let (method, target) = match req.start_line().as_request() {
+      Some(start) => (start.method, start.target.clone()),
+      None => (Method::Get, String::from("/")),
+    };
+    Self {
+      method,
+      path: req.path().unwrap_or("/").to_string(),
+      target,
+      headers: req.owned_headers(),
+      body: String::from_utf8_lossy(req.body()).to_string(),
+      status: res
+        .and_then(|res| res.start_line().as_response())
+        .map(|start| start.status),
+      at: now_millis(0),
+      tls: req.extensions().get::<TlsInfo>().cloned(),
+    }
+  }
+
+  pub fn header<K: AsRef<str>>(&self, k: K) -> Option<&String> {
+    self
+      .headers
+      .iter()
+      .find(|(key, _value)| key.eq_ignore_ascii_case(k.as_ref()))
+      .map(|(_key, value)| value)
+  }
+}
+
+/// Filters applied when querying the journal, all optional.
+#[derive(Debug, Default, Clone)]
+pub struct JournalQuery {
+  pub method: Option<Method>,
+  /// Glob pattern matched against the request path
+  pub path: Option<String>,
+  /// Header name, optionally with the exact value it must have
+  pub header: Option<(String, Option<String>)>,
+  pub since: Option<u128>,
+  pub until: Option<u128>,
+}
+
+impl JournalQuery {
+  /// Build a query from `method`, `path`, `header` (`Name` or `Name:value`),
+  /// `since` and `until` (unix milliseconds) query parameters.
+  pub fn from_request(req: &Request) -> crate::Result<Self> {
+    let mut ret = Self::default();
+    for (key, value) in req.query_params() {
+      let value = match value {
+        Some(v) => v,
+        None => continue,
+      };
+      match key.to_ascii_lowercase().as_str() {
+        "method" => ret.method = Some(value.parse()?),
+        "path" => ret.path = Some(value),
+        "header" => {
+          ret.header = Some(match value.split_once(':') {
+            Some((name, value)) => (name.trim().to_string(), Some(value.trim().to_string())),
+            None => (value.trim().to_string(), None),
+          })
+        }
+        "since" => ret.since = Some(Self::parse_time(&value)?),
+        "until" => ret.until = Some(Self::parse_time(&value)?),
+        _ => {}
+      }
+    }
+    Ok(ret)
+  }
+
+  fn parse_time(value: &str) -> crate::Result<u128> {
+    value.parse::<u128>().map_err(|e| {
+      Error::new(
+        ErrorKind::Api(Status::BadRequest),
+        Some(format!("invalid timestamp '{}': {}", value, e)),
+        None,
+      )
+    })
+  }
+
+  pub fn matches(&self, entry: &JournalEntry) -> bool {
+    if let Some(method) = self.method {
+      if method != entry.method {
+        return false;
+      }
+    }
+    if let Some(path) = self.path.as_ref() {
+      if !glob_match(path, &entry.path) {
+        return false;
+      }
+    }
+    if let Some((name, value)) = self.header.as_ref() {
+      match (entry.header(name), value) {
+        (None, _) => return false,
+        (Some(actual), Some(expected)) if actual != expected => return false,
+        _ => {}
+      }
+    }
+    if self.since.is_some_and(|since| entry.at < since) {
+      return false;
+    }
+    if self.until.is_some_and(|until| entry.at > until) {
+      return false;
+    }
+    true
+  }
+}
+
+/// Bounded log of received requests, oldest entries are evicted first.
+#[derive(Debug)]
+pub struct Journal {
+  entries: Mutex<VecDeque<JournalEntry>>,
+  limit: usize,
+  /// Live listeners, receiving every recorded entry as json
+  subscribers: Mutex<Vec<Sender<String>>>,
+}
+
+impl Default for Journal {
+  fn default() -> Self {
+    Self::new(Self::DEFAULT_LIMIT)
+  }
+}
+
+impl Journal {
+  pub const DEFAULT_LIMIT: usize = 1000;
+
+  pub fn new(limit: usize) -> Self {
+    Self {
+      entries: Mutex::new(VecDeque::new()),
+      limit,
+      subscribers: Mutex::new(vec![]),
+    }
+  }
+
+  pub fn limit(&self) -> usize {
+    self.limit
+  }
+
+  /// Receive every entry recorded from now on, encoded as json
+  pub fn subscribe(&self) -> crate::Result<Receiver<String>> {
+    let (tx, rx) = channel();
+    self.subscribers.lock()?.push(tx);
+    Ok(rx)
+  }
+
+  pub fn record(&self, entry: JournalEntry) -> crate::Result<()> {
+    #[cfg(feature = "json")]
+    {
+      let mut subscribers = self.subscribers.lock()?;
+      if !subscribers.is_empty() {
+        let data = serde_json::to_string(&entry)?;
+        subscribers.retain(|tx| tx.send(data.clone()).is_ok());
+      }
+    }
+    if self.limit == 0 {
+      return Ok(());
+    }
+    let mut g = self.entries.lock()?;
+    while g.len() >= self.limit {
+      g.pop_front();
+    }
+    g.push_back(entry);
+    Ok(())
+  }
+
+  pub fn query(&self, query: &JournalQuery) -> crate::Result<Vec<JournalEntry>> {
+    let g = self.entries.lock()?;
+    Ok(g.iter().filter(|e| query.matches(e)).cloned().collect())
+  }
+
+  pub fn len(&self) -> crate::Result<usize> {
+    Ok(self.entries.lock()?.len())
+  }
+
+  pub fn is_empty(&self) -> crate::Result<bool> {
+    Ok(self.entries.lock()?.is_empty())
+  }
+
+  pub fn clear(&self) -> crate::Result<()> {
+    self.entries.lock()?.clear();
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Request};
+
+  use super::{Journal, JournalEntry, JournalQuery};
+
+  #[test]
+  fn retention() {
+    let journal = Journal::new(2);
+    for id in 0..3 {
+      let req = Request::new(Method::Get, format!("/users/{}", id));
+      journal.record(JournalEntry::new(&req, None)).unwrap();
+    }
+    let entries = journal.query(&JournalQuery::default()).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].path, "/users/1");
+  }
+
+  #[test]
+  fn query() {
+    let journal = Journal::default();
+    let req = Request::new(Method::Get, "/users/42?verbose").with_header("X-Api-Key", "secret");
+    journal.record(JournalEntry::new(&req, None)).unwrap();
+    let req = Request::new(Method::Post, "/orders");
+    journal.record(JournalEntry::new(&req, None)).unwrap();
+
+    let q = JournalQuery::from_request(&Request::new(
+      Method::Get,
+      "/__mocker/requests?path=/users/*&header=X-Api-Key:secret",
+    ))
+    .unwrap();
+    let found = journal.query(&q).unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].target, "/users/42?verbose");
+
+    let q = JournalQuery::from_request(&Request::new(Method::Get, "/?method=POST")).unwrap();
+    assert_eq!(journal.query(&q).unwrap()[0].path, "/orders");
+
+    let q = JournalQuery::from_request(&Request::new(Method::Get, "/?header=X-Other")).unwrap();
+    assert!(journal.query(&q).unwrap().is_empty());
+
+    let q = JournalQuery::from_request(&Request::new(Method::Get, "/?since=0&until=1")).unwrap();
+    assert!(journal.query(&q).unwrap().is_empty());
+  }
+}
@@ -0,0 +1,475 @@
+use std::{
+  collections::HashMap,
+  fs::{File, OpenOptions},
+  io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+  path::{Path, PathBuf},
+  time::SystemTime,
+};
+
+use log::debug;
+
+use crate::{merge_patch, render_value, Error, ErrorKind, Status, Value};
+
+type Item = HashMap<String, Value>;
+
+/// Bytes of an item's line in the data file, newline excluded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+  offset: u64,
+  len: u64,
+}
+
+/// Store over a newline-delimited JSON file too big to be loaded at once.
+///
+/// Only the position of each item is kept in memory, in an index persisted
+/// next to the data file (`users.ndjson.idx`) so that restarting does not
+/// require a full scan. Items are read from disk when requested.
+///
+/// Writes keep the data file valid NDJSON on its own: removed items are
+/// blanked out in place, updated items are rewritten in place when they fit
+/// and moved to the end otherwise. [`LazyStore::compact`] reclaims the space.
+#[derive(Debug)]
+pub struct LazyStore {
+  path: PathBuf,
+  identifier: String,
+  /// Live items in file order, `None` once removed
+  slots: Vec<Option<(String, Span)>>,
+  /// Slot of each item, by rendered identifier
+  ids: HashMap<String, usize>,
+  live: usize,
+  /// Length and modification time of the data file as last seen, to notice
+  /// edits made behind our back
+  seen: (u64, Option<SystemTime>),
+}
+
+impl LazyStore {
+  pub fn open<P: AsRef<Path>, I: AsRef<str>>(path: P, identifier: I) -> crate::Result<Self> {
+    let mut ret = Self {
+      path: path.as_ref().to_path_buf(),
+      identifier: identifier.as_ref().to_string(),
+      slots: vec![],
+      ids: HashMap::new(),
+      live: 0,
+      seen: (0, None),
+    };
+    ret.load_index()?;
+    Ok(ret)
+  }
+
+  /// Index file kept next to `path`
+  pub fn index_path_for<P: AsRef<Path>>(path: P) -> PathBuf {
+    let mut name = path.as_ref().as_os_str().to_owned();
+    name.push(".idx");
+    PathBuf::from(name)
+  }
+
+  pub fn path(&self) -> &PathBuf {
+    &self.path
+  }
+
+  pub fn identifier(&self) -> &String {
+    &self.identifier
+  }
+
+  pub fn len(&self) -> usize {
+    self.live
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.live == 0
+  }
+
+  fn metadata(&self) -> crate::Result<(u64, Option<SystemTime>)> {
+    let meta = std::fs::metadata(&self.path)?;
+    Ok((meta.len(), meta.modified().ok()))
+  }
+
+  /// Rebuild the index if the data file was changed by someone else
+  pub fn refresh(&mut self) -> crate::Result<()> {
+    if self.metadata()? != self.seen {
+      debug!("{} changed, reindexing", self.path.display());
+      self.reindex()?;
+    }
+    Ok(())
+  }
+
+  fn key(&self, item: &Item) -> Option<String> {
+    item
+      .iter()
+      .find(|(k, _)| k.eq_ignore_ascii_case(&self.identifier))
+      .map(|(_, v)| render_value(v))
+  }
+
+  fn clear(&mut self) {
+    self.slots.clear();
+    self.ids.clear();
+    self.live = 0;
+  }
+
+  /// Record `span` as the current line of `key`, keeping its place in the
+  /// order unless it moved
+  fn upsert(&mut self, key: String, span: Span) {
+    if let Some(slot) = self.ids.get(&key).copied() {
+      if let Some((_, old)) = &mut self.slots[slot] {
+        if old.offset == span.offset {
+          *old = span;
+          return;
+        }
+      }
+      self.slots[slot] = None;
+      self.live -= 1;
+    }
+    self.ids.insert(key.clone(), self.slots.len());
+    self.slots.push(Some((key, span)));
+    self.live += 1;
+  }
+
+  fn forget(&mut self, key: &str) -> Option<Span> {
+    let slot = self.ids.remove(key)?;
+    self.live -= 1;
+    self.slots[slot].take().map(|(_, span)| span)
+  }
+
+  /// Load the persisted index, or rebuild it when missing or outdated
+  fn load_index(&mut self) -> crate::Result<()> {
+    let seen = self.metadata()?;
+    let index_path = Self::index_path_for(&self.path);
+    let fresh = std::fs::metadata(&index_path)
+      .and_then(|meta| meta.modified())
+      .ok()
+      .zip(seen.1)
+      .is_some_and(|(index, data)| index >= data);
+    if !fresh {
+      return self.reindex();
+    }
+    self.clear();
+    for line in BufReader::new(File::open(&index_path)?).lines() {
+      let line = line?;
+      let mut fields = line.splitn(4, '\t');
+      match (fields.next(), fields.next(), fields.next(), fields.next()) {
+        (Some("+"), Some(offset), Some(len), Some(key)) => {
+          let span = Span {
+            offset: offset.parse()?,
+            len: len.parse()?,
+          };
+          if span.offset + span.len > seen.0 {
+            return self.reindex();
+          }
+          self.upsert(key.to_string(), span);
+        }
+        (Some("-"), Some(key), None, None) => {
+          self.forget(key);
+        }
+        _ => return self.reindex(),
+      }
+    }
+    self.seen = seen;
+    Ok(())
+  }
+
+  /// Scan the data file and write a fresh index
+  pub fn reindex(&mut self) -> crate::Result<()> {
+    self.clear();
+    let mut reader = BufReader::new(File::open(&self.path)?);
+    let (mut offset, mut line) = (0u64, String::new());
+    loop {
+      line.clear();
+      let read = reader.read_line(&mut line)? as u64;
+      if read == 0 {
+        break;
+      }
+      let content = line.trim_end_matches(['\r', '\n']);
+      if !content.trim().is_empty() {
+        let item = Self::parse(content.as_bytes())?;
+        let key = self.key(&item).ok_or_else(|| self.missing_identifier())?;
+        let len = content.len() as u64;
+        self.upsert(key, Span { offset, len });
+      }
+      offset += read;
+    }
+    let mut index = BufWriter::new(File::create(Self::index_path_for(&self.path))?);
+    for (key, span) in self.slots.iter().flatten() {
+      writeln!(index, "+\t{}\t{}\t{}", span.offset, span.len, key)?;
+    }
+    index.flush()?;
+    self.seen = self.metadata()?;
+    Ok(())
+  }
+
+  fn missing_identifier(&self) -> Error {
+    Error::new(
+      ErrorKind::Api(Status::BadRequest),
+      Some(format!("missing `{}` field in object", self.identifier)),
+      None,
+    )
+  }
+
+  fn parse(line: &[u8]) -> crate::Result<Item> {
+    let data: HashMap<String, serde_json::Value> = serde_json::from_slice(line)?;
+    data
+      .into_iter()
+      .map(|(k, v)| Ok((k, Value::try_from_json(v)?)))
+      .collect()
+  }
+
+  fn read(file: &mut File, span: Span) -> crate::Result<Item> {
+    let mut buf = vec![0; span.len as usize];
+    file.seek(SeekFrom::Start(span.offset))?;
+    file.read_exact(&mut buf)?;
+    Self::parse(&buf)
+  }
+
+  pub fn find(&self, id: &Value) -> crate::Result<Option<Item>> {
+    let span = match self.ids.get(&render_value(id)) {
+      Some(slot) => match &self.slots[*slot] {
+        Some((_, span)) => *span,
+        None => return Ok(None),
+      },
+      None => return Ok(None),
+    };
+    Ok(Some(Self::read(&mut File::open(&self.path)?, span)?))
+  }
+
+  /// Up to `limit` items, skipping the first `offset` ones
+  pub fn page(&self, offset: usize, limit: usize) -> crate::Result<Vec<Item>> {
+    let mut file = File::open(&self.path)?;
+    self
+      .slots
+      .iter()
+      .flatten()
+      .skip(offset)
+      .take(limit)
+      .map(|(_, span)| Self::read(&mut file, *span))
+      .collect()
+  }
+
+  /// Every item, read one at a time
+  pub fn iter(&self) -> crate::Result<impl Iterator<Item = crate::Result<Item>> + '_> {
+    let mut file = File::open(&self.path)?;
+    Ok(
+      self
+        .slots
+        .iter()
+        .flatten()
+        .map(move |(_, span)| Self::read(&mut file, *span)),
+    )
+  }
+
+  /// Append `item` as a new line, returning its span
+  fn append(&mut self, item: &Item) -> crate::Result<Span> {
+    let line = Self::serialize(item)?;
+    let mut file = OpenOptions::new().append(true).open(&self.path)?;
+    let mut offset = file.seek(SeekFrom::End(0))?;
+    if offset > 0 {
+      let mut last = [0u8];
+      let mut reader = File::open(&self.path)?;
+      reader.seek(SeekFrom::Start(offset - 1))?;
+      reader.read_exact(&mut last)?;
+      if last[0] != b'\n' {
+        file.write_all(b"\n")?;
+        offset += 1;
+      }
+    }
+    file.write_all(line.as_bytes())?;
+    file.write_all(b"\n")?;
+    Ok(Span {
+      offset,
+      len: line.len() as u64,
+    })
+  }
+
+  /// Overwrite the bytes of `span` with `line`, padded with spaces
+  fn overwrite(&self, span: Span, line: &[u8]) -> crate::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(&self.path)?;
+    file.seek(SeekFrom::Start(span.offset))?;
+    file.write_all(line)?;
+    file.write_all(&vec![b' '; span.len as usize - line.len()])?;
+    Ok(())
+  }
+
+  fn serialize(item: &Item) -> crate::Result<String> {
+    let json = item
+      .iter()
+      .map(|(k, v)| (k.clone(), v.to_json()))
+      .collect::<serde_json::Map<_, _>>();
+    Ok(serde_json::to_string(&json)?)
+  }
+
+  /// Persist the latest changes to the index
+  fn log(&mut self, records: &[String]) -> crate::Result<()> {
+    let mut index = OpenOptions::new()
+      .append(true)
+      .create(true)
+      .open(Self::index_path_for(&self.path))?;
+    for record in records {
+      writeln!(index, "{}", record)?;
+    }
+    self.seen = self.metadata()?;
+    Ok(())
+  }
+
+  pub fn create(&mut self, item: Item) -> crate::Result<()> {
+    let key = self.key(&item).ok_or_else(|| self.missing_identifier())?;
+    if self.ids.contains_key(&key) {
+      return Err(Error::new(
+        ErrorKind::Api(Status::Conflict),
+        Some(format!(
+          "entity with `{}`={} already exists",
+          self.identifier, key
+        )),
+        None,
+      ));
+    }
+    let span = self.append(&item)?;
+    self.upsert(key.clone(), span);
+    self.log(&[format!("+\t{}\t{}\t{}", span.offset, span.len, key)])
+  }
+
+  /// Replace the fields of the entity identified by `id` with those of
+  /// `obj`, or apply `obj` as a JSON merge patch when `merge`-ing, see
+  /// [`Value::merge_patch`]. The identifier is kept.
+  pub fn update(&mut self, id: &Value, mut obj: Item, merge: bool) -> crate::Result<Option<Item>> {
+    let mut item = match self.find(id)? {
+      Some(item) => item,
+      None => return Ok(None),
+    };
+    let key = render_value(id);
+    let old = self
+      .ids
+      .get(&key)
+      .and_then(|slot| self.slots[*slot].as_ref());
+    let old = old
+      .map(|(_, span)| *span)
+      .unwrap_or(Span { offset: 0, len: 0 });
+    let identifier = self.identifier.clone();
+    obj.retain(|k, _| !k.eq_ignore_ascii_case(&identifier));
+    match merge {
+      true => merge_patch(&mut item, obj),
+      false => {
+        item.retain(|k, _| k.eq_ignore_ascii_case(&identifier));
+        item.extend(obj);
+      }
+    }
+    let line = Self::serialize(&item)?;
+    let span = match line.len() as u64 <= old.len {
+      true => {
+        self.overwrite(old, line.as_bytes())?;
+        old
+      }
+      false => {
+        self.overwrite(old, b"")?;
+        self.append(&item)?
+      }
+    };
+    self.upsert(key.clone(), span);
+    self.log(&[format!("+\t{}\t{}\t{}", span.offset, span.len, key)])?;
+    Ok(Some(item))
+  }
+
+  pub fn remove(&mut self, id: &Value) -> crate::Result<Option<Item>> {
+    let item = match self.find(id)? {
+      Some(item) => item,
+      None => return Ok(None),
+    };
+    let key = render_value(id);
+    if let Some(span) = self.forget(&key) {
+      self.overwrite(span, b"")?;
+    }
+    self.log(&[format!("-\t{}", key)])?;
+    Ok(Some(item))
+  }
+
+  /// Rewrite the data file without the space left by removed and moved
+  /// items
+  pub fn compact(&mut self) -> crate::Result<()> {
+    let tmp = self.path.with_extension("compacting");
+    {
+      let mut out = BufWriter::new(File::create(&tmp)?);
+      let mut file = File::open(&self.path)?;
+      for (_, span) in self.slots.iter().flatten() {
+        let mut buf = vec![0; span.len as usize];
+        file.seek(SeekFrom::Start(span.offset))?;
+        file.read_exact(&mut buf)?;
+        out.write_all(String::from_utf8_lossy(&buf).trim_end().as_bytes())?;
+        out.write_all(b"\n")?;
+      }
+      out.flush()?;
+    }
+    std::fs::rename(&tmp, &self.path)?;
+    self.reindex()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use crate::Value;
+
+  use super::LazyStore;
+
+  #[test]
+  fn lazy() {
+    let dir = std::env::temp_dir().join(format!("mocker-lazy-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("users.ndjson");
+    let lines = (1..=5)
+      .map(|id| format!(r#"{{"id": {}, "name": "user {}"}}"#, id, id))
+      .collect::<Vec<_>>();
+    std::fs::write(&path, lines.join("\n")).unwrap();
+    let item = |id: u64, name: &str| {
+      HashMap::from([
+        ("id".to_string(), Value::from(id)),
+        ("name".to_string(), Value::from(name)),
+      ])
+    };
+
+    let mut store = LazyStore::open(&path, "id").unwrap();
+    assert!(LazyStore::index_path_for(&path).exists());
+    assert_eq!(store.len(), 5);
+    assert_eq!(
+      store.find(&Value::from(3u64)).unwrap(),
+      Some(item(3, "user 3"))
+    );
+    let names = |page: Vec<HashMap<String, Value>>| {
+      page
+        .into_iter()
+        .map(|item| item["id"].clone())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      names(store.page(3, 10).unwrap()),
+      [Value::from(4u64), Value::from(5u64)]
+    );
+
+    store.create(item(6, "user 6")).unwrap();
+    assert!(store.create(item(6, "again")).is_err());
+    let short = HashMap::from([("name".to_string(), Value::from("ada"))]);
+    store.update(&Value::from(1u64), short, true).unwrap();
+    let long = HashMap::from([("name".to_string(), Value::from("grace brewster hopper"))]);
+    store.update(&Value::from(2u64), long, false).unwrap();
+    assert_eq!(
+      store.remove(&Value::from(4u64)).unwrap(),
+      Some(item(4, "user 4"))
+    );
+    assert_eq!(store.remove(&Value::from(4u64)).unwrap(), None);
+
+    let expected = [1u64, 3, 5, 6, 2].map(Value::from);
+    assert_eq!(names(store.page(0, 10).unwrap()), expected);
+    let reopened = LazyStore::open(&path, "id").unwrap();
+    assert_eq!(names(reopened.page(0, 10).unwrap()), expected);
+    std::fs::remove_file(LazyStore::index_path_for(&path)).unwrap();
+    let mut rebuilt = LazyStore::open(&path, "id").unwrap();
+    assert_eq!(names(rebuilt.page(0, 10).unwrap()), expected);
+    assert_eq!(
+      rebuilt.find(&Value::from(1u64)).unwrap(),
+      Some(item(1, "ada"))
+    );
+
+    rebuilt.compact().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 5);
+    let all = rebuilt.iter().unwrap().collect::<crate::Result<Vec<_>>>();
+    assert_eq!(names(all.unwrap()), expected);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,423 @@
+use std::{
+  collections::{BTreeSet, HashMap},
+  fmt::Display,
+  path::{Path, PathBuf},
+};
+
+use regex::Regex;
+
+use crate::{schema_diff, Config, RouteKind, SchemaChange, Value};
+
+/// A likely mistake found in a workspace, optionally tied to a route.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+  pub route: Option<String>,
+  pub message: String,
+}
+
+impl Display for Lint {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match &self.route {
+      Some(route) => write!(f, "{}: {}", route, self.message),
+      None => write!(f, "{}", self.message),
+    }
+  }
+}
+
+/// Detects configuration mistakes: routes a workspace would refuse to load,
+/// unreachable routes, conflicting stores, missing script
+/// functions, fixtures drifting from their schema and unused fixture files. Paths resolve against `dir`.
+pub struct Linter<'a> {
+  config: &'a Config,
+  dir: PathBuf,
+}
+
+impl<'a> Linter<'a> {
+  pub fn new<P: AsRef<Path>>(config: &'a Config, dir: P) -> Self {
+    Self {
+      config,
+      dir: dir.as_ref().to_path_buf(),
+    }
+  }
+
+  pub fn lint(&self) -> Vec<Lint> {
+    let mut lints = vec![];
+    self.invalid_routes(&mut lints);
+    self.shadowed_routes(&mut lints);
+    #[cfg(feature = "json")]
+    self.shared_stores(&mut lints);
+    #[cfg(feature = "json")]
+    self.nested_stores(&mut lints);
+    #[cfg(feature = "js")]
+    self.script_functions(&mut lints);
+    self.fixture_schemas(&mut lints);
+    self.unused_fixtures(&mut lints);
+    lints
+  }
+
+  /// Routes which cannot be served as declared, see
+  /// [`Config::route_problems`]
+  fn invalid_routes(&self, lints: &mut Vec<Lint>) {
+    for (route, message) in self.config.route_problems() {
+      lints.push(Lint {
+        route: Some(route),
+        message,
+      });
+    }
+  }
+
+  /// Routes declared after one serving the same endpoint and methods
+  /// unconditionally (no scenario state required) can never be reached.
+  fn shadowed_routes(&self, lints: &mut Vec<Lint>) {
+    let routes = &self.config.routes;
+    for (i, route) in routes.iter().enumerate() {
+      let mut shadowed = BTreeSet::new();
+      for earlier in &routes[..i] {
+        let conditional = earlier
+          .options()
+          .scenario
+          .as_ref()
+          .is_some_and(|s| s.state.is_some());
+        if conditional || earlier.endpoint() != route.endpoint() {
+          continue;
+        }
+        for method in route.methods() {
+          if earlier.methods().contains(method) {
+            shadowed.insert(method.to_string());
+          }
+        }
+      }
+      if !shadowed.is_empty() {
+        lints.push(Lint {
+          route: Some(route.id()),
+          message: format!(
+            "unreachable for {}, an earlier route serves the same requests",
+            shadowed.into_iter().collect::<Vec<_>>().join(", ")
+          ),
+        });
+      }
+    }
+  }
+
+  /// Store routes backed by the same file must agree on the identifier
+  #[cfg(feature = "json")]
+  fn shared_stores(&self, lints: &mut Vec<Lint>) {
+    let mut stores: HashMap<PathBuf, BTreeSet<String>> = HashMap::new();
+    for route in &self.config.routes {
+      if let RouteKind::Store {
+        path, identifier, ..
+      } = route.kind()
+      {
+        stores
+          .entry(self.dir.join(path))
+          .or_default()
+          .insert(identifier.clone());
+      }
+    }
+    let mut paths = stores.keys().collect::<Vec<_>>();
+    paths.sort();
+    for path in paths {
+      let identifiers = &stores[path];
+      if identifiers.len() > 1 {
+        lints.push(Lint {
+          route: None,
+          message: format!(
+            "{} is used by stores with different identifiers: {}",
+            path.display(),
+            identifiers.iter().cloned().collect::<Vec<_>>().join(", ")
+          ),
+        });
+      }
+    }
+  }
+
+  /// Nested store routes must capture the parent identifier in their path
+  #[cfg(feature = "json")]
+  fn nested_stores(&self, lints: &mut Vec<Lint>) {
+    for route in &self.config.routes {
+      if let RouteKind::Store {
+        parent: Some(parent),
+        ..
+      } = route.kind()
+      {
+        let param = parent.param();
+        let captured = route
+          .endpoint()
+          .split('/')
+          .any(|s| s.strip_prefix(':') == Some(param) || s == format!("{{{}}}", param));
+        if !captured {
+          lints.push(Lint {
+            route: Some(route.id()),
+            message: format!("the path has no `{}` parameter for the parent", param),
+          });
+        }
+      }
+    }
+  }
+
+  /// Script routes must point to a script defining the declared function
+  #[cfg(feature = "js")]
+  fn script_functions(&self, lints: &mut Vec<Lint>) {
+    for route in &self.config.routes {
+      if let RouteKind::Script { script, func } = route.kind() {
+        let message = match std::fs::read_to_string(self.dir.join(script)) {
+          Err(e) => Some(format!("cannot read {}: {}", script.display(), e)),
+          Ok(source) if !Self::defines(&source, func) => Some(format!(
+            "{} does not define function `{}`",
+            script.display(),
+            func
+          )),
+          Ok(_) => None,
+        };
+        if let Some(message) = message {
+          lints.push(Lint {
+            route: Some(route.id()),
+            message,
+          });
+        }
+      }
+    }
+  }
+
+  /// Whether a script source defines or exports `func`
+  pub fn defines(source: &str, func: &str) -> bool {
+    let func = regex::escape(func);
+    let patterns = [
+      format!(r"\bfunction\s*\*?\s*{}\s*\(", func),
+      format!(r"\b(const|let|var)\s+{}\s*=", func),
+      format!(r"\bexports\.{}\s*=", func),
+      format!(r"\bexport\s+(async\s+)?function\s+{}\b", func),
+    ];
+    patterns
+      .iter()
+      .any(|p| Regex::new(p).is_ok_and(|re| re.is_match(source)))
+  }
+
+  /// Inline fixture bodies must have the shape their route's schema
+  /// declares. Fields the schema lists but the body lacks may be optional,
+  /// so only unexpected fields and types are reported.
+  fn fixture_schemas(&self, lints: &mut Vec<Lint>) {
+    for route in &self.config.routes {
+      let (body, schema) = match (route.kind(), &route.options().schema) {
+        (
+          RouteKind::Fixture {
+            body: Some(body), ..
+          },
+          Some(schema),
+        ) if !matches!(body, Value::String(_)) => (body, schema),
+        _ => continue,
+      };
+      for change in schema_diff("body", schema, &body.infer_schema()) {
+        let message = match change {
+          SchemaChange::Added(path) => format!("`{}` is not described by the schema", path),
+          SchemaChange::Removed(_) => continue,
+          SchemaChange::TypeChanged { path, from, to } => {
+            format!(
+              "`{}` is {} in the fixture but {} in the schema",
+              path, to, from
+            )
+          }
+        };
+        lints.push(Lint {
+          route: Some(route.id()),
+          message,
+        });
+      }
+    }
+  }
+
+  /// Files lying next to fixture files (or in a `fixtures` directory)
+  /// which no route references
+  fn unused_fixtures(&self, lints: &mut Vec<Lint>) {
+    let used = self
+      .config
+      .routes
+      .iter()
+      .filter_map(|route| match route.kind() {
+        RouteKind::Fixture {
+          file: Some(file), ..
+        } => Some(self.dir.join(file)),
+        _ => None,
+      })
+      .collect::<BTreeSet<_>>();
+    let mut dirs = used
+      .iter()
+      .filter_map(|file| file.parent().map(Path::to_path_buf))
+      .filter(|dir| *dir != self.dir)
+      .collect::<BTreeSet<_>>();
+    dirs.insert(self.dir.join("fixtures"));
+    for dir in dirs {
+      let mut files = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries
+          .filter_map(|e| e.ok().map(|e| e.path()))
+          .filter(|p| p.is_file())
+          .collect::<Vec<_>>(),
+        Err(_) => continue,
+      };
+      files.sort();
+      for file in files {
+        if !used.contains(&file) {
+          lints.push(Lint {
+            route: None,
+            message: format!("{} is not used by any route", file.display()),
+          });
+        }
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use crate::{Config, Method, Route, RouteKind, Value};
+
+  use super::Linter;
+
+  #[test]
+  fn shadowed() {
+    let fixture = || RouteKind::Fixture {
+      status: 200,
+      headers: Default::default(),
+      body: None,
+      file: None,
+      template: false,
+    };
+    let config = Config {
+      routes: vec![
+        Route::new(vec![Method::Get], "/users", fixture()),
+        Route::new(vec![Method::Get, Method::Post], "/users", fixture()),
+      ],
+      ..Default::default()
+    };
+    let lints = Linter::new(&config, "/nonexistent").lint();
+    assert_eq!(lints.len(), 1);
+    assert_eq!(lints[0].route.as_deref(), Some("GET,POST /users"));
+    assert!(lints[0].message.contains("unreachable for GET"));
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn lazy_sessions() {
+    let mut route = Route::new(
+      vec![Method::Get],
+      "/users",
+      RouteKind::Store {
+        path: "users.ndjson".into(),
+        identifier: "id".to_string(),
+        parent: None,
+      },
+    );
+    route.options_mut().lazy = true;
+    route.options_mut().session_header = Some("X-Session".to_string());
+    let mut config = Config {
+      routes: vec![route],
+      ..Default::default()
+    };
+    let messages = |config: &Config| {
+      Linter::new(config, "/nonexistent")
+        .lint()
+        .into_iter()
+        .map(|lint| lint.message)
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(
+      messages(&config),
+      vec!["lazy stores cannot keep sessions apart, remove `session_header`"]
+    );
+    assert!(config.validate().is_err());
+    config.routes[0].options_mut().session_header = None;
+    assert!(config.validate().is_ok());
+    config.tenancy = Some(Default::default());
+    assert_eq!(
+      messages(&config),
+      vec!["lazy stores cannot keep tenants apart, remove `lazy`"]
+    );
+  }
+
+  #[test]
+  fn invalid_regex() {
+    let fixture = RouteKind::Fixture {
+      status: 200,
+      headers: Default::default(),
+      body: None,
+      file: None,
+      template: false,
+    };
+    let config = Config {
+      routes: vec![Route::new(vec![Method::Get], "~^/users/(", fixture)],
+      ..Default::default()
+    };
+    let lints = Linter::new(&config, "/nonexistent").lint();
+    assert_eq!(lints.len(), 1);
+    assert!(lints[0].message.contains("invalid endpoint regex"));
+    assert!(config.validate().is_err());
+    assert!(crate::Router::default()
+      .add(config.routes[0].clone())
+      .is_err());
+  }
+
+  #[test]
+  fn fixture_schema() {
+    let mut route = Route::new(
+      vec![Method::Get],
+      "/users",
+      RouteKind::Fixture {
+        status: 200,
+        headers: Default::default(),
+        body: Some(Value::from(vec![Value::from(HashMap::from([
+          ("id".to_string(), Value::from("1")),
+          ("role".to_string(), Value::from("admin")),
+        ]))])),
+        file: None,
+        template: false,
+      },
+    );
+    route.options_mut().schema = Some(Value::from(HashMap::from([
+      ("type".to_string(), Value::from("array")),
+      (
+        "items".to_string(),
+        Value::from(HashMap::from([(
+          "properties".to_string(),
+          Value::from(HashMap::from([
+            (
+              "id".to_string(),
+              Value::from(HashMap::from([(
+                "type".to_string(),
+                Value::from("integer"),
+              )])),
+            ),
+            (
+              "name".to_string(),
+              Value::from(HashMap::from([("type".to_string(), Value::from("string"))])),
+            ),
+          ])),
+        )])),
+      ),
+    ])));
+    let config = Config {
+      routes: vec![route],
+      ..Default::default()
+    };
+    let messages = Linter::new(&config, "/nonexistent")
+      .lint()
+      .into_iter()
+      .map(|lint| lint.message)
+      .collect::<Vec<_>>();
+    assert_eq!(
+      messages,
+      vec![
+        "`body.0.id` is string in the fixture but number in the schema",
+        "`body.0.role` is not described by the schema",
+      ]
+    );
+  }
+
+  #[test]
+  fn defines() {
+    assert!(Linter::defines("function handle(req) {}", "handle"));
+    assert!(Linter::defines("const handle = (req) => 1", "handle"));
+    assert!(!Linter::defines("function handler(req) {}", "handle"));
+  }
+}
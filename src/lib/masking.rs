@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  render_value,
+  transform::{parse_path, visit_parents, Step},
+  Buffer, MediaType, Value,
+};
+
+/// How a masked field is disguised.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MaskStrategy {
+  /// Replaced by [`MaskStrategy::REDACTED`]
+  Redact,
+  /// Replaced by a stable, non-cryptographic digest, so masked values can
+  /// still be told apart and joined on
+  Hash,
+  /// Only the last four characters kept, or the first letter and domain of
+  /// an email address, e.g. `************4242` or `a***@example.com`
+  Partial,
+}
+
+impl MaskStrategy {
+  pub const REDACTED: &'static str = "[REDACTED]";
+
+  pub fn mask(&self, value: &Value) -> Value {
+    if matches!(value, Value::Null) {
+      return Value::Null;
+    }
+    let text = render_value(value);
+    Value::String(match self {
+      Self::Redact => Self::REDACTED.to_string(),
+      Self::Hash => format!("{:016x}", fnv1a(text.as_bytes())),
+      Self::Partial => match text.split_once('@') {
+        Some((local, domain)) if !local.is_empty() => {
+          format!("{}***@{}", local.chars().next().unwrap_or('*'), domain)
+        }
+        _ => {
+          let len = text.chars().count();
+          let kept = if len > 4 { 4 } else { 0 };
+          text
+            .chars()
+            .enumerate()
+            .map(|(i, c)| if i + kept < len { '*' } else { c })
+            .collect()
+        }
+      },
+    })
+  }
+}
+
+/// 64-bit FNV-1a digest of `data`
+pub(crate) fn fnv1a(data: &[u8]) -> u64 {
+  data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+    (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+  })
+}
+
+/// Fields of outgoing bodies to disguise, by JSONPath, so recorded data can be
+/// served without exposing personal information, e.g.
+/// `{"$.email": "partial", "$.items[*].card": "redact"}`.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MaskRules(pub BTreeMap<String, MaskStrategy>);
+
+impl MaskRules {
+  pub fn with_rule<P: AsRef<str>>(mut self, path: P, strategy: MaskStrategy) -> Self {
+    self.0.insert(path.as_ref().to_string(), strategy);
+    self
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  pub fn apply(&self, body: &mut Value) -> crate::Result<()> {
+    for (path, strategy) in &self.0 {
+      visit_parents(
+        body,
+        &parse_path(path)?,
+        false,
+        &mut |parent, step| match (parent, step) {
+          (Value::Map(map), Step::Key(key)) => {
+            if let Some(value) = map.get_mut(key) {
+              *value = strategy.mask(value);
+            }
+          }
+          (Value::Map(map), Step::All) => {
+            map.values_mut().for_each(|v| *v = strategy.mask(v));
+          }
+          (Value::Array(items), Step::Index(i)) => {
+            if let Some(value) = items.get_mut(*i) {
+              *value = strategy.mask(value);
+            }
+          }
+          (Value::Array(items), Step::All) => {
+            items.iter_mut().for_each(|v| *v = strategy.mask(v));
+          }
+          _ => {}
+        },
+      );
+    }
+    Ok(())
+  }
+
+  /// Mask a JSON body, other bodies being left untouched
+  pub fn apply_to(&self, buf: &mut Buffer) -> crate::Result<()> {
+    let json = buf
+      .header("Content-Type")
+      .and_then(|ct| ct.parse::<MediaType>().ok())
+      .is_some_and(|ct| ct.is_json());
+    if self.is_empty() || !json || buf.body().is_empty() {
+      return Ok(());
+    }
+    #[cfg(feature = "json")]
+    {
+      let mut body = Value::try_from_json(serde_json::from_slice(buf.body())?)?;
+      self.apply(&mut body)?;
+      let body = serde_json::to_vec(&body.to_json())?;
+      *buf = std::mem::take(buf).with_body_bytes(body);
+    }
+    Ok(())
+  }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+  use crate::Value;
+
+  use super::{MaskRules, MaskStrategy};
+
+  #[test]
+  fn strategies() {
+    let mut body = Value::try_from_json(serde_json::json!({
+      "email": "ada@example.com",
+      "password": "secret",
+      "cards": [{"number": "4242424242424242"}, {"number": 1234}],
+      "ssn": "123-45-6789",
+      "phone": null
+    }))
+    .unwrap();
+    MaskRules::default()
+      .with_rule("$.email", MaskStrategy::Partial)
+      .with_rule("$.password", MaskStrategy::Redact)
+      .with_rule("$.cards[*].number", MaskStrategy::Partial)
+      .with_rule("$.ssn", MaskStrategy::Hash)
+      .with_rule("$.phone", MaskStrategy::Redact)
+      .apply(&mut body)
+      .unwrap();
+    let body = body.to_json();
+    assert_eq!(body["email"], "a***@example.com");
+    assert_eq!(body["password"], MaskStrategy::REDACTED);
+    assert_eq!(body["cards"][0]["number"], "************4242");
+    assert_eq!(body["cards"][1]["number"], "****");
+    let hash = body["ssn"].as_str().unwrap();
+    assert_eq!(hash.len(), 16);
+    assert_eq!(
+      MaskStrategy::Hash.mask(&Value::from("123-45-6789")),
+      Value::from(hash)
+    );
+    assert!(body["phone"].is_null());
+  }
+}
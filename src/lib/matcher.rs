@@ -0,0 +1,458 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{query_decode, Request};
+
+/// Whether a request satisfied a [`Matcher`], and why.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchResult {
+  pub matched: bool,
+  /// One line per check performed, failed or not
+  pub explanations: Vec<String>,
+}
+
+impl MatchResult {
+  pub fn new<E: Into<String>>(matched: bool, explanation: E) -> Self {
+    Self {
+      matched,
+      explanations: vec![explanation.into()],
+    }
+  }
+
+  /// Both results, matched when both are
+  pub fn and(mut self, other: MatchResult) -> Self {
+    self.matched &= other.matched;
+    self.explanations.extend(other.explanations);
+    self
+  }
+}
+
+impl Default for MatchResult {
+  fn default() -> Self {
+    Self {
+      matched: true,
+      explanations: vec![],
+    }
+  }
+}
+
+/// Decides whether a request is of interest, used by routes to narrow what
+/// they serve and by tests to assert on what was sent.
+pub trait Matcher: Send + Sync {
+  fn matches(&self, req: &Request) -> MatchResult;
+}
+
+/// Checks applied to a piece of a request: a header value, the body, or a
+/// value picked from a JSON body. Every given check must pass.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Predicate {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub equals: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub contains: Option<String>,
+  /// Regular expression the text must match
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub matches: Option<String>,
+  /// Whether the text must be there at all, `true` by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub present: Option<bool>,
+}
+
+impl Predicate {
+  /// Test `text`, `None` when it is missing, explaining the outcome in terms
+  /// of `subject`
+  pub fn test(&self, subject: &str, text: Option<&str>) -> MatchResult {
+    let text = match (text, self.present.unwrap_or(true)) {
+      (None, present) => {
+        return MatchResult::new(
+          !present,
+          format!(
+            "{} is missing{}",
+            subject,
+            if present { "" } else { ", as expected" }
+          ),
+        )
+      }
+      (Some(text), false) => {
+        return MatchResult::new(
+          false,
+          format!("{} is '{}', expected missing", subject, text),
+        )
+      }
+      (Some(text), true) => text,
+    };
+    let mut ret = MatchResult::default();
+    if let Some(expected) = &self.equals {
+      ret = ret.and(MatchResult::new(
+        text == expected,
+        format!("{} is '{}', expected '{}'", subject, text, expected),
+      ));
+    }
+    if let Some(needle) = &self.contains {
+      ret = ret.and(MatchResult::new(
+        text.contains(needle.as_str()),
+        format!(
+          "{} is '{}', expected to contain '{}'",
+          subject, text, needle
+        ),
+      ));
+    }
+    if let Some(pattern) = &self.matches {
+      ret = ret.and(match Regex::new(pattern) {
+        Ok(re) => MatchResult::new(
+          re.is_match(text),
+          format!("{} is '{}', expected to match /{}/", subject, text, pattern),
+        ),
+        Err(e) => MatchResult::new(false, format!("invalid pattern /{}/: {}", pattern, e)),
+      });
+    }
+    if ret.explanations.is_empty() {
+      ret = MatchResult::new(true, format!("{} is present", subject));
+    }
+    ret
+  }
+}
+
+/// Builders of the [`Predicate`] of a matcher.
+pub trait WithPredicate: Sized {
+  fn predicate_mut(&mut self) -> &mut Predicate;
+
+  fn with_equals<S: AsRef<str>>(mut self, text: S) -> Self {
+    self.predicate_mut().equals = Some(text.as_ref().to_string());
+    self
+  }
+
+  fn with_contains<S: AsRef<str>>(mut self, text: S) -> Self {
+    self.predicate_mut().contains = Some(text.as_ref().to_string());
+    self
+  }
+
+  fn with_pattern<S: AsRef<str>>(mut self, pattern: S) -> Self {
+    self.predicate_mut().matches = Some(pattern.as_ref().to_string());
+    self
+  }
+
+  fn with_present(mut self, present: bool) -> Self {
+    self.predicate_mut().present = Some(present);
+    self
+  }
+}
+
+/// Checks a request header, by case-insensitive name.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HeaderMatcher {
+  pub name: String,
+  #[serde(flatten)]
+  pub predicate: Predicate,
+}
+
+impl HeaderMatcher {
+  pub fn new<N: AsRef<str>>(name: N) -> Self {
+    Self {
+      name: name.as_ref().to_string(),
+      predicate: Predicate::default(),
+    }
+  }
+}
+
+impl WithPredicate for HeaderMatcher {
+  fn predicate_mut(&mut self) -> &mut Predicate {
+    &mut self.predicate
+  }
+}
+
+impl Matcher for HeaderMatcher {
+  fn matches(&self, req: &Request) -> MatchResult {
+    self
+      .predicate
+      .test(&format!("header {}", self.name), req.header(&self.name))
+  }
+}
+
+/// Checks the decoded values of a query parameter, by case-insensitive name,
+/// passing when any of them does.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueryMatcher {
+  pub name: String,
+  #[serde(flatten)]
+  pub predicate: Predicate,
+}
+
+impl QueryMatcher {
+  pub fn new<N: AsRef<str>>(name: N) -> Self {
+    Self {
+      name: name.as_ref().to_string(),
+      predicate: Predicate::default(),
+    }
+  }
+}
+
+impl WithPredicate for QueryMatcher {
+  fn predicate_mut(&mut self) -> &mut Predicate {
+    &mut self.predicate
+  }
+}
+
+impl Matcher for QueryMatcher {
+  fn matches(&self, req: &Request) -> MatchResult {
+    let subject = format!("query parameter {}", self.name);
+    let results = req
+      .query_params()
+      .into_iter()
+      .filter(|(key, _)| query_decode(key).eq_ignore_ascii_case(&self.name))
+      .map(|(_, value)| {
+        let value = query_decode(&value.unwrap_or_default());
+        self.predicate.test(&subject, Some(&value))
+      })
+      .collect::<Vec<_>>();
+    if results.is_empty() {
+      return self.predicate.test(&subject, None);
+    }
+    match results.iter().find(|r| r.matched) {
+      Some(found) => found.clone(),
+      None => results
+        .into_iter()
+        .reduce(MatchResult::and)
+        .unwrap_or_default(),
+    }
+  }
+}
+
+/// Checks the request body as text, an empty body being missing.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BodyMatcher {
+  #[serde(flatten)]
+  pub predicate: Predicate,
+}
+
+impl BodyMatcher {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl WithPredicate for BodyMatcher {
+  fn predicate_mut(&mut self) -> &mut Predicate {
+    &mut self.predicate
+  }
+}
+
+impl Matcher for BodyMatcher {
+  fn matches(&self, req: &Request) -> MatchResult {
+    let body = String::from_utf8_lossy(req.body());
+    self
+      .predicate
+      .test("body", Some(body.as_ref()).filter(|b| !b.is_empty()))
+  }
+}
+
+/// Checks the values a JSONPath expression picks from a JSON body, passing
+/// when any of them does. Strings are tested as is, other values as JSON.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JsonPathMatcher {
+  pub path: String,
+  #[serde(flatten)]
+  pub predicate: Predicate,
+}
+
+impl JsonPathMatcher {
+  pub fn new<P: AsRef<str>>(path: P) -> Self {
+    Self {
+      path: path.as_ref().to_string(),
+      predicate: Predicate::default(),
+    }
+  }
+
+  #[cfg(feature = "json")]
+  fn values(&self, req: &Request) -> Result<Vec<String>, String> {
+    use crate::{
+      transform::{parse_path, select},
+      BodyParsers, MediaType, Value,
+    };
+
+    let steps = parse_path(&self.path).map_err(|e| e.to_string())?;
+    let parser = req
+      .header("Content-Type")
+      .and_then(|v| v.parse::<MediaType>().ok())
+      .and_then(|content_type| BodyParsers::find(&content_type));
+    let body = match parser {
+      Some(parser) => parser(req.body()).map_err(|e| e.to_string())?,
+      None => serde_json::from_slice(req.body())
+        .map_err(|e| format!("body is not JSON: {}", e))
+        .and_then(|json| Value::try_from_json(json).map_err(|e| e.to_string()))?,
+    };
+    Ok(
+      select(&body, &steps)
+        .into_iter()
+        .map(|value| match value {
+          Value::String(s) => s.clone(),
+          value => value.to_json().to_string(),
+        })
+        .collect(),
+    )
+  }
+
+  #[cfg(not(feature = "json"))]
+  fn values(&self, _req: &Request) -> Result<Vec<String>, String> {
+    Err("JSONPath matching requires the `json` feature".to_string())
+  }
+}
+
+impl WithPredicate for JsonPathMatcher {
+  fn predicate_mut(&mut self) -> &mut Predicate {
+    &mut self.predicate
+  }
+}
+
+impl Matcher for JsonPathMatcher {
+  fn matches(&self, req: &Request) -> MatchResult {
+    let values = match self.values(req) {
+      Ok(values) => values,
+      Err(e) => return MatchResult::new(false, format!("{}: {}", self.path, e)),
+    };
+    if values.is_empty() {
+      return self.predicate.test(&self.path, None);
+    }
+    let results = values
+      .iter()
+      .map(|v| self.predicate.test(&self.path, Some(v)))
+      .collect::<Vec<_>>();
+    match results.iter().find(|r| r.matched) {
+      Some(found) => found.clone(),
+      None => results
+        .into_iter()
+        .reduce(MatchResult::and)
+        .unwrap_or_default(),
+    }
+  }
+}
+
+/// Every check a request must pass, built up from header, query, body and
+/// JSONPath matchers, as found in the `when` option of routes.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RequestMatcher {
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub headers: Vec<HeaderMatcher>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub query: Vec<QueryMatcher>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub body: Option<BodyMatcher>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub json: Vec<JsonPathMatcher>,
+}
+
+impl RequestMatcher {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.headers.is_empty() && self.query.is_empty() && self.body.is_none() && self.json.is_empty()
+  }
+
+  pub fn with_header(mut self, matcher: HeaderMatcher) -> Self {
+    self.headers.push(matcher);
+    self
+  }
+
+  pub fn with_query(mut self, matcher: QueryMatcher) -> Self {
+    self.query.push(matcher);
+    self
+  }
+
+  pub fn with_body(mut self, matcher: BodyMatcher) -> Self {
+    self.body = Some(matcher);
+    self
+  }
+
+  pub fn with_json_path(mut self, matcher: JsonPathMatcher) -> Self {
+    self.json.push(matcher);
+    self
+  }
+}
+
+impl Matcher for RequestMatcher {
+  fn matches(&self, req: &Request) -> MatchResult {
+    let mut ret = MatchResult::default();
+    for matcher in &self.headers {
+      ret = ret.and(matcher.matches(req));
+    }
+    for matcher in &self.query {
+      ret = ret.and(matcher.matches(req));
+    }
+    if let Some(matcher) = &self.body {
+      ret = ret.and(matcher.matches(req));
+    }
+    for matcher in &self.json {
+      ret = ret.and(matcher.matches(req));
+    }
+    ret
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Request};
+
+  use super::{
+    BodyMatcher, HeaderMatcher, JsonPathMatcher, Matcher, QueryMatcher, RequestMatcher,
+    WithPredicate,
+  };
+
+  #[test]
+  fn headers_and_body() {
+    let req = Request::new(Method::Post, "/orders")
+      .with_header("Content-Type", "application/json")
+      .with_body("{\"items\": [{\"sku\": \"A1\"}, {\"sku\": \"B2\"}], \"total\": 12}");
+    let matcher = RequestMatcher::new()
+      .with_header(HeaderMatcher::new("content-type").with_contains("json"))
+      .with_header(HeaderMatcher::new("X-Debug").with_present(false))
+      .with_body(BodyMatcher::new().with_pattern("\"total\": \\d+"));
+    let result = matcher.matches(&req);
+    assert!(result.matched, "{:?}", result.explanations);
+    assert_eq!(result.explanations.len(), 3);
+
+    let result = HeaderMatcher::new("Accept").matches(&req);
+    assert!(!result.matched);
+    assert_eq!(result.explanations, vec!["header Accept is missing"]);
+    #[cfg(feature = "json")]
+    {
+      let sku = |sku: &str| JsonPathMatcher::new("$.items[*].sku").with_equals(sku);
+      assert!(sku("B2").matches(&req).matched);
+      let result = sku("C3").matches(&req);
+      assert!(!result.matched);
+      assert_eq!(result.explanations.len(), 2);
+      assert!(
+        JsonPathMatcher::new("$.total")
+          .with_equals("12")
+          .matches(&req)
+          .matched
+      );
+    }
+  }
+
+  #[test]
+  fn query() {
+    let req = Request::new(Method::Get, "/orders?tag=a&tag=b%20c&page=2");
+    assert!(
+      QueryMatcher::new("tag")
+        .with_equals("b c")
+        .matches(&req)
+        .matched
+    );
+    assert!(
+      QueryMatcher::new("PAGE")
+        .with_equals("2")
+        .matches(&req)
+        .matched
+    );
+    assert!(
+      !QueryMatcher::new("tag")
+        .with_equals("d")
+        .matches(&req)
+        .matched
+    );
+    let result = QueryMatcher::new("sort").matches(&req);
+    assert_eq!(result.explanations, vec!["query parameter sort is missing"]);
+  }
+}
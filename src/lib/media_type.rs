@@ -0,0 +1,323 @@
+use std::{fmt::Display, path::Path, str::FromStr};
+
+use crate::{Error, ErrorKind, Status};
+
+/// Media type of a file after its extension, `application/octet-stream` when
+/// unknown
+pub fn content_type_for<P: AsRef<Path>>(path: P) -> &'static str {
+  let ext = path
+    .as_ref()
+    .extension()
+    .and_then(|e| e.to_str())
+    .unwrap_or_default()
+    .to_ascii_lowercase();
+  match ext.as_str() {
+    "html" | "htm" => "text/html; charset=utf-8",
+    "css" => "text/css; charset=utf-8",
+    "js" | "mjs" => "text/javascript; charset=utf-8",
+    "txt" | "log" => "text/plain; charset=utf-8",
+    "csv" => "text/csv; charset=utf-8",
+    "md" => "text/markdown; charset=utf-8",
+    "xml" => "application/xml",
+    "json" => "application/json",
+    "yaml" | "yml" => "application/yaml",
+    "toml" => "application/toml",
+    "pdf" => "application/pdf",
+    "zip" => "application/zip",
+    "gz" => "application/gzip",
+    "tar" => "application/x-tar",
+    "wasm" => "application/wasm",
+    "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    "png" => "image/png",
+    "jpg" | "jpeg" => "image/jpeg",
+    "gif" => "image/gif",
+    "webp" => "image/webp",
+    "svg" => "image/svg+xml",
+    "ico" => "image/x-icon",
+    "mp3" => "audio/mpeg",
+    "wav" => "audio/wav",
+    "mp4" => "video/mp4",
+    "webm" => "video/webm",
+    "woff" => "font/woff",
+    "woff2" => "font/woff2",
+    _ => "application/octet-stream",
+  }
+}
+
+/// Content type of `body`, guessed from its magic bytes or, for text, from
+/// its first characters. Unrecognized text is `text/plain`, other data
+/// `application/octet-stream`.
+pub fn sniff_content_type(body: &[u8]) -> &'static str {
+  const MAGIC: [(&[u8], &str); 9] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF8", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\0asm", "application/wasm"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+  ];
+  if let Some((_, kind)) = MAGIC.iter().find(|(magic, _)| body.starts_with(magic)) {
+    return kind;
+  }
+  if body.len() >= 12 && body.starts_with(b"RIFF") && &body[8..12] == b"WEBP" {
+    return "image/webp";
+  }
+  let text = match std::str::from_utf8(body) {
+    Ok(text) => text.trim_start_matches('\u{feff}').trim_start(),
+    Err(_) => return "application/octet-stream",
+  };
+  let lower = text
+    .chars()
+    .take(64)
+    .collect::<String>()
+    .to_ascii_lowercase();
+  if lower.starts_with("<!doctype html") || lower.starts_with("<html") {
+    "text/html; charset=utf-8"
+  } else if lower.starts_with("<svg") {
+    "image/svg+xml"
+  } else if lower.starts_with("<?xml") || lower.starts_with('<') {
+    "application/xml"
+  } else if (text.starts_with('{') || text.starts_with('[')) && is_json(text) {
+    "application/json"
+  } else {
+    "text/plain; charset=utf-8"
+  }
+}
+
+#[cfg(feature = "json")]
+fn is_json(text: &str) -> bool {
+  serde_json::from_str::<serde::de::IgnoredAny>(text).is_ok()
+}
+
+#[cfg(not(feature = "json"))]
+fn is_json(text: &str) -> bool {
+  text.trim_end().ends_with(['}', ']'])
+}
+
+/// A media type such as `application/vnd.api+json; charset=utf-8`, as found
+/// in `Content-Type` headers and, as ranges, in `Accept` headers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaType {
+  /// Top-level type, e.g. `application`
+  pub kind: String,
+  /// Subtype without its suffix, e.g. `vnd.api`
+  pub subtype: String,
+  /// Structured syntax suffix, e.g. `json` for `vnd.api+json`
+  pub suffix: Option<String>,
+  pub params: Vec<(String, String)>,
+}
+
+impl MediaType {
+  /// `type/subtype[+suffix]`, without parameters
+  pub fn essence(&self) -> String {
+    match &self.suffix {
+      Some(suffix) => format!("{}/{}+{}", self.kind, self.subtype, suffix),
+      None => format!("{}/{}", self.kind, self.subtype),
+    }
+  }
+
+  pub fn param<N: AsRef<str>>(&self, name: N) -> Option<&str> {
+    self
+      .params
+      .iter()
+      .find(|(k, _)| k.eq_ignore_ascii_case(name.as_ref()))
+      .map(|(_, v)| v.as_str())
+  }
+
+  pub fn charset(&self) -> Option<&str> {
+    self.param("charset")
+  }
+
+  /// Weight of a range in an `Accept` header, from its `q` parameter
+  pub fn quality(&self) -> f32 {
+    self.param("q").and_then(|q| q.parse().ok()).unwrap_or(1.0)
+  }
+
+  /// Whether the subtype or its suffix is `format`, e.g. `json` for both
+  /// `application/json` and `application/problem+json`
+  pub fn is<F: AsRef<str>>(&self, format: F) -> bool {
+    let format = format.as_ref();
+    self.subtype == format
+      || self.subtype == format!("x-{}", format)
+      || self.suffix.as_deref() == Some(format)
+  }
+
+  pub fn is_json(&self) -> bool {
+    self.is("json")
+  }
+
+  pub fn is_toml(&self) -> bool {
+    self.is("toml")
+  }
+
+  pub fn is_yaml(&self) -> bool {
+    self.is("yaml")
+  }
+
+  /// Whether the body is text in a charset requests can be decoded with
+  pub fn is_utf8(&self) -> bool {
+    self
+      .charset()
+      .is_none_or(|c| c.eq_ignore_ascii_case("utf-8") || c.eq_ignore_ascii_case("us-ascii"))
+  }
+
+  /// Whether this media type falls within `range` (`*/*`, `type/*` or an
+  /// exact type whose parameters, `q` aside, must all be present here)
+  pub fn matches(&self, range: &MediaType) -> bool {
+    let kind = range.kind == "*" || range.kind == self.kind;
+    let subtype = range.subtype == "*"
+      || (range.subtype == self.subtype && (range.suffix.is_none() || range.suffix == self.suffix));
+    kind
+      && subtype
+      && range
+        .params
+        .iter()
+        .filter(|(k, _)| k != "q")
+        .all(|(k, v)| self.param(k).is_some_and(|p| p.eq_ignore_ascii_case(v)))
+  }
+
+  /// How specific a range is, most specific ranges deciding the quality
+  fn precedence(&self) -> usize {
+    match (self.kind.as_str(), self.subtype.as_str()) {
+      ("*", _) => 0,
+      (_, "*") => 1,
+      _ => 2 + self.params.iter().filter(|(k, _)| k != "q").count(),
+    }
+  }
+
+  /// Parse every range of an `Accept` header, skipping invalid ones
+  pub fn parse_list<S: AsRef<str>>(s: S) -> Vec<MediaType> {
+    s.as_ref()
+      .split(',')
+      .filter_map(|range| range.parse().ok())
+      .collect()
+  }
+
+  /// The first of `offered` with the highest quality according to `accept`,
+  /// if any is acceptable at all
+  pub fn negotiate<A: AsRef<str>>(accept: A, offered: &[MediaType]) -> Option<&MediaType> {
+    let ranges = Self::parse_list(accept);
+    let mut best: Option<(&MediaType, f32)> = None;
+    for media_type in offered {
+      let quality = ranges
+        .iter()
+        .filter(|range| media_type.matches(range))
+        .max_by_key(|range| range.precedence())
+        .map(MediaType::quality)
+        .unwrap_or(0.0);
+      if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+        best = Some((media_type, quality));
+      }
+    }
+    best.map(|(media_type, _)| media_type)
+  }
+}
+
+impl FromStr for MediaType {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || {
+      Error::new(
+        ErrorKind::Api(Status::UnsupportedMediaType),
+        Some(format!("invalid media type '{}'", s)),
+        None,
+      )
+    };
+    let mut parts = s.split(';');
+    let essence = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+    let (kind, subtype) = essence.split_once('/').ok_or_else(invalid)?;
+    if kind.is_empty() || subtype.is_empty() {
+      return Err(invalid());
+    }
+    let (subtype, suffix) = match subtype.rsplit_once('+') {
+      Some((subtype, suffix)) => (subtype, Some(suffix.to_string())),
+      None => (subtype, None),
+    };
+    let params = parts
+      .filter(|p| !p.trim().is_empty())
+      .map(|p| {
+        let (k, v) = p.split_once('=').ok_or_else(invalid)?;
+        Ok((
+          k.trim().to_ascii_lowercase(),
+          v.trim().trim_matches('"').to_string(),
+        ))
+      })
+      .collect::<crate::Result<Vec<_>>>()?;
+    Ok(Self {
+      kind: kind.to_string(),
+      subtype: subtype.to_string(),
+      suffix,
+      params,
+    })
+  }
+}
+
+impl Display for MediaType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{}", self.essence())?;
+    for (k, v) in &self.params {
+      write!(f, "; {}={}", k, v)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{sniff_content_type, MediaType};
+
+  #[test]
+  fn sniff() {
+    assert_eq!(sniff_content_type(b" {\"a\": [1]}"), "application/json");
+    assert_eq!(sniff_content_type(b"[1, 2"), "text/plain; charset=utf-8");
+    assert_eq!(
+      sniff_content_type(b"<!DOCTYPE html><p>hi"),
+      "text/html; charset=utf-8"
+    );
+    assert_eq!(
+      sniff_content_type(b"<?xml version=\"1.0\"?><a/>"),
+      "application/xml"
+    );
+    assert_eq!(sniff_content_type(b"\x89PNG\r\n\x1a\n...."), "image/png");
+    assert_eq!(
+      sniff_content_type(&[0xde, 0xad, 0xbe, 0xef]),
+      "application/octet-stream"
+    );
+  }
+
+  #[test]
+  fn parse() {
+    let mt: MediaType = "Application/Problem+JSON; charset=\"UTF-8\""
+      .parse()
+      .unwrap();
+    assert_eq!(mt.essence(), "application/problem+json");
+    assert_eq!(mt.charset(), Some("UTF-8"));
+    assert!(mt.is_json() && mt.is_utf8());
+    assert!("application/x-yaml".parse::<MediaType>().unwrap().is_yaml());
+    assert!(!"text/plain; charset=latin1"
+      .parse::<MediaType>()
+      .unwrap()
+      .is_utf8());
+    assert!("json".parse::<MediaType>().is_err());
+  }
+
+  #[test]
+  fn negotiate() {
+    let offered = ["application/json", "application/yaml"].map(|t| t.parse::<MediaType>().unwrap());
+    let pick = |accept: &str| MediaType::negotiate(accept, &offered).map(|m| m.essence());
+    assert_eq!(pick("*/*").as_deref(), Some("application/json"));
+    assert_eq!(
+      pick("application/json;q=0.5, application/yaml").as_deref(),
+      Some("application/yaml")
+    );
+    assert_eq!(
+      pick("application/*;q=0.2, application/json;q=0").as_deref(),
+      Some("application/yaml")
+    );
+    assert_eq!(pick("text/html"), None);
+  }
+}
@@ -0,0 +1,170 @@
+use std::{collections::BTreeMap, io::Write, sync::Mutex, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::Table;
+
+/// Requests served by a route, with their latencies bucketed in a histogram.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RouteStats {
+  pub requests: u64,
+  /// Requests answered with a 5xx status, or not answered at all
+  pub errors: u64,
+  /// Number of requests per latency bucket, see [`RouteStats::BOUNDS`]
+  pub histogram: Vec<u64>,
+}
+
+impl Default for RouteStats {
+  fn default() -> Self {
+    Self {
+      requests: 0,
+      errors: 0,
+      histogram: vec![0; Self::BOUNDS.len() + 1],
+    }
+  }
+}
+
+impl RouteStats {
+  /// Upper bounds of the latency buckets, in microseconds, the last bucket
+  /// holding everything slower
+  pub const BOUNDS: [u64; 16] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000, 10_000_000,
+  ];
+
+  pub fn observe(&mut self, latency: Duration, error: bool) {
+    let micros = latency.as_micros() as u64;
+    let bucket = Self::BOUNDS
+      .iter()
+      .position(|b| micros <= *b)
+      .unwrap_or(Self::BOUNDS.len());
+    if self.histogram.len() <= bucket {
+      self.histogram.resize(Self::BOUNDS.len() + 1, 0);
+    }
+    self.histogram[bucket] += 1;
+    self.requests += 1;
+    self.errors += error as u64;
+  }
+
+  pub fn error_rate(&self) -> f64 {
+    match self.requests {
+      0 => 0.0,
+      n => self.errors as f64 / n as f64,
+    }
+  }
+
+  /// Upper bound of the bucket holding the `p`th percentile latency, `None`
+  /// when it falls past the last bound
+  pub fn percentile(&self, p: f64) -> Option<Duration> {
+    let rank = ((p / 100.0 * self.requests as f64).ceil() as u64).max(1);
+    let mut seen = 0;
+    for (i, count) in self.histogram.iter().enumerate() {
+      seen += count;
+      if seen >= rank {
+        return Self::BOUNDS.get(i).map(|b| Duration::from_micros(*b));
+      }
+    }
+    None
+  }
+}
+
+/// Per-route request counts and latencies, collected while serving.
+#[derive(Debug, Default)]
+pub struct Metrics {
+  routes: Mutex<BTreeMap<String, RouteStats>>,
+}
+
+impl Metrics {
+  /// Key of requests no route matched
+  pub const UNMATCHED: &'static str = "(unmatched)";
+
+  pub fn record<K: AsRef<str>>(
+    &self,
+    route: K,
+    latency: Duration,
+    status: Option<u16>,
+  ) -> crate::Result<()> {
+    let error = status.is_none_or(|s| s >= 500);
+    self
+      .routes
+      .lock()?
+      .entry(route.as_ref().to_string())
+      .or_default()
+      .observe(latency, error);
+    Ok(())
+  }
+
+  pub fn report(&self) -> crate::Result<MetricsReport> {
+    Ok(MetricsReport {
+      routes: self.routes.lock()?.clone(),
+    })
+  }
+
+  pub fn reset(&self) -> crate::Result<()> {
+    self.routes.lock()?.clear();
+    Ok(())
+  }
+}
+
+/// Snapshot of the [`Metrics`], as served by `/__mocker/stats`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MetricsReport {
+  pub routes: BTreeMap<String, RouteStats>,
+}
+
+impl MetricsReport {
+  pub fn write<W: Write>(&self, mut w: W) -> crate::Result<()> {
+    let ms = |d: Option<Duration>| match d {
+      Some(d) => format!("{:.1}ms", d.as_secs_f64() * 1000.0),
+      None => format!(
+        ">{}s",
+        RouteStats::BOUNDS[RouteStats::BOUNDS.len() - 1] / 1_000_000
+      ),
+    };
+    let mut table = Table::new()
+      .with_line_prefix("  ")
+      .with_separator(" │ ")
+      .with_row(["route", "requests", "errors", "p50", "p95"]);
+    for (route, stats) in &self.routes {
+      table.push([
+        route.clone(),
+        stats.requests.to_string(),
+        format!("{:.1}%", stats.error_rate() * 100.0),
+        ms(stats.percentile(50.0)),
+        ms(stats.percentile(95.0)),
+      ]);
+    }
+    table.aligned().write(&mut w)?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::Metrics;
+
+  #[test]
+  fn percentiles() {
+    let metrics = Metrics::default();
+    for i in 0..100 {
+      let latency = Duration::from_micros(if i < 90 { 80 } else { 3_000 });
+      let status = if i % 10 == 0 { 503 } else { 200 };
+      metrics.record("GET /users", latency, Some(status)).unwrap();
+    }
+    metrics
+      .record("GET /users", Duration::from_secs(60), None)
+      .unwrap();
+    let report = metrics.report().unwrap();
+    let stats = &report.routes["GET /users"];
+    assert_eq!(stats.requests, 101);
+    assert_eq!(stats.errors, 11);
+    assert_eq!(stats.percentile(50.0), Some(Duration::from_micros(100)));
+    assert_eq!(stats.percentile(95.0), Some(Duration::from_micros(5_000)));
+    assert_eq!(stats.percentile(100.0), None);
+    let mut out = vec![];
+    report.write(&mut out).unwrap();
+    assert!(String::from_utf8(out).unwrap().contains("GET /users"));
+  }
+}
@@ -4,22 +4,32 @@ use std::{
 };
 
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 
-use crate::{Error, ErrorKind, Method, Request, Response};
+use crate::{glob_match, Error, ErrorKind, Method, Request, Response};
 
+/// Request and response hook. Hooks take `&self` and run concurrently for
+/// every request: keep any state behind interior mutability.
 pub trait Middleware: Send + Sync {
   fn name(&self) -> &String;
   fn supported_methods(&self) -> Vec<Method>;
-  fn execute(&mut self, request: &Request, response: Response) -> crate::Result<Response>;
+  /// Called with the incoming request, before any other step
+  fn prepare(&self, _request: &mut Request) -> crate::Result<()> {
+    Ok(())
+  }
+  fn execute(&self, request: &Request, response: Response) -> crate::Result<Response>;
+  /// Called with the final response, once the request has been routed
+  fn finish(&self, _request: &Request, response: Response) -> crate::Result<Response> {
+    Ok(response)
+  }
 }
 
-pub struct Middlewares(HashMap<String, Arc<dyn Fn() -> crate::Result<Arc<Mutex<dyn Middleware>>>>>);
+pub type MiddlewareCtor = Arc<dyn Fn() -> crate::Result<Arc<dyn Middleware>> + Send + Sync>;
 
-unsafe impl Send for Middlewares {}
-unsafe impl Sync for Middlewares {}
+pub struct Middlewares(HashMap<String, MiddlewareCtor>);
 
 impl Middlewares {
-  pub fn create<N: AsRef<str>>(name: N) -> crate::Result<Arc<Mutex<dyn Middleware>>> {
+  pub fn create<N: AsRef<str>>(name: N) -> crate::Result<Arc<dyn Middleware>> {
     match Self::constructor(name.as_ref()) {
       Some(ctor) => ctor(),
       None => Err(Error::new(
@@ -30,21 +40,22 @@ impl Middlewares {
     }
   }
 
-  pub fn constructor<N: AsRef<str>>(
-    name: N,
-  ) -> Option<Arc<dyn Fn() -> crate::Result<Arc<Mutex<dyn Middleware>>>>> {
+  pub fn constructor<N: AsRef<str>>(name: N) -> Option<MiddlewareCtor> {
     let g = middlewares.lock().unwrap();
     match g
       .0
       .iter()
-      .find(|(k, v)| k.eq_ignore_ascii_case(name.as_ref()))
+      .find(|(k, _v)| k.eq_ignore_ascii_case(name.as_ref()))
     {
-      Some((name, constructor)) => Some(constructor.clone()),
+      Some((_name, constructor)) => Some(constructor.clone()),
       None => None,
     }
   }
 
-  pub fn register<N: AsRef<str>, M: Fn() -> crate::Result<Arc<Mutex<dyn Middleware>>> + 'static>(
+  pub fn register<
+    N: AsRef<str>,
+    M: Fn() -> crate::Result<Arc<dyn Middleware>> + Send + Sync + 'static,
+  >(
     name: N,
     ctor: M,
   ) {
@@ -57,3 +68,146 @@ lazy_static! {
   static ref middlewares: Arc<Mutex<Middlewares>> =
     Arc::new(Mutex::new(Middlewares(HashMap::new())));
 }
+
+/// Requests a middleware applies to, every one when left empty.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MiddlewareScope {
+  /// Glob patterns of the paths handled, e.g. `/api/*`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub include: Vec<String>,
+  /// Glob patterns of the paths left alone, e.g. `/__mocker/*`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub exclude: Vec<String>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub methods: Vec<Method>,
+}
+
+impl MiddlewareScope {
+  pub fn is_empty(&self) -> bool {
+    self.include.is_empty() && self.exclude.is_empty() && self.methods.is_empty()
+  }
+
+  pub fn applies(&self, req: &Request) -> bool {
+    let path = req.path().unwrap_or("/");
+    let method = req.method().unwrap_or(Method::Get);
+    (self.include.is_empty() || self.include.iter().any(|p| glob_match(p, path)))
+      && !self.exclude.iter().any(|p| glob_match(p, path))
+      && (self.methods.is_empty() || self.methods.contains(&method))
+  }
+}
+
+/// Middleware enabled in the config: a name, or a name along with the
+/// requests it applies to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum MiddlewareSpec {
+  Name(String),
+  Scoped {
+    name: String,
+    #[serde(flatten)]
+    scope: MiddlewareScope,
+  },
+}
+
+impl MiddlewareSpec {
+  pub fn name(&self) -> &String {
+    match self {
+      MiddlewareSpec::Name(name) | MiddlewareSpec::Scoped { name, .. } => name,
+    }
+  }
+
+  pub fn scope(&self) -> Option<&MiddlewareScope> {
+    match self {
+      MiddlewareSpec::Name(_) => None,
+      MiddlewareSpec::Scoped { scope, .. } => Some(scope).filter(|s| !s.is_empty()),
+    }
+  }
+
+  /// Instantiate the registered middleware, restricted to its scope
+  pub fn create(&self) -> crate::Result<Arc<dyn Middleware>> {
+    let middleware = Middlewares::create(self.name())?;
+    Ok(match self.scope() {
+      Some(scope) => Arc::new(ScopedMiddleware::new(middleware, scope.clone())),
+      None => middleware,
+    })
+  }
+}
+
+impl From<String> for MiddlewareSpec {
+  fn from(name: String) -> Self {
+    MiddlewareSpec::Name(name)
+  }
+}
+
+impl From<&str> for MiddlewareSpec {
+  fn from(name: &str) -> Self {
+    MiddlewareSpec::Name(name.to_string())
+  }
+}
+
+/// Runs a middleware only for the requests of its scope.
+pub struct ScopedMiddleware {
+  inner: Arc<dyn Middleware>,
+  scope: MiddlewareScope,
+}
+
+impl ScopedMiddleware {
+  pub fn new(inner: Arc<dyn Middleware>, scope: MiddlewareScope) -> Self {
+    Self { inner, scope }
+  }
+}
+
+impl Middleware for ScopedMiddleware {
+  fn name(&self) -> &String {
+    self.inner.name()
+  }
+
+  fn supported_methods(&self) -> Vec<Method> {
+    self.inner.supported_methods()
+  }
+
+  fn prepare(&self, request: &mut Request) -> crate::Result<()> {
+    match self.scope.applies(request) {
+      true => self.inner.prepare(request),
+      false => Ok(()),
+    }
+  }
+
+  fn execute(&self, request: &Request, response: Response) -> crate::Result<Response> {
+    match self.scope.applies(request) {
+      true => self.inner.execute(request, response),
+      false => Ok(response),
+    }
+  }
+
+  fn finish(&self, request: &Request, response: Response) -> crate::Result<Response> {
+    match self.scope.applies(request) {
+      true => self.inner.finish(request, response),
+      false => Ok(response),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Request};
+
+  use super::MiddlewareSpec;
+
+  #[test]
+  fn scope() {
+    let specs: Vec<MiddlewareSpec> = serde_json::from_str(
+      r#"["cors", {"name": "log", "exclude": ["/__mocker/*"]}, {"name": "auth", "include": ["/api/*"], "methods": ["POST"]}]"#,
+    )
+    .unwrap();
+    assert_eq!(specs[0], MiddlewareSpec::from("cors"));
+    assert!(specs[0].scope().is_none());
+    let log = specs[1].scope().unwrap();
+    assert!(log.applies(&Request::new(Method::Get, "/users")));
+    assert!(!log.applies(&Request::new(Method::Get, "/__mocker/stats")));
+    let auth = specs[2].scope().unwrap();
+    assert!(auth.applies(&Request::new(Method::Post, "/api/orders")));
+    assert!(!auth.applies(&Request::new(Method::Get, "/api/orders")));
+    assert!(!auth.applies(&Request::new(Method::Post, "/health")));
+  }
+}
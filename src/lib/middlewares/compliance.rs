@@ -0,0 +1,205 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{format_http_date, Method, Middleware, Request, Response, VirtualClock};
+
+pub const COMPLIANCE_MW_NAME: &str = "Compliance";
+
+/// Headers a response must not repeat
+const SINGLETONS: [&str; 6] = [
+  "Content-Length",
+  "Content-Type",
+  "Date",
+  "Server",
+  "Location",
+  "ETag",
+];
+
+/// Strict HTTP compliance, for picky clients rejecting the minimal responses
+/// mocker sends by default: responses get `Date` and `Server` headers, are
+/// written with CRLF line endings and the header names as configured, and
+/// RFC 9110/9112 violations are logged.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Compliance {
+  /// `Server` header value, `mocker/<version>` by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub server: Option<String>,
+}
+
+impl Compliance {
+  /// `Server` header sent with responses not setting one
+  pub fn server(&self) -> String {
+    match &self.server {
+      Some(server) => server.clone(),
+      None => format!("mocker/{}", env!("CARGO_PKG_VERSION")),
+    }
+  }
+}
+
+/// Whether `name` is a valid header field name, a token as in RFC 9110 §5.1
+fn is_token(name: &str) -> bool {
+  !name.is_empty()
+    && name
+      .bytes()
+      .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+/// Requirements of RFC 9110 and 9112 `res` breaks, as an answer to `req`
+pub fn audit(req: &Request, res: &Response) -> Vec<String> {
+  let mut violations = vec![];
+  let status = res.status();
+  if !(100..=599).contains(&status) {
+    violations.push(format!("status {} is out of the 100-599 range", status));
+  }
+  for (name, value) in res.headers() {
+    if !is_token(name) {
+      violations.push(format!("header name `{}` is not a token", name));
+    }
+    if value.contains(['\r', '\n', '\0']) {
+      violations.push(format!("header `{}` has a control character", name));
+    }
+  }
+  for singleton in SINGLETONS {
+    let count = res
+      .headers()
+      .iter()
+      .filter(|(name, _)| name.eq_ignore_ascii_case(singleton))
+      .count();
+    if count > 1 {
+      violations.push(format!("header `{}` is sent {} times", singleton, count));
+    }
+  }
+  let bodiless = (100..200).contains(&status) || status == 204 || status == 304;
+  if bodiless && !res.body().is_empty() {
+    violations.push(format!("a {} response must not have a body", status));
+  }
+  if ((100..200).contains(&status) || status == 204) && res.header("Content-Length").is_some() {
+    violations.push(format!(
+      "a {} response must not have a Content-Length",
+      status
+    ));
+  }
+  if req.method() == Some(Method::Head) && !res.body().is_empty() {
+    violations.push("a HEAD response must not have a body".to_string());
+  }
+  if let Some(length) = res.header("Content-Length") {
+    if length.trim().parse::<usize>().ok() != Some(res.body().len()) && !bodiless {
+      violations.push(format!(
+        "Content-Length {} does not match the {} bytes of the body",
+        length,
+        res.body().len()
+      ));
+    }
+  }
+  let required = match status {
+    201 | 301 | 302 | 303 | 307 | 308 => Some("Location"),
+    401 => Some("WWW-Authenticate"),
+    405 => Some("Allow"),
+    _ => None,
+  };
+  if let Some(required) = required.filter(|h| res.header(h).is_none()) {
+    violations.push(format!("a {} response must have a {}", status, required));
+  }
+  if !res.body().is_empty() && res.header("Content-Type").is_none() {
+    violations.push("a response with a body should have a Content-Type".to_string());
+  }
+  violations
+}
+
+/// Adds the `Date` and `Server` headers to responses and logs the
+/// requirements they break.
+pub struct ComplianceMiddleware {
+  name: String,
+  compliance: Compliance,
+  clock: VirtualClock,
+}
+
+impl ComplianceMiddleware {
+  pub fn new(compliance: Compliance) -> Self {
+    Self {
+      name: COMPLIANCE_MW_NAME.to_string(),
+      compliance,
+      clock: VirtualClock::default(),
+    }
+  }
+
+  /// Date responses by `clock` instead of the system clock
+  pub fn with_clock(mut self, clock: VirtualClock) -> Self {
+    self.clock = clock;
+    self
+  }
+}
+
+impl Middleware for ComplianceMiddleware {
+  fn name(&self) -> &String {
+    &self.name
+  }
+
+  fn supported_methods(&self) -> Vec<Method> {
+    vec![]
+  }
+
+  fn execute(&self, _request: &Request, response: Response) -> crate::Result<Response> {
+    Ok(response)
+  }
+
+  fn finish(&self, request: &Request, mut response: Response) -> crate::Result<Response> {
+    if response.header("Date").is_none() {
+      response.set_header("Date", format_http_date(self.clock.now_millis()));
+    }
+    if response.header("Server").is_none() {
+      response.set_header("Server", self.compliance.server());
+    }
+    for violation in audit(request, &response) {
+      warn!(
+        "Non-compliant response to {}: {}",
+        request.start_line(),
+        violation
+      );
+    }
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Middleware, Request, Response, Status};
+
+  use super::{audit, Compliance, ComplianceMiddleware};
+
+  #[test]
+  fn audit_responses() {
+    let get = Request::new(Method::Get, "/");
+    let ok = Response::default()
+      .with_header("Content-Type", "text/plain")
+      .with_body("hi");
+    assert!(audit(&get, &ok).is_empty());
+    assert!(audit(&Request::new(Method::Head, "/"), &ok)[0].contains("HEAD"));
+    let no_content = Response::default()
+      .with_status(Status::NoContent)
+      .with_body("x");
+    assert_eq!(audit(&get, &no_content).len(), 3);
+    let redirect = Response::default()
+      .with_status_code(302)
+      .with_header("X Bad", "a")
+      .with_header("Date", "a")
+      .with_header("date", "b");
+    let violations = audit(&get, &redirect);
+    assert_eq!(violations.len(), 3, "{:?}", violations);
+
+    let mw = ComplianceMiddleware::new(Compliance {
+      server: Some("api/2".to_string()),
+    });
+    let res = mw.finish(&get, ok).unwrap();
+    assert_eq!(res.header("Server"), Some("api/2"));
+    assert!(res.header("Date").unwrap().ends_with(" GMT"));
+    let head = String::from_utf8(res.strict_head()).unwrap();
+    assert!(head.starts_with("HTTP/1.1 200 OK\r\n"), "{}", head);
+    assert!(head.ends_with("\r\n\r\n"));
+    let mut wire = vec![];
+    res.write_strict_to(&mut wire).unwrap();
+    let parsed = Response::from_reader(&wire[..]).unwrap();
+    assert_eq!(parsed.header("Server"), Some("api/2"));
+    assert_eq!(parsed.body().as_ref(), b"hi");
+  }
+}
@@ -25,7 +25,7 @@ impl Middleware for CorsMiddleware {
     return vec![Method::Options];
   }
 
-  fn execute(&mut self, request: &Request, mut response: Response) -> crate::Result<Response> {
+  fn execute(&self, request: &Request, mut response: Response) -> crate::Result<Response> {
     response.set_header("Access-Control-Allow-Origin", "*");
     Ok(response)
   }
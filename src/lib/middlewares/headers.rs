@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Buffer, Method, Middleware, Request, Response};
+
+pub const HEADERS_MW_NAME: &str = "Headers";
+
+/// Header edits, applied in order: remove, rename, add, set.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HeaderRuleSet {
+  /// Headers to strip
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub remove: Vec<String>,
+  /// Old name to new name
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub rename: BTreeMap<String, String>,
+  /// Headers added unless already present
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub add: BTreeMap<String, String>,
+  /// Headers set, overriding any existing value
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub set: BTreeMap<String, String>,
+}
+
+impl HeaderRuleSet {
+  pub fn is_empty(&self) -> bool {
+    self.remove.is_empty() && self.rename.is_empty() && self.add.is_empty() && self.set.is_empty()
+  }
+
+  pub fn apply(&self, buf: &mut Buffer) {
+    for name in &self.remove {
+      buf.remove_header(name);
+    }
+    for (from, to) in &self.rename {
+      if let Some(value) = buf.remove_header(from) {
+        buf.set_header(to, value);
+      }
+    }
+    for (name, value) in &self.add {
+      if buf.header(name).is_none() {
+        buf.set_header(name, value);
+      }
+    }
+    for (name, value) in &self.set {
+      buf.set_header(name, value);
+    }
+  }
+}
+
+/// Declarative header manipulation, for incoming requests and outgoing responses.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HeaderRules {
+  #[serde(default, skip_serializing_if = "HeaderRuleSet::is_empty")]
+  pub request: HeaderRuleSet,
+  #[serde(default, skip_serializing_if = "HeaderRuleSet::is_empty")]
+  pub response: HeaderRuleSet,
+}
+
+impl HeaderRules {
+  pub fn is_empty(&self) -> bool {
+    self.request.is_empty() && self.response.is_empty()
+  }
+}
+
+/// Applies the globally configured [`HeaderRules`] to every exchange.
+pub struct HeadersMiddleware {
+  name: String,
+  rules: HeaderRules,
+}
+
+impl HeadersMiddleware {
+  pub fn new(rules: HeaderRules) -> Self {
+    Self {
+      name: HEADERS_MW_NAME.to_string(),
+      rules,
+    }
+  }
+}
+
+impl Middleware for HeadersMiddleware {
+  fn name(&self) -> &String {
+    &self.name
+  }
+
+  fn supported_methods(&self) -> Vec<Method> {
+    vec![]
+  }
+
+  fn prepare(&self, request: &mut Request) -> crate::Result<()> {
+    self.rules.request.apply(request);
+    Ok(())
+  }
+
+  fn execute(&self, _request: &Request, response: Response) -> crate::Result<Response> {
+    Ok(response)
+  }
+
+  fn finish(&self, _request: &Request, mut response: Response) -> crate::Result<Response> {
+    self.rules.response.apply(&mut response);
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use crate::Response;
+
+  use super::HeaderRuleSet;
+
+  #[test]
+  fn apply() {
+    let rules = HeaderRuleSet {
+      remove: vec!["server".to_string()],
+      rename: BTreeMap::from([("X-Old".to_string(), "X-New".to_string())]),
+      add: BTreeMap::from([
+        ("X-Env".to_string(), "mock".to_string()),
+        ("Content-Type".to_string(), "text/plain".to_string()),
+      ]),
+      set: BTreeMap::from([("Cache-Control".to_string(), "no-store".to_string())]),
+    };
+    let mut res = Response::default()
+      .with_header("Server", "nginx")
+      .with_header("X-Old", "1")
+      .with_header("Content-Type", "application/json")
+      .with_header("Cache-Control", "max-age=60");
+    rules.apply(&mut res);
+    assert_eq!(res.header("Server"), None);
+    assert_eq!(res.header("X-Old"), None);
+    assert_eq!(res.header("X-New"), Some("1"));
+    assert_eq!(res.header("X-Env"), Some("mock"));
+    assert_eq!(res.header("Content-Type"), Some("application/json"));
+    assert_eq!(res.header("Cache-Control"), Some("no-store"));
+  }
+}
@@ -1,2 +1,12 @@
+pub mod compliance;
 #[cfg(feature = "cors")]
 pub mod cors;
+pub mod headers;
+pub mod namespace;
+pub mod persona;
+#[cfg(any(feature = "server", feature = "reqwest"))]
+pub mod shadow;
+#[cfg(feature = "signature")]
+pub mod signature;
+pub mod tenancy;
+pub mod transform;
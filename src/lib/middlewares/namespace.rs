@@ -0,0 +1,133 @@
+use std::{
+  collections::{hash_map::RandomState, HashSet},
+  hash::{BuildHasher, Hasher},
+  sync::{Arc, Mutex},
+};
+
+use crate::{now_millis, Method, Middleware, Request, Response};
+
+pub const NAMESPACE_MW_NAME: &str = "Namespace";
+
+/// Header store routes read the namespace of a request from, once resolved.
+/// Namespaces get their own copy of the store data, as sessions do.
+pub const NAMESPACE_HEADER: &str = "X-Mock-Namespace";
+
+/// Run-specific path prefixes every endpoint is also mounted under, so test
+/// suites sharing a long-lived server neither see each other's requests nor
+/// each other's data.
+#[derive(Debug, Default)]
+pub struct Namespaces(Mutex<HashSet<String>>);
+
+impl Namespaces {
+  /// Allocate a new namespace, returning its id
+  pub fn create(&self) -> crate::Result<String> {
+    let mut g = self.0.lock()?;
+    loop {
+      let mut hasher = RandomState::new().build_hasher();
+      hasher.write_u128(now_millis(0));
+      hasher.write_usize(g.len());
+      let id = format!("ns-{:016x}", hasher.finish());
+      if g.insert(id.clone()) {
+        return Ok(id);
+      }
+    }
+  }
+
+  /// Forget namespace `id`, returning whether it existed
+  pub fn remove(&self, id: &str) -> crate::Result<bool> {
+    Ok(self.0.lock()?.remove(id))
+  }
+
+  pub fn contains(&self, id: &str) -> crate::Result<bool> {
+    Ok(self.0.lock()?.contains(id))
+  }
+
+  /// Path prefix of namespace `id`
+  pub fn prefix(id: &str) -> String {
+    format!("/{}", id)
+  }
+}
+
+/// Strips the namespace prefix off requests into [`NAMESPACE_HEADER`], so
+/// routes are matched as if unprefixed.
+pub struct NamespaceMiddleware {
+  name: String,
+  namespaces: Arc<Namespaces>,
+}
+
+impl NamespaceMiddleware {
+  pub fn new(namespaces: Arc<Namespaces>) -> Self {
+    Self {
+      name: NAMESPACE_MW_NAME.to_string(),
+      namespaces,
+    }
+  }
+}
+
+impl Middleware for NamespaceMiddleware {
+  fn name(&self) -> &String {
+    &self.name
+  }
+
+  fn supported_methods(&self) -> Vec<Method> {
+    vec![]
+  }
+
+  fn prepare(&self, request: &mut Request) -> crate::Result<()> {
+    request.remove_header(NAMESPACE_HEADER);
+    let start = match request.start_line_mut().as_request_mut() {
+      Some(start) => start,
+      None => return Ok(()),
+    };
+    let rest = start.target.trim_start_matches('/');
+    let (id, rest) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+    if id.is_empty() || !self.namespaces.contains(id)? {
+      return Ok(());
+    }
+    let id = id.to_string();
+    start.target = match rest.starts_with('/') {
+      true => rest.to_string(),
+      false => format!("/{}", rest),
+    };
+    request.set_header(NAMESPACE_HEADER, id);
+    Ok(())
+  }
+
+  fn execute(&self, _request: &Request, response: Response) -> crate::Result<Response> {
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use crate::{Method, Middleware, Request};
+
+  use super::{NamespaceMiddleware, Namespaces, NAMESPACE_HEADER};
+
+  #[test]
+  fn prefix() {
+    let namespaces = Arc::new(Namespaces::default());
+    let id = namespaces.create().unwrap();
+    assert_ne!(namespaces.create().unwrap(), id);
+    let mw = NamespaceMiddleware::new(namespaces.clone());
+
+    let target = format!("{}/users?page=2", Namespaces::prefix(&id));
+    let mut req = Request::new(Method::Get, target);
+    mw.prepare(&mut req).unwrap();
+    assert_eq!(req.path(), Some("/users"));
+    assert_eq!(req.query(), Some("page=2"));
+    assert_eq!(req.header(NAMESPACE_HEADER), Some(id.as_str()));
+
+    let mut req = Request::new(Method::Get, "/ns-unknown/users").with_header(NAMESPACE_HEADER, &id);
+    mw.prepare(&mut req).unwrap();
+    assert_eq!(req.path(), Some("/ns-unknown/users"));
+    assert_eq!(req.header(NAMESPACE_HEADER), None);
+
+    assert!(namespaces.remove(&id).unwrap());
+    let mut req = Request::new(Method::Get, Namespaces::prefix(&id));
+    mw.prepare(&mut req).unwrap();
+    assert_eq!(req.header(NAMESPACE_HEADER), None);
+  }
+}
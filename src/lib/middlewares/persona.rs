@@ -0,0 +1,202 @@
+use std::{
+  collections::BTreeMap,
+  path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  fault::chance, Admin, Error, ErrorKind, Method, Middleware, Request, Response, Status, Value,
+};
+
+pub const PERSONA_MW_NAME: &str = "Persona";
+
+/// Data profile a client is served with, such as "user with an empty cart".
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Persona {
+  /// Files store routes read instead of their own, by route endpoint.
+  /// Writes stay in memory, keeping the files pristine.
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub stores: BTreeMap<String, PathBuf>,
+  /// Fraction of requests (0 to 1) failing with `error_status`
+  #[serde(default)]
+  pub error_rate: f64,
+  #[serde(default = "Persona::default_error_status")]
+  pub error_status: u16,
+  /// Workspace variables overridden for this persona
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub variables: BTreeMap<String, Value>,
+}
+
+impl Persona {
+  fn default_error_status() -> u16 {
+    500
+  }
+
+  /// Store file served to this persona on `endpoint`, if overridden
+  pub fn store(&self, endpoint: &str) -> Option<&Path> {
+    self.stores.get(endpoint).map(PathBuf::as_path)
+  }
+}
+
+/// How requests select a persona: a header, a cookie or a bearer token,
+/// checked in that order.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Personas {
+  #[serde(default = "Personas::default_header")]
+  pub header: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub cookie: Option<String>,
+  /// Personas selected by bearer token
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub tokens: BTreeMap<String, String>,
+  pub profiles: BTreeMap<String, Persona>,
+}
+
+impl Personas {
+  fn default_header() -> String {
+    "X-Mock-Persona".to_string()
+  }
+
+  /// Persona `req` selects, unknown names being rejected
+  pub fn resolve(&self, req: &Request) -> crate::Result<Option<ActivePersona>> {
+    let cookie = self.cookie.as_ref().and_then(|name| req.cookie(name));
+    let token = req
+      .header("Authorization")
+      .and_then(|h| h.strip_prefix("Bearer "))
+      .and_then(|t| self.tokens.get(t.trim()));
+    let name = match req.header(&self.header).or(cookie) {
+      Some(name) => name,
+      None => match token {
+        Some(name) => name.as_str(),
+        None => return Ok(None),
+      },
+    };
+    match self.profiles.get(name) {
+      Some(persona) => Ok(Some(ActivePersona {
+        name: name.to_string(),
+        persona: persona.clone(),
+      })),
+      None => Err(Error::new(
+        ErrorKind::Api(Status::BadRequest),
+        Some(format!("unknown persona '{}'", name)),
+        None,
+      )),
+    }
+  }
+}
+
+/// Persona the request was resolved to, as a request extension
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActivePersona {
+  pub name: String,
+  pub persona: Persona,
+}
+
+/// Resolves the persona of each request, failing a share of them as its
+/// error rate demands. The admin API is left alone.
+pub struct PersonaMiddleware {
+  name: String,
+  personas: Personas,
+}
+
+impl PersonaMiddleware {
+  pub fn new(personas: Personas) -> Self {
+    Self {
+      name: PERSONA_MW_NAME.to_string(),
+      personas,
+    }
+  }
+}
+
+impl Middleware for PersonaMiddleware {
+  fn name(&self) -> &String {
+    &self.name
+  }
+
+  fn supported_methods(&self) -> Vec<Method> {
+    vec![]
+  }
+
+  fn prepare(&self, request: &mut Request) -> crate::Result<()> {
+    request.extensions_mut().remove::<ActivePersona>();
+    if Admin::handles(request) {
+      return Ok(());
+    }
+    let active = match self.personas.resolve(request)? {
+      Some(active) => active,
+      None => return Ok(()),
+    };
+    if chance(active.persona.error_rate) {
+      return Err(Error::new(
+        ErrorKind::Api(Status::try_from(active.persona.error_status)?),
+        Some(format!("simulated failure of persona '{}'", active.name)),
+        None,
+      ));
+    }
+    request.extensions_mut().insert(active);
+    Ok(())
+  }
+
+  fn execute(&self, _request: &Request, response: Response) -> crate::Result<Response> {
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use crate::{Method, Middleware, Request};
+
+  use super::{ActivePersona, Persona, PersonaMiddleware, Personas};
+
+  #[test]
+  fn resolve() {
+    let personas = Personas {
+      header: "X-Persona".to_string(),
+      cookie: Some("persona".to_string()),
+      tokens: BTreeMap::from([("t0k".to_string(), "busy".to_string())]),
+      profiles: BTreeMap::from([
+        (
+          "empty".to_string(),
+          Persona {
+            stores: BTreeMap::from([("/carts".to_string(), "empty.json".into())]),
+            ..Default::default()
+          },
+        ),
+        (
+          "busy".to_string(),
+          Persona {
+            error_rate: 1.0,
+            error_status: 503,
+            ..Default::default()
+          },
+        ),
+      ]),
+    };
+    let mw = PersonaMiddleware::new(personas);
+    let persona = |req: Request| {
+      let mut req = req;
+      mw.prepare(&mut req).map(|_| {
+        req
+          .extensions()
+          .get::<ActivePersona>()
+          .map(|a| a.name.clone())
+      })
+    };
+    let get = || Request::new(Method::Get, "/carts");
+    assert_eq!(persona(get()).unwrap(), None);
+    assert_eq!(
+      persona(get().with_header("X-Persona", "empty")).unwrap(),
+      Some("empty".to_string())
+    );
+    assert_eq!(
+      persona(get().with_header("Cookie", "a=1; persona=empty")).unwrap(),
+      Some("empty".to_string())
+    );
+    assert!(persona(get().with_header("X-Persona", "ghost")).is_err());
+    let failed = persona(get().with_header("Authorization", "Bearer t0k")).unwrap_err();
+    assert!(failed.to_string().contains("busy"), "{}", failed);
+  }
+}
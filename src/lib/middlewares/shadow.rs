@@ -0,0 +1,143 @@
+use std::{sync::Arc, thread};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  fault::chance, glob_match, Admin, Method, Middleware, Request, Response, UpstreamClient,
+  UpstreamConfig,
+};
+
+pub const SHADOW_MW_NAME: &str = "Shadow";
+
+/// Shadow traffic: incoming requests are mirrored to `target` in the
+/// background, its responses being discarded, so a real backend sees the
+/// traffic the mock serves, e.g. while migrating to it.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Shadow {
+  /// Base url requests are mirrored to, e.g. `http://localhost:9090`
+  pub target: String,
+  /// Fraction of requests (0 to 1) mirrored
+  #[serde(default = "Shadow::default_rate")]
+  pub rate: f64,
+  /// Glob patterns of the paths mirrored, every path when empty
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub paths: Vec<String>,
+}
+
+impl Shadow {
+  fn default_rate() -> f64 {
+    1.0
+  }
+
+  /// Whether `req` is to be mirrored, not accounting for the rate
+  pub fn matches(&self, req: &Request) -> bool {
+    let path = req.path().unwrap_or_default();
+    self.paths.is_empty() || self.paths.iter().any(|p| glob_match(p, path))
+  }
+}
+
+/// Mirrors requests to the shadow target from a thread of their own, the
+/// client response being left alone. The admin API is not mirrored.
+pub struct ShadowMiddleware {
+  name: String,
+  shadow: Shadow,
+  client: Arc<dyn UpstreamClient>,
+}
+
+impl ShadowMiddleware {
+  /// Mirror requests as `shadow` says, with the timeouts and retries of
+  /// `upstream`
+  pub fn new(shadow: Shadow, upstream: &UpstreamConfig) -> crate::Result<Self> {
+    Ok(Self {
+      name: SHADOW_MW_NAME.to_string(),
+      client: Arc::from(upstream.client(&shadow.target)?),
+      shadow,
+    })
+  }
+}
+
+impl Middleware for ShadowMiddleware {
+  fn name(&self) -> &String {
+    &self.name
+  }
+
+  fn supported_methods(&self) -> Vec<Method> {
+    vec![]
+  }
+
+  fn prepare(&self, request: &mut Request) -> crate::Result<()> {
+    if Admin::handles(request) || !self.shadow.matches(request) || !chance(self.shadow.rate) {
+      return Ok(());
+    }
+    let mut mirrored = request.clone();
+    mirrored.remove_header("Host");
+    let client = self.client.clone();
+    let target = self.shadow.target.clone();
+    thread::spawn(move || match client.send(&mirrored) {
+      Ok(res) => debug!(
+        "Mirrored {} to {}: {}",
+        mirrored.start_line(),
+        target,
+        res.status()
+      ),
+      Err(e) => warn!(
+        "Failed to mirror {} to {}: {}",
+        mirrored.start_line(),
+        target,
+        e
+      ),
+    });
+    Ok(())
+  }
+
+  fn execute(&self, _request: &Request, response: Response) -> crate::Result<Response> {
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    sync::mpsc,
+    thread,
+    time::Duration,
+  };
+
+  use crate::{Method, Middleware, Request, UpstreamConfig};
+
+  use super::{Shadow, ShadowMiddleware};
+
+  #[test]
+  fn mirror() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let mut stream = stream.unwrap();
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        stream
+          .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+          .unwrap();
+        tx.send(line.trim().to_string()).unwrap();
+      }
+    });
+    let shadow = Shadow {
+      target: format!("http://{}", addr),
+      rate: 1.0,
+      paths: vec!["/orders*".to_string()],
+    };
+    let mw = ShadowMiddleware::new(shadow, &UpstreamConfig::default()).unwrap();
+    for target in ["/__mocker/routes", "/users", "/orders?page=2"] {
+      mw.prepare(&mut Request::new(Method::Get, target).with_header("Host", "mock"))
+        .unwrap();
+    }
+    let mirrored = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(mirrored, "GET /orders?page=2 HTTP/1.1");
+    assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+  }
+}
@@ -0,0 +1,292 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+
+use crate::{Admin, Error, ErrorKind, Method, Middleware, Request, Response, Status};
+
+pub const SIGNATURE_MW_NAME: &str = "Signature";
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureAlgorithm {
+  Sha1,
+  #[default]
+  Sha256,
+  Sha384,
+  Sha512,
+}
+
+impl SignatureAlgorithm {
+  fn hmac(&self) -> hmac::Algorithm {
+    match self {
+      Self::Sha1 => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+      Self::Sha256 => hmac::HMAC_SHA256,
+      Self::Sha384 => hmac::HMAC_SHA384,
+      Self::Sha512 => hmac::HMAC_SHA512,
+    }
+  }
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureEncoding {
+  #[default]
+  Hex,
+  Base64,
+}
+
+/// HMAC signature carried by a request header, as webhook providers send,
+/// e.g. GitHub's `X-Hub-Signature-256: sha256=<hex digest of the body>`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Signature {
+  #[serde(default = "Signature::default_header")]
+  pub header: String,
+  #[serde(default)]
+  pub algorithm: SignatureAlgorithm,
+  pub secret: String,
+  /// Parts of the request signed, joined by `separator`: `method`, `path`,
+  /// `target`, `body` or `header:<name>`
+  #[serde(default = "Signature::default_components")]
+  pub components: Vec<String>,
+  #[serde(default = "Signature::default_separator")]
+  pub separator: String,
+  #[serde(default)]
+  pub encoding: SignatureEncoding,
+  /// Text before the digest in the header, e.g. `sha256=`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub prefix: Option<String>,
+}
+
+impl Signature {
+  fn default_header() -> String {
+    "X-Signature".to_string()
+  }
+
+  fn default_components() -> Vec<String> {
+    vec!["body".to_string()]
+  }
+
+  fn default_separator() -> String {
+    ".".to_string()
+  }
+
+  pub fn new<S: AsRef<str>>(secret: S) -> Self {
+    Self {
+      header: Self::default_header(),
+      algorithm: SignatureAlgorithm::default(),
+      secret: secret.as_ref().to_string(),
+      components: Self::default_components(),
+      separator: Self::default_separator(),
+      encoding: SignatureEncoding::default(),
+      prefix: None,
+    }
+  }
+
+  pub fn with_header<H: AsRef<str>>(mut self, header: H) -> Self {
+    self.header = header.as_ref().to_string();
+    self
+  }
+
+  pub fn with_algorithm(mut self, algorithm: SignatureAlgorithm) -> Self {
+    self.algorithm = algorithm;
+    self
+  }
+
+  pub fn with_components<C: AsRef<str>, I: IntoIterator<Item = C>>(
+    mut self,
+    components: I,
+  ) -> Self {
+    self.components = components
+      .into_iter()
+      .map(|c| c.as_ref().to_string())
+      .collect();
+    self
+  }
+
+  pub fn with_encoding(mut self, encoding: SignatureEncoding) -> Self {
+    self.encoding = encoding;
+    self
+  }
+
+  pub fn with_prefix<P: AsRef<str>>(mut self, prefix: P) -> Self {
+    self.prefix = Some(prefix.as_ref().to_string());
+    self
+  }
+
+  fn key(&self) -> hmac::Key {
+    hmac::Key::new(self.algorithm.hmac(), self.secret.as_bytes())
+  }
+
+  /// Bytes of `req` the signature covers
+  pub fn payload(&self, req: &Request) -> crate::Result<Vec<u8>> {
+    let mut payload = vec![];
+    for (i, component) in self.components.iter().enumerate() {
+      if i > 0 {
+        payload.extend_from_slice(self.separator.as_bytes());
+      }
+      match component.split_once(':') {
+        Some(("header", name)) => {
+          payload.extend_from_slice(req.header(name).unwrap_or("").as_bytes())
+        }
+        _ => match component.as_str() {
+          "method" => payload.extend_from_slice(
+            req
+              .method()
+              .map(|m| m.repr())
+              .unwrap_or_default()
+              .as_bytes(),
+          ),
+          "path" => payload.extend_from_slice(req.path().unwrap_or("/").as_bytes()),
+          "target" => {
+            let target = req
+              .start_line()
+              .as_request()
+              .map_or("/", |s| s.target.as_str());
+            payload.extend_from_slice(target.as_bytes())
+          }
+          "body" => payload.extend_from_slice(req.body()),
+          _ => {
+            return Err(Error::new(
+              ErrorKind::Parse,
+              Some(format!("unknown signed component '{}'", component)),
+              None,
+            ))
+          }
+        },
+      }
+    }
+    Ok(payload)
+  }
+
+  /// Header value signing `req`
+  pub fn compute(&self, req: &Request) -> crate::Result<String> {
+    let tag = hmac::sign(&self.key(), &self.payload(req)?);
+    let digest = match self.encoding {
+      SignatureEncoding::Hex => tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect(),
+      SignatureEncoding::Base64 => STANDARD.encode(tag.as_ref()),
+    };
+    Ok(format!(
+      "{}{}",
+      self.prefix.as_deref().unwrap_or(""),
+      digest
+    ))
+  }
+
+  /// Sign an outgoing request, such as a webhook callback
+  pub fn sign(&self, req: &mut Request) -> crate::Result<()> {
+    let signature = self.compute(req)?;
+    req.set_header(&self.header, signature);
+    Ok(())
+  }
+
+  /// Check the signature `req` carries, in constant time
+  pub fn verify(&self, req: &Request) -> crate::Result<()> {
+    let unauthorized = |msg: &str| {
+      Error::new(
+        ErrorKind::Api(Status::Unauthorized),
+        Some(format!("{}: {}", self.header, msg)),
+        None,
+      )
+    };
+    let value = req
+      .header(&self.header)
+      .ok_or_else(|| unauthorized("missing signature"))?;
+    let digest = value
+      .trim()
+      .strip_prefix(self.prefix.as_deref().unwrap_or(""))
+      .ok_or_else(|| unauthorized("malformed signature"))?;
+    let tag = match self.encoding {
+      SignatureEncoding::Hex => (0..digest.len())
+        .step_by(2)
+        .map(|i| {
+          digest
+            .get(i..i + 2)
+            .and_then(|b| u8::from_str_radix(b, 16).ok())
+        })
+        .collect::<Option<Vec<_>>>(),
+      SignatureEncoding::Base64 => STANDARD.decode(digest).ok(),
+    }
+    .ok_or_else(|| unauthorized("malformed signature"))?;
+    hmac::verify(&self.key(), &self.payload(req)?, &tag)
+      .map_err(|_| unauthorized("signature mismatch"))
+  }
+}
+
+/// Rejects requests whose signature does not match, with `401 Unauthorized`,
+/// the admin API excepted.
+pub struct SignatureMiddleware {
+  name: String,
+  signature: Signature,
+}
+
+impl SignatureMiddleware {
+  pub fn new(signature: Signature) -> Self {
+    Self {
+      name: SIGNATURE_MW_NAME.to_string(),
+      signature,
+    }
+  }
+}
+
+impl Middleware for SignatureMiddleware {
+  fn name(&self) -> &String {
+    &self.name
+  }
+
+  fn supported_methods(&self) -> Vec<Method> {
+    vec![]
+  }
+
+  fn prepare(&self, request: &mut Request) -> crate::Result<()> {
+    match Admin::handles(request) {
+      true => Ok(()),
+      false => self.signature.verify(request),
+    }
+  }
+
+  fn execute(&self, _request: &Request, response: Response) -> crate::Result<Response> {
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Middleware, Request};
+
+  use super::{Signature, SignatureEncoding, SignatureMiddleware};
+
+  #[test]
+  fn verify() {
+    let github = Signature::new("It's a Secret to Everybody")
+      .with_header("X-Hub-Signature-256")
+      .with_prefix("sha256=");
+    let mut req = Request::new(Method::Post, "/hooks").with_body("Hello, World!");
+    github.sign(&mut req).unwrap();
+    assert_eq!(
+      req.header("X-Hub-Signature-256").unwrap(),
+      "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+    );
+    let mw = SignatureMiddleware::new(github.clone());
+    assert!(mw.prepare(&mut req.clone()).is_ok());
+    let mut tampered = req.clone().with_body("Hello, World?");
+    assert!(mw.prepare(&mut tampered).is_err());
+    assert!(mw
+      .prepare(&mut Request::new(Method::Post, "/hooks"))
+      .is_err());
+
+    let stripe = Signature::new("whsec")
+      .with_components(["header:X-Timestamp", "method", "path", "body"])
+      .with_encoding(SignatureEncoding::Base64);
+    let mut req = Request::new(Method::Post, "/hooks?a=1")
+      .with_header("X-Timestamp", "1700000000")
+      .with_body("{}");
+    assert_eq!(
+      stripe.payload(&req).unwrap(),
+      b"1700000000.POST./hooks.{}".to_vec()
+    );
+    stripe.sign(&mut req).unwrap();
+    assert!(stripe.verify(&req).is_ok());
+    req.set_header("X-Timestamp", "1700000001");
+    assert!(stripe.verify(&req).is_err());
+  }
+}
@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, ErrorKind, Method, Middleware, Request, Response, Status, Tenant};
+
+pub const TENANCY_MW_NAME: &str = "Tenancy";
+
+/// Header store routes read the current tenant from, once resolved
+pub const TENANT_HEADER: &str = "X-Mock-Tenant";
+
+/// How the tenant of a request is found: a header, or a path segment
+/// following `prefix` (`/t/{tenant}/users` with a `/t` prefix).
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Tenancy {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub header: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub prefix: Option<String>,
+}
+
+impl Tenancy {
+  /// Tenant of `req`, along with the path left once the tenant segment is stripped
+  pub fn resolve(&self, req: &Request) -> Option<(String, Option<String>)> {
+    if let (Some(prefix), Some(target)) = (
+      &self.prefix,
+      req.start_line().as_request().map(|s| &s.target),
+    ) {
+      let rest = target
+        .strip_prefix(prefix.trim_end_matches('/'))
+        .and_then(|rest| rest.strip_prefix('/'));
+      if let Some(rest) = rest {
+        let (tenant, rest) = rest.split_at(rest.find(['/', '?']).unwrap_or(rest.len()));
+        if !tenant.is_empty() {
+          let target = match rest.starts_with('/') {
+            true => rest.to_string(),
+            false => format!("/{}", rest),
+          };
+          return Some((tenant.to_string(), Some(target)));
+        }
+      }
+    }
+    let header = self.header.as_ref()?;
+    req.header(header).map(|tenant| (tenant.to_string(), None))
+  }
+
+  /// Reject tenant names which could escape the tenants directory
+  pub fn validate(tenant: &str) -> crate::Result<()> {
+    match tenant.is_empty() || tenant.contains(['/', '\\']) || tenant.starts_with('.') {
+      true => Err(Error::new(
+        ErrorKind::Api(Status::BadRequest),
+        Some(format!("invalid tenant '{}'", tenant)),
+        None,
+      )),
+      false => Ok(()),
+    }
+  }
+}
+
+/// Resolves the tenant of each request into [`TENANT_HEADER`], stripping the
+/// tenant path segment so routes are matched as if unscoped.
+pub struct TenancyMiddleware {
+  name: String,
+  tenancy: Tenancy,
+}
+
+impl TenancyMiddleware {
+  pub fn new(tenancy: Tenancy) -> Self {
+    Self {
+      name: TENANCY_MW_NAME.to_string(),
+      tenancy,
+    }
+  }
+}
+
+impl Middleware for TenancyMiddleware {
+  fn name(&self) -> &String {
+    &self.name
+  }
+
+  fn supported_methods(&self) -> Vec<Method> {
+    vec![]
+  }
+
+  fn prepare(&self, request: &mut Request) -> crate::Result<()> {
+    request.remove_header(TENANT_HEADER);
+    if let Some((tenant, target)) = self.tenancy.resolve(request) {
+      if let (Some(target), Some(start)) = (target, request.start_line_mut().as_request_mut()) {
+        start.target = target;
+      }
+      request.set_header(TENANT_HEADER, &tenant);
+      request.extensions_mut().insert(Tenant(tenant));
+    }
+    Ok(())
+  }
+
+  fn execute(&self, _request: &Request, response: Response) -> crate::Result<Response> {
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Middleware, Request, Tenant};
+
+  use super::{Tenancy, TenancyMiddleware, TENANT_HEADER};
+
+  #[test]
+  fn resolve() {
+    let mw = TenancyMiddleware::new(Tenancy {
+      header: Some("X-Tenant".to_string()),
+      prefix: Some("/t".to_string()),
+    });
+    let mut req = Request::new(Method::Get, "/t/acme/users?id=1");
+    mw.prepare(&mut req).unwrap();
+    assert_eq!(req.path(), Some("/users"));
+    assert_eq!(req.header(TENANT_HEADER), Some("acme"));
+    assert_eq!(
+      req.extensions().get::<Tenant>(),
+      Some(&Tenant("acme".to_string()))
+    );
+
+    let mut req = Request::new(Method::Get, "/users").with_header("X-Tenant", "globex");
+    mw.prepare(&mut req).unwrap();
+    assert_eq!(req.path(), Some("/users"));
+    assert_eq!(req.header(TENANT_HEADER), Some("globex"));
+
+    let mut req = Request::new(Method::Get, "/users").with_header(TENANT_HEADER, "spoofed");
+    mw.prepare(&mut req).unwrap();
+    assert_eq!(req.header(TENANT_HEADER), None);
+    assert!(Tenancy::validate("../etc").is_err());
+  }
+}
@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Buffer, Error, ErrorKind, MediaType, Method, Middleware, Request, Response, Value};
+
+pub const TRANSFORM_MW_NAME: &str = "Transform";
+
+/// One step of a JSONPath expression
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Step {
+  Key(String),
+  Index(usize),
+  All,
+}
+
+/// Parse the JSONPath subset used by transforms: `$.a.b`, `$.items[0]`,
+/// `$.items[*].id`, `$['a key']`, the leading `$.` being optional.
+pub(crate) fn parse_path(path: &str) -> crate::Result<Vec<Step>> {
+  let invalid = || {
+    Error::new(
+      ErrorKind::Parse,
+      Some(format!("invalid JSONPath '{}'", path)),
+      None,
+    )
+  };
+  let mut steps = vec![];
+  let mut rest = path.strip_prefix('$').unwrap_or(path);
+  while !rest.is_empty() {
+    if let Some(bracket) = rest.strip_prefix('[') {
+      let end = bracket.find(']').ok_or_else(invalid)?;
+      let inner = &bracket[..end];
+      steps.push(match inner {
+        "*" => Step::All,
+        quoted if quoted.len() >= 2 && (quoted.starts_with('\'') || quoted.starts_with('"')) => {
+          Step::Key(quoted[1..quoted.len() - 1].to_string())
+        }
+        index => Step::Index(index.parse().map_err(|_| invalid())?),
+      });
+      rest = &bracket[end + 1..];
+      continue;
+    }
+    let key = rest.strip_prefix('.').unwrap_or(rest);
+    let end = key.find(['.', '[']).unwrap_or(key.len());
+    if end == 0 {
+      return Err(invalid());
+    }
+    steps.push(match &key[..end] {
+      "*" => Step::All,
+      key => Step::Key(key.to_string()),
+    });
+    rest = &key[end..];
+  }
+  match steps.is_empty() {
+    true => Err(invalid()),
+    false => Ok(steps),
+  }
+}
+
+/// Nodes `steps` points at
+pub(crate) fn select<'a>(value: &'a Value, steps: &[Step]) -> Vec<&'a Value> {
+  let (step, rest) = match steps.split_first() {
+    Some(split) => split,
+    None => return vec![value],
+  };
+  let children: Vec<&Value> = match (value, step) {
+    (Value::Map(map), Step::Key(key)) => map.get(key).into_iter().collect(),
+    (Value::Array(items), Step::Index(i)) => items.get(*i).into_iter().collect(),
+    (Value::Array(items), Step::All) => items.iter().collect(),
+    (Value::Map(map), Step::All) => map.values().collect(),
+    _ => vec![],
+  };
+  children
+    .into_iter()
+    .flat_map(|child| select(child, rest))
+    .collect()
+}
+
+/// Call `f` with every parent of the nodes `steps` points at, along with the
+/// last step. Missing objects along the way are created when `create` is set.
+pub(crate) fn visit_parents<F: FnMut(&mut Value, &Step)>(
+  value: &mut Value,
+  steps: &[Step],
+  create: bool,
+  f: &mut F,
+) {
+  let (step, rest) = match steps.split_first() {
+    Some((step, [])) => return f(value, step),
+    Some(split) => split,
+    None => return,
+  };
+  match (value, step) {
+    (Value::Map(map), Step::Key(key)) => {
+      if create && !map.contains_key(key) {
+        map.insert(key.clone(), Value::Map(Default::default()));
+      }
+      if let Some(child) = map.get_mut(key) {
+        visit_parents(child, rest, create, f);
+      }
+    }
+    (Value::Array(items), Step::Index(i)) => {
+      if let Some(child) = items.get_mut(*i) {
+        visit_parents(child, rest, create, f);
+      }
+    }
+    (Value::Array(items), Step::All) => {
+      for child in items {
+        visit_parents(child, rest, create, f);
+      }
+    }
+    (Value::Map(map), Step::All) => {
+      for child in map.values_mut() {
+        visit_parents(child, rest, create, f);
+      }
+    }
+    _ => {}
+  }
+}
+
+/// Body edits addressed by JSONPath, applied in order: remove, rename, add, set.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BodyRuleSet {
+  /// Fields to strip
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub remove: Vec<String>,
+  /// Field path to its new name, kept within the same object
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub rename: BTreeMap<String, String>,
+  /// Fields added unless already present
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub add: BTreeMap<String, Value>,
+  /// Fields set, overriding any existing value
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub set: BTreeMap<String, Value>,
+}
+
+impl BodyRuleSet {
+  pub fn is_empty(&self) -> bool {
+    self.remove.is_empty() && self.rename.is_empty() && self.add.is_empty() && self.set.is_empty()
+  }
+
+  pub fn apply(&self, body: &mut Value) -> crate::Result<()> {
+    for path in &self.remove {
+      visit_parents(
+        body,
+        &parse_path(path)?,
+        false,
+        &mut |parent, step| match (parent, step) {
+          (Value::Map(map), Step::Key(key)) => {
+            map.remove(key);
+          }
+          (Value::Map(map), Step::All) => map.clear(),
+          (Value::Array(items), Step::Index(i)) if *i < items.len() => {
+            items.remove(*i);
+          }
+          (Value::Array(items), Step::All) => items.clear(),
+          _ => {}
+        },
+      );
+    }
+    for (path, to) in &self.rename {
+      visit_parents(body, &parse_path(path)?, false, &mut |parent, step| {
+        if let (Value::Map(map), Step::Key(key)) = (parent, step) {
+          if let Some(value) = map.remove(key) {
+            map.insert(to.clone(), value);
+          }
+        }
+      });
+    }
+    for (overwrite, rules) in [(false, &self.add), (true, &self.set)] {
+      for (path, value) in rules {
+        visit_parents(
+          body,
+          &parse_path(path)?,
+          true,
+          &mut |parent, step| match (parent, step) {
+            (Value::Map(map), Step::Key(key)) if overwrite || !map.contains_key(key) => {
+              map.insert(key.clone(), value.clone());
+            }
+            (Value::Array(items), Step::Index(i)) if overwrite && *i < items.len() => {
+              items[*i] = value.clone();
+            }
+            _ => {}
+          },
+        );
+      }
+    }
+    Ok(())
+  }
+
+  /// Apply the rules to a JSON body, other bodies being left untouched
+  pub fn apply_to(&self, buf: &mut Buffer) -> crate::Result<()> {
+    let json = buf
+      .header("Content-Type")
+      .and_then(|ct| ct.parse::<MediaType>().ok())
+      .is_some_and(|ct| ct.is_json());
+    if self.is_empty() || !json || buf.body().is_empty() {
+      return Ok(());
+    }
+    #[cfg(feature = "json")]
+    {
+      let mut body = Value::try_from_json(serde_json::from_slice(buf.body())?)?;
+      self.apply(&mut body)?;
+      let body = serde_json::to_vec(&body.to_json())?;
+      *buf = std::mem::take(buf).with_body_bytes(body);
+    }
+    Ok(())
+  }
+}
+
+/// Declarative body transformations, for incoming requests before routing and
+/// outgoing responses, as an API gateway would.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TransformRules {
+  #[serde(default, skip_serializing_if = "BodyRuleSet::is_empty")]
+  pub request: BodyRuleSet,
+  #[serde(default, skip_serializing_if = "BodyRuleSet::is_empty")]
+  pub response: BodyRuleSet,
+}
+
+impl TransformRules {
+  pub fn is_empty(&self) -> bool {
+    self.request.is_empty() && self.response.is_empty()
+  }
+}
+
+/// Applies the globally configured [`TransformRules`] to every exchange.
+pub struct TransformMiddleware {
+  name: String,
+  rules: TransformRules,
+}
+
+impl TransformMiddleware {
+  pub fn new(rules: TransformRules) -> Self {
+    Self {
+      name: TRANSFORM_MW_NAME.to_string(),
+      rules,
+    }
+  }
+}
+
+impl Middleware for TransformMiddleware {
+  fn name(&self) -> &String {
+    &self.name
+  }
+
+  fn supported_methods(&self) -> Vec<Method> {
+    vec![]
+  }
+
+  fn prepare(&self, request: &mut Request) -> crate::Result<()> {
+    self.rules.request.apply_to(request)
+  }
+
+  fn execute(&self, _request: &Request, response: Response) -> crate::Result<Response> {
+    Ok(response)
+  }
+
+  fn finish(&self, _request: &Request, mut response: Response) -> crate::Result<Response> {
+    self.rules.response.apply_to(&mut response)?;
+    Ok(response)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use crate::Value;
+
+  use super::{parse_path, BodyRuleSet, Step};
+
+  #[test]
+  fn paths() {
+    assert_eq!(
+      parse_path("$.items[*].id").unwrap(),
+      vec![
+        Step::Key("items".to_string()),
+        Step::All,
+        Step::Key("id".to_string())
+      ]
+    );
+    assert_eq!(
+      parse_path("$['a key'][2]").unwrap(),
+      vec![Step::Key("a key".to_string()), Step::Index(2)]
+    );
+    assert!(parse_path("$").is_err());
+    assert!(parse_path("$.items[x]").is_err());
+  }
+
+  #[cfg(feature = "json")]
+  #[test]
+  fn apply() {
+    let mut body = Value::try_from_json(serde_json::json!({
+      "user_name": "joe",
+      "password": "secret",
+      "items": [{"id": 1, "internal": true}, {"id": 2}]
+    }))
+    .unwrap();
+    let rules = BodyRuleSet {
+      remove: vec!["$.password".to_string(), "$.items[*].internal".to_string()],
+      rename: BTreeMap::from([("$.user_name".to_string(), "userName".to_string())]),
+      add: BTreeMap::from([
+        ("$.meta.source".to_string(), Value::from("gateway")),
+        ("$.userName".to_string(), Value::from("ignored")),
+      ]),
+      set: BTreeMap::from([("$.items[0].id".to_string(), Value::from(10))]),
+    };
+    rules.apply(&mut body).unwrap();
+    assert_eq!(
+      body.to_json(),
+      serde_json::json!({
+        "userName": "joe",
+        "items": [{"id": 10}, {"id": 2}],
+        "meta": {"source": "gateway"}
+      })
+    );
+  }
+}
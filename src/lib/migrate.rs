@@ -0,0 +1,177 @@
+use std::path::Path;
+
+use log::warn;
+
+use crate::{document_formats, Error, ErrorKind, Value};
+
+/// Version of the config format written by this release
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Upgrade of config documents from one version to the next.
+pub struct Migration {
+  /// Version upgraded from, to the next one
+  pub from: u32,
+  /// Rewrite the document in place, describing each change made
+  pub apply: fn(&mut Value) -> Vec<String>,
+}
+
+/// Every migration, by increasing version
+pub fn migrations() -> Vec<Migration> {
+  vec![Migration {
+    from: 0,
+    apply: normalize_routes,
+  }]
+}
+
+/// Routes listing a single method as a string, or naming their kind in
+/// lowercase as `mocker` prints it, in the shape routes now have
+fn normalize_routes(doc: &mut Value) -> Vec<String> {
+  let mut changes = vec![];
+  let routes = match doc {
+    Value::Map(map) => map.get_mut("routes"),
+    _ => None,
+  };
+  if let Some(Value::Array(routes)) = routes {
+    for (i, route) in routes.iter_mut().enumerate() {
+      let route = match route {
+        Value::Array(route) => route,
+        _ => continue,
+      };
+      if let Some(Value::String(methods)) = route.first().cloned() {
+        let list = methods
+          .split(',')
+          .map(|m| Value::from(m.trim().to_ascii_uppercase()))
+          .collect::<Vec<_>>();
+        changes.push(format!("routes[{}]: methods `{}` made a list", i, methods));
+        route[0] = Value::Array(list);
+      }
+      if let Some(Value::Map(kind)) = route.get_mut(2) {
+        if let Some(Value::String(name)) = kind.get_mut("type") {
+          if name.starts_with(|c: char| c.is_ascii_lowercase()) {
+            let upgraded = format!("{}{}", name[..1].to_ascii_uppercase(), &name[1..]);
+            changes.push(format!(
+              "routes[{}]: type `{}` renamed `{}`",
+              i, name, upgraded
+            ));
+            *name = upgraded;
+          }
+        }
+      }
+    }
+  }
+  changes
+}
+
+/// Version `doc` was written for, `0` for documents predating versioning
+pub fn config_version(doc: &Value) -> u32 {
+  match doc {
+    Value::Map(map) => match map.get("version") {
+      Some(Value::Unsigned(v)) => *v as u32,
+      Some(Value::Integer(v)) => *v as u32,
+      _ => 0,
+    },
+    _ => 0,
+  }
+}
+
+/// Upgrade `doc` to [`CONFIG_VERSION`], returning the changes made
+pub fn migrate(doc: &mut Value) -> crate::Result<Vec<String>> {
+  let version = config_version(doc);
+  if version > CONFIG_VERSION {
+    return Err(Error::new(
+      ErrorKind::Parse,
+      Some(format!(
+        "config version {} is newer than the supported {}, upgrade mocker",
+        version, CONFIG_VERSION
+      )),
+      None,
+    ));
+  }
+  let mut changes = vec![];
+  for migration in migrations().iter().filter(|m| m.from >= version) {
+    changes.extend((migration.apply)(doc));
+  }
+  if version < CONFIG_VERSION {
+    if let Value::Map(map) = doc {
+      map.insert("version".to_string(), Value::from(CONFIG_VERSION as u64));
+    }
+  }
+  Ok(changes)
+}
+
+/// `doc` as loaded from `path`, upgraded with a warning for every change,
+/// or `None` if already up to date
+pub fn upgrade(mut doc: Value, path: &Path) -> crate::Result<Option<Value>> {
+  if config_version(&doc) == CONFIG_VERSION {
+    return Ok(None);
+  }
+  for change in migrate(&mut doc)? {
+    warn!("{}: {}", path.display(), change);
+  }
+  warn!(
+    "{}: loaded as config version {}, run `mocker migrate` to update the file",
+    path.display(),
+    CONFIG_VERSION
+  );
+  Ok(Some(doc))
+}
+
+/// Upgrade the config file at `path` in place, returning the changes made
+pub fn migrate_file<P: AsRef<Path>>(path: P) -> crate::Result<Vec<String>> {
+  let path = path.as_ref();
+  let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+  let fmt = document_formats()
+    .into_iter()
+    .find(|fmt| fmt.exts.iter().any(|e| e.eq_ignore_ascii_case(ext)))
+    .ok_or_else(|| {
+      Error::new(
+        ErrorKind::IO,
+        Some(format!("{}: unknown config format", path.display())),
+        None,
+      )
+    })?;
+  let mut doc = (fmt.deserialize)(path)?;
+  if config_version(&doc) == CONFIG_VERSION {
+    return Ok(vec![]);
+  }
+  let mut changes = migrate(&mut doc)?;
+  changes.push(format!("version set to {}", CONFIG_VERSION));
+  (fmt.serialize)(path, &doc)?;
+  Ok(changes)
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+  use crate::{Config, Value, CONFIG_VERSION};
+
+  use super::{migrate, migrate_file};
+
+  #[test]
+  fn upgrade() {
+    let legacy = serde_json::json!({
+      "port": 9000,
+      "routes": [
+        ["get", "/health", {"type": "fixture", "body": "ok"}],
+        [["POST"], "/users", {"type": "Fixture", "status": 201}]
+      ]
+    });
+    let mut doc = Value::try_from_json(legacy.clone()).unwrap();
+    let changes = migrate(&mut doc).unwrap();
+    assert_eq!(changes.len(), 2);
+    let doc = doc.to_json();
+    assert_eq!(doc["version"], CONFIG_VERSION);
+    assert_eq!(doc["routes"][0][0], serde_json::json!(["GET"]));
+    assert_eq!(doc["routes"][0][2]["type"], "Fixture");
+
+    let path = std::env::temp_dir().join(format!("mocker-migrate-{}.json", std::process::id()));
+    std::fs::write(&path, legacy.to_string()).unwrap();
+    let config = Config::load(&path).unwrap();
+    assert_eq!(config.routes.len(), 2);
+    assert_eq!(migrate_file(&path).unwrap().len(), 3);
+    assert!(migrate_file(&path).unwrap().is_empty());
+    std::fs::remove_file(&path).unwrap();
+
+    let mut future = Value::try_from_json(serde_json::json!({"version": 99})).unwrap();
+    assert!(migrate(&mut future).is_err());
+  }
+}
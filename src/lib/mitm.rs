@@ -0,0 +1,459 @@
+use std::{
+  collections::HashMap,
+  fs,
+  io::Write,
+  net::TcpStream,
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+};
+
+use log::info;
+use rcgen::{
+  BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType, GeneralSubtree,
+  IsCa, KeyPair, KeyUsagePurpose, NameConstraints,
+};
+use rustls::{
+  client::danger::HandshakeSignatureValid,
+  crypto::{
+    ring::default_provider, verify_tls12_signature, verify_tls13_signature, CryptoProvider,
+  },
+  pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime},
+  server::danger::{ClientCertVerified, ClientCertVerifier},
+  ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, ServerConfig,
+  ServerConnection, SignatureScheme, StreamOwned,
+};
+
+use crate::{Error, ErrorKind, TlsInfo};
+
+fn tls_error<E: std::fmt::Display>(e: E) -> Error {
+  Error::new(ErrorKind::IO, Some(format!("TLS: {}", e)), None)
+}
+
+/// Write `contents` to a new file only its owner can read
+fn write_private(path: &Path, contents: &str) -> crate::Result<()> {
+  let mut options = fs::OpenOptions::new();
+  options.write(true).create_new(true);
+  #[cfg(unix)]
+  std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+  options.open(path)?.write_all(contents.as_bytes())?;
+  Ok(())
+}
+
+/// Domains the intercept rules stay within, `None` when one of them may
+/// match any host or an address
+fn permitted_domains(intercept: &[String]) -> Option<Vec<String>> {
+  let mut domains = vec![];
+  for rule in intercept {
+    let host = rule.split('/').next().unwrap_or_default();
+    if host.parse::<std::net::IpAddr>().is_ok() {
+      return None;
+    }
+    let labels = host
+      .split('.')
+      .skip_while(|label| label.contains(['*', '?', '[']))
+      .collect::<Vec<_>>();
+    if labels.is_empty() || labels.iter().any(|label| label.contains(['*', '?', '['])) {
+      return None;
+    }
+    let domain = labels.join(".").to_ascii_lowercase();
+    if !domains.contains(&domain) {
+      domains.push(domain);
+    }
+  }
+  Some(domains).filter(|domains| !domains.is_empty())
+}
+
+/// Certificate authority issuing, on the fly, certificates for the hosts the
+/// forward proxy intercepts. Clients must trust its certificate, written as
+/// `mocker-ca.pem` next to its key, and name-constrained to the domains
+/// intercepted when it was generated.
+pub struct Mitm {
+  ca_cert: Certificate,
+  ca_key: KeyPair,
+  ca_path: PathBuf,
+  provider: Arc<CryptoProvider>,
+  client_certs: bool,
+  configs: Mutex<HashMap<String, Arc<ServerConfig>>>,
+}
+
+impl Mitm {
+  const CERT_FILE: &'static str = "mocker-ca.pem";
+  const KEY_FILE: &'static str = "mocker-ca.key";
+
+  fn ca_params(intercept: &[String]) -> crate::Result<CertificateParams> {
+    let mut params = CertificateParams::new(vec![]).map_err(tls_error)?;
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, "mocker proxy CA");
+    name.push(DnType::OrganizationName, "mocker");
+    params.distinguished_name = name;
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    params.name_constraints = permitted_domains(intercept).map(|domains| NameConstraints {
+      permitted_subtrees: domains.into_iter().map(GeneralSubtree::DnsName).collect(),
+      excluded_subtrees: vec![],
+    });
+    Ok(params)
+  }
+
+  /// Authority whose key is kept in `dir`, generated on first use for the
+  /// hosts matched by the `intercept` rules
+  pub fn load_or_create<P: AsRef<Path>>(dir: P, intercept: &[String]) -> crate::Result<Self> {
+    let (cert_path, key_path) = (
+      dir.as_ref().join(Self::CERT_FILE),
+      dir.as_ref().join(Self::KEY_FILE),
+    );
+    let ca_key = match key_path.exists() {
+      true => KeyPair::from_pem(&fs::read_to_string(&key_path)?).map_err(tls_error)?,
+      false => {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(dir.as_ref())?;
+        let key = KeyPair::generate().map_err(tls_error)?;
+        write_private(&key_path, &key.serialize_pem())?;
+        key
+      }
+    };
+    // same key and name as the written certificate, so it verifies what is issued
+    let ca_cert = Self::ca_params(intercept)?
+      .self_signed(&ca_key)
+      .map_err(tls_error)?;
+    if !cert_path.exists() {
+      fs::write(&cert_path, ca_cert.pem())?;
+      info!(
+        "🔐 Generated the proxy CA, trust {} to intercept HTTPS traffic",
+        cert_path.display()
+      );
+    }
+    Ok(Self {
+      ca_cert,
+      ca_key,
+      ca_path: cert_path,
+      provider: Arc::new(default_provider()),
+      client_certs: false,
+      configs: Mutex::new(HashMap::new()),
+    })
+  }
+
+  /// Ask clients for a certificate, which is accepted whoever issued it and
+  /// only used to tell who they are
+  pub fn with_client_certs(mut self, client_certs: bool) -> Self {
+    self.client_certs = client_certs;
+    self
+  }
+
+  /// Certificate clients must trust
+  pub fn ca_path(&self) -> &Path {
+    &self.ca_path
+  }
+
+  /// TLS settings presenting a certificate for `host`, issued on first use
+  fn server_config(&self, host: &str) -> crate::Result<Arc<ServerConfig>> {
+    let mut configs = self.configs.lock()?;
+    if let Some(config) = configs.get(host) {
+      return Ok(config.clone());
+    }
+    let key = KeyPair::generate().map_err(tls_error)?;
+    let mut params = CertificateParams::new(vec![host.to_string()]).map_err(tls_error)?;
+    params.distinguished_name.push(DnType::CommonName, host);
+    let cert = params
+      .signed_by(&key, &self.ca_cert, &self.ca_key)
+      .map_err(tls_error)?;
+    let chain = vec![cert.der().clone(), self.ca_cert.der().clone()];
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+    let builder = ServerConfig::builder_with_provider(self.provider.clone())
+      .with_safe_default_protocol_versions()
+      .map_err(tls_error)?;
+    let builder = match self.client_certs {
+      true => builder.with_client_cert_verifier(Arc::new(AnyClientCert(self.provider.clone()))),
+      false => builder.with_no_client_auth(),
+    };
+    let config = builder.with_single_cert(chain, key).map_err(tls_error)?;
+    let config = Arc::new(config);
+    configs.insert(host.to_string(), config.clone());
+    Ok(config)
+  }
+
+  /// Terminate the TLS connection a client opened to `host`
+  pub fn accept(
+    &self,
+    host: &str,
+    stream: TcpStream,
+  ) -> crate::Result<StreamOwned<ServerConnection, TcpStream>> {
+    let conn = ServerConnection::new(self.server_config(host)?).map_err(tls_error)?;
+    Ok(StreamOwned::new(conn, stream))
+  }
+
+  /// Open a TLS connection to the real `host`, trusting the usual web roots
+  pub fn connect(host: &str, port: u16) -> crate::Result<StreamOwned<ClientConnection, TcpStream>> {
+    let roots = RootCertStore {
+      roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let config = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+      .with_safe_default_protocol_versions()
+      .map_err(tls_error)?
+      .with_root_certificates(roots)
+      .with_no_client_auth();
+    let name = ServerName::try_from(host.to_string()).map_err(tls_error)?;
+    let conn = ClientConnection::new(Arc::new(config), name).map_err(tls_error)?;
+    Ok(StreamOwned::new(conn, TcpStream::connect((host, port))?))
+  }
+}
+
+/// Details of an established connection terminated by the proxy
+pub fn tls_info(conn: &ServerConnection) -> TlsInfo {
+  TlsInfo {
+    sni: conn.server_name().map(String::from),
+    protocol: conn.protocol_version().map(|v| format!("{:?}", v)),
+    cipher: conn
+      .negotiated_cipher_suite()
+      .map(|s| format!("{:?}", s.suite())),
+    alpn: conn
+      .alpn_protocol()
+      .map(|p| String::from_utf8_lossy(p).to_string()),
+    client_subject: conn
+      .peer_certificates()
+      .and_then(|certs| certs.first())
+      .and_then(|cert| cert_subject(cert)),
+  }
+}
+
+/// Optional client authentication trusting any certificate, as long as the
+/// client proves it holds its key
+#[derive(Debug)]
+struct AnyClientCert(Arc<CryptoProvider>);
+
+impl ClientCertVerifier for AnyClientCert {
+  fn client_auth_mandatory(&self) -> bool {
+    false
+  }
+
+  fn root_hint_subjects(&self) -> &[rustls::DistinguishedName] {
+    &[]
+  }
+
+  fn verify_client_cert(
+    &self,
+    _end_entity: &CertificateDer<'_>,
+    _intermediates: &[CertificateDer<'_>],
+    _now: UnixTime,
+  ) -> Result<ClientCertVerified, rustls::Error> {
+    Ok(ClientCertVerified::assertion())
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    verify_tls12_signature(
+      message,
+      cert,
+      dss,
+      &self.0.signature_verification_algorithms,
+    )
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    message: &[u8],
+    cert: &CertificateDer<'_>,
+    dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, rustls::Error> {
+    verify_tls13_signature(
+      message,
+      cert,
+      dss,
+      &self.0.signature_verification_algorithms,
+    )
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    self.0.signature_verification_algorithms.supported_schemes()
+  }
+}
+
+/// Split the DER element at the start of `der` into its tag, its content and
+/// what follows it
+fn der_element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+  let (&tag, rest) = der.split_first()?;
+  let (&first, rest) = rest.split_first()?;
+  let (len, rest) = match first {
+    0..=0x7f => (first as usize, rest),
+    _ => {
+      let count = (first & 0x7f) as usize;
+      if count == 0 || count > 4 || rest.len() < count {
+        return None;
+      }
+      let len = rest[..count]
+        .iter()
+        .fold(0usize, |len, b| (len << 8) | *b as usize);
+      (len, &rest[count..])
+    }
+  };
+  (rest.len() >= len).then(|| (tag, &rest[..len], &rest[len..]))
+}
+
+/// Subject of a DER certificate, as `CN=svc, O=acme`
+fn cert_subject(der: &[u8]) -> Option<String> {
+  let (_, cert, _) = der_element(der)?;
+  let (_, tbs, _) = der_element(cert)?;
+  let mut fields = vec![];
+  let mut rest = tbs;
+  while !rest.is_empty() {
+    let (tag, content, next) = der_element(rest)?;
+    // [0] version being optional
+    if tag != 0xa0 {
+      fields.push(content);
+    }
+    rest = next;
+  }
+  // serial number, signature algorithm, issuer, validity, subject
+  let mut rdns = *fields.get(4)?;
+  let mut parts = vec![];
+  while !rdns.is_empty() {
+    let (_, set, next) = der_element(rdns)?;
+    let (_, attribute, _) = der_element(set)?;
+    let (_, oid, value) = der_element(attribute)?;
+    let (_, value, _) = der_element(value)?;
+    let name = match oid {
+      [0x55, 0x04, 0x03] => "CN".to_string(),
+      [0x55, 0x04, 0x06] => "C".to_string(),
+      [0x55, 0x04, 0x07] => "L".to_string(),
+      [0x55, 0x04, 0x08] => "ST".to_string(),
+      [0x55, 0x04, 0x0a] => "O".to_string(),
+      [0x55, 0x04, 0x0b] => "OU".to_string(),
+      oid => oid.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+    };
+    parts.push(format!("{}={}", name, String::from_utf8_lossy(value)));
+    rdns = next;
+  }
+  Some(parts.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    io::{Read, Write},
+    net::{Ipv4Addr, TcpStream},
+    sync::Arc,
+  };
+
+  use rustls::{
+    crypto::ring::default_provider, pki_types::ServerName, ClientConfig, ClientConnection,
+    RootCertStore, StreamOwned,
+  };
+
+  use rcgen::{CertificateParams, DnType, KeyPair};
+  use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+
+  use crate::{Config, Engine, JournalQuery, Method, Proxy, ProxyConfig, Route, RouteKind, Value};
+
+  use super::Mitm;
+
+  #[test]
+  fn intercept_https() {
+    let dir = std::env::temp_dir().join(format!("mocker-mitm-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let config = ProxyConfig {
+      port: 0,
+      intercept: vec!["api.example.test".into()],
+      mitm: true,
+      client_certs: true,
+      ca_dir: Some(dir.clone()),
+      ..Default::default()
+    };
+    let engine = Engine::new(&Config {
+      routes: vec![Route::new(
+        vec![Method::Get],
+        "/hello",
+        RouteKind::Fixture {
+          status: 200,
+          headers: Default::default(),
+          body: Some(Value::from(
+            "secure stub for {{request.tls.client_subject}} via {{request.tls.sni}}",
+          )),
+          file: None,
+          template: true,
+        },
+      )],
+      ..Default::default()
+    })
+    .unwrap();
+    let proxy = Proxy::new(config, engine.clone())
+      .unwrap()
+      .spawn(Ipv4Addr::LOCALHOST.into())
+      .unwrap();
+    // reloading keeps the same authority
+    let ca = Mitm::load_or_create(&dir, &["api.example.test".to_string()]).unwrap();
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let key = std::fs::metadata(dir.join(Mitm::KEY_FILE)).unwrap();
+      assert_eq!(key.permissions().mode() & 0o777, 0o600);
+    }
+    let mut roots = RootCertStore::empty();
+    roots.add(ca.ca_cert.der().clone()).unwrap();
+
+    let mut stream = TcpStream::connect(proxy).unwrap();
+    stream
+      .write_all(b"CONNECT api.example.test:443 HTTP/1.1\r\n\r\n")
+      .unwrap();
+    let mut established = [0; 39];
+    stream.read_exact(&mut established).unwrap();
+    assert!(established.starts_with(b"HTTP/1.1 200"));
+    let key = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(vec![]).unwrap();
+    params.distinguished_name.push(DnType::CommonName, "svc");
+    params
+      .distinguished_name
+      .push(DnType::OrganizationName, "acme");
+    let cert = params.self_signed(&key).unwrap();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der()));
+    let client = ClientConfig::builder_with_provider(Arc::new(default_provider()))
+      .with_safe_default_protocol_versions()
+      .unwrap()
+      .with_root_certificates(roots)
+      .with_client_auth_cert(vec![cert.der().clone()], key)
+      .unwrap();
+    let name = ServerName::try_from("api.example.test").unwrap();
+    let conn = ClientConnection::new(Arc::new(client), name).unwrap();
+    let mut tls = StreamOwned::new(conn, stream);
+    tls
+      .write_all(b"GET /hello HTTP/1.1\r\nHost: api.example.test\r\nConnection: close\r\n\r\n")
+      .unwrap();
+    let mut res = vec![];
+    tls.read_to_end(&mut res).unwrap();
+    let res = String::from_utf8_lossy(&res);
+    assert!(
+      res.ends_with("secure stub for CN=svc, O=acme via api.example.test"),
+      "{}",
+      res
+    );
+    let entries = engine.journal().query(&JournalQuery::default()).unwrap();
+    let tls = entries[0].tls.as_ref().unwrap();
+    assert_eq!(tls.protocol.as_deref(), Some("TLSv1_3"));
+    assert!(tls.cipher.as_ref().unwrap().starts_with("TLS13_"));
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn permitted_domains() {
+    let rules = |rules: &[&str]| {
+      super::permitted_domains(&rules.iter().map(|r| r.to_string()).collect::<Vec<_>>())
+    };
+    assert_eq!(
+      rules(&["api.example.test/v1/*", "*.Acme.io", "acme.io"]),
+      Some(vec!["api.example.test".to_string(), "acme.io".to_string()])
+    );
+    assert_eq!(
+      rules(&["api-*.example.test"]),
+      Some(vec!["example.test".to_string()])
+    );
+    assert_eq!(rules(&["api.example.test", "*"]), None);
+    assert_eq!(rules(&["10.0.0.1"]), None);
+    assert_eq!(rules(&[]), None);
+  }
+}
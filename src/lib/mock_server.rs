@@ -0,0 +1,148 @@
+use log::debug;
+
+use crate::{Client, Error, ErrorKind, Method, Request, Status, ADMIN_PREFIX};
+
+/// A test run's share of a long-lived mocker server, its endpoints mounted
+/// under a prefix of its own. Test binaries sharing the server never see
+/// each other's store data. The namespace is dropped along with the handle.
+pub struct MockServer {
+  url: String,
+  id: String,
+  prefix: String,
+}
+
+impl MockServer {
+  /// Allocate a namespace on the server at `url`, which must have
+  /// `namespaces` enabled
+  pub fn start<U: AsRef<str>>(url: U) -> crate::Result<Self> {
+    let url = url.as_ref().trim_end_matches('/').to_string();
+    let res = Client::from_url(&url)?.send(&Request::new(
+      Method::Post,
+      format!("{}/namespaces", ADMIN_PREFIX),
+    ))?;
+    if res.status() != Status::Created.code() {
+      return Err(Error::new(
+        ErrorKind::Api(Status::BadGatewayOuProxyError),
+        Some(format!(
+          "{}: cannot allocate a namespace ({}): {}",
+          url,
+          res.status(),
+          String::from_utf8_lossy(res.body())
+        )),
+        None,
+      ));
+    }
+    let body: serde_json::Value = serde_json::from_slice(res.body())?;
+    let field = |name: &str| {
+      body[name].as_str().map(str::to_string).ok_or_else(|| {
+        Error::new(
+          ErrorKind::Parse,
+          Some(format!("{}: namespace without `{}`", url, name)),
+          None,
+        )
+      })
+    };
+    Ok(Self {
+      id: field("id")?,
+      prefix: field("prefix")?,
+      url,
+    })
+  }
+
+  pub fn id(&self) -> &str {
+    &self.id
+  }
+
+  /// Path prefix every endpoint is mounted under for this run
+  pub fn prefix(&self) -> &str {
+    &self.prefix
+  }
+
+  /// Base url of this run's endpoints
+  pub fn url(&self) -> String {
+    format!("{}{}", self.url, self.prefix)
+  }
+
+  /// Client sending requests to this run's endpoints
+  pub fn client(&self) -> crate::Result<Client> {
+    Client::from_url(self.url())
+  }
+}
+
+impl Drop for MockServer {
+  fn drop(&mut self) {
+    let target = format!("{}/namespaces/{}", ADMIN_PREFIX, self.id);
+    let res =
+      Client::from_url(&self.url).and_then(|c| c.send(&Request::new(Method::Delete, target)));
+    if let Err(e) = res {
+      debug!("Failed to drop namespace '{}': {}", self.id, e);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{net::TcpListener, thread};
+
+  use crate::{Config, Method, Request, Route, RouteKind, Server};
+
+  use super::MockServer;
+
+  #[test]
+  fn namespaces() {
+    let port = TcpListener::bind("127.0.0.1:0")
+      .unwrap()
+      .local_addr()
+      .unwrap()
+      .port();
+    let path = std::env::temp_dir().join(format!("mocker-ns-{}.json", std::process::id()));
+    std::fs::write(&path, "[]").unwrap();
+    let config = Config {
+      port,
+      namespaces: true,
+      routes: vec![Route::new(
+        vec![Method::Get, Method::Post],
+        "/users",
+        RouteKind::Store {
+          path: path.clone(),
+          identifier: "id".to_string(),
+          parent: None,
+        },
+      )],
+      ..Default::default()
+    };
+    let server = Server::new(config).unwrap();
+    thread::spawn(move || server.listen());
+    let url = format!("http://127.0.0.1:{}", port);
+    // the server may take a moment to listen, but not forever
+    let start = || {
+      for _ in 0..100 {
+        match MockServer::start(&url) {
+          Ok(server) => return server,
+          Err(_) => thread::sleep(std::time::Duration::from_millis(20)),
+        }
+      }
+      MockServer::start(&url).unwrap()
+    };
+    let (a, b) = (start(), start());
+    assert_ne!(a.prefix(), b.prefix());
+    let created = a
+      .client()
+      .unwrap()
+      .send(
+        &Request::new(Method::Post, "/users")
+          .with_header("Content-Type", "application/json")
+          .with_body(r#"{"id": 1}"#),
+      )
+      .unwrap();
+    assert_eq!(created.status(), 201);
+    let get = |server: &MockServer| {
+      let req = Request::new(Method::Get, "/users?id=1");
+      server.client().unwrap().send(&req).unwrap().status()
+    };
+    assert_eq!(get(&a), 200);
+    assert_eq!(get(&b), 404);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]");
+    std::fs::remove_file(&path).unwrap();
+  }
+}
@@ -1,32 +1,200 @@
 #[macro_use]
 extern crate strum;
 
+pub mod admin;
+pub mod audit;
+pub mod auth;
+#[cfg(feature = "server")]
+pub mod bench;
+pub mod binary;
+pub mod body_parser;
+#[cfg(feature = "json")]
+pub mod capture;
+#[cfg(feature = "server")]
+pub mod client;
+pub mod computed;
+pub mod concurrency;
 pub mod config;
+pub mod contract;
+#[cfg(feature = "dashboard")]
+pub mod dashboard;
+pub mod diff;
+pub mod engine;
 pub mod error;
+pub mod error_pages;
+pub mod experiment;
+pub mod explain;
+pub mod extensions;
+pub mod fault;
+#[cfg(feature = "js")]
+pub mod fetch;
 pub mod file_fmt;
+pub mod generate;
+pub mod hooks;
+pub mod hosts;
 pub mod http;
+pub mod hypermedia;
+pub mod ids;
+#[cfg(feature = "http")]
+pub mod interop;
+pub mod invocation;
+pub mod journal;
+#[cfg(feature = "json")]
+pub mod lazy_store;
+pub mod lint;
+pub mod masking;
+pub mod matcher;
+pub mod media_type;
+pub mod metrics;
 pub mod middleware;
 pub mod middlewares;
+pub mod migrate;
+#[cfg(feature = "mitm")]
+pub mod mitm;
+#[cfg(all(feature = "json", feature = "server"))]
+pub mod mock_server;
+#[cfg(feature = "oidc")]
+pub mod oidc;
+pub mod openapi;
+pub mod ordering;
+pub mod pack;
+#[cfg(feature = "json")]
+pub mod pact;
+pub mod pagination;
+pub mod pattern;
+pub mod proxy;
+pub mod rate_limit;
+pub mod raw;
+#[cfg(all(feature = "json", feature = "server"))]
+pub mod replay;
 pub mod request;
 pub mod response;
+pub mod response_cache;
+pub mod route_index;
 pub mod router;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod scenario;
+pub mod schema;
+#[cfg(feature = "js")]
+pub mod script;
+#[cfg(feature = "server")]
 pub mod server;
+#[cfg(feature = "tower")]
+pub mod service;
+pub mod sheet;
+pub mod smtp;
 pub mod store;
+pub mod store_events;
+pub mod switches;
 pub mod table;
+pub mod tags;
+pub mod template;
+#[cfg(feature = "js")]
+pub mod test_runner;
+pub mod time;
+pub mod upstream;
+pub mod validate;
 pub mod value;
+pub mod variables;
+#[cfg(feature = "json")]
+pub mod wiremock;
 pub mod workspace;
 
+pub use admin::*;
+pub use audit::*;
+pub use auth::*;
+#[cfg(feature = "server")]
+pub use bench::*;
+pub use binary::*;
+pub use body_parser::*;
+#[cfg(feature = "json")]
+pub use capture::*;
+#[cfg(feature = "server")]
+pub use client::*;
+pub use computed::*;
+pub use concurrency::*;
 pub use config::*;
+pub use contract::*;
+#[cfg(feature = "dashboard")]
+pub use dashboard::*;
+pub use diff::*;
+pub use engine::*;
 pub use error::*;
+pub use error_pages::*;
+pub use experiment::*;
+pub use explain::*;
+pub use extensions::*;
+pub use fault::*;
+#[cfg(feature = "js")]
+pub use fetch::*;
 pub use file_fmt::*;
+pub use generate::*;
+pub use hooks::*;
+pub use hosts::*;
 pub use http::*;
+pub use hypermedia::*;
+pub use ids::*;
+pub use invocation::*;
+pub use journal::*;
+#[cfg(feature = "json")]
+pub use lazy_store::*;
+pub use lint::*;
+pub use masking::*;
+pub use matcher::*;
+pub use media_type::*;
+pub use metrics::*;
 pub use middleware::*;
 pub use middlewares::*;
+pub use migrate::*;
+#[cfg(feature = "mitm")]
+pub use mitm::*;
+#[cfg(all(feature = "json", feature = "server"))]
+pub use mock_server::*;
+#[cfg(feature = "oidc")]
+pub use oidc::*;
+pub use openapi::*;
+pub use ordering::*;
+pub use pack::*;
+#[cfg(feature = "json")]
+pub use pact::*;
+pub use pagination::*;
+pub use pattern::*;
+pub use proxy::*;
+pub use rate_limit::*;
+pub use raw::*;
+#[cfg(all(feature = "json", feature = "server"))]
+pub use replay::*;
 pub use request::*;
 pub use response::*;
+pub use response_cache::*;
+pub use route_index::*;
 pub use router::*;
+#[cfg(feature = "s3")]
+pub use s3::*;
+pub use scenario::*;
+pub use schema::*;
+#[cfg(feature = "js")]
+pub use script::*;
+#[cfg(feature = "server")]
 pub use server::*;
+#[cfg(feature = "tower")]
+pub use service::*;
+pub use sheet::*;
+pub use smtp::*;
 pub use store::*;
+pub use store_events::*;
+pub use switches::*;
 pub use table::*;
+pub use tags::*;
+pub use template::*;
+#[cfg(feature = "js")]
+pub use test_runner::*;
+pub use time::*;
+pub use upstream::*;
+pub use validate::*;
 pub use value::*;
+pub use variables::*;
+#[cfg(feature = "json")]
+pub use wiremock::*;
 pub use workspace::*;
@@ -0,0 +1,495 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use ring::{
+  digest,
+  rand::SystemRandom,
+  signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use crate::{
+  basic_credentials, form_encode, form_params, random_token, Error, ErrorKind, Method, Request,
+  Response, Status, Tokens, ADMIN_PREFIX,
+};
+
+/// Path of the OpenID Connect discovery document
+pub const OIDC_DISCOVERY_PATH: &str = "/.well-known/openid-configuration";
+
+/// Account of the identity provider mock
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OidcUser {
+  pub username: String,
+  /// Required by the password grant when set, any password goes otherwise
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub password: Option<String>,
+  /// Claims added to the ID token and userinfo, `sub` being the username
+  /// unless given
+  #[serde(default, skip_serializing_if = "Map::is_empty")]
+  pub claims: Map<String, Value>,
+}
+
+/// Identity provider mock, read from the `oidc` section of the workspace.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OidcConfig {
+  /// Issuer URL, `http://{Host}` of the discovery request by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub issuer: Option<String>,
+  /// Accounts users can log in as, a single `user` one when empty
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub users: Vec<OidcUser>,
+}
+
+/// Authorization code waiting to be exchanged
+struct PendingCode {
+  username: String,
+  client_id: String,
+  nonce: Option<String>,
+  scope: Option<String>,
+  challenge: Option<(String, String)>,
+  expires_at: u128,
+}
+
+/// OpenID Connect provider answering discovery, JWKS, authorize, token and
+/// userinfo requests, so OIDC clients can log in without a real provider.
+/// ID tokens are signed with ES256, by a key generated at startup.
+pub struct Oidc {
+  config: OidcConfig,
+  tokens: std::sync::Arc<Tokens>,
+  key: EcdsaKeyPair,
+  kid: String,
+  rng: SystemRandom,
+  codes: Mutex<HashMap<String, PendingCode>>,
+  /// Username by access token
+  sessions: Mutex<HashMap<String, String>>,
+}
+
+impl Oidc {
+  /// Path under the admin API prefix
+  pub const PREFIX: &'static str = "/oidc";
+  const CODE_TTL: Duration = Duration::from_secs(60);
+
+  /// Provider issuing its access tokens through `tokens`, so routes with an
+  /// `oauth2-client-credentials` auth preset accept them
+  pub fn new(config: OidcConfig, tokens: std::sync::Arc<Tokens>) -> crate::Result<Self> {
+    let rng = SystemRandom::new();
+    let crypto = |what: &str| Error::new(ErrorKind::Unknown, Some(what.to_string()), None);
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+      .map_err(|_| crypto("failed to generate signing key"))?;
+    let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+      .map_err(|_| crypto("failed to load signing key"))?;
+    let thumbprint = digest::digest(&digest::SHA256, key.public_key().as_ref());
+    let kid = URL_SAFE_NO_PAD.encode(&thumbprint.as_ref()[..12]);
+    Ok(Self {
+      config,
+      tokens,
+      key,
+      kid,
+      rng,
+      codes: Mutex::new(HashMap::new()),
+      sessions: Mutex::new(HashMap::new()),
+    })
+  }
+
+  fn users(&self) -> Vec<OidcUser> {
+    match self.config.users.is_empty() {
+      true => vec![OidcUser {
+        username: "user".to_string(),
+        ..Default::default()
+      }],
+      false => self.config.users.clone(),
+    }
+  }
+
+  fn user(&self, username: &str) -> Option<OidcUser> {
+    self.users().into_iter().find(|u| u.username == username)
+  }
+
+  fn issuer(&self, req: &Request) -> String {
+    match &self.config.issuer {
+      Some(issuer) => issuer.trim_end_matches('/').to_string(),
+      None => format!("http://{}", req.header("Host").unwrap_or("localhost")),
+    }
+  }
+
+  fn endpoint(&self, req: &Request, name: &str) -> String {
+    format!(
+      "{}{}{}/{}",
+      self.issuer(req),
+      ADMIN_PREFIX,
+      Self::PREFIX,
+      name
+    )
+  }
+
+  /// The discovery document, for `GET /.well-known/openid-configuration`
+  pub fn discovery(&self, req: &Request) -> crate::Result<Response> {
+    Response::api_for(
+      req,
+      Status::OK,
+      &json!({
+        "issuer": self.issuer(req),
+        "authorization_endpoint": self.endpoint(req, "authorize"),
+        "token_endpoint": self.endpoint(req, "token"),
+        "userinfo_endpoint": self.endpoint(req, "userinfo"),
+        "jwks_uri": self.endpoint(req, "jwks"),
+        "response_types_supported": ["code"],
+        "grant_types_supported": ["authorization_code", "password", "client_credentials"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": ["ES256"],
+        "scopes_supported": ["openid", "profile", "email"],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post", "none"],
+        "code_challenge_methods_supported": ["plain", "S256"],
+      }),
+    )
+  }
+
+  /// Answer a request to `path`, relative to the admin API prefix, `None`
+  /// when it is not an OIDC endpoint
+  pub fn handle(&self, req: &Request, path: &str) -> crate::Result<Option<Response>> {
+    let name = match path.strip_prefix(Self::PREFIX) {
+      Some(name) => name,
+      None => return Ok(None),
+    };
+    let res = match (req.method().unwrap_or(Method::Get), name) {
+      (Method::Get, "/jwks") => self.jwks(req)?,
+      (Method::Get, "/authorize") => self.authorize(req)?,
+      (Method::Post, "/token") => self.token(req)?,
+      (Method::Get | Method::Post, "/userinfo") => self.userinfo(req)?,
+      _ => return Ok(None),
+    };
+    Ok(Some(res))
+  }
+
+  fn jwks(&self, req: &Request) -> crate::Result<Response> {
+    // uncompressed point: 0x04, then x and y
+    let point = self.key.public_key().as_ref();
+    Response::api_for(
+      req,
+      Status::OK,
+      &json!({"keys": [{
+        "kty": "EC",
+        "crv": "P-256",
+        "use": "sig",
+        "alg": "ES256",
+        "kid": self.kid,
+        "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+        "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+      }]}),
+    )
+  }
+
+  /// Log in as the user named by `login_hint`, or the only one, redirecting
+  /// back with a code. A page listing the users is served otherwise.
+  fn authorize(&self, req: &Request) -> crate::Result<Response> {
+    let query = form_params(req.query().unwrap_or_default().as_bytes());
+    let param = |name: &str| query.get(name).filter(|v| !v.is_empty()).cloned();
+    let (redirect_uri, client_id) = match (param("redirect_uri"), param("client_id")) {
+      (Some(redirect_uri), Some(client_id)) => (redirect_uri, client_id),
+      _ => return Ok(oauth_error(Status::BadRequest, "invalid_request")),
+    };
+    let users = self.users();
+    let user = match (param("login_hint"), users.as_slice()) {
+      (Some(hint), _) => self.user(&hint),
+      (None, [only]) => Some(only.clone()),
+      (None, _) => None,
+    };
+    let user = match user {
+      Some(user) => user,
+      None => return Ok(self.login_page(req, &users)),
+    };
+    let code = random_token();
+    self.codes.lock()?.insert(
+      code.clone(),
+      PendingCode {
+        username: user.username,
+        client_id,
+        nonce: param("nonce"),
+        scope: param("scope"),
+        challenge: param("code_challenge").map(|c| {
+          let method = param("code_challenge_method").unwrap_or("plain".to_string());
+          (c, method)
+        }),
+        expires_at: self.tokens.clock().now_millis() + Self::CODE_TTL.as_millis(),
+      },
+    );
+    let mut location = format!(
+      "{}{}code={}",
+      redirect_uri,
+      if redirect_uri.contains('?') { '&' } else { '?' },
+      code
+    );
+    if let Some(state) = param("state") {
+      location += &format!("&state={}", form_encode(&state));
+    }
+    Ok(
+      Response::default()
+        .with_status(Status::Found)
+        .with_header("Location", location),
+    )
+  }
+
+  fn login_page(&self, req: &Request, users: &[OidcUser]) -> Response {
+    let target = req
+      .start_line()
+      .as_request()
+      .map(|s| s.target.clone())
+      .unwrap_or_default();
+    let links = users
+      .iter()
+      .map(|u| {
+        format!(
+          "<li><a href=\"{}&amp;login_hint={}\">{}</a></li>",
+          escape(&target),
+          form_encode(&u.username),
+          escape(&u.username)
+        )
+      })
+      .collect::<String>();
+    Response::default()
+      .with_status(Status::OK)
+      .with_header("Content-Type", "text/html; charset=utf-8")
+      .with_body(format!(
+        "<!doctype html><title>Log in</title><h1>Log in as</h1><ul>{}</ul>",
+        links
+      ))
+  }
+
+  fn token(&self, req: &Request) -> crate::Result<Response> {
+    let form = form_params(req.body());
+    let field = |name: &str| form.get(name).filter(|v| !v.is_empty()).cloned();
+    let client_id = basic_credentials(req)
+      .map(|(id, _)| id)
+      .or_else(|| field("client_id"));
+    match field("grant_type").as_deref() {
+      Some("authorization_code") => {
+        let pending = field("code").and_then(|code| self.codes.lock().ok()?.remove(&code));
+        let pending = match pending {
+          Some(pending) if self.tokens.clock().now_millis() < pending.expires_at => pending,
+          _ => return Ok(oauth_error(Status::BadRequest, "invalid_grant")),
+        };
+        if client_id
+          .as_ref()
+          .is_some_and(|id| *id != pending.client_id)
+          || !verify_challenge(
+            pending.challenge.as_ref(),
+            field("code_verifier").as_deref(),
+          )
+        {
+          return Ok(oauth_error(Status::BadRequest, "invalid_grant"));
+        }
+        self.grant(
+          req,
+          &pending.username,
+          &pending.client_id,
+          pending.scope,
+          pending.nonce,
+        )
+      }
+      Some("password") => {
+        let user = field("username").and_then(|u| self.user(&u));
+        let user = match user {
+          Some(user) if user.password.is_none() || user.password == field("password") => user,
+          _ => return Ok(oauth_error(Status::BadRequest, "invalid_grant")),
+        };
+        let client_id = client_id.unwrap_or_default();
+        self.grant(req, &user.username, &client_id, field("scope"), None)
+      }
+      Some("client_credentials") => self.tokens.token_endpoint(req),
+      _ => Ok(oauth_error(Status::BadRequest, "unsupported_grant_type")),
+    }
+  }
+
+  /// Access and ID tokens of `username`
+  fn grant(
+    &self,
+    req: &Request,
+    username: &str,
+    client_id: &str,
+    scope: Option<String>,
+    nonce: Option<String>,
+  ) -> crate::Result<Response> {
+    let grant = self.tokens.issue(client_id, scope)?;
+    self
+      .sessions
+      .lock()?
+      .insert(grant.access_token.clone(), username.to_string());
+    let now = (self.tokens.clock().now_millis() / 1000) as u64;
+    let mut claims = self.claims(username);
+    claims.insert("iss".to_string(), self.issuer(req).into());
+    claims.insert("aud".to_string(), client_id.into());
+    claims.insert("iat".to_string(), now.into());
+    claims.insert("exp".to_string(), (now + grant.expires_in).into());
+    if let Some(nonce) = nonce {
+      claims.insert("nonce".to_string(), nonce.into());
+    }
+    let mut body = serde_json::to_value(&grant)?;
+    body["id_token"] = self.sign(&Value::Object(claims))?.into();
+    Response::api_for(req, Status::OK, &body)
+  }
+
+  fn claims(&self, username: &str) -> Map<String, Value> {
+    let mut claims = Map::new();
+    claims.insert("sub".to_string(), username.into());
+    if let Some(user) = self.user(username) {
+      claims.extend(user.claims);
+    }
+    claims
+  }
+
+  fn userinfo(&self, req: &Request) -> crate::Result<Response> {
+    let token = req
+      .header("Authorization")
+      .and_then(|h| h.strip_prefix("Bearer "))
+      .map(|t| t.trim().to_string());
+    let username = match token {
+      Some(token) if self.tokens.introspect(&token)?.is_some() => {
+        self.sessions.lock()?.get(&token).cloned()
+      }
+      _ => None,
+    };
+    match username {
+      Some(username) => Response::api_for(req, Status::OK, &self.claims(&username)),
+      None => Ok(
+        oauth_error(Status::Unauthorized, "invalid_token")
+          .with_header("WWW-Authenticate", "Bearer error=\"invalid_token\""),
+      ),
+    }
+  }
+
+  /// Compact JWS of `claims`
+  pub fn sign(&self, claims: &Value) -> crate::Result<String> {
+    let header = json!({"alg": "ES256", "typ": "JWT", "kid": self.kid});
+    let input = format!(
+      "{}.{}",
+      URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+      URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?)
+    );
+    let signature = self
+      .key
+      .sign(&self.rng, input.as_bytes())
+      .map_err(|_| Error::new(ErrorKind::Unknown, Some("failed to sign".to_string()), None))?;
+    Ok(format!(
+      "{}.{}",
+      input,
+      URL_SAFE_NO_PAD.encode(signature.as_ref())
+    ))
+  }
+
+  /// Public key ID tokens are signed with, as an uncompressed point
+  pub fn public_key(&self) -> &[u8] {
+    self.key.public_key().as_ref()
+  }
+}
+
+/// Whether a PKCE `verifier` answers the `(challenge, method)` of a code
+fn verify_challenge(challenge: Option<&(String, String)>, verifier: Option<&str>) -> bool {
+  match (challenge, verifier) {
+    (None, _) => true,
+    (Some(_), None) => false,
+    (Some((challenge, method)), Some(verifier)) => match method.as_str() {
+      "S256" => {
+        URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, verifier.as_bytes())) == *challenge
+      }
+      _ => verifier == challenge,
+    },
+  }
+}
+
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+fn oauth_error(status: Status, error: &str) -> Response {
+  Response::default()
+    .with_status(status)
+    .with_header("Content-Type", "application/json")
+    .with_body(json!({ "error": error }).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::sync::Arc;
+
+  use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+  use ring::{digest, signature};
+
+  use crate::{Method, Request, Tokens};
+
+  use super::{Oidc, OidcConfig, OidcUser};
+
+  #[test]
+  fn login() {
+    let oidc = Oidc::new(
+      OidcConfig {
+        issuer: Some("http://idp.test".to_string()),
+        users: vec![OidcUser {
+          username: "alice".to_string(),
+          claims: serde_json::json!({"email": "alice@example.com"})
+            .as_object()
+            .cloned()
+            .unwrap(),
+          ..Default::default()
+        }],
+      },
+      Arc::new(Tokens::default()),
+    )
+    .unwrap();
+    let verifier = "a-long-enough-code-verifier";
+    let challenge = URL_SAFE_NO_PAD.encode(digest::digest(&digest::SHA256, verifier.as_bytes()));
+    let req = Request::new(
+      Method::Get,
+      format!(
+        "/__mocker/oidc/authorize?client_id=app&redirect_uri=http%3A%2F%2Fapp%2Fcb&state=s+1&nonce=n&code_challenge={}&code_challenge_method=S256",
+        challenge
+      ),
+    );
+    let res = oidc.handle(&req, "/oidc/authorize").unwrap().unwrap();
+    assert_eq!(res.status(), 302);
+    let location = res.header("Location").unwrap();
+    assert!(location.starts_with("http://app/cb?code="));
+    assert!(location.ends_with("&state=s%201"));
+    let code = &location["http://app/cb?code=".len()..location.find('&').unwrap()];
+
+    let token = |verifier: &str| {
+      let req = Request::new(Method::Post, "/__mocker/oidc/token").with_body(format!(
+        "grant_type=authorization_code&client_id=app&code={}&code_verifier={}",
+        code, verifier
+      ));
+      oidc.handle(&req, "/oidc/token").unwrap().unwrap()
+    };
+    let res = token(verifier);
+    assert_eq!(res.status(), 200);
+    let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    let id_token = body["id_token"].as_str().unwrap();
+    let (input, sig) = id_token.rsplit_once('.').unwrap();
+    signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, oidc.public_key())
+      .verify(input.as_bytes(), &URL_SAFE_NO_PAD.decode(sig).unwrap())
+      .unwrap();
+    let claims: serde_json::Value = serde_json::from_slice(
+      &URL_SAFE_NO_PAD
+        .decode(input.split('.').nth(1).unwrap())
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(claims["sub"], "alice");
+    assert_eq!(claims["aud"], "app");
+    assert_eq!(claims["nonce"], "n");
+    assert_eq!(claims["iss"], "http://idp.test");
+    // codes are single use
+    assert_eq!(token(verifier).status(), 400);
+
+    let req = Request::new(Method::Get, "/__mocker/oidc/userinfo").with_header(
+      "Authorization",
+      format!("Bearer {}", body["access_token"].as_str().unwrap()),
+    );
+    let res = oidc.handle(&req, "/oidc/userinfo").unwrap().unwrap();
+    let info: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+    assert_eq!(info["email"], "alice@example.com");
+  }
+}
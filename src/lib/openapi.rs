@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+
+use crate::{Route, RouteKind, Status, Value};
+
+/// Version of the OpenAPI specification documents are generated for
+pub const OPENAPI_VERSION: &str = "3.0.3";
+
+fn obj<const N: usize>(entries: [(&str, Value); N]) -> Value {
+  Value::from(
+    entries
+      .into_iter()
+      .map(|(k, v)| (k.to_string(), v))
+      .collect::<HashMap<_, _>>(),
+  )
+}
+
+fn response(status: u16, description: String, example: Option<&Value>) -> (String, Value) {
+  let mut res = HashMap::from([("description".to_string(), Value::from(description))]);
+  if let Some(example) = example {
+    let content_type = match example {
+      Value::String(_) => "text/plain",
+      _ => "application/json",
+    };
+    res.insert(
+      "content".to_string(),
+      obj([(content_type, obj([("example", example.clone())]))]),
+    );
+  }
+  (status.to_string(), Value::from(res))
+}
+
+fn status_text(status: u16) -> String {
+  Status::try_from(status)
+    .map(|s| s.text().to_string())
+    .unwrap_or_else(|_| format!("status {}", status))
+}
+
+/// Describe what a route answers, as an OpenAPI operation
+pub fn route_operation(route: &Route) -> Value {
+  let (summary, responses) = match route.kind() {
+    RouteKind::Fixture { status, body, .. } => (
+      "Static response".to_string(),
+      vec![response(*status, status_text(*status), body.as_ref())],
+    ),
+    RouteKind::Exec {
+      command, status, ..
+    } => (
+      format!("Output of `{}`", command),
+      vec![response(*status, status_text(*status), None)],
+    ),
+    RouteKind::Echo { status, .. } => (
+      "Reflection of the request".to_string(),
+      vec![response(*status, status_text(*status), None)],
+    ),
+    #[cfg(feature = "json")]
+    RouteKind::Store {
+      path, identifier, ..
+    } => (
+      format!(
+        "Items of {}, identified by `{}`",
+        path.display(),
+        identifier
+      ),
+      vec![
+        response(200, status_text(200), None),
+        response(404, "Item not found".to_string(), None),
+      ],
+    ),
+    #[cfg(feature = "js")]
+    RouteKind::Script { script, func } => (
+      format!("Result of `{}` in {}", func, script.display()),
+      vec![response(200, status_text(200), None)],
+    ),
+    #[cfg(feature = "s3")]
+    RouteKind::S3 { root_dir } => (
+      format!("S3 object storage in {}", root_dir.display()),
+      vec![
+        response(200, status_text(200), None),
+        response(404, "No such bucket or key".to_string(), None),
+      ],
+    ),
+  };
+  obj([
+    ("summary", Value::from(summary)),
+    (
+      "tags",
+      Value::from(
+        std::iter::once(route.kind_str().to_string())
+          .chain(route.options().tags.iter().cloned())
+          .map(Value::from)
+          .collect::<Vec<_>>(),
+      ),
+    ),
+    (
+      "responses",
+      Value::from(responses.into_iter().collect::<HashMap<_, _>>()),
+    ),
+  ])
+}
+
+/// Generate an OpenAPI document describing `routes`, served from `server` if given.
+pub fn openapi<T: AsRef<str>>(title: T, server: Option<String>, routes: &[Route]) -> Value {
+  let mut paths: HashMap<String, Value> = HashMap::new();
+  for route in routes {
+    let item = paths
+      .entry(route.endpoint().clone())
+      .or_insert_with(|| Value::Map(HashMap::new()));
+    if let Value::Map(item) = item {
+      for method in route.methods() {
+        item
+          .entry(method.repr().to_lowercase())
+          .or_insert_with(|| route_operation(route));
+      }
+    }
+  }
+  let mut doc = HashMap::from([
+    ("openapi".to_string(), Value::from(OPENAPI_VERSION)),
+    (
+      "info".to_string(),
+      obj([
+        ("title", Value::from(title.as_ref())),
+        ("version", Value::from(env!("CARGO_PKG_VERSION"))),
+      ]),
+    ),
+    ("paths".to_string(), Value::from(paths)),
+  ]);
+  if let Some(server) = server {
+    doc.insert(
+      "servers".to_string(),
+      Value::from(vec![obj([("url", Value::from(server))])]),
+    );
+  }
+  Value::from(doc)
+}
+
+fn escape_html(s: &str) -> String {
+  s.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+/// Interactive documentation page: a plain route listing, upgraded to Swagger UI
+/// (loaded from a CDN) rendering the document served at `spec_url`.
+pub fn docs_page<T: AsRef<str>, U: AsRef<str>>(title: T, spec_url: U, routes: &[Route]) -> String {
+  let rows = routes
+    .iter()
+    .map(|route| {
+      format!(
+        "<tr><td>{}</td><td><code>{}</code></td><td>{}</td></tr>",
+        route
+          .methods()
+          .iter()
+          .map(|m| m.repr())
+          .collect::<Vec<_>>()
+          .join(", "),
+        escape_html(route.endpoint()),
+        route.kind_str()
+      )
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+  format!(
+    r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+<style>body {{ font-family: sans-serif; margin: 0 }} #routes {{ margin: 2em }} td {{ padding: 0.2em 1em }}</style>
+</head>
+<body>
+<div id="swagger-ui">
+<div id="routes">
+<h1>{title}</h1>
+<p><a href="{spec}">OpenAPI document</a></p>
+<table>
+<tr><th>Methods</th><th>Endpoint</th><th>Kind</th></tr>
+{rows}
+</table>
+</div>
+</div>
+<script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+<script>
+if (window.SwaggerUIBundle) {{
+  SwaggerUIBundle({{ url: "{spec}", dom_id: "#swagger-ui" }});
+}}
+</script>
+</body>
+</html>
+"##,
+    title = escape_html(title.as_ref()),
+    spec = escape_html(spec_url.as_ref()),
+    rows = rows,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Route, RouteKind, Value};
+
+  use super::openapi;
+
+  #[test]
+  fn paths() {
+    let routes = vec![
+      Route::new(
+        vec![Method::Get, Method::Head],
+        "/health",
+        RouteKind::Fixture {
+          status: 200,
+          headers: Default::default(),
+          body: Some(Value::from("ok")),
+          file: None,
+          template: false,
+        },
+      ),
+      Route::new(
+        vec![Method::Post],
+        "/jobs",
+        RouteKind::Exec {
+          command: "./run.sh".to_string(),
+          args: vec![],
+          status: 202,
+          headers: Default::default(),
+          timeout: None,
+          cwd: None,
+          env: Default::default(),
+          clear_env: false,
+        },
+      ),
+    ];
+    let doc = openapi("mock", Some("http://localhost:8080".into()), &routes);
+    let get = |path: &str| doc.get_path(path).cloned().unwrap_or_default();
+    assert_eq!(get("openapi"), Value::from("3.0.3"));
+    assert_eq!(get("servers.0.url"), Value::from("http://localhost:8080"));
+    assert_eq!(
+      get("paths./health.get.responses.200.description"),
+      Value::from("OK")
+    );
+    assert!(doc.get_path("paths./health.head").is_some());
+    assert_eq!(
+      doc
+        .get_path("paths./jobs.post.summary")
+        .map(|v| v.to_string()),
+      Some("Output of `./run.sh`".to_string())
+    );
+  }
+}
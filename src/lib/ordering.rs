@@ -0,0 +1,186 @@
+use std::{
+  collections::{BTreeSet, HashMap},
+  net::IpAddr,
+  sync::{Arc, Condvar, Mutex},
+  time::{Duration, Instant},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{parse_duration, Request};
+
+/// Responses sent to a client in the order its requests arrived, even when
+/// handlers finish out of order, like backends guaranteeing ordering do.
+/// Clients are told apart by a header or a cookie, else by their address.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ResponseOrdering {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub header: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub cookie: Option<String>,
+  /// Maximum time a response waits for the ones before it, e.g. `30s`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub timeout: Option<String>,
+}
+
+impl ResponseOrdering {
+  pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+  /// Key of the queue `req`, sent from `peer`, is served in
+  pub fn key(&self, req: &Request, peer: Option<IpAddr>) -> Option<String> {
+    let header = self.header.as_ref().and_then(|name| req.header(name));
+    let cookie = self.cookie.as_ref().and_then(|name| req.cookie(name));
+    match (header, cookie) {
+      (Some(header), _) => Some(format!("header:{}", header)),
+      (None, Some(cookie)) => Some(format!("cookie:{}", cookie)),
+      (None, None) => peer.map(|peer| format!("peer:{}", peer)),
+    }
+  }
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+  /// Ticket handed to the next request
+  next: u64,
+  /// Ticket allowed to respond
+  serving: u64,
+  /// Tickets done before their turn came
+  done: BTreeSet<u64>,
+}
+
+/// Hands out tickets in arrival order and lets each respond once the
+/// tickets before it, of the same key, are done.
+#[derive(Debug)]
+pub struct Sequencer {
+  ordering: ResponseOrdering,
+  timeout: Duration,
+  queues: Mutex<HashMap<String, Queue>>,
+  turn: Condvar,
+}
+
+impl Sequencer {
+  pub fn new(ordering: ResponseOrdering) -> crate::Result<Self> {
+    let timeout = match &ordering.timeout {
+      Some(timeout) => parse_duration(timeout)?,
+      None => ResponseOrdering::DEFAULT_TIMEOUT,
+    };
+    Ok(Self {
+      ordering,
+      timeout,
+      queues: Mutex::new(HashMap::new()),
+      turn: Condvar::new(),
+    })
+  }
+
+  /// Ticket of `req`, to be held until its response is written. Requests
+  /// without a key are not ordered.
+  pub fn ticket(self: &Arc<Self>, req: &Request, peer: Option<IpAddr>) -> Option<Ticket> {
+    let key = self.ordering.key(req, peer)?;
+    let mut queues = self.queues.lock().ok()?;
+    let queue = queues.entry(key.clone()).or_default();
+    let number = queue.next;
+    queue.next += 1;
+    Some(Ticket {
+      sequencer: self.clone(),
+      key,
+      number,
+    })
+  }
+}
+
+/// A request's place in its client's queue. Dropping it lets the next one
+/// respond.
+pub struct Ticket {
+  sequencer: Arc<Sequencer>,
+  key: String,
+  number: u64,
+}
+
+impl Ticket {
+  /// Block until every earlier request of the client responded, or the
+  /// timeout elapsed
+  pub fn wait(&self) -> crate::Result<()> {
+    let deadline = Instant::now() + self.sequencer.timeout;
+    let mut queues = self.sequencer.queues.lock()?;
+    loop {
+      if queues
+        .get(&self.key)
+        .is_none_or(|queue| queue.serving >= self.number)
+      {
+        return Ok(());
+      }
+      let left = deadline.saturating_duration_since(Instant::now());
+      if left.is_zero() {
+        warn!(
+          "Responding out of order to '{}' after waiting {:?}",
+          self.key, self.sequencer.timeout
+        );
+        return Ok(());
+      }
+      queues = self.sequencer.turn.wait_timeout(queues, left)?.0;
+    }
+  }
+}
+
+impl Drop for Ticket {
+  fn drop(&mut self) {
+    let Ok(mut queues) = self.sequencer.queues.lock() else {
+      return;
+    };
+    if let Some(queue) = queues.get_mut(&self.key) {
+      queue.done.insert(self.number);
+      while queue.done.remove(&queue.serving) {
+        queue.serving += 1;
+      }
+      if queue.serving == queue.next {
+        queues.remove(&self.key);
+      }
+    }
+    self.sequencer.turn.notify_all();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+  };
+
+  use crate::{Method, Request};
+
+  use super::{ResponseOrdering, Sequencer};
+
+  #[test]
+  fn fifo() {
+    let ordering = ResponseOrdering {
+      header: Some("X-Session".to_string()),
+      ..Default::default()
+    };
+    let sequencer = Arc::new(Sequencer::new(ordering).unwrap());
+    let req = |session: &str| Request::new(Method::Get, "/").with_header("X-Session", session);
+    let written = Arc::new(Mutex::new(vec![]));
+    let handles = [(1, 60), (2, 30), (3, 0)].map(|(n, delay)| {
+      let ticket = sequencer.ticket(&req("a"), None).unwrap();
+      let written = written.clone();
+      thread::spawn(move || {
+        thread::sleep(Duration::from_millis(delay));
+        ticket.wait().unwrap();
+        written.lock().unwrap().push(n);
+      })
+    });
+    let other = sequencer.ticket(&req("b"), None).unwrap();
+    other.wait().unwrap();
+    drop(other);
+    for handle in handles {
+      handle.join().unwrap();
+    }
+    assert_eq!(*written.lock().unwrap(), vec![1, 2, 3]);
+    assert!(sequencer.queues.lock().unwrap().is_empty());
+    assert!(sequencer
+      .ticket(&Request::new(Method::Get, "/"), None)
+      .is_none());
+  }
+}
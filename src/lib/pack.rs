@@ -0,0 +1,204 @@
+use std::{
+  fs,
+  io::{Read, Write},
+  path::{Component, Path, PathBuf},
+};
+
+use crate::{Error, ErrorKind};
+
+/// Extension of workspace archives
+pub const PACK_EXTENSION: &str = "mock";
+
+const BLOCK: usize = 512;
+
+fn invalid<S: AsRef<str>>(message: S) -> Error {
+  Error::new(
+    ErrorKind::Parse,
+    Some(format!("invalid pack: {}", message.as_ref())),
+    None,
+  )
+}
+
+/// Files of the workspace in `dir` that go in its pack: every file but the
+/// hidden ones (`.mocker`, `.git`, ...), build outputs and other packs
+pub fn pack_files<P: AsRef<Path>>(dir: P) -> crate::Result<Vec<PathBuf>> {
+  let mut files = vec![];
+  let mut pending = vec![PathBuf::new()];
+  while let Some(rel) = pending.pop() {
+    for entry in fs::read_dir(dir.as_ref().join(&rel))? {
+      let entry = entry?;
+      let name = entry.file_name().to_string_lossy().to_string();
+      let path = rel.join(&name);
+      let kind = entry.file_type()?;
+      if name.starts_with('.') || name == "target" {
+        continue;
+      }
+      if kind.is_dir() {
+        pending.push(path);
+      } else if kind.is_file() && path.extension().and_then(|e| e.to_str()) != Some(PACK_EXTENSION)
+      {
+        files.push(path);
+      }
+    }
+  }
+  files.sort();
+  Ok(files)
+}
+
+/// Octal field of a tar header, NUL terminated
+fn octal(field: &mut [u8], value: u64) -> crate::Result<()> {
+  let digits = format!("{:0width$o}", value, width = field.len() - 1);
+  if digits.len() >= field.len() {
+    return Err(invalid(format!("{} does not fit a header field", value)));
+  }
+  field[..digits.len()].copy_from_slice(digits.as_bytes());
+  Ok(())
+}
+
+/// Header of a regular file `name`, the part of long names before a `/`
+/// going in the ustar prefix
+fn header(name: &str, size: u64) -> crate::Result<[u8; BLOCK]> {
+  let mut block = [0u8; BLOCK];
+  let (prefix, name) = match name.len() > 100 {
+    true => name
+      .char_indices()
+      .filter(|(i, c)| *c == '/' && *i <= 155 && name.len() - i - 1 <= 100)
+      .map(|(i, _)| (&name[..i], &name[i + 1..]))
+      .next()
+      .ok_or_else(|| invalid(format!("path '{}' is too long", name)))?,
+    false => ("", name),
+  };
+  block[..name.len()].copy_from_slice(name.as_bytes());
+  octal(&mut block[100..108], 0o644)?;
+  octal(&mut block[108..116], 0)?;
+  octal(&mut block[116..124], 0)?;
+  octal(&mut block[124..136], size)?;
+  octal(&mut block[136..148], 0)?;
+  block[156] = b'0';
+  block[257..263].copy_from_slice(b"ustar\0");
+  block[263..265].copy_from_slice(b"00");
+  block[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+  block[148..156].fill(b' ');
+  let sum = block.iter().map(|b| *b as u64).sum::<u64>();
+  octal(&mut block[148..155], sum)?;
+  Ok(block)
+}
+
+/// Write the workspace in `dir` to `out`, as a tar archive, returning the
+/// number of files packed
+pub fn pack<P: AsRef<Path>, W: Write>(dir: P, mut out: W) -> crate::Result<usize> {
+  let files = pack_files(&dir)?;
+  for file in &files {
+    let data = fs::read(dir.as_ref().join(file))?;
+    let name = file
+      .components()
+      .map(|c| c.as_os_str().to_string_lossy())
+      .collect::<Vec<_>>()
+      .join("/");
+    out.write_all(&header(&name, data.len() as u64)?)?;
+    out.write_all(&data)?;
+    out.write_all(&vec![0; (BLOCK - data.len() % BLOCK) % BLOCK])?;
+  }
+  out.write_all(&[0; BLOCK * 2])?;
+  out.flush()?;
+  Ok(files.len())
+}
+
+/// Text of a NUL padded header field
+fn field(bytes: &[u8]) -> String {
+  let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+  String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+/// Extract the pack read from `input` to `dir`, returning the number of
+/// files written. Entries escaping `dir` are rejected.
+pub fn unpack<R: Read, P: AsRef<Path>>(mut input: R, dir: P) -> crate::Result<usize> {
+  let mut count = 0;
+  let mut block = [0u8; BLOCK];
+  loop {
+    input.read_exact(&mut block)?;
+    if block.iter().all(|b| *b == 0) {
+      return Ok(count);
+    }
+    let stored = u64::from_str_radix(&field(&block[148..156]), 8).ok();
+    block[148..156].fill(b' ');
+    if stored != Some(block.iter().map(|b| *b as u64).sum()) {
+      return Err(invalid("bad header checksum"));
+    }
+    let name = match field(&block[345..500]) {
+      prefix if prefix.is_empty() => field(&block[..100]),
+      prefix => format!("{}/{}", prefix, field(&block[..100])),
+    };
+    let size = u64::from_str_radix(&field(&block[124..136]), 8)
+      .map_err(|_| invalid(format!("bad size for '{}'", name)))?;
+    let mut data = vec![];
+    input
+      .by_ref()
+      .take(size.div_ceil(BLOCK as u64) * BLOCK as u64)
+      .read_to_end(&mut data)?;
+    if (data.len() as u64) < size {
+      return Err(invalid(format!("'{}' is truncated", name)));
+    }
+    let path = Path::new(&name);
+    if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+      return Err(invalid(format!("'{}' escapes the workspace", name)));
+    }
+    match block[156] {
+      b'0' | 0 => {
+        let target = dir.as_ref().join(path);
+        if let Some(parent) = target.parent() {
+          fs::create_dir_all(parent)?;
+        }
+        fs::write(target, &data[..size as usize])?;
+        count += 1;
+      }
+      b'5' => fs::create_dir_all(dir.as_ref().join(path))?,
+      _ => {}
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use super::{header, pack, pack_files, unpack};
+
+  #[test]
+  fn round_trip() {
+    let root = std::env::temp_dir().join(format!("mocker-pack-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let (src, dst) = (root.join("src"), root.join("dst"));
+    let long = format!("fixtures/{}/body.json", "nested".repeat(20));
+    for (path, content) in [
+      ("mocker.json", "{}"),
+      ("stores/users.json", "[]"),
+      (long.as_str(), "{\"ok\": true}"),
+      (".mocker/ca/mocker-ca.key", "secret"),
+      ("old.mock", ""),
+    ] {
+      let path = src.join(path);
+      fs::create_dir_all(path.parent().unwrap()).unwrap();
+      fs::write(path, content).unwrap();
+    }
+    assert_eq!(pack_files(&src).unwrap().len(), 3);
+    let mut archive = vec![];
+    assert_eq!(pack(&src, &mut archive).unwrap(), 3);
+    assert_eq!(archive.len() % 512, 0);
+    assert_eq!(unpack(&archive[..], &dst).unwrap(), 3);
+    assert_eq!(
+      fs::read_to_string(dst.join(&long)).unwrap(),
+      "{\"ok\": true}"
+    );
+    assert_eq!(
+      fs::read_to_string(dst.join("stores/users.json")).unwrap(),
+      "[]"
+    );
+    assert!(!dst.join(".mocker").exists());
+
+    let mut evil = header("../escape", 0).unwrap().to_vec();
+    evil.extend([0; 1024]);
+    assert!(unpack(&evil[..], &dst).is_err());
+    fs::remove_dir_all(&root).unwrap();
+  }
+}
@@ -0,0 +1,606 @@
+use std::{collections::BTreeMap, path::Path};
+
+use log::warn;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  query_decode, BodyMatcher, ContractCheck, Error, ErrorKind, HeaderMatcher, JsonPathMatcher,
+  Method, QueryMatcher, Request, RequestMatcher, Response, Route, RouteKind, RouteOptions, Router,
+  Value, WithPredicate,
+};
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PactParticipant {
+  pub name: String,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct PactRequest {
+  pub method: String,
+  pub path: String,
+  /// A raw query string (v2) or a map of values (v3)
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub query: Option<Value>,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub headers: BTreeMap<String, Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub body: Option<Value>,
+}
+
+impl PactRequest {
+  /// Decoded query parameters, from a raw (v2) or structured (v3) query
+  fn query_params(&self) -> Vec<(String, String)> {
+    match &self.query {
+      Some(Value::String(q)) => q
+        .split('&')
+        .filter(|p| !p.is_empty())
+        .map(|p| match p.split_once('=') {
+          Some((k, v)) => (query_decode(k), query_decode(v)),
+          None => (query_decode(p), String::new()),
+        })
+        .collect(),
+      Some(Value::Map(m)) => {
+        let mut params = vec![];
+        for (k, v) in m.iter().collect::<BTreeMap<_, _>>() {
+          match v {
+            Value::Array(values) => {
+              params.extend(values.iter().map(|v| (k.clone(), v.to_string())))
+            }
+            v => params.push((k.clone(), v.to_string())),
+          }
+        }
+        params
+      }
+      _ => vec![],
+    }
+  }
+
+  /// Checks a request must pass to be this one, on its query, headers and
+  /// body, `None` when it has none. JSON bodies are checked leaf by leaf,
+  /// and content types by media type.
+  fn matcher(&self) -> Option<RequestMatcher> {
+    let mut ret = RequestMatcher::new();
+    for (name, value) in self.query_params() {
+      ret = ret.with_query(QueryMatcher::new(name).with_equals(value));
+    }
+    for (name, value) in &self.headers {
+      let value = value.to_string();
+      let header = HeaderMatcher::new(name);
+      ret = ret.with_header(match name.eq_ignore_ascii_case("Content-Type") {
+        true => {
+          let essence = value.split(';').next().unwrap_or_default().trim();
+          header.with_pattern(format!("^(?i){}\\s*(;|$)", regex::escape(essence)))
+        }
+        false => header.with_equals(value),
+      });
+    }
+    match &self.body {
+      Some(body @ (Value::Map(_) | Value::Array(_))) => json_leaves("$", body, &mut ret.json),
+      Some(body) => ret = ret.with_body(BodyMatcher::new().with_equals(body.to_json().to_string())),
+      None => {}
+    }
+    Some(ret).filter(|m| !m.is_empty())
+  }
+}
+
+/// Matchers requiring every leaf of `value`, found at `path`, to be equal
+fn json_leaves(path: &str, value: &Value, matchers: &mut Vec<JsonPathMatcher>) {
+  match value {
+    Value::Map(m) if !m.is_empty() => {
+      for (k, v) in m.iter().collect::<BTreeMap<_, _>>() {
+        json_leaves(&format!("{}['{}']", path, k), v, matchers);
+      }
+    }
+    Value::Array(a) if !a.is_empty() => {
+      for (i, v) in a.iter().enumerate() {
+        json_leaves(&format!("{}[{}]", path, i), v, matchers);
+      }
+    }
+    // an empty body has no path to check
+    _ if path == "$" => {}
+    Value::String(s) => matchers.push(JsonPathMatcher::new(path).with_equals(s)),
+    v => matchers.push(JsonPathMatcher::new(path).with_equals(v.to_json().to_string())),
+  }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PactResponse {
+  pub status: u16,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub headers: BTreeMap<String, Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub body: Option<Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub matching_rules: Option<Value>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PactInteraction {
+  pub description: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub provider_state: Option<String>,
+  pub request: PactRequest,
+  pub response: PactResponse,
+}
+
+/// A consumer-driven contract, as written by Pact (specification v2 or v3).
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Pact {
+  pub consumer: PactParticipant,
+  pub provider: PactParticipant,
+  pub interactions: Vec<PactInteraction>,
+}
+
+/// How a value found at some path of a response must be checked.
+#[derive(Debug, Clone)]
+pub enum PactMatcher {
+  Equality,
+  Type,
+  Regex(Regex),
+  Include(String),
+  Integer,
+  Decimal,
+  Number,
+}
+
+impl PactMatcher {
+  fn parse(rule: &Value) -> crate::Result<Self> {
+    let get = |key: &str| rule.get_path(key).map(|v| v.to_string());
+    // v2 rules may omit `match` when giving a regex
+    let kind = get("match").or_else(|| get("regex").map(|_| "regex".to_string()));
+    Ok(match kind.as_deref() {
+      Some("regex") => {
+        let pattern = get("regex").unwrap_or_default();
+        Self::Regex(Regex::new(&format!("^(?:{})$", pattern)).map_err(|e| {
+          Error::new(
+            ErrorKind::Parse,
+            Some(format!("invalid pact regex '{}': {}", pattern, e)),
+            None,
+          )
+        })?)
+      }
+      Some("include") => Self::Include(get("value").unwrap_or_default()),
+      Some("integer") => Self::Integer,
+      Some("decimal") => Self::Decimal,
+      Some("number") => Self::Number,
+      Some("equality") => Self::Equality,
+      // `type`, `min`/`max` (typed arrays), and anything else degrade to a type check
+      _ => Self::Type,
+    })
+  }
+
+  fn check(&self, expected: &Value, actual: &Value) -> Option<String> {
+    let ok = match self {
+      Self::Equality => expected.loose_eq(actual),
+      Self::Type => expected.type_name() == actual.type_name(),
+      Self::Regex(re) => re.is_match(&actual.to_string()),
+      Self::Include(s) => actual.to_string().contains(s.as_str()),
+      Self::Integer => matches!(actual, Value::Integer(_) | Value::Unsigned(_)),
+      Self::Decimal => matches!(actual, Value::Float(_)),
+      Self::Number => actual.type_name() == "number",
+    };
+    match ok {
+      true => None,
+      false => Some(match self {
+        Self::Equality => format!("expected {} but got {}", expected, actual),
+        Self::Regex(re) => format!("{} does not match /{}/", actual, re.as_str()),
+        Self::Include(s) => format!("{} does not include '{}'", actual, s),
+        _ => format!(
+          "expected a {} but got {}",
+          match self {
+            Self::Integer => "integer",
+            Self::Decimal => "decimal",
+            Self::Number => "number",
+            _ => expected.type_name(),
+          },
+          actual.type_name()
+        ),
+      }),
+    }
+  }
+}
+
+/// Response body matching rules, keyed by path segments relative to the body
+/// (`*` standing for any array item or object key).
+#[derive(Debug, Default, Clone)]
+pub struct PactRules(Vec<(Vec<String>, PactMatcher)>);
+
+impl PactRules {
+  /// Parse both v2 (`{"$.body.id": {...}}`) and v3
+  /// (`{"body": {"$.id": {"matchers": [...]}}}`) body rules.
+  pub fn parse(rules: Option<&Value>) -> crate::Result<Self> {
+    let mut ret = vec![];
+    let rules = match rules {
+      Some(Value::Map(m)) => m,
+      _ => return Ok(Self(ret)),
+    };
+    if let Some(Value::Map(body)) = rules.get("body") {
+      for (path, rule) in body {
+        let matchers = match rule.get_path("matchers") {
+          Some(Value::Array(matchers)) => matchers.clone(),
+          _ => vec![rule.clone()],
+        };
+        for matcher in matchers {
+          ret.push((Self::segments(path), PactMatcher::parse(&matcher)?));
+        }
+      }
+    }
+    for (path, rule) in rules {
+      if let Some(path) = path.strip_prefix("$.body") {
+        ret.push((Self::segments(path), PactMatcher::parse(rule)?));
+      }
+    }
+    Ok(Self(ret))
+  }
+
+  fn segments(path: &str) -> Vec<String> {
+    path
+      .trim_start_matches('$')
+      .replace('[', ".")
+      .replace(']', "")
+      .split('.')
+      .filter(|s| !s.is_empty())
+      .map(|s| s.trim_matches('\'').to_string())
+      .collect()
+  }
+
+  /// Most specific rule applying to `path`
+  fn find(&self, path: &[String]) -> Option<&PactMatcher> {
+    self
+      .0
+      .iter()
+      .filter(|(segments, _)| {
+        segments.len() == path.len() && segments.iter().zip(path).all(|(s, p)| s == "*" || s == p)
+      })
+      .max_by_key(|(segments, _)| segments.iter().filter(|s| *s != "*").count())
+      .map(|(_, matcher)| matcher)
+  }
+
+  /// Check `actual` against `expected`, values being compared for equality
+  /// unless a rule says otherwise. Extra object keys are allowed.
+  pub fn compare(&self, expected: &Value, actual: &Value) -> Vec<String> {
+    let mut mismatches = vec![];
+    self.compare_at(&mut vec![], expected, actual, &mut mismatches);
+    mismatches
+  }
+
+  fn compare_at(
+    &self,
+    path: &mut Vec<String>,
+    expected: &Value,
+    actual: &Value,
+    mismatches: &mut Vec<String>,
+  ) {
+    let display = |path: &Vec<String>| format!("$.body.{}", path.join("."));
+    let matcher = self.find(path);
+    if let Some(mismatch) = matcher.and_then(|m| m.check(expected, actual)) {
+      mismatches.push(format!("{}: {}", display(path), mismatch));
+      return;
+    }
+    let typed = matcher.is_some_and(|m| !matches!(m, PactMatcher::Equality));
+    match (expected, actual) {
+      (Value::Map(e), Value::Map(a)) => {
+        for (key, e) in e.iter().collect::<BTreeMap<_, _>>() {
+          path.push(key.clone());
+          match a.get(key) {
+            Some(a) => self.compare_at(path, e, a, mismatches),
+            None => mismatches.push(format!("{}: missing", display(path))),
+          }
+          path.pop();
+        }
+      }
+      (Value::Array(e), Value::Array(a)) => {
+        if !typed && e.len() != a.len() {
+          mismatches.push(format!(
+            "{}: expected {} items but got {}",
+            display(path),
+            e.len(),
+            a.len()
+          ));
+          return;
+        }
+        for (i, a) in a.iter().enumerate() {
+          // typed arrays compare every item against the first example
+          let e = match typed {
+            true => e.first(),
+            false => e.get(i),
+          };
+          if let Some(e) = e {
+            path.push(i.to_string());
+            self.compare_at(path, e, a, mismatches);
+            path.pop();
+          }
+        }
+      }
+      (e, a) if matcher.is_none() && !e.loose_eq(a) => {
+        mismatches.push(format!("{}: expected {} but got {}", display(path), e, a))
+      }
+      _ => {}
+    }
+  }
+}
+
+impl PactInteraction {
+  /// Fixture route answering this interaction's request, only when it
+  /// has the query, headers and body of the interaction
+  pub fn to_route(&self) -> crate::Result<Route> {
+    let headers = self
+      .response
+      .headers
+      .iter()
+      .map(|(k, v)| (k.clone(), v.to_string()))
+      .collect();
+    Ok(
+      Route::new(
+        vec![self.request.method.parse::<Method>()?],
+        &self.request.path,
+        RouteKind::Fixture {
+          status: self.response.status,
+          headers,
+          body: self.response.body.clone(),
+          file: None,
+          template: false,
+        },
+      )
+      .with_options(RouteOptions {
+        when: self.request.matcher(),
+        ..Default::default()
+      }),
+    )
+  }
+
+  fn to_request(&self) -> crate::Result<Request> {
+    let query = match &self.request.query {
+      Some(Value::String(q)) => format!("?{}", q),
+      Some(Value::Map(_)) => {
+        let params = self
+          .request
+          .query_params()
+          .into_iter()
+          .map(|(k, v)| format!("{}={}", k, v))
+          .collect::<Vec<_>>();
+        format!("?{}", params.join("&"))
+      }
+      _ => String::new(),
+    };
+    let mut req = Request::new(
+      self.request.method.parse::<Method>()?,
+      format!("{}{}", self.request.path, query),
+    );
+    for (k, v) in &self.request.headers {
+      req.set_header(k, v.to_string());
+    }
+    if let Some(body) = &self.request.body {
+      req.set_header("Content-Type", "application/json");
+      req.append_body(body.to_json().to_string());
+    }
+    Ok(req)
+  }
+
+  /// Mismatches between the expected response and `res`
+  pub fn verify(&self, res: &Response) -> crate::Result<Vec<String>> {
+    let mut mismatches = vec![];
+    if res.status() != self.response.status {
+      mismatches.push(format!(
+        "status: expected {} but got {}",
+        self.response.status,
+        res.status()
+      ));
+    }
+    for (name, expected) in &self.response.headers {
+      match res.header(name) {
+        Some(actual) if actual.starts_with(&expected.to_string()) => {}
+        Some(actual) => mismatches.push(format!(
+          "header {}: expected {} but got {}",
+          name, expected, actual
+        )),
+        None => mismatches.push(format!("header {}: missing", name)),
+      }
+    }
+    if let Some(expected) = &self.response.body {
+      let actual = serde_json::from_slice::<Value>(res.body())
+        .unwrap_or_else(|_| Value::from(String::from_utf8_lossy(res.body()).to_string()));
+      let rules = PactRules::parse(self.response.matching_rules.as_ref())?;
+      mismatches.extend(rules.compare(expected, &actual));
+    }
+    Ok(mismatches)
+  }
+}
+
+impl Pact {
+  pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+    let content = std::fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&content)?)
+  }
+
+  /// One fixture route per distinct request, the first interaction winning.
+  /// Among those sharing a method and path, routes with more request checks
+  /// come first so that the more general ones do not shadow them.
+  pub fn to_routes(&self) -> Vec<Route> {
+    let checks = |route: &Route| {
+      route.options().when.as_ref().map_or(0, |when| {
+        when.headers.len() + when.query.len() + when.json.len() + usize::from(when.body.is_some())
+      })
+    };
+    let mut routes: Vec<Route> = vec![];
+    for interaction in &self.interactions {
+      let route = match interaction.to_route() {
+        Ok(route) => route,
+        Err(e) => {
+          warn!("'{}': skipping interaction, {}", interaction.description, e);
+          continue;
+        }
+      };
+      if routes
+        .iter()
+        .any(|r| r.id() == route.id() && r.options().when == route.options().when)
+      {
+        warn!(
+          "'{}': {} is already answered by another interaction with the same request",
+          interaction.description,
+          route.id()
+        );
+        continue;
+      }
+      let at = routes
+        .iter()
+        .position(|r| r.id() == route.id() && checks(r) < checks(&route))
+        .unwrap_or(routes.len());
+      routes.insert(at, route);
+    }
+    routes
+  }
+
+  /// Replay every interaction against `router`, which should serve
+  /// [`scratch_routes`](crate::scratch_routes) as writes are replayed too
+  pub fn verify(&self, router: &Router) -> crate::Result<Vec<ContractCheck>> {
+    let mut ret = vec![];
+    for interaction in &self.interactions {
+      let req = interaction.to_request()?;
+      let res = router
+        .dispatch(&req, Response::default())
+        .unwrap_or_else(|e| e.into());
+      ret.push(ContractCheck {
+        method: req.method().unwrap_or(Method::Get),
+        endpoint: interaction.request.path.clone(),
+        drifts: interaction.verify(&res)?,
+      });
+    }
+    Ok(ret)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Request, Response, Router};
+
+  use super::Pact;
+
+  const PACT: &str = r#"{
+    "consumer": {"name": "web"},
+    "provider": {"name": "users"},
+    "interactions": [{
+      "description": "a list of users",
+      "providerState": "users exist",
+      "request": {"method": "GET", "path": "/users"},
+      "response": {
+        "status": 200,
+        "headers": {"Content-Type": "application/json"},
+        "body": [{"id": 1, "name": "Joe", "email": "joe@example.com"}],
+        "matchingRules": {
+          "$.body": {"min": 1},
+          "$.body[*].id": {"match": "type"},
+          "$.body[*].email": {"match": "regex", "regex": ".+@.+"}
+        }
+      }
+    }]
+  }"#;
+
+  #[test]
+  fn import_and_verify() {
+    let pact: Pact = serde_json::from_str(PACT).unwrap();
+    let routes = pact.to_routes();
+    assert_eq!(routes.len(), 1);
+    assert_eq!(routes[0].id(), "GET /users");
+    let router = Router::default().with_routes(routes);
+    let results = pact.verify(&router).unwrap();
+    assert!(results[0].is_ok(), "{:?}", results);
+  }
+
+  #[test]
+  fn mismatches() {
+    let pact: Pact = serde_json::from_str(PACT).unwrap();
+    let mut drifted = pact.clone();
+    drifted.interactions[0].response.body = Some(
+      serde_json::from_str(r#"[{"id": 2, "name": "Ann", "email": "ann@x"}, {"id": "3", "name": "Bob", "email": "nope"}]"#)
+        .unwrap(),
+    );
+    let router = Router::default().with_routes(drifted.to_routes());
+    let check = pact.verify(&router).unwrap().remove(0);
+    assert_eq!(
+      check.drifts,
+      vec![
+        "$.body.0.name: expected Joe but got Ann",
+        "$.body.1.email: nope does not match /^(?:.+@.+)$/",
+        "$.body.1.id: expected a number but got string",
+        "$.body.1.name: expected Joe but got Bob",
+      ]
+    );
+  }
+
+  #[test]
+  fn request_criteria() {
+    let pact: Pact = serde_json::from_str(
+      r#"{
+        "consumer": {"name": "web"},
+        "provider": {"name": "users"},
+        "interactions": [{
+          "description": "all users",
+          "request": {"method": "GET", "path": "/users"},
+          "response": {"status": 200, "body": [1, 2]}
+        }, {
+          "description": "admins",
+          "request": {"method": "GET", "path": "/users", "query": "role=admin%20user"},
+          "response": {"status": 200, "body": [1]}
+        }, {
+          "description": "admins again",
+          "request": {"method": "GET", "path": "/users", "query": {"role": ["admin user"]}},
+          "response": {"status": 200, "body": [2]}
+        }, {
+          "description": "a new admin",
+          "request": {
+            "method": "POST",
+            "path": "/users",
+            "headers": {"Content-Type": "application/json"},
+            "body": {"name": "Ann", "roles": ["admin"]}
+          },
+          "response": {"status": 201}
+        }, {
+          "description": "an invalid user",
+          "request": {"method": "POST", "path": "/users", "body": {"name": ""}},
+          "response": {"status": 422}
+        }]
+      }"#,
+    )
+    .unwrap();
+    let routes = pact.to_routes();
+    assert_eq!(
+      routes
+        .iter()
+        .map(|r| r.options().when.is_some())
+        .collect::<Vec<_>>(),
+      vec![true, false, true, true]
+    );
+    let router = Router::default().with_routes(routes);
+    let status = |req: Request| {
+      router
+        .dispatch(&req, Response::default())
+        .map_or_else(|e| Response::from(e).status(), |res| res.status())
+    };
+    let body = |target: &str| {
+      let res = router
+        .dispatch(&Request::new(Method::Get, target), Response::default())
+        .unwrap();
+      serde_json::from_slice::<serde_json::Value>(res.body()).unwrap()
+    };
+    assert_eq!(body("/users"), serde_json::json!([1, 2]));
+    assert_eq!(body("/users?role=admin+user"), serde_json::json!([1]));
+    let post = |content_type: &str, body: &str| {
+      Request::new(Method::Post, "/users")
+        .with_header("Content-Type", content_type)
+        .with_body(body)
+    };
+    assert_eq!(
+      status(post(
+        "application/json; charset=utf-8",
+        r#"{"roles": ["admin"], "name": "Ann"}"#
+      )),
+      201
+    );
+    assert_eq!(status(post("application/json", r#"{"name": ""}"#)), 422);
+    assert_eq!(status(post("text/plain", r#"{"name": "Bob"}"#)), 404);
+  }
+}
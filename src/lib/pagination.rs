@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use serde::{Deserialize, Serialize};
+
+use crate::{query_decode, Error, ErrorKind, Request, Response, Status, Value};
+
+type Item = HashMap<String, Value>;
+
+/// Keep the `items` having a string field, at any depth, containing the
+/// decoded `q` query parameter of `req`, ignoring case
+pub fn search_items(req: &Request, items: &mut Vec<Item>) {
+  fn contains(value: &Value, needle: &str) -> bool {
+    match value {
+      Value::String(s) => s.to_lowercase().contains(needle),
+      Value::Array(values) => values.iter().any(|v| contains(v, needle)),
+      Value::Map(map) => map.values().any(|v| contains(v, needle)),
+      _ => false,
+    }
+  }
+  if let Some((_, Some(q))) = req.query_param("q") {
+    let needle = query_decode(&q).to_lowercase();
+    items.retain(|item| item.values().any(|v| contains(v, &needle)));
+  }
+}
+
+/// Sort `items` by the fields the `_sort` query parameter of `req` lists,
+/// e.g. `_sort=age,name`, each in the matching `_order`, `asc` by default.
+/// Fields are dotted paths, items missing them coming first.
+pub fn sort_items(req: &Request, items: &mut [Item]) -> crate::Result<()> {
+  let param = |name: &str| {
+    req
+      .query_param(name)
+      .and_then(|(_, value)| value)
+      .map(|value| {
+        value
+          .split(',')
+          .map(|v| v.trim().to_string())
+          .collect::<Vec<_>>()
+      })
+      .unwrap_or_default()
+  };
+  let (fields, orders) = (param("_sort"), param("_order"));
+  let mut keys = vec![];
+  for (i, field) in fields.into_iter().enumerate() {
+    let descending = match orders.get(i).map(|o| o.to_ascii_lowercase()).as_deref() {
+      None | Some("asc") => false,
+      Some("desc") => true,
+      Some(order) => {
+        return Err(Pagination::bad_request(format!(
+          "invalid `_order` '{}', expected asc or desc",
+          order
+        )))
+      }
+    };
+    keys.push((field, descending));
+  }
+  let lookup = |item: &Item, field: &str| -> Value {
+    let (root, rest) = field.split_once('.').unwrap_or((field, ""));
+    item
+      .get(root)
+      .and_then(|v| v.get_path(rest))
+      .cloned()
+      .unwrap_or_default()
+  };
+  if !keys.is_empty() {
+    items.sort_by(|a, b| {
+      keys
+        .iter()
+        .map(|(field, descending)| {
+          let ordering = lookup(a, field).loose_cmp(&lookup(b, field));
+          match descending {
+            true => ordering.reverse(),
+            false => ordering,
+          }
+        })
+        .find(|o| o.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+    });
+  }
+  Ok(())
+}
+
+/// Convention store routes follow to list their items page by page.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PaginationStyle {
+  /// `?offset=40&limit=20`, answered with `offset`, `limit` and `total`
+  #[default]
+  Offset,
+  /// `?page=3&per_page=20`, answered with `page`, `per_page`, `total` and
+  /// `total_pages`
+  Page,
+  /// `?cursor=...&limit=20`, answered with an opaque `next_cursor`
+  Cursor,
+  /// `?page=3&per_page=20`, answered with a bare array, a `Link` header
+  /// (RFC 5988) and `X-Total-Count`
+  Link,
+}
+
+/// How a store route lists its items when requested without an identifier.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pagination {
+  #[serde(default)]
+  pub style: PaginationStyle,
+  /// Items per page when the request does not say
+  #[serde(default = "Pagination::default_size")]
+  pub default_size: usize,
+  /// Most items a page can hold, whatever the request asks for
+  #[serde(default = "Pagination::default_max_size")]
+  pub max_size: usize,
+}
+
+/// Items of a page along with what the style tells about the others
+#[derive(Serialize)]
+struct Envelope<'a> {
+  items: &'a [Item],
+  #[serde(skip_serializing_if = "Option::is_none")]
+  offset: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  limit: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  page: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  per_page: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  total: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  total_pages: Option<usize>,
+  /// `Some(Value::Null)` on the last page of cursor-based pagination
+  #[serde(skip_serializing_if = "Option::is_none")]
+  next_cursor: Option<Value>,
+}
+
+impl<'a> Envelope<'a> {
+  fn new(items: &'a [Item]) -> Self {
+    Self {
+      items,
+      offset: None,
+      limit: None,
+      page: None,
+      per_page: None,
+      total: None,
+      total_pages: None,
+      next_cursor: None,
+    }
+  }
+}
+
+impl Pagination {
+  fn default_size() -> usize {
+    20
+  }
+
+  fn default_max_size() -> usize {
+    100
+  }
+
+  pub fn new(style: PaginationStyle) -> Self {
+    Self {
+      style,
+      default_size: Self::default_size(),
+      max_size: Self::default_max_size(),
+    }
+  }
+
+  pub fn with_default_size(mut self, size: usize) -> Self {
+    self.default_size = size;
+    self
+  }
+
+  pub fn with_max_size(mut self, size: usize) -> Self {
+    self.max_size = size;
+    self
+  }
+
+  fn bad_request(message: String) -> Error {
+    Error::new(ErrorKind::Api(Status::BadRequest), Some(message), None)
+  }
+
+  /// Numeric query parameter `name` of `req`, if given
+  fn param(req: &Request, name: &str) -> crate::Result<Option<usize>> {
+    match req.query_param(name).and_then(|(_, value)| value) {
+      Some(value) => value.parse().map(Some).map_err(|_| {
+        Self::bad_request(format!(
+          "invalid `{}` '{}', expected a positive integer",
+          name, value
+        ))
+      }),
+      None => Ok(None),
+    }
+  }
+
+  /// Page size asked for by the `name` query parameter, within bounds
+  fn size(&self, req: &Request, name: &str) -> crate::Result<usize> {
+    let size = Self::param(req, name)?.unwrap_or(self.default_size);
+    Ok(size.clamp(1, self.max_size.max(1)))
+  }
+
+  pub fn encode_cursor(offset: usize) -> String {
+    URL_SAFE_NO_PAD.encode(format!("offset:{}", offset))
+  }
+
+  pub fn decode_cursor(cursor: &str) -> crate::Result<usize> {
+    URL_SAFE_NO_PAD
+      .decode(cursor)
+      .ok()
+      .and_then(|bytes| String::from_utf8(bytes).ok())
+      .and_then(|s| s.strip_prefix("offset:").and_then(|o| o.parse().ok()))
+      .ok_or_else(|| Self::bad_request(format!("invalid cursor '{}'", cursor)))
+  }
+
+  /// Target of `req` asking for another page
+  fn page_target(req: &Request, page: usize, per_page: usize) -> String {
+    let mut query = req
+      .query_params()
+      .into_iter()
+      .filter(|(k, _)| !k.eq_ignore_ascii_case("page") && !k.eq_ignore_ascii_case("per_page"))
+      .map(|(k, v)| match v {
+        Some(v) => format!("{}={}", k, v),
+        None => k,
+      })
+      .collect::<Vec<_>>();
+    query.push(format!("page={}", page));
+    query.push(format!("per_page={}", per_page));
+    format!("{}?{}", req.path().unwrap_or("/"), query.join("&"))
+  }
+
+  /// Page size `_page` alone implies, as json-server does
+  pub const COLLECTION_PAGE_SIZE: usize = 10;
+
+  /// Items of a store route without a `pagination` option asked for by
+  /// `req`: every item, or those `_page`/`_limit` or `offset`/`limit`
+  /// select, as a bare array along with `X-Total-Count`
+  pub fn collection(req: &Request, items: &[Item]) -> crate::Result<Response> {
+    Self::collection_with(req, items.len(), |offset, size| {
+      Ok(items[offset..offset.saturating_add(size).min(items.len())].to_vec())
+    })
+  }
+
+  /// Items asked for by `req` out of `total`, see [`Self::collection`],
+  /// `fetch` reading `size` of them from `offset` on
+  pub fn collection_with<F: FnOnce(usize, usize) -> crate::Result<Vec<Item>>>(
+    req: &Request,
+    total: usize,
+    fetch: F,
+  ) -> crate::Result<Response> {
+    let mut limit = match Self::param(req, "_limit")? {
+      Some(limit) => Some(limit),
+      None => Self::param(req, "limit")?,
+    };
+    let offset = match (Self::param(req, "_page")?, Self::param(req, "offset")?) {
+      (Some(page), _) => {
+        let limit = *limit.get_or_insert(Self::COLLECTION_PAGE_SIZE);
+        (page.max(1) - 1).saturating_mul(limit)
+      }
+      (None, offset) => offset.unwrap_or_default(),
+    };
+    let offset = offset.min(total);
+    let items = fetch(offset, limit.unwrap_or(total - offset))?;
+    let mut res = Response::api_for(req, Status::OK, &items)?;
+    res.set_header("X-Total-Count", total.to_string());
+    Ok(res)
+  }
+
+  /// The page of `items` asked for by `req`
+  pub fn respond(&self, req: &Request, items: &[Item]) -> crate::Result<Response> {
+    let total = items.len();
+    self.respond_with(req, total, |offset, size| {
+      Ok(items[offset.min(total)..(offset + size).min(total)].to_vec())
+    })
+  }
+
+  /// The page asked for by `req` out of `total` items, `fetch` reading
+  /// `size` of them from `offset` on
+  pub fn respond_with<F: FnMut(usize, usize) -> crate::Result<Vec<Item>>>(
+    &self,
+    req: &Request,
+    total: usize,
+    mut fetch: F,
+  ) -> crate::Result<Response> {
+    let mut slice = |offset: usize, size: usize| fetch(offset.min(total), size);
+    match self.style {
+      PaginationStyle::Offset => {
+        let (offset, limit) = (
+          Self::param(req, "offset")?.unwrap_or_default(),
+          self.size(req, "limit")?,
+        );
+        let items = slice(offset, limit)?;
+        let mut envelope = Envelope::new(&items);
+        envelope.offset = Some(offset);
+        envelope.limit = Some(limit);
+        envelope.total = Some(total);
+        Response::api_for(req, Status::OK, &envelope)
+      }
+      PaginationStyle::Page | PaginationStyle::Link => {
+        let page = Self::param(req, "page")?.unwrap_or(1).max(1);
+        let per_page = self.size(req, "per_page")?;
+        let total_pages = total.div_ceil(per_page).max(1);
+        let items = slice((page - 1) * per_page, per_page)?;
+        if self.style == PaginationStyle::Page {
+          let mut envelope = Envelope::new(&items);
+          envelope.page = Some(page);
+          envelope.per_page = Some(per_page);
+          envelope.total = Some(total);
+          envelope.total_pages = Some(total_pages);
+          return Response::api_for(req, Status::OK, &envelope);
+        }
+        let mut links = vec![(1, "first")];
+        if page > 1 {
+          links.push(((page - 1).min(total_pages), "prev"));
+        }
+        if page < total_pages {
+          links.push((page + 1, "next"));
+        }
+        links.push((total_pages, "last"));
+        let link = links
+          .iter()
+          .map(|(page, rel)| {
+            format!(
+              "<{}>; rel=\"{}\"",
+              Self::page_target(req, *page, per_page),
+              rel
+            )
+          })
+          .collect::<Vec<_>>()
+          .join(", ");
+        let mut res = Response::api_for(req, Status::OK, &items)?;
+        res.set_header("Link", link);
+        res.set_header("X-Total-Count", total.to_string());
+        Ok(res)
+      }
+      PaginationStyle::Cursor => {
+        let offset = match req.query_param("cursor").and_then(|(_, c)| c) {
+          Some(cursor) => Self::decode_cursor(&cursor)?,
+          None => 0,
+        };
+        let limit = self.size(req, "limit")?;
+        let items = slice(offset, limit)?;
+        let mut envelope = Envelope::new(&items);
+        envelope.next_cursor = Some(match offset + limit < total {
+          true => Value::from(Self::encode_cursor(offset + limit)),
+          false => Value::Null,
+        });
+        Response::api_for(req, Status::OK, &envelope)
+      }
+    }
+  }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+  use std::collections::HashMap;
+
+  use crate::{Method, Request, Value};
+
+  use super::{search_items, sort_items, Item, Pagination, PaginationStyle};
+
+  #[test]
+  fn styles() {
+    let items = (1..=5)
+      .map(|id| HashMap::from([("id".to_string(), Value::from(id))]))
+      .collect::<Vec<_>>();
+    let body = |style: PaginationStyle, target: &str| {
+      let res = Pagination::new(style)
+        .with_default_size(2)
+        .respond(&Request::new(Method::Get, target), &items)
+        .unwrap();
+      let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+      (res, body)
+    };
+
+    let (_, offset) = body(PaginationStyle::Offset, "/users?offset=4");
+    assert_eq!(offset["items"], serde_json::json!([{"id": 5}]));
+    assert_eq!(offset["total"], 5);
+
+    let (_, page) = body(PaginationStyle::Page, "/users?page=2&per_page=3");
+    assert_eq!(page["items"], serde_json::json!([{"id": 4}, {"id": 5}]));
+    assert_eq!(page["total_pages"], 2);
+
+    let (_, first) = body(PaginationStyle::Cursor, "/users");
+    let cursor = first["next_cursor"].as_str().unwrap();
+    assert_eq!(Pagination::decode_cursor(cursor).unwrap(), 2);
+    let (_, last) = body(PaginationStyle::Cursor, "/users?cursor=b2Zmc2V0OjQ");
+    assert_eq!(last["items"], serde_json::json!([{"id": 5}]));
+    assert!(last["next_cursor"].is_null());
+
+    let (res, linked) = body(PaginationStyle::Link, "/users?sort=id&page=2");
+    assert_eq!(linked, serde_json::json!([{"id": 3}, {"id": 4}]));
+    assert_eq!(
+      res.header("Link").unwrap(),
+      "</users?sort=id&page=1&per_page=2>; rel=\"first\", \
+       </users?sort=id&page=1&per_page=2>; rel=\"prev\", \
+       </users?sort=id&page=3&per_page=2>; rel=\"next\", \
+       </users?sort=id&page=3&per_page=2>; rel=\"last\""
+    );
+    assert_eq!(res.header("X-Total-Count").unwrap(), "5");
+  }
+
+  #[test]
+  fn collection() {
+    let items = (1..=25)
+      .map(|id| HashMap::from([("id".to_string(), Value::from(id))]))
+      .collect::<Vec<_>>();
+    let ids = |target: &str| {
+      let res = Pagination::collection(&Request::new(Method::Get, target), &items).unwrap();
+      assert_eq!(res.header("X-Total-Count").unwrap(), "25");
+      let body: Vec<serde_json::Value> = serde_json::from_slice(res.body()).unwrap();
+      body
+        .iter()
+        .map(|i| i["id"].as_u64().unwrap())
+        .collect::<Vec<_>>()
+    };
+    assert_eq!(ids("/users").len(), 25);
+    assert_eq!(ids("/users?_page=3"), (21..=25).collect::<Vec<_>>());
+    assert_eq!(ids("/users?_page=2&_limit=2"), vec![3, 4]);
+    assert_eq!(ids("/users?offset=23&limit=5"), vec![24, 25]);
+    assert_eq!(ids("/users?offset=30"), Vec::<u64>::new());
+  }
+
+  #[test]
+  fn sort() {
+    let mut items = [("ada", 36), ("bob", 7), ("cid", 36), ("dan", 110)]
+      .map(|(name, age)| {
+        HashMap::from([
+          ("name".to_string(), Value::from(name)),
+          ("age".to_string(), Value::from(age)),
+        ])
+      })
+      .to_vec();
+    items.push(HashMap::from([("name".to_string(), Value::from("eve"))]));
+    let names = |target: &str, items: &mut Vec<Item>| {
+      sort_items(&Request::new(Method::Get, target), items).unwrap();
+      items
+        .iter()
+        .map(|i| i["name"].to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+    };
+    assert_eq!(names("/?_sort=age", &mut items), "eve,bob,ada,cid,dan");
+    assert_eq!(
+      names("/?_sort=age,name&_order=desc,desc", &mut items),
+      "dan,cid,ada,bob,eve"
+    );
+    assert_eq!(names("/?_sort=name", &mut items), "ada,bob,cid,dan,eve");
+    assert!(sort_items(
+      &Request::new(Method::Get, "/?_sort=age&_order=up"),
+      &mut items
+    )
+    .is_err());
+
+    search_items(&Request::new(Method::Get, "/?q=D"), &mut items);
+    assert_eq!(names("/", &mut items), "ada,cid,dan");
+  }
+}
@@ -0,0 +1,42 @@
+/// Match `text` against a glob `pattern`, where `*` matches any sequence of
+/// characters (including none) and `?` matches exactly one character.
+pub fn glob_match<P: AsRef<str>, T: AsRef<str>>(pattern: P, text: T) -> bool {
+  let pattern = pattern.as_ref().chars().collect::<Vec<_>>();
+  let text = text.as_ref().chars().collect::<Vec<_>>();
+  let (mut p, mut t) = (0, 0);
+  let mut backtrack: Option<(usize, usize)> = None;
+  while t < text.len() {
+    if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+      p += 1;
+      t += 1;
+    } else if p < pattern.len() && pattern[p] == '*' {
+      backtrack = Some((p, t));
+      p += 1;
+    } else if let Some((star_p, star_t)) = backtrack {
+      p = star_p + 1;
+      t = star_t + 1;
+      backtrack = Some((star_p, star_t + 1));
+    } else {
+      return false;
+    }
+  }
+  pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+  use super::glob_match;
+
+  #[test]
+  fn glob() {
+    assert!(glob_match("/users/*", "/users/42"));
+    assert!(glob_match("/users/*", "/users/42/orders"));
+    assert!(glob_match("/users/*", "/users/"));
+    assert!(!glob_match("/users/*", "/user"));
+    assert!(glob_match("/u?ers", "/users"));
+    assert!(glob_match("*", ""));
+    assert!(glob_match("/a*b*c", "/aXXbYYc"));
+    assert!(!glob_match("/a*b*c", "/aXXbYY"));
+    assert!(glob_match("/exact", "/exact"));
+  }
+}
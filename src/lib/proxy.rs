@@ -0,0 +1,499 @@
+use std::{
+  io::{copy, BufRead, BufReader, Read, Write},
+  net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream},
+  path::PathBuf,
+  sync::Arc,
+  thread,
+};
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{glob_match, Engine, Error, ErrorKind, Method, Request, Status, TlsInfo};
+
+/// Forward proxy answering some hosts and paths with the workspace routes,
+/// passing everything else through.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ProxyConfig {
+  /// Address to bind, the server's host by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub host: Option<IpAddr>,
+  #[serde(default = "ProxyConfig::default_port")]
+  pub port: u16,
+  /// Requests answered locally, as `host` or `host/path` glob patterns, e.g.
+  /// `api.example.com` or `*.example.com/v1/*`
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub intercept: Vec<String>,
+  /// Decrypt HTTPS traffic to intercepted hosts, with certificates issued by
+  /// a generated CA clients must trust
+  #[serde(default)]
+  pub mitm: bool,
+  /// Ask intercepted clients for a certificate, whose subject is exposed to
+  /// templates and the journal along with the other TLS details
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub client_certs: bool,
+  /// Where the CA is kept, `mocker/ca` in the user's data directory by
+  /// default, out of the workspace so its key is not shared along with it
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub ca_dir: Option<PathBuf>,
+}
+
+impl ProxyConfig {
+  pub(crate) fn default_port() -> u16 {
+    8888
+  }
+
+  pub fn ca_dir(&self) -> PathBuf {
+    self.ca_dir.clone().unwrap_or_else(|| {
+      let data = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from));
+      match data {
+        Some(dir) => dir.join("mocker").join("ca"),
+        None => PathBuf::from(".mocker/ca"),
+      }
+    })
+  }
+
+  /// Whether a request to `host` for `path` is answered locally, `None`
+  /// standing for any path
+  pub fn intercepts(&self, host: &str, path: Option<&str>) -> bool {
+    let host = host.to_ascii_lowercase();
+    self.intercept.iter().any(|rule| {
+      let (rule_host, rule_path) = match rule.split_once('/') {
+        Some((h, p)) => (h, Some(format!("/{}", p))),
+        None => (rule.as_str(), None),
+      };
+      glob_match(rule_host.to_ascii_lowercase(), &host)
+        && match (rule_path, path) {
+          (Some(pattern), Some(path)) => glob_match(pattern, path),
+          _ => true,
+        }
+    })
+  }
+}
+
+impl Default for ProxyConfig {
+  fn default() -> Self {
+    Self {
+      host: None,
+      port: Self::default_port(),
+      intercept: vec![],
+      mitm: false,
+      client_certs: false,
+      ca_dir: None,
+    }
+  }
+}
+
+/// Request line, headers and body read from a proxy client
+struct ProxyRequest {
+  method: String,
+  target: String,
+  version: String,
+  headers: Vec<(String, String)>,
+  body: Vec<u8>,
+}
+
+impl ProxyRequest {
+  /// Next request of `r`, `None` once the client is gone
+  fn read<R: BufRead>(mut r: R) -> crate::Result<Option<Self>> {
+    let mut line = String::new();
+    if r.read_line(&mut line)? == 0 {
+      return Ok(None);
+    }
+    let mut parts = line.split_whitespace();
+    let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+      (Some(m), Some(t), v) => (
+        m.to_string(),
+        t.to_string(),
+        v.unwrap_or("HTTP/1.1").to_string(),
+      ),
+      _ => {
+        return Err(Error::new(
+          ErrorKind::Api(Status::BadRequest),
+          Some(format!("invalid request line '{}'", line.trim())),
+          None,
+        ))
+      }
+    };
+    let mut headers = vec![];
+    loop {
+      line.clear();
+      if r.read_line(&mut line)? == 0 || line.trim().is_empty() {
+        break;
+      }
+      if let Some((name, value)) = line.split_once(':') {
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+      }
+    }
+    let mut req = Self {
+      method,
+      target,
+      version,
+      headers,
+      body: vec![],
+    };
+    let len = req
+      .header("Content-Length")
+      .and_then(|l| l.parse::<usize>().ok())
+      .unwrap_or_default();
+    req.body.resize(len, 0);
+    r.read_exact(&mut req.body)?;
+    Ok(Some(req))
+  }
+
+  fn header(&self, name: &str) -> Option<&str> {
+    self
+      .headers
+      .iter()
+      .find(|(k, _)| k.eq_ignore_ascii_case(name))
+      .map(|(_, v)| v.as_str())
+  }
+
+  /// Host, port and origin-form target the request is for, `default_port`
+  /// applying to origin-form requests
+  fn destination(&self, default_port: u16) -> Option<(String, u16, String)> {
+    let (authority, path) = match self.target.split_once("://") {
+      Some((_scheme, rest)) => match rest.find('/') {
+        Some(i) => (rest[..i].to_string(), rest[i..].to_string()),
+        None => (rest.to_string(), "/".to_string()),
+      },
+      None => (self.header("Host")?.to_string(), self.target.clone()),
+    };
+    let default_port = match self.target.starts_with("https://") {
+      true => 443,
+      false if self.target.starts_with("http://") => 80,
+      false => default_port,
+    };
+    let (host, port) = split_authority(&authority, default_port);
+    Some((host, port, path))
+  }
+
+  /// The request as sent to the origin server, asking it to close the
+  /// connection once answered
+  fn origin_bytes(&self, host: &str, port: u16, path: &str, default_port: u16) -> Vec<u8> {
+    let mut head = format!("{} {} {}\r\n", self.method, path, self.version);
+    match port == default_port {
+      true => head.push_str(&format!("Host: {}\r\n", host)),
+      false => head.push_str(&format!("Host: {}:{}\r\n", host, port)),
+    }
+    for (name, value) in &self.headers {
+      let hop = [
+        "host",
+        "connection",
+        "proxy-connection",
+        "proxy-authorization",
+      ];
+      if !hop.contains(&name.to_ascii_lowercase().as_str()) {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+      }
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    let mut ret = head.into_bytes();
+    ret.extend_from_slice(&self.body);
+    ret
+  }
+}
+
+/// Host and port of `host[:port]`
+fn split_authority(authority: &str, default_port: u16) -> (String, u16) {
+  match authority.rsplit_once(':') {
+    Some((host, port)) if !host.ends_with(':') => (
+      host.trim_matches(['[', ']']).to_ascii_lowercase(),
+      port.parse().unwrap_or(default_port),
+    ),
+    _ => (authority.to_ascii_lowercase(), default_port),
+  }
+}
+
+/// Forward proxy listener, see [`ProxyConfig`].
+#[derive(Clone)]
+pub struct Proxy {
+  config: ProxyConfig,
+  engine: Engine,
+  #[cfg(feature = "mitm")]
+  mitm: Option<Arc<crate::Mitm>>,
+}
+
+impl Proxy {
+  pub fn new(config: ProxyConfig, engine: Engine) -> crate::Result<Self> {
+    #[cfg(feature = "mitm")]
+    let mitm = match config.mitm {
+      true => Some(Arc::new(
+        crate::Mitm::load_or_create(config.ca_dir(), &config.intercept)?
+          .with_client_certs(config.client_certs),
+      )),
+      false => None,
+    };
+    #[cfg(not(feature = "mitm"))]
+    if config.mitm {
+      warn!("TLS interception requires the `mitm` feature, HTTPS traffic will pass through");
+    }
+    Ok(Self {
+      config,
+      engine,
+      #[cfg(feature = "mitm")]
+      mitm,
+    })
+  }
+
+  pub fn config(&self) -> &ProxyConfig {
+    &self.config
+  }
+
+  /// Bind the listener and serve it in the background
+  pub fn spawn(&self, default_host: IpAddr) -> crate::Result<SocketAddr> {
+    let addr = SocketAddr::new(self.config.host.unwrap_or(default_host), self.config.port);
+    let listener = TcpListener::bind(addr)?;
+    let addr = listener.local_addr()?;
+    info!("🛰  Forward proxy on http://{}", addr);
+    let proxy = Arc::new(self.clone());
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let stream = match stream {
+          Ok(stream) => stream,
+          Err(e) => {
+            warn!("Proxy connection failed: {}", e);
+            continue;
+          }
+        };
+        let proxy = proxy.clone();
+        thread::spawn(move || {
+          if let Err(e) = proxy.serve_connection(&stream) {
+            error!("Proxy session crashed: {}", e);
+          }
+        });
+      }
+    });
+    Ok(addr)
+  }
+
+  /// Serve one client connection: a tunnel, or a single plain HTTP request
+  pub fn serve_connection(&self, stream: &TcpStream) -> crate::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let req = match ProxyRequest::read(&mut reader)? {
+      Some(req) => req,
+      None => return Ok(()),
+    };
+    if req.method.eq_ignore_ascii_case("CONNECT") {
+      let (host, port) = split_authority(&req.target, 443);
+      let mut stream = stream;
+      stream.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")?;
+      stream.flush()?;
+      #[cfg(feature = "mitm")]
+      if let Some(mitm) = self
+        .mitm
+        .as_ref()
+        .filter(|_| self.config.intercepts(&host, None))
+      {
+        debug!("Intercepting TLS traffic to {}:{}", host, port);
+        let mut tls = mitm.accept(&host, stream.try_clone()?)?;
+        while let Some(req) = ProxyRequest::read(BufReader::new(&mut tls))? {
+          let info = crate::tls_info(&tls.conn);
+          let keep_alive = self.exchange(&req, &host, port, 443, Some(info), &mut tls)?;
+          if !keep_alive {
+            break;
+          }
+        }
+        tls.conn.send_close_notify();
+        tls.flush()?;
+        return Ok(());
+      }
+      return Self::tunnel(stream, &host, port);
+    }
+    let (host, port, _) = match req.destination(80) {
+      Some(destination) => destination,
+      None => {
+        return Self::reply(
+          stream,
+          Status::BadRequest,
+          "missing destination host".to_string(),
+        )
+      }
+    };
+    let mut stream = stream;
+    self.exchange(&req, &host, port, 80, None, &mut stream)?;
+    stream.flush()?;
+    Ok(stream.shutdown(Shutdown::Both)?)
+  }
+
+  /// Answer `req`, received over a connection with `tls` details if any,
+  /// locally when intercepted, otherwise through the origin server, returning
+  /// whether the client connection can be reused
+  fn exchange<S: Read + Write>(
+    &self,
+    req: &ProxyRequest,
+    host: &str,
+    port: u16,
+    default_port: u16,
+    tls: Option<TlsInfo>,
+    client: &mut S,
+  ) -> crate::Result<bool> {
+    let (_, _, path) =
+      req
+        .destination(default_port)
+        .unwrap_or((host.to_string(), port, req.target.clone()));
+    let path_only = path.split('?').next().unwrap_or_default();
+    // Intercepted requests only reaching routes switched off go through
+    let switched_off = match req.method.parse::<Method>() {
+      Ok(method) => self.engine.router().switched_off(method, path_only)?,
+      Err(_) => false,
+    };
+    if self.config.intercepts(host, Some(path_only)) && !switched_off {
+      debug!("Intercepted {} {}{}", req.method, host, path);
+      let bytes = req.origin_bytes(host, port, &path, default_port);
+      let mut local = Request::from_reader(&bytes[..])?;
+      if let Some(tls) = tls {
+        local.extensions_mut().insert(tls);
+      }
+      let mut res = self
+        .engine
+        .prepare(&mut local)
+        .and_then(|_| self.engine.respond(&local))
+        .map(|(res, _options)| res)
+        .unwrap_or_else(|e| e.into());
+      let keep_alive = !req
+        .header("Connection")
+        .is_some_and(|c| c.eq_ignore_ascii_case("close"));
+      res.set_header("Content-Length", res.body().len().to_string());
+      let mut head = res.head();
+      if res.body().is_empty() {
+        head.push(b'\n');
+      }
+      client.write_all(&head)?;
+      client.write_all(res.body())?;
+      client.flush()?;
+      return Ok(keep_alive);
+    }
+    debug!("Passing {} {}:{}{} through", req.method, host, port, path);
+    let bytes = req.origin_bytes(host, port, &path, default_port);
+    #[cfg(feature = "mitm")]
+    if default_port == 443 {
+      let mut origin = crate::Mitm::connect(host, port)?;
+      origin.write_all(&bytes)?;
+      origin.flush()?;
+      Self::relay(&mut origin, client)?;
+      return Ok(false);
+    }
+    let mut origin = TcpStream::connect((host, port))?;
+    origin.write_all(&bytes)?;
+    Self::relay(&mut origin, client)?;
+    Ok(false)
+  }
+
+  /// Copy the origin response to the client, until the origin closes
+  fn relay<R: Read, W: Write>(origin: &mut R, client: &mut W) -> crate::Result<()> {
+    match copy(origin, client) {
+      Ok(_) => {}
+      // TLS peers closing without notify are common enough to be ignored
+      Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {}
+      Err(e) => return Err(e.into()),
+    }
+    Ok(client.flush()?)
+  }
+
+  /// Relay bytes both ways between the client and `host:port`
+  fn tunnel(client: &TcpStream, host: &str, port: u16) -> crate::Result<()> {
+    debug!("Tunnelling to {}:{}", host, port);
+    let origin = TcpStream::connect((host, port))?;
+    let (mut client_in, mut origin_out) = (client.try_clone()?, origin.try_clone()?);
+    let upstream = thread::spawn(move || {
+      let _ = copy(&mut client_in, &mut origin_out);
+      let _ = origin_out.shutdown(Shutdown::Write);
+    });
+    let (mut origin_in, mut client_out) = (origin, client.try_clone()?);
+    let _ = copy(&mut origin_in, &mut client_out);
+    let _ = client_out.shutdown(Shutdown::Write);
+    let _ = upstream.join();
+    Ok(())
+  }
+
+  fn reply(mut stream: &TcpStream, status: Status, message: String) -> crate::Result<()> {
+    let res: crate::Response = Error::new(ErrorKind::Api(status), Some(message), None).into();
+    res.write_to(&mut stream)?;
+    Ok(stream.shutdown(Shutdown::Both)?)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    io::{Read, Write},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    thread,
+  };
+
+  use crate::{Config, Engine, Method, Route, RouteKind, Value};
+
+  use super::{Proxy, ProxyConfig};
+
+  #[test]
+  fn intercept() {
+    let config = ProxyConfig {
+      port: 0,
+      intercept: vec!["api.example.test".into(), "*.cdn.test/v1/*".into()],
+      ..Default::default()
+    };
+    assert!(config.intercepts("API.example.test", None));
+    assert!(config.intercepts("img.cdn.test", None));
+    assert!(config.intercepts("img.cdn.test", Some("/v1/logo.png")));
+    assert!(!config.intercepts("img.cdn.test", Some("/v2/logo.png")));
+    assert!(!config.intercepts("example.test", None));
+
+    let engine = Engine::new(&Config {
+      routes: vec![Route::new(
+        vec![Method::Get],
+        "/hello",
+        RouteKind::Fixture {
+          status: 200,
+          headers: Default::default(),
+          body: Some(Value::from("stubbed")),
+          file: None,
+          template: false,
+        },
+      )],
+      ..Default::default()
+    })
+    .unwrap();
+    let localhost = Ipv4Addr::LOCALHOST.into();
+    let proxy = Proxy::new(config, engine)
+      .unwrap()
+      .spawn(localhost)
+      .unwrap();
+    let send = |raw: String| {
+      let mut stream = TcpStream::connect(proxy).unwrap();
+      stream.write_all(raw.as_bytes()).unwrap();
+      let mut res = String::new();
+      stream.read_to_string(&mut res).unwrap();
+      res
+    };
+    let res =
+      send("GET http://api.example.test/hello HTTP/1.1\r\nConnection: close\r\n\r\n".into());
+    assert!(res.ends_with("stubbed"), "{}", res);
+
+    // anything else reaches the origin
+    let origin = TcpListener::bind((localhost, 0)).unwrap();
+    let origin_addr = origin.local_addr().unwrap();
+    thread::spawn(move || {
+      let (mut stream, _) = origin.accept().unwrap();
+      let mut buf = [0; 1024];
+      let n = stream.read(&mut buf).unwrap();
+      let received = String::from_utf8_lossy(&buf[..n]).to_string();
+      assert!(
+        received.starts_with("GET /hello HTTP/1.1\r\n"),
+        "{}",
+        received
+      );
+      stream
+        .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\norigin")
+        .unwrap();
+    });
+    let res = send(format!(
+      "GET http://{}/hello HTTP/1.1\r\nProxy-Connection: keep-alive\r\n\r\n",
+      origin_addr
+    ));
+    assert!(res.ends_with("origin"), "{}", res);
+  }
+}
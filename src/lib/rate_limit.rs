@@ -0,0 +1,208 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{format_http_date, parse_duration, Response, Route};
+
+/// Which rate-limit headers a route sends, after a real API's conventions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RateLimitProfile {
+  /// `X-RateLimit-Limit`, `-Remaining`, `-Used` and `-Reset` (epoch seconds),
+  /// as sent by GitHub
+  #[default]
+  Github,
+  /// `RateLimit-Limit`, `-Remaining` and `-Reset` (delta seconds) along with
+  /// `RateLimit-Policy`, as in RFC 9239
+  Ietf,
+  /// Only `Retry-After`, once the limit is exceeded
+  RetryAfter,
+}
+
+/// How `Retry-After` tells clients when to come back.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RetryAfterFormat {
+  /// Delay in seconds, e.g. `30`
+  #[default]
+  Seconds,
+  /// HTTP date, e.g. `Wed, 21 Oct 2015 07:28:00 GMT`
+  Date,
+}
+
+/// Fixed-window rate limit of a route, announcing itself through the headers
+/// of a [`RateLimitProfile`] and answering `429 Too Many Requests` once
+/// exhausted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+  #[serde(default)]
+  pub profile: RateLimitProfile,
+  /// Requests allowed per window
+  pub limit: u64,
+  #[serde(default = "RateLimit::default_window")]
+  pub window: String,
+  #[serde(default)]
+  pub retry_after: RetryAfterFormat,
+}
+
+impl RateLimit {
+  fn default_window() -> String {
+    "60s".to_string()
+  }
+
+  pub fn new(profile: RateLimitProfile, limit: u64) -> Self {
+    Self {
+      profile,
+      limit,
+      window: Self::default_window(),
+      retry_after: Default::default(),
+    }
+  }
+
+  pub fn with_window<W: AsRef<str>>(mut self, window: W) -> Self {
+    self.window = window.as_ref().to_string();
+    self
+  }
+
+  pub fn with_retry_after(mut self, format: RetryAfterFormat) -> Self {
+    self.retry_after = format;
+    self
+  }
+
+  /// Add the headers of the profile to `res`, given the state of the window
+  pub fn apply(&self, usage: &RateLimitUsage, res: &mut Response) {
+    let reset_secs = usage.reset_in.div_ceil(1000);
+    match self.profile {
+      RateLimitProfile::Github => {
+        res.set_header("X-RateLimit-Limit", self.limit.to_string());
+        res.set_header("X-RateLimit-Remaining", usage.remaining().to_string());
+        res.set_header("X-RateLimit-Used", usage.used.min(self.limit).to_string());
+        res.set_header(
+          "X-RateLimit-Reset",
+          ((usage.now + usage.reset_in) / 1000).to_string(),
+        );
+      }
+      RateLimitProfile::Ietf => {
+        let window = parse_duration(&self.window).unwrap_or_default().as_secs();
+        res.set_header("RateLimit-Limit", self.limit.to_string());
+        res.set_header("RateLimit-Remaining", usage.remaining().to_string());
+        res.set_header("RateLimit-Reset", reset_secs.to_string());
+        res.set_header("RateLimit-Policy", format!("{};w={}", self.limit, window));
+      }
+      RateLimitProfile::RetryAfter => {}
+    }
+    if usage.exceeded() {
+      let value = match self.retry_after {
+        RetryAfterFormat::Seconds => reset_secs.to_string(),
+        RetryAfterFormat::Date => format_http_date(usage.now + usage.reset_in.max(1000)),
+      };
+      res.set_header("Retry-After", value);
+    }
+  }
+}
+
+/// State of a rate-limit window, as of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitUsage {
+  pub limit: u64,
+  /// Requests received during the window, this one included
+  pub used: u64,
+  /// Milliseconds since the unix epoch
+  pub now: u128,
+  /// Milliseconds until the window resets
+  pub reset_in: u128,
+}
+
+impl RateLimitUsage {
+  pub fn remaining(&self) -> u64 {
+    self.limit.saturating_sub(self.used)
+  }
+
+  pub fn exceeded(&self) -> bool {
+    self.used > self.limit
+  }
+}
+
+/// Rate-limit windows of every route, keyed by [`Route::id`].
+#[derive(Debug, Default)]
+pub struct RateLimits(Mutex<HashMap<String, (u128, u64)>>);
+
+impl RateLimits {
+  /// Count a request to `route` against `limit`, received at `now`
+  pub fn hit(&self, route: &Route, limit: &RateLimit, now: u128) -> crate::Result<RateLimitUsage> {
+    let window = parse_duration(&limit.window)?.as_millis().max(1);
+    let mut g = self.0.lock()?;
+    let (start, used) = g.entry(route.id()).or_insert((now, 0));
+    if now >= *start + window || now < *start {
+      (*start, *used) = (now, 0);
+    }
+    *used += 1;
+    Ok(RateLimitUsage {
+      limit: limit.limit,
+      used: *used,
+      now,
+      reset_in: *start + window - now,
+    })
+  }
+
+  pub fn reset(&self) -> crate::Result<()> {
+    self.0.lock()?.clear();
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{now_millis, Method, Response, Route, RouteKind};
+
+  use super::{RateLimit, RateLimitProfile, RateLimits, RetryAfterFormat};
+
+  #[test]
+  fn profiles() {
+    let route = Route::new(
+      vec![Method::Get],
+      "/repos",
+      RouteKind::Fixture {
+        status: 200,
+        headers: Default::default(),
+        body: None,
+        file: None,
+        template: false,
+      },
+    );
+    let limits = RateLimits::default();
+    let github = RateLimit::new(RateLimitProfile::Github, 2);
+    let usage = limits.hit(&route, &github, now_millis(0)).unwrap();
+    let mut res = Response::default();
+    github.apply(&usage, &mut res);
+    let header = |res: &Response, name: &str| res.header(name).map(str::to_string);
+    assert_eq!(header(&res, "X-RateLimit-Limit").as_deref(), Some("2"));
+    assert_eq!(header(&res, "X-RateLimit-Remaining").as_deref(), Some("1"));
+    assert_eq!(header(&res, "Retry-After"), None);
+
+    limits.hit(&route, &github, now_millis(0)).unwrap();
+    let usage = limits.hit(&route, &github, now_millis(0)).unwrap();
+    assert!(usage.exceeded());
+    let ietf = RateLimit::new(RateLimitProfile::Ietf, 2).with_window("1m");
+    let mut res = Response::default();
+    ietf.apply(&usage, &mut res);
+    assert_eq!(header(&res, "RateLimit-Remaining").as_deref(), Some("0"));
+    assert_eq!(header(&res, "RateLimit-Policy").as_deref(), Some("2;w=60"));
+    let seconds = header(&res, "Retry-After").unwrap().parse::<u64>().unwrap();
+    assert!((1..=60).contains(&seconds));
+
+    let dated =
+      RateLimit::new(RateLimitProfile::RetryAfter, 2).with_retry_after(RetryAfterFormat::Date);
+    let mut res = Response::default();
+    dated.apply(&usage, &mut res);
+    assert_eq!(res.headers().len(), 1);
+    assert!(header(&res, "Retry-After").unwrap().ends_with(" GMT"));
+
+    let parsed: RateLimit =
+      serde_json::from_str(r#"{"profile": "retry-after", "limit": 10, "retry_after": "date"}"#)
+        .unwrap();
+    assert_eq!(parsed.profile, RateLimitProfile::RetryAfter);
+    assert_eq!(parsed.window, "60s");
+    assert_eq!(parsed.retry_after, RetryAfterFormat::Date);
+  }
+}
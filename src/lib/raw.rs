@@ -0,0 +1,281 @@
+use std::{
+  collections::HashMap,
+  io::{Read, Write},
+  net::{IpAddr, SocketAddr, TcpListener, UdpSocket},
+  thread,
+};
+
+use log::{debug, error, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, ErrorKind};
+
+/// Bytes exchanged by a raw listener, given as text or as hexadecimal digits.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum Payload {
+  Text(String),
+  Hex { hex: String },
+}
+
+impl Payload {
+  pub fn bytes(&self) -> crate::Result<Vec<u8>> {
+    match self {
+      Payload::Text(text) => Ok(text.as_bytes().to_vec()),
+      Payload::Hex { hex } => {
+        let digits = hex
+          .chars()
+          .filter(|c| !c.is_whitespace())
+          .collect::<Vec<_>>();
+        digits
+          .chunks(2)
+          .map(|pair| {
+            let pair = pair.iter().collect::<String>();
+            u8::from_str_radix(&pair, 16).map_err(|e| {
+              Error::new(
+                ErrorKind::Parse,
+                Some(format!("invalid hex byte '{}': {}", pair, e)),
+                None,
+              )
+            })
+          })
+          .collect()
+      }
+    }
+  }
+}
+
+/// Step of a scripted exchange: wait for some bytes, then answer.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RawStep {
+  /// Bytes to receive before going on, anything when missing
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expect: Option<Payload>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub send: Option<Payload>,
+}
+
+/// How a raw listener answers what it receives
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum RawMode {
+  /// The same bytes to every message
+  Fixed { reply: Payload },
+  /// Every message sent back as is
+  Echo,
+  /// Steps played in order for every connection, or UDP peer
+  Script { steps: Vec<RawStep> },
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+  #[default]
+  Tcp,
+  Udp,
+}
+
+/// A non-HTTP listener stubbing a custom protocol dependency.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RawListener {
+  #[serde(default)]
+  pub transport: Transport,
+  /// Address to bind, the server's host by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub host: Option<IpAddr>,
+  pub port: u16,
+  #[serde(flatten)]
+  pub mode: RawMode,
+}
+
+impl RawListener {
+  const BUF_SIZE: usize = 64 * 1024;
+
+  pub fn new(transport: Transport, port: u16, mode: RawMode) -> Self {
+    Self {
+      transport,
+      host: None,
+      port,
+      mode,
+    }
+  }
+
+  pub fn with_host(mut self, host: IpAddr) -> Self {
+    self.host = Some(host);
+    self
+  }
+
+  /// Answer `received` as the `step`th message of an exchange, returning the
+  /// bytes to send and whether the exchange moves on to the next step
+  fn answer(&self, step: usize, received: &[u8]) -> crate::Result<(Option<Vec<u8>>, bool)> {
+    Ok(match &self.mode {
+      RawMode::Fixed { reply } => (Some(reply.bytes()?), false),
+      RawMode::Echo => (Some(received.to_vec()), false),
+      RawMode::Script { steps } => match steps.get(step) {
+        None => (None, false),
+        Some(RawStep { expect, send }) => {
+          let expected = expect.as_ref().map(|e| e.bytes()).transpose()?;
+          let complete = expected
+            .is_none_or(|e| e.is_empty() || received.windows(e.len()).any(|w| w == e.as_slice()));
+          match complete {
+            true => (send.as_ref().map(|s| s.bytes()).transpose()?, true),
+            false => (None, false),
+          }
+        }
+      },
+    })
+  }
+
+  /// Send what script steps from `step` on have to say without waiting for
+  /// anything, returning whether the script is over
+  fn speak<W: Write>(&self, step: &mut usize, mut w: W) -> crate::Result<bool> {
+    let steps = match &self.mode {
+      RawMode::Script { steps } => steps,
+      _ => return Ok(false),
+    };
+    while let Some(RawStep { expect: None, send }) = steps.get(*step) {
+      if let Some(send) = send {
+        w.write_all(&send.bytes()?)?;
+      }
+      *step += 1;
+    }
+    Ok(*step >= steps.len())
+  }
+
+  /// Play the exchange over a connected `stream`, until the peer or the
+  /// script ends it
+  pub fn serve_stream<S: Read + Write>(&self, mut stream: S) -> crate::Result<()> {
+    let mut step = 0;
+    let mut buf = vec![0; Self::BUF_SIZE];
+    let mut pending: Vec<u8> = vec![];
+    let mut over = self.speak(&mut step, &mut stream)?;
+    while !over {
+      stream.flush()?;
+      let n = stream.read(&mut buf)?;
+      if n == 0 {
+        break;
+      }
+      pending.extend_from_slice(&buf[..n]);
+      let (reply, next) = self.answer(step, &pending)?;
+      if let Some(reply) = reply {
+        stream.write_all(&reply)?;
+      }
+      // scripts accumulate bytes until they get what a step expects
+      if next || !matches!(self.mode, RawMode::Script { .. }) {
+        pending.clear();
+      }
+      if next {
+        step += 1;
+        over = self.speak(&mut step, &mut stream)?;
+      }
+    }
+    Ok(stream.flush()?)
+  }
+
+  /// Bind the listener and serve it in the background
+  pub fn spawn(&self, default_host: IpAddr) -> crate::Result<SocketAddr> {
+    let addr = SocketAddr::new(self.host.unwrap_or(default_host), self.port);
+    let listener = self.clone();
+    match self.transport {
+      Transport::Tcp => {
+        let socket = TcpListener::bind(addr)?;
+        let addr = socket.local_addr()?;
+        info!("🔌 Raw TCP listener on {}", addr);
+        thread::spawn(move || {
+          for stream in socket.incoming() {
+            let stream = match stream {
+              Ok(stream) => stream,
+              Err(e) => {
+                warn!("Raw TCP connection failed: {}", e);
+                continue;
+              }
+            };
+            let listener = listener.clone();
+            thread::spawn(move || {
+              if let Err(e) = listener.serve_stream(&stream) {
+                error!("Raw TCP session on {} crashed: {}", addr, e);
+              }
+            });
+          }
+        });
+        Ok(addr)
+      }
+      Transport::Udp => {
+        let socket = UdpSocket::bind(addr)?;
+        let addr = socket.local_addr()?;
+        info!("🔌 Raw UDP listener on {}", addr);
+        thread::spawn(move || {
+          if let Err(e) = listener.serve_datagrams(&socket) {
+            error!("Raw UDP listener on {} crashed: {}", addr, e);
+          }
+        });
+        Ok(addr)
+      }
+    }
+  }
+
+  /// Answer datagrams as they come, scripts keeping one exchange per peer
+  fn serve_datagrams(&self, socket: &UdpSocket) -> crate::Result<()> {
+    let mut buf = vec![0; Self::BUF_SIZE];
+    let mut steps: HashMap<SocketAddr, usize> = HashMap::new();
+    loop {
+      let (n, peer) = socket.recv_from(&mut buf)?;
+      debug!("Raw UDP datagram of {} bytes from {}", n, peer);
+      let step = steps.entry(peer).or_default();
+      let (reply, next) = self.answer(*step, &buf[..n])?;
+      if let Some(reply) = reply {
+        socket.send_to(&reply, peer)?;
+      }
+      if next {
+        *step += 1;
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    io::{Read, Write},
+    net::{Ipv4Addr, TcpStream, UdpSocket},
+  };
+
+  use super::{Payload, RawListener, RawMode, RawStep, Transport};
+
+  #[test]
+  fn listeners() {
+    let localhost = Ipv4Addr::LOCALHOST.into();
+    let script = RawMode::Script {
+      steps: vec![
+        RawStep {
+          expect: None,
+          send: Some(Payload::Text("HELLO\n".into())),
+        },
+        RawStep {
+          expect: Some(Payload::Text("PING\n".into())),
+          send: Some(Payload::Hex {
+            hex: "50 4f 4e 47 0a".into(),
+          }),
+        },
+      ],
+    };
+    let addr = RawListener::new(Transport::Tcp, 0, script)
+      .spawn(localhost)
+      .unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(b"PI").unwrap();
+    stream.write_all(b"NG\n").unwrap();
+    let mut received = String::new();
+    stream.read_to_string(&mut received).unwrap();
+    assert_eq!(received, "HELLO\nPONG\n");
+
+    let addr = RawListener::new(Transport::Udp, 0, RawMode::Echo)
+      .spawn(localhost)
+      .unwrap();
+    let socket = UdpSocket::bind((localhost, 0)).unwrap();
+    socket.send_to(b"\x01\x02", addr).unwrap();
+    let mut buf = [0; 8];
+    let (n, _) = socket.recv_from(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"\x01\x02");
+  }
+}
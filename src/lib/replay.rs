@@ -0,0 +1,239 @@
+use std::{
+  io::Write,
+  path::Path,
+  sync::mpsc::channel,
+  thread,
+  time::{Duration, Instant},
+};
+
+use crate::{Capture, CaptureConfig, CaptureStore, Client, Error, ErrorKind, Method, Request};
+
+/// Parse a replay speed such as `2x`, `0.5x` or `1`; `max` sends everything
+/// without waiting
+pub fn parse_speed<S: AsRef<str>>(s: S) -> crate::Result<f64> {
+  let s = s.as_ref().trim();
+  if s.eq_ignore_ascii_case("max") {
+    return Ok(f64::INFINITY);
+  }
+  match s.trim_end_matches(['x', 'X']).parse::<f64>() {
+    Ok(speed) if speed > 0.0 => Ok(speed),
+    _ => Err(Error::new(
+      ErrorKind::Parse,
+      Some(format!(
+        "invalid speed '{}', expected a positive factor such as `2x`",
+        s
+      )),
+      None,
+    )),
+  }
+}
+
+/// What became of one replayed request
+#[derive(Debug, Clone)]
+pub struct ReplayOutcome {
+  pub method: Method,
+  pub target: String,
+  /// When it was sent, relative to the first request
+  pub offset: Duration,
+  /// Status recorded along with the request
+  pub expected: u16,
+  /// Status the target answered with, or why it could not be reached
+  pub actual: Result<u16, String>,
+  pub latency: Duration,
+}
+
+impl ReplayOutcome {
+  pub fn is_ok(&self) -> bool {
+    self.actual.as_ref().is_ok_and(|s| *s == self.expected)
+  }
+}
+
+/// Plays the client side of recorded traffic against a server again, keeping
+/// the delays between requests, scaled by a speed factor. Requests are sent
+/// on time even when earlier ones are still waiting for their response.
+#[derive(Debug, Clone)]
+pub struct SessionReplay {
+  captures: Vec<Capture>,
+  speed: f64,
+}
+
+impl SessionReplay {
+  pub fn new(mut captures: Vec<Capture>) -> Self {
+    captures.sort_by_key(|c| c.at);
+    Self {
+      captures,
+      speed: 1.0,
+    }
+  }
+
+  /// Captures of a capture directory, or of a single capture file
+  pub fn load<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+    let path = path.as_ref();
+    let captures = match path.is_dir() {
+      true => CaptureStore::open(CaptureConfig {
+        dir: path.to_path_buf(),
+        compress: false,
+      })?
+      .load()?,
+      false => CaptureStore::load_file(path)?,
+    };
+    Ok(Self::new(captures))
+  }
+
+  pub fn with_speed(mut self, speed: f64) -> Self {
+    self.speed = speed;
+    self
+  }
+
+  /// Only keep requests served by the route declared on `endpoint`
+  pub fn with_route<E: AsRef<str>>(mut self, endpoint: E) -> Self {
+    self
+      .captures
+      .retain(|c| c.route.as_deref() == Some(endpoint.as_ref()));
+    self
+  }
+
+  pub fn captures(&self) -> &[Capture] {
+    &self.captures
+  }
+
+  /// Delay of `capture` after the first request, at the replay speed
+  fn offset(&self, capture: &Capture) -> Duration {
+    let first = self.captures.first().map(|c| c.at).unwrap_or_default();
+    let recorded = Duration::from_millis((capture.at - first) as u64);
+    match self.speed.is_finite() {
+      true => recorded.div_f64(self.speed),
+      false => Duration::ZERO,
+    }
+  }
+
+  fn request(capture: &Capture) -> Request {
+    let headers = capture.request_headers.iter().filter(|(name, _)| {
+      !name.eq_ignore_ascii_case("Host") && !name.eq_ignore_ascii_case("Content-Length")
+    });
+    let mut req = Request::new(capture.method, &capture.target);
+    for (name, value) in headers {
+      req.set_header(name, value);
+    }
+    match capture.request_body.is_empty() {
+      true => req,
+      false => req.with_body(&capture.request_body),
+    }
+  }
+
+  /// Send every request through `client`, reporting outcomes to `on_outcome`
+  /// as they come, then all of them in recorded order
+  pub fn run<F: FnMut(&ReplayOutcome)>(
+    &self,
+    client: &Client,
+    mut on_outcome: F,
+  ) -> crate::Result<Vec<ReplayOutcome>> {
+    let (tx, rx) = channel();
+    let started = Instant::now();
+    let mut outcomes = vec![];
+    for (i, capture) in self.captures.iter().enumerate() {
+      let offset = self.offset(capture);
+      if let Some(wait) = offset.checked_sub(started.elapsed()) {
+        thread::sleep(wait);
+      }
+      while let Ok((i, outcome)) = rx.try_recv() {
+        on_outcome(&outcome);
+        outcomes.push((i, outcome));
+      }
+      let (client, tx, req) = (client.clone(), tx.clone(), Self::request(capture));
+      let (method, target, expected) = (capture.method, capture.target.clone(), capture.status);
+      thread::spawn(move || {
+        let sent = Instant::now();
+        let actual = client
+          .send(&req)
+          .map(|res| res.status())
+          .map_err(|e| e.to_string());
+        let outcome = ReplayOutcome {
+          method,
+          target,
+          offset: sent.duration_since(started),
+          expected,
+          actual,
+          latency: sent.elapsed(),
+        };
+        let _ = tx.send((i, outcome));
+      });
+    }
+    drop(tx);
+    for (i, outcome) in rx {
+      on_outcome(&outcome);
+      outcomes.push((i, outcome));
+    }
+    outcomes.sort_by_key(|(i, _)| *i);
+    Ok(outcomes.into_iter().map(|(_, outcome)| outcome).collect())
+  }
+
+  pub fn write_outcome<W: Write>(outcome: &ReplayOutcome, mut w: W) -> crate::Result<()> {
+    let status = match &outcome.actual {
+      Ok(status) if *status == outcome.expected => format!("{}", status),
+      Ok(status) => format!("{} (recorded {})", status, outcome.expected),
+      Err(e) => format!("failed: {}", e),
+    };
+    writeln!(
+      w,
+      "  {} +{:.3}s {} {} → {} ({:.1}ms)",
+      if outcome.is_ok() { "✔" } else { "✘" },
+      outcome.offset.as_secs_f64(),
+      outcome.method,
+      outcome.target,
+      status,
+      outcome.latency.as_secs_f64() * 1000.0
+    )?;
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::{Duration, Instant};
+
+  use crate::{Capture, Client, Method, Request, Response};
+
+  use super::{parse_speed, SessionReplay};
+
+  #[test]
+  fn replay() {
+    assert_eq!(parse_speed("2x").unwrap(), 2.0);
+    assert_eq!(parse_speed("0.5").unwrap(), 0.5);
+    assert!(parse_speed("max").unwrap().is_infinite());
+    assert!(parse_speed("-1x").is_err());
+
+    let capture = |at: u128, target: &str, status: u16| {
+      let req = Request::new(Method::Get, target).with_header("Host", "prod.example.com");
+      let mut capture = Capture::new(None, &req, &Response::default().with_status_code(status));
+      capture.at = at;
+      capture
+    };
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+      for stream in listener.incoming() {
+        let stream = stream.unwrap();
+        let req = Request::from_reader(&stream).unwrap();
+        let res = match req.path() {
+          Some("/a") => Response::default().with_status_code(200),
+          _ => Response::default().with_status_code(404),
+        };
+        res.write_to(&stream).unwrap();
+      }
+    });
+    let replay = SessionReplay::new(vec![
+      capture(1_000_400, "/b", 200),
+      capture(1_000_000, "/a", 200),
+    ])
+    .with_speed(4.0);
+    let started = Instant::now();
+    let outcomes = replay.run(&Client::new(addr.to_string()), |_| {}).unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(100));
+    assert_eq!(outcomes.len(), 2);
+    assert_eq!(outcomes[0].target, "/a");
+    assert!(outcomes[0].is_ok());
+    assert_eq!(outcomes[1].actual, Ok(404));
+    assert!(!outcomes[1].is_ok());
+  }
+}
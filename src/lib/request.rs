@@ -4,39 +4,60 @@ use std::{
   ops::{Deref, DerefMut},
 };
 
-use serde::{de::DeserializeOwned, Deserialize};
+use serde::de::DeserializeOwned;
 
-use crate::{Buffer, Error, ErrorKind, Method, Status, Value};
+use crate::{
+  BodyParsers, Buffer, Error, ErrorKind, Extensions, MediaType, Method, PathParams, StartLine,
+  Status, TlsInfo, Value, Version,
+};
 
 #[derive(Clone, Default)]
-pub struct Request(Buffer);
+pub struct Request(Buffer, Extensions);
 
 impl Request {
-  const BUF_SIZE: usize = 255;
-
-  pub fn from_reader<R: Read>(mut r: R) -> crate::Result<Self> {
-    let mut block: [u8; Self::BUF_SIZE] = [0u8; Self::BUF_SIZE];
-    let mut buf = vec![];
-    loop {
-      let nread = r.read(&mut block)?;
-      buf.extend_from_slice(&block[0..nread]);
-      if nread < Self::BUF_SIZE {
-        break;
-      }
-    }
-    let s = std::str::from_utf8(&buf)?;
-    Ok(Self(s.parse::<Buffer>()?))
+  pub fn from_reader<R: Read>(r: R) -> crate::Result<Self> {
+    Ok(Self(Buffer::read_from(r)?, Extensions::default()))
+  }
+
+  pub fn new<T: AsRef<str>>(method: Method, target: T) -> Self {
+    Self(
+      Buffer::default().with_start_line(StartLine::request(method, target, Version::default())),
+      Extensions::default(),
+    )
+  }
+
+  /// Values attached by middlewares and the router while serving this request
+  pub fn extensions(&self) -> &Extensions {
+    &self.1
+  }
+
+  pub fn extensions_mut(&mut self) -> &mut Extensions {
+    &mut self.1
+  }
+
+  /// Path parameters captured by the route serving this request, such as
+  /// `id` for `/users/{id}` or `/users/:id`
+  pub fn path_params(&self) -> HashMap<String, String> {
+    self
+      .extensions()
+      .get::<PathParams>()
+      .map(|p| p.0.clone())
+      .unwrap_or_default()
+  }
+
+  pub fn path_param<N: AsRef<str>>(&self, name: N) -> Option<String> {
+    self
+      .extensions()
+      .get::<PathParams>()
+      .and_then(|p| p.0.get(name.as_ref()).cloned())
   }
 
   pub fn query_param<K: AsRef<str>>(&self, k: K) -> Option<(String, Option<String>)> {
-    match self
+    self
       .query_params()
       .iter()
       .find(|(key, _val)| key.eq_ignore_ascii_case(k.as_ref()))
-    {
-      Some((key, val)) => Some((key.clone(), val.clone())),
-      None => None,
-    }
+      .map(|(key, val)| (key.clone(), val.clone()))
   }
 
   pub fn query_params(&self) -> Vec<(String, Option<String>)> {
@@ -56,7 +77,7 @@ impl Request {
   pub fn query(&self) -> Option<&str> {
     let start = self.start_line().as_request().unwrap();
     match start.target.split_once('?') {
-      Some((first, second)) => Some(second),
+      Some((_first, second)) => Some(second),
       None => None,
     }
   }
@@ -65,11 +86,13 @@ impl Request {
     self.start_line().as_request().map(|r| r.method)
   }
 
+  /// Target of the request without its query string, the whole target when
+  /// it has none
   pub fn path(&self) -> Option<&str> {
     let start = self.start_line().as_request().unwrap();
     match start.target.split_once('?') {
-      Some((first, second)) => Some(first),
-      None => None,
+      Some((first, _second)) => Some(first),
+      None => Some(start.target.as_str()),
     }
   }
 
@@ -88,6 +111,10 @@ impl Request {
     self.0 = self.0.with_body(v);
     self
   }
+  pub fn with_body_bytes<B: Into<bytes::Bytes>>(mut self, v: B) -> Self {
+    self.0 = self.0.with_body_bytes(v);
+    self
+  }
   pub fn append_body<B: AsRef<str>>(&mut self, v: B) {
     self.0.append_body(v);
   }
@@ -95,20 +122,85 @@ impl Request {
     self.0.set_header(k, v);
   }
 
+  /// Value of the cookie `name`, from the `Cookie` header
+  pub fn cookie<N: AsRef<str>>(&self, name: N) -> Option<&str> {
+    self
+      .header("Cookie")?
+      .split(';')
+      .filter_map(|pair| pair.trim().split_once('='))
+      .find(|(k, _)| *k == name.as_ref())
+      .map(|(_, v)| v)
+  }
+
+  /// Structured view of this request (method, path, query, headers and body),
+  /// with header names lowercased and the body parsed when possible.
+  pub fn to_value(&self) -> Value {
+    let query = self
+      .query_params()
+      .into_iter()
+      .map(|(k, v)| (k, Value::from(v)))
+      .collect::<HashMap<_, _>>();
+    let headers = self
+      .headers()
+      .iter()
+      .map(|(k, v)| (k.to_ascii_lowercase(), Value::from(v.as_str())))
+      .collect::<HashMap<_, _>>();
+    let body = match self.body().is_empty() {
+      true => Value::Null,
+      false => self
+        .parse_body::<Value>()
+        .unwrap_or_else(|_| Value::from(String::from_utf8_lossy(self.body()).to_string())),
+    };
+    let mut map = HashMap::from([
+      (
+        "method".to_string(),
+        Value::from(self.method().map(|m| m.repr())),
+      ),
+      ("path".to_string(), Value::from(self.path())),
+      ("query".to_string(), Value::from(query)),
+      ("headers".to_string(), Value::from(headers)),
+      ("body".to_string(), body),
+    ]);
+    if let Some(tls) = self.extensions().get::<TlsInfo>() {
+      map.insert("tls".to_string(), tls.to_value());
+    }
+    Value::from(map)
+  }
+
   pub fn parse_body<T: DeserializeOwned>(&self) -> crate::Result<T> {
-    let body = format!("{}\n", std::str::from_utf8(self.body())?.trim());
     let content_type = match self.header("Content-Type") {
-      Some(v) => v,
+      Some(v) => v.parse::<MediaType>()?,
       None => {
         return Err(Error::new(
           ErrorKind::Api(Status::BadRequest),
-          Some(format!("Missing `Content-Type` header")),
+          Some("Missing `Content-Type` header".to_string()),
           None,
         ));
       }
     };
+    if let Some(parser) = BodyParsers::find(&content_type) {
+      let value = parser(self.body())?;
+      #[cfg(feature = "json")]
+      return serde_json::from_value(value.to_json()).map_err(|e| {
+        Error::new(
+          ErrorKind::Parse,
+          Some(format!("failed to deserialize request body, {}", e)),
+          None,
+        )
+      });
+      #[cfg(not(feature = "json"))]
+      let _ = value;
+    }
+    if !content_type.is_utf8() {
+      return Err(Error::new(
+        ErrorKind::Api(Status::UnsupportedMediaType),
+        Some(format!("Unsupported charset in '{}'", content_type)),
+        None,
+      ));
+    }
+    let body = format!("{}\n", std::str::from_utf8(self.body())?.trim());
     #[cfg(feature = "json")]
-    if content_type.eq_ignore_ascii_case("application/json") {
+    if content_type.is_json() {
       let ret: T = serde_json::from_str(&body).map_err(|e| {
         let mut arrowed_body = body
           .to_string()
@@ -136,7 +228,7 @@ impl Request {
       return Ok(ret);
     }
     #[cfg(feature = "toml")]
-    if content_type.eq_ignore_ascii_case("application/toml") {
+    if content_type.is_toml() {
       let ret: T = toml::from_str(&body).map_err(|e| {
         Error::new(
           ErrorKind::Parse,
@@ -147,7 +239,7 @@ impl Request {
       return Ok(ret);
     }
     #[cfg(feature = "yaml")]
-    if content_type.eq_ignore_ascii_case("application/yaml") {
+    if content_type.is_yaml() {
       let ret: T = serde_yml::from_str(&body).map_err(|e| {
         Error::new(
           ErrorKind::Parse,
@@ -168,9 +260,6 @@ impl Request {
   }
 }
 
-unsafe impl Send for Request {}
-unsafe impl Sync for Request {}
-
 impl Deref for Request {
   type Target = Buffer;
 
@@ -184,3 +273,18 @@ impl DerefMut for Request {
     &mut self.0
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use crate::Method;
+
+  use super::Request;
+
+  #[test]
+  fn path() {
+    assert_eq!(Request::new(Method::Get, "/users").path(), Some("/users"));
+    let req = Request::new(Method::Get, "/users?q=ada");
+    assert_eq!(req.path(), Some("/users"));
+    assert_eq!(req.query(), Some("q=ada"));
+  }
+}
@@ -1,6 +1,10 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+  io::Read,
+  ops::{Deref, DerefMut},
+  path::Path,
+};
 
-use crate::{Buffer, Error, ErrorKind, StartLine, Status, Version};
+use crate::{content_type_for, Buffer, Error, ErrorKind, MediaType, Request, Status, Version};
 
 #[derive(Clone, Default)]
 pub struct Response(Buffer);
@@ -48,19 +52,112 @@ impl Response {
   pub fn api<B: serde::Serialize>(status: Status, body: &B) -> crate::Result<Self> {
     #[cfg(feature = "json")]
     return Self::json(status, body);
-    #[cfg(feature = "toml")]
+    #[cfg(all(feature = "toml", not(feature = "json")))]
     return Self::toml(status, body);
-    #[cfg(feature = "yaml")]
+    #[cfg(all(feature = "yaml", not(any(feature = "json", feature = "toml"))))]
     return Self::yaml(status, body);
+    #[cfg(not(any(feature = "json", feature = "toml", feature = "yaml")))]
     Err(Error::new(
       ErrorKind::Api(Status::InternalServerError),
-      Some(format!(
-        "no api format defined: please select either `json`, `toml` or `yaml` feature"
-      )),
+      Some(
+        "no api format defined: please select either `json`, `toml` or `yaml` feature".to_string(),
+      ),
       None,
     ))
   }
 
+  /// Like [`Response::api`], in the enabled format `req` prefers, if it says
+  pub fn api_for<B: serde::Serialize>(
+    req: &Request,
+    status: Status,
+    body: &B,
+  ) -> crate::Result<Self> {
+    let accept = match req.header("Accept") {
+      Some(accept) => accept,
+      None => return Self::api(status, body),
+    };
+    let formats: &[&str] = &[
+      #[cfg(feature = "json")]
+      "application/json",
+      #[cfg(feature = "toml")]
+      "application/toml",
+      #[cfg(feature = "yaml")]
+      "application/yaml",
+    ];
+    let offered = formats
+      .iter()
+      .map(|f| f.parse())
+      .collect::<crate::Result<Vec<MediaType>>>()?;
+    match MediaType::negotiate(accept, &offered) {
+      #[cfg(feature = "json")]
+      Some(media_type) if media_type.is_json() => Self::json(status, body),
+      #[cfg(feature = "toml")]
+      Some(media_type) if media_type.is_toml() => Self::toml(status, body),
+      #[cfg(feature = "yaml")]
+      Some(media_type) if media_type.is_yaml() => Self::yaml(status, body),
+      _ => Self::api(status, body),
+    }
+  }
+
+  /// Response carrying `data` as is, typed `content_type`
+  pub fn bytes<C: AsRef<str>, B: Into<bytes::Bytes>>(content_type: C, data: B) -> Self {
+    Self::default()
+      .with_status(Status::OK)
+      .with_header("Content-Type", content_type)
+      .with_body_bytes(data)
+  }
+
+  /// Response carrying the content of the file at `path`, typed after its
+  /// extension
+  pub fn file<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+    let path = path.as_ref();
+    let data = std::fs::read(path).map_err(|e| match e.kind() {
+      std::io::ErrorKind::NotFound => Error::new(
+        ErrorKind::Api(Status::NotFound),
+        Some(format!("{} does not exist", path.display())),
+        None,
+      ),
+      _ => e.into(),
+    })?;
+    Ok(Self::bytes(content_type_for(path), data))
+  }
+
+  /// Like [`Response::file`], saved by browsers under the file's name
+  pub fn download<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
+    let name = path
+      .as_ref()
+      .file_name()
+      .map(|n| n.to_string_lossy().to_string())
+      .unwrap_or_default();
+    Ok(Self::file(path)?.with_attachment(name))
+  }
+
+  /// Ask browsers to save the body as `filename` instead of showing it
+  pub fn with_attachment<F: AsRef<str>>(mut self, filename: F) -> Self {
+    let filename = filename.as_ref().replace(['"', '\\', '\r', '\n'], "_");
+    self.set_header(
+      "Content-Disposition",
+      format!("attachment; filename=\"{}\"", filename),
+    );
+    self
+  }
+
+  /// Read a whole response, until the peer closes the connection
+  pub fn from_reader<R: Read>(mut r: R) -> crate::Result<Self> {
+    let mut buf = vec![];
+    r.read_to_end(&mut buf)?;
+    Ok(Self(Buffer::parse(buf.into())?))
+  }
+
+  pub fn status(&self) -> u16 {
+    self
+      .0
+      .start_line()
+      .as_response()
+      .map(|start| start.status)
+      .unwrap_or_default()
+  }
+
   pub fn with_status(mut self, status: Status) -> Self {
     let res = self.0.start_line_mut().as_response_mut().unwrap();
     res.status = status.code();
@@ -104,6 +201,10 @@ impl Response {
     self.0 = self.0.with_body(v);
     self
   }
+  pub fn with_body_bytes<B: Into<bytes::Bytes>>(mut self, v: B) -> Self {
+    self.0 = self.0.with_body_bytes(v);
+    self
+  }
   pub fn append_body<B: AsRef<str>>(&mut self, v: B) {
     self.0.append_body(v);
   }
@@ -112,9 +213,6 @@ impl Response {
   }
 }
 
-unsafe impl Send for Response {}
-unsafe impl Sync for Response {}
-
 impl Deref for Response {
   type Target = Buffer;
 
@@ -142,3 +240,37 @@ impl From<Error> for Response {
     res
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::Response;
+
+  #[test]
+  fn files() {
+    let res = Response::bytes("application/octet-stream", vec![0u8, 159, 146, 150]);
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.header("Content-Length").unwrap(), "4");
+
+    let path = std::env::temp_dir().join(format!("mocker-report-{}.pdf", std::process::id()));
+    std::fs::write(&path, b"%PDF-1.7").unwrap();
+    let res = Response::download(&path).unwrap();
+    assert_eq!(res.header("Content-Type").unwrap(), "application/pdf");
+    assert_eq!(res.header("Content-Length").unwrap(), "8");
+    assert_eq!(
+      res.header("Content-Disposition").unwrap(),
+      &format!(
+        "attachment; filename=\"{}\"",
+        path.file_name().unwrap().to_string_lossy()
+      )
+    );
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+      Response::file(&path)
+        .map_err(Response::from)
+        .err()
+        .unwrap()
+        .status(),
+      404
+    );
+  }
+}
@@ -0,0 +1,276 @@
+use std::{
+  collections::HashMap,
+  hash::{DefaultHasher, Hash, Hasher},
+  sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  namespace::NAMESPACE_HEADER, parse_duration, persona::ActivePersona, router::tenant, Request,
+  Response, Route, StoreEvents,
+};
+
+/// Memoization of a route's responses, sparing expensive templates and
+/// scripts from running again for the same request.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachePolicy {
+  /// How long responses are served from the cache, e.g. `30s`
+  #[serde(default = "CachePolicy::default_ttl")]
+  pub ttl: String,
+  /// Request headers telling cached responses apart, besides the method,
+  /// target and body
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub vary: Vec<String>,
+  /// Store endpoints whose changes invalidate the cache, any when empty
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub invalidate_on: Vec<String>,
+}
+
+impl Default for CachePolicy {
+  fn default() -> Self {
+    Self {
+      ttl: Self::default_ttl(),
+      vary: vec![],
+      invalidate_on: vec![],
+    }
+  }
+}
+
+impl CachePolicy {
+  fn default_ttl() -> String {
+    "60s".to_string()
+  }
+
+  pub fn with_ttl<T: AsRef<str>>(mut self, ttl: T) -> Self {
+    self.ttl = ttl.as_ref().to_string();
+    self
+  }
+
+  pub fn with_vary<H: AsRef<str>>(mut self, header: H) -> Self {
+    self.vary.push(header.as_ref().to_string());
+    self
+  }
+
+  pub fn with_invalidate_on<E: AsRef<str>>(mut self, endpoint: E) -> Self {
+    self.invalidate_on.push(endpoint.as_ref().to_string());
+    self
+  }
+
+  /// Key `req` is cached under for `route`, apart for each tenant,
+  /// namespace, session and persona since their target no longer tells
+  /// them apart
+  pub fn fingerprint(&self, route: &Route, req: &Request) -> String {
+    let mut hasher = DefaultHasher::new();
+    req.method().map(|m| m.to_string()).hash(&mut hasher);
+    req.start_line().to_string().hash(&mut hasher);
+    tenant(req).hash(&mut hasher);
+    req.header(NAMESPACE_HEADER).hash(&mut hasher);
+    route
+      .options()
+      .session_header
+      .as_ref()
+      .and_then(|header| req.header(header))
+      .hash(&mut hasher);
+    req
+      .extensions()
+      .get::<ActivePersona>()
+      .map(|active| &active.name)
+      .hash(&mut hasher);
+    for name in &self.vary {
+      name.to_ascii_lowercase().hash(&mut hasher);
+      req.header(name).hash(&mut hasher);
+    }
+    req.body().hash(&mut hasher);
+    format!("{}:{:016x}", route.id(), hasher.finish())
+  }
+
+  /// Changes made so far to the stores this cache depends on
+  pub fn revision(&self, events: &StoreEvents) -> crate::Result<u64> {
+    match self.invalidate_on.is_empty() {
+      true => events.revision(None),
+      false => self
+        .invalidate_on
+        .iter()
+        .map(|endpoint| events.revision(Some(endpoint)))
+        .sum(),
+    }
+  }
+}
+
+struct CachedResponse {
+  at: u128,
+  revision: u64,
+  response: Response,
+}
+
+/// Responses of every route with a [`CachePolicy`], by fingerprint.
+#[derive(Default)]
+pub struct ResponseCache(Mutex<HashMap<String, CachedResponse>>);
+
+impl ResponseCache {
+  /// Response header telling whether the cache was used, `HIT` or `MISS`
+  pub const STATUS_HEADER: &'static str = "X-Mocker-Cache";
+
+  /// Response cached under `key`, unless expired or stale at `now`
+  pub fn get(
+    &self,
+    key: &str,
+    policy: &CachePolicy,
+    revision: u64,
+    now: u128,
+  ) -> crate::Result<Option<Response>> {
+    let ttl = parse_duration(&policy.ttl)?.as_millis();
+    let mut g = self.0.lock()?;
+    match g.get(key) {
+      Some(cached) if cached.revision == revision && now < cached.at + ttl => {
+        Ok(Some(cached.response.clone()))
+      }
+      Some(_) => {
+        g.remove(key);
+        Ok(None)
+      }
+      None => Ok(None),
+    }
+  }
+
+  /// Cache `response` under `key` at `now`, if successful
+  pub fn put(
+    &self,
+    key: String,
+    revision: u64,
+    response: &Response,
+    now: u128,
+  ) -> crate::Result<()> {
+    if (200..300).contains(&response.status()) {
+      let cached = CachedResponse {
+        at: now,
+        revision,
+        response: response.clone(),
+      };
+      self.0.lock()?.insert(key, cached);
+    }
+    Ok(())
+  }
+
+  pub fn clear(&self) -> crate::Result<()> {
+    self.0.lock()?.clear();
+    Ok(())
+  }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+  use serde_json::json;
+
+  use crate::{
+    tenancy::Tenancy, CachePolicy, Config, Engine, Method, Request, ResponseCache, Route,
+    RouteKind, RouteOptions,
+  };
+
+  #[test]
+  fn memoize() {
+    let path = std::env::temp_dir().join(format!("mocker-cache-{}.json", std::process::id()));
+    std::fs::write(&path, "[]").unwrap();
+    let engine = Engine::new(&Config {
+      routes: vec![
+        Route::new(
+          vec![Method::Get],
+          "/token",
+          RouteKind::Fixture {
+            status: 200,
+            headers: Default::default(),
+            body: Some("{{counter 'calls'}}".into()),
+            file: None,
+            template: true,
+          },
+        )
+        .with_options(RouteOptions {
+          cache: Some(CachePolicy::default().with_vary("X-User")),
+          ..Default::default()
+        }),
+        Route::new(
+          vec![Method::Post],
+          "/users",
+          RouteKind::Store {
+            path: path.clone(),
+            identifier: "id".to_string(),
+            parent: None,
+          },
+        ),
+      ],
+      ..Default::default()
+    })
+    .unwrap();
+    let get = |user: &str| {
+      let res = engine.handle(Request::new(Method::Get, "/token").with_header("X-User", user));
+      let status = res
+        .header(ResponseCache::STATUS_HEADER)
+        .map(str::to_string)
+        .unwrap();
+      (String::from_utf8_lossy(res.body()).to_string(), status)
+    };
+    assert_eq!(get("ada"), ("1".to_string(), "MISS".to_string()));
+    assert_eq!(get("ada"), ("1".to_string(), "HIT".to_string()));
+    assert_eq!(get("bob"), ("2".to_string(), "MISS".to_string()));
+    let created = engine.handle(
+      Request::new(Method::Post, "/users")
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"id": 1}"#),
+    );
+    assert_eq!(created.status(), 201);
+    assert_eq!(get("ada"), ("3".to_string(), "MISS".to_string()));
+    engine.router().cache().clear().unwrap();
+    assert_eq!(get("ada"), ("4".to_string(), "MISS".to_string()));
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[test]
+  fn tenants() {
+    let dir = std::env::temp_dir().join(format!("mocker-cache-tenants-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("users.json");
+    std::fs::write(&path, "[]").unwrap();
+    let engine = Engine::from_config(&Config {
+      routes: vec![Route::new(
+        vec![Method::Get, Method::Post],
+        "/users",
+        RouteKind::Store {
+          path: path.clone(),
+          identifier: "id".to_string(),
+          parent: None,
+        },
+      )
+      .with_options(RouteOptions {
+        cache: Some(CachePolicy::default()),
+        ..Default::default()
+      })],
+      tenancy: Some(Tenancy {
+        header: None,
+        prefix: Some("/t".to_string()),
+      }),
+      ..Default::default()
+    })
+    .unwrap();
+    let get = |tenant: &str| {
+      let res = engine.handle(Request::new(Method::Get, format!("/t/{}/users", tenant)));
+      let status = res
+        .header(ResponseCache::STATUS_HEADER)
+        .map(str::to_string)
+        .unwrap();
+      let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+      (body, status)
+    };
+    let created = engine.handle(
+      Request::new(Method::Post, "/t/acme/users")
+        .with_header("Content-Type", "application/json")
+        .with_body(r#"{"id": 1}"#),
+    );
+    assert_eq!(created.status(), 201);
+    assert_eq!(get("acme"), (json!([{"id": 1}]), "MISS".to_string()));
+    assert_eq!(get("acme"), (json!([{"id": 1}]), "HIT".to_string()));
+    assert_eq!(get("globex"), (json!([]), "MISS".to_string()));
+    assert_eq!(get("globex"), (json!([]), "HIT".to_string()));
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,353 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+use crate::{Error, ErrorKind};
+
+/// Prefix of endpoints declared as a regular expression, e.g. `~^/v[0-9]+/users$`
+pub const REGEX_ENDPOINT_PREFIX: char = '~';
+
+lazy_static! {
+  static ref endpoint_regexes: Mutex<HashMap<String, Regex>> = Mutex::new(HashMap::new());
+}
+
+/// Regular expression `endpoint` is declared as, if it starts with
+/// [`REGEX_ENDPOINT_PREFIX`]
+pub fn endpoint_regex(endpoint: &str) -> Option<crate::Result<Regex>> {
+  let pattern = endpoint.strip_prefix(REGEX_ENDPOINT_PREFIX)?;
+  if let Some(re) = endpoint_regexes.lock().ok()?.get(pattern) {
+    return Some(Ok(re.clone()));
+  }
+  Some(match Regex::new(pattern) {
+    Ok(re) => {
+      if let Ok(mut cache) = endpoint_regexes.lock() {
+        cache.insert(pattern.to_string(), re.clone());
+      }
+      Ok(re)
+    }
+    Err(e) => Err(Error::new(
+      ErrorKind::Parse,
+      Some(format!("invalid endpoint regex '{}': {}", pattern, e)),
+      None,
+    )),
+  })
+}
+
+/// Segments of an endpoint or of a request path
+fn segments(path: &str) -> Vec<&str> {
+  path.trim_start_matches('/').split('/').collect()
+}
+
+/// Whether `segment`, found at position `i` of `count` segments, captures
+/// the rest of the path
+fn is_tail(segment: &str, i: usize, count: usize) -> bool {
+  i + 1 == count && (segment == "*" || segment == "**")
+}
+
+/// Name of the parameter declared by `segment`, as `{name}` or `:name`
+fn param_name(segment: &str) -> Option<&str> {
+  match segment.strip_prefix(':') {
+    Some(name) => Some(name),
+    None => segment.strip_prefix('{')?.strip_suffix('}'),
+  }
+  .filter(|name| !name.is_empty())
+}
+
+/// Trie of endpoint segments, finding what is registered for a path in time
+/// proportional to its length rather than to the number of routes.
+///
+/// Endpoint segments are either literal, a `{name}` (or `:name`) parameter
+/// matching any non-empty segment, a `*` doing the same anonymously, or a
+/// trailing `*` or `**` matching the rest of the path, `**` also matching
+/// the path without it (`/static/**` serves `/static`).
+/// Literal segments win over parameters, which win over wildcards.
+///
+/// Endpoints starting with `~` are regular expressions matched against the
+/// whole path, in registration order, when no other endpoint matches.
+#[derive(Debug, Clone)]
+pub struct RouteIndex<T> {
+  value: Option<T>,
+  statics: HashMap<String, RouteIndex<T>>,
+  param: Option<Box<RouteIndex<T>>>,
+  wildcard: Option<T>,
+  globstar: Option<T>,
+  regexes: Vec<(String, Regex, T)>,
+}
+
+impl<T> Default for RouteIndex<T> {
+  fn default() -> Self {
+    Self {
+      value: None,
+      statics: HashMap::new(),
+      param: None,
+      wildcard: None,
+      globstar: None,
+      regexes: Vec::new(),
+    }
+  }
+}
+
+impl<T> RouteIndex<T> {
+  /// Value registered for `endpoint`, inserted if missing
+  pub fn entry<E: AsRef<str>>(&mut self, endpoint: E) -> &mut T
+  where
+    T: Default,
+  {
+    if let Some(Ok(re)) = endpoint_regex(endpoint.as_ref()) {
+      let source = endpoint.as_ref();
+      let i = match self.regexes.iter().position(|(s, _, _)| s == source) {
+        Some(i) => i,
+        None => {
+          self.regexes.push((source.to_string(), re, T::default()));
+          self.regexes.len() - 1
+        }
+      };
+      return &mut self.regexes[i].2;
+    }
+    let segments = segments(endpoint.as_ref());
+    let mut node = self;
+    for (i, segment) in segments.iter().enumerate() {
+      node = match *segment {
+        "*" if i + 1 == segments.len() => return node.wildcard.get_or_insert_with(T::default),
+        "**" if i + 1 == segments.len() => return node.globstar.get_or_insert_with(T::default),
+        "*" | "**" => node.param.get_or_insert_with(Default::default),
+        s if param_name(s).is_some() => node.param.get_or_insert_with(Default::default),
+        s => node.statics.entry(s.to_string()).or_default(),
+      };
+    }
+    node.value.get_or_insert_with(T::default)
+  }
+
+  /// Most specific value registered for an endpoint matching `path`, and
+  /// accepted by `filter`
+  pub fn find<P: AsRef<str>, F: Fn(&T) -> bool>(&self, path: P, filter: F) -> Option<&T> {
+    self.lookup(&segments(path.as_ref()), &filter).or_else(|| {
+      self
+        .regexes
+        .iter()
+        .find(|(_, re, v)| re.is_match(path.as_ref()) && filter(v))
+        .map(|(_, _, v)| v)
+    })
+  }
+
+  fn lookup<F: Fn(&T) -> bool>(&self, segments: &[&str], filter: &F) -> Option<&T> {
+    let (first, rest) = match segments.split_first() {
+      Some(split) => split,
+      None => {
+        return self
+          .value
+          .as_ref()
+          .filter(|v| filter(v))
+          .or_else(|| self.globstar.as_ref().filter(|v| filter(v)))
+      }
+    };
+    self
+      .statics
+      .get(*first)
+      .and_then(|node| node.lookup(rest, filter))
+      .or_else(|| {
+        self
+          .param
+          .as_ref()
+          .filter(|_| !first.is_empty())
+          .and_then(|node| node.lookup(rest, filter))
+      })
+      .or_else(|| self.wildcard.as_ref().filter(|v| filter(v)))
+      .or_else(|| self.globstar.as_ref().filter(|v| filter(v)))
+  }
+
+  /// Every value registered for an endpoint matching `path`, most specific
+  /// first
+  pub fn find_all<P: AsRef<str>>(&self, path: P) -> Vec<&T> {
+    let mut ret = vec![];
+    self.collect(&segments(path.as_ref()), &mut ret);
+    ret.extend(
+      (self.regexes.iter())
+        .filter(|(_, re, _)| re.is_match(path.as_ref()))
+        .map(|(_, _, v)| v),
+    );
+    ret
+  }
+
+  fn collect<'a>(&'a self, segments: &[&str], ret: &mut Vec<&'a T>) {
+    let (first, rest) = match segments.split_first() {
+      Some(split) => split,
+      None => {
+        ret.extend(self.value.iter().chain(&self.globstar));
+        return;
+      }
+    };
+    if let Some(node) = self.statics.get(*first) {
+      node.collect(rest, ret);
+    }
+    if let Some(node) = self.param.as_ref().filter(|_| !first.is_empty()) {
+      node.collect(rest, ret);
+    }
+    ret.extend(self.wildcard.iter().chain(&self.globstar));
+  }
+
+  /// Every registered value
+  pub fn values(&self) -> Vec<&T> {
+    let mut ret = self
+      .value
+      .iter()
+      .chain(&self.wildcard)
+      .chain(&self.globstar)
+      .chain(self.regexes.iter().map(|(_, _, v)| v))
+      .collect::<Vec<_>>();
+    for node in self.statics.values().chain(self.param.as_deref()) {
+      ret.extend(node.values());
+    }
+    ret
+  }
+}
+
+/// Whether `path` is served by a route declared on `endpoint`
+pub fn endpoint_matches<E: AsRef<str>, P: AsRef<str>>(endpoint: E, path: P) -> bool {
+  let mut index = RouteIndex::default();
+  *index.entry(endpoint) = true;
+  index.find(path, |v| *v).is_some()
+}
+
+/// Segments of `path` captured by the parameters of `endpoint`, the rest of
+/// the path being captured as `*` by a trailing `*` or `**`, or by the named
+/// groups of a regular expression. `None` when `path` does not match.
+pub fn endpoint_params<E: AsRef<str>, P: AsRef<str>>(
+  endpoint: E,
+  path: P,
+) -> Option<HashMap<String, String>> {
+  if let Some(re) = endpoint_regex(endpoint.as_ref()) {
+    let re = re.ok()?;
+    let captures = re.captures(path.as_ref())?;
+    return Some(
+      re.capture_names()
+        .flatten()
+        .filter_map(|name| Some((name.to_string(), captures.name(name)?.as_str().to_string())))
+        .collect(),
+    );
+  }
+  let (pattern, segments) = (segments(endpoint.as_ref()), segments(path.as_ref()));
+  let mut params = HashMap::new();
+  for (i, expected) in pattern.iter().enumerate() {
+    match *expected {
+      s if is_tail(s, i, pattern.len()) => {
+        if s == "*" && segments.len() <= i {
+          return None;
+        }
+        params.insert("*".to_string(), segments.get(i..)?.join("/"));
+        return Some(params);
+      }
+      "*" | "**" => {
+        segments.get(i).filter(|s| !s.is_empty())?;
+      }
+      s => match param_name(s) {
+        Some(name) => {
+          let given = segments.get(i).filter(|s| !s.is_empty())?;
+          params.insert(name.to_string(), given.to_string());
+        }
+        None if segments.get(i) == Some(&s) => {}
+        None => return None,
+      },
+    }
+  }
+  (pattern.len() == segments.len()).then_some(params)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{endpoint_matches, endpoint_params, endpoint_regex, RouteIndex};
+
+  #[test]
+  fn precedence() {
+    let mut index = RouteIndex::default();
+    *index.entry("/users/me") = "me";
+    *index.entry("/users/{id}") = "user";
+    *index.entry("/users/{id}/orders") = "orders";
+    *index.entry("/files/*") = "files";
+    *index.entry("/") = "root";
+    let find = |path| index.find(path, |_| true).copied();
+    assert_eq!(find("/users/me"), Some("me"));
+    assert_eq!(find("/users/42"), Some("user"));
+    assert_eq!(find("/users/me/orders"), Some("orders"));
+    assert_eq!(find("/users/"), None);
+    assert_eq!(find("/files/a/b.txt"), Some("files"));
+    assert_eq!(find("/files"), None);
+    assert_eq!(find("/"), Some("root"));
+    assert_eq!(index.find("/users/me", |v| *v != "me"), Some(&"user"));
+    assert_eq!(index.find_all("/users/me"), vec![&"me", &"user"]);
+    assert_eq!(index.values().len(), 5);
+    assert!(endpoint_matches("/exact", "/exact"));
+    assert!(!endpoint_matches("/exact", "/exact/"));
+    let params = endpoint_params("/users/{id}/files/*", "/users/42/files/a/b.txt").unwrap();
+    assert_eq!(params["id"], "42");
+    assert_eq!(params["*"], "a/b.txt");
+    assert_eq!(endpoint_params("/users/{id}", "/users/"), None);
+    assert_eq!(endpoint_params("/users/{id}", "/orders/1"), None);
+    assert_eq!(
+      endpoint_params("/users/:id", "/users/7").unwrap()["id"],
+      "7"
+    );
+    assert!(endpoint_matches("/users/:id/orders", "/users/7/orders"));
+  }
+
+  #[test]
+  fn regexes() {
+    let mut index = RouteIndex::default();
+    *index.entry("~^/v[0-9]+/users$") = "users";
+    *index.entry("/v1/users") = "v1";
+    let find = |path| index.find(path, |_| true).copied();
+    assert_eq!(find("/v1/users"), Some("v1"));
+    assert_eq!(find("/v12/users"), Some("users"));
+    assert_eq!(find("/v12/users/1"), None);
+    let params = endpoint_params(
+      r"~^/v(?P<version>[0-9]+)/users/(?P<id>\d+)$",
+      "/v2/users/42",
+    )
+    .unwrap();
+    assert_eq!(params["version"], "2");
+    assert_eq!(params["id"], "42");
+    assert!(endpoint_regex("~(").unwrap().is_err());
+    assert!(endpoint_regex("/users").is_none());
+  }
+
+  #[test]
+  fn wildcards() {
+    let mut index = RouteIndex::default();
+    *index.entry("/api/*") = "api";
+    *index.entry("/api/users") = "users";
+    *index.entry("/static/**") = "static";
+    *index.entry("/static/app.js") = "app";
+    *index.entry("/teams/*/members") = "members";
+    let find = |path| index.find(path, |_| true).copied();
+    assert_eq!(find("/api/users"), Some("users"));
+    assert_eq!(find("/api/orders/1"), Some("api"));
+    assert_eq!(find("/api"), None);
+    assert_eq!(find("/static"), Some("static"));
+    assert_eq!(find("/static/css/site.css"), Some("static"));
+    assert_eq!(find("/static/app.js"), Some("app"));
+    assert_eq!(find("/teams/red/members"), Some("members"));
+    assert_eq!(find("/teams//members"), None);
+    assert_eq!(
+      endpoint_params("/static/**", "/static/css/site.css").unwrap()["*"],
+      "css/site.css"
+    );
+    assert_eq!(endpoint_params("/static/**", "/static").unwrap()["*"], "");
+    assert_eq!(endpoint_params("/api/*", "/api"), None);
+    assert!(endpoint_params("/teams/*/members", "/teams/red/members")
+      .unwrap()
+      .is_empty());
+  }
+
+  #[test]
+  fn many_routes() {
+    let mut index = RouteIndex::default();
+    for i in 0..10_000 {
+      *index.entry(format!("/api/v1/resource{}/{{id}}", i)) = i;
+    }
+    for i in (0..10_000).step_by(7) {
+      let path = format!("/api/v1/resource{}/42", i);
+      assert_eq!(index.find(&path, |_| true), Some(&i));
+    }
+  }
+}
@@ -1,86 +1,734 @@
 use std::{
-  any::Any,
-  collections::HashMap,
-  path::{Path, PathBuf},
-  sync::{Arc, Mutex},
+  collections::{hash_map::Entry, BTreeSet, HashMap},
+  io::{Read, Write},
+  path::Path,
+  process::{Command, Stdio},
+  sync::{Arc, Mutex, RwLock},
+  thread,
+  time::{Duration, Instant},
 };
 
-use log::debug;
+use log::{debug, warn};
 
-use crate::{Error, ErrorKind, Method, Request, Response, Route, RouteKind, Status, Store, Value};
+#[cfg(feature = "json")]
+use crate::LazyStore;
 
-pub trait RouteHandler {
+use crate::{
+  content_type_for, endpoint_params, endpoint_regex,
+  namespace::{Namespaces, NAMESPACE_HEADER},
+  parse_duration,
+  persona::ActivePersona,
+  read_file, render, render_value, search_items, sniff_content_type, sort_items,
+  tenancy::{Tenancy, TENANT_HEADER},
+  Concurrency, Error, ErrorKind, Invocations, MatchedRoute, Matcher, Method, Pagination,
+  ParentRelation, PathParams, Principal, RateLimits, Request, Response, ResponseCache,
+  ResponseCheck, Route, RouteIndex, RouteKind, RouteOptions, RouteSwitches, RouteTags,
+  ScenarioConfig, Scenarios, Status, Store, StoreAction, StoreEvent, StoreEvents, TemplateContext,
+  Tenant, Tokens, Value, Variables, VirtualClock, GLOBAL_SCOPE,
+};
+
+/// Tenant `req` was resolved to, by the tenancy middleware
+pub(crate) fn tenant(req: &Request) -> Option<String> {
+  match req.extensions().get::<Tenant>() {
+    Some(Tenant(tenant)) => Some(tenant.clone()),
+    None => req.header(TENANT_HEADER).map(str::to_string),
+  }
+}
+
+pub trait RouteHandler: Send + Sync {
+  fn route(&self) -> &Route;
   fn handle(&self, req: &Request, res: Response) -> crate::Result<Response>;
+
+  /// Forget whatever state was kept for an isolated session
+  fn end_session(&self, _session: &str) -> crate::Result<()> {
+    Ok(())
+  }
+
+  /// Key invocations and switches are tracked under, unique per registered
+  /// route even when several share an id
+  fn key(&self) -> String {
+    self.route().id()
+  }
+}
+
+/// Handler registered in a [`Router`], under its key
+struct Registered {
+  key: String,
+  handler: Box<dyn RouteHandler>,
+}
+
+impl RouteHandler for Registered {
+  fn route(&self) -> &Route {
+    self.handler.route()
+  }
+
+  fn handle(&self, req: &Request, res: Response) -> crate::Result<Response> {
+    self.handler.handle(req, res)
+  }
+
+  fn end_session(&self, session: &str) -> crate::Result<()> {
+    self.handler.end_session(session)
+  }
+
+  fn key(&self) -> String {
+    self.key.clone()
+  }
+}
+
+/// Response recorded for an idempotency key, with the request body it
+/// answered, `None` while the entity is being created
+#[cfg(feature = "json")]
+struct IdempotentResponse {
+  at: u128,
+  body: bytes::Bytes,
+  response: Option<Response>,
 }
 
+/// Idempotency key as sent by a tenant, in a session
+#[cfg(feature = "json")]
+type IdempotencyKey = (Option<String>, Option<String>, String);
+
+/// Owner of an in-memory store copy: an isolated session, a persona, or a
+/// persona within a session, of a tenant
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct SessionKey {
+  tenant: Option<String>,
+  session: Option<String>,
+  persona: Option<String>,
+}
+
+#[cfg(feature = "json")]
 pub struct StoreRouteHandler {
   route: Route,
   store: Mutex<Store>,
+  sessions: Mutex<HashMap<SessionKey, Store>>,
+  /// Store read from disk on demand, for lazy routes
+  lazy: Mutex<Option<LazyStore>>,
+  idempotency: Mutex<HashMap<IdempotencyKey, IdempotentResponse>>,
+  events: Arc<StoreEvents>,
+  parent: Option<ParentRelation>,
+  clock: VirtualClock,
 }
 
+#[cfg(feature = "json")]
 impl StoreRouteHandler {
+  /// Header clients send to make POSTs safe to retry
+  pub const IDEMPOTENCY_HEADER: &'static str = "Idempotency-Key";
+  /// How long idempotency keys are remembered unless the route says otherwise
+  pub const DEFAULT_IDEMPOTENCY_RETENTION: Duration = Duration::from_secs(24 * 3600);
+
   pub fn new<P: AsRef<Path>, I: AsRef<str>>(route: Route, path: P, identifier: I) -> Self {
     Self {
       route,
       store: Mutex::new(Store::json(path, identifier)),
+      sessions: Mutex::new(HashMap::new()),
+      lazy: Mutex::new(None),
+      idempotency: Mutex::new(HashMap::new()),
+      events: Arc::default(),
+      parent: None,
+      clock: VirtualClock::default(),
     }
   }
 
-  pub fn load_entity(&self, req: &Request) -> crate::Result<Response> {
-    let mut store = self.store.lock()?;
-    let (id_key, id_value) = match req.query_param(store.identifier()) {
-      Some((key, Some(val))) => (key.clone(), Value::from(val.clone())),
-      Some((key, None)) => {
-        return Ok(Response::default().with_status_code(400).with_body(format!(
-          "Identifier '{}' was found in query params but has no value",
-          store.identifier()
-        )))
+  /// Scope the store to the items of the parent resource `parent` says
+  pub fn with_parent(mut self, parent: Option<ParentRelation>) -> Self {
+    self.parent = parent;
+    self
+  }
+
+  /// Publish changes to the store to `events`
+  pub fn with_events(mut self, events: Arc<StoreEvents>) -> Self {
+    self.events = events;
+    self
+  }
+
+  /// Compute fields and expire idempotency keys as of now on `clock`
+  pub fn with_clock(mut self, clock: VirtualClock) -> Self {
+    self.clock = clock;
+    self
+  }
+
+  fn publish(
+    &self,
+    req: &Request,
+    action: StoreAction,
+    id: Value,
+    entity: HashMap<String, Value>,
+  ) -> crate::Result<()> {
+    let event = StoreEvent::new(action, self.route.endpoint(), id, entity).with_tenant(tenant(req));
+    self.events.publish(event)
+  }
+
+  /// Identifier parameter of `req`, from the path parameters of the route
+  /// (as in `/users/{id}`) or else from the query parameters
+  fn id_param(req: &Request, identifier: &str) -> Option<(String, Option<String>)> {
+    match req.path_param(identifier) {
+      Some(value) => Some((identifier.to_string(), Some(value))),
+      None => req.query_param(identifier),
+    }
+  }
+
+  /// Identifier of the entity `req` targets, from the path or query parameters
+  fn requested_id(req: &Request, identifier: &str) -> crate::Result<Value> {
+    match Self::id_param(req, identifier) {
+      Some((_key, Some(value))) => Ok(Value::from(value)),
+      _ => Err(Error::new(
+        ErrorKind::Api(Status::BadRequest),
+        Some(format!(
+          "Identifier '{}' not found in path or query params",
+          identifier
+        )),
+        None,
+      )),
+    }
+  }
+
+  /// Foreign key the route scopes `req` to, when nested: the field and the
+  /// parent identifier found in the path
+  fn parent_key(&self, req: &Request) -> crate::Result<Option<(String, Value)>> {
+    let parent = match &self.parent {
+      Some(parent) => parent,
+      None => return Ok(None),
+    };
+    match req.path_param(parent.param()) {
+      // numeric identifiers keep their type, as in the items of the store
+      Some(value) => Ok(Some((
+        parent.field.clone(),
+        serde_json::from_str(&value).unwrap_or_else(|_| Value::from(value)),
+      ))),
+      None => Err(Error::new(
+        ErrorKind::Api(Status::BadRequest),
+        Some(format!(
+          "Parent identifier '{}' not found in path params",
+          parent.param()
+        )),
+        None,
+      )),
+    }
+  }
+
+  /// Whether `item` belongs to the parent of `key`, if any
+  fn in_parent(key: &Option<(String, Value)>, item: &HashMap<String, Value>) -> bool {
+    match key {
+      Some((field, value)) => item.get(field).is_some_and(|v| v.loose_eq(value)),
+      None => true,
+    }
+  }
+
+  fn not_found(identifier: &str, id: &Value) -> Error {
+    Error::new(
+      ErrorKind::Api(Status::NotFound),
+      Some(format!(
+        "Entity with `{}` = {} was not found",
+        identifier, id
+      )),
+      None,
+    )
+  }
+
+  /// Isolated session `req` belongs to, if the route keys sessions by a
+  /// header, or else the namespace it was sent to
+  fn session(&self, req: &Request) -> Option<String> {
+    match self.route.options().session_header.as_ref() {
+      Some(header) => req.header(header).map(str::to_string),
+      None => req.header(NAMESPACE_HEADER).map(str::to_string),
+    }
+  }
+
+  /// Store of `tenant`, kept in `tenants/{tenant}` next to the route's store
+  /// file and created from it on first use.
+  fn tenant_store(base: &Store, tenant: &str) -> crate::Result<Store> {
+    Tenancy::validate(tenant)?;
+    let file_name = base.path().file_name().unwrap_or_default();
+    let dir = base
+      .path()
+      .parent()
+      .unwrap_or(Path::new(""))
+      .join("tenants")
+      .join(tenant);
+    let mut store = base.clone();
+    *store.path_mut() = dir.join(file_name);
+    if !store.path().exists() {
+      debug!("Creating store {}", store.path().display());
+      std::fs::create_dir_all(&dir)?;
+      match base.path().exists() {
+        true => {
+          std::fs::copy(base.path(), store.path())?;
+        }
+        false => store.save()?,
+      }
+    }
+    Ok(store)
+  }
+
+  /// Run `f` on the store seen by `req`, the tenant's one if any, or the one
+  /// its persona is given. A session gets its own in-memory copy of the data
+  /// on its first write, as do personas, other requests use the file.
+  fn with_store<R, F: FnOnce(&mut Store) -> crate::Result<R>>(
+    &self,
+    req: &Request,
+    write: bool,
+    f: F,
+  ) -> crate::Result<R> {
+    let mut guard = self.store.lock()?;
+    let tenant = tenant(req);
+    let mut tenant_store;
+    let base = match &tenant {
+      Some(tenant) => {
+        tenant_store = Self::tenant_store(&guard, tenant)?;
+        &mut tenant_store
+      }
+      None => &mut *guard,
+    };
+    let persona = req.extensions().get::<ActivePersona>();
+    let mut persona_store;
+    let base = match persona.and_then(|p| p.persona.store(self.route.endpoint())) {
+      Some(path) => {
+        persona_store = base.clone();
+        *persona_store.path_mut() = path.to_path_buf();
+        &mut persona_store
+      }
+      None => base,
+    };
+    let key = SessionKey {
+      tenant,
+      session: self.session(req),
+      persona: persona.map(|p| p.name.clone()),
+    };
+    if key.session.is_none() && key.persona.is_none() {
+      base.load()?;
+      let ret = f(base)?;
+      if write {
+        base.save()?;
+      }
+      return Ok(ret);
+    }
+    let mut sessions = self.sessions.lock()?;
+    let store = match sessions.entry(key) {
+      Entry::Occupied(e) => e.into_mut(),
+      Entry::Vacant(e) if write => {
+        debug!("Copying store for {:?}", e.key());
+        base.load()?;
+        e.insert(base.clone())
+      }
+      Entry::Vacant(_) => {
+        base.load()?;
+        return f(base);
+      }
+    };
+    f(store)
+  }
+
+  /// Run `f` on the store of a lazy route, opened on first use
+  fn with_lazy_store<R, F: FnOnce(&mut LazyStore) -> crate::Result<R>>(
+    &self,
+    f: F,
+  ) -> crate::Result<R> {
+    let mut guard = self.lazy.lock()?;
+    let store = match &mut *guard {
+      Some(store) => {
+        store.refresh()?;
+        store
       }
       None => {
-        return Ok(Response::default().with_status_code(400).with_body(format!(
-          "Identifier '{}' not found in query params",
-          store.identifier()
-        )))
+        let base = self.store.lock()?;
+        guard.insert(LazyStore::open(base.path(), base.identifier())?)
       }
     };
-    store.load()?;
-    match store.find(&id_value) {
-      Some(obj) => Response::api(Status::OK, obj),
-      None => Ok(Response::default().with_status_code(404).with_body(format!(
-        "Entity with `{}` = {} was not found",
-        id_key, id_value
-      ))),
+    f(store)
+  }
+
+  /// `item` as served, with its computed fields and hypermedia links
+  fn present(
+    &self,
+    identifier: &str,
+    item: &HashMap<String, Value>,
+  ) -> crate::Result<HashMap<String, Value>> {
+    let item = self.computed(item)?;
+    Ok(match &self.route.options().hypermedia {
+      Some(hypermedia) => hypermedia.wrap(&self.route, identifier, &item),
+      None => item,
+    })
+  }
+
+  fn load_lazy_entity(&self, req: &Request) -> crate::Result<Response> {
+    let parent = self.parent_key(req)?;
+    self.with_lazy_store(|store| {
+      let identifier = store.identifier().clone();
+      if Self::id_param(req, &identifier).is_none() {
+        // nesting, searching and sorting need every item, served from memory
+        if parent.is_some() || req.query_param("q").is_some() || req.query_param("_sort").is_some()
+        {
+          let mut items = store
+            .page(0, store.len())?
+            .iter()
+            .filter(|item| Self::in_parent(&parent, item))
+            .map(|item| self.computed(item))
+            .collect::<crate::Result<Vec<_>>>()?;
+          search_items(req, &mut items);
+          sort_items(req, &mut items)?;
+          if let Some(hypermedia) = &self.route.options().hypermedia {
+            items = items
+              .iter()
+              .map(|item| hypermedia.wrap(&self.route, &identifier, item))
+              .collect();
+          }
+          return match &self.route.options().pagination {
+            Some(pagination) => pagination.respond(req, &items),
+            None => Pagination::collection(req, &items),
+          };
+        }
+        if let Some(pagination) = &self.route.options().pagination {
+          return pagination.respond_with(req, store.len(), |offset, size| {
+            store
+              .page(offset, size)?
+              .iter()
+              .map(|item| self.present(&identifier, item))
+              .collect()
+          });
+        }
+        return Pagination::collection_with(req, store.len(), |offset, size| {
+          store
+            .page(offset, size)?
+            .iter()
+            .map(|item| self.present(&identifier, item))
+            .collect()
+        });
+      }
+      let id = Self::requested_id(req, &identifier)?;
+      let obj = match store.find(&id)? {
+        Some(obj) if Self::in_parent(&parent, &obj) => self.computed(&obj)?,
+        _ => return Err(Self::not_found(&identifier, &id)),
+      };
+      match &self.route.options().hypermedia {
+        Some(hypermedia) => Response::api_for(
+          req,
+          Status::OK,
+          &hypermedia.document(&self.route, &identifier, &obj),
+        ),
+        None => Response::api_for(req, Status::OK, &obj),
+      }
+    })
+  }
+
+  fn create_lazy_entity(&self, req: &Request) -> crate::Result<Response> {
+    let mut new_data = req.parse_body::<HashMap<String, Value>>()?;
+    if let Some((field, value)) = self.parent_key(req)? {
+      new_data.insert(field, value);
     }
+    self.with_lazy_store(|store| {
+      let id = match new_data
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(store.identifier()))
+      {
+        Some((_, id)) => id.clone(),
+        None => match self.route.options().ids {
+          Some(ids) => {
+            let taken = store
+              .iter()?
+              .filter_map(|item| item.ok()?.remove(store.identifier()))
+              .collect::<Vec<_>>();
+            let id = ids.next(&taken);
+            new_data.insert(store.identifier().clone(), id.clone());
+            id
+          }
+          None => Value::Null,
+        },
+      };
+      store.create(new_data.clone())?;
+      self.publish(req, StoreAction::Created, id.clone(), new_data)?;
+      Response::api_for(req, Status::Created, &id)
+    })
   }
 
-  pub fn create_entity(&self, req: &Request) -> crate::Result<Response> {
-    let mut store = self.store.lock()?;
-    store.load()?;
-    let new_data = req.parse_body::<HashMap<String, Value>>()?;
-    let id = match store.id_field(&new_data) {
-      Some((_key, value)) => value.clone(),
-      None => Value::Null,
+  fn update_lazy_entity(&self, req: &Request, merge: bool) -> crate::Result<Response> {
+    let mut data = req.parse_body::<HashMap<String, Value>>()?;
+    let parent = self.parent_key(req)?;
+    self.with_lazy_store(|store| {
+      let id = Self::requested_id(req, store.identifier())?;
+      if !store
+        .find(&id)?
+        .is_some_and(|item| Self::in_parent(&parent, &item))
+      {
+        return Err(Self::not_found(store.identifier(), &id));
+      }
+      if let Some((field, value)) = parent {
+        data.insert(field, value);
+      }
+      let entity = match store.update(&id, data, merge)? {
+        Some(entity) => entity,
+        None => return Err(Self::not_found(store.identifier(), &id)),
+      };
+      self.publish(req, StoreAction::Updated, id, entity.clone())?;
+      Response::api_for(req, Status::OK, &self.computed(&entity)?)
+    })
+  }
+
+  fn delete_lazy_entity(&self, req: &Request) -> crate::Result<Response> {
+    let parent = self.parent_key(req)?;
+    self.with_lazy_store(|store| {
+      let id = Self::requested_id(req, store.identifier())?;
+      if !store
+        .find(&id)?
+        .is_some_and(|item| Self::in_parent(&parent, &item))
+      {
+        return Err(Self::not_found(store.identifier(), &id));
+      }
+      let entity = match store.remove(&id)? {
+        Some(entity) => entity,
+        None => return Err(Self::not_found(store.identifier(), &id)),
+      };
+      self.publish(req, StoreAction::Deleted, id, entity)?;
+      Ok(Response::default().with_status(Status::NoContent))
+    })
+  }
+
+  /// `item` along with the computed fields of the route, if any
+  fn computed(&self, item: &HashMap<String, Value>) -> crate::Result<HashMap<String, Value>> {
+    match &self.route.options().computed {
+      Some(computed) => computed.apply(item, &self.clock),
+      None => Ok(item.clone()),
+    }
+  }
+
+  fn idempotency_retention(&self) -> crate::Result<Duration> {
+    match &self.route.options().idempotency_retention {
+      Some(retention) => parse_duration(retention),
+      None => Ok(Self::DEFAULT_IDEMPOTENCY_RETENTION),
+    }
+  }
+
+  /// Create an entity once per `Idempotency-Key` of a tenant and session,
+  /// replaying the original response when the key is seen again with the
+  /// same body. Requests reusing a key still being processed are rejected.
+  pub fn create_entity_once(&self, req: &Request) -> crate::Result<Response> {
+    let key = match req.header(Self::IDEMPOTENCY_HEADER) {
+      Some(key) => (tenant(req), self.session(req), key.to_string()),
+      None => return self.create_entity(req),
     };
-    store.create(new_data)?;
-    store.save()?;
-    return Response::api(Status::Created, &id);
+    let now = self.clock.now_millis();
+    let retention = self.idempotency_retention()?.as_millis();
+    {
+      let mut seen = self.idempotency.lock()?;
+      seen.retain(|_, r| now < r.at + retention);
+      if let Some(previous) = seen.get(&key) {
+        let reject = |status, reason| {
+          Err(Error::new(
+            ErrorKind::Api(status),
+            Some(format!(
+              "{} '{}' {}",
+              Self::IDEMPOTENCY_HEADER,
+              key.2,
+              reason
+            )),
+            None,
+          ))
+        };
+        if previous.body != *req.body() {
+          return reject(
+            Status::UnprocessableEntity,
+            "was already used with a different body",
+          );
+        }
+        return match &previous.response {
+          Some(response) => {
+            debug!(
+              "Replaying response for {} '{}'",
+              Self::IDEMPOTENCY_HEADER,
+              key.2
+            );
+            Ok(response.clone())
+          }
+          None => reject(Status::Conflict, "is still being processed"),
+        };
+      }
+      seen.insert(
+        key.clone(),
+        IdempotentResponse {
+          at: now,
+          body: req.body().clone(),
+          response: None,
+        },
+      );
+    }
+    // the lock is released while creating, so that other keys are served
+    let response = self.create_entity(req);
+    let mut seen = self.idempotency.lock()?;
+    match &response {
+      Ok(response) => {
+        if let Some(entry) = seen.get_mut(&key) {
+          entry.response = Some(response.clone());
+        }
+      }
+      Err(_) => {
+        seen.remove(&key);
+      }
+    }
+    response
+  }
+
+  pub fn load_entity(&self, req: &Request) -> crate::Result<Response> {
+    if self.route.options().lazy {
+      return self.load_lazy_entity(req);
+    }
+    let parent = self.parent_key(req)?;
+    self.with_store(req, false, |store| {
+      let (id_key, id_value) = match Self::id_param(req, store.identifier()) {
+        Some((key, Some(val))) => (key.clone(), Value::from(val.clone())),
+        Some((_key, None)) => {
+          return Ok(Response::default().with_status_code(400).with_body(format!(
+            "Identifier '{}' was found in query params but has no value",
+            store.identifier()
+          )))
+        }
+        None => {
+          let mut items = match &self.route.options().computed {
+            Some(computed) => computed.apply_all(store.items(), &self.clock)?,
+            None => store.items().to_vec(),
+          };
+          items.retain(|item| Self::in_parent(&parent, item));
+          search_items(req, &mut items);
+          sort_items(req, &mut items)?;
+          if let Some(hypermedia) = &self.route.options().hypermedia {
+            items = items
+              .iter()
+              .map(|item| hypermedia.wrap(&self.route, store.identifier(), item))
+              .collect();
+          }
+          return match &self.route.options().pagination {
+            Some(pagination) => pagination.respond(req, &items),
+            None => Pagination::collection(req, &items),
+          };
+        }
+      };
+      match store.find(&id_value) {
+        Some(obj) if Self::in_parent(&parent, obj) => {
+          let obj = self.computed(obj)?;
+          match &self.route.options().hypermedia {
+            Some(hypermedia) => Response::api_for(
+              req,
+              Status::OK,
+              &hypermedia.document(&self.route, store.identifier(), &obj),
+            ),
+            None => Response::api_for(req, Status::OK, &obj),
+          }
+        }
+        _ => Ok(Response::default().with_status_code(404).with_body(format!(
+          "Entity with `{}` = {} was not found",
+          id_key, id_value
+        ))),
+      }
+    })
+  }
+
+  pub fn create_entity(&self, req: &Request) -> crate::Result<Response> {
+    if self.route.options().lazy {
+      return self.create_lazy_entity(req);
+    }
+    let mut new_data = req.parse_body::<HashMap<String, Value>>()?;
+    if let Some((field, value)) = self.parent_key(req)? {
+      new_data.insert(field, value);
+    }
+    self.with_store(req, true, |store| {
+      let id = match (store.id_field(&new_data), self.route.options().ids) {
+        (Some((_key, value)), _) => value.clone(),
+        (None, Some(ids)) => {
+          let id = ids.next(
+            store
+              .items()
+              .iter()
+              .filter_map(|item| store.id_field(item).map(|(_, v)| v)),
+          );
+          new_data.insert(store.identifier().clone(), id.clone());
+          id
+        }
+        (None, None) => Value::Null,
+      };
+      store.create(new_data.clone())?;
+      self.publish(req, StoreAction::Created, id.clone(), new_data)?;
+      Response::api_for(req, Status::Created, &id)
+    })
+  }
+
+  /// Replace the requested entity with the body, or apply the body to it as
+  /// a JSON merge patch when `merge`-ing
+  pub fn update_entity(&self, req: &Request, merge: bool) -> crate::Result<Response> {
+    if self.route.options().lazy {
+      return self.update_lazy_entity(req, merge);
+    }
+    let mut data = req.parse_body::<HashMap<String, Value>>()?;
+    let parent = self.parent_key(req)?;
+    self.with_store(req, true, |store| {
+      let id = Self::requested_id(req, store.identifier())?;
+      if !store
+        .find(&id)
+        .is_some_and(|item| Self::in_parent(&parent, item))
+      {
+        return Err(Self::not_found(store.identifier(), &id));
+      }
+      if let Some((field, value)) = parent {
+        data.insert(field, value);
+      }
+      let entity = match store.update(&id, data, merge) {
+        Some(entity) => entity.clone(),
+        None => return Err(Self::not_found(store.identifier(), &id)),
+      };
+      let id = store.id_field(&entity).map_or(id, |(_, id)| id.clone());
+      self.publish(req, StoreAction::Updated, id, entity.clone())?;
+      Response::api_for(req, Status::OK, &self.computed(&entity)?)
+    })
+  }
+
+  pub fn delete_entity(&self, req: &Request) -> crate::Result<Response> {
+    if self.route.options().lazy {
+      return self.delete_lazy_entity(req);
+    }
+    let parent = self.parent_key(req)?;
+    self.with_store(req, true, |store| {
+      let id = Self::requested_id(req, store.identifier())?;
+      if !store
+        .find(&id)
+        .is_some_and(|item| Self::in_parent(&parent, item))
+      {
+        return Err(Self::not_found(store.identifier(), &id));
+      }
+      let entity = match store.remove(&id) {
+        Some(entity) => entity,
+        None => return Err(Self::not_found(store.identifier(), &id)),
+      };
+      let id = store.id_field(&entity).map_or(id, |(_, id)| id.clone());
+      self.publish(req, StoreAction::Deleted, id, entity)?;
+      Ok(Response::default().with_status(Status::NoContent))
+    })
   }
 }
 
+#[cfg(feature = "json")]
 impl RouteHandler for StoreRouteHandler {
-  fn handle(&self, req: &Request, res: Response) -> crate::Result<Response> {
+  fn route(&self) -> &Route {
+    &self.route
+  }
+
+  fn end_session(&self, session: &str) -> crate::Result<()> {
+    self
+      .sessions
+      .lock()?
+      .retain(|key, _store| key.session.as_deref() != Some(session));
+    self
+      .idempotency
+      .lock()?
+      .retain(|(_tenant, id, _key), _response| id.as_deref() != Some(session));
+    Ok(())
+  }
+
+  fn handle(&self, req: &Request, _res: Response) -> crate::Result<Response> {
     match req.method().expect("Missing method") {
       Method::Get => self.load_entity(req),
-      Method::Post => self.create_entity(req),
-      Method::Put => {
-        todo!("StoreRouteHandler PUT method");
-      }
-      Method::Patch => {
-        todo!("StoreRouteHandler PATCH method");
-      }
-      Method::Delete => {
-        todo!("StoreRouteHandler DELETE method");
-      }
+      Method::Post => self.create_entity_once(req),
+      Method::Put => self.update_entity(req, false),
+      Method::Patch => self.update_entity(req, true),
+      Method::Delete => self.delete_entity(req),
       m => Err(Error::new(
         ErrorKind::Unknown,
         Some(format!("unsupported method: {:?}", m)),
@@ -93,8 +741,7 @@ impl RouteHandler for StoreRouteHandler {
 #[cfg(feature = "js")]
 pub struct ScriptRouteHandler {
   route: Route,
-  script_path: PathBuf,
-  func_name: String,
+  script: crate::Script,
 }
 
 #[cfg(feature = "js")]
@@ -102,89 +749,952 @@ impl ScriptRouteHandler {
   pub fn new<S: AsRef<Path>, F: AsRef<str>>(route: Route, script_path: S, func_name: F) -> Self {
     Self {
       route,
-      script_path: script_path.as_ref().to_path_buf(),
-      func_name: func_name.as_ref().to_string(),
+      script: crate::Script::new(script_path, func_name),
     }
   }
+
+  pub fn with_fetch(mut self, policy: Option<crate::FetchPolicy>) -> Self {
+    self.script = self.script.with_fetch(policy);
+    self
+  }
 }
 
 #[cfg(feature = "js")]
 impl RouteHandler for ScriptRouteHandler {
+  fn route(&self) -> &Route {
+    &self.route
+  }
+
+  fn handle(&self, req: &Request, res: Response) -> crate::Result<Response> {
+    self.script.handle(req, res)
+  }
+}
+
+/// Serves an [`crate::S3Storage`], mounted on the route endpoint
+#[cfg(feature = "s3")]
+pub struct S3RouteHandler {
+  route: Route,
+  storage: crate::S3Storage,
+}
+
+#[cfg(feature = "s3")]
+impl S3RouteHandler {
+  pub fn new<P: AsRef<Path>>(route: Route, root_dir: P) -> Self {
+    Self {
+      route,
+      storage: crate::S3Storage::new(root_dir),
+    }
+  }
+}
+
+#[cfg(feature = "s3")]
+impl RouteHandler for S3RouteHandler {
+  fn route(&self) -> &Route {
+    &self.route
+  }
+
+  fn handle(&self, req: &Request, _res: Response) -> crate::Result<Response> {
+    // what follows the endpoint, e.g. `bucket/key` for `/s3/*`
+    let mount = self
+      .route
+      .endpoint()
+      .trim_start_matches('/')
+      .split('/')
+      .filter(|s| !s.is_empty() && *s != "*")
+      .count();
+    let path = req
+      .path()
+      .unwrap_or("/")
+      .trim_start_matches('/')
+      .splitn(mount + 1, '/')
+      .nth(mount)
+      .unwrap_or_default();
+    self.storage.handle(req, path)
+  }
+}
+
+pub struct FixtureRouteHandler {
+  route: Route,
+  variables: Arc<Variables>,
+  clock: VirtualClock,
+}
+
+impl FixtureRouteHandler {
+  pub fn new(route: Route, variables: Arc<Variables>) -> Self {
+    Self {
+      route,
+      variables,
+      clock: VirtualClock::default(),
+    }
+  }
+
+  /// Render templates as of now on `clock`
+  pub fn with_clock(mut self, clock: VirtualClock) -> Self {
+    self.clock = clock;
+    self
+  }
+}
+
+impl RouteHandler for FixtureRouteHandler {
+  fn route(&self) -> &Route {
+    &self.route
+  }
+
   fn handle(&self, req: &Request, res: Response) -> crate::Result<Response> {
-    todo!();
+    let (status, headers, body, file, template) = match self.route.kind() {
+      RouteKind::Fixture {
+        status,
+        headers,
+        body,
+        file,
+        template,
+      } => (*status, headers, body, file, *template),
+      #[allow(unreachable_patterns)]
+      kind => {
+        return Err(Error::new(
+          ErrorKind::Unknown,
+          Some(format!("not a fixture route: {}", kind.name())),
+          None,
+        ))
+      }
+    };
+    let binary = self.route.options().binary;
+    let (data, content_type) = match (body, file) {
+      _ if binary.is_some() => (
+        binary.map(|b| b.generate()),
+        binary.map(|b| b.content_type().to_string()),
+      ),
+      (_, Some(file)) => (
+        Some(read_file(file)?),
+        Some(content_type_for(file).to_string()).filter(|ct| ct != "application/octet-stream"),
+      ),
+      (Some(Value::String(body)), None) => (Some(body.clone().into_bytes()), None),
+      (Some(body), None) => {
+        let api = Response::api(Status::OK, body)?;
+        (
+          Some(api.body().to_vec()),
+          api.header("Content-Type").map(str::to_string),
+        )
+      }
+      (None, None) => (None, None),
+    };
+    let mut res = res.with_status_code(status);
+    if let Some(data) = data {
+      res = match template && binary.is_none() {
+        true => {
+          let scope = match self.route.options().scenario.as_ref() {
+            Some(scenario) => scenario.name.as_str(),
+            None => GLOBAL_SCOPE,
+          };
+          let ctx = TemplateContext::new(&self.variables)
+            .with_clock(&self.clock)
+            .with_scope(scope)
+            .with_request(req);
+          res.with_body(render(std::str::from_utf8(&data)?, &ctx)?)
+        }
+        false => res.with_body_bytes(data),
+      };
+    }
+    let content_type = match (&self.route.options().content_type, content_type) {
+      (Some(forced), _) => Some(forced.clone()),
+      (None, Some(content_type)) => Some(content_type),
+      (None, None) if !res.body().is_empty() && res.header("Content-Type").is_none() => {
+        Some(sniff_content_type(res.body()).to_string())
+      }
+      (None, None) => None,
+    };
+    if let Some(content_type) = content_type {
+      res.set_header("Content-Type", content_type);
+    }
+    for (key, value) in headers {
+      res.set_header(key, value);
+    }
+    Ok(res)
+  }
+}
+
+pub struct ExecRouteHandler {
+  route: Route,
+}
+
+impl ExecRouteHandler {
+  pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+  pub fn new(route: Route) -> Self {
+    Self { route }
+  }
+
+  fn run(&self, input: String) -> crate::Result<(u16, Vec<u8>)> {
+    let (command, args, status, timeout, cwd, env, clear_env) = match self.route.kind() {
+      RouteKind::Exec {
+        command,
+        args,
+        status,
+        timeout,
+        cwd,
+        env,
+        clear_env,
+        ..
+      } => (command, args, *status, timeout, cwd, env, *clear_env),
+      kind => {
+        return Err(Error::new(
+          ErrorKind::Unknown,
+          Some(format!("not an exec route: {}", kind.name())),
+          None,
+        ))
+      }
+    };
+    let timeout = match timeout {
+      Some(t) => parse_duration(t)?,
+      None => Self::DEFAULT_TIMEOUT,
+    };
+    let mut cmd = Command::new(command);
+    cmd
+      .args(args)
+      .stdin(Stdio::piped())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped());
+    if clear_env {
+      cmd.env_clear();
+    }
+    cmd.envs(env);
+    if let Some(cwd) = cwd {
+      cmd.current_dir(cwd);
+    }
+    let mut child = cmd.spawn().map_err(|e| {
+      Error::new(
+        ErrorKind::IO,
+        Some(format!("failed to run `{}`: {}", command, e)),
+        None,
+      )
+    })?;
+    // pipes are drained on their own threads so a chatty command cannot block
+    let stdin = child.stdin.take().map(|mut stdin| {
+      thread::spawn(move || {
+        let _ = stdin.write_all(input.as_bytes());
+      })
+    });
+    let stdout = child.stdout.take().map(|mut stdout| {
+      thread::spawn(move || {
+        let mut buf = vec![];
+        let _ = stdout.read_to_end(&mut buf);
+        buf
+      })
+    });
+    let stderr = child.stderr.take().map(|mut stderr| {
+      thread::spawn(move || {
+        let mut buf = String::new();
+        let _ = stderr.read_to_string(&mut buf);
+        buf
+      })
+    });
+    let started = Instant::now();
+    let exit = loop {
+      if let Some(exit) = child.try_wait()? {
+        break exit;
+      }
+      if started.elapsed() >= timeout {
+        child.kill()?;
+        child.wait()?;
+        return Err(Error::new(
+          ErrorKind::Api(Status::GatewayTimeOut),
+          Some(format!("`{}` timed out after {:?}", command, timeout)),
+          None,
+        ));
+      }
+      thread::sleep(Duration::from_millis(5));
+    };
+    if let Some(stdin) = stdin {
+      let _ = stdin.join();
+    }
+    let stdout = stdout.and_then(|h| h.join().ok()).unwrap_or_default();
+    let stderr = stderr.and_then(|h| h.join().ok()).unwrap_or_default();
+    if !exit.success() {
+      return Err(Error::new(
+        ErrorKind::Api(Status::BadGatewayOuProxyError),
+        Some(format!(
+          "`{}` failed ({}): {}",
+          command,
+          exit,
+          stderr.trim()
+        )),
+        None,
+      ));
+    }
+    if !stderr.is_empty() {
+      warn!("`{}`: {}", command, stderr.trim());
+    }
+    Ok((status, stdout))
+  }
+}
+
+impl RouteHandler for ExecRouteHandler {
+  fn route(&self) -> &Route {
+    &self.route
+  }
+
+  fn handle(&self, req: &Request, res: Response) -> crate::Result<Response> {
+    let (status, stdout) = self.run(render_value(&req.to_value()))?;
+    let mut res = res
+      .with_status_code(status)
+      .with_body(String::from_utf8_lossy(&stdout));
+    if let RouteKind::Exec { headers, .. } = self.route.kind() {
+      for (key, value) in headers {
+        res.set_header(key, value);
+      }
+    }
     Ok(res)
   }
 }
 
-#[derive(Default, Clone)]
-pub struct Router(HashMap<String, HashMap<Method, Arc<dyn RouteHandler>>>);
+pub struct EchoRouteHandler {
+  route: Route,
+}
 
-unsafe impl Send for Router {}
-unsafe impl Sync for Router {}
+impl EchoRouteHandler {
+  pub fn new(route: Route) -> Self {
+    Self { route }
+  }
+}
+
+impl RouteHandler for EchoRouteHandler {
+  fn route(&self) -> &Route {
+    &self.route
+  }
+
+  fn handle(&self, req: &Request, mut res: Response) -> crate::Result<Response> {
+    let api = Response::api_for(req, Status::OK, &req.to_value())?;
+    if let Some(content_type) = api.header("Content-Type") {
+      res.set_header("Content-Type", content_type);
+    }
+    res = res.with_body_bytes(api.body().clone());
+    if let RouteKind::Echo { status, headers } = self.route.kind() {
+      res = res.with_status_code(*status);
+      for (key, value) in headers {
+        res.set_header(key, value);
+      }
+    }
+    Ok(res)
+  }
+}
+
+/// Handlers by endpoint and method, along with the routes they were built from
+#[derive(Default)]
+struct RouteTable {
+  endpoints: RouteIndex<HashMap<Method, Vec<Arc<dyn RouteHandler>>>>,
+  routes: Vec<Route>,
+  keys: Vec<String>,
+}
+
+pub struct Router {
+  table: RwLock<RouteTable>,
+  invocations: Arc<Invocations>,
+  concurrency: Arc<Concurrency>,
+  rate_limits: Arc<RateLimits>,
+  cache: Arc<ResponseCache>,
+  store_events: Arc<StoreEvents>,
+  tokens: Arc<Tokens>,
+  namespaces: Option<Arc<Namespaces>>,
+  tags: Arc<RouteTags>,
+  switches: Arc<RouteSwitches>,
+  #[cfg(feature = "js")]
+  fetch: Option<crate::FetchPolicy>,
+  scenarios: Arc<Scenarios>,
+  variables: Arc<Variables>,
+  response_check: ResponseCheck,
+  clock: VirtualClock,
+}
+
+impl Default for Router {
+  fn default() -> Self {
+    let clock = VirtualClock::default();
+    Self {
+      table: Default::default(),
+      invocations: Default::default(),
+      concurrency: Default::default(),
+      rate_limits: Default::default(),
+      cache: Default::default(),
+      store_events: Default::default(),
+      tokens: Arc::new(Tokens::default().with_clock(clock.clone())),
+      namespaces: None,
+      tags: Default::default(),
+      switches: Default::default(),
+      #[cfg(feature = "js")]
+      fetch: None,
+      scenarios: Arc::new(Scenarios::default().with_clock(clock.clone())),
+      variables: Default::default(),
+      response_check: Default::default(),
+      clock,
+    }
+  }
+}
 
 impl Router {
   pub fn set<M: IntoIterator<Item = Method>, E: AsRef<str>, H: RouteHandler + 'static>(
-    &mut self,
+    &self,
     methods: M,
     endpoint: E,
     handler: H,
-  ) {
-    let entry = self
-      .0
-      .entry(endpoint.as_ref().to_string())
-      .or_insert_with(|| HashMap::new());
-    let handler = Arc::new(handler);
+  ) -> crate::Result<()> {
+    if let Some(re) = endpoint_regex(endpoint.as_ref()) {
+      re?;
+    }
+    let mut table = self.table.write()?;
+    let id = handler.route().id();
+    let key = match table.routes.iter().filter(|r| r.id() == id).count() {
+      0 => id,
+      n => format!("{} #{}", id, n + 1),
+    };
+    table.routes.push(handler.route().clone());
+    table.keys.push(key.clone());
+    let entry = table.endpoints.entry(endpoint);
+    let handler: Arc<dyn RouteHandler> = Arc::new(Registered {
+      key,
+      handler: Box::new(handler),
+    });
     for meth in methods.into_iter() {
-      entry.insert(meth, handler.clone());
+      entry.entry(meth).or_default().push(handler.clone());
     }
+    Ok(())
   }
 
+  /// Every handler registered for `method` on the most specific endpoint
+  /// matching `path`, in declaration order, routes whose tags are inactive
+  /// or which are switched off excepted
+  pub fn handlers<P: AsRef<str>>(
+    &self,
+    method: Method,
+    path: P,
+  ) -> crate::Result<Vec<Arc<dyn RouteHandler>>> {
+    let table = self.table.read()?;
+    let mut handlers = vec![];
+    for handler in table
+      .endpoints
+      .find(path, |methods| methods.contains_key(&method))
+      .and_then(|methods| methods.get(&method))
+      .into_iter()
+      .flatten()
+    {
+      if self.serves(handler.as_ref())? {
+        handlers.push(handler.clone());
+      }
+    }
+    Ok(handlers)
+  }
+
+  /// Whether `handler` is switched on and its tags active
+  fn serves(&self, handler: &dyn RouteHandler) -> crate::Result<bool> {
+    Ok(
+      self.tags.serves(handler.route())?
+        && self.switches.enabled(&handler.key(), handler.route())?,
+    )
+  }
+
+  /// Whether `method` requests to `path` match routes, all of them switched
+  /// off, so a proxy can pass them through to the real server
+  pub fn switched_off<P: AsRef<str>>(&self, method: Method, path: P) -> crate::Result<bool> {
+    let table = self.table.read()?;
+    let mut switched_off = false;
+    for handler in table
+      .endpoints
+      .find(path, |methods| methods.contains_key(&method))
+      .and_then(|methods| methods.get(&method))
+      .into_iter()
+      .flatten()
+    {
+      if !self.tags.serves(handler.route())? {
+        continue;
+      }
+      if self.switches.enabled(&handler.key(), handler.route())? {
+        return Ok(false);
+      }
+      switched_off = true;
+    }
+    Ok(switched_off)
+  }
+
+  /// Methods handled on any endpoint matching `path`, by routes whose tags
+  /// are active and which are switched on
+  pub fn allowed_methods<P: AsRef<str>>(&self, path: P) -> crate::Result<Vec<Method>> {
+    let table = self.table.read()?;
+    let mut methods = BTreeSet::new();
+    for endpoint in table.endpoints.find_all(path) {
+      for (method, handlers) in endpoint {
+        for handler in handlers {
+          if self.serves(handler.as_ref())? {
+            methods.insert(*method);
+            break;
+          }
+        }
+      }
+    }
+    Ok(methods.into_iter().collect())
+  }
+
+  /// First handler for `method` on `endpoint` whose scenario state allows it to serve
   pub fn handler<E: AsRef<str>>(
     &self,
     method: Method,
     endpoint: E,
-  ) -> Option<&Arc<dyn RouteHandler>> {
-    match self
-      .0
-      .iter()
-      .find(|(_endpoint, _methods)| _endpoint.as_str().eq(endpoint.as_ref()))
+  ) -> crate::Result<Option<Arc<dyn RouteHandler>>> {
+    for handler in self.handlers(method, endpoint)? {
+      let accepted = match handler.route().options().scenario.as_ref() {
+        Some(scenario) => self.scenarios.accepts(scenario)?,
+        None => true,
+      };
+      if accepted {
+        return Ok(Some(handler));
+      }
+    }
+    Ok(None)
+  }
+
+  /// First handler serving `req`, its scenario state allowing it and the
+  /// request passing its `when` checks
+  pub fn handler_for(&self, req: &Request) -> crate::Result<Option<Arc<dyn RouteHandler>>> {
+    let (method, path) = (
+      req.method().unwrap_or(Method::Get),
+      req.path().unwrap_or("/"),
+    );
+    for handler in self.handlers(method, path)? {
+      let accepted = match handler.route().options().scenario.as_ref() {
+        Some(scenario) => self.scenarios.accepts(scenario)?,
+        None => true,
+      };
+      let matched = match handler.route().options().when.as_ref() {
+        Some(when) => when.matches(req).matched,
+        None => true,
+      };
+      let hosted = match handler.route().options().host.as_ref() {
+        Some(host) => crate::host_matches(host, req),
+        None => true,
+      };
+      if accepted && matched && hosted {
+        return Ok(Some(handler));
+      }
+    }
+    Ok(None)
+  }
+
+  /// Drop the isolated copies of store data kept for `session`
+  pub fn end_session<S: AsRef<str>>(&self, session: S) -> crate::Result<()> {
+    let table = self.table.read()?;
+    for handlers in table
+      .endpoints
+      .values()
+      .into_iter()
+      .flat_map(|m| m.values())
     {
-      Some((_endpoint, methods)) => match methods.iter().find(|(m, h)| method as u8 == **m as u8) {
-        Some((m, h)) => Some(h),
-        None => None,
-      },
-      None => None,
+      for handler in handlers {
+        handler.end_session(session.as_ref())?;
+      }
     }
+    Ok(())
+  }
+
+  /// Route serving `req`, if any
+  pub fn route(&self, req: &Request) -> crate::Result<Option<Route>> {
+    Ok(self.handler_for(req)?.map(|h| h.route().clone()))
+  }
+
+  /// Options of the route serving `req`, if any
+  pub fn options(&self, req: &Request) -> crate::Result<Option<RouteOptions>> {
+    Ok(self.route(req)?.map(|r| r.options().clone()))
   }
 
   pub fn dispatch(&self, req: &Request, res: Response) -> crate::Result<Response> {
-    let endpoint = req.path().unwrap_or_else(|| "/");
-    match self.handler(req.method().unwrap_or_else(|| Method::Get), endpoint) {
+    let endpoint = req.path().unwrap_or("/");
+    match self.handler_for(req)? {
       Some(handler) => {
         debug!("Found handler for '{}'", endpoint);
-        handler.handle(req, res)
+        let mut req = req.clone();
+        let params = endpoint_params(handler.route().endpoint(), endpoint).unwrap_or_default();
+        req.extensions_mut().insert(PathParams(params));
+        req
+          .extensions_mut()
+          .insert(MatchedRoute(handler.route().clone()));
+        if let Some(auth) = handler.route().options().auth.as_ref() {
+          if let Some(denied) = auth.check(&req, &self.tokens)? {
+            return Ok(denied);
+          }
+          if let Some(principal) = auth.principal(&req, &self.tokens)? {
+            req.extensions_mut().insert(Principal(principal));
+          }
+        }
+        let req = &req;
+        let rate_limit = match handler.route().options().rate_limit.as_ref() {
+          Some(limit) => {
+            let usage = self
+              .rate_limits
+              .hit(handler.route(), limit, self.clock.now_millis())?;
+            if usage.exceeded() {
+              warn!("'{}' is over its rate limit of {}", endpoint, limit.limit);
+              let mut res: Response = Error::new(
+                ErrorKind::Api(Status::TooManyRequests),
+                Some(format!("rate limit of {} requests exceeded", limit.limit)),
+                None,
+              )
+              .into();
+              limit.apply(&usage, &mut res);
+              return Ok(res);
+            }
+            Some((limit, usage))
+          }
+          None => None,
+        };
+        let _permit = match handler.route().options().max_concurrent {
+          Some(limit) => match self.concurrency.acquire(handler.route(), limit)? {
+            Some(permit) => Some(permit),
+            None => {
+              warn!("'{}' is serving {} requests already", endpoint, limit);
+              let res: Response = Error::new(
+                ErrorKind::Api(Status::ServiceUnavailable),
+                Some(format!("{} requests are being served already", limit)),
+                None,
+              )
+              .into();
+              return Ok(res.with_header("Retry-After", Concurrency::RETRY_AFTER.to_string()));
+            }
+          },
+          None => None,
+        };
+        self.invocations.record(&handler.key(), req)?;
+        if let Some(scenario) = handler.route().options().scenario.as_ref() {
+          self.scenarios.served(scenario)?;
+        }
+        let mut res = match handler.route().options().cache.as_ref() {
+          Some(policy) => {
+            let key = policy.fingerprint(handler.route(), req);
+            let revision = policy.revision(&self.store_events)?;
+            match self
+              .cache
+              .get(&key, policy, revision, self.clock.now_millis())?
+            {
+              Some(mut cached) => {
+                cached.set_header(ResponseCache::STATUS_HEADER, "HIT");
+                cached
+              }
+              None => {
+                let mut res = Self::handle_with(&handler, req, res)?;
+                self
+                  .cache
+                  .put(key, revision, &res, self.clock.now_millis())?;
+                res.set_header(ResponseCache::STATUS_HEADER, "MISS");
+                res
+              }
+            }
+          }
+          None => Self::handle_with(&handler, req, res)?,
+        };
+        if let Some(experiment) = handler.route().options().experiment.as_ref() {
+          res = experiment.apply(req, res)?;
+        }
+        if let Some((limit, usage)) = rate_limit {
+          limit.apply(&usage, &mut res);
+        }
+        let mut res = self.response_check.apply(handler.route(), res)?;
+        if let Some(mask) = handler.route().options().mask.as_ref() {
+          mask.apply_to(&mut res)?;
+        }
+        Ok(res)
+      }
+      None => {
+        let allowed = self.allowed_methods(endpoint)?;
+        let method = req.method().unwrap_or(Method::Get);
+        match allowed.is_empty() || allowed.contains(&method) {
+          true => Ok(Response::default().with_status_code(404)),
+          false => {
+            let allowed = allowed.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+            Ok(
+              Response::default()
+                .with_status_code(Status::MethodNotAllowed.code())
+                .with_header("Allow", allowed.join(", ")),
+            )
+          }
+        }
+      }
+    }
+  }
+
+  /// Let `handler` answer `req`, applying the header rules of its route
+  fn handle_with(
+    handler: &Arc<dyn RouteHandler>,
+    req: &Request,
+    res: Response,
+  ) -> crate::Result<Response> {
+    match handler.route().options().headers.as_ref() {
+      Some(rules) => {
+        let mut req = req.clone();
+        rules.request.apply(&mut req);
+        let mut res = handler.handle(&req, res)?;
+        rules.response.apply(&mut res);
+        Ok(res)
       }
-      None => Ok(Response::default().with_status_code(404)),
+      None => handler.handle(req, res),
     }
   }
 
-  pub fn with_routes<I: IntoIterator<Item = crate::Route>>(mut self, routes: I) -> Self {
+  pub fn routes(&self) -> crate::Result<Vec<Route>> {
+    Ok(self.table.read()?.routes.clone())
+  }
+
+  /// Key of every registered route, in the order of [`Router::routes`]
+  pub fn keys(&self) -> crate::Result<Vec<String>> {
+    Ok(self.table.read()?.keys.clone())
+  }
+
+  pub fn invocations(&self) -> &Arc<Invocations> {
+    &self.invocations
+  }
+
+  /// Requests in flight on routes with a `max_concurrent` option
+  pub fn concurrency(&self) -> &Arc<Concurrency> {
+    &self.concurrency
+  }
+
+  /// Windows of routes with a `rate_limit` option
+  pub fn rate_limits(&self) -> &Arc<RateLimits> {
+    &self.rate_limits
+  }
+
+  /// Responses of routes with a `cache` option
+  pub fn cache(&self) -> &Arc<ResponseCache> {
+    &self.cache
+  }
+
+  /// Changes made to the data of store routes
+  pub fn store_events(&self) -> &Arc<StoreEvents> {
+    &self.store_events
+  }
+
+  pub fn tokens(&self) -> &Arc<Tokens> {
+    &self.tokens
+  }
+
+  /// Run-specific prefixes endpoints are mounted under, if enabled
+  pub fn namespaces(&self) -> Option<&Arc<Namespaces>> {
+    self.namespaces.as_ref()
+  }
+
+  /// Tags of the routes served
+  pub fn tags(&self) -> &Arc<RouteTags> {
+    &self.tags
+  }
+
+  /// Routes switched on or off at runtime
+  pub fn switches(&self) -> &Arc<RouteSwitches> {
+    &self.switches
+  }
+
+  pub fn scenarios(&self) -> &Arc<Scenarios> {
+    &self.scenarios
+  }
+
+  pub fn variables(&self) -> &Arc<Variables> {
+    &self.variables
+  }
+
+  /// Virtual clock of the routes, shifted through the admin API
+  pub fn clock(&self) -> &VirtualClock {
+    &self.clock
+  }
+
+  pub fn with_scenarios(mut self, scenarios: HashMap<String, ScenarioConfig>) -> Self {
+    self.scenarios = Arc::new(Scenarios::new(scenarios).with_clock(self.clock.clone()));
+    self
+  }
+
+  pub fn with_namespaces(mut self, enabled: bool) -> Self {
+    self.namespaces = enabled.then(Default::default);
+    self
+  }
+
+  /// Serve only the routes tagged with one of `tags`, or every route
+  pub fn with_tags(mut self, tags: Option<Vec<String>>) -> Self {
+    self.tags = Arc::new(RouteTags::new(tags));
+    self
+  }
+
+  /// Let script routes registered from now on call the servers `policy`
+  /// allows with `fetch`
+  #[cfg(feature = "js")]
+  pub fn with_fetch(mut self, policy: Option<crate::FetchPolicy>) -> Self {
+    self.fetch = policy;
+    self
+  }
+
+  pub fn with_response_check(mut self, check: ResponseCheck) -> Self {
+    self.response_check = check;
+    self
+  }
+
+  /// Check that every route with an `expect` constraint was called accordingly
+  pub fn verify(&self) -> crate::Result<()> {
+    let table = self.table.read()?;
+    self
+      .invocations
+      .verify(table.keys.iter().zip(table.routes.iter()))
+  }
+
+  pub fn with_routes<I: IntoIterator<Item = crate::Route>>(self, routes: I) -> Self {
     for route in routes.into_iter() {
-      match route.kind() {
-        #[cfg(feature = "js")]
-        RouteKind::Script { script, func } => self.set(
-          route.methods().clone(),
-          route.endpoint(),
-          ScriptRouteHandler::new(route.clone(), script, func),
-        ),
-        #[cfg(feature = "json")]
-        RouteKind::Store { path, identifier } => self.set(
-          route.methods().clone(),
-          route.endpoint(),
-          StoreRouteHandler::new(route.clone(), path, identifier),
-        ),
+      if let Err(e) = self.add(route) {
+        warn!("Failed to register route: {}", e);
       }
     }
     self
   }
+
+  /// Register a route at runtime, after the already defined ones
+  pub fn add(&self, route: Route) -> crate::Result<()> {
+    let methods = route.methods().clone();
+    let endpoint = route.endpoint().clone();
+    if let Some(Err(e)) = endpoint_regex(&endpoint) {
+      return Err(e);
+    }
+    match route.kind() {
+      RouteKind::Fixture { .. } => self.set(
+        methods,
+        endpoint,
+        FixtureRouteHandler::new(route, self.variables.clone()).with_clock(self.clock.clone()),
+      ),
+      RouteKind::Exec { .. } => self.set(methods, endpoint, ExecRouteHandler::new(route)),
+      RouteKind::Echo { .. } => self.set(methods, endpoint, EchoRouteHandler::new(route)),
+      #[cfg(feature = "js")]
+      RouteKind::Script { script, func } => {
+        let (script, func) = (script.clone(), func.clone());
+        self.set(
+          methods,
+          endpoint,
+          ScriptRouteHandler::new(route, script, func).with_fetch(self.fetch.clone()),
+        )
+      }
+      #[cfg(feature = "json")]
+      RouteKind::Store {
+        path,
+        identifier,
+        parent,
+      } => {
+        let (path, identifier, parent) = (path.clone(), identifier.clone(), parent.clone());
+        self.set(
+          methods,
+          endpoint,
+          StoreRouteHandler::new(route, path, identifier)
+            .with_events(self.store_events.clone())
+            .with_clock(self.clock.clone())
+            .with_parent(parent),
+        )
+      }
+      #[cfg(feature = "s3")]
+      RouteKind::S3 { root_dir } => {
+        let root_dir = root_dir.clone();
+        self.set(methods, endpoint, S3RouteHandler::new(route, root_dir))
+      }
+    }
+  }
+
+  /// Replace every route at runtime, keeping invocations, scenarios and variables
+  pub fn replace<I: IntoIterator<Item = crate::Route>>(&self, routes: I) -> crate::Result<()> {
+    *self.table.write()? = RouteTable::default();
+    for route in routes.into_iter() {
+      self.add(route)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use std::collections::BTreeMap;
+
+  use crate::{ErrorKind, Method, Request, Response, Route, RouteKind, Status};
+
+  use super::{ExecRouteHandler, RouteHandler};
+
+  fn exec(script: &str, configure: impl FnOnce(&mut RouteKind)) -> ExecRouteHandler {
+    let mut kind = RouteKind::Exec {
+      command: "/bin/sh".to_string(),
+      args: vec!["-c".to_string(), script.to_string()],
+      status: 200,
+      headers: BTreeMap::new(),
+      timeout: Some("5s".to_string()),
+      cwd: None,
+      env: BTreeMap::new(),
+      clear_env: false,
+    };
+    configure(&mut kind);
+    ExecRouteHandler::new(Route::new(vec![Method::Post], "/run", kind))
+  }
+
+  fn run(handler: &ExecRouteHandler) -> crate::Result<String> {
+    let req = Request::new(Method::Post, "/run")
+      .with_header("Content-Type", "application/json")
+      .with_body(r#"{"name": "ada"}"#);
+    let res = handler.handle(&req, Response::default())?;
+    Ok(String::from_utf8_lossy(res.body()).to_string())
+  }
+
+  #[test]
+  fn stdin() {
+    let out = run(&exec("cat", |_| {})).unwrap();
+    let req: serde_json::Value = serde_json::from_str(&out).unwrap();
+    assert_eq!(req["method"], "POST");
+    assert_eq!(req["body"]["name"], "ada");
+  }
+
+  #[test]
+  fn environment() {
+    let dir = std::env::temp_dir().canonicalize().unwrap();
+    std::env::set_var("MOCKER_EXEC_INHERITED", "yes");
+    let script = r#"echo "$SEED ${MOCKER_EXEC_INHERITED:-none} $(pwd)""#;
+    let handler = |clear_env| {
+      exec(script, |kind| {
+        if let RouteKind::Exec {
+          env,
+          cwd,
+          clear_env: clear,
+          ..
+        } = kind
+        {
+          env.insert("SEED".to_string(), "42".to_string());
+          *cwd = Some(dir.clone());
+          *clear = clear_env;
+        }
+      })
+    };
+    assert_eq!(
+      run(&handler(false)).unwrap().trim(),
+      format!("42 yes {}", dir.display())
+    );
+    assert_eq!(
+      run(&handler(true)).unwrap().trim(),
+      format!("42 none {}", dir.display())
+    );
+  }
+
+  #[test]
+  fn failure() {
+    let err = run(&exec("echo broken >&2; exit 3", |_| {})).unwrap_err();
+    assert!(matches!(
+      err.kind(),
+      ErrorKind::Api(Status::BadGatewayOuProxyError)
+    ));
+    assert!(err.to_string().contains("broken"), "{}", err);
+  }
+
+  #[test]
+  fn timeout() {
+    let marker = std::env::temp_dir().join(format!("mocker-exec-{}", std::process::id()));
+    let script = format!("sleep 0.5; touch {}", marker.display());
+    let handler = exec(&script, |kind| {
+      if let RouteKind::Exec { timeout, .. } = kind {
+        *timeout = Some("50ms".to_string());
+      }
+    });
+    let err = run(&handler).unwrap_err();
+    assert!(matches!(err.kind(), ErrorKind::Api(Status::GatewayTimeOut)));
+    // the command was killed before it could go on
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    assert!(!marker.exists());
+  }
 }
@@ -0,0 +1,644 @@
+use std::{
+  collections::HashMap,
+  fs,
+  io::Read,
+  path::{Component, Path, PathBuf},
+  time::UNIX_EPOCH,
+};
+
+use md5::{Digest, Md5};
+
+use crate::{
+  form_params, format_http_date, format_iso8601, percent_decode, random_token, Method, Request,
+  Response, Status,
+};
+
+const XMLNS: &str = "http://s3.amazonaws.com/doc/2006-03-01/";
+
+fn escape(text: &str) -> String {
+  text
+    .replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+fn xml(status: Status, body: String) -> Response {
+  Response::default()
+    .with_status(status)
+    .with_header("Content-Type", "application/xml")
+    .with_body(format!(
+      "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}",
+      body
+    ))
+}
+
+/// S3 error document
+fn error(status: Status, code: &str, message: &str, resource: &str) -> Response {
+  xml(
+    status,
+    format!(
+      "<Error><Code>{}</Code><Message>{}</Message><Resource>{}</Resource><RequestId>{}</RequestId></Error>",
+      code,
+      escape(message),
+      escape(resource),
+      random_token()
+    ),
+  )
+}
+
+/// Payload of a request, aws-chunked uploads being decoded
+fn payload(req: &Request) -> Vec<u8> {
+  let chunked = req
+    .header("Content-Encoding")
+    .is_some_and(|e| e.contains("aws-chunked"))
+    || req
+      .header("x-amz-content-sha256")
+      .is_some_and(|s| s.starts_with("STREAMING-"));
+  let body = req.body();
+  if !chunked {
+    return body.to_vec();
+  }
+  // `{size hex}[;chunk-signature=...]\r\n{data}\r\n`, until a 0 sized chunk
+  let mut out = vec![];
+  let mut rest = &body[..];
+  while let Some(eol) = rest.windows(2).position(|w| w == b"\r\n") {
+    let line = String::from_utf8_lossy(&rest[..eol]);
+    let size = line.split(';').next().unwrap_or_default().trim();
+    let size = match usize::from_str_radix(size, 16) {
+      Ok(0) | Err(_) => break,
+      Ok(size) => size,
+    };
+    let data = &rest[eol + 2..];
+    let size = size.min(data.len());
+    out.extend_from_slice(&data[..size]);
+    rest = data[size..].strip_prefix(b"\r\n").unwrap_or(&data[size..]);
+  }
+  out
+}
+
+fn etag(data: &[u8]) -> String {
+  let digest = Md5::digest(data);
+  format!(
+    "\"{}\"",
+    digest
+      .iter()
+      .map(|b| format!("{:02x}", b))
+      .collect::<String>()
+  )
+}
+
+fn modified(path: &Path) -> u128 {
+  fs::metadata(path)
+    .and_then(|m| m.modified())
+    .ok()
+    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+    .map(|d| d.as_millis())
+    .unwrap_or_default()
+}
+
+/// Object of a bucket listing
+struct Object {
+  key: String,
+  size: u64,
+  modified: u128,
+  etag: String,
+}
+
+/// Enough of the S3 REST API for SDKs to work against a directory, in path
+/// style (`/{bucket}/{key}`): buckets are its subdirectories and objects the
+/// files below them. Multipart uploads are staged in `.uploads`.
+#[derive(Debug, Clone)]
+pub struct S3Storage {
+  root: PathBuf,
+}
+
+impl S3Storage {
+  const UPLOADS_DIR: &'static str = ".uploads";
+
+  pub fn new<P: AsRef<Path>>(root: P) -> Self {
+    Self {
+      root: root.as_ref().to_path_buf(),
+    }
+  }
+
+  pub fn root(&self) -> &Path {
+    &self.root
+  }
+
+  /// Answer `req`, `path` being `{bucket}/{key}` relative to where the
+  /// storage is mounted
+  pub fn handle(&self, req: &Request, path: &str) -> crate::Result<Response> {
+    let path = percent_decode(path.trim_start_matches('/'));
+    let (bucket, key) = path.split_once('/').unwrap_or((path.as_str(), ""));
+    let unsafe_path = Path::new(&path)
+      .components()
+      .any(|c| !matches!(c, Component::Normal(_)));
+    if unsafe_path || bucket.starts_with('.') {
+      return Ok(error(
+        Status::BadRequest,
+        "InvalidURI",
+        "Couldn't parse the specified URI.",
+        &path,
+      ));
+    }
+    let query = form_params(req.query().unwrap_or_default().as_bytes());
+    let upload_id = query.get("uploadId").cloned();
+    let method = req.method().unwrap_or(Method::Get);
+    let res = match (method, bucket.is_empty(), key.is_empty()) {
+      (Method::Get, true, _) => self.list_buckets()?,
+      (_, true, _) => error(
+        Status::MethodNotAllowed,
+        "MethodNotAllowed",
+        "The specified method is not allowed against this resource.",
+        "/",
+      ),
+      (Method::Put, false, true) => {
+        fs::create_dir_all(self.root.join(bucket))?;
+        Response::default()
+          .with_status(Status::OK)
+          .with_header("Location", format!("/{}", bucket))
+      }
+      (_, false, _) if !self.root.join(bucket).is_dir() => error(
+        Status::NotFound,
+        "NoSuchBucket",
+        "The specified bucket does not exist",
+        bucket,
+      ),
+      (Method::Head, false, true) => Response::default().with_status(Status::OK),
+      (Method::Get, false, true) => self.list_objects(bucket, &query)?,
+      (Method::Delete, false, true) => self.delete_bucket(bucket)?,
+      (Method::Post, false, false) if query.contains_key("uploads") => {
+        self.create_upload(bucket, key)?
+      }
+      (Method::Put, false, false) if upload_id.is_some() => {
+        let part = query.get("partNumber").and_then(|n| n.parse::<u32>().ok());
+        self.upload_part(req, upload_id.as_deref().unwrap_or_default(), part)?
+      }
+      (Method::Post, false, false) if upload_id.is_some() => {
+        self.complete_upload(req, bucket, key, upload_id.as_deref().unwrap_or_default())?
+      }
+      (Method::Delete, false, false) if upload_id.is_some() => {
+        let dir = self.upload_dir(upload_id.as_deref().unwrap_or_default());
+        if dir.is_dir() {
+          fs::remove_dir_all(dir)?;
+        }
+        Response::default().with_status(Status::NoContent)
+      }
+      (Method::Put, false, false) => self.put_object(req, bucket, key)?,
+      (Method::Get | Method::Head, false, false) => self.get_object(method, bucket, key)?,
+      (Method::Delete, false, false) => self.delete_object(bucket, key)?,
+      _ => error(
+        Status::MethodNotAllowed,
+        "MethodNotAllowed",
+        "The specified method is not allowed against this resource.",
+        &path,
+      ),
+    };
+    Ok(res)
+  }
+
+  fn list_buckets(&self) -> crate::Result<Response> {
+    let mut buckets = vec![];
+    if self.root.is_dir() {
+      for entry in fs::read_dir(&self.root)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.path().is_dir() && !name.starts_with('.') {
+          buckets.push((name, modified(&entry.path())));
+        }
+      }
+    }
+    buckets.sort();
+    let buckets = buckets
+      .iter()
+      .map(|(name, at)| {
+        format!(
+          "<Bucket><Name>{}</Name><CreationDate>{}</CreationDate></Bucket>",
+          escape(name),
+          format_iso8601(*at)
+        )
+      })
+      .collect::<String>();
+    Ok(xml(
+      Status::OK,
+      format!(
+        "<ListAllMyBucketsResult xmlns=\"{}\"><Owner><ID>mocker</ID><DisplayName>mocker</DisplayName></Owner><Buckets>{}</Buckets></ListAllMyBucketsResult>",
+        XMLNS, buckets
+      ),
+    ))
+  }
+
+  /// Objects below `dir`, keyed relative to `base`
+  fn walk(&self, base: &Path, dir: &Path, out: &mut Vec<Object>) -> crate::Result<()> {
+    for entry in fs::read_dir(dir)? {
+      let path = entry?.path();
+      if path.is_dir() {
+        self.walk(base, &path, out)?;
+        continue;
+      }
+      let key = path
+        .strip_prefix(base)
+        .unwrap_or(&path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/");
+      out.push(Object {
+        key,
+        size: fs::metadata(&path)?.len(),
+        modified: modified(&path),
+        etag: etag(&fs::read(&path)?),
+      });
+    }
+    Ok(())
+  }
+
+  fn list_objects(&self, bucket: &str, query: &HashMap<String, String>) -> crate::Result<Response> {
+    let base = self.root.join(bucket);
+    let mut objects = vec![];
+    self.walk(&base, &base, &mut objects)?;
+    objects.sort_by(|a, b| a.key.cmp(&b.key));
+    let prefix = query.get("prefix").cloned().unwrap_or_default();
+    let delimiter = query.get("delimiter").filter(|d| !d.is_empty());
+    let after = query
+      .get("start-after")
+      .or_else(|| query.get("marker"))
+      .or_else(|| query.get("continuation-token"))
+      .cloned()
+      .unwrap_or_default();
+    let max_keys = query
+      .get("max-keys")
+      .and_then(|m| m.parse::<usize>().ok())
+      .unwrap_or(1000);
+    let mut contents = String::new();
+    let mut prefixes: Vec<String> = vec![];
+    let mut count = 0;
+    let mut last = None;
+    let mut truncated = false;
+    for object in objects
+      .iter()
+      .filter(|o| o.key.starts_with(&prefix) && o.key > after)
+    {
+      let common = delimiter.and_then(|d| {
+        object.key[prefix.len()..]
+          .find(d.as_str())
+          .map(|i| object.key[..prefix.len() + i + d.len()].to_string())
+      });
+      if let Some(common) = &common {
+        if prefixes.contains(common) {
+          continue;
+        }
+      }
+      if count == max_keys {
+        truncated = true;
+        break;
+      }
+      count += 1;
+      last = Some(object.key.clone());
+      match common {
+        Some(common) => prefixes.push(common),
+        None => {
+          contents += &format!(
+            "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+            escape(&object.key),
+            format_iso8601(object.modified),
+            escape(&object.etag),
+            object.size
+          )
+        }
+      }
+    }
+    let prefixes = prefixes
+      .iter()
+      .map(|p| {
+        format!(
+          "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+          escape(p)
+        )
+      })
+      .collect::<String>();
+    let next = match (truncated, &last) {
+      (true, Some(last)) => format!(
+        "<NextContinuationToken>{0}</NextContinuationToken><NextMarker>{0}</NextMarker>",
+        escape(last)
+      ),
+      _ => String::new(),
+    };
+    Ok(xml(
+      Status::OK,
+      format!(
+        "<ListBucketResult xmlns=\"{}\"><Name>{}</Name><Prefix>{}</Prefix>{}<KeyCount>{}</KeyCount><MaxKeys>{}</MaxKeys><IsTruncated>{}</IsTruncated>{}{}{}</ListBucketResult>",
+        XMLNS,
+        escape(bucket),
+        escape(&prefix),
+        delimiter
+          .map(|d| format!("<Delimiter>{}</Delimiter>", escape(d)))
+          .unwrap_or_default(),
+        count,
+        max_keys,
+        truncated,
+        next,
+        contents,
+        prefixes
+      ),
+    ))
+  }
+
+  fn delete_bucket(&self, bucket: &str) -> crate::Result<Response> {
+    let dir = self.root.join(bucket);
+    if fs::read_dir(&dir)?.next().is_some() {
+      return Ok(error(
+        Status::Conflict,
+        "BucketNotEmpty",
+        "The bucket you tried to delete is not empty",
+        bucket,
+      ));
+    }
+    fs::remove_dir(dir)?;
+    Ok(Response::default().with_status(Status::NoContent))
+  }
+
+  fn put_object(&self, req: &Request, bucket: &str, key: &str) -> crate::Result<Response> {
+    let path = self.root.join(bucket).join(key);
+    if key.ends_with('/') {
+      fs::create_dir_all(&path)?;
+      return Ok(
+        Response::default()
+          .with_status(Status::OK)
+          .with_header("ETag", etag(b"")),
+      );
+    }
+    let data = match req.header("x-amz-copy-source") {
+      Some(source) => {
+        let source = percent_decode(source.trim_start_matches('/'));
+        let source = source.split('?').next().unwrap_or_default();
+        let from = self.root.join(source);
+        let unsafe_path = Path::new(source)
+          .components()
+          .any(|c| !matches!(c, Component::Normal(_)));
+        if unsafe_path || !from.is_file() {
+          return Ok(error(
+            Status::NotFound,
+            "NoSuchKey",
+            "The specified key does not exist.",
+            source,
+          ));
+        }
+        let data = fs::read(from)?;
+        Self::write(&path, &data)?;
+        return Ok(xml(
+          Status::OK,
+          format!(
+            "<CopyObjectResult><LastModified>{}</LastModified><ETag>{}</ETag></CopyObjectResult>",
+            format_iso8601(modified(&path)),
+            escape(&etag(&data))
+          ),
+        ));
+      }
+      None => payload(req),
+    };
+    Self::write(&path, &data)?;
+    Ok(
+      Response::default()
+        .with_status(Status::OK)
+        .with_header("ETag", etag(&data)),
+    )
+  }
+
+  fn write(path: &Path, data: &[u8]) -> crate::Result<()> {
+    if let Some(parent) = path.parent() {
+      fs::create_dir_all(parent)?;
+    }
+    fs::write(path, data)?;
+    Ok(())
+  }
+
+  fn get_object(&self, method: Method, bucket: &str, key: &str) -> crate::Result<Response> {
+    let path = self.root.join(bucket).join(key);
+    if !path.is_file() {
+      return Ok(match method {
+        Method::Head => Response::default().with_status(Status::NotFound),
+        _ => error(
+          Status::NotFound,
+          "NoSuchKey",
+          "The specified key does not exist.",
+          key,
+        ),
+      });
+    }
+    let mut data = vec![];
+    fs::File::open(&path)?.read_to_end(&mut data)?;
+    let res = Response::default()
+      .with_status(Status::OK)
+      .with_header("Content-Type", "application/octet-stream")
+      .with_header("ETag", etag(&data))
+      .with_header("Last-Modified", format_http_date(modified(&path)))
+      .with_header("Accept-Ranges", "bytes");
+    Ok(match method {
+      Method::Head => res.with_header("Content-Length", data.len().to_string()),
+      _ => res.with_body_bytes(data),
+    })
+  }
+
+  fn delete_object(&self, bucket: &str, key: &str) -> crate::Result<Response> {
+    let base = self.root.join(bucket);
+    let path = base.join(key);
+    if path.is_file() {
+      fs::remove_file(&path)?;
+      // drop the directories the object was the last file of
+      let mut dir = path.parent();
+      while let Some(d) = dir.filter(|d| *d != base) {
+        if fs::remove_dir(d).is_err() {
+          break;
+        }
+        dir = d.parent();
+      }
+    }
+    Ok(Response::default().with_status(Status::NoContent))
+  }
+
+  fn upload_dir(&self, upload_id: &str) -> PathBuf {
+    let id = upload_id
+      .chars()
+      .filter(|c| c.is_ascii_alphanumeric())
+      .collect::<String>();
+    self.root.join(Self::UPLOADS_DIR).join(id)
+  }
+
+  fn create_upload(&self, bucket: &str, key: &str) -> crate::Result<Response> {
+    let upload_id = random_token();
+    fs::create_dir_all(self.upload_dir(&upload_id))?;
+    Ok(xml(
+      Status::OK,
+      format!(
+        "<InitiateMultipartUploadResult xmlns=\"{}\"><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+        XMLNS,
+        escape(bucket),
+        escape(key),
+        upload_id
+      ),
+    ))
+  }
+
+  fn no_such_upload(upload_id: &str) -> Response {
+    error(
+      Status::NotFound,
+      "NoSuchUpload",
+      "The specified multipart upload does not exist.",
+      upload_id,
+    )
+  }
+
+  fn upload_part(
+    &self,
+    req: &Request,
+    upload_id: &str,
+    part: Option<u32>,
+  ) -> crate::Result<Response> {
+    let dir = self.upload_dir(upload_id);
+    let part = match part {
+      Some(part) if (1..=10_000).contains(&part) => part,
+      _ => {
+        return Ok(error(
+          Status::BadRequest,
+          "InvalidArgument",
+          "Part number must be an integer between 1 and 10000, inclusive",
+          upload_id,
+        ))
+      }
+    };
+    if !dir.is_dir() {
+      return Ok(Self::no_such_upload(upload_id));
+    }
+    let data = payload(req);
+    fs::write(dir.join(format!("{:05}", part)), &data)?;
+    Ok(
+      Response::default()
+        .with_status(Status::OK)
+        .with_header("ETag", etag(&data)),
+    )
+  }
+
+  /// Assemble the parts listed by the request, or every uploaded one
+  fn complete_upload(
+    &self,
+    req: &Request,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+  ) -> crate::Result<Response> {
+    let dir = self.upload_dir(upload_id);
+    if !dir.is_dir() {
+      return Ok(Self::no_such_upload(upload_id));
+    }
+    let body = String::from_utf8_lossy(req.body());
+    let re = regex::Regex::new(r"<PartNumber>\s*(\d+)\s*</PartNumber>").expect("valid regex");
+    let mut parts = re
+      .captures_iter(&body)
+      .filter_map(|c| c[1].parse::<u32>().ok())
+      .collect::<Vec<_>>();
+    if parts.is_empty() {
+      for entry in fs::read_dir(&dir)? {
+        if let Some(n) = entry?.file_name().to_str().and_then(|n| n.parse().ok()) {
+          parts.push(n);
+        }
+      }
+      parts.sort();
+    }
+    let mut data = vec![];
+    for part in &parts {
+      let path = dir.join(format!("{:05}", part));
+      if !path.is_file() {
+        return Ok(error(
+          Status::BadRequest,
+          "InvalidPart",
+          "One or more of the specified parts could not be found.",
+          &part.to_string(),
+        ));
+      }
+      data.extend(fs::read(path)?);
+    }
+    let path = self.root.join(bucket).join(key);
+    Self::write(&path, &data)?;
+    fs::remove_dir_all(dir)?;
+    Ok(xml(
+      Status::OK,
+      format!(
+        "<CompleteMultipartUploadResult xmlns=\"{}\"><Location>/{}/{}</Location><Bucket>{}</Bucket><Key>{}</Key><ETag>{}</ETag></CompleteMultipartUploadResult>",
+        XMLNS,
+        escape(bucket),
+        escape(key),
+        escape(bucket),
+        escape(key),
+        escape(&etag(&data))
+      ),
+    ))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Request};
+
+  use super::S3Storage;
+
+  #[test]
+  fn objects() {
+    let dir = std::env::temp_dir().join(format!("mocker-s3-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let s3 = S3Storage::new(&dir);
+    let call = |req: Request, path: &str| {
+      let res = s3.handle(&req, path).unwrap();
+      (
+        res.status(),
+        String::from_utf8_lossy(res.body()).to_string(),
+      )
+    };
+    assert_eq!(call(Request::new(Method::Put, "/b"), "b").0, 200);
+    let put = Request::new(Method::Put, "/b/docs/a%20b.txt").with_body("hello");
+    assert_eq!(call(put, "b/docs/a%20b.txt").0, 200);
+    assert_eq!(
+      call(
+        Request::new(Method::Get, "/b/docs/a%20b.txt"),
+        "b/docs/a%20b.txt"
+      ),
+      (200, "hello".to_string())
+    );
+    let (status, body) = call(
+      Request::new(Method::Get, "/b?list-type=2&delimiter=%2F"),
+      "b",
+    );
+    assert_eq!(status, 200);
+    assert!(body.contains("<CommonPrefixes><Prefix>docs/</Prefix></CommonPrefixes>"));
+    assert!(call(Request::new(Method::Get, "/b?prefix=docs"), "b")
+      .1
+      .contains("<Key>docs/a b.txt</Key>"));
+    assert_eq!(call(Request::new(Method::Delete, "/b"), "b").0, 409);
+    assert_eq!(call(Request::new(Method::Get, "/b/../x"), "b/../x").0, 400);
+
+    let (_, body) = call(Request::new(Method::Post, "/b/big?uploads"), "b/big");
+    let id = body
+      .split("<UploadId>")
+      .nth(1)
+      .and_then(|s| s.split("</UploadId>").next())
+      .unwrap()
+      .to_string();
+    for (n, data) in [(2, "world"), (1, "hello ")] {
+      let target = format!("/b/big?partNumber={}&uploadId={}", n, id);
+      let req = Request::new(Method::Put, &target).with_body(data);
+      assert_eq!(call(req, "b/big").0, 200);
+    }
+    let complete = Request::new(Method::Post, format!("/b/big?uploadId={}", id)).with_body(
+      "<CompleteMultipartUpload><Part><PartNumber>1</PartNumber></Part><Part><PartNumber>2</PartNumber></Part></CompleteMultipartUpload>",
+    );
+    assert_eq!(call(complete, "b/big").0, 200);
+    assert_eq!(
+      call(Request::new(Method::Get, "/b/big"), "b/big").1,
+      "hello world"
+    );
+    assert_eq!(call(Request::new(Method::Delete, "/b/big"), "b/big").0, 204);
+    assert_eq!(call(Request::new(Method::Get, "/b/big"), "b/big").0, 404);
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -0,0 +1,289 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{parse_duration, Error, ErrorKind, VirtualClock};
+
+/// State every scenario starts in unless configured otherwise
+pub const SCENARIO_STARTED: &str = "Started";
+
+/// An automatic state change, fired after a delay or a number of requests
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Transition {
+  pub from: String,
+  pub to: String,
+  /// Delay spent in `from` before moving on, e.g. `5s`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub after: Option<String>,
+  /// Number of requests served in `from` before moving on
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub after_requests: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScenarioConfig {
+  #[serde(default = "ScenarioConfig::default_initial")]
+  pub initial: String,
+  #[serde(default)]
+  pub transitions: Vec<Transition>,
+}
+
+impl Default for ScenarioConfig {
+  fn default() -> Self {
+    Self {
+      initial: Self::default_initial(),
+      transitions: vec![],
+    }
+  }
+}
+
+impl ScenarioConfig {
+  fn default_initial() -> String {
+    SCENARIO_STARTED.to_string()
+  }
+
+  /// Check that the delays of the transitions are valid durations
+  pub fn validate(&self) -> crate::Result<()> {
+    for t in &self.transitions {
+      if let Some(after) = &t.after {
+        parse_duration(after).map_err(|e| {
+          Error::new(
+            ErrorKind::Parse,
+            Some(format!(
+              "invalid transition from '{}' to '{}': {}",
+              t.from, t.to, e
+            )),
+            None,
+          )
+        })?;
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Scenario a route takes part in: it only matches while the scenario is in
+/// `state` (if set), and moves it to `next` (if set) once served.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RouteScenario {
+  pub name: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub state: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub next: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScenarioState {
+  pub state: String,
+  /// Milliseconds since the unix epoch at which `state` was entered
+  pub since: u128,
+  /// Requests served since `state` was entered
+  pub requests: usize,
+}
+
+impl ScenarioState {
+  fn enter<S: AsRef<str>>(state: S, since: u128) -> Self {
+    Self {
+      state: state.as_ref().to_string(),
+      since,
+      requests: 0,
+    }
+  }
+}
+
+/// Tracks the current state of every scenario.
+#[derive(Debug, Default)]
+pub struct Scenarios {
+  configs: HashMap<String, ScenarioConfig>,
+  states: Mutex<HashMap<String, ScenarioState>>,
+  clock: VirtualClock,
+}
+
+impl Scenarios {
+  pub fn new(configs: HashMap<String, ScenarioConfig>) -> Self {
+    Self {
+      configs,
+      states: Mutex::new(HashMap::new()),
+      clock: VirtualClock::default(),
+    }
+  }
+
+  /// Time transitions by `clock` instead of the system clock
+  pub fn with_clock(mut self, clock: VirtualClock) -> Self {
+    self.clock = clock;
+    self
+  }
+
+  pub fn config<N: AsRef<str>>(&self, name: N) -> ScenarioConfig {
+    self.configs.get(name.as_ref()).cloned().unwrap_or_default()
+  }
+
+  /// Current state of `name`, after applying any pending automatic transition.
+  pub fn state<N: AsRef<str>>(&self, name: N) -> crate::Result<ScenarioState> {
+    let mut g = self.states.lock()?;
+    Ok(self.tick(&mut g, name.as_ref()).clone())
+  }
+
+  pub fn states(&self) -> crate::Result<HashMap<String, ScenarioState>> {
+    let mut g = self.states.lock()?;
+    let mut names = self.configs.keys().cloned().collect::<Vec<_>>();
+    names.extend(g.keys().cloned());
+    let mut ret = HashMap::new();
+    for name in names {
+      ret.insert(name.clone(), self.tick(&mut g, &name).clone());
+    }
+    Ok(ret)
+  }
+
+  /// Whether a route taking part in `scenario` may serve the current request
+  pub fn accepts(&self, scenario: &RouteScenario) -> crate::Result<bool> {
+    Ok(match scenario.state.as_ref() {
+      Some(required) => self.state(&scenario.name)?.state.eq(required),
+      None => true,
+    })
+  }
+
+  /// Account for a request served by a route taking part in `scenario`
+  pub fn served(&self, scenario: &RouteScenario) -> crate::Result<()> {
+    let mut g = self.states.lock()?;
+    let current = self.tick(&mut g, &scenario.name);
+    current.requests += 1;
+    if let Some(next) = scenario.next.as_ref() {
+      *current = ScenarioState::enter(next, self.clock.now_millis());
+    }
+    Ok(())
+  }
+
+  pub fn set<N: AsRef<str>, S: AsRef<str>>(&self, name: N, state: S) -> crate::Result<()> {
+    let mut g = self.states.lock()?;
+    g.insert(
+      name.as_ref().to_string(),
+      ScenarioState::enter(state, self.clock.now_millis()),
+    );
+    Ok(())
+  }
+
+  pub fn reset(&self) -> crate::Result<()> {
+    self.states.lock()?.clear();
+    Ok(())
+  }
+
+  fn tick<'a>(
+    &self,
+    states: &'a mut HashMap<String, ScenarioState>,
+    name: &str,
+  ) -> &'a mut ScenarioState {
+    let config = self.config(name);
+    let now = self.clock.now_millis();
+    let current = states
+      .entry(name.to_string())
+      .or_insert_with(|| ScenarioState::enter(&config.initial, now));
+    // chained transitions are bounded by the number of declared transitions
+    for _ in 0..config.transitions.len() {
+      let fired = config.transitions.iter().find_map(|t| {
+        if t.from != current.state {
+          return None;
+        }
+        if let Some(after) = t
+          .after
+          .as_ref()
+          .and_then(|after| parse_duration(after).ok())
+        {
+          let deadline = current.since + after.as_millis();
+          if now >= deadline {
+            return Some(ScenarioState::enter(&t.to, deadline));
+          }
+        }
+        if let Some(n) = t.after_requests {
+          if current.requests >= n {
+            return Some(ScenarioState::enter(&t.to, now));
+          }
+        }
+        None
+      });
+      match fired {
+        Some(next) => *current = next,
+        None => break,
+      }
+    }
+    current
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use super::{RouteScenario, ScenarioConfig, Scenarios, Transition, SCENARIO_STARTED};
+
+  fn polling() -> Scenarios {
+    Scenarios::new(HashMap::from([(
+      "job".to_string(),
+      ScenarioConfig {
+        initial: "processing".to_string(),
+        transitions: vec![
+          Transition {
+            from: "processing".to_string(),
+            to: "almost".to_string(),
+            after: None,
+            after_requests: Some(2),
+          },
+          Transition {
+            from: "almost".to_string(),
+            to: "done".to_string(),
+            after: Some("0ms".to_string()),
+            after_requests: None,
+          },
+        ],
+      },
+    )]))
+  }
+
+  #[test]
+  fn invalid_delay() {
+    assert!(polling().config("job").validate().is_ok());
+    let config = ScenarioConfig {
+      transitions: vec![Transition {
+        from: SCENARIO_STARTED.to_string(),
+        to: "done".to_string(),
+        after: Some("soon".to_string()),
+        after_requests: None,
+      }],
+      ..Default::default()
+    };
+    assert!(config.validate().is_err());
+  }
+
+  #[test]
+  fn after_requests() {
+    let scenarios = polling();
+    let processing = RouteScenario {
+      name: "job".to_string(),
+      state: Some("processing".to_string()),
+      next: None,
+    };
+    assert!(scenarios.accepts(&processing).unwrap());
+    scenarios.served(&processing).unwrap();
+    assert!(scenarios.accepts(&processing).unwrap());
+    scenarios.served(&processing).unwrap();
+    assert!(!scenarios.accepts(&processing).unwrap());
+    // `almost` immediately chains into `done`
+    assert_eq!(scenarios.state("job").unwrap().state, "done");
+  }
+
+  #[test]
+  fn explicit_next() {
+    let scenarios = Scenarios::default();
+    let login = RouteScenario {
+      name: "auth".to_string(),
+      state: None,
+      next: Some("logged-in".to_string()),
+    };
+    assert_eq!(scenarios.state("auth").unwrap().state, SCENARIO_STARTED);
+    scenarios.served(&login).unwrap();
+    assert_eq!(scenarios.state("auth").unwrap().state, "logged-in");
+    scenarios.reset().unwrap();
+    assert_eq!(scenarios.state("auth").unwrap().state, SCENARIO_STARTED);
+  }
+}
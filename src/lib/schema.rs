@@ -0,0 +1,383 @@
+use std::{
+  collections::{BTreeMap, BTreeSet, HashMap},
+  fmt::Display,
+};
+
+use crate::Value;
+
+fn type_matches(expected: &str, value: &Value) -> bool {
+  match expected {
+    "integer" => match value {
+      Value::Integer(_) | Value::Unsigned(_) => true,
+      Value::Float(f) => f.fract() == 0.0,
+      _ => false,
+    },
+    expected => value.type_name() == expected,
+  }
+}
+
+/// Collect the ways `value` violates `schema`, a subset of JSON Schema:
+/// `type` (a name or a list of names), `enum`, `properties`, `required`,
+/// `additionalProperties: false`, `items`, `minItems` and `maxItems`.
+pub fn schema_errors(path: &str, schema: &Value, value: &Value, errors: &mut Vec<String>) {
+  let schema = match schema {
+    Value::Map(schema) => schema,
+    _ => return,
+  };
+  if let Some(expected) = schema.get("type") {
+    let names = match expected {
+      Value::Array(names) => names.iter().map(|n| n.to_string()).collect(),
+      name => vec![name.to_string()],
+    };
+    if !names.iter().any(|name| type_matches(name, value)) {
+      errors.push(format!(
+        "`{}` is {} but should be {}",
+        path,
+        value.type_name(),
+        names.join(" or ")
+      ));
+      return;
+    }
+  }
+  if let Some(Value::Array(allowed)) = schema.get("enum") {
+    if !allowed.iter().any(|v| v.loose_eq(value)) {
+      errors.push(format!("`{}` is {}, which is not allowed", path, value));
+    }
+  }
+  match value {
+    Value::Map(map) => {
+      if let Some(Value::Array(required)) = schema.get("required") {
+        for key in required {
+          if !map.contains_key(&key.to_string()) {
+            errors.push(format!("`{}.{}` is missing", path, key));
+          }
+        }
+      }
+      let properties = match schema.get("properties") {
+        Some(Value::Map(properties)) => Some(properties),
+        _ => None,
+      };
+      let closed = matches!(schema.get("additionalProperties"), Some(Value::Bool(false)));
+      let mut keys = map.keys().collect::<Vec<_>>();
+      keys.sort();
+      for key in keys {
+        let sub = format!("{}.{}", path, key);
+        match properties.and_then(|p| p.get(key)) {
+          Some(property) => schema_errors(&sub, property, &map[key], errors),
+          None if closed => errors.push(format!("`{}` is not expected", sub)),
+          None => {}
+        }
+      }
+    }
+    Value::Array(items) => {
+      let bound = |key: &str| match schema.get(key) {
+        Some(Value::Integer(n)) => Some(*n as usize),
+        Some(Value::Unsigned(n)) => Some(*n as usize),
+        _ => None,
+      };
+      if bound("minItems").is_some_and(|min| items.len() < min) {
+        errors.push(format!("`{}` has too few items ({})", path, items.len()));
+      }
+      if bound("maxItems").is_some_and(|max| items.len() > max) {
+        errors.push(format!("`{}` has too many items ({})", path, items.len()));
+      }
+      if let Some(item_schema) = schema.get("items") {
+        for (i, item) in items.iter().enumerate() {
+          schema_errors(&format!("{}.{}", path, i), item_schema, item, errors);
+        }
+      }
+    }
+    _ => {}
+  }
+}
+
+impl Value {
+  /// JSON Schema describing the shape of this value: `type`, `properties`
+  /// and `required` for objects, `items` for arrays. Array items are merged,
+  /// so a field missing from some items is not required and one with varying
+  /// types lists them all.
+  pub fn infer_schema(&self) -> Value {
+    let mut schema = BTreeMap::new();
+    let kind = match self {
+      Value::Integer(_) | Value::Unsigned(_) => "integer",
+      value => value.type_name(),
+    };
+    schema.insert("type".to_string(), Value::from(kind));
+    match self {
+      Value::Map(map) => {
+        let properties = map
+          .iter()
+          .map(|(k, v)| (k.clone(), v.infer_schema()))
+          .collect::<BTreeMap<_, _>>();
+        let mut required = map.keys().cloned().map(Value::from).collect::<Vec<_>>();
+        required.sort_by_key(|k| k.to_string());
+        schema.insert("properties".to_string(), Value::from(properties));
+        schema.insert("required".to_string(), Value::from(required));
+      }
+      Value::Array(items) => {
+        if let Some(merged) = items
+          .iter()
+          .map(Value::infer_schema)
+          .reduce(|a, b| merge_schemas(&a, &b))
+        {
+          schema.insert("items".to_string(), merged);
+        }
+      }
+      _ => {}
+    }
+    Value::from(schema)
+  }
+}
+
+/// Type names a schema allows
+fn schema_types(schema: &Value) -> BTreeSet<String> {
+  match schema.get_path("type") {
+    Some(Value::Array(names)) => names.iter().map(|n| n.to_string()).collect(),
+    Some(name) => BTreeSet::from([name.to_string()]),
+    None => BTreeSet::new(),
+  }
+}
+
+/// Schema accepting what either `a` or `b` does
+fn merge_schemas(a: &Value, b: &Value) -> Value {
+  let (a, b) = match (a, b) {
+    (Value::Map(a), Value::Map(b)) => (a, b),
+    _ => return a.clone(),
+  };
+  let mut merged = a.clone().into_iter().collect::<BTreeMap<_, _>>();
+  let types = schema_types(&Value::Map(a.clone()))
+    .union(&schema_types(&Value::Map(b.clone())))
+    .cloned()
+    .collect::<Vec<_>>();
+  let types = match types.as_slice() {
+    [name] => Value::from(name.clone()),
+    names => Value::from(names.iter().cloned().map(Value::from).collect::<Vec<_>>()),
+  };
+  merged.insert("type".to_string(), types);
+  if let (Some(Value::Map(pa)), Some(Value::Map(pb))) = (a.get("properties"), b.get("properties")) {
+    let mut properties = pa.clone().into_iter().collect::<BTreeMap<_, _>>();
+    for (key, schema) in pb {
+      let schema = match properties.get(key) {
+        Some(existing) => merge_schemas(existing, schema),
+        None => schema.clone(),
+      };
+      properties.insert(key.clone(), schema);
+    }
+    merged.insert("properties".to_string(), Value::from(properties));
+  } else if let Some(properties) = b.get("properties") {
+    merged.insert("properties".to_string(), properties.clone());
+  }
+  let required = |schema: &HashMap<String, Value>| match schema.get("required") {
+    Some(Value::Array(keys)) => Some(keys.iter().map(|k| k.to_string()).collect::<BTreeSet<_>>()),
+    _ => None,
+  };
+  match (required(a), required(b)) {
+    (Some(ra), Some(rb)) => {
+      let both = ra
+        .intersection(&rb)
+        .cloned()
+        .map(Value::from)
+        .collect::<Vec<_>>();
+      merged.insert("required".to_string(), Value::from(both));
+    }
+    _ => {
+      merged.remove("required");
+    }
+  }
+  match (a.get("items"), b.get("items")) {
+    (Some(ia), Some(ib)) => {
+      merged.insert("items".to_string(), merge_schemas(ia, ib));
+    }
+    (None, Some(ib)) => {
+      merged.insert("items".to_string(), ib.clone());
+    }
+    _ => {}
+  }
+  Value::from(merged)
+}
+
+/// A difference between two schemas, at a dotted path where array items
+/// are `0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaChange {
+  /// Only described by the second schema
+  Added(String),
+  /// Only described by the first schema
+  Removed(String),
+  TypeChanged {
+    path: String,
+    from: String,
+    to: String,
+  },
+}
+
+impl Display for SchemaChange {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      SchemaChange::Added(path) => write!(f, "`{}` was added", path),
+      SchemaChange::Removed(path) => write!(f, "`{}` was removed", path),
+      SchemaChange::TypeChanged { path, from, to } => {
+        write!(f, "`{}` changed from {} to {}", path, from, to)
+      }
+    }
+  }
+}
+
+/// Fields added, removed or whose type changed from schema `a` to `b`, below
+/// `path`. `null` is compatible with any type, as are integers with numbers;
+/// a schema leaving `type` or `properties` out does not constrain them.
+pub fn schema_diff(path: &str, a: &Value, b: &Value) -> Vec<SchemaChange> {
+  let mut changes = vec![];
+  diff_into(path, a, b, &mut changes);
+  changes
+}
+
+fn diff_into(path: &str, a: &Value, b: &Value, changes: &mut Vec<SchemaChange>) {
+  let normalized = |schema: &Value| {
+    schema_types(schema)
+      .into_iter()
+      .filter(|t| t != "null")
+      .map(|t| match t.as_str() {
+        "integer" => "number".to_string(),
+        _ => t,
+      })
+      .collect::<BTreeSet<_>>()
+  };
+  let (ta, tb) = (normalized(a), normalized(b));
+  if !ta.is_empty() && !tb.is_empty() && ta != tb {
+    let join = |types: BTreeSet<String>| types.into_iter().collect::<Vec<_>>().join(" or ");
+    changes.push(SchemaChange::TypeChanged {
+      path: path.to_string(),
+      from: join(ta),
+      to: join(tb),
+    });
+    return;
+  }
+  let properties = |schema: &Value| match schema.get_path("properties") {
+    Some(Value::Map(properties)) => Some(properties.clone()),
+    _ => None,
+  };
+  let (pa, pb) = match (properties(a), properties(b)) {
+    (Some(pa), Some(pb)) => (pa, pb),
+    _ => (HashMap::new(), HashMap::new()),
+  };
+  for key in pa.keys().chain(pb.keys()).collect::<BTreeSet<_>>() {
+    let sub = format!("{}.{}", path, key);
+    match (pa.get(key), pb.get(key)) {
+      (Some(a), Some(b)) => diff_into(&sub, a, b, changes),
+      (Some(_), None) => changes.push(SchemaChange::Removed(sub)),
+      (None, Some(_)) => changes.push(SchemaChange::Added(sub)),
+      (None, None) => {}
+    }
+  }
+  if let (Some(ia), Some(ib)) = (a.get_path("items"), b.get_path("items")) {
+    diff_into(&format!("{}.0", path), ia, ib, changes);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use crate::Value;
+
+  use super::{schema_diff, schema_errors, SchemaChange};
+
+  fn map<const N: usize>(entries: [(&str, Value); N]) -> Value {
+    Value::from(
+      entries
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect::<HashMap<_, _>>(),
+    )
+  }
+
+  #[test]
+  fn errors() {
+    let schema = map([
+      ("type", "array".into()),
+      (
+        "items",
+        map([
+          ("type", "object".into()),
+          ("required", Value::from(vec!["id".into(), "name".into()])),
+          (
+            "properties",
+            map([
+              ("id", map([("type", "integer".into())])),
+              (
+                "role",
+                map([("enum", Value::from(vec!["admin".into(), "user".into()]))]),
+              ),
+            ]),
+          ),
+        ]),
+      ),
+    ]);
+    let users = Value::from(vec![
+      map([
+        ("id", 1.into()),
+        ("name", "Joe".into()),
+        ("role", "admin".into()),
+      ]),
+      map([("id", "2".into()), ("role", "root".into())]),
+    ]);
+    let mut errors = vec![];
+    schema_errors("body", &schema, &users, &mut errors);
+    assert_eq!(
+      errors,
+      vec![
+        "`body.1.name` is missing",
+        "`body.1.id` is string but should be integer",
+        "`body.1.role` is root, which is not allowed",
+      ]
+    );
+  }
+
+  #[test]
+  fn infer() {
+    let users = Value::from(vec![
+      map([("id", 1.into()), ("name", "Joe".into())]),
+      map([("id", 2.5.into()), ("email", "a@b.c".into())]),
+    ]);
+    let schema = users.infer_schema();
+    let mut errors = vec![];
+    schema_errors("body", &schema, &users, &mut errors);
+    assert!(errors.is_empty(), "{:?}", errors);
+    let get = |path: &str| schema.get_path(path).cloned().unwrap_or_default();
+    assert_eq!(get("type"), Value::from("array"));
+    assert_eq!(get("items.required"), Value::from(vec!["id".into()]));
+    assert_eq!(
+      get("items.properties.id.type"),
+      Value::from(vec!["integer".into(), "number".into()])
+    );
+    assert_eq!(get("items.properties.email.type"), Value::from("string"));
+
+    let before = map([
+      ("id", 1.into()),
+      ("name", "Joe".into()),
+      ("tags", Value::Null),
+    ])
+    .infer_schema();
+    let after = map([
+      ("id", "1".into()),
+      ("tags", Value::from(vec!["a".into()])),
+      ("role", "admin".into()),
+    ])
+    .infer_schema();
+    let changes = schema_diff("body", &before, &after);
+    assert_eq!(
+      changes,
+      vec![
+        SchemaChange::TypeChanged {
+          path: "body.id".to_string(),
+          from: "number".to_string(),
+          to: "string".to_string(),
+        },
+        SchemaChange::Removed("body.name".to_string()),
+        SchemaChange::Added("body.role".to_string()),
+      ]
+    );
+    assert_eq!(changes[1].to_string(), "`body.name` was removed");
+  }
+}
@@ -0,0 +1,531 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  rc::Rc,
+  sync::{
+    mpsc::{channel, Receiver, Sender},
+    Mutex,
+  },
+  thread,
+  time::SystemTime,
+};
+
+use boa_engine::{
+  builtins::promise::PromiseState, module::SimpleModuleLoader, object::builtins::JsPromise,
+  Context, JsError, JsNativeError, JsObject, JsResult, JsString, JsValue, Module, NativeFunction,
+  Source,
+};
+use boa_gc::{Finalize, Trace};
+use log::{debug, info};
+use regex::Regex;
+
+use crate::{
+  Error, ErrorKind, FetchPolicy, MatchedRoute, Request, Response, Status, Value, WorkspaceVars,
+};
+
+type Reply = Result<serde_json::Value, String>;
+type Job = (serde_json::Value, Sender<Reply>);
+
+/// Extensions of the files a script may import or require
+const SCRIPT_EXTENSIONS: [&str; 3] = ["js", "mjs", "cjs"];
+
+/// Script files of `dir` along with their modification times, any change
+/// meaning scripts loaded from there must be reloaded
+fn fingerprint(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>, u64)> {
+  let mut ret = fs::read_dir(dir)
+    .map(|entries| {
+      entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| {
+          p.extension()
+            .is_some_and(|ext| SCRIPT_EXTENSIONS.iter().any(|e| ext == *e))
+        })
+        .map(|p| {
+          let meta = fs::metadata(&p).ok();
+          let modified = meta.as_ref().and_then(|m| m.modified().ok());
+          let len = meta.map(|m| m.len()).unwrap_or_default();
+          (p, modified, len)
+        })
+        .collect::<Vec<_>>()
+    })
+    .unwrap_or_default();
+  ret.sort();
+  ret
+}
+
+/// Whether `source` is an ES module rather than a classic script
+fn is_module(source: &str) -> bool {
+  Regex::new(r"(?m)^\s*(import|export)\b").is_ok_and(|re| re.is_match(source))
+}
+
+/// `__mocker_read(path)`, backing `require`
+fn read_file(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+  let path = args
+    .first()
+    .cloned()
+    .unwrap_or_default()
+    .to_string(context)?
+    .to_std_string_escaped();
+  let source = fs::read_to_string(&path).map_err(|e| {
+    JsNativeError::error().with_message(format!("cannot require '{}': {}", path, e))
+  })?;
+  Ok(JsValue::from(JsString::from(source)))
+}
+
+/// Servers a script handler may `fetch` from, calls failing when `None`
+#[derive(Trace, Finalize)]
+struct Fetcher(#[unsafe_ignore_trace] Option<FetchPolicy>);
+
+/// `__mocker_fetch(url, {method, headers, body})`, backing `fetch`
+fn fetch(
+  _this: &JsValue,
+  args: &[JsValue],
+  fetcher: &Fetcher,
+  context: &mut Context,
+) -> JsResult<JsValue> {
+  let arg = |i: usize| args.get(i).cloned().unwrap_or_default();
+  let url = arg(0).to_string(context)?.to_std_string_escaped();
+  let options = match arg(1).is_undefined() {
+    true => serde_json::Value::Null,
+    false => arg(1).to_json(context)?,
+  };
+  let policy = fetcher.0.as_ref().ok_or_else(|| {
+    JsNativeError::error().with_message("fetch is disabled, configure `fetch` to enable it")
+  })?;
+  let res = policy
+    .fetch(&url, &options)
+    .map_err(|e| JsNativeError::error().with_message(format!("fetch({}): {}", url, e)))?;
+  JsValue::from_json(&res.to_value().to_json(), context)
+}
+
+/// `fetch`, blocking but shaped like the browser's so it can be awaited
+const FETCH_PRELUDE: &str = r#"
+function fetch(url, options) {
+  const res = __mocker_fetch(String(url), options || {});
+  res.ok = res.status >= 200 && res.status < 300;
+  res.json = () => typeof res.body === 'string' ? JSON.parse(res.body) : res.body;
+  res.text = () => typeof res.body === 'string' ? res.body : JSON.stringify(res.body);
+  return res;
+}
+"#;
+
+/// CommonJS globals, `require` resolving paths against `dir` and caching
+/// every module it loads
+fn prelude(dir: &Path) -> String {
+  let dir = serde_json::Value::from(dir.display().to_string());
+  format!(
+    r#"
+var module = {{ exports: {{}} }};
+var exports = module.exports;
+var require = (function () {{
+  const dir = {dir};
+  const cache = {{}};
+  return function require(name) {{
+    let path = name.startsWith('/') ? name : dir + '/' + name;
+    if (!/\.[cm]?js$/.test(path)) path += '.js';
+    if (!(path in cache)) {{
+      const module = {{ exports: {{}} }};
+      cache[path] = module;
+      new Function('module', 'exports', 'require', __mocker_read(path))(module, module.exports, require);
+    }}
+    return cache[path].exports;
+  }};
+}})();
+"#
+  )
+}
+
+/// Message of a JavaScript error, prefixed with the file it comes from
+fn js_error(path: &Path, e: JsError) -> String {
+  format!("{}: {}", path.display(), e)
+}
+
+/// Wait for `value` to settle if it is a promise
+pub(crate) fn settle(context: &mut Context, value: JsValue) -> Result<JsValue, String> {
+  let promise = match value.as_promise() {
+    Some(promise) => JsPromise::from_object(promise.clone()).map_err(|e| e.to_string())?,
+    None => return Ok(value),
+  };
+  context.run_jobs();
+  match promise.state() {
+    PromiseState::Fulfilled(value) => Ok(value),
+    PromiseState::Rejected(e) => Err(JsError::from_opaque(e).to_string()),
+    PromiseState::Pending => Err("promise never settled".to_string()),
+  }
+}
+
+/// A script file evaluated in a context of its own, along with the modules
+/// it imported or required
+pub(crate) struct ScriptFile {
+  path: PathBuf,
+  context: Context,
+  module: Option<Module>,
+}
+
+impl ScriptFile {
+  /// Evaluate `path` as an ES module or a classic script, once `globals`
+  /// defined whatever else it expects
+  pub(crate) fn load<G: FnOnce(&mut Context) -> JsResult<()>>(
+    path: &Path,
+    dir: &Path,
+    globals: G,
+  ) -> Result<Self, String> {
+    let err = |e| js_error(path, e);
+    let loader = Rc::new(SimpleModuleLoader::new(dir).map_err(err)?);
+    let mut context = Context::builder()
+      .module_loader(loader.clone())
+      .build()
+      .map_err(err)?;
+    context
+      .register_global_callable(
+        JsString::from("__mocker_read"),
+        1,
+        NativeFunction::from_fn_ptr(read_file),
+      )
+      .map_err(err)?;
+    context
+      .eval(Source::from_bytes(&prelude(dir)))
+      .map_err(err)?;
+    globals(&mut context).map_err(err)?;
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let module = match is_module(&source) {
+      true => {
+        let module = Module::parse(Source::from_bytes(&source), None, &mut context).map_err(err)?;
+        if let Ok(path) = path.canonicalize() {
+          loader.insert(path, module.clone());
+        }
+        let promise = module.load_link_evaluate(&mut context);
+        context.run_jobs();
+        if let PromiseState::Rejected(e) = promise.state() {
+          return Err(err(JsError::from_opaque(e)));
+        }
+        Some(module)
+      }
+      false => {
+        context.eval(Source::from_bytes(&source)).map_err(err)?;
+        None
+      }
+    };
+    Ok(Self {
+      path: path.to_path_buf(),
+      context,
+      module,
+    })
+  }
+
+  pub(crate) fn context(&mut self) -> &mut Context {
+    &mut self.context
+  }
+
+  /// What the file exports as `name`, or defines globally for classic scripts
+  pub(crate) fn get(&mut self, name: &str) -> Result<JsValue, String> {
+    let err = |e| js_error(&self.path, e);
+    let context = &mut self.context;
+    let key = JsString::from(name);
+    if let Some(module) = &self.module {
+      return module.namespace(context).get(key, context).map_err(err);
+    }
+    let global = context.global_object();
+    let value = global.get(key.clone(), context).map_err(err)?;
+    if !value.is_undefined() {
+      return Ok(value);
+    }
+    let exports = global
+      .get(JsString::from("module"), context)
+      .and_then(|m| match m.as_object() {
+        Some(m) => m.get(JsString::from("exports"), context),
+        None => Ok(JsValue::undefined()),
+      })
+      .map_err(err)?;
+    match exports.as_object() {
+      Some(exports) => exports.get(key, context).map_err(err),
+      None => Ok(JsValue::undefined()),
+    }
+  }
+}
+
+/// A script function, loaded in its own context
+struct Loaded {
+  file: ScriptFile,
+  func: JsObject,
+  fingerprint: Vec<(PathBuf, Option<SystemTime>, u64)>,
+}
+
+impl Loaded {
+  fn new(path: &Path, func: &str, dir: &Path, policy: Option<FetchPolicy>) -> Result<Self, String> {
+    let fingerprint = fingerprint(dir);
+    let mut file = ScriptFile::load(path, dir, |context| {
+      context.register_global_callable(
+        JsString::from("__mocker_fetch"),
+        2,
+        NativeFunction::from_copy_closure_with_captures(fetch, Fetcher(policy)),
+      )?;
+      context.eval(Source::from_bytes(FETCH_PRELUDE))?;
+      Ok(())
+    })?;
+    let func = file
+      .get(func)?
+      .as_callable()
+      .cloned()
+      .ok_or_else(|| format!("{} does not define function `{}`", path.display(), func))?;
+    Ok(Self {
+      file,
+      func,
+      fingerprint,
+    })
+  }
+
+  fn call(&mut self, input: &serde_json::Value) -> Reply {
+    let context = self.file.context();
+    let err = |e: JsError| e.to_string();
+    let arg = JsValue::from_json(input, context).map_err(err)?;
+    let ret = self
+      .func
+      .call(&JsValue::undefined(), &[arg], context)
+      .map_err(err)?;
+    let ret = settle(context, ret)?;
+    match ret.is_undefined() {
+      true => Ok(serde_json::Value::Null),
+      false => ret.to_json(context).map_err(err),
+    }
+  }
+}
+
+/// Serve jobs until every sender is gone, reloading the script whenever a
+/// file next to it changes
+fn work(path: PathBuf, func: String, policy: Option<FetchPolicy>, jobs: Receiver<Job>) {
+  let dir = path
+    .parent()
+    .filter(|p| !p.as_os_str().is_empty())
+    .unwrap_or(Path::new("."))
+    .to_path_buf();
+  let mut loaded: Option<Loaded> = None;
+  for (input, reply) in jobs {
+    let stale = loaded
+      .as_ref()
+      .is_none_or(|l| l.fingerprint != fingerprint(&dir));
+    if stale {
+      if loaded.is_some() {
+        info!("Reloading script {}", path.display());
+      }
+      loaded = match Loaded::new(&path, &func, &dir, policy.clone()) {
+        Ok(l) => Some(l),
+        Err(e) => {
+          let _ = reply.send(Err(e));
+          continue;
+        }
+      };
+    }
+    let ret = match loaded.as_mut() {
+      Some(loaded) => loaded.call(&input),
+      None => Err("script not loaded".to_string()),
+    };
+    let _ = reply.send(ret);
+  }
+  debug!("Script worker for {} stopped", path.display());
+}
+
+/// A function of a JavaScript file, called on a dedicated thread owning its
+/// context. Sibling files can be pulled in with `import` (ES modules) or
+/// `require` (CommonJS), and everything is reloaded when any of them changes.
+pub struct Script {
+  path: PathBuf,
+  func: String,
+  fetch: Option<FetchPolicy>,
+  jobs: Mutex<Option<Sender<Job>>>,
+}
+
+impl Script {
+  pub fn new<P: AsRef<Path>, F: AsRef<str>>(path: P, func: F) -> Self {
+    Self {
+      path: path.as_ref().to_path_buf(),
+      func: func.as_ref().to_string(),
+      fetch: None,
+      jobs: Mutex::new(None),
+    }
+  }
+
+  /// Let the function call the servers `policy` allows with `fetch`
+  pub fn with_fetch(mut self, policy: Option<FetchPolicy>) -> Self {
+    self.fetch = policy;
+    self
+  }
+
+  pub fn path(&self) -> &PathBuf {
+    &self.path
+  }
+
+  /// Call the function with `input`, returning what it returned (or resolved to)
+  pub fn call(&self, input: serde_json::Value) -> crate::Result<serde_json::Value> {
+    let (tx, rx) = channel();
+    let mut job = (input, tx);
+    let mut jobs = self.jobs.lock()?;
+    for _ in 0..2 {
+      let sender = jobs.get_or_insert_with(|| {
+        let (tx, rx) = channel();
+        let (path, func, policy) = (self.path.clone(), self.func.clone(), self.fetch.clone());
+        thread::spawn(move || work(path, func, policy, rx));
+        tx
+      });
+      match sender.send(job) {
+        Ok(()) => break,
+        Err(e) => {
+          // the worker is gone, start another one
+          job = e.0;
+          *jobs = None;
+        }
+      }
+    }
+    drop(jobs);
+    rx.recv()
+      .map_err(|_| {
+        Error::new(
+          ErrorKind::Sync,
+          Some(format!("script worker for {} died", self.path.display())),
+          None,
+        )
+      })?
+      .map_err(|e| {
+        Error::new(
+          ErrorKind::Api(Status::InternalServerError),
+          Some(format!("`{}` failed: {}", self.func, e)),
+          None,
+        )
+      })
+  }
+
+  /// Answer `req` with the function's result: either `{status, headers, body}`,
+  /// `body` giving way to the content of `file` (downloaded as `filename` if
+  /// set), or any other value, sent as a JSON body
+  pub fn handle(&self, req: &Request, res: Response) -> crate::Result<Response> {
+    let mut input = req.to_value();
+    if let Value::Map(map) = &mut input {
+      if let Some(matched) = req.extensions().get::<MatchedRoute>() {
+        map.insert("route".to_string(), matched.to_value(req));
+      }
+      map.insert("vars".to_string(), WorkspaceVars::of(req));
+    }
+    let ret = self.call(input.to_json())?;
+    let is_spec = ret.as_object().is_some_and(|o| {
+      !o.is_empty()
+        && o
+          .keys()
+          .all(|k| ["status", "headers", "body", "file", "filename"].contains(&k.as_str()))
+    });
+    let (file, filename) = match is_spec {
+      true => (
+        ret.get("file").and_then(|f| f.as_str()).map(String::from),
+        ret
+          .get("filename")
+          .and_then(|f| f.as_str())
+          .map(String::from),
+      ),
+      false => (None, None),
+    };
+    let (status, headers, body) = match is_spec {
+      true => (
+        ret.get("status").and_then(|s| s.as_u64()).unwrap_or(200) as u16,
+        ret.get("headers").and_then(|h| h.as_object()).cloned(),
+        ret.get("body").cloned().unwrap_or_default(),
+      ),
+      false => (200, None, ret),
+    };
+    let mut res = match (file, body) {
+      (Some(file), _) => {
+        let res = Response::file(file)?;
+        match filename {
+          Some(filename) => res.with_attachment(filename),
+          None => res,
+        }
+      }
+      (None, serde_json::Value::Null) => res,
+      (None, serde_json::Value::String(body)) => res
+        .with_header("Content-Type", "text/plain; charset=utf-8")
+        .with_body(body),
+      (None, body) => res
+        .with_header("Content-Type", "application/json")
+        .with_body(serde_json::to_string(&body)?),
+    };
+    res = res.with_status_code(status);
+    for (key, value) in headers.unwrap_or_default() {
+      match value {
+        serde_json::Value::String(value) => res.set_header(key, value),
+        value => res.set_header(key, value.to_string()),
+      }
+    }
+    Ok(res)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{fs, thread, time::Duration};
+
+  use crate::{Method, Request, Response};
+
+  use super::Script;
+
+  #[test]
+  fn modules_and_reload() {
+    let dir = std::env::temp_dir().join(format!("mocker-script-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+      dir.join("greet.mjs"),
+      "export const greet = (n) => `hello ${n}`;",
+    )
+    .unwrap();
+    fs::write(
+      dir.join("upper.js"),
+      "exports.upper = (s) => s.toUpperCase();",
+    )
+    .unwrap();
+    fs::write(
+      dir.join("handler.mjs"),
+      r#"import { greet } from "./greet.mjs";
+const { upper } = require("./upper");
+export function handle(req) {
+  return { status: 201, headers: { "X-Path": req.path }, body: { message: upper(greet(req.query.name)) } };
+}"#,
+    )
+    .unwrap();
+    fs::write(
+      dir.join("plain.js"),
+      "function handle(req) { return req.method; }",
+    )
+    .unwrap();
+
+    let script = Script::new(dir.join("handler.mjs"), "handle");
+    let res = script
+      .handle(
+        &Request::new(Method::Get, "/hi?name=joe"),
+        Response::default(),
+      )
+      .unwrap();
+    assert_eq!(res.status(), 201);
+    assert_eq!(res.header("X-Path").unwrap(), "/hi");
+    assert_eq!(&res.body()[..], br#"{"message":"HELLO JOE"}"#);
+
+    // make sure the modification time moves on coarse filesystems
+    thread::sleep(Duration::from_millis(20));
+    fs::write(
+      dir.join("upper.js"),
+      "exports.upper = (s) => s.toLowerCase();",
+    )
+    .unwrap();
+    let ret = script
+      .call(serde_json::json!({"path": "/", "query": {"name": "JOE"}}))
+      .unwrap();
+    assert_eq!(ret["body"]["message"], "hello joe");
+
+    let plain = Script::new(dir.join("plain.js"), "handle");
+    assert_eq!(
+      plain.call(serde_json::json!({"method": "PUT"})).unwrap(),
+      "PUT"
+    );
+    assert!(Script::new(dir.join("plain.js"), "missing")
+      .call(serde_json::Value::Null)
+      .is_err());
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
@@ -1,35 +1,58 @@
 use std::{
   collections::VecDeque,
-  io::{stdout, Read, Write},
-  net::{IpAddr, Shutdown, TcpListener, TcpStream},
-  sync::{Arc, Mutex},
+  io::{stdout, Write},
+  net::{Shutdown, TcpListener, TcpStream},
+  sync::{
+    mpsc::{Receiver, RecvTimeoutError},
+    Arc,
+  },
   thread,
   time::Duration,
 };
 
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 
-use crate::{Buffer, Config, Middleware, Middlewares, Request, Response, Router, Table};
+use crate::{
+  smtp_session, Config, Engine, Explanation, Journal, Middleware, Proxy, Request, Response,
+  RouteOptions, Router, Sequencer, SmtpConfig, Status, Table,
+};
 
 #[derive(Default)]
 pub struct Server {
   config: Config,
-  router: Arc<Router>,
-  middlewares: Vec<Arc<Mutex<dyn Middleware>>>,
+  engine: Engine,
 }
 
 impl Server {
-  pub fn new(config: Config) -> Self {
-    Self {
-      config: config.clone(),
-      router: Arc::new(Router::default().with_routes(config.routes)),
-      middlewares: Vec::new(),
-    }
+  const EVENTS_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+  pub fn new(config: Config) -> crate::Result<Self> {
+    Ok(Self {
+      engine: Engine::new(&config)?,
+      config,
+    })
+  }
+
+  pub fn engine(&self) -> &Engine {
+    &self.engine
+  }
+
+  pub fn router(&self) -> &Arc<Router> {
+    self.engine.router()
+  }
+
+  pub fn journal(&self) -> &Arc<Journal> {
+    self.engine.journal()
+  }
+
+  /// Check that every route expectation is met, listing the actual invocations otherwise
+  pub fn verify(&self) -> crate::Result<()> {
+    self.router().verify()
   }
 
   pub fn with_middleware<M: Middleware + 'static>(mut self, m: M) -> Self {
-    self.config.middlewares.push(m.name().clone());
-    self.middlewares.push(Arc::new(Mutex::new(m)));
+    self.config.middlewares.push(m.name().clone().into());
+    self.engine = self.engine.with_middleware(m);
     self
   }
 
@@ -51,8 +74,8 @@ impl Server {
   pub fn banner<W: Write>(&self, mut w: W) -> crate::Result<()> {
     writeln!(
       w,
-      "🚀 Server running at \x1b[4m{}://{}:{}\x1b[0m\n",
-      "http", self.config.host, self.config.port
+      "🚀 Server running at \x1b[4mhttp://{}:{}\x1b[0m\n",
+      self.config.host, self.config.port
     )?;
     writeln!(
       w,
@@ -81,19 +104,34 @@ impl Server {
   }
 
   pub fn listen(mut self) -> crate::Result<()> {
-    self = self.init_middlewares()?;
+    self.engine = self.engine.with_config_features(&self.config)?;
+    crate::run_startup_hooks(&self.config.startup)?;
     self.banner(stdout())?;
+    if let Some(smtp) = &self.config.smtp {
+      self.listen_smtp(smtp)?;
+    }
+    for listener in &self.config.listeners {
+      listener.spawn(self.config.host)?;
+    }
+    if let Some(proxy) = &self.config.proxy {
+      Proxy::new(proxy.clone(), self.engine.clone())?.spawn(self.config.host)?;
+    }
+    let sequencer = match &self.config.ordering {
+      Some(ordering) => Some(Arc::new(Sequencer::new(ordering.clone())?)),
+      None => None,
+    };
+    let strict = self.config.compliance.is_some();
     let listener = TcpListener::bind(format!("{}:{}", self.config.host, self.config.port)).unwrap();
     let mut handles = VecDeque::new();
     for stream in listener.incoming() {
-      let mut stream = stream.unwrap();
-      let middlewares = self.middlewares.clone();
-      let router = self.router.clone();
+      let stream = stream.unwrap();
+      let engine = self.engine.clone();
+      let sequencer = sequencer.clone();
       handles.push_back(thread::spawn(move || {
-        if let Err(e) = Self::handle_request(&mut stream, &router, &middlewares) {
+        if let Err(e) = Self::handle_request(&stream, &engine, sequencer.as_ref(), strict) {
           error!("Handler crashed: {}", &e);
           let res: Response = e.into();
-          if let Err(we) = res.write_to(&stream) {
+          if let Err(we) = Self::write_response(&res, &stream, strict) {
             error!("Failed to write response: {}", we);
           }
         }
@@ -105,70 +143,185 @@ impl Server {
     Ok(())
   }
 
-  fn execute_middleware(
-    request: &Request,
-    mut response: Response,
-    middleware: &Arc<Mutex<dyn Middleware>>,
-  ) -> crate::Result<Response> {
-    let mut m = None;
+  /// Accept emails in the background, storing them in the engine's mailbox
+  fn listen_smtp(&self, smtp: &SmtpConfig) -> crate::Result<()> {
+    let addr = format!("{}:{}", smtp.host.unwrap_or(self.config.host), smtp.port);
+    let listener = TcpListener::bind(&addr)?;
+    info!("📧 Capturing emails sent to smtp://{}", addr);
+    let mailbox = self.engine.mailbox().clone();
+    thread::spawn(move || {
+      for stream in listener.incoming() {
+        let stream = match stream {
+          Ok(stream) => stream,
+          Err(e) => {
+            warn!("SMTP connection failed: {}", e);
+            continue;
+          }
+        };
+        let mailbox = mailbox.clone();
+        thread::spawn(move || {
+          if let Err(e) = smtp_session(&stream, &mailbox) {
+            error!("SMTP session crashed: {}", e);
+          }
+        });
+      }
+    });
+    Ok(())
+  }
+
+  /// Keep the connection open, forwarding every event until the client leaves
+  fn stream_events(mut stream: &TcpStream, events: Receiver<String>) -> crate::Result<Response> {
+    let res = Response::default()
+      .with_status(Status::OK)
+      .with_header("Content-Type", "text/event-stream")
+      .with_header("Cache-Control", "no-cache");
+    res.write_to(stream)?;
+    writeln!(stream)?;
+    stream.flush()?;
     loop {
-      match middleware.try_lock() {
-        Ok(g) => {
-          debug!("Executing middleware: {}", g.name());
-          m = Some(g);
-          break;
-        }
-        Err(e) => {
-          error!("Failed to lock middleware: {}", e);
-          thread::sleep(Duration::from_millis(10));
-        }
+      let sent = match events.recv_timeout(Self::EVENTS_KEEP_ALIVE) {
+        Ok(data) => write!(stream, "data: {}\n\n", data),
+        Err(RecvTimeoutError::Timeout) => write!(stream, ": keep-alive\n\n"),
+        Err(RecvTimeoutError::Disconnected) => break,
+      };
+      if sent.and_then(|_| stream.flush()).is_err() {
+        debug!("Event stream closed by peer");
+        break;
       }
     }
-    response = m.unwrap().execute(request, response)?;
-    Ok(response)
+    Ok(res)
+  }
+
+  /// Write `res` to `w`, as RFC 9112 requires it when `strict`
+  fn write_response<W: Write>(res: &Response, w: W, strict: bool) -> crate::Result<()> {
+    match strict {
+      true => res.write_strict_to(w),
+      false => res.write_to(w),
+    }
   }
 
   fn handle_request(
     mut stream: &TcpStream,
-    router: &Router,
-    middlewares: &Vec<Arc<Mutex<dyn Middleware>>>,
+    engine: &Engine,
+    sequencer: Option<&Arc<Sequencer>>,
+    strict: bool,
   ) -> crate::Result<Response> {
-    info!("Connection accepted from '{}'", stream.peer_addr()?);
-    let req = Request::from_reader(stream)?;
-    let mut res = Response::default();
-    for middleware in middlewares {
-      res = Self::execute_middleware(&req, res, middleware)?;
+    let peer = stream.peer_addr()?;
+    info!("Connection accepted from '{}'", peer);
+    let mut req = Request::from_reader(stream)?;
+    engine.prepare(&mut req)?;
+    if let Some(events) = engine.admin().events(&req)? {
+      return Self::stream_events(stream, events);
+    }
+    let ticket = sequencer.and_then(|s| s.ticket(&req, Some(peer.ip())));
+    // failures answer in turn too, the ticket being held until written
+    let (res, options) = engine.respond(&req).unwrap_or_else(|e| {
+      error!("Handler crashed: {}", &e);
+      (e.into(), RouteOptions::default())
+    });
+    if let Some(ticket) = &ticket {
+      ticket.wait()?;
+    }
+    debug!("Response: {}", String::from_utf8_lossy(&res.head()).trim());
+    if options.fault.is_some() || options.disorder.is_some() {
+      let mut buf = vec![];
+      Self::write_response(&res, &mut buf, strict)?;
+      if let Some(fault) = options.fault {
+        warn!("Simulating {:?} fault", fault);
+        fault.inject(stream, &buf)?;
+        return Ok(res);
+      }
+      if let Some(disorder) = &options.disorder {
+        disorder.deliver(stream, &buf)?;
+      }
+    } else {
+      Self::write_response(&res, stream, strict)?;
     }
-    res = router.dispatch(&req, res)?;
-    let mut buf = vec![];
-    res.write_to(&mut buf)?;
-    debug!(
-      "Response: {}",
-      unsafe { std::str::from_utf8_unchecked(&buf) }.trim()
-    );
-    stream.write(&buf)?;
     stream.flush()?;
     stream.shutdown(Shutdown::Both)?;
     Ok(res)
   }
 
-  fn init_middlewares(mut self) -> crate::Result<Self> {
-    #[cfg(feature = "cors")]
-    Middlewares::register(String::from(crate::cors::CORS_MW_NAME), || {
-      Ok(Arc::new(Mutex::new(crate::cors::CorsMiddleware::new())))
-    });
-    for mw_name in &self.config.middlewares {
-      let found = self.middlewares.iter().find(|mw| {
-        let g = mw.lock().expect("failed to lock middleware");
-        if g.name().eq_ignore_ascii_case(&mw_name) {
-          return true;
-        }
-        return false;
-      });
-      if found.is_none() {
-        self.middlewares.push(Middlewares::create(&mw_name)?)
-      }
+  /// Route `req` without serving it, after letting middlewares prepare it
+  pub fn explain(self, req: Request) -> crate::Result<Explanation> {
+    self
+      .engine
+      .with_config_middlewares(&self.config)?
+      .explain(req)
+  }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+  use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+  };
+
+  use crate::{Config, Engine, Method, ResponseOrdering, Route, RouteKind, Sequencer};
+
+  use super::Server;
+
+  #[test]
+  fn failures_respond_in_order() {
+    let route = |endpoint: &str, script: &str| {
+      Route::new(
+        vec![Method::Get],
+        endpoint,
+        RouteKind::Exec {
+          command: "/bin/sh".to_string(),
+          args: vec!["-c".to_string(), script.to_string()],
+          status: 200,
+          headers: BTreeMap::new(),
+          timeout: Some("5s".to_string()),
+          cwd: None,
+          env: BTreeMap::new(),
+          clear_env: false,
+        },
+      )
+    };
+    let engine = Engine::new(&Config {
+      routes: vec![
+        route("/slow-failure", "sleep 0.2; exit 3"),
+        route("/failure", "exit 3"),
+        route("/success", "echo ok"),
+      ],
+      ..Default::default()
+    })
+    .unwrap();
+    let sequencer = Arc::new(Sequencer::new(ResponseOrdering::default()).unwrap());
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let written = Arc::new(Mutex::new(vec![]));
+    let (mut servers, mut clients) = (vec![], vec![]);
+    for path in ["/slow-failure", "/failure", "/success"] {
+      let mut client = TcpStream::connect(addr).unwrap();
+      write!(client, "GET {} HTTP/1.1\r\nHost: test\r\n\r\n", path).unwrap();
+      let (stream, _) = listener.accept().unwrap();
+      let (engine, sequencer) = (engine.clone(), sequencer.clone());
+      servers.push(thread::spawn(move || {
+        Server::handle_request(&stream, &engine, Some(&sequencer), false).unwrap();
+      }));
+      let written = written.clone();
+      clients.push(thread::spawn(move || {
+        let mut res = String::new();
+        client.read_to_string(&mut res).unwrap();
+        written.lock().unwrap().push((path, res));
+      }));
+      // let the request take its ticket before the next one arrives
+      thread::sleep(Duration::from_millis(50));
+    }
+    for handle in servers.into_iter().chain(clients) {
+      handle.join().unwrap();
     }
-    Ok(self)
+    let written = written.lock().unwrap();
+    let order = written.iter().map(|(path, _)| *path).collect::<Vec<_>>();
+    assert_eq!(order, vec!["/slow-failure", "/failure", "/success"]);
+    assert!(written[0].1.starts_with("HTTP/1.1 502"), "{}", written[0].1);
+    assert!(written[2].1.starts_with("HTTP/1.1 200"), "{}", written[2].1);
   }
 }
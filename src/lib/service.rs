@@ -0,0 +1,114 @@
+use std::{
+  fmt::Display,
+  future::Future,
+  pin::Pin,
+  task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use http_body::Body;
+use http_body_util::{BodyExt, Full};
+use tower_service::Service;
+
+use crate::{Engine, Error, ErrorKind, Request};
+
+/// [`Engine`] adapted to `tower`, to mount the mock in an axum or hyper
+/// application and benefit from their connection handling.
+#[derive(Clone)]
+pub struct MockService {
+  engine: Engine,
+}
+
+impl MockService {
+  pub fn new(engine: Engine) -> Self {
+    Self { engine }
+  }
+
+  pub fn engine(&self) -> &Engine {
+    &self.engine
+  }
+}
+
+impl<B> Service<http::Request<B>> for MockService
+where
+  B: Body + Send + 'static,
+  B::Data: Send,
+  B::Error: Display,
+{
+  type Response = http::Response<Full<Bytes>>;
+  type Error = Error;
+  type Future = Pin<Box<dyn Future<Output = crate::Result<Self::Response>> + Send>>;
+
+  fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<crate::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn call(&mut self, req: http::Request<B>) -> Self::Future {
+    let engine = self.engine.clone();
+    Box::pin(async move {
+      let (parts, body) = req.into_parts();
+      let body = body
+        .collect()
+        .await
+        .map_err(|e| Error::new(ErrorKind::IO, Some(e.to_string()), None))?
+        .to_bytes();
+      let req = Request::try_from(http::Request::from_parts(parts, body.to_vec()))?;
+      let res = http::Response::<Vec<u8>>::try_from(engine.handle(req))?;
+      Ok(res.map(|body| Full::new(Bytes::from(body))))
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{
+    future::Future,
+    pin::pin,
+    task::{Context, Poll, Waker},
+  };
+
+  use bytes::Bytes;
+  use http_body_util::{BodyExt, Full};
+  use tower_service::Service;
+
+  use crate::{Config, Engine, Method, Route, RouteKind, Value};
+
+  use super::MockService;
+
+  fn block_on<F: Future>(f: F) -> F::Output {
+    let mut f = pin!(f);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+      if let Poll::Ready(out) = f.as_mut().poll(&mut cx) {
+        return out;
+      }
+    }
+  }
+
+  #[test]
+  fn call() {
+    let config = Config {
+      routes: vec![Route::new(
+        vec![Method::Post],
+        "/echo",
+        RouteKind::Fixture {
+          status: 201,
+          headers: Default::default(),
+          body: Some(Value::from("{{request.body.name}}")),
+          file: None,
+          template: true,
+        },
+      )],
+      ..Default::default()
+    };
+    let mut service = MockService::new(Engine::from_config(&config).unwrap());
+    let req = http::Request::post("/echo?x=1")
+      .header("Content-Type", "application/json")
+      .body(Full::new(Bytes::from_static(br#"{"name": "Joe"}"#)))
+      .unwrap();
+    let res = block_on(service.call(req)).unwrap();
+    assert_eq!(res.status(), 201);
+    let body = block_on(res.into_body().collect()).unwrap().to_bytes();
+    assert_eq!(body.as_ref(), b"Joe");
+  }
+}
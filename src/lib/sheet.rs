@@ -0,0 +1,244 @@
+use std::{collections::BTreeSet, io::Write, str::FromStr};
+
+use crate::{render_value, Error, ErrorKind, Value};
+
+/// Spreadsheet formats store data can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SheetFormat {
+  Csv,
+  #[cfg(feature = "xlsx")]
+  Xlsx,
+}
+
+impl SheetFormat {
+  pub fn content_type(&self) -> &'static str {
+    match self {
+      SheetFormat::Csv => "text/csv; charset=utf-8",
+      #[cfg(feature = "xlsx")]
+      SheetFormat::Xlsx => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    }
+  }
+}
+
+impl FromStr for SheetFormat {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "csv" => Ok(SheetFormat::Csv),
+      #[cfg(feature = "xlsx")]
+      "xlsx" => Ok(SheetFormat::Xlsx),
+      _ => Err(Error::new(
+        ErrorKind::Parse,
+        Some(format!("unsupported sheet format '{}'", s)),
+        None,
+      )),
+    }
+  }
+}
+
+/// A sheet column: a header and the dotted path of the value it shows
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+  pub header: String,
+  pub path: String,
+}
+
+impl FromStr for Column {
+  type Err = Error;
+
+  /// Parse `Header=path`, or a bare `path` used as its own header
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let (header, path) = s.split_once('=').unwrap_or((s, s));
+    if path.is_empty() {
+      return Err(Error::new(
+        ErrorKind::Parse,
+        Some(format!("invalid column '{}'", s)),
+        None,
+      ));
+    }
+    Ok(Self {
+      header: header.to_string(),
+      path: path.to_string(),
+    })
+  }
+}
+
+fn flatten_into(prefix: &str, value: &Value, paths: &mut BTreeSet<String>) {
+  let join = |key: &str| match prefix.is_empty() {
+    true => key.to_string(),
+    false => format!("{}.{}", prefix, key),
+  };
+  match value {
+    Value::Map(m) if !m.is_empty() => {
+      for (k, v) in m {
+        flatten_into(&join(k), v, paths);
+      }
+    }
+    Value::Array(a) if !a.is_empty() => {
+      for (i, v) in a.iter().enumerate() {
+        flatten_into(&join(&i.to_string()), v, paths);
+      }
+    }
+    _ => {
+      paths.insert(prefix.to_string());
+    }
+  }
+}
+
+/// Store items laid out as rows, nested values flattened to dotted columns.
+#[derive(Debug, Clone)]
+pub struct Sheet {
+  columns: Vec<Column>,
+  rows: Vec<Vec<Value>>,
+}
+
+impl Sheet {
+  /// Lay out `items` with the given `columns`, or with one column per leaf
+  /// value found in the items when none are given.
+  pub fn new(items: &[Value], columns: Vec<Column>) -> Self {
+    let columns = match columns.is_empty() {
+      false => columns,
+      true => {
+        let mut paths = BTreeSet::new();
+        for item in items {
+          flatten_into("", item, &mut paths);
+        }
+        paths
+          .into_iter()
+          .filter(|p| !p.is_empty())
+          .map(|path| Column {
+            header: path.clone(),
+            path,
+          })
+          .collect()
+      }
+    };
+    let rows = items
+      .iter()
+      .map(|item| {
+        columns
+          .iter()
+          .map(|c| item.get_path(&c.path).cloned().unwrap_or_default())
+          .collect()
+      })
+      .collect();
+    Self { columns, rows }
+  }
+
+  pub fn columns(&self) -> &Vec<Column> {
+    &self.columns
+  }
+
+  pub fn rows(&self) -> &Vec<Vec<Value>> {
+    &self.rows
+  }
+
+  fn csv_field(s: &str) -> String {
+    match s.contains([',', '"', '\n', '\r']) {
+      true => format!("\"{}\"", s.replace('"', "\"\"")),
+      false => s.to_string(),
+    }
+  }
+
+  pub fn write_csv<W: Write>(&self, mut w: W) -> crate::Result<()> {
+    let line = |fields: Vec<String>| {
+      fields
+        .iter()
+        .map(|f| Self::csv_field(f))
+        .collect::<Vec<_>>()
+        .join(",")
+    };
+    writeln!(
+      w,
+      "{}",
+      line(self.columns.iter().map(|c| c.header.clone()).collect())
+    )?;
+    for row in &self.rows {
+      writeln!(w, "{}", line(row.iter().map(render_value).collect()))?;
+    }
+    Ok(())
+  }
+
+  #[cfg(feature = "xlsx")]
+  pub fn to_xlsx(&self) -> crate::Result<Vec<u8>> {
+    use rust_xlsxwriter::{Format, Workbook};
+
+    let xlsx_err =
+      |e: rust_xlsxwriter::XlsxError| Error::new(ErrorKind::IO, Some(e.to_string()), None);
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    let bold = Format::new().set_bold();
+    for (col, column) in self.columns.iter().enumerate() {
+      sheet
+        .write_string_with_format(0, col as u16, &column.header, &bold)
+        .map_err(xlsx_err)?;
+    }
+    for (row, values) in self.rows.iter().enumerate() {
+      let row = row as u32 + 1;
+      for (col, value) in values.iter().enumerate() {
+        let col = col as u16;
+        match value {
+          Value::Null => continue,
+          Value::Bool(b) => sheet.write_boolean(row, col, *b),
+          Value::Integer(i) => sheet.write_number(row, col, *i as f64),
+          Value::Unsigned(u) => sheet.write_number(row, col, *u as f64),
+          Value::Float(f) => sheet.write_number(row, col, *f),
+          v => sheet.write_string(row, col, render_value(v)),
+        }
+        .map_err(xlsx_err)?;
+      }
+    }
+    workbook.save_to_buffer().map_err(xlsx_err)
+  }
+
+  /// Encode the sheet in `format`
+  pub fn to_bytes(&self, format: SheetFormat) -> crate::Result<Vec<u8>> {
+    match format {
+      SheetFormat::Csv => {
+        let mut buf = vec![];
+        self.write_csv(&mut buf)?;
+        Ok(buf)
+      }
+      #[cfg(feature = "xlsx")]
+      SheetFormat::Xlsx => self.to_xlsx(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use crate::Value;
+
+  use super::{Column, Sheet};
+
+  #[test]
+  fn csv() {
+    let item = |id: i32, name: &str, city: &str| {
+      Value::from(HashMap::from([
+        ("id".to_string(), Value::from(id)),
+        ("name".to_string(), Value::from(name)),
+        (
+          "address".to_string(),
+          Value::from(HashMap::from([("city".to_string(), Value::from(city))])),
+        ),
+      ]))
+    };
+    let items = vec![item(1, "Joe", "Paris"), item(2, "Doe, Jane", "Lyon")];
+    let mut out = vec![];
+    Sheet::new(&items, vec![]).write_csv(&mut out).unwrap();
+    assert_eq!(
+      String::from_utf8(out).unwrap(),
+      "address.city,id,name\nParis,1,Joe\nLyon,2,\"Doe, Jane\"\n"
+    );
+    let columns = vec!["Name=name".parse::<Column>().unwrap()];
+    let mut out = vec![];
+    Sheet::new(&items, columns).write_csv(&mut out).unwrap();
+    assert_eq!(
+      String::from_utf8(out).unwrap(),
+      "Name\nJoe\n\"Doe, Jane\"\n"
+    );
+  }
+}
@@ -0,0 +1,345 @@
+use std::{
+  collections::VecDeque,
+  io::{BufRead, BufReader, Write},
+  net::IpAddr,
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    Mutex,
+  },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::now_millis;
+
+/// Where the SMTP capture listener accepts mail.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SmtpConfig {
+  /// Address to bind, the server's host by default
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub host: Option<IpAddr>,
+  #[serde(default = "SmtpConfig::default_port")]
+  pub port: u16,
+}
+
+impl SmtpConfig {
+  pub(crate) fn default_port() -> u16 {
+    2525
+  }
+}
+
+impl Default for SmtpConfig {
+  fn default() -> Self {
+    Self {
+      host: None,
+      port: Self::default_port(),
+    }
+  }
+}
+
+/// A message accepted by the SMTP listener.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Email {
+  pub id: u64,
+  /// Envelope sender, as given by `MAIL FROM`
+  pub from: String,
+  /// Envelope recipients, as given by `RCPT TO`
+  pub to: Vec<String>,
+  pub subject: Option<String>,
+  pub headers: Vec<(String, String)>,
+  pub body: String,
+  /// Milliseconds since the unix epoch
+  pub at: u128,
+}
+
+impl Email {
+  /// Message made of the envelope and the `DATA` sent by the client, headers
+  /// being unfolded
+  pub fn parse<F: AsRef<str>>(from: F, to: Vec<String>, data: &str) -> Self {
+    let data = data.replace("\r\n", "\n");
+    let (head, body) = match data.split_once("\n\n") {
+      Some((head, body)) => (head, body),
+      None if data.contains(':') => (data.as_str(), ""),
+      None => ("", data.as_str()),
+    };
+    let mut headers: Vec<(String, String)> = vec![];
+    for line in head.lines() {
+      if line.starts_with([' ', '\t']) {
+        if let Some((_, value)) = headers.last_mut() {
+          value.push(' ');
+          value.push_str(line.trim());
+        }
+      } else if let Some((name, value)) = line.split_once(':') {
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+      }
+    }
+    Self {
+      id: 0,
+      from: from.as_ref().to_string(),
+      to,
+      subject: headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("Subject"))
+        .map(|(_, value)| value.clone()),
+      headers,
+      body: body.to_string(),
+      at: now_millis(0),
+    }
+  }
+
+  pub fn header<K: AsRef<str>>(&self, k: K) -> Option<&String> {
+    self
+      .headers
+      .iter()
+      .find(|(key, _value)| key.eq_ignore_ascii_case(k.as_ref()))
+      .map(|(_key, value)| value)
+  }
+
+  /// Whether `address` is among the recipients, ignoring case
+  pub fn is_for<A: AsRef<str>>(&self, address: A) -> bool {
+    self
+      .to
+      .iter()
+      .any(|to| to.eq_ignore_ascii_case(address.as_ref()))
+  }
+}
+
+/// Bounded store of captured emails, oldest ones are evicted first.
+#[derive(Debug)]
+pub struct Mailbox {
+  emails: Mutex<VecDeque<Email>>,
+  limit: usize,
+  next_id: AtomicU64,
+}
+
+impl Default for Mailbox {
+  fn default() -> Self {
+    Self::new(Self::DEFAULT_LIMIT)
+  }
+}
+
+impl Mailbox {
+  pub const DEFAULT_LIMIT: usize = 1000;
+
+  pub fn new(limit: usize) -> Self {
+    Self {
+      emails: Mutex::new(VecDeque::new()),
+      limit,
+      next_id: AtomicU64::new(1),
+    }
+  }
+
+  /// Store `email`, returning the id it was given
+  pub fn deliver(&self, mut email: Email) -> crate::Result<u64> {
+    email.id = self.next_id.fetch_add(1, Ordering::Relaxed);
+    let id = email.id;
+    let mut g = self.emails.lock()?;
+    while !g.is_empty() && g.len() >= self.limit {
+      g.pop_front();
+    }
+    g.push_back(email);
+    Ok(id)
+  }
+
+  /// Emails received so far, only those sent to `to` if given
+  pub fn emails(&self, to: Option<&str>) -> crate::Result<Vec<Email>> {
+    let g = self.emails.lock()?;
+    Ok(
+      g.iter()
+        .filter(|e| to.is_none_or(|to| e.is_for(to)))
+        .cloned()
+        .collect(),
+    )
+  }
+
+  pub fn get(&self, id: u64) -> crate::Result<Option<Email>> {
+    Ok(self.emails.lock()?.iter().find(|e| e.id == id).cloned())
+  }
+
+  pub fn clear(&self) -> crate::Result<()> {
+    self.emails.lock()?.clear();
+    Ok(())
+  }
+}
+
+/// Address inside `MAIL FROM:<...>` or `RCPT TO:<...>` arguments
+fn address(arg: &str) -> String {
+  let arg = arg.split_once(':').map(|(_, a)| a).unwrap_or(arg).trim();
+  match (arg.find('<'), arg.find('>')) {
+    (Some(start), Some(end)) if start < end => arg[start + 1..end].to_string(),
+    _ => arg
+      .split_whitespace()
+      .next()
+      .unwrap_or_default()
+      .to_string(),
+  }
+}
+
+/// Play the server side of an SMTP session over `stream`, delivering every
+/// accepted message to `mailbox`. Authentication always succeeds.
+pub fn smtp_session<S: std::io::Read + Write>(stream: S, mailbox: &Mailbox) -> crate::Result<()> {
+  let mut reader = BufReader::new(stream);
+  let mut from: Option<String> = None;
+  let mut to: Vec<String> = vec![];
+  macro_rules! reply {
+    ($($arg:tt)*) => {{
+      let w = reader.get_mut();
+      write!(w, $($arg)*)?;
+      w.write_all(b"\r\n")?;
+      w.flush()?;
+    }};
+  }
+  let mut line = String::new();
+  let mut read_line = |reader: &mut BufReader<S>| -> crate::Result<Option<String>> {
+    line.clear();
+    match reader.read_line(&mut line)? {
+      0 => Ok(None),
+      _ => Ok(Some(line.trim_end_matches(['\r', '\n']).to_string())),
+    }
+  };
+  reply!("220 mocker ESMTP ready");
+  while let Some(command) = read_line(&mut reader)? {
+    let (verb, arg) = command.split_once(' ').unwrap_or((command.as_str(), ""));
+    match verb.to_ascii_uppercase().as_str() {
+      "HELO" => reply!("250 mocker"),
+      "EHLO" => {
+        reply!("250-mocker");
+        reply!("250-8BITMIME");
+        reply!("250-SMTPUTF8");
+        reply!("250 AUTH PLAIN LOGIN");
+      }
+      "AUTH" => {
+        let mut args = arg.split_whitespace();
+        match (args.next().map(|m| m.to_ascii_uppercase()), args.next()) {
+          (Some(m), None) if m == "PLAIN" => {
+            reply!("334 ");
+            read_line(&mut reader)?;
+          }
+          (Some(m), initial) if m == "LOGIN" => {
+            if initial.is_none() {
+              reply!("334 VXNlcm5hbWU6");
+              read_line(&mut reader)?;
+            }
+            reply!("334 UGFzc3dvcmQ6");
+            read_line(&mut reader)?;
+          }
+          _ => {}
+        }
+        reply!("235 2.7.0 Authentication successful");
+      }
+      "MAIL" => {
+        from = Some(address(arg));
+        to.clear();
+        reply!("250 2.1.0 OK");
+      }
+      "RCPT" if from.is_none() => reply!("503 5.5.1 MAIL first"),
+      "RCPT" => {
+        to.push(address(arg));
+        reply!("250 2.1.5 OK");
+      }
+      "DATA" if to.is_empty() => reply!("503 5.5.1 RCPT first"),
+      "DATA" => {
+        reply!("354 End data with <CR><LF>.<CR><LF>");
+        let mut data = String::new();
+        while let Some(line) = read_line(&mut reader)? {
+          if line == "." {
+            break;
+          }
+          data.push_str(line.strip_prefix('.').unwrap_or(&line));
+          data.push_str("\r\n");
+        }
+        let email = Email::parse(
+          from.take().unwrap_or_default(),
+          std::mem::take(&mut to),
+          &data,
+        );
+        let id = mailbox.deliver(email)?;
+        reply!("250 2.0.0 OK: queued as {}", id);
+      }
+      "RSET" => {
+        from = None;
+        to.clear();
+        reply!("250 2.0.0 OK");
+      }
+      "NOOP" => reply!("250 2.0.0 OK"),
+      "QUIT" => {
+        reply!("221 2.0.0 Bye");
+        break;
+      }
+      _ => reply!("502 5.5.2 Command not recognized"),
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::{Cursor, Read, Write};
+
+  use super::{smtp_session, Mailbox};
+
+  /// In-memory client side of a session
+  struct Conversation {
+    input: Cursor<Vec<u8>>,
+    output: Vec<u8>,
+  }
+
+  impl Read for Conversation {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+      self.input.read(buf)
+    }
+  }
+
+  impl Write for Conversation {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+      self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn session() {
+    let input = [
+      "EHLO client",
+      "AUTH PLAIN AHVzZXIAcGFzcw==",
+      "MAIL FROM:<noreply@example.com> SIZE=120",
+      "RCPT TO:<Alice@example.com>",
+      "RCPT TO:<bob@example.com>",
+      "DATA",
+      "Subject: Welcome",
+      "  aboard",
+      "From: noreply@example.com",
+      "",
+      "Hello,",
+      "..dotted line",
+      ".",
+      "QUIT",
+      "",
+    ]
+    .join("\r\n");
+    let mut conversation = Conversation {
+      input: Cursor::new(input.into_bytes()),
+      output: vec![],
+    };
+    let mailbox = Mailbox::default();
+    smtp_session(&mut conversation, &mailbox).unwrap();
+    let output = String::from_utf8(conversation.output).unwrap();
+    assert!(output.starts_with("220 "));
+    assert!(output.contains("250 2.0.0 OK: queued as 1\r\n"));
+    assert!(output.ends_with("221 2.0.0 Bye\r\n"));
+
+    let emails = mailbox.emails(Some("alice@example.com")).unwrap();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].from, "noreply@example.com");
+    assert_eq!(emails[0].to, vec!["Alice@example.com", "bob@example.com"]);
+    assert_eq!(emails[0].subject.as_deref(), Some("Welcome aboard"));
+    assert_eq!(emails[0].body, "Hello,\n.dotted line\n");
+    assert!(mailbox
+      .emails(Some("carol@example.com"))
+      .unwrap()
+      .is_empty());
+  }
+}
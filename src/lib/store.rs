@@ -1,22 +1,64 @@
-use std::collections::{BTreeMap, VecDeque};
 use std::fmt::Debug;
 use std::{
   collections::HashMap,
   io::{Read, Write},
   path::{Path, PathBuf},
+  str::FromStr,
   sync::Arc,
 };
 
-use log::error;
+use crate::{
+  merge_patch, open_file, write_file, Column, Error, ErrorKind, Route, RouteKind, Sheet, Status,
+  Value,
+};
+
+pub type StoreSerializer =
+  Arc<dyn Fn(&Vec<HashMap<String, Value>>, &mut dyn Write) -> crate::Result<()> + Send + Sync>;
+pub type StoreDeserializer =
+  Arc<dyn Fn(&mut dyn Read) -> crate::Result<Vec<HashMap<String, Value>>> + Send + Sync>;
+
+/// How imported items are combined with the items already in a store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportStrategy {
+  /// Drop the current items
+  Replace,
+  /// Update items sharing an identifier, add the others
+  Merge,
+  /// Add every item, identifiers must not be taken yet
+  Append,
+}
 
-use crate::{Error, ErrorKind, Status, Value};
+impl FromStr for ImportStrategy {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "replace" => Ok(ImportStrategy::Replace),
+      "merge" => Ok(ImportStrategy::Merge),
+      "append" => Ok(ImportStrategy::Append),
+      _ => Err(Error::new(
+        ErrorKind::Parse,
+        Some(format!("unknown import strategy '{}'", s)),
+        None,
+      )),
+    }
+  }
+}
 
+/// Outcome of [`Store::import`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportReport {
+  pub created: usize,
+  pub updated: usize,
+}
+
+#[derive(Clone)]
 pub struct Store {
   path: PathBuf,
   items: Vec<HashMap<String, Value>>,
   identifier: String,
-  serializer: Arc<dyn Fn(&Vec<HashMap<String, Value>>, &mut dyn Write) -> crate::Result<()>>,
-  deserializer: Arc<dyn Fn(&mut dyn Read) -> crate::Result<Vec<HashMap<String, Value>>>>,
+  serializer: StoreSerializer,
+  deserializer: StoreDeserializer,
 }
 
 fn convert_items<V: Clone, R, F: Fn(V) -> crate::Result<R>>(
@@ -38,7 +80,7 @@ fn convert_items<V: Clone, R, F: Fn(V) -> crate::Result<R>>(
 impl Store {
   fn json_deserialize(r: &mut dyn Read) -> crate::Result<Vec<HashMap<String, Value>>> {
     let data: Vec<HashMap<String, serde_json::Value>> = serde_json::from_reader(r)?;
-    Ok(convert_items(&data, |val| Value::try_from_json(val))?)
+    convert_items(&data, Value::try_from_json)
   }
 
   fn json_serialize(
@@ -58,6 +100,25 @@ impl Store {
       Self::json_deserialize,
     )
   }
+
+  /// Store backing the store route mounted at `endpoint`
+  pub fn for_endpoint<E: AsRef<str>>(routes: &[Route], endpoint: E) -> crate::Result<Self> {
+    routes
+      .iter()
+      .find_map(|route| match route.kind() {
+        RouteKind::Store {
+          path, identifier, ..
+        } if route.endpoint() == endpoint.as_ref() => Some(Self::json(path, identifier)),
+        _ => None,
+      })
+      .ok_or_else(|| {
+        Error::new(
+          ErrorKind::Api(Status::NotFound),
+          Some(format!("no store route at '{}'", endpoint.as_ref())),
+          None,
+        )
+      })
+  }
 }
 
 #[cfg(feature = "toml")]
@@ -119,8 +180,8 @@ impl Store {
   pub fn new<
     P: AsRef<Path>,
     I: AsRef<str>,
-    S: Fn(&Vec<HashMap<String, Value>>, &mut dyn Write) -> crate::Result<()> + 'static,
-    D: Fn(&mut dyn Read) -> crate::Result<Vec<HashMap<String, Value>>> + 'static,
+    S: Fn(&Vec<HashMap<String, Value>>, &mut dyn Write) -> crate::Result<()> + Send + Sync + 'static,
+    D: Fn(&mut dyn Read) -> crate::Result<Vec<HashMap<String, Value>>> + Send + Sync + 'static,
   >(
     path: P,
     identifier: I,
@@ -173,7 +234,7 @@ impl Store {
   }
 
   pub fn contains(&self, id: &Value) -> bool {
-    return self.find(id).is_some();
+    self.find(id).is_some()
   }
 
   pub fn find(&self, id: &Value) -> Option<&HashMap<String, Value>> {
@@ -198,7 +259,7 @@ impl Store {
         ));
       }
     };
-    if let Some(_) = self.find(id_value) {
+    if self.find(id_value).is_some() {
       return Err(Error::new(
         ErrorKind::Api(Status::Conflict),
         Some(format!(
@@ -213,10 +274,36 @@ impl Store {
     Ok(ret)
   }
 
+  /// Replace the fields of the entity identified by `id` with those of
+  /// `obj`, or apply `obj` as a JSON merge patch when `merge`-ing, see
+  /// [`Value::merge_patch`]. The identifier is kept.
+  pub fn update(
+    &mut self,
+    id: &Value,
+    mut obj: HashMap<String, Value>,
+    merge: bool,
+  ) -> Option<&HashMap<String, Value>> {
+    let identifier = self.identifier.clone();
+    let item = self.items.iter_mut().find(|item| {
+      item
+        .iter()
+        .any(|(k, v)| k.eq_ignore_ascii_case(&identifier) && v.loose_eq(id))
+    })?;
+    obj.retain(|k, _| !k.eq_ignore_ascii_case(&identifier));
+    match merge {
+      true => merge_patch(item, obj),
+      false => {
+        item.retain(|k, _| k.eq_ignore_ascii_case(&identifier));
+        item.extend(obj);
+      }
+    }
+    Some(item)
+  }
+
   pub fn remove(&mut self, id: &Value) -> Option<HashMap<String, Value>> {
-    let found = self.items.iter().enumerate().find(|(item_id, item)| {
+    let found = self.items.iter().enumerate().find(|(_item_id, item)| {
       if let Some((_id_key, id_val)) = self.id_field(item) {
-        if *id_val == *id {
+        if id_val.loose_eq(id) {
           return true;
         }
       }
@@ -228,16 +315,100 @@ impl Store {
     }
   }
 
+  /// Problems preventing `items` from being imported: missing or duplicate
+  /// identifiers, and fields whose type differs from the current items.
+  pub fn validate_import(&self, items: &[HashMap<String, Value>]) -> Vec<String> {
+    let mut problems = vec![];
+    let mut ids: Vec<&Value> = vec![];
+    let reference = self.items.first().or(items.first());
+    for (i, item) in items.iter().enumerate() {
+      match self.id_field(item) {
+        Some((_, id)) if ids.iter().any(|other| other.loose_eq(id)) => problems.push(format!(
+          "item {}: duplicate `{}` {}",
+          i, self.identifier, id
+        )),
+        Some((_, id)) => ids.push(id),
+        None => problems.push(format!("item {}: missing `{}` field", i, self.identifier)),
+      }
+      for (key, value) in item {
+        let expected = match reference.and_then(|r| r.get(key)) {
+          Some(Value::Null) | None => continue,
+          Some(expected) => expected,
+        };
+        if *value != Value::Null && value.type_name() != expected.type_name() {
+          problems.push(format!(
+            "item {}: `{}` is {} but should be {}",
+            i,
+            key,
+            value.type_name(),
+            expected.type_name()
+          ));
+        }
+      }
+    }
+    problems
+  }
+
+  /// Validate `items` then combine them with the current ones following `strategy`
+  pub fn import(
+    &mut self,
+    items: Vec<HashMap<String, Value>>,
+    strategy: ImportStrategy,
+  ) -> crate::Result<ImportReport> {
+    let problems = self.validate_import(&items);
+    if !problems.is_empty() {
+      return Err(Error::new(
+        ErrorKind::Parse,
+        Some(format!("invalid import data:\n  {}", problems.join("\n  "))),
+        None,
+      ));
+    }
+    let mut report = ImportReport::default();
+    if strategy == ImportStrategy::Replace {
+      self.items.clear();
+    }
+    for item in items {
+      let existing = self.id_field(&item).and_then(|(_, id)| self.position(id));
+      match (strategy, existing) {
+        (ImportStrategy::Merge, Some(index)) => {
+          self.items[index].extend(item);
+          report.updated += 1;
+        }
+        _ => {
+          self.create(item)?;
+          report.created += 1;
+        }
+      }
+    }
+    Ok(report)
+  }
+
+  fn position(&self, id: &Value) -> Option<usize> {
+    self.items.iter().position(|item| {
+      self
+        .id_field(item)
+        .is_some_and(|(_, item_id)| item_id.loose_eq(id))
+    })
+  }
+
   pub fn load(&mut self) -> crate::Result<usize> {
-    let mut f = std::fs::File::open(&self.path)?;
+    let mut f = open_file(&self.path)?;
     self.items = (self.deserializer)(&mut f)?;
     Ok(self.items.len())
   }
 
+  /// Lay the items out as a spreadsheet, see [`Sheet::new`]
+  pub fn sheet(&self, columns: Vec<Column>) -> Sheet {
+    let items = self
+      .items
+      .iter()
+      .map(|item| Value::from(item.clone()))
+      .collect::<Vec<_>>();
+    Sheet::new(&items, columns)
+  }
+
   pub fn save(&self) -> crate::Result<()> {
-    let mut f = std::fs::File::create(&self.path)?;
-    (self.serializer)(&self.items, &mut f)?;
-    Ok(())
+    write_file(&self.path, |f| (self.serializer)(&self.items, f))
   }
 }
 
@@ -257,7 +428,7 @@ impl Debug for Store {
 mod tests {
   use crate::Value;
 
-  use super::Store;
+  use super::{ImportReport, ImportStrategy, Store};
 
   #[test]
   fn find() {
@@ -278,6 +449,59 @@ mod tests {
       .unwrap();
     let found = store.find(&Value::from(84));
     assert_eq!(found, Some(&store.items[1]));
+    let patch = HashMap::from([("age".to_string(), Value::from(3))]);
+    let updated = store
+      .update(&Value::from("84"), patch.clone(), true)
+      .unwrap();
+    assert_eq!(updated.len(), 3);
+    let updated = store.update(&Value::from(84), patch, false).unwrap();
+    assert_eq!(updated.get("name"), None);
+    assert!(store.remove(&Value::from("84")).is_some());
+    assert!(store
+      .update(&Value::from(84), HashMap::new(), true)
+      .is_none());
     println!("{:#?}", store);
   }
+
+  #[test]
+  fn import() {
+    use std::collections::HashMap;
+
+    let user = |id: i32, name: Value| {
+      HashMap::from([
+        ("id".to_string(), Value::from(id)),
+        ("name".to_string(), name),
+      ])
+    };
+    let mut store = Store::json("/tmp/test.json", "id");
+    store.create(user(1, "Joe".into())).unwrap();
+    let report = store
+      .import(
+        vec![user(1, "Jack".into()), user(2, "Jane".into())],
+        ImportStrategy::Merge,
+      )
+      .unwrap();
+    assert_eq!(
+      report,
+      ImportReport {
+        created: 1,
+        updated: 1
+      }
+    );
+    assert_eq!(store.items[0]["name"], Value::from("Jack"));
+    assert!(store
+      .import(vec![user(2, "Jane".into())], ImportStrategy::Append)
+      .is_err());
+    assert_eq!(
+      store.validate_import(&[user(3, Value::from(3)), user(3, "Jim".into())]),
+      vec![
+        "item 0: `name` is number but should be string",
+        "item 1: duplicate `id` 3"
+      ]
+    );
+    store
+      .import(vec![user(5, "Jim".into())], ImportStrategy::Replace)
+      .unwrap();
+    assert_eq!(store.items.len(), 1);
+  }
 }
@@ -0,0 +1,161 @@
+use std::{
+  collections::HashMap,
+  sync::{
+    mpsc::{channel, Receiver, Sender},
+    Mutex,
+  },
+};
+
+use serde::Serialize;
+
+use crate::{now_millis, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreAction {
+  Created,
+  Updated,
+  Deleted,
+}
+
+/// Change made to the data of a store route.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StoreEvent {
+  pub action: StoreAction,
+  /// Endpoint of the store route
+  pub endpoint: String,
+  pub id: Value,
+  /// The entity as it is after the change, as it was before a deletion
+  pub entity: HashMap<String, Value>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub tenant: Option<String>,
+  /// Milliseconds since the unix epoch
+  pub at: u128,
+}
+
+impl StoreEvent {
+  pub fn new<E: AsRef<str>>(
+    action: StoreAction,
+    endpoint: E,
+    id: Value,
+    entity: HashMap<String, Value>,
+  ) -> Self {
+    Self {
+      action,
+      endpoint: endpoint.as_ref().to_string(),
+      id,
+      entity,
+      tenant: None,
+      at: now_millis(0),
+    }
+  }
+
+  pub fn with_tenant(mut self, tenant: Option<String>) -> Self {
+    self.tenant = tenant;
+    self
+  }
+}
+
+/// Fan-out of store changes to live listeners, such as `/__mocker/events`.
+#[derive(Debug, Default)]
+pub struct StoreEvents {
+  /// Live listeners, receiving every event as json
+  subscribers: Mutex<Vec<Sender<String>>>,
+  /// Events published so far, by endpoint
+  revisions: Mutex<HashMap<String, u64>>,
+}
+
+impl StoreEvents {
+  /// Receive every event published from now on, encoded as json
+  pub fn subscribe(&self) -> crate::Result<Receiver<String>> {
+    let (tx, rx) = channel();
+    self.subscribers.lock()?.push(tx);
+    Ok(rx)
+  }
+
+  /// Events published so far about `endpoint`, or about any store
+  pub fn revision(&self, endpoint: Option<&str>) -> crate::Result<u64> {
+    let revisions = self.revisions.lock()?;
+    Ok(match endpoint {
+      Some(endpoint) => revisions.get(endpoint).copied().unwrap_or_default(),
+      None => revisions.values().sum(),
+    })
+  }
+
+  pub fn publish(&self, event: StoreEvent) -> crate::Result<()> {
+    *self
+      .revisions
+      .lock()?
+      .entry(event.endpoint.clone())
+      .or_default() += 1;
+    #[cfg(feature = "json")]
+    {
+      let mut subscribers = self.subscribers.lock()?;
+      if !subscribers.is_empty() {
+        let data = serde_json::to_string(&event)?;
+        subscribers.retain(|tx| tx.send(data.clone()).is_ok());
+      }
+    }
+    #[cfg(not(feature = "json"))]
+    let _ = event;
+    Ok(())
+  }
+}
+
+#[cfg(all(test, feature = "json"))]
+mod tests {
+  use crate::{Config, Engine, Method, Request, Route, RouteKind};
+
+  #[test]
+  fn publish() {
+    let path = std::env::temp_dir().join(format!("mocker-events-{}.json", std::process::id()));
+    std::fs::write(&path, "[]").unwrap();
+    let engine = Engine::new(&Config {
+      routes: vec![Route::new(
+        vec![Method::Post, Method::Patch, Method::Delete],
+        "/users",
+        RouteKind::Store {
+          path: path.clone(),
+          identifier: "id".to_string(),
+          parent: None,
+        },
+      )],
+      ..Default::default()
+    })
+    .unwrap();
+    let events = engine.router().store_events().subscribe().unwrap();
+    let send = |method: Method, target: &str, body: Option<&str>| {
+      let req = Request::new(method, target);
+      let req = match body {
+        Some(body) => req
+          .with_header("Content-Type", "application/json")
+          .with_body(body),
+        None => req,
+      };
+      engine.handle(req).status()
+    };
+    assert_eq!(
+      send(Method::Post, "/users", Some(r#"{"id": 1, "name": "ada"}"#)),
+      201
+    );
+    assert_eq!(
+      send(Method::Patch, "/users?id=1", Some(r#"{"name": "grace"}"#)),
+      200
+    );
+    assert_eq!(send(Method::Delete, "/users?id=1", None), 204);
+    assert_eq!(send(Method::Delete, "/users?id=1", None), 404);
+    let received = events
+      .try_iter()
+      .map(|data| serde_json::from_str::<serde_json::Value>(&data).unwrap())
+      .collect::<Vec<_>>();
+    let actions = received
+      .iter()
+      .map(|e| e["action"].as_str().unwrap())
+      .collect::<Vec<_>>();
+    assert_eq!(actions, ["created", "updated", "deleted"]);
+    assert_eq!(received[1]["entity"]["name"], "grace");
+    assert_eq!(received[2]["endpoint"], "/users");
+    assert_eq!(received[2]["id"], 1);
+    std::fs::remove_file(&path).unwrap();
+  }
+}
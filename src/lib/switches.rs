@@ -0,0 +1,72 @@
+use std::{collections::BTreeMap, sync::RwLock};
+
+use crate::Route;
+
+/// Routes switched on or off at runtime, by [`Router::keys`](crate::Router::keys),
+/// overriding their `enabled` option so a dependency failing can be
+/// simulated mid-test.
+#[derive(Debug, Default)]
+pub struct RouteSwitches(RwLock<BTreeMap<String, bool>>);
+
+impl RouteSwitches {
+  /// Whether `route`, registered under `key`, serves requests
+  pub fn enabled(&self, key: &str, route: &Route) -> crate::Result<bool> {
+    Ok(match self.0.read()?.get(key) {
+      Some(enabled) => *enabled,
+      None => route.options().enabled != Some(false),
+    })
+  }
+
+  /// Switch the route registered under `key` on or off
+  pub fn set(&self, key: &str, enabled: bool) -> crate::Result<()> {
+    self.0.write()?.insert(key.to_string(), enabled);
+    Ok(())
+  }
+
+  /// Switches flipped at runtime, by route key
+  pub fn overrides(&self) -> crate::Result<BTreeMap<String, bool>> {
+    Ok(self.0.read()?.clone())
+  }
+
+  /// Go back to the `enabled` option of every route
+  pub fn reset(&self) -> crate::Result<()> {
+    self.0.write()?.clear();
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Route, RouteKind, RouteOptions};
+
+  use super::RouteSwitches;
+
+  #[test]
+  fn enabled() {
+    let route = |enabled: Option<bool>| {
+      Route::new(
+        vec![Method::Get],
+        "/",
+        RouteKind::Echo {
+          status: 200,
+          headers: Default::default(),
+        },
+      )
+      .with_options(RouteOptions {
+        enabled,
+        ..Default::default()
+      })
+    };
+    let switches = RouteSwitches::default();
+    assert!(switches.enabled("GET /", &route(None)).unwrap());
+    assert!(!switches.enabled("GET /", &route(Some(false))).unwrap());
+    switches.set("GET /", false).unwrap();
+    assert!(!switches.enabled("GET /", &route(None)).unwrap());
+    assert!(switches.enabled("GET / #2", &route(None)).unwrap());
+    assert_eq!(switches.overrides().unwrap().get("GET /"), Some(&false));
+    switches.set("GET /", true).unwrap();
+    assert!(switches.enabled("GET /", &route(Some(false))).unwrap());
+    switches.reset().unwrap();
+    assert!(!switches.enabled("GET /", &route(Some(false))).unwrap());
+  }
+}
@@ -11,6 +11,22 @@ pub struct Table<const N: usize> {
   dirty: bool,
 }
 
+impl<const N: usize> Default for Table<N> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl<const N: usize> FromIterator<[String; N]> for Table<N> {
+  fn from_iter<I: IntoIterator<Item = [String; N]>>(iter: I) -> Self {
+    let mut ret = Self::new();
+    for row in iter.into_iter() {
+      ret.push(row);
+    }
+    ret
+  }
+}
+
 impl<const N: usize> Table<N> {
   const C_STR: String = String::new();
 
@@ -25,14 +41,6 @@ impl<const N: usize> Table<N> {
     }
   }
 
-  pub fn from_iter<I: IntoIterator<Item = [String; N]>>(iter: I) -> Self {
-    let mut ret = Self::new();
-    for row in iter.into_iter() {
-      ret.push(row);
-    }
-    ret
-  }
-
   pub fn with_header(mut self, v: [String; N]) -> Self {
     self.header = Some(v);
     self
@@ -0,0 +1,110 @@
+use std::{collections::BTreeSet, sync::RwLock};
+
+use crate::Route;
+
+/// Tags whose routes are served, so one workspace can model several
+/// deployments. Untagged routes are always served, tagged ones when any of
+/// their tags is active or no selection was made.
+#[derive(Debug, Default)]
+pub struct RouteTags(RwLock<Option<BTreeSet<String>>>);
+
+impl RouteTags {
+  pub fn new<T: AsRef<str>, I: IntoIterator<Item = T>>(active: Option<I>) -> Self {
+    let tags = Self::default();
+    *tags.0.write().expect("poisoned tags") = active.map(Self::collect);
+    tags
+  }
+
+  fn collect<T: AsRef<str>, I: IntoIterator<Item = T>>(tags: I) -> BTreeSet<String> {
+    tags.into_iter().map(|t| t.as_ref().to_string()).collect()
+  }
+
+  /// Tags served, `None` when every route is
+  pub fn active(&self) -> crate::Result<Option<BTreeSet<String>>> {
+    Ok(self.0.read()?.clone())
+  }
+
+  /// Serve only the routes tagged with one of `tags`, or every route
+  pub fn select<T: AsRef<str>, I: IntoIterator<Item = T>>(
+    &self,
+    tags: Option<I>,
+  ) -> crate::Result<()> {
+    *self.0.write()? = tags.map(Self::collect);
+    Ok(())
+  }
+
+  pub fn enable<T: AsRef<str>>(&self, tag: T) -> crate::Result<()> {
+    if let Some(active) = self.0.write()?.as_mut() {
+      active.insert(tag.as_ref().to_string());
+    }
+    Ok(())
+  }
+
+  /// Stop serving `tag`, the others of `known` staying active when every
+  /// route was served
+  pub fn disable<T: AsRef<str>>(&self, tag: T, known: &BTreeSet<String>) -> crate::Result<()> {
+    let mut g = self.0.write()?;
+    let active = g.get_or_insert_with(|| known.clone());
+    active.remove(tag.as_ref());
+    Ok(())
+  }
+
+  pub fn serves(&self, route: &Route) -> crate::Result<bool> {
+    let tags = &route.options().tags;
+    Ok(match self.0.read()?.as_ref() {
+      Some(active) if !tags.is_empty() => tags.iter().any(|t| active.contains(t)),
+      _ => true,
+    })
+  }
+
+  /// Every tag used by `routes`
+  pub fn known<'a, I: IntoIterator<Item = &'a Route>>(routes: I) -> BTreeSet<String> {
+    routes
+      .into_iter()
+      .flat_map(|r| r.options().tags.iter().cloned())
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, Route, RouteKind, RouteOptions};
+
+  use super::RouteTags;
+
+  #[test]
+  fn serves() {
+    let route = |tags: &[&str]| {
+      Route::new(
+        vec![Method::Get],
+        "/",
+        RouteKind::Echo {
+          status: 200,
+          headers: Default::default(),
+        },
+      )
+      .with_options(RouteOptions {
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+        ..Default::default()
+      })
+    };
+    let (untagged, payments, v2) = (route(&[]), route(&["payments"]), route(&["v2"]));
+    let known = RouteTags::known([&untagged, &payments, &v2]);
+    assert_eq!(known.len(), 2);
+
+    let tags = RouteTags::new(Some(["payments"]));
+    assert!(tags.serves(&untagged).unwrap());
+    assert!(tags.serves(&payments).unwrap());
+    assert!(!tags.serves(&v2).unwrap());
+    tags.enable("v2").unwrap();
+    assert!(tags.serves(&v2).unwrap());
+
+    let tags = RouteTags::default();
+    assert!(tags.serves(&v2).unwrap());
+    tags.disable("payments", &known).unwrap();
+    assert!(!tags.serves(&payments).unwrap());
+    assert!(tags.serves(&v2).unwrap());
+    tags.select(None::<Vec<String>>).unwrap();
+    assert!(tags.serves(&payments).unwrap());
+  }
+}
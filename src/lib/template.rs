@@ -0,0 +1,521 @@
+use std::{
+  collections::HashMap,
+  path::{Component, Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+use crate::{
+  civil_date, parse_date, read_file, snowflake, ulid, uuid_v4, BinaryPayload, Error, ErrorKind,
+  MatchedRoute, Request, Value, Variables, VirtualClock, WorkspaceVars, GLOBAL_SCOPE,
+};
+
+/// Directory of the workspace templates: reusable fragments in `partials/`,
+/// envelopes templates extend in `layouts/`
+pub const TEMPLATES_DIR: &str = "templates";
+
+/// Extension of partial and layout files
+pub const TEMPLATE_EXTENSION: &str = "hbs";
+
+/// How deep partials and layouts may nest, guarding against cycles
+const MAX_TEMPLATE_DEPTH: usize = 16;
+
+/// Data and server-side state available while rendering a template.
+pub struct TemplateContext<'a> {
+  data: HashMap<String, Value>,
+  variables: &'a Variables,
+  scope: String,
+  templates_dir: PathBuf,
+  clock: VirtualClock,
+  depth: usize,
+}
+
+impl<'a> TemplateContext<'a> {
+  pub fn new(variables: &'a Variables) -> Self {
+    Self {
+      data: HashMap::new(),
+      variables,
+      scope: GLOBAL_SCOPE.to_string(),
+      templates_dir: PathBuf::from(TEMPLATES_DIR),
+      clock: VirtualClock::default(),
+      depth: 0,
+    }
+  }
+
+  /// Directory partials and layouts are read from, [`TEMPLATES_DIR`] by
+  /// default
+  pub fn with_templates_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+    self.templates_dir = dir.as_ref().to_path_buf();
+    self
+  }
+
+  /// Clock the `now` and `age` helpers read, the system clock by default
+  pub fn with_clock(mut self, clock: &VirtualClock) -> Self {
+    self.clock = clock.clone();
+    self
+  }
+
+  /// Scope in which `counter`, `set` and `get` helpers operate
+  pub fn with_scope<S: AsRef<str>>(mut self, scope: S) -> Self {
+    self.scope = scope.as_ref().to_string();
+    self
+  }
+
+  pub fn with_data<K: AsRef<str>, V: Into<Value>>(mut self, key: K, value: V) -> Self {
+    self.data.insert(key.as_ref().to_string(), value.into());
+    self
+  }
+
+  pub fn with_request(self, req: &Request) -> Self {
+    let ctx = self
+      .with_data("request", req.to_value())
+      .with_data("context", req.extensions().to_value())
+      .with_data("vars", WorkspaceVars::of(req));
+    match req.extensions().get::<MatchedRoute>() {
+      Some(matched) => ctx.with_data("route", matched.to_value(req)),
+      None => ctx,
+    }
+  }
+
+  pub fn data(&self) -> &HashMap<String, Value> {
+    &self.data
+  }
+
+  pub fn scope(&self) -> &String {
+    &self.scope
+  }
+
+  /// Resolve a dotted path such as `request.body.name` or `items.0.id`
+  pub fn lookup<P: AsRef<str>>(&self, path: P) -> Value {
+    let (root, rest) = match path.as_ref().split_once('.') {
+      Some((root, rest)) => (root, rest),
+      None => (path.as_ref(), ""),
+    };
+    self
+      .data
+      .get(root)
+      .and_then(|v| v.get_path(rest))
+      .cloned()
+      .unwrap_or_default()
+  }
+
+  /// Source of the template `name` in the `kind` directory, without the
+  /// newline ending its file
+  fn template_source(&self, kind: &str, name: &str) -> crate::Result<String> {
+    let invalid = |why: &str| {
+      Error::new(
+        ErrorKind::Parse,
+        Some(format!("invalid template {}/{}: {}", kind, name, why)),
+        None,
+      )
+    };
+    if !Path::new(name)
+      .components()
+      .all(|c| matches!(c, Component::Normal(_)))
+    {
+      return Err(invalid("the name must be relative to the templates"));
+    }
+    if self.depth >= MAX_TEMPLATE_DEPTH {
+      return Err(invalid("templates nest too deeply"));
+    }
+    let path = self
+      .templates_dir
+      .join(kind)
+      .join(format!("{}.{}", name, TEMPLATE_EXTENSION));
+    let source = String::from_utf8(read_file(path)?).map_err(|e| invalid(&e.to_string()))?;
+    Ok(source.strip_suffix('\n').unwrap_or(&source).to_string())
+  }
+
+  /// Context of a partial or layout, one level deeper
+  fn nested(&self) -> Self {
+    Self {
+      data: self.data.clone(),
+      variables: self.variables,
+      scope: self.scope.clone(),
+      templates_dir: self.templates_dir.clone(),
+      clock: self.clock.clone(),
+      depth: self.depth + 1,
+    }
+  }
+
+  /// Rendered partial of `{{> name}}`, or of `{{> name user}}` to add the
+  /// fields of `user` to the data in scope
+  fn partial(&self, expr: &str) -> crate::Result<String> {
+    let tokens = tokenize(expr)?;
+    let (name, arg) = match &tokens[..] {
+      [name] => (template_name(name), Value::Null),
+      [name, Token::Ident(path)] => (template_name(name), self.lookup(path)),
+      [name, Token::Literal(v)] => (template_name(name), v.clone()),
+      _ => (None, Value::Null),
+    };
+    let name = name.ok_or_else(|| {
+      Error::new(
+        ErrorKind::Parse,
+        Some(format!("invalid partial '{{{{>{}}}}}'", expr)),
+        None,
+      )
+    })?;
+    let mut ctx = self.nested();
+    if let Value::Map(fields) = arg {
+      ctx.data.extend(fields);
+    }
+    render(self.template_source("partials", &name)?, &ctx)
+  }
+
+  fn helper(&self, name: &str, args: &[Value]) -> Option<crate::Result<Value>> {
+    let arg = |i: usize| args.get(i).cloned().unwrap_or_default();
+    Some(match name {
+      "counter" => self
+        .variables
+        .incr(&self.scope, arg(0).to_string())
+        .map(Value::from),
+      "set" => self
+        .variables
+        .set(&self.scope, arg(0).to_string(), arg(1))
+        .map(|_| Value::Null),
+      "get" => self.variables.get(&self.scope, arg(0).to_string()),
+      "now" => Ok(Value::from(self.clock.now_millis() as i128)),
+      "uuid" => Ok(Value::from(uuid_v4())),
+      "ulid" => Ok(Value::from(ulid())),
+      "snowflake" => Ok(Value::from(snowflake() as i128)),
+      "sequence" => self
+        .variables
+        .incr(&self.scope, arg(0).to_string())
+        .map(|n| {
+          let number = |v: Value, default| v.to_string().parse::<i128>().unwrap_or(default);
+          Value::from(number(arg(1), 1) + (n - 1) * number(arg(2), 1))
+        }),
+      "random_bytes" | "png" | "jpeg" | "pdf" => {
+        let args = args.iter().map(render_value).collect::<Vec<_>>();
+        format!("{}({})", name.trim_end_matches("_bytes"), args.join(", "))
+          .parse::<BinaryPayload>()
+          .map(|payload| Value::from(STANDARD.encode(payload.generate())))
+      }
+      "age" => Ok(match parse_date(render_value(&arg(0))) {
+        Some((year, month, day)) => {
+          let (y, m, d) = civil_date(self.clock.now_millis());
+          Value::from((y - year - i64::from((m, d) < (month, day))) as i128)
+        }
+        None => Value::Null,
+      }),
+      _ => return None,
+    })
+  }
+
+  /// Value of an expression such as `user.name`, `counter 'orders'` or
+  /// `firstName + ' ' + lastName`, numbers being added and anything else
+  /// concatenated
+  pub fn eval(&self, expr: &str) -> crate::Result<Value> {
+    let tokens = tokenize(expr)?;
+    let mut terms = tokens.split(|t| matches!(t, Token::Plus));
+    let mut ret = self.eval_term(expr, terms.next().unwrap_or_default())?;
+    for term in terms {
+      ret = match (ret, self.eval_term(expr, term)?) {
+        (Value::Integer(a), Value::Integer(b)) => Value::Integer(a + b),
+        (Value::Integer(a), Value::Float(b)) => Value::Float(a as f64 + b),
+        (Value::Float(a), Value::Integer(b)) => Value::Float(a + b as f64),
+        (Value::Float(a), Value::Float(b)) => Value::Float(a + b),
+        (a, b) => Value::String(render_value(&a) + &render_value(&b)),
+      };
+    }
+    Ok(ret)
+  }
+
+  /// Value of a helper call, a lookup or a literal
+  fn eval_term(&self, expr: &str, tokens: &[Token]) -> crate::Result<Value> {
+    let (name, args) = match tokens.split_first() {
+      Some((Token::Ident(name), args)) => (name, args),
+      Some((Token::Literal(v), [])) => return Ok(v.clone()),
+      _ => {
+        return Err(Error::new(
+          ErrorKind::Parse,
+          Some(format!("invalid template expression '{{{{{}}}}}'", expr)),
+          None,
+        ))
+      }
+    };
+    let args = args
+      .iter()
+      .map(|arg| match arg {
+        Token::Ident(path) => self.lookup(path),
+        Token::Literal(v) => v.clone(),
+        Token::Plus => Value::Null,
+      })
+      .collect::<Vec<_>>();
+    match self.helper(name, &args) {
+      Some(ret) => ret,
+      None if args.is_empty() => Ok(self.lookup(name)),
+      None => Err(Error::new(
+        ErrorKind::Parse,
+        Some(format!("unknown template helper '{}'", name)),
+        None,
+      )),
+    }
+  }
+}
+
+enum Token {
+  Ident(String),
+  Literal(Value),
+  Plus,
+}
+
+fn tokenize(expr: &str) -> crate::Result<Vec<Token>> {
+  let mut tokens = vec![];
+  let mut chars = expr.trim().chars().peekable();
+  while let Some(c) = chars.next() {
+    if c.is_whitespace() {
+      continue;
+    }
+    if c == '+' {
+      tokens.push(Token::Plus);
+      continue;
+    }
+    if c == '\'' || c == '"' {
+      let mut s = String::new();
+      loop {
+        match chars.next() {
+          Some(q) if q == c => break,
+          Some(ch) => s.push(ch),
+          None => {
+            return Err(Error::new(
+              ErrorKind::Parse,
+              Some(format!(
+                "unterminated string in template expression '{}'",
+                expr
+              )),
+              None,
+            ))
+          }
+        }
+      }
+      tokens.push(Token::Literal(Value::String(s)));
+      continue;
+    }
+    let mut word = String::from(c);
+    while let Some(ch) = chars.peek() {
+      if ch.is_whitespace() || *ch == '+' {
+        break;
+      }
+      word.push(*ch);
+      chars.next();
+    }
+    tokens.push(match word.as_str() {
+      "true" => Token::Literal(Value::Bool(true)),
+      "false" => Token::Literal(Value::Bool(false)),
+      "null" => Token::Literal(Value::Null),
+      _ => match (word.parse::<i128>(), word.parse::<f64>()) {
+        (Ok(i), _) => Token::Literal(Value::Integer(i)),
+        (_, Ok(f)) => Token::Literal(Value::Float(f)),
+        _ => Token::Ident(word),
+      },
+    });
+  }
+  Ok(tokens)
+}
+
+/// Name of a partial or layout, bare or quoted
+fn template_name(token: &Token) -> Option<String> {
+  match token {
+    Token::Ident(name) | Token::Literal(Value::String(name)) => Some(name.clone()),
+    _ => None,
+  }
+}
+
+/// Textual form of a value once interpolated in a template
+pub fn render_value(v: &Value) -> String {
+  match v {
+    Value::Null => String::new(),
+    Value::String(s) => s.clone(),
+    #[cfg(feature = "json")]
+    Value::Map(_) | Value::Array(_) => v.to_json().to_string(),
+    v => v.to_string(),
+  }
+}
+
+/// `source` with its `{{vars.name}}` expressions replaced by the variable of
+/// `vars` they name, as `escape` writes it, other expressions being kept
+pub fn substitute_vars<F: Fn(String) -> String>(source: &str, vars: &Value, escape: F) -> String {
+  let mut rest = source;
+  let mut out = String::with_capacity(rest.len());
+  while let Some(start) = rest.find("{{") {
+    let end = match rest[start..].find("}}") {
+      Some(end) => start + end,
+      None => break,
+    };
+    out.push_str(&rest[..start]);
+    let expr = rest[start + 2..end].trim();
+    match expr
+      .strip_prefix("vars.")
+      .and_then(|path| vars.get_path(path))
+    {
+      Some(value) => out.push_str(&escape(render_value(value))),
+      None => out.push_str(&rest[start..end + 2]),
+    }
+    rest = &rest[end + 2..];
+  }
+  out.push_str(rest);
+  out
+}
+
+/// Render every `{{expression}}` found in `source`.
+///
+/// `{{> name}}` includes the partial `templates/partials/<name>.hbs`, and
+/// `{{extends 'name'}}` renders `templates/layouts/<name>.hbs` in place of
+/// the template, with its output as `content`.
+pub fn render<S: AsRef<str>>(source: S, ctx: &TemplateContext) -> crate::Result<String> {
+  let mut rest = source.as_ref();
+  let mut out = String::with_capacity(rest.len());
+  let mut layout = None;
+  while let Some(start) = rest.find("{{") {
+    out.push_str(&rest[..start]);
+    let end = rest[start..].find("}}").ok_or_else(|| {
+      Error::new(
+        ErrorKind::Parse,
+        Some(format!(
+          "unterminated template expression at '{}'",
+          &rest[start..]
+        )),
+        None,
+      )
+    })?;
+    let expr = &rest[start + 2..start + end];
+    rest = &rest[start + end + 2..];
+    match expr.trim_start().strip_prefix('>') {
+      Some(partial) => out.push_str(&ctx.partial(partial)?),
+      None => match &tokenize(expr)?[..] {
+        [Token::Ident(directive), name] if directive == "extends" => {
+          layout = Some(template_name(name).ok_or_else(|| {
+            Error::new(
+              ErrorKind::Parse,
+              Some(format!("invalid layout '{{{{{}}}}}'", expr)),
+              None,
+            )
+          })?);
+          rest = rest.strip_prefix('\n').unwrap_or(rest);
+        }
+        _ => out.push_str(&render_value(&ctx.eval(expr)?)),
+      },
+    }
+  }
+  out.push_str(rest);
+  match layout {
+    Some(layout) => render(
+      ctx.template_source("layouts", &layout)?,
+      &ctx.nested().with_data("content", out),
+    ),
+    None => Ok(out),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashMap;
+
+  use crate::{Value, Variables};
+
+  use super::{render, TemplateContext};
+
+  #[test]
+  fn lookup() {
+    let vars = Variables::default();
+    let ctx = TemplateContext::new(&vars).with_data(
+      "user",
+      HashMap::from([
+        ("name".to_string(), Value::from("Joe")),
+        ("tags".to_string(), Value::from(vec![Value::from("admin")])),
+      ]),
+    );
+    assert_eq!(
+      render("Hello {{ user.name }} ({{user.tags.0}}){{missing}}", &ctx).unwrap(),
+      "Hello Joe (admin)"
+    );
+    assert!(render("{{user.name", &ctx).is_err());
+  }
+
+  #[test]
+  fn state() {
+    let vars = Variables::default();
+    let ctx = TemplateContext::new(&vars)
+      .with_scope("checkout")
+      .with_data("name", "Joe");
+    assert_eq!(render("{{counter 'orders'}}", &ctx).unwrap(), "1");
+    assert_eq!(render("{{counter \"orders\"}}", &ctx).unwrap(), "2");
+    assert_eq!(render("{{set 'lastUser' name}}", &ctx).unwrap(), "");
+    assert_eq!(render("{{get 'lastUser'}}", &ctx).unwrap(), "Joe");
+    let other = TemplateContext::new(&vars);
+    assert_eq!(render("{{counter 'orders'}}", &other).unwrap(), "1");
+    assert!(render("{{unknown 'x'}}", &other).is_err());
+    assert_eq!(render("{{sequence 'ids' 100 10}}", &ctx).unwrap(), "100");
+    assert_eq!(render("{{sequence 'ids' 100 10}}", &ctx).unwrap(), "110");
+    assert_eq!(render("{{uuid}}", &ctx).unwrap().len(), 36);
+    let png = render("{{png 4 4}}", &ctx).unwrap();
+    assert!(png.starts_with("iVBORw0KGgo"), "{}", png);
+    assert_eq!(render("{{random_bytes 12}}", &ctx).unwrap().len(), 16);
+  }
+
+  #[test]
+  fn operators() {
+    let vars = Variables::default();
+    let ctx = TemplateContext::new(&vars)
+      .with_data("first", "Ada")
+      .with_data("last", "Lovelace")
+      .with_data("born", "1815-12-10");
+    assert_eq!(
+      render("{{first + ' ' + last}}", &ctx).unwrap(),
+      "Ada Lovelace"
+    );
+    assert_eq!(ctx.eval("1+2").unwrap(), Value::Integer(3));
+    assert_eq!(ctx.eval("1 + 0.5").unwrap(), Value::Float(1.5));
+    let age = ctx.eval("age born").unwrap();
+    assert!(matches!(age, Value::Integer(years) if years > 200));
+    assert_eq!(ctx.eval("age missing").unwrap(), Value::Null);
+    assert!(ctx.eval("first +").is_err());
+  }
+
+  #[test]
+  fn partials() {
+    let dir = std::env::temp_dir().join(format!("mocker-templates-{}", std::process::id()));
+    for (path, source) in [
+      (
+        "partials/error.hbs",
+        "{\"code\": {{code}}, \"message\": \"{{message}}\"}\n",
+      ),
+      ("partials/loop.hbs", "{{> loop}}"),
+      (
+        "layouts/envelope.hbs",
+        "{\"data\": {{content}}, \"by\": \"{{name}}\"}",
+      ),
+      ("layouts/outer.hbs", "[{{content}}]"),
+      ("layouts/inner.hbs", "{{extends outer}}\n({{content}})"),
+    ] {
+      let path = dir.join(path);
+      std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+      std::fs::write(path, source).unwrap();
+    }
+    let vars = Variables::default();
+    let ctx = TemplateContext::new(&vars)
+      .with_templates_dir(&dir)
+      .with_data("name", "Joe")
+      .with_data(
+        "failure",
+        HashMap::from([
+          ("code".to_string(), Value::from(404)),
+          ("message".to_string(), Value::from("gone")),
+        ]),
+      );
+    assert_eq!(
+      render("{\"error\": {{> error failure}}}", &ctx).unwrap(),
+      "{\"error\": {\"code\": 404, \"message\": \"gone\"}}"
+    );
+    assert_eq!(
+      render("{{extends 'envelope'}}\n[{{name}}]", &ctx).unwrap(),
+      "{\"data\": [Joe], \"by\": \"Joe\"}"
+    );
+    assert_eq!(render("{{extends inner}}x", &ctx).unwrap(), "[(x)]");
+    assert!(render("{{> loop}}", &ctx).is_err());
+    assert!(render("{{> missing}}", &ctx).is_err());
+    assert!(render("{{> ../secret}}", &ctx).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+}
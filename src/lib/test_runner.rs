@@ -0,0 +1,278 @@
+use std::{
+  fs,
+  io::Write,
+  path::{Path, PathBuf},
+  time::{Duration, Instant},
+};
+
+use boa_engine::{
+  Context, JsNativeError, JsObject, JsResult, JsString, JsValue, NativeFunction, Source,
+};
+use boa_gc::{Finalize, Trace};
+
+use crate::{
+  fetch::request_from_options,
+  script::{settle, ScriptFile},
+  Config, Engine, FetchResponse, Method,
+};
+
+/// Globals of test files: `test`, `assert` and the `mock` client
+const TESTS_PRELUDE: &str = r#"
+var __tests = [];
+function test(name, fn) { __tests.push({ name, fn }); }
+function assert(cond, message) {
+  if (!cond) throw new Error(message || 'assertion failed');
+}
+assert.equal = function (actual, expected, message) {
+  const a = JSON.stringify(actual), e = JSON.stringify(expected);
+  if (a !== e) throw new Error((message ? message + ': ' : '') + 'expected ' + e + ', got ' + a);
+};
+assert.notEqual = function (actual, expected, message) {
+  const a = JSON.stringify(actual);
+  if (a === JSON.stringify(expected)) throw new Error((message ? message + ': ' : '') + 'expected anything but ' + a);
+};
+assert.status = function (res, status) {
+  assert.equal(res.status, status, 'status');
+};
+assert.throws = function (fn, message) {
+  try { fn(); } catch (e) { return e; }
+  throw new Error(message || 'expected an exception');
+};
+var mock = {
+  request: (method, path, options) => __mocker_request(method, path, options || {}),
+  get: (path, options) => mock.request('GET', path, options),
+  head: (path, options) => mock.request('HEAD', path, options),
+  delete: (path, options) => mock.request('DELETE', path, options),
+  options: (path, options) => mock.request('OPTIONS', path, options),
+  post: (path, body, options) => mock.request('POST', path, Object.assign({}, options, { body })),
+  put: (path, body, options) => mock.request('PUT', path, Object.assign({}, options, { body })),
+  patch: (path, body, options) => mock.request('PATCH', path, Object.assign({}, options, { body })),
+};
+"#;
+
+/// Engine the `mock` client of a test file sends its requests to
+#[derive(Trace, Finalize)]
+struct Mock(#[unsafe_ignore_trace] Engine);
+
+/// `__mocker_request(method, target, {headers, body})`, answered in-process
+fn mock_request(
+  _this: &JsValue,
+  args: &[JsValue],
+  mock: &Mock,
+  context: &mut Context,
+) -> JsResult<JsValue> {
+  let arg = |i: usize| args.get(i).cloned().unwrap_or_default();
+  let method = arg(0).to_string(context)?.to_std_string_escaped();
+  let target = arg(1).to_string(context)?.to_std_string_escaped();
+  let options = match arg(2).is_undefined() {
+    true => serde_json::Value::Null,
+    false => arg(2).to_json(context)?,
+  };
+  let method = method
+    .parse::<Method>()
+    .map_err(|e| JsNativeError::typ().with_message(e.to_string()))?;
+  let res = mock
+    .0
+    .handle(request_from_options(method, target, &options));
+  JsValue::from_json(&FetchResponse::from(&res).to_value().to_json(), context)
+}
+
+/// Result of one `test(name, fn)` of a test file
+#[derive(Debug, Clone)]
+pub struct TestOutcome {
+  pub file: PathBuf,
+  pub name: String,
+  pub error: Option<String>,
+  pub elapsed: Duration,
+}
+
+impl TestOutcome {
+  pub fn is_ok(&self) -> bool {
+    self.error.is_none()
+  }
+}
+
+/// Runs `*.test.js` files, each against an in-process engine serving the
+/// workspace, so the logic of script handlers can be tested like any code.
+pub struct TestRunner {
+  config: Config,
+}
+
+impl TestRunner {
+  pub const SUFFIX: &'static str = ".test.js";
+
+  pub fn new(config: Config) -> Self {
+    Self { config }
+  }
+
+  /// Test files below `dir`, hidden directories and `node_modules` aside
+  pub fn discover<P: AsRef<Path>>(dir: P) -> crate::Result<Vec<PathBuf>> {
+    let mut ret = vec![];
+    for entry in fs::read_dir(dir)? {
+      let path = entry?.path();
+      let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+      if path.is_dir() {
+        if !name.starts_with('.') && name != "node_modules" {
+          ret.extend(Self::discover(&path)?);
+        }
+      } else if name.ends_with(Self::SUFFIX) {
+        ret.push(path);
+      }
+    }
+    ret.sort();
+    Ok(ret)
+  }
+
+  /// Run every test declared by the file at `path`, against a fresh engine
+  pub fn run_file<P: AsRef<Path>>(&self, path: P) -> crate::Result<Vec<TestOutcome>> {
+    let path = path.as_ref();
+    let engine = Engine::from_config(&self.config)?;
+    let dir = path
+      .parent()
+      .filter(|p| !p.as_os_str().is_empty())
+      .unwrap_or(Path::new("."));
+    let outcome = |name: &str, error: Option<String>, elapsed| TestOutcome {
+      file: path.to_path_buf(),
+      name: name.to_string(),
+      error,
+      elapsed,
+    };
+    let started = Instant::now();
+    let file = ScriptFile::load(path, dir, |context| {
+      context.register_global_callable(
+        JsString::from("__mocker_request"),
+        3,
+        NativeFunction::from_copy_closure_with_captures(mock_request, Mock(engine)),
+      )?;
+      context.eval(Source::from_bytes(TESTS_PRELUDE))?;
+      Ok(())
+    });
+    let mut file = match file {
+      Ok(file) => file,
+      Err(e) => return Ok(vec![outcome("(load)", Some(e), started.elapsed())]),
+    };
+    let context = file.context();
+    let err = |e: boa_engine::JsError| e.to_string();
+    let tests = context
+      .global_object()
+      .get(JsString::from("__tests"), context)
+      .map_err(|e| crate::Error::new(crate::ErrorKind::Parse, Some(err(e)), None))?;
+    let tests = tests
+      .as_object()
+      .cloned()
+      .unwrap_or_else(|| JsObject::with_null_proto());
+    let count = tests
+      .get(JsString::from("length"), context)
+      .ok()
+      .and_then(|l| l.as_number())
+      .unwrap_or_default() as u32;
+    let mut ret = vec![];
+    for i in 0..count {
+      let started = Instant::now();
+      let test = tests
+        .get(i, context)
+        .ok()
+        .and_then(|t| t.as_object().cloned());
+      let test = match test {
+        Some(test) => test,
+        None => continue,
+      };
+      let name = test
+        .get(JsString::from("name"), context)
+        .and_then(|n| n.to_string(context))
+        .map(|n| n.to_std_string_escaped())
+        .unwrap_or_default();
+      let result = test
+        .get(JsString::from("fn"), context)
+        .map_err(err)
+        .and_then(|f| match f.as_callable() {
+          Some(f) => f.call(&JsValue::undefined(), &[], context).map_err(err),
+          None => Err("not a function".to_string()),
+        })
+        .and_then(|ret| settle(context, ret));
+      ret.push(outcome(&name, result.err(), started.elapsed()));
+    }
+    Ok(ret)
+  }
+
+  pub fn write_report<W: Write>(outcomes: &[TestOutcome], mut w: W) -> crate::Result<()> {
+    let mut file = None;
+    for outcome in outcomes {
+      if file != Some(&outcome.file) {
+        writeln!(w, "{}", outcome.file.display())?;
+        file = Some(&outcome.file);
+      }
+      let ms = outcome.elapsed.as_secs_f64() * 1000.0;
+      match &outcome.error {
+        None => writeln!(w, "  ✔ {} ({:.1}ms)", outcome.name, ms)?,
+        Some(error) => {
+          writeln!(w, "  ✘ {} ({:.1}ms)", outcome.name, ms)?;
+          writeln!(w, "      {}", error)?;
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use crate::{Config, Method, Route, RouteKind, Value};
+
+  use super::TestRunner;
+
+  #[test]
+  fn run() {
+    let dir = std::env::temp_dir().join(format!("mocker-tests-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("node_modules")).unwrap();
+    fs::write(dir.join("node_modules/skipped.test.js"), "").unwrap();
+    fs::write(
+      dir.join("total.js"),
+      "exports.total = (items) => items.reduce((a, b) => a + b, 0);",
+    )
+    .unwrap();
+    fs::write(
+      dir.join("api.test.js"),
+      r#"const { total } = require("./total");
+test("health", () => {
+  const res = mock.get("/health");
+  assert.status(res, 200);
+  assert.equal(res.body, "ok");
+});
+test("total", () => assert.equal(total([1, 2]), 3));
+test("fails", () => assert.equal(mock.get("/nope").status, 200));"#,
+    )
+    .unwrap();
+    let config = Config {
+      routes: vec![Route::new(
+        vec![Method::Get],
+        "/health",
+        RouteKind::Fixture {
+          status: 200,
+          headers: Default::default(),
+          body: Some(Value::from("ok")),
+          file: None,
+          template: false,
+        },
+      )],
+      ..Default::default()
+    };
+    let files = TestRunner::discover(&dir).unwrap();
+    assert_eq!(files, vec![dir.join("api.test.js")]);
+    let outcomes = TestRunner::new(config).run_file(&files[0]).unwrap();
+    let failed = outcomes
+      .iter()
+      .filter(|o| !o.is_ok())
+      .map(|o| o.name.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(outcomes.len(), 3);
+    assert_eq!(failed, vec!["fails"]);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+}
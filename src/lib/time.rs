@@ -0,0 +1,204 @@
+use std::{
+  sync::{
+    atomic::{AtomicI64, Ordering},
+    Arc,
+  },
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+use crate::{Error, ErrorKind};
+
+/// Milliseconds elapsed since the unix epoch, shifted by `offset` milliseconds
+pub fn now_millis(offset: i64) -> u128 {
+  let real = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis())
+    .unwrap_or_default() as i128;
+  (real + offset as i128).max(0) as u128
+}
+
+/// Virtual clock of a router, shifted from the system clock by the admin API.
+/// Clones share the same offset.
+#[derive(Debug, Default, Clone)]
+pub struct VirtualClock(Arc<AtomicI64>);
+
+impl VirtualClock {
+  /// How far the clock is ahead (or behind, if negative) of the system clock
+  pub fn offset(&self) -> i64 {
+    self.0.load(Ordering::Relaxed)
+  }
+
+  /// Move the clock `offset` milliseconds away from the system clock
+  pub fn set_offset(&self, offset: i64) {
+    self.0.store(offset, Ordering::Relaxed);
+  }
+
+  /// Milliseconds elapsed since the unix epoch, according to this clock
+  pub fn now_millis(&self) -> u128 {
+    now_millis(self.offset())
+  }
+}
+
+/// Current state of the virtual clock, as reported by the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct Clock {
+  pub now: u128,
+  pub offset: i64,
+}
+
+impl Clock {
+  pub fn of(clock: &VirtualClock) -> Self {
+    let offset = clock.offset();
+    Self {
+      now: now_millis(offset),
+      offset,
+    }
+  }
+}
+
+/// UTC year, month and day of a unix timestamp in milliseconds
+pub fn civil_date(millis: u128) -> (i64, u32, u32) {
+  // civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+  let z = (millis / 86_400_000) as i64 + 719_468;
+  let era = z.div_euclid(146_097);
+  let doe = z.rem_euclid(146_097);
+  let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let d = doy - (153 * mp + 2) / 5 + 1;
+  let m = if mp < 10 { mp + 3 } else { mp - 9 };
+  let y = yoe + era * 400 + i64::from(m <= 2);
+  (y, m as u32, d as u32)
+}
+
+/// Year, month and day of a date starting with `YYYY-MM-DD`, such as an
+/// ISO 8601 timestamp
+pub fn parse_date<S: AsRef<str>>(s: S) -> Option<(i64, u32, u32)> {
+  let s = s.as_ref().trim();
+  let mut parts = s.get(..10)?.splitn(3, '-');
+  let (y, m, d) = (parts.next()?, parts.next()?, parts.next()?);
+  let ret = (y.parse().ok()?, m.parse().ok()?, d.parse().ok()?);
+  ((1..=12).contains(&ret.1) && (1..=31).contains(&ret.2)).then_some(ret)
+}
+
+/// UTC hours, minutes and seconds of a unix timestamp in milliseconds
+fn civil_time(millis: u128) -> (u32, u32, u32) {
+  let secs = (millis / 1000 % 86_400) as u32;
+  (secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// A unix timestamp in milliseconds as `2006-01-02T15:04:05.000Z`
+pub fn format_iso8601(millis: u128) -> String {
+  let (y, mo, d) = civil_date(millis);
+  let (h, mi, s) = civil_time(millis);
+  format!(
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+    y,
+    mo,
+    d,
+    h,
+    mi,
+    s,
+    millis % 1000
+  )
+}
+
+/// A unix timestamp in milliseconds as an HTTP date, `Mon, 02 Jan 2006 15:04:05 GMT`
+pub fn format_http_date(millis: u128) -> String {
+  const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+  const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+  ];
+  let (y, mo, d) = civil_date(millis);
+  let (h, mi, s) = civil_time(millis);
+  format!(
+    "{}, {:02} {} {:04} {:02}:{:02}:{:02} GMT",
+    DAYS[(millis / 86_400_000 % 7) as usize],
+    d,
+    MONTHS[mo as usize - 1],
+    y,
+    h,
+    mi,
+    s
+  )
+}
+
+/// Parse a signed duration such as `+2h` or `-30m` into milliseconds
+pub fn parse_offset<S: AsRef<str>>(s: S) -> crate::Result<i64> {
+  let s = s.as_ref().trim();
+  let (sign, rest) = match s.strip_prefix('-') {
+    Some(rest) => (-1, rest),
+    None => (1, s.strip_prefix('+').unwrap_or(s)),
+  };
+  Ok(sign * parse_duration(rest)?.as_millis() as i64)
+}
+
+/// Parse a human duration such as `250ms`, `5s`, `2m`, `1h` or `3d`.
+/// A bare number is interpreted as milliseconds.
+pub fn parse_duration<S: AsRef<str>>(s: S) -> crate::Result<Duration> {
+  let s = s.as_ref().trim();
+  let split = s
+    .find(|c: char| !c.is_ascii_digit() && c != '.')
+    .unwrap_or(s.len());
+  let (value, unit) = s.split_at(split);
+  let value = value.parse::<f64>().map_err(|e| {
+    Error::new(
+      ErrorKind::Parse,
+      Some(format!("invalid duration '{}': {}", s, e)),
+      None,
+    )
+  })?;
+  let millis = match unit.trim() {
+    "" | "ms" => value,
+    "s" => value * 1_000.0,
+    "m" => value * 60_000.0,
+    "h" => value * 3_600_000.0,
+    "d" => value * 86_400_000.0,
+    unit => {
+      return Err(Error::new(
+        ErrorKind::Parse,
+        Some(format!("unknown duration unit '{}' in '{}'", unit, s)),
+        None,
+      ))
+    }
+  };
+  Ok(Duration::from_micros((millis * 1_000.0) as u64))
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::{format_http_date, format_iso8601, parse_duration, parse_offset};
+
+  #[test]
+  fn durations() {
+    assert_eq!(parse_duration("250").unwrap(), Duration::from_millis(250));
+    assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+    assert_eq!(parse_duration("5s").unwrap(), Duration::from_secs(5));
+    assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+    assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+    assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+    assert!(parse_duration("3y").is_err());
+    assert!(parse_duration("s").is_err());
+  }
+
+  #[test]
+  fn dates() {
+    assert_eq!(format_iso8601(951_827_696_789), "2000-02-29T12:34:56.789Z");
+    assert_eq!(
+      format_http_date(951_827_696_789),
+      "Tue, 29 Feb 2000 12:34:56 GMT"
+    );
+  }
+
+  #[test]
+  fn offsets() {
+    assert_eq!(parse_offset("+2h").unwrap(), 7_200_000);
+    assert_eq!(parse_offset("-30m").unwrap(), -1_800_000);
+    assert_eq!(parse_offset("1s").unwrap(), 1_000);
+    assert!(parse_offset("+").is_err());
+  }
+}
@@ -0,0 +1,351 @@
+use std::{path::PathBuf, thread, time::Duration};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "server")]
+use crate::Client;
+use crate::{parse_duration, Request, Response};
+
+/// How failed upstream calls are retried: transport errors and `statuses` are
+/// retried up to `attempts` more times, waiting `backoff` doubled each time.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RetryPolicy {
+  #[serde(default)]
+  pub attempts: u32,
+  #[serde(default = "RetryPolicy::default_backoff")]
+  pub backoff: String,
+  #[serde(default = "RetryPolicy::default_statuses")]
+  pub statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+  fn default() -> Self {
+    Self {
+      attempts: 0,
+      backoff: Self::default_backoff(),
+      statuses: Self::default_statuses(),
+    }
+  }
+}
+
+impl RetryPolicy {
+  fn default_backoff() -> String {
+    "200ms".to_string()
+  }
+
+  fn default_statuses() -> Vec<u16> {
+    vec![502, 503, 504]
+  }
+
+  fn retries(&self, ret: &crate::Result<Response>) -> bool {
+    match ret {
+      Ok(res) => self.statuses.contains(&res.status()),
+      Err(_) => true,
+    }
+  }
+
+  /// Delay before retry number `attempt`, starting at 0
+  pub fn delay(&self, attempt: u32) -> crate::Result<Duration> {
+    Ok(parse_duration(&self.backoff)? * 2u32.saturating_pow(attempt))
+  }
+
+  /// Call `f` until it succeeds or the policy gives up
+  pub fn run<F: FnMut() -> crate::Result<Response>>(&self, mut f: F) -> crate::Result<Response> {
+    let mut ret = f();
+    for attempt in 0..self.attempts {
+      if !self.retries(&ret) {
+        break;
+      }
+      debug!("Retrying upstream call ({}/{})", attempt + 1, self.attempts);
+      thread::sleep(self.delay(attempt)?);
+      ret = f();
+    }
+    ret
+  }
+}
+
+/// Settings shared by every outgoing call (proxying, recording, webhooks,
+/// contract verification), read from the `upstream` section of the workspace.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UpstreamConfig {
+  /// Per-request timeout, e.g. `10s`
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub timeout: Option<String>,
+  /// Proxy url every call goes through
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub proxy: Option<String>,
+  /// Additional PEM root certificate to trust
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub ca_cert: Option<PathBuf>,
+  /// Accept invalid TLS certificates
+  #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+  pub insecure: bool,
+  #[serde(default)]
+  pub retry: RetryPolicy,
+}
+
+impl UpstreamConfig {
+  pub fn is_default(&self) -> bool {
+    *self == Self::default()
+  }
+
+  #[cfg(any(feature = "server", feature = "reqwest"))]
+  fn timeout(&self) -> crate::Result<Option<Duration>> {
+    self.timeout.as_deref().map(parse_duration).transpose()
+  }
+
+  /// Blocking client for `url`, backed by reqwest when the feature is enabled
+  /// and by the built-in plain http `Client` otherwise.
+  #[cfg(any(feature = "server", feature = "reqwest"))]
+  pub fn client<U: AsRef<str>>(&self, url: U) -> crate::Result<Box<dyn UpstreamClient>> {
+    #[cfg(feature = "reqwest")]
+    let client = ReqwestClient::new(url, self)?;
+    #[cfg(not(feature = "reqwest"))]
+    let client = {
+      if self.proxy.is_some() || self.ca_cert.is_some() || self.insecure {
+        return Err(crate::Error::new(
+          crate::ErrorKind::Parse,
+          Some("upstream proxy and TLS settings require the `reqwest` feature".to_string()),
+          None,
+        ));
+      }
+      Client::from_url(url)?.with_timeout(self.timeout()?)
+    };
+    Ok(Box::new(Retrying::new(client, self.retry.clone())))
+  }
+
+  /// Async client for `url`
+  #[cfg(feature = "reqwest")]
+  pub fn async_client<U: AsRef<str>>(&self, url: U) -> crate::Result<AsyncReqwestClient> {
+    AsyncReqwestClient::new(url, self)
+  }
+}
+
+/// Sends requests to a real backend.
+pub trait UpstreamClient: Send + Sync {
+  fn send(&self, req: &Request) -> crate::Result<Response>;
+}
+
+#[cfg(feature = "server")]
+impl UpstreamClient for Client {
+  fn send(&self, req: &Request) -> crate::Result<Response> {
+    Client::send(self, req)
+  }
+}
+
+impl<C: UpstreamClient + ?Sized> UpstreamClient for Box<C> {
+  fn send(&self, req: &Request) -> crate::Result<Response> {
+    (**self).send(req)
+  }
+}
+
+/// Wraps a client to retry its calls according to a [`RetryPolicy`].
+pub struct Retrying<C> {
+  client: C,
+  policy: RetryPolicy,
+}
+
+impl<C: UpstreamClient> Retrying<C> {
+  pub fn new(client: C, policy: RetryPolicy) -> Self {
+    Self { client, policy }
+  }
+}
+
+impl<C: UpstreamClient> UpstreamClient for Retrying<C> {
+  fn send(&self, req: &Request) -> crate::Result<Response> {
+    self.policy.run(|| self.client.send(req))
+  }
+}
+
+#[cfg(feature = "reqwest")]
+mod reqwest_client {
+  use std::{future::Future, pin::Pin};
+
+  use http::HeaderMap;
+
+  use super::UpstreamConfig;
+  use crate::{Error, ErrorKind, Request, Response};
+
+  macro_rules! configure {
+    ($builder:expr, $config:expr) => {{
+      let config: &UpstreamConfig = $config;
+      let mut builder = $builder.danger_accept_invalid_certs(config.insecure);
+      if let Some(timeout) = config.timeout()? {
+        builder = builder.timeout(timeout);
+      }
+      if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+      }
+      if let Some(path) = &config.ca_cert {
+        builder =
+          builder.add_root_certificate(reqwest::Certificate::from_pem(&std::fs::read(path)?)?);
+      }
+      builder.build()?
+    }};
+  }
+
+  fn url(base: &str, req: &Request) -> crate::Result<String> {
+    let target = req
+      .start_line()
+      .as_request()
+      .map(|s| s.target.clone())
+      .ok_or_else(|| Error::new(ErrorKind::Parse, Some("not a request".to_string()), None))?;
+    Ok(format!("{}{}", base, target))
+  }
+
+  fn base(url: &str) -> String {
+    match url.contains("://") {
+      true => url.trim_end_matches('/').to_string(),
+      false => format!("http://{}", url.trim_end_matches('/')),
+    }
+  }
+
+  fn response(status: u16, headers: HeaderMap, body: Vec<u8>) -> crate::Result<Response> {
+    let mut res = http::Response::new(body);
+    *res.status_mut() = http::StatusCode::from_u16(status)
+      .map_err(|e| Error::new(ErrorKind::Parse, Some(e.to_string()), None))?;
+    *res.headers_mut() = headers;
+    Response::try_from(res)
+  }
+
+  /// Blocking client backed by reqwest, supporting https and proxies.
+  pub struct ReqwestClient {
+    base: String,
+    client: reqwest::blocking::Client,
+  }
+
+  impl ReqwestClient {
+    pub fn new<U: AsRef<str>>(url: U, config: &UpstreamConfig) -> crate::Result<Self> {
+      Ok(Self {
+        base: base(url.as_ref()),
+        client: configure!(reqwest::blocking::Client::builder(), config),
+      })
+    }
+  }
+
+  impl super::UpstreamClient for ReqwestClient {
+    fn send(&self, req: &Request) -> crate::Result<Response> {
+      let method = req
+        .method()
+        .ok_or_else(|| Error::new(ErrorKind::Parse, Some("not a request".to_string()), None))?;
+      let res = self
+        .client
+        .request(method.into(), url(&self.base, req)?)
+        .headers(HeaderMap::try_from(&**req)?)
+        .body(req.body().clone())
+        .send()?;
+      let (status, headers) = (res.status().as_u16(), res.headers().clone());
+      response(status, headers, res.bytes()?.to_vec())
+    }
+  }
+
+  /// Sends requests to a real backend without blocking the calling task.
+  pub trait AsyncUpstreamClient: Send + Sync {
+    fn send<'a>(
+      &'a self,
+      req: &'a Request,
+    ) -> Pin<Box<dyn Future<Output = crate::Result<Response>> + Send + 'a>>;
+  }
+
+  /// Async client backed by reqwest, retrying according to the workspace policy.
+  pub struct AsyncReqwestClient {
+    base: String,
+    client: reqwest::Client,
+    config: UpstreamConfig,
+  }
+
+  impl AsyncReqwestClient {
+    pub fn new<U: AsRef<str>>(url: U, config: &UpstreamConfig) -> crate::Result<Self> {
+      Ok(Self {
+        base: base(url.as_ref()),
+        client: configure!(reqwest::Client::builder(), config),
+        config: config.clone(),
+      })
+    }
+
+    async fn send_once(&self, req: &Request) -> crate::Result<Response> {
+      let method = req
+        .method()
+        .ok_or_else(|| Error::new(ErrorKind::Parse, Some("not a request".to_string()), None))?;
+      let res = self
+        .client
+        .request(method.into(), url(&self.base, req)?)
+        .headers(HeaderMap::try_from(&**req)?)
+        .body(req.body().clone())
+        .send()
+        .await?;
+      let (status, headers) = (res.status().as_u16(), res.headers().clone());
+      response(status, headers, res.bytes().await?.to_vec())
+    }
+  }
+
+  impl AsyncUpstreamClient for AsyncReqwestClient {
+    fn send<'a>(
+      &'a self,
+      req: &'a Request,
+    ) -> Pin<Box<dyn Future<Output = crate::Result<Response>> + Send + 'a>> {
+      Box::pin(async move {
+        let policy = &self.config.retry;
+        let mut ret = self.send_once(req).await;
+        for attempt in 0..policy.attempts {
+          if !policy.retries(&ret) {
+            break;
+          }
+          tokio::time::sleep(policy.delay(attempt)?).await;
+          ret = self.send_once(req).await;
+        }
+        ret
+      })
+    }
+  }
+}
+
+#[cfg(feature = "reqwest")]
+pub use reqwest_client::*;
+
+#[cfg(test)]
+mod tests {
+  use std::sync::atomic::{AtomicU16, Ordering};
+
+  use crate::{Method, Request, Response};
+
+  use super::{RetryPolicy, Retrying, UpstreamClient, UpstreamConfig};
+
+  struct Flaky(AtomicU16);
+
+  impl UpstreamClient for Flaky {
+    fn send(&self, _req: &Request) -> crate::Result<Response> {
+      let status = match self.0.fetch_add(1, Ordering::SeqCst) {
+        0 => 503,
+        _ => 200,
+      };
+      Ok(Response::default().with_status_code(status))
+    }
+  }
+
+  #[test]
+  fn retry() {
+    let req = Request::new(Method::Get, "/");
+    let policy = RetryPolicy {
+      attempts: 2,
+      backoff: "1ms".to_string(),
+      ..Default::default()
+    };
+    let client = Retrying::new(Flaky(AtomicU16::new(0)), policy.clone());
+    assert_eq!(client.send(&req).unwrap().status(), 200);
+    let client = Retrying::new(Flaky(AtomicU16::new(0)), RetryPolicy::default());
+    assert_eq!(client.send(&req).unwrap().status(), 503);
+    assert_eq!(policy.delay(2).unwrap().as_millis(), 4);
+  }
+
+  #[cfg(all(feature = "json", feature = "server"))]
+  #[test]
+  fn config() {
+    let config: UpstreamConfig =
+      serde_json::from_str(r#"{"timeout": "5s", "retry": {"attempts": 3}}"#).unwrap();
+    assert_eq!(config.retry.attempts, 3);
+    assert_eq!(config.retry.statuses, vec![502, 503, 504]);
+    assert!(config.client("http://localhost:1").is_ok());
+  }
+}
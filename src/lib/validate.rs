@@ -0,0 +1,165 @@
+use std::str::FromStr;
+
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  schema_errors, ContractCheck, Error, ErrorKind, MediaType, Response, Route, Router, Status, Value,
+};
+
+/// Smoke test of a workspace: every route is called in-process with its
+/// sample request, and its response checked against the route's schema and
+/// size limit.
+pub struct Validation<'a> {
+  router: &'a Router,
+}
+
+impl<'a> Validation<'a> {
+  pub fn new(router: &'a Router) -> Self {
+    Self { router }
+  }
+
+  pub fn check(&self) -> crate::Result<Vec<ContractCheck>> {
+    let mut ret = vec![];
+    for route in self.router.routes()? {
+      for method in route.methods() {
+        ret.push(ContractCheck {
+          method: *method,
+          endpoint: route.endpoint().clone(),
+          drifts: self.problems(&route, *method),
+        });
+      }
+    }
+    Ok(ret)
+  }
+
+  fn problems(&self, route: &Route, method: crate::Method) -> Vec<String> {
+    let options = route.options();
+    let req = options
+      .sample
+      .clone()
+      .unwrap_or_default()
+      .to_request(method, route);
+    let res = match self.router.dispatch(&req, Response::default()) {
+      Ok(res) => res,
+      Err(e) => return vec![format!("handler failed: {}", e)],
+    };
+    let mut problems = vec![];
+    if res.status() >= 500 {
+      problems.push(format!("responded with status {}", res.status()));
+    }
+    if let Some(max) = options.max_size {
+      if res.body().len() > max {
+        problems.push(format!(
+          "body is {} bytes, more than the {} allowed",
+          res.body().len(),
+          max
+        ));
+      }
+    }
+    let body = match response_value(&res) {
+      Ok(body) => body,
+      Err(e) => {
+        problems.push(e);
+        return problems;
+      }
+    };
+    if let Some(schema) = &options.schema {
+      schema_errors("body", schema, &body, &mut problems);
+    }
+    problems
+  }
+}
+
+/// Body of `res`, parsed according to its content type
+pub(crate) fn response_value(res: &Response) -> Result<Value, String> {
+  let json = res
+    .header("Content-Type")
+    .and_then(|ct| ct.parse::<MediaType>().ok())
+    .is_some_and(|ct| ct.is_json());
+  #[cfg(feature = "json")]
+  if json {
+    return serde_json::from_slice::<Value>(res.body())
+      .map_err(|e| format!("body is not valid JSON: {}", e));
+  }
+  if json {
+    return Err("cannot parse JSON body, missing feature".to_string());
+  }
+  std::str::from_utf8(res.body())
+    .map(|body| match body.is_empty() {
+      true => Value::Null,
+      false => Value::from(body),
+    })
+    .map_err(|e| format!("body is not valid UTF-8: {}", e))
+}
+
+/// How served responses are checked against their route's `schema`, to
+/// catch a mock drifting from the contract it stands for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResponseCheck {
+  #[default]
+  Off,
+  /// Log a warning for every non-conforming response
+  Warn,
+  /// Replace non-conforming responses with a 500 listing the problems
+  Strict,
+}
+
+impl ResponseCheck {
+  pub fn is_off(&self) -> bool {
+    *self == ResponseCheck::Off
+  }
+
+  /// Check `res`, served by `route`, according to this mode
+  pub fn apply(&self, route: &Route, res: Response) -> crate::Result<Response> {
+    let schema = match (self, &route.options().schema) {
+      (ResponseCheck::Off, _) | (_, None) => return Ok(res),
+      (_, Some(schema)) => schema,
+    };
+    let mut problems = vec![];
+    match response_value(&res) {
+      Ok(body) => schema_errors("body", schema, &body, &mut problems),
+      Err(e) => problems.push(e),
+    }
+    if problems.is_empty() {
+      return Ok(res);
+    }
+    let message = format!(
+      "response of {} does not match its schema: {}",
+      route.id(),
+      problems.join(", ")
+    );
+    match self {
+      ResponseCheck::Strict => {
+        error!("{}", message);
+        Err(Error::new(
+          ErrorKind::Api(Status::InternalServerError),
+          Some(message),
+          None,
+        ))
+      }
+      _ => {
+        warn!("{}", message);
+        Ok(res)
+      }
+    }
+  }
+}
+
+impl FromStr for ResponseCheck {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "off" => Ok(ResponseCheck::Off),
+      "warn" => Ok(ResponseCheck::Warn),
+      "strict" => Ok(ResponseCheck::Strict),
+      _ => Err(Error::new(
+        ErrorKind::Parse,
+        Some(format!("unknown response check '{}'", s)),
+        None,
+      )),
+    }
+  }
+}
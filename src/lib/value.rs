@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fmt::Display;
 
@@ -7,8 +8,9 @@ use serde::{Deserialize, Serialize};
 
 use crate::{Error, ErrorKind};
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Default)]
 pub enum Value {
+  #[default]
   Null,
   Bool(bool),
   Float(f64),
@@ -23,10 +25,97 @@ impl Value {
   pub fn loose_eq(&self, other: &Value) -> bool {
     format!("{}", self).eq(&format!("{}", other))
   }
+
+  /// Resolve a dotted path such as `user.name` or `items.0.id`
+  pub fn get_path<P: AsRef<str>>(&self, path: P) -> Option<&Value> {
+    let mut current = self;
+    for part in path.as_ref().split('.').filter(|part| !part.is_empty()) {
+      current = match current {
+        Value::Map(m) => m.get(part)?,
+        Value::Array(a) => a.get(part.parse::<usize>().ok()?)?,
+        _ => return None,
+      };
+    }
+    Some(current)
+  }
+
+  /// Apply `patch` as a JSON merge patch (RFC 7386): maps are merged
+  /// recursively, `null` removes a key, anything else replaces the value
+  pub fn merge_patch(&mut self, patch: Value) {
+    match (self, patch) {
+      (Value::Map(target), Value::Map(patch)) => merge_patch(target, patch),
+      (target, Value::Map(patch)) => {
+        let mut map = HashMap::new();
+        merge_patch(&mut map, patch);
+        *target = Value::Map(map);
+      }
+      (target, patch) => *target = patch,
+    }
+  }
+
+  /// Total order of values: numbers numerically whatever their
+  /// representation, strings lexically, arrays element-wise, and values of
+  /// different types by type: null, booleans, numbers, strings, arrays, maps
+  pub fn loose_cmp(&self, other: &Value) -> Ordering {
+    let rank = |v: &Value| match v {
+      Value::Null => 0,
+      Value::Bool(_) => 1,
+      Value::Float(_) | Value::Integer(_) | Value::Unsigned(_) => 2,
+      Value::String(_) => 3,
+      Value::Array(_) => 4,
+      Value::Map(_) => 5,
+    };
+    match (self, other) {
+      (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+      (Value::Integer(a), Value::Integer(b)) => a.cmp(b),
+      (Value::Unsigned(a), Value::Unsigned(b)) => a.cmp(b),
+      (Value::Integer(a), Value::Unsigned(b)) => match u128::try_from(*a) {
+        Ok(a) => a.cmp(b),
+        Err(_) => Ordering::Less,
+      },
+      (Value::Unsigned(_), Value::Integer(_)) => other.loose_cmp(self).reverse(),
+      (Value::String(a), Value::String(b)) => a.cmp(b),
+      (Value::Array(a), Value::Array(b)) => a
+        .iter()
+        .zip(b)
+        .map(|(a, b)| a.loose_cmp(b))
+        .find(|o| o.is_ne())
+        .unwrap_or_else(|| a.len().cmp(&b.len())),
+      (Value::Map(a), Value::Map(b)) => a.len().cmp(&b.len()),
+      (a, b) if rank(a) == 2 && rank(b) == 2 => {
+        let float = |v: &Value| match v {
+          Value::Float(v) => *v,
+          Value::Integer(v) => *v as f64,
+          Value::Unsigned(v) => *v as f64,
+          _ => 0.0,
+        };
+        float(a).total_cmp(&float(b))
+      }
+      (a, b) => rank(a).cmp(&rank(b)),
+    }
+  }
+
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Value::Null => "null",
+      Value::Bool(_) => "boolean",
+      Value::Float(_) | Value::Integer(_) | Value::Unsigned(_) => "number",
+      Value::String(_) => "string",
+      Value::Map(_) => "object",
+      Value::Array(_) => "array",
+    }
+  }
 }
-impl Default for Value {
-  fn default() -> Self {
-    Self::Null
+
+/// Apply the fields of `patch` to `target`, see [`Value::merge_patch`]
+pub fn merge_patch(target: &mut HashMap<String, Value>, patch: HashMap<String, Value>) {
+  for (key, value) in patch {
+    match value {
+      Value::Null => {
+        target.remove(&key);
+      }
+      value => target.entry(key).or_default().merge_patch(value),
+    }
   }
 }
 
@@ -45,7 +134,7 @@ impl Display for Value {
         Self::Float(v) => format!("{}", v),
         Self::Integer(v) => format!("{}", v),
         Self::Unsigned(v) => format!("{}", v),
-        Self::String(v) => format!("{}", v),
+        Self::String(v) => v.to_string(),
         Self::Map(v) => format!("{:?}", v),
         Self::Array(v) => format!("{:?}", v),
       }
@@ -105,25 +194,25 @@ impl From<BTreeMap<String, Value>> for Value {
 
 impl<const N: usize> From<&[Value; N]> for Value {
   fn from(value: &[Value; N]) -> Self {
-    Value::Array(value.iter().map(|v| v.clone()).collect::<Vec<_>>())
+    Value::Array(value.iter().cloned().collect::<Vec<_>>())
   }
 }
 
 impl<const N: usize> From<[Value; N]> for Value {
   fn from(value: [Value; N]) -> Self {
-    Value::Array(value.iter().map(|v| v.clone()).collect::<Vec<_>>())
+    Value::Array(value.iter().cloned().collect::<Vec<_>>())
   }
 }
 
 impl From<Vec<Value>> for Value {
   fn from(value: Vec<Value>) -> Self {
-    Value::Array(value.iter().map(|v| v.clone()).collect::<Vec<_>>())
+    Value::Array(value.to_vec())
   }
 }
 
 impl From<VecDeque<Value>> for Value {
   fn from(value: VecDeque<Value>) -> Self {
-    Value::Array(value.iter().map(|v| v.clone()).collect::<Vec<_>>())
+    Value::Array(value.iter().cloned().collect::<Vec<_>>())
   }
 }
 
@@ -184,7 +273,7 @@ impl Value {
               Some(format!("invalid floating value: {}", v)),
               None,
             )
-          })? as f64)
+          })?)
         }
       }
       serde_json::Value::String(v) => Self::String(v),
@@ -208,10 +297,10 @@ impl Value {
   pub fn to_json(&self) -> serde_json::Value {
     match self {
       Self::Null => serde_json::Value::Null,
-      Self::Bool(v) => serde_json::Value::Bool(v.clone()),
-      Self::Float(v) => serde_json::Value::Number(serde_json::Number::from_f64(v.clone()).unwrap()),
-      Self::Integer(v) => serde_json::Value::Number(serde_json::Number::from(v.clone() as i64)),
-      Self::Unsigned(v) => serde_json::Value::Number(serde_json::Number::from(v.clone() as u64)),
+      Self::Bool(v) => serde_json::Value::Bool(*v),
+      Self::Float(v) => serde_json::Value::Number(serde_json::Number::from_f64(*v).unwrap()),
+      Self::Integer(v) => serde_json::Value::Number(serde_json::Number::from(*v as i64)),
+      Self::Unsigned(v) => serde_json::Value::Number(serde_json::Number::from(*v as u64)),
       Self::String(v) => serde_json::Value::String(v.clone()),
       Self::Map(v) => serde_json::Value::Object(serde_json::Map::from_iter(
         v.iter()
@@ -537,6 +626,13 @@ impl<'de> Visitor<'de> for ValueVisitor {
   {
     Ok(Value::Null)
   }
+
+  fn visit_unit<E>(self) -> Result<Self::Value, E>
+  where
+    E: serde::de::Error,
+  {
+    Ok(Value::Null)
+  }
   // Similar for other methods:
   //   - visit_i16
   //   - visit_u8
@@ -573,6 +669,60 @@ mod tests {
     };
   }
 
+  #[test]
+  fn merge_patch() {
+    let map = |pairs: &[(&str, Value)]| {
+      Value::from(
+        pairs
+          .iter()
+          .map(|(k, v)| (k.to_string(), v.clone()))
+          .collect::<HashMap<_, _>>(),
+      )
+    };
+    let mut doc = map(&[
+      ("title", "Hello".into()),
+      (
+        "author",
+        map(&[("given", "John".into()), ("family", "Doe".into())]),
+      ),
+      ("tags", Value::from([Value::from("a")])),
+    ]);
+    doc.merge_patch(map(&[
+      ("title", "Hi".into()),
+      ("author", map(&[("family", Value::Null)])),
+      ("tags", Value::from([Value::from("b")])),
+      (
+        "phone",
+        map(&[("home", "123".into()), ("none", Value::Null)]),
+      ),
+    ]));
+    assert_eq!(
+      doc,
+      map(&[
+        ("title", "Hi".into()),
+        ("author", map(&[("given", "John".into())])),
+        ("tags", Value::from([Value::from("b")])),
+        ("phone", map(&[("home", "123".into())])),
+      ])
+    );
+  }
+
+  #[test]
+  fn loose_cmp() {
+    use std::cmp::Ordering::*;
+    assert_eq!(Value::Integer(9).loose_cmp(&Value::Unsigned(10)), Less);
+    assert_eq!(Value::Integer(-1).loose_cmp(&Value::Unsigned(0)), Less);
+    assert_eq!(Value::Unsigned(3).loose_cmp(&Value::Float(2.5)), Greater);
+    assert_eq!(Value::Float(2.0).loose_cmp(&Value::Integer(2)), Equal);
+    assert_eq!(Value::from("10").loose_cmp(&Value::from("9")), Less);
+    assert_eq!(Value::Null.loose_cmp(&Value::Bool(false)), Less);
+    assert_eq!(Value::from(1).loose_cmp(&Value::from("1")), Less);
+    assert_eq!(
+      Value::from([Value::from(1), Value::from(2)]).loose_cmp(&Value::from([Value::from(1)])),
+      Greater
+    );
+  }
+
   impl_from_test!(Bool, true, true);
   impl_from_test!(Float, 42f64, 42f32, 42f64);
   impl_from_test!(Integer, 42i128, 42i8, 42i16, 42i32, 42i64, 42i128);
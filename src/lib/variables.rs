@@ -0,0 +1,73 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::Value;
+
+/// Scope used by routes which are not part of any scenario
+pub const GLOBAL_SCOPE: &str = "";
+
+/// Named server-side variables, grouped by scope (usually a scenario name).
+#[derive(Debug, Default)]
+pub struct Variables(Mutex<HashMap<String, HashMap<String, Value>>>);
+
+impl Variables {
+  pub fn get<S: AsRef<str>, N: AsRef<str>>(&self, scope: S, name: N) -> crate::Result<Value> {
+    let g = self.0.lock()?;
+    Ok(
+      g.get(scope.as_ref())
+        .and_then(|vars| vars.get(name.as_ref()))
+        .cloned()
+        .unwrap_or_default(),
+    )
+  }
+
+  pub fn set<S: AsRef<str>, N: AsRef<str>>(
+    &self,
+    scope: S,
+    name: N,
+    value: Value,
+  ) -> crate::Result<()> {
+    let mut g = self.0.lock()?;
+    g.entry(scope.as_ref().to_string())
+      .or_default()
+      .insert(name.as_ref().to_string(), value);
+    Ok(())
+  }
+
+  /// Increment the counter `name`, returning its new value
+  pub fn incr<S: AsRef<str>, N: AsRef<str>>(&self, scope: S, name: N) -> crate::Result<i128> {
+    let mut g = self.0.lock()?;
+    let value = g
+      .entry(scope.as_ref().to_string())
+      .or_default()
+      .entry(name.as_ref().to_string())
+      .or_insert(Value::Integer(0));
+    let next = match value {
+      Value::Integer(v) => *v + 1,
+      Value::Unsigned(v) => *v as i128 + 1,
+      Value::Float(v) => *v as i128 + 1,
+      Value::String(v) => v.parse::<i128>().unwrap_or_default() + 1,
+      _ => 1,
+    };
+    *value = Value::Integer(next);
+    Ok(next)
+  }
+
+  pub fn scope<S: AsRef<str>>(&self, scope: S) -> crate::Result<HashMap<String, Value>> {
+    let g = self.0.lock()?;
+    Ok(g.get(scope.as_ref()).cloned().unwrap_or_default())
+  }
+
+  pub fn all(&self) -> crate::Result<HashMap<String, HashMap<String, Value>>> {
+    Ok(self.0.lock()?.clone())
+  }
+
+  pub fn reset(&self) -> crate::Result<()> {
+    self.0.lock()?.clear();
+    Ok(())
+  }
+
+  pub fn reset_scope<S: AsRef<str>>(&self, scope: S) -> crate::Result<()> {
+    self.0.lock()?.remove(scope.as_ref());
+    Ok(())
+  }
+}
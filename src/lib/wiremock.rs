@@ -0,0 +1,349 @@
+use std::{
+  collections::BTreeMap,
+  path::{Path, PathBuf},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::{Error, ErrorKind, Method, Route, RouteKind, RouteScenario, Value};
+
+/// Request side of a WireMock stub mapping.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WireMockRequest {
+  #[serde(default = "WireMockRequest::any")]
+  pub method: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub url: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub url_path: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub url_pattern: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub url_path_pattern: Option<String>,
+}
+
+impl WireMockRequest {
+  fn any() -> String {
+    "ANY".to_string()
+  }
+}
+
+/// Response side of a WireMock stub mapping.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WireMockResponse {
+  #[serde(default = "RouteKind::default_status")]
+  pub status: u16,
+  /// Header values, either a single string or a list of strings
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub headers: BTreeMap<String, Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub body: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub json_body: Option<Value>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub body_file_name: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub base64_body: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub fixed_delay_milliseconds: Option<u64>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub transformers: Vec<String>,
+}
+
+/// A WireMock stub mapping, as found in `mappings/*.json`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WireMockMapping {
+  pub request: WireMockRequest,
+  pub response: WireMockResponse,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub scenario_name: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub required_scenario_state: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub new_scenario_state: Option<String>,
+}
+
+/// A mapping file holds either several mappings or a single one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum WireMockMappings {
+  Many { mappings: Vec<WireMockMapping> },
+  One(Box<WireMockMapping>),
+}
+
+impl WireMockMapping {
+  const TEMPLATE_TRANSFORMER: &'static str = "response-template";
+
+  /// Convert this mapping into a fixture route. `files` is the directory
+  /// `bodyFileName` is relative to (WireMock's `__files`).
+  pub fn to_route<P: AsRef<Path>>(&self, files: P) -> crate::Result<Route> {
+    let unsupported = |what: &str| {
+      Error::new(
+        ErrorKind::Parse,
+        Some(format!("unsupported wiremock mapping: {}", what)),
+        None,
+      )
+    };
+    let endpoint = match (&self.request.url_path, &self.request.url) {
+      (Some(path), _) => path.clone(),
+      (None, Some(url)) => url.split('?').next().unwrap_or(url).to_string(),
+      (None, None) => match (&self.request.url_path_pattern, &self.request.url_pattern) {
+        (None, None) => "/".to_string(),
+        _ => return Err(unsupported("url patterns")),
+      },
+    };
+    let methods = match self.request.method.to_ascii_uppercase().as_str() {
+      "ANY" => Method::iter().collect(),
+      method => vec![method.parse::<Method>()?],
+    };
+    let res = &self.response;
+    if res.base64_body.is_some() {
+      return Err(unsupported("base64 bodies"));
+    }
+    if res.fixed_delay_milliseconds.is_some() {
+      warn!("{} {}: ignoring fixed delay", self.request.method, endpoint);
+    }
+    let headers = res
+      .headers
+      .iter()
+      .map(|(k, v)| {
+        let v = match v {
+          Value::Array(values) => values
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(", "),
+          v => v.to_string(),
+        };
+        (k.clone(), v)
+      })
+      .collect();
+    let body = match (&res.json_body, &res.body) {
+      (Some(json), _) => Some(json.clone()),
+      (None, Some(body)) => Some(Value::from(body.as_str())),
+      (None, None) => None,
+    };
+    let mut route = Route::new(
+      methods,
+      endpoint,
+      RouteKind::Fixture {
+        status: res.status,
+        headers,
+        body,
+        file: res
+          .body_file_name
+          .as_ref()
+          .map(|name| files.as_ref().join(name)),
+        template: res
+          .transformers
+          .iter()
+          .any(|t| t == Self::TEMPLATE_TRANSFORMER),
+      },
+    );
+    if let Some(name) = &self.scenario_name {
+      route.options_mut().scenario = Some(RouteScenario {
+        name: name.clone(),
+        state: self.required_scenario_state.clone(),
+        next: self.new_scenario_state.clone(),
+      });
+    }
+    Ok(route)
+  }
+
+  /// Convert a route into a mapping, only fixture routes can be exported.
+  pub fn from_route(route: &Route) -> crate::Result<Self> {
+    let (status, headers, body, file, template) = match route.kind() {
+      RouteKind::Fixture {
+        status,
+        headers,
+        body,
+        file,
+        template,
+      } => (*status, headers, body, file, *template),
+      kind => {
+        return Err(Error::new(
+          ErrorKind::Parse,
+          Some(format!(
+            "{}: {} routes cannot be exported to wiremock",
+            route.id(),
+            kind.name()
+          )),
+          None,
+        ))
+      }
+    };
+    let method = match route.methods().len() == Method::iter().count() {
+      true => WireMockRequest::any(),
+      false if route.methods().len() == 1 => route.methods()[0].repr(),
+      false => {
+        return Err(Error::new(
+          ErrorKind::Parse,
+          Some(format!(
+            "{}: wiremock mappings match a single method",
+            route.id()
+          )),
+          None,
+        ))
+      }
+    };
+    let (body, json_body) = match body {
+      Some(Value::String(s)) => (Some(s.clone()), None),
+      Some(v) => (None, Some(v.clone())),
+      None => (None, None),
+    };
+    let scenario = route.options().scenario.clone();
+    Ok(Self {
+      request: WireMockRequest {
+        method,
+        url_path: Some(route.endpoint().clone()),
+        ..Default::default()
+      },
+      response: WireMockResponse {
+        status,
+        headers: headers
+          .iter()
+          .map(|(k, v)| (k.clone(), Value::from(v.as_str())))
+          .collect(),
+        body,
+        json_body,
+        body_file_name: file
+          .as_ref()
+          .and_then(|f| f.file_name())
+          .map(|f| f.to_string_lossy().to_string()),
+        transformers: match template {
+          true => vec![Self::TEMPLATE_TRANSFORMER.to_string()],
+          false => vec![],
+        },
+        ..Default::default()
+      },
+      scenario_name: scenario.as_ref().map(|s| s.name.clone()),
+      required_scenario_state: scenario.as_ref().and_then(|s| s.state.clone()),
+      new_scenario_state: scenario.as_ref().and_then(|s| s.next.clone()),
+    })
+  }
+}
+
+impl WireMockMappings {
+  pub fn into_vec(self) -> Vec<WireMockMapping> {
+    match self {
+      WireMockMappings::Many { mappings } => mappings,
+      WireMockMappings::One(mapping) => vec![*mapping],
+    }
+  }
+}
+
+/// Read routes from a WireMock mapping file, or from every `.json` file of a
+/// `mappings` directory. Body files are looked up in the sibling `__files`.
+pub fn import_wiremock<P: AsRef<Path>>(path: P) -> crate::Result<Vec<Route>> {
+  let path = path.as_ref();
+  let mut files = match path.is_dir() {
+    true => std::fs::read_dir(path)?
+      .filter_map(|entry| entry.ok().map(|e| e.path()))
+      .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+      .collect::<Vec<_>>(),
+    false => vec![path.to_path_buf()],
+  };
+  files.sort();
+  let mappings_dir = match path.is_dir() {
+    true => path.to_path_buf(),
+    false => path.parent().map(Path::to_path_buf).unwrap_or_default(),
+  };
+  let body_files = mappings_dir
+    .parent()
+    .map(|p| p.join("__files"))
+    .unwrap_or_else(|| PathBuf::from("__files"));
+  let mut routes = vec![];
+  for file in files {
+    let content = std::fs::read_to_string(&file)?;
+    let mappings = serde_json::from_str::<WireMockMappings>(&content)?;
+    for mapping in mappings.into_vec() {
+      match mapping.to_route(&body_files) {
+        Ok(route) => routes.push(route),
+        Err(e) => warn!("{}: skipping mapping, {}", file.display(), e),
+      }
+    }
+  }
+  Ok(routes)
+}
+
+/// Write every exportable route to a single WireMock mapping file.
+pub fn export_wiremock<P: AsRef<Path>>(routes: &[Route], path: P) -> crate::Result<usize> {
+  let mut mappings = vec![];
+  for route in routes {
+    match WireMockMapping::from_route(route) {
+      Ok(mapping) => mappings.push(mapping),
+      Err(e) => warn!("skipping route, {}", e),
+    }
+  }
+  let count = mappings.len();
+  let content = serde_json::to_string_pretty(&WireMockMappings::Many { mappings })?;
+  std::fs::write(path, content)?;
+  Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{Method, RouteKind, Value};
+
+  use super::{WireMockMapping, WireMockMappings};
+
+  #[test]
+  fn round_trip() {
+    let mappings: WireMockMappings = serde_json::from_str(
+      r#"{"mappings": [{
+        "scenarioName": "cart",
+        "requiredScenarioState": "Started",
+        "newScenarioState": "filled",
+        "request": {"method": "POST", "url": "/cart?verbose=1"},
+        "response": {
+          "status": 201,
+          "jsonBody": {"items": 1},
+          "headers": {"Set-Cookie": ["a=1", "b=2"]},
+          "transformers": ["response-template"]
+        }
+      }]}"#,
+    )
+    .unwrap();
+    let mapping = mappings.into_vec().remove(0);
+    let route = mapping.to_route("__files").unwrap();
+    assert_eq!(route.id(), "POST /cart");
+    match route.kind() {
+      RouteKind::Fixture {
+        status,
+        headers,
+        body: Some(Value::Map(body)),
+        template: true,
+        ..
+      } => {
+        assert_eq!(*status, 201);
+        assert_eq!(headers["Set-Cookie"], "a=1, b=2");
+        assert!(body["items"].loose_eq(&Value::from(1)));
+      }
+      kind => panic!("unexpected route kind: {:?}", kind),
+    }
+    assert_eq!(
+      route.options().scenario.as_ref().unwrap().next.as_deref(),
+      Some("filled")
+    );
+
+    let exported = WireMockMapping::from_route(&route).unwrap();
+    assert_eq!(exported.request.method, Method::Post.repr());
+    assert_eq!(exported.request.url_path.as_deref(), Some("/cart"));
+    assert_eq!(exported.scenario_name.as_deref(), Some("cart"));
+    assert_eq!(exported.to_route("__files").unwrap(), route);
+  }
+
+  #[test]
+  fn unsupported() {
+    let mapping: WireMockMapping = serde_json::from_str(
+      r#"{"request": {"urlPattern": "/users/.*"}, "response": {"status": 200}}"#,
+    )
+    .unwrap();
+    assert!(mapping.to_route("__files").is_err());
+  }
+}
@@ -1,6 +1,6 @@
 use std::path::{Path, PathBuf};
 
-use crate::{Config, Error, ErrorKind, UserConfig};
+use crate::{Config, Error, ErrorKind};
 
 #[derive(Debug)]
 pub struct Workspace {
@@ -16,6 +16,14 @@ impl Workspace {
     })
   }
 
+  /// Directory the workspace config lives in, against which store paths resolve
+  pub fn dir(&self) -> &Path {
+    match self.path.parent() {
+      Some(dir) if !dir.as_os_str().is_empty() => dir,
+      _ => Path::new("."),
+    }
+  }
+
   pub fn create<P: AsRef<Path>>(path: P) -> crate::Result<Self> {
     if path.as_ref().exists() {
       return Err(Error::new(
@@ -1,20 +1,227 @@
-use std::{
-  collections::VecDeque,
-  net::{IpAddr, Shutdown, TcpListener, TcpStream},
-  sync::Arc,
-  thread,
-};
+use std::path::{Path, PathBuf};
 
-use clap::{Parser, Subcommand};
-use mocker_core::{Response, Server, Workspace, CONFIG_NAME};
-use std::io::Write;
+use clap::{ArgGroup, Parser, Subcommand};
+use mocker_core::{
+  parse_duration, parse_speed, scratch_routes, Bench, Client, Column, Contract, Error, ErrorKind,
+  HostsFile, ImportStrategy, Linter, Method, MetricsReport, Request, Router, Server, SessionReplay,
+  SheetFormat, Status, Validation, Workspace, WorkspaceDiff, ADMIN_PREFIX, CONFIG_NAME,
+};
 
 #[derive(Subcommand)]
 enum Command {
   /// Initialize the current workspace
   Init {},
   /// Serve the current workspace
-  Serve {},
+  Serve {
+    /// Check responses against their route schema: `off`, `warn` or `strict`
+    #[arg(long)]
+    check_responses: Option<String>,
+    /// Only serve the routes tagged with one of these, and untagged ones
+    #[arg(long, value_delimiter = ',')]
+    tags: Vec<String>,
+    /// Serve the workspace packed in this archive instead
+    #[arg(long)]
+    pack: Option<PathBuf>,
+    /// Override a workspace variable, e.g. `apiVersion=v2` or `db.port=5433`
+    #[arg(long = "var", value_name = "KEY=VALUE")]
+    vars: Vec<String>,
+  },
+  /// Pack the workspace in a single archive to share or version
+  Pack {
+    /// Archive to write, `<workspace directory>.mock` by default
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+  },
+  /// Extract a packed workspace
+  Unpack {
+    pack: PathBuf,
+    /// Directory to extract to, named after the archive by default
+    #[arg(short, long)]
+    dir: Option<PathBuf>,
+  },
+  /// Check that every route of the workspace can be served
+  Validate {
+    /// Call each route in-process and check its response schema and size
+    #[arg(long)]
+    execute: bool,
+  },
+  /// Show, as JSON, how a request would be routed without serving it
+  Explain {
+    method: String,
+    /// Path and query, e.g. `/users?id=42`
+    target: String,
+    /// Request header, as `Name: value`
+    #[arg(short = 'H', long = "header")]
+    headers: Vec<String>,
+    #[arg(long)]
+    body: Option<String>,
+  },
+  /// Report likely mistakes in the workspace
+  Lint {},
+  /// Upgrade the workspace config file to the current format version
+  Migrate {},
+  /// Run the `*.test.js` files of the workspace against an in-process server
+  #[cfg(feature = "js")]
+  Test {
+    /// Only run test files whose path contains this
+    filter: Option<String>,
+  },
+  /// Load test a running server
+  Bench {
+    /// Route to request, e.g. `/users`
+    #[arg(long)]
+    route: String,
+    #[arg(long, default_value = "GET")]
+    method: String,
+    /// Number of concurrent workers
+    #[arg(long, default_value_t = 10)]
+    concurrency: usize,
+    #[arg(long, default_value = "10s")]
+    duration: String,
+    /// Server address, defaults to the workspace host and port
+    #[arg(long)]
+    addr: Option<String>,
+  },
+  /// Show per-route request counts, error rates and latencies of a running server
+  Stats {
+    /// Server address, defaults to the workspace host and port
+    #[arg(long)]
+    addr: Option<String>,
+  },
+  /// Send recorded requests to a running server again, keeping their timing
+  #[cfg(feature = "json")]
+  ReplaySession {
+    /// Capture directory, or a single capture file
+    capture: PathBuf,
+    /// How much faster than recorded to go, e.g. `2x`, or `max`
+    #[arg(long, default_value = "1x")]
+    speed: String,
+    /// Only replay the requests served by the route declared on this endpoint
+    #[arg(long)]
+    route: Option<String>,
+    /// Server address, defaults to the workspace host and port
+    #[arg(long)]
+    addr: Option<String>,
+  },
+  /// Replay every stub against a real backend and report the differences
+  #[command(group(ArgGroup::new("reference").required(true).args(["upstream", "pact"])))]
+  Verify {
+    /// Base url of the real backend, e.g. `http://localhost:3000/api`
+    #[arg(long)]
+    upstream: Option<String>,
+    /// Pact file whose interactions the stubs must honour, instead of a backend
+    #[arg(long)]
+    pact: Option<PathBuf>,
+    /// Dotted path of a body field which must be equal on both sides
+    #[arg(long = "field")]
+    fields: Vec<String>,
+  },
+  /// Compare the routes, stores and config of two workspaces
+  Diff {
+    /// Workspace directory or config file
+    a: PathBuf,
+    /// Workspace directory or config file
+    b: PathBuf,
+  },
+  /// Add routes converted from another mocking tool to the workspace
+  Import {
+    #[command(subcommand)]
+    format: ImportFormat,
+  },
+  /// Convert the workspace routes for another mocking tool
+  Export {
+    #[command(subcommand)]
+    format: ExportFormat,
+  },
+  /// Inspect the data of store routes
+  Store {
+    #[command(subcommand)]
+    command: StoreCommand,
+  },
+  /// Resolve the virtual hostnames of the workspace to the server
+  Hosts {
+    #[command(subcommand)]
+    command: HostsCommand,
+  },
+}
+
+#[derive(Subcommand)]
+enum HostsCommand {
+  /// Map the workspace hostnames to the server in the hosts file
+  Install {
+    /// Address the hostnames resolve to
+    #[arg(long, default_value = "127.0.0.1")]
+    addr: String,
+    /// Hosts file to edit, the system one by default
+    #[arg(long)]
+    file: Option<PathBuf>,
+  },
+  /// Drop the entries added by `mocker hosts install`
+  Remove {
+    /// Hosts file to edit, the system one by default
+    #[arg(long)]
+    file: Option<PathBuf>,
+  },
+}
+
+#[derive(Subcommand)]
+enum StoreCommand {
+  /// Write the items of a store route to a spreadsheet
+  #[cfg(feature = "json")]
+  Export {
+    /// Endpoint of the store route, e.g. `/users`
+    route: String,
+    /// `csv`, or `xlsx` when built with the `xlsx` feature
+    #[arg(long, default_value = "csv")]
+    format: String,
+    /// Column as `Header=dotted.path`, every value is exported when omitted
+    #[arg(long = "column")]
+    columns: Vec<String>,
+    /// Output file, defaults to the store file with the format extension
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+  },
+  /// Load items from a JSON file into a store route
+  #[cfg(feature = "json")]
+  Import {
+    /// Endpoint of the store route, e.g. `/users`
+    route: String,
+    /// JSON array of items
+    path: PathBuf,
+    /// `replace`, `merge` or `append`
+    #[arg(long, default_value = "append")]
+    strategy: String,
+  },
+  /// Fill store routes with fake data whose relations hold, as described by
+  /// a JSON dataset keyed by store endpoint
+  #[cfg(feature = "json")]
+  Generate {
+    /// JSON dataset, e.g. `{"/users": {"count": 10, "fields": {...}}}`
+    dataset: PathBuf,
+    /// `replace`, `merge` or `append`
+    #[arg(long, default_value = "replace")]
+    strategy: String,
+  },
+}
+
+#[derive(Subcommand)]
+enum ImportFormat {
+  /// WireMock stub mappings: a mapping file or a `mappings` directory
+  #[cfg(feature = "json")]
+  Wiremock { path: PathBuf },
+  /// Pact contract file, one route per interaction
+  #[cfg(feature = "json")]
+  Pact { path: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum ExportFormat {
+  /// WireMock stub mappings file
+  #[cfg(feature = "json")]
+  Wiremock { path: PathBuf },
+  /// OpenAPI document, as served at `/__mocker/openapi.json`
+  #[cfg(feature = "json")]
+  Openapi { path: PathBuf },
 }
 
 #[derive(Parser)]
@@ -30,28 +237,553 @@ fn cmd_init() -> mocker_core::Result<()> {
   Ok(())
 }
 
-fn cmd_serve() -> mocker_core::Result<()> {
-  let w = Workspace::load(CONFIG_NAME)?;
+fn cmd_serve(
+  check_responses: Option<String>,
+  tags: Vec<String>,
+  pack: Option<PathBuf>,
+  vars: Vec<String>,
+) -> mocker_core::Result<()> {
+  if let Some(pack) = pack {
+    let dir = std::env::temp_dir().join(format!("mocker-pack-{}", std::process::id()));
+    mocker_core::unpack(std::fs::File::open(&pack)?, &dir)?;
+    println!("📦 Serving {} from {}", pack.display(), dir.display());
+    // store and fixture paths are relative to the workspace
+    std::env::set_current_dir(&dir)?;
+  }
+  let mut w = Workspace::load(CONFIG_NAME)?;
+  if let Some(check) = check_responses {
+    w.config.check_responses = check.parse()?;
+  }
+  if !tags.is_empty() {
+    w.config.tags = Some(tags);
+  }
+  for var in vars {
+    let (key, value) = var.split_once('=').ok_or_else(|| {
+      Error::new(
+        ErrorKind::Parse,
+        Some(format!("invalid variable '{}', expected `key=value`", var)),
+        None,
+      )
+    })?;
+    // numbers, booleans and other JSON values keep their type
+    let value = serde_json::from_str(value).unwrap_or_else(|_| mocker_core::Value::from(value));
+    w.config.set_variable(key.trim(), value);
+  }
   println!("{:#?}", w);
-  let srv = Server::new(w.config);
+  let srv = Server::new(w.config)?;
   srv.listen()?;
   Ok(())
 }
 
+fn cmd_pack(output: Option<PathBuf>) -> mocker_core::Result<()> {
+  let w = Workspace::load(CONFIG_NAME)?;
+  let output = match output {
+    Some(output) => output,
+    None => {
+      let dir = std::fs::canonicalize(w.dir())?;
+      let name = dir
+        .file_name()
+        .map_or("workspace".into(), |n| n.to_string_lossy());
+      PathBuf::from(format!("{}.{}", name, mocker_core::PACK_EXTENSION))
+    }
+  };
+  let file = std::io::BufWriter::new(std::fs::File::create(&output)?);
+  let count = mocker_core::pack(w.dir(), file)?;
+  println!("📦 Packed {} files in {}", count, output.display());
+  Ok(())
+}
+
+fn cmd_unpack(pack: PathBuf, dir: Option<PathBuf>) -> mocker_core::Result<()> {
+  let dir = dir.unwrap_or_else(|| PathBuf::from(pack.file_stem().unwrap_or_default()));
+  let file = std::io::BufReader::new(std::fs::File::open(&pack)?);
+  let count = mocker_core::unpack(file, &dir)?;
+  println!("📂 Extracted {} files to {}", count, dir.display());
+  Ok(())
+}
+
+fn cmd_validate(execute: bool) -> mocker_core::Result<()> {
+  let w = Workspace::load(CONFIG_NAME)?;
+  let router = Router::default().with_scenarios(w.config.scenarios.clone());
+  let mut failed = 0;
+  for route in &w.config.routes {
+    if let Err(e) = router.add(route.clone()) {
+      println!("  ✘ {}\n      {}", route.id(), e);
+      failed += 1;
+    }
+  }
+  let checks = match execute {
+    true => Validation::new(&router).check()?,
+    false => vec![],
+  };
+  Contract::write_report(&checks, std::io::stdout())?;
+  failed += checks.iter().filter(|c| !c.is_ok()).count();
+  match failed {
+    0 => {
+      println!("✔ {} routes are valid", w.config.routes.len());
+      Ok(())
+    }
+    n => Err(Error::new(
+      ErrorKind::Parse,
+      Some(format!("{} validation checks failed", n)),
+      None,
+    )),
+  }
+}
+
+fn cmd_explain(
+  method: String,
+  target: String,
+  headers: Vec<String>,
+  body: Option<String>,
+) -> mocker_core::Result<()> {
+  let w = Workspace::load(CONFIG_NAME)?;
+  let mut req = Request::new(method.parse::<Method>()?, target);
+  for header in headers {
+    let (name, value) = header.split_once(':').ok_or_else(|| {
+      Error::new(
+        ErrorKind::Parse,
+        Some(format!(
+          "invalid header '{}', expected `Name: value`",
+          header
+        )),
+        None,
+      )
+    })?;
+    req.set_header(name.trim(), value.trim());
+  }
+  if let Some(body) = body {
+    req = req.with_body(body);
+  }
+  let explanation = Server::new(w.config)?.explain(req)?;
+  #[cfg(feature = "json")]
+  println!("{}", serde_json::to_string_pretty(&explanation)?);
+  #[cfg(not(feature = "json"))]
+  println!("{:#?}", explanation);
+  Ok(())
+}
+
+fn cmd_lint() -> mocker_core::Result<()> {
+  let w = Workspace::load(CONFIG_NAME)?;
+  let lints = Linter::new(&w.config, w.dir()).lint();
+  for lint in &lints {
+    println!("  ⚠ {}", lint);
+  }
+  match lints.len() {
+    0 => {
+      println!("✔ No issues found");
+      Ok(())
+    }
+    n => Err(Error::new(
+      ErrorKind::Parse,
+      Some(format!("{} issues found", n)),
+      None,
+    )),
+  }
+}
+
+fn cmd_migrate() -> mocker_core::Result<()> {
+  let changes = mocker_core::migrate_file(CONFIG_NAME)?;
+  for change in &changes {
+    println!("  ↑ {}", change);
+  }
+  match changes.is_empty() {
+    true => println!("✔ {} is up to date", CONFIG_NAME),
+    false => println!(
+      "✔ {} migrated to version {}",
+      CONFIG_NAME,
+      mocker_core::CONFIG_VERSION
+    ),
+  }
+  Ok(())
+}
+
+#[cfg(feature = "js")]
+fn cmd_test(filter: Option<String>) -> mocker_core::Result<()> {
+  let w = Workspace::load(CONFIG_NAME)?;
+  let runner = mocker_core::TestRunner::new(w.config.clone());
+  let mut outcomes = vec![];
+  for file in mocker_core::TestRunner::discover(w.dir())? {
+    let file = file.strip_prefix(w.dir()).unwrap_or(&file).to_path_buf();
+    if filter
+      .as_ref()
+      .is_none_or(|f| file.to_string_lossy().contains(f.as_str()))
+    {
+      outcomes.extend(runner.run_file(&file)?);
+    }
+  }
+  mocker_core::TestRunner::write_report(&outcomes, std::io::stdout())?;
+  let failed = outcomes.iter().filter(|o| !o.is_ok()).count();
+  match failed {
+    0 => {
+      println!("\n✔ {} tests passed", outcomes.len());
+      Ok(())
+    }
+    n => Err(Error::new(
+      ErrorKind::Parse,
+      Some(format!("{} of {} tests failed", n, outcomes.len())),
+      None,
+    )),
+  }
+}
+
+fn cmd_bench(
+  route: String,
+  method: String,
+  concurrency: usize,
+  duration: String,
+  addr: Option<String>,
+) -> mocker_core::Result<()> {
+  let addr = match addr {
+    Some(addr) => addr,
+    None => {
+      let w = Workspace::load(CONFIG_NAME)?;
+      format!("{}:{}", w.config.host, w.config.port)
+    }
+  };
+  let method = method.parse::<Method>()?;
+  let duration = parse_duration(duration)?;
+  println!(
+    "🔨 {} {} on {} ({} workers, {:?})\n",
+    method, route, addr, concurrency, duration
+  );
+  let report = Bench::new(Client::new(addr), Request::new(method, route))
+    .with_concurrency(concurrency)
+    .with_duration(duration)
+    .run()?;
+  report.write(std::io::stdout())?;
+  Ok(())
+}
+
+fn cmd_stats(addr: Option<String>) -> mocker_core::Result<()> {
+  let addr = match addr {
+    Some(addr) => addr,
+    None => {
+      let w = Workspace::load(CONFIG_NAME)?;
+      format!("{}:{}", w.config.host, w.config.port)
+    }
+  };
+  let req = Request::new(Method::Get, format!("{}/stats", ADMIN_PREFIX))
+    .with_header("Accept", "application/json");
+  let res = Client::new(&addr).send(&req)?;
+  if res.status() != 200 {
+    return Err(Error::new(
+      ErrorKind::IO,
+      Some(format!("{} answered with status {}", addr, res.status())),
+      None,
+    ));
+  }
+  let report: MetricsReport = serde_json::from_slice(res.body())?;
+  println!("📊 Routes served by {}\n", addr);
+  report.write(std::io::stdout())?;
+  println!();
+  Ok(())
+}
+
+#[cfg(feature = "json")]
+fn cmd_replay_session(
+  capture: PathBuf,
+  speed: String,
+  route: Option<String>,
+  addr: Option<String>,
+) -> mocker_core::Result<()> {
+  let addr = match addr {
+    Some(addr) => addr,
+    None => {
+      let w = Workspace::load(CONFIG_NAME)?;
+      format!("{}:{}", w.config.host, w.config.port)
+    }
+  };
+  let mut replay = SessionReplay::load(&capture)?.with_speed(parse_speed(&speed)?);
+  if let Some(route) = route {
+    replay = replay.with_route(route);
+  }
+  println!(
+    "⏯  Replaying {} requests against {} at {}\n",
+    replay.captures().len(),
+    addr,
+    speed
+  );
+  let outcomes = replay.run(&Client::new(&addr), |outcome| {
+    let _ = SessionReplay::write_outcome(outcome, std::io::stdout());
+  })?;
+  println!();
+  match outcomes.iter().filter(|o| !o.is_ok()).count() {
+    0 => {
+      println!("✔ {} requests answered as recorded", outcomes.len());
+      Ok(())
+    }
+    n => Err(Error::new(
+      ErrorKind::Api(Status::ExpectationFailed),
+      Some(format!(
+        "{} of {} requests were not answered as recorded",
+        n,
+        outcomes.len()
+      )),
+      None,
+    )),
+  }
+}
+
+fn cmd_verify(
+  upstream: Option<String>,
+  pact: Option<PathBuf>,
+  fields: Vec<String>,
+) -> mocker_core::Result<()> {
+  let w = Workspace::load(CONFIG_NAME)?;
+  let scratch = std::env::temp_dir().join(format!("mocker-verify-{}", std::process::id()));
+  let router = Router::default()
+    .with_scenarios(w.config.scenarios.clone())
+    .with_routes(scratch_routes(&w.config.routes, &scratch)?);
+  let checks = match (upstream, pact) {
+    #[cfg(feature = "json")]
+    (None, Some(pact)) => mocker_core::Pact::load(&pact)
+      .and_then(|p| p.verify(&router))
+      .map(|checks| (checks, pact.display().to_string())),
+    (Some(upstream), None) => w
+      .config
+      .upstream
+      .client(&upstream)
+      .and_then(|client| Contract::new(&router, client).with_fields(fields).check())
+      .map(|checks| (checks, upstream)),
+    _ => unreachable!("clap requires either --upstream or --pact"),
+  };
+  let _ = std::fs::remove_dir_all(&scratch);
+  let (checks, reference) = checks?;
+  println!("🔍 Comparing stubs with {}\n", reference);
+  Contract::write_report(&checks, std::io::stdout())?;
+  match checks.iter().filter(|c| !c.is_ok()).count() {
+    0 => Ok(()),
+    n => Err(Error::new(
+      ErrorKind::Api(Status::ExpectationFailed),
+      Some(format!(
+        "{} of {} checks drifted from {}",
+        n,
+        checks.len(),
+        reference
+      )),
+      None,
+    )),
+  }
+}
+
+fn load_workspace(path: &Path) -> mocker_core::Result<Workspace> {
+  match path.is_dir() {
+    true => Workspace::load(path.join(CONFIG_NAME)),
+    false => Workspace::load(path),
+  }
+}
+
+fn cmd_diff(a: PathBuf, b: PathBuf) -> mocker_core::Result<()> {
+  let diff = WorkspaceDiff::new(&load_workspace(&a)?, &load_workspace(&b)?);
+  match diff.is_empty() {
+    true => println!("✔ {} and {} are identical", a.display(), b.display()),
+    false => diff.write(std::io::stdout())?,
+  }
+  Ok(())
+}
+
+fn cmd_import(format: ImportFormat) -> mocker_core::Result<()> {
+  let mut w = Workspace::load(CONFIG_NAME)?;
+  let routes = match format {
+    #[cfg(feature = "json")]
+    ImportFormat::Wiremock { path } => mocker_core::import_wiremock(path)?,
+    #[cfg(feature = "json")]
+    ImportFormat::Pact { path } => mocker_core::Pact::load(path)?.to_routes(),
+  };
+  println!("📥 Imported {} routes", routes.len());
+  w.config.routes.extend(routes);
+  w.config.save(&w.path)
+}
+
+fn cmd_export(format: ExportFormat) -> mocker_core::Result<()> {
+  let w = Workspace::load(CONFIG_NAME)?;
+  let count = match format {
+    #[cfg(feature = "json")]
+    ExportFormat::Wiremock { path } => mocker_core::export_wiremock(&w.config.routes, path)?,
+    #[cfg(feature = "json")]
+    ExportFormat::Openapi { path } => {
+      let doc = mocker_core::openapi(
+        "Mocker",
+        Some(format!("http://{}:{}", w.config.host, w.config.port)),
+        &w.config.routes,
+      );
+      std::fs::write(path, serde_json::to_string_pretty(&doc)?)?;
+      w.config.routes.len()
+    }
+  };
+  println!("📤 Exported {} of {} routes", count, w.config.routes.len());
+  Ok(())
+}
+
+fn cmd_store(command: StoreCommand) -> mocker_core::Result<()> {
+  let w = Workspace::load(CONFIG_NAME)?;
+  match command {
+    #[cfg(feature = "json")]
+    StoreCommand::Export {
+      route,
+      format,
+      columns,
+      output,
+    } => {
+      let mut store = mocker_core::Store::for_endpoint(&w.config.routes, &route)?;
+      store.load()?;
+      let columns = columns
+        .iter()
+        .map(|c| c.parse::<Column>())
+        .collect::<mocker_core::Result<Vec<_>>>()?;
+      let output = output.unwrap_or_else(|| store.path().with_extension(format.to_lowercase()));
+      let sheet = store.sheet(columns);
+      std::fs::write(&output, sheet.to_bytes(format.parse::<SheetFormat>()?)?)?;
+      println!(
+        "📤 Exported {} items of {} to {}",
+        sheet.rows().len(),
+        route,
+        output.display()
+      );
+      Ok(())
+    }
+    #[cfg(feature = "json")]
+    StoreCommand::Import {
+      route,
+      path,
+      strategy,
+    } => {
+      let mut store = mocker_core::Store::for_endpoint(&w.config.routes, &route)?;
+      if store.path().exists() {
+        store.load()?;
+      }
+      let mut data = mocker_core::Store::json(&path, store.identifier());
+      data.load()?;
+      let report = store.import(data.items().clone(), strategy.parse::<ImportStrategy>()?)?;
+      store.save()?;
+      println!(
+        "📥 Imported {} into {}: {} created, {} updated",
+        path.display(),
+        route,
+        report.created,
+        report.updated
+      );
+      Ok(())
+    }
+    #[cfg(feature = "json")]
+    StoreCommand::Generate { dataset, strategy } => {
+      let dataset: mocker_core::Dataset =
+        serde_json::from_slice(&mocker_core::read_file(&dataset)?)?;
+      let strategy = strategy.parse::<ImportStrategy>()?;
+      let stores = dataset
+        .generate()?
+        .into_iter()
+        .map(|(route, items)| {
+          let store = mocker_core::Store::for_endpoint(&w.config.routes, &route)?;
+          Ok((route, store, items))
+        })
+        .collect::<mocker_core::Result<Vec<_>>>()?;
+      for (route, mut store, items) in stores {
+        if store.path().exists() {
+          store.load()?;
+        }
+        let report = store.import(items, strategy)?;
+        store.save()?;
+        println!(
+          "🎲 Generated {}: {} created, {} updated",
+          route, report.created, report.updated
+        );
+      }
+      Ok(())
+    }
+  }
+}
+
+fn cmd_hosts(command: HostsCommand) -> mocker_core::Result<()> {
+  let file = |path: Option<PathBuf>| path.map(HostsFile::new).unwrap_or_else(HostsFile::system);
+  match command {
+    HostsCommand::Install { addr, file: path } => {
+      let w = Workspace::load(CONFIG_NAME)?;
+      let names = HostsFile::hostnames(&w.config);
+      if names.is_empty() {
+        println!("No hostname configured, see the `hosts` setting and the `host` route option");
+        return Ok(());
+      }
+      let hosts = file(path);
+      let changed = hosts.install(&names, &addr)?;
+      for name in &names {
+        println!("  📍 {} → {}", name, addr);
+      }
+      match changed {
+        true => println!("✔ Updated {}", hosts.path().display()),
+        false => println!("✔ {} is up to date", hosts.path().display()),
+      }
+      Ok(())
+    }
+    HostsCommand::Remove { file: path } => {
+      let hosts = file(path);
+      match hosts.remove()? {
+        true => println!("✔ Removed mocker entries from {}", hosts.path().display()),
+        false => println!("✔ No mocker entries in {}", hosts.path().display()),
+      }
+      Ok(())
+    }
+  }
+}
+
 fn run() -> mocker_core::Result<()> {
   let options = Options::parse();
-  if let Err(_) = std::env::var("RUST_LOG") {
+  if std::env::var("RUST_LOG").is_err() {
     std::env::set_var("RUST_LOG", "info");
   }
   pretty_env_logger::init();
   match options.command {
     Command::Init { .. } => cmd_init(),
-    Command::Serve { .. } => cmd_serve(),
+    Command::Serve {
+      check_responses,
+      tags,
+      pack,
+      vars,
+    } => cmd_serve(check_responses, tags, pack, vars),
+    Command::Pack { output } => cmd_pack(output),
+    Command::Unpack { pack, dir } => cmd_unpack(pack, dir),
+    Command::Validate { execute } => cmd_validate(execute),
+    Command::Explain {
+      method,
+      target,
+      headers,
+      body,
+    } => cmd_explain(method, target, headers, body),
+    Command::Lint { .. } => cmd_lint(),
+    Command::Migrate { .. } => cmd_migrate(),
+    #[cfg(feature = "js")]
+    Command::Test { filter } => cmd_test(filter),
+    Command::Bench {
+      route,
+      method,
+      concurrency,
+      duration,
+      addr,
+    } => cmd_bench(route, method, concurrency, duration, addr),
+    Command::Stats { addr } => cmd_stats(addr),
+    #[cfg(feature = "json")]
+    Command::ReplaySession {
+      capture,
+      speed,
+      route,
+      addr,
+    } => cmd_replay_session(capture, speed, route, addr),
+    Command::Verify {
+      upstream,
+      pact,
+      fields,
+    } => cmd_verify(upstream, pact, fields),
+    Command::Diff { a, b } => cmd_diff(a, b),
+    Command::Import { format } => cmd_import(format),
+    Command::Export { format } => cmd_export(format),
+    Command::Store { command } => cmd_store(command),
+    Command::Hosts { command } => cmd_hosts(command),
   }
 }
 
 fn main() {
   if let Err(e) = run() {
     eprintln!("\x1b[1;31mfatal\x1b[0m: {}", e);
+    std::process::exit(1);
   }
 }